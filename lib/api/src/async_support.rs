@@ -0,0 +1,151 @@
+//! Support for calling WebAssembly asynchronously.
+//!
+//! Async calls run on a separately allocated stack (a fiber). When an async
+//! host function called from WebAssembly isn't ready yet, the fiber is
+//! suspended and the executor gets `Poll::Pending`. The next poll resumes
+//! the fiber right where it was suspended, in the middle of the WebAssembly
+//! code.
+
+use crate::RuntimeError;
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::ptr;
+use std::task::{Context, Poll};
+use wasmer_vm::AsyncCallState;
+use wasmtime_fiber::{Fiber, FiberStack, Suspend};
+
+/// The size of the stack async calls run on.
+const FIBER_STACK_SIZE: usize = 2 << 20;
+
+thread_local! {
+    /// The async call being polled on this thread, if any.
+    static CURRENT: Cell<*const AsyncCx> = Cell::new(ptr::null());
+}
+
+/// The context shared between a `FiberFuture` and the code on its fiber.
+struct AsyncCx {
+    /// Suspends the fiber, set when the fiber starts.
+    suspend: Cell<*const Suspend<(), (), ()>>,
+    /// The context of the poll currently resuming the fiber.
+    poll_cx: Cell<*mut Context<'static>>,
+    /// Set when the future is dropped before the fiber is done.
+    cancelled: Cell<bool>,
+}
+
+/// A future driving a fiber to completion.
+struct FiberFuture<'a> {
+    fiber: Fiber<'a, (), (), ()>,
+    async_cx: Box<AsyncCx>,
+    state: AsyncCallState,
+}
+
+// The fiber only runs the `Send` function given to `on_fiber`, and it only
+// runs while the future is being polled or dropped.
+unsafe impl Send for FiberFuture<'_> {}
+
+impl FiberFuture<'_> {
+    /// Resumes the fiber until it suspends (`Ok(Err(()))`) or is done
+    /// (`Ok(Ok(()))`).
+    fn resume(&mut self) -> Result<Result<(), ()>, RuntimeError> {
+        unsafe {
+            self.state.enter()?;
+            let prev = CURRENT.with(|current| current.replace(&*self.async_cx));
+            let result = self.fiber.resume(());
+            CURRENT.with(|current| current.set(prev));
+            self.state.leave();
+            Ok(result)
+        }
+    }
+}
+
+impl Future for FiberFuture<'_> {
+    type Output = Result<(), RuntimeError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.async_cx
+            .poll_cx
+            .set(unsafe { std::mem::transmute::<&mut Context<'_>, *mut Context<'static>>(cx) });
+        let result = this.resume();
+        this.async_cx.poll_cx.set(ptr::null_mut());
+        match result {
+            Ok(Ok(())) => Poll::Ready(Ok(())),
+            Ok(Err(())) => Poll::Pending,
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+impl Drop for FiberFuture<'_> {
+    fn drop(&mut self) {
+        if self.fiber.done() {
+            return;
+        }
+        // The call is being cancelled: let the pending host function fail,
+        // which unwinds the WebAssembly frames on the fiber.
+        self.async_cx.cancelled.set(true);
+        let result = self.resume();
+        debug_assert!(
+            matches!(result, Ok(Ok(()))),
+            "a cancelled fiber must run to completion"
+        );
+    }
+}
+
+/// Runs `func` on a new fiber, so that async host functions it calls can
+/// suspend it.
+pub(crate) async fn on_fiber<R: Send>(
+    func: impl FnOnce() -> Result<R, RuntimeError> + Send,
+) -> Result<R, RuntimeError> {
+    let mut slot = None;
+    {
+        let stack = FiberStack::new(FIBER_STACK_SIZE)
+            .map_err(|e| RuntimeError::new(format!("failed to allocate a fiber stack: {}", e)))?;
+        let fiber = Fiber::new(stack, |(), suspend| {
+            let async_cx = unsafe { &*CURRENT.with(|current| current.get()) };
+            async_cx.suspend.set(suspend);
+            if !async_cx.cancelled.get() {
+                slot = Some(func());
+            }
+        })
+        .map_err(|e| RuntimeError::new(format!("failed to create a fiber: {}", e)))?;
+        FiberFuture {
+            fiber,
+            async_cx: Box::new(AsyncCx {
+                suspend: Cell::new(ptr::null()),
+                poll_cx: Cell::new(ptr::null_mut()),
+                cancelled: Cell::new(false),
+            }),
+            state: AsyncCallState::new(),
+        }
+        .await?;
+    }
+    slot.unwrap_or_else(|| Err(RuntimeError::new("the async call was cancelled")))
+}
+
+/// Waits for `future` from within an async call, suspending the call each
+/// time the future isn't ready.
+///
+/// Fails if there is no async call running on this thread, or if the async
+/// call gets cancelled while waiting.
+pub(crate) fn block_on<F: Future>(future: F) -> Result<F::Output, RuntimeError> {
+    let async_cx = CURRENT.with(|current| current.get());
+    if async_cx.is_null() {
+        return Err(RuntimeError::new(
+            "async host functions can only be called from `call_async`",
+        ));
+    }
+    let async_cx = unsafe { &*async_cx };
+    let mut future = Box::pin(future);
+    loop {
+        if async_cx.cancelled.get() {
+            return Err(RuntimeError::new("the async call was cancelled"));
+        }
+        let poll_cx = unsafe { &mut *async_cx.poll_cx.get() };
+        if let Poll::Ready(output) = future.as_mut().poll(poll_cx) {
+            return Ok(output);
+        }
+        unsafe { (*async_cx.suspend.get()).suspend(()) };
+    }
+}
@@ -1,7 +1,7 @@
 use crate::exports::{ExportError, Exportable};
 use crate::externals::Extern;
 use crate::store::Store;
-use crate::types::Val;
+use crate::types::{read_value_from_slot, val_matches_type, write_value_to_slot, Val};
 use crate::FunctionType;
 use crate::NativeFunc;
 use crate::RuntimeError;
@@ -14,12 +14,14 @@ use loupe::MemoryUsage;
 use std::cmp::max;
 use std::ffi::c_void;
 use std::fmt;
+#[cfg(feature = "async")]
+use std::future::Future;
 use std::sync::Arc;
 use wasmer_engine::{Export, ExportFunction, ExportFunctionMetadata};
 use wasmer_vm::{
     raise_user_trap, resume_panic, wasmer_call_trampoline, ImportInitializerFuncPtr,
-    VMCallerCheckedAnyfunc, VMDynamicFunctionContext, VMExportFunction, VMFunctionBody,
-    VMFunctionEnvironment, VMFunctionKind, VMTrampoline,
+    ReferenceScope, VMCallerCheckedAnyfunc, VMDynamicFunctionContext, VMExportFunction,
+    VMFunctionBody, VMFunctionEnvironment, VMFunctionKind, VMTrampoline,
 };
 
 /// A function defined in the Wasm module
@@ -155,6 +157,7 @@ impl Function {
             VMDynamicFunctionContext::from_context(DynamicFunctionWithoutEnv {
                 func: Arc::new(func),
                 function_type: ty.clone(),
+                store: store.clone(),
             });
         // We don't yet have the address with the Wasm ABI signature.
         // The engine linker will replace the address with one pointing to a
@@ -262,6 +265,7 @@ impl Function {
                 env: Box::new(env),
                 func: Arc::new(func),
                 function_type: ty.clone(),
+                store: store.clone(),
             });
 
         let import_init_function_ptr: for<'a> fn(&'a mut _, &'a _) -> Result<(), _> =
@@ -297,6 +301,40 @@ impl Function {
         }
     }
 
+    /// Creates a new async host `Function` (dynamic) with the provided
+    /// signature.
+    ///
+    /// The future returned by `func` is awaited from within the WebAssembly
+    /// call: whenever it isn't ready, the call is suspended and the
+    /// executor polling [`Function::call_async`] gets `Poll::Pending`.
+    /// Async host functions can therefore only be called from WebAssembly
+    /// started with `call_async`; otherwise they fail with a trap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use wasmer::{Function, FunctionType, Type, Store, Value};
+    /// # let store = Store::default();
+    /// #
+    /// let signature = FunctionType::new(vec![Type::I32], vec![Type::I32]);
+    ///
+    /// let f = Function::new_async(&store, &signature, |args| async move {
+    ///     let x = args[0].unwrap_i32();
+    ///     Ok(vec![Value::I32(x * 2)])
+    /// });
+    /// ```
+    #[cfg(feature = "async")]
+    pub fn new_async<FT, F, Fut>(store: &Store, ty: FT, func: F) -> Self
+    where
+        FT: Into<FunctionType>,
+        F: Fn(Vec<Val>) -> Fut + 'static + Send + Sync,
+        Fut: Future<Output = Result<Vec<Val>, RuntimeError>> + 'static + Send,
+    {
+        Self::new(store, ty, move |args| {
+            crate::async_support::block_on(func(args.to_vec()))?
+        })
+    }
+
     /// Creates a new host `Function` from a native function.
     ///
     /// The function signature is automatically retrieved using the
@@ -515,10 +553,15 @@ impl Function {
 
         let mut values_vec = vec![0; max(params.len(), results.len())];
 
+        // References handed to the callee are kept alive until the results
+        // have been read.
+        let _scope = ReferenceScope::enter();
+        let _running = self.store.interrupts().enter();
+
         // Store the argument values into `values_vec`.
         let param_tys = signature.params().iter();
         for ((arg, slot), ty) in params.iter().zip(&mut values_vec).zip(param_tys) {
-            if arg.ty() != *ty {
+            if !val_matches_type(arg, *ty) {
                 let param_types = format_types_for_error_message(params);
                 return Err(RuntimeError::new(format!(
                     "Parameters of type [{}] did not match signature {}",
@@ -526,7 +569,7 @@ impl Function {
                 )));
             }
             unsafe {
-                arg.write_value_to(slot);
+                write_value_to_slot(arg, *ty, slot, &self.store)?;
             }
         }
 
@@ -546,7 +589,7 @@ impl Function {
         for (index, &value_type) in signature.results().iter().enumerate() {
             unsafe {
                 let ptr = values_vec.as_ptr().add(index);
-                results[index] = Val::read_value_from(ptr, value_type);
+                results[index] = read_value_from_slot(ptr, value_type, &self.store);
             }
         }
 
@@ -635,6 +678,16 @@ impl Function {
         Ok(results.into_boxed_slice())
     }
 
+    /// Call the `Function` function asynchronously.
+    ///
+    /// The call runs on a separate stack, so that the async host functions
+    /// it calls (see [`Function::new_async`]) can suspend it while they
+    /// wait.
+    #[cfg(feature = "async")]
+    pub async fn call_async(&self, params: &[Val]) -> Result<Box<[Val]>, RuntimeError> {
+        crate::async_support::on_fiber(|| self.call(params)).await
+    }
+
     pub(crate) fn from_vm_export(store: &Store, wasmer_export: ExportFunction) -> Self {
         if let Some(trampoline) = wasmer_export.vm_function.call_trampoline {
             Self {
@@ -812,6 +865,7 @@ impl fmt::Debug for Function {
 pub(crate) trait VMDynamicFunction: Send + Sync {
     fn call(&self, args: &[Val]) -> Result<Vec<Val>, RuntimeError>;
     fn function_type(&self) -> &FunctionType;
+    fn store(&self) -> &Store;
 }
 
 #[derive(Clone)]
@@ -819,6 +873,7 @@ pub(crate) struct DynamicFunctionWithoutEnv {
     #[allow(clippy::type_complexity)]
     func: Arc<dyn Fn(&[Val]) -> Result<Vec<Val>, RuntimeError> + 'static + Send + Sync>,
     function_type: FunctionType,
+    store: Store,
 }
 
 impl VMDynamicFunction for DynamicFunctionWithoutEnv {
@@ -828,6 +883,9 @@ impl VMDynamicFunction for DynamicFunctionWithoutEnv {
     fn function_type(&self) -> &FunctionType {
         &self.function_type
     }
+    fn store(&self) -> &Store {
+        &self.store
+    }
 }
 
 pub(crate) struct DynamicFunctionWithEnv<Env>
@@ -838,6 +896,7 @@ where
     #[allow(clippy::type_complexity)]
     func: Arc<dyn Fn(&Env, &[Val]) -> Result<Vec<Val>, RuntimeError> + 'static + Send + Sync>,
     env: Box<Env>,
+    store: Store,
 }
 
 impl<Env: Sized + Clone + 'static + Send + Sync> Clone for DynamicFunctionWithEnv<Env> {
@@ -846,6 +905,7 @@ impl<Env: Sized + Clone + 'static + Send + Sync> Clone for DynamicFunctionWithEn
             env: self.env.clone(),
             function_type: self.function_type.clone(),
             func: self.func.clone(),
+            store: self.store.clone(),
         }
     }
 }
@@ -860,6 +920,9 @@ where
     fn function_type(&self) -> &FunctionType {
         &self.function_type
     }
+    fn store(&self) -> &Store {
+        &self.store
+    }
 }
 
 trait VMDynamicFunctionCall<T: VMDynamicFunction> {
@@ -891,24 +954,30 @@ impl<T: VMDynamicFunction> VMDynamicFunctionCall<T> for VMDynamicFunctionContext
         use std::panic::{self, AssertUnwindSafe};
         let result = panic::catch_unwind(AssertUnwindSafe(|| {
             let func_ty = self.ctx.function_type();
+            let store = self.ctx.store();
             let mut args = Vec::with_capacity(func_ty.params().len());
             for (i, ty) in func_ty.params().iter().enumerate() {
-                args.push(Val::read_value_from(values_vec.add(i), *ty));
+                args.push(read_value_from_slot(values_vec.add(i), *ty, store));
             }
             let returns = self.ctx.call(&args)?;
 
             // We need to dynamically check that the returns
             // match the expected types, as well as expected length.
-            let return_types = returns.iter().map(|ret| ret.ty()).collect::<Vec<_>>();
-            if return_types != func_ty.results() {
+            if returns.len() != func_ty.results().len()
+                || !returns
+                    .iter()
+                    .zip(func_ty.results())
+                    .all(|(ret, ty)| val_matches_type(ret, *ty))
+            {
+                let return_types = returns.iter().map(|ret| ret.ty()).collect::<Vec<_>>();
                 return Err(RuntimeError::new(format!(
                     "Dynamic function returned wrong signature. Expected {:?} but got {:?}",
                     func_ty.results(),
                     return_types
                 )));
             }
-            for (i, ret) in returns.iter().enumerate() {
-                ret.write_value_to(values_vec.add(i));
+            for (i, (ret, ty)) in returns.iter().zip(func_ty.results()).enumerate() {
+                write_value_to_slot(ret, *ty, values_vec.add(i), store)?;
             }
             Ok(())
         }));
@@ -1,7 +1,7 @@
 use crate::exports::{ExportError, Exportable};
 use crate::externals::Extern;
 use crate::store::{Store, StoreObject};
-use crate::types::Val;
+use crate::types::{val_from_vm_reference, val_into_vm_reference, val_matches_type, Val};
 use crate::GlobalType;
 use crate::Mutability;
 use crate::RuntimeError;
@@ -67,11 +67,16 @@ impl Global {
             mutability,
             ty: val.ty(),
         });
-        unsafe {
-            global
-                .set_unchecked(val.clone())
-                .map_err(|e| RuntimeError::new(format!("create global for {:?}: {}", val, e)))?;
-        };
+        if val.ty().is_ref() {
+            let reference = val_into_vm_reference(&val, val.ty(), store)?;
+            unsafe { global.set_ref_unchecked(reference) };
+        } else {
+            unsafe {
+                global.set_unchecked(val.clone()).map_err(|e| {
+                    RuntimeError::new(format!("create global for {:?}: {}", val, e))
+                })?;
+            };
+        }
 
         Ok(Self {
             store: store.clone(),
@@ -126,7 +131,11 @@ impl Global {
     /// assert_eq!(g.get(), Value::I32(1));
     /// ```
     pub fn get(&self) -> Val {
-        self.global.get()
+        if self.ty().ty.is_ref() {
+            val_from_vm_reference(self.global.get_ref(), &self.store)
+        } else {
+            self.global.get()
+        }
     }
 
     /// Sets a custom value [`Val`] to the runtime Global.
@@ -174,10 +183,20 @@ impl Global {
         if !val.comes_from_same_store(&self.store) {
             return Err(RuntimeError::new("cross-`Store` values are not supported"));
         }
-        unsafe {
-            self.global
-                .set(val)
-                .map_err(|e| RuntimeError::new(format!("{}", e)))?;
+        let ty = self.ty().ty;
+        if ty.is_ref() && val_matches_type(&val, ty) {
+            let reference = val_into_vm_reference(&val, ty, &self.store)?;
+            unsafe {
+                self.global
+                    .set_ref(reference)
+                    .map_err(|e| RuntimeError::new(format!("{}", e)))?;
+            }
+        } else {
+            unsafe {
+                self.global
+                    .set(val)
+                    .map_err(|e| RuntimeError::new(format!("{}", e)))?;
+            }
         }
         Ok(())
     }
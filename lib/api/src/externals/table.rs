@@ -1,18 +1,18 @@
 use crate::exports::{ExportError, Exportable};
 use crate::externals::Extern;
 use crate::store::Store;
-use crate::types::{Val, ValFuncRef};
+use crate::types::{val_from_vm_reference, val_into_vm_reference, Val};
 use crate::RuntimeError;
 use crate::TableType;
 use loupe::MemoryUsage;
 use std::sync::Arc;
 use wasmer_engine::{Export, ExportTable};
-use wasmer_vm::{Table as RuntimeTable, VMCallerCheckedAnyfunc, VMExportTable};
+use wasmer_vm::{Table as RuntimeTable, VMExportTable, VMReference};
 
 /// A WebAssembly `table` instance.
 ///
 /// The `Table` struct is an array-like structure representing a WebAssembly Table,
-/// which stores function references.
+/// which stores function references or external references.
 ///
 /// A table created by the host or in WebAssembly code will be accessible and
 /// mutable from both host and WebAssembly.
@@ -27,7 +27,7 @@ pub struct Table {
 fn set_table_item(
     table: &dyn RuntimeTable,
     item_index: u32,
-    item: VMCallerCheckedAnyfunc,
+    item: VMReference,
 ) -> Result<(), RuntimeError> {
     table.set(item_index, item).map_err(|e| e.into())
 }
@@ -40,7 +40,7 @@ impl Table {
     /// This function will construct the `Table` using the store
     /// [`BaseTunables`][crate::tunables::BaseTunables].
     pub fn new(store: &Store, ty: TableType, init: Val) -> Result<Self, RuntimeError> {
-        let item = init.into_checked_anyfunc(store)?;
+        let item = val_into_vm_reference(&init, ty.ty, store)?;
         let tunables = store.tunables();
         let style = tunables.table_style(&ty);
         let table = tunables
@@ -71,12 +71,12 @@ impl Table {
     /// Retrieves an element of the table at the provided `index`.
     pub fn get(&self, index: u32) -> Option<Val> {
         let item = self.table.get(index)?;
-        Some(ValFuncRef::from_checked_anyfunc(item, &self.store))
+        Some(val_from_vm_reference(item, &self.store))
     }
 
     /// Sets an element `val` in the Table at the provided `index`.
     pub fn set(&self, index: u32, val: Val) -> Result<(), RuntimeError> {
-        let item = val.into_checked_anyfunc(&self.store)?;
+        let item = val_into_vm_reference(&val, self.ty().ty, &self.store)?;
         set_table_item(self.table.as_ref(), index, item)
     }
 
@@ -95,14 +95,9 @@ impl Table {
     ///
     /// Returns an error if the `delta` is out of bounds for the table.
     pub fn grow(&self, delta: u32, init: Val) -> Result<u32, RuntimeError> {
-        let item = init.into_checked_anyfunc(&self.store)?;
-        match self.table.grow(delta) {
-            Some(len) => {
-                for i in 0..delta {
-                    set_table_item(self.table.as_ref(), len + i, item.clone())?;
-                }
-                Ok(len)
-            }
+        let item = val_into_vm_reference(&init, self.ty().ty, &self.store)?;
+        match self.table.grow(delta, item) {
+            Some(len) => Ok(len),
             None => Err(RuntimeError::new(format!(
                 "failed to grow table by `{}`",
                 delta
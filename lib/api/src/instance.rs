@@ -1,13 +1,14 @@
 use crate::exports::Exports;
-use crate::externals::Extern;
+use crate::externals::{Extern, Global, Memory};
 use crate::module::Module;
-use crate::store::Store;
+use crate::store::{InterruptHandle, Store};
 use crate::{HostEnvInitError, LinkError, RuntimeError};
 use loupe::MemoryUsage;
 use std::fmt;
 use std::sync::{Arc, Mutex};
 use thiserror::Error;
 use wasmer_engine::Resolver;
+use wasmer_types::ExportIndex;
 use wasmer_vm::{InstanceHandle, VMContext};
 
 /// A WebAssembly Instance is a stateful, executable
@@ -160,10 +161,56 @@ impl Instance {
         self.module.store()
     }
 
+    /// Returns an [`InterruptHandle`] that can stop the WebAssembly code
+    /// running in this `Instance`, or in any other instance of its [`Store`].
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.store().interrupt_handle()
+    }
+
     #[doc(hidden)]
     pub fn vmctx_ptr(&self) -> *mut VMContext {
         self.handle.lock().unwrap().vmctx_ptr()
     }
+
+    /// Returns every memory of the instance in index order, including the
+    /// imported ones and the ones that aren't exported.
+    #[doc(hidden)]
+    pub fn all_memories(&self) -> Vec<Memory> {
+        let handle = self.handle.lock().unwrap();
+        let store = self.store();
+        handle
+            .module_ref()
+            .memories
+            .keys()
+            .map(|index| {
+                let export = handle.lookup_by_declaration(&ExportIndex::Memory(index));
+                match Extern::from_vm_export(store, export.into()) {
+                    Extern::Memory(extern_) => extern_,
+                    _ => unreachable!("a memory index resolves to a memory"),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns every global of the instance in index order, including the
+    /// imported ones and the ones that aren't exported.
+    #[doc(hidden)]
+    pub fn all_globals(&self) -> Vec<Global> {
+        let handle = self.handle.lock().unwrap();
+        let store = self.store();
+        handle
+            .module_ref()
+            .globals
+            .keys()
+            .map(|index| {
+                let export = handle.lookup_by_declaration(&ExportIndex::Global(index));
+                match Extern::from_vm_export(store, export.into()) {
+                    Extern::Global(extern_) => extern_,
+                    _ => unreachable!("a global index resolves to a global"),
+                }
+            })
+            .collect()
+    }
 }
 
 impl fmt::Debug for Instance {
@@ -221,6 +221,7 @@
 //! - `llvm` - enable Wasmer's LLVM compiler. (See [wasmer-llvm][])
 //! - `singlepass` - enable Wasmer's Singlepass compiler. (See [wasmer-singlepass][])
 //! - `wat` - enable `wasmer` to parse the WebAssembly text format.
+//! - `async` - enable async host functions and async calls into WebAssembly.
 //!
 //! The features that set defaults come in sets that are mutually exclusive.
 //!
@@ -253,6 +254,8 @@
 //! [wasmer-llvm]: https://docs.rs/wasmer-llvm/*/wasmer_llvm/
 //! [wasmer-wasi]: https://docs.rs/wasmer-wasi/*/wasmer_wasi/
 
+#[cfg(feature = "async")]
+mod async_support;
 mod env;
 mod exports;
 mod externals;
@@ -293,11 +296,11 @@ pub use crate::instance::{Instance, InstantiationError};
 pub use crate::module::Module;
 pub use crate::native::NativeFunc;
 pub use crate::ptr::{Array, Item, WasmPtr};
-pub use crate::store::{Store, StoreObject};
-pub use crate::tunables::BaseTunables;
+pub use crate::store::{InterruptHandle, Store, StoreObject};
+pub use crate::tunables::{BaseTunables, PoolingTunables};
 pub use crate::types::{
-    ExportType, ExternRef, ExternType, FunctionType, GlobalType, HostInfo, HostRef, ImportType,
-    MemoryType, Mutability, TableType, Val, ValType,
+    ExportType, ExternRef, ExternType, FunctionType, GlobalType, HostInfo, ImportType, MemoryType,
+    Mutability, TableType, Val, ValType,
 };
 pub use crate::types::{Val as Value, ValType as Type};
 pub use crate::utils::is_wasm;
@@ -320,7 +323,9 @@ pub use wasmer_types::{
 };
 
 // TODO: should those be moved into wasmer::vm as well?
-pub use wasmer_vm::{raise_user_trap, MemoryError, VMExport};
+pub use wasmer_vm::{
+    raise_user_trap, MemoryError, PoolingAllocator, PoolingLimits, TrapCode, VMExport,
+};
 pub mod vm {
     //! The vm module re-exports wasmer-vm types.
 
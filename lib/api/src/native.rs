@@ -135,6 +135,8 @@ macro_rules! impl_native_traits {
                             }
                             rets_list.as_mut()
                         };
+                        let _scope = wasmer_vm::ReferenceScope::enter();
+                        let _running = self.store.interrupts().enter();
                         unsafe {
                             wasmer_vm::wasmer_call_trampoline(
                                 self.vmctx(),
@@ -210,6 +212,19 @@ macro_rules! impl_native_traits {
                 }
 
             }
+
+            /// Call the typed func asynchronously and return results.
+            ///
+            /// See [`Function::call_async`].
+            #[cfg(feature = "async")]
+            pub async fn call_async(&self, $( $x: $x, )* ) -> Result<Rets, RuntimeError>
+            where
+                $( $x: Send, )*
+                Rets: Send,
+            {
+                let func = self.clone();
+                crate::async_support::on_fiber(move || func.call($( $x, )*)).await
+            }
         }
 
         #[allow(unused_parens)]
@@ -4,7 +4,8 @@ use std::fmt;
 use std::sync::Arc;
 #[cfg(all(feature = "compiler", feature = "engine"))]
 use wasmer_compiler::CompilerConfig;
-use wasmer_engine::{Engine, Tunables};
+use wasmer_engine::{Engine, RuntimeError, Tunables};
+use wasmer_vm::VMInterrupts;
 
 /// The store represents all global state that can be manipulated by
 /// WebAssembly programs. It consists of the runtime representation
@@ -20,6 +21,7 @@ use wasmer_engine::{Engine, Tunables};
 pub struct Store {
     engine: Arc<dyn Engine + Send + Sync>,
     tunables: Arc<dyn Tunables + Send + Sync>,
+    interrupts: Arc<VMInterrupts>,
 }
 
 impl Store {
@@ -31,6 +33,7 @@ impl Store {
         Self {
             engine: engine.cloned(),
             tunables: Arc::new(BaseTunables::for_target(engine.target())),
+            interrupts: Arc::new(VMInterrupts::new(engine.epoch().counter().clone())),
         }
     }
 
@@ -42,6 +45,7 @@ impl Store {
         Self {
             engine: engine.cloned(),
             tunables: Arc::new(tunables),
+            interrupts: Arc::new(VMInterrupts::new(engine.epoch().counter().clone())),
         }
     }
 
@@ -55,6 +59,57 @@ impl Store {
         &self.engine
     }
 
+    /// Returns an [`InterruptHandle`] that can stop the WebAssembly code
+    /// running in the instances of this `Store`, from any thread.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle {
+            interrupts: self.interrupts.clone(),
+        }
+    }
+
+    /// Sets the epoch deadline of this `Store` to `ticks_beyond_current`
+    /// ticks of the engine epoch from now.
+    ///
+    /// WebAssembly code running in this `Store` checks the deadline when it
+    /// enters a function or a loop iteration. Once the engine epoch reaches
+    /// the deadline, it traps with [`TrapCode::Interrupt`], or runs the
+    /// callback set with [`Store::epoch_deadline_callback`].
+    ///
+    /// There is no deadline by default.
+    ///
+    /// [`TrapCode::Interrupt`]: crate::TrapCode::Interrupt
+    pub fn set_epoch_deadline(&self, ticks_beyond_current: u64) {
+        self.interrupts.set_epoch_deadline(ticks_beyond_current);
+    }
+
+    /// Makes the WebAssembly code running in this `Store` trap when the
+    /// epoch deadline is reached. This is the default behavior.
+    pub fn epoch_deadline_trap(&self) {
+        self.interrupts.set_epoch_deadline_callback(None);
+    }
+
+    /// Runs `callback` when the epoch deadline of this `Store` is reached.
+    ///
+    /// The callback returns the number of ticks to extend the deadline by,
+    /// after which execution resumes, or an error to trap with.
+    ///
+    /// The callback may set the deadline behavior of this `Store` again, or
+    /// call into WebAssembly, which runs without a deadline until the
+    /// callback returns.
+    pub fn epoch_deadline_callback<F>(&self, mut callback: F)
+    where
+        F: FnMut() -> Result<u64, RuntimeError> + Send + 'static,
+    {
+        self.interrupts
+            .set_epoch_deadline_callback(Some(Box::new(move || {
+                callback().map_err(|e| Box::new(e) as _)
+            })));
+    }
+
+    pub(crate) fn interrupts(&self) -> &Arc<VMInterrupts> {
+        &self.interrupts
+    }
+
     /// Checks whether two stores are identical. A store is considered
     /// equal to another store if both have the same engine. The
     /// tunables are excluded from the logic.
@@ -109,9 +164,11 @@ impl Default for Store {
         let config = get_config();
         let engine = get_engine(config);
         let tunables = BaseTunables::for_target(engine.target());
+        let interrupts = VMInterrupts::new(engine.epoch().counter().clone());
         Store {
             engine: Arc::new(engine),
             tunables: Arc::new(tunables),
+            interrupts: Arc::new(interrupts),
         }
     }
 }
@@ -122,6 +179,27 @@ impl fmt::Debug for Store {
     }
 }
 
+/// A handle to interrupt the WebAssembly code running in a [`Store`].
+///
+/// Interrupted code traps with [`TrapCode::Interrupt`] when it next enters a
+/// function or a loop iteration. An interruption stops every call running in
+/// the store, including the calls made from host functions, and is dropped
+/// once the last of them returns: it has no effect when nothing is running.
+///
+/// [`TrapCode::Interrupt`]: crate::TrapCode::Interrupt
+#[derive(Clone, Debug)]
+pub struct InterruptHandle {
+    interrupts: Arc<VMInterrupts>,
+}
+
+impl InterruptHandle {
+    /// Requests the WebAssembly code running in the associated [`Store`] to
+    /// stop.
+    pub fn interrupt(&self) {
+        self.interrupts.interrupt();
+    }
+}
+
 /// A trait represinting any object that lives in the `Store`.
 pub trait StoreObject {
     /// Return true if the object `Store` is the same as the provided `Store`.
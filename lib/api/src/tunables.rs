@@ -5,11 +5,11 @@ use std::ptr::NonNull;
 use std::sync::Arc;
 use target_lexicon::{OperatingSystem, PointerWidth};
 use wasmer_compiler::Target;
-use wasmer_engine::Tunables;
+use wasmer_engine::{LinkError, Tunables};
 use wasmer_vm::MemoryError;
 use wasmer_vm::{
-    LinearMemory, LinearTable, Memory, MemoryStyle, Table, TableStyle, VMMemoryDefinition,
-    VMTableDefinition,
+    InstanceAllocator, LinearMemory, LinearTable, Memory, MemoryStyle, ModuleInfo,
+    PoolingAllocator, PoolingLimits, Table, TableStyle, VMMemoryDefinition, VMTableDefinition,
 };
 
 /// Tunable parameters for WebAssembly compilation.
@@ -150,6 +150,128 @@ impl Tunables for BaseTunables {
     }
 }
 
+/// Tunables allocating instances, and their memories and tables, in the
+/// slots of a [`PoolingAllocator`].
+///
+/// Instantiation fails with a [`LinkError::Resource`] once the pool is
+/// exhausted. Memories and tables created by the host are not pooled.
+///
+/// # Example
+///
+/// ```
+/// # use wasmer::{PoolingLimits, PoolingTunables, Store};
+/// # let engine = Store::default().engine().clone();
+/// let limits = PoolingLimits {
+///     max_instances: 10,
+///     ..Default::default()
+/// };
+/// let tunables = PoolingTunables::new(limits).unwrap();
+/// let store = Store::new_with_tunables(&*engine, tunables);
+/// ```
+#[derive(Clone, MemoryUsage)]
+pub struct PoolingTunables {
+    allocator: PoolingAllocator,
+}
+
+impl PoolingTunables {
+    /// Reserves a pool with the given limits.
+    pub fn new(limits: PoolingLimits) -> Result<Self, String> {
+        Ok(Self {
+            allocator: PoolingAllocator::new(limits)?,
+        })
+    }
+
+    /// The allocator of the pool.
+    pub fn allocator(&self) -> &PoolingAllocator {
+        &self.allocator
+    }
+}
+
+impl Tunables for PoolingTunables {
+    /// Get the `MemoryStyle` of the memories of the pool.
+    fn memory_style(&self, _memory: &MemoryType) -> MemoryStyle {
+        self.allocator.memory_style()
+    }
+
+    /// Get a [`TableStyle`] for the provided [`TableType`].
+    fn table_style(&self, _table: &TableType) -> TableStyle {
+        TableStyle::CallerChecksSignature
+    }
+
+    /// Create a memory owned by the host given a [`MemoryType`] and a [`MemoryStyle`].
+    fn create_host_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+    ) -> Result<Arc<dyn Memory>, MemoryError> {
+        if let MemoryStyle::Static { bound, .. } = style {
+            if ty.minimum > *bound {
+                return Err(MemoryError::MinimumMemoryTooLarge {
+                    min_requested: ty.minimum,
+                    max_allowed: *bound,
+                });
+            }
+        }
+        Ok(Arc::new(LinearMemory::new(&ty, &style)?))
+    }
+
+    /// Create a memory owned by the VM in a slot of the pool.
+    ///
+    /// # Safety
+    /// - `vm_definition_location` must point to a valid, owned `VMMemoryDefinition`,
+    ///   for example in `VMContext`.
+    unsafe fn create_vm_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+        vm_definition_location: NonNull<VMMemoryDefinition>,
+    ) -> Result<Arc<dyn Memory>, MemoryError> {
+        self.allocator
+            .create_memory(ty, style, vm_definition_location)
+    }
+
+    /// Create a table owned by the host given a [`TableType`] and a [`TableStyle`].
+    fn create_host_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+    ) -> Result<Arc<dyn Table>, String> {
+        Ok(Arc::new(LinearTable::new(&ty, &style)?))
+    }
+
+    /// Create a table owned by the VM in a slot of the pool.
+    ///
+    /// # Safety
+    /// - `vm_definition_location` must point to a valid, owned `VMTableDefinition`,
+    ///   for example in `VMContext`.
+    unsafe fn create_vm_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+        vm_definition_location: NonNull<VMTableDefinition>,
+    ) -> Result<Arc<dyn Table>, String> {
+        self.allocator
+            .create_table(ty, style, vm_definition_location)
+    }
+
+    /// Allocate the memory of a new instance in a slot of the pool.
+    fn allocate_instance(
+        &self,
+        module: &ModuleInfo,
+    ) -> Result<
+        (
+            InstanceAllocator,
+            Vec<NonNull<VMMemoryDefinition>>,
+            Vec<NonNull<VMTableDefinition>>,
+        ),
+        LinkError,
+    > {
+        self.allocator
+            .allocate_instance(module)
+            .map_err(LinkError::Resource)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -4,9 +4,10 @@ use crate::RuntimeError;
 use std::ptr;
 use wasmer_types::Value;
 pub use wasmer_types::{
-    ExportType, ExternRef, ExternType, FunctionType, GlobalType, HostInfo, HostRef, ImportType,
-    MemoryType, Mutability, TableType, Type as ValType,
+    ExportType, ExternRef, ExternType, FunctionType, GlobalType, HostInfo, ImportType, MemoryType,
+    Mutability, TableType, Type as ValType,
 };
+use wasmer_vm::{VMExternRef, VMRawReference, VMReference};
 
 /// WebAssembly computations manipulate values of basic value types:
 /// * Integers (32 or 64 bit width)
@@ -20,8 +21,8 @@ impl StoreObject for Val {
     fn comes_from_same_store(&self, store: &Store) -> bool {
         match self {
             Self::FuncRef(f) => Store::same(store, f.store()),
-            Self::ExternRef(ExternRef::Ref(_)) | Self::ExternRef(ExternRef::Other(_)) => false,
-            Self::ExternRef(ExternRef::Null) => true,
+            // Host data isn't tied to a `Store`.
+            Self::ExternRef(_) => true,
             Self::I32(_) | Self::I64(_) | Self::F32(_) | Self::F64(_) | Self::V128(_) => true,
         }
     }
@@ -92,3 +93,78 @@ impl ValFuncRef for Val {
         Self::FuncRef(f)
     }
 }
+
+/// Returns whether `val` can be passed where a value of type `ty` is
+/// expected.
+///
+/// A null `funcref` is represented by a null `externref` on the host side,
+/// so the latter is accepted as a `funcref` too.
+pub(crate) fn val_matches_type(val: &Val, ty: ValType) -> bool {
+    val.ty() == ty || (ty == ValType::FuncRef && matches!(val, Val::ExternRef(ExternRef::Null)))
+}
+
+/// Converts a reference [`Val`] of type `ty` into a reference held by the VM.
+pub(crate) fn val_into_vm_reference(
+    val: &Val,
+    ty: ValType,
+    store: &Store,
+) -> Result<VMReference, RuntimeError> {
+    match (ty, val) {
+        (ValType::FuncRef, _) => Ok(VMReference::FuncRef(val.into_checked_anyfunc(store)?)),
+        (ValType::ExternRef, Val::ExternRef(extern_ref)) => {
+            Ok(VMReference::ExternRef(VMExternRef::new(extern_ref.clone())))
+        }
+        _ => Err(RuntimeError::new(format!("val is not {}", ty))),
+    }
+}
+
+/// Converts a reference held by the VM into a [`Val`].
+pub(crate) fn val_from_vm_reference(reference: VMReference, store: &Store) -> Val {
+    match reference {
+        VMReference::FuncRef(anyfunc) => Val::from_checked_anyfunc(anyfunc, store),
+        VMReference::ExternRef(extern_ref) => Val::ExternRef(
+            extern_ref
+                .map(|extern_ref| extern_ref.extern_ref())
+                .unwrap_or(ExternRef::Null),
+        ),
+    }
+}
+
+/// Writes a value of type `ty` to a slot of the values passed to and from
+/// compiled code.
+///
+/// References are handed out to compiled code, so this must be called within
+/// a [`wasmer_vm::ReferenceScope`].
+///
+/// # Safety
+///
+/// `slot` must be valid for writes of a value of type `ty`.
+pub(crate) unsafe fn write_value_to_slot(
+    val: &Val,
+    ty: ValType,
+    slot: *mut i128,
+    store: &Store,
+) -> Result<(), RuntimeError> {
+    if ty.is_ref() {
+        let raw = val_into_vm_reference(val, ty, store)?.into_raw();
+        ptr::write(slot as *mut VMRawReference, raw);
+    } else {
+        val.write_value_to(slot);
+    }
+    Ok(())
+}
+
+/// Reads a value of type `ty` from a slot of the values passed to and from
+/// compiled code.
+///
+/// # Safety
+///
+/// `slot` must hold a value of type `ty`, and references must be live.
+pub(crate) unsafe fn read_value_from_slot(slot: *const i128, ty: ValType, store: &Store) -> Val {
+    if ty.is_ref() {
+        let raw = ptr::read(slot as *const VMRawReference);
+        val_from_vm_reference(VMReference::from_raw(ty, raw), store)
+    } else {
+        Val::read_value_from(slot, ty)
+    }
+}
@@ -0,0 +1,148 @@
+#![cfg(feature = "async")]
+
+use anyhow::Result;
+use std::future::Future;
+use std::pin::Pin;
+use std::ptr;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use wasmer::*;
+
+fn noop_waker() -> Waker {
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(ptr::null(), &VTABLE)
+    }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+    unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) }
+}
+
+/// Polls `future` to completion, returning its output and the number of
+/// times it was pending.
+fn run<F: Future>(future: F) -> (F::Output, usize) {
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    let mut pending = 0;
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return (output, pending),
+            Poll::Pending => pending += 1,
+        }
+    }
+}
+
+/// A future that is pending the first time it is polled.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            Poll::Pending
+        }
+    }
+}
+
+fn async_instance(store: &Store) -> Result<Instance> {
+    let module = Module::new(
+        store,
+        r#"
+(module
+  (import "host" "double" (func $double (param i32) (result i32)))
+  (func (export "run") (param i32) (result i32)
+    (call $double (call $double (local.get 0)))))
+"#,
+    )?;
+    let double = Function::new_async(
+        store,
+        FunctionType::new(vec![Type::I32], vec![Type::I32]),
+        |args| async move {
+            YieldNow(false).await;
+            Ok(vec![Value::I32(args[0].unwrap_i32() * 2)])
+        },
+    );
+    Ok(Instance::new(
+        &module,
+        &imports! {
+            "host" => {
+                "double" => double,
+            },
+        },
+    )?)
+}
+
+#[test]
+fn call_async_suspends_in_host_functions() -> Result<()> {
+    let store = Store::default();
+    let instance = async_instance(&store)?;
+    let run_func = instance.exports.get_function("run")?;
+
+    let (result, pending) = run(run_func.call_async(&[Value::I32(3)]));
+    assert_eq!(result?.to_vec(), vec![Value::I32(12)]);
+    assert_eq!(pending, 2);
+
+    let run_native = run_func.native::<i32, i32>()?;
+    let (result, pending) = run(run_native.call_async(5));
+    assert_eq!(result?, 20);
+    assert_eq!(pending, 2);
+
+    Ok(())
+}
+
+#[test]
+fn async_host_function_outside_call_async() -> Result<()> {
+    let store = Store::default();
+    let instance = async_instance(&store)?;
+    let run_func = instance.exports.get_function("run")?;
+
+    let err = run_func.call(&[Value::I32(3)]).unwrap_err();
+    assert!(err.message().contains("call_async"));
+
+    Ok(())
+}
+
+#[test]
+fn call_async_traps() -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(
+        &store,
+        r#"
+(module
+  (import "host" "yield" (func $yield))
+  (func (export "run")
+    (call $yield)
+    unreachable))
+"#,
+    )?;
+    let yield_ = Function::new_async(&store, FunctionType::new(vec![], vec![]), |_| async {
+        YieldNow(false).await;
+        Ok(vec![])
+    });
+    let instance = Instance::new(
+        &module,
+        &imports! {
+            "host" => {
+                "yield" => yield_,
+            },
+        },
+    )?;
+    let run_func = instance.exports.get_function("run")?;
+
+    let (result, pending) = run(run_func.call_async(&[]));
+    let err = result.unwrap_err();
+    assert_eq!(err.to_trap(), Some(TrapCode::UnreachableCodeReached));
+    assert_eq!(pending, 1);
+
+    // Dropping a suspended call cancels it.
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(run_func.call_async(&[]));
+    assert!(future.as_mut().poll(&mut cx).is_pending());
+    drop(future);
+
+    Ok(())
+}
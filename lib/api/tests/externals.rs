@@ -58,6 +58,45 @@ fn global_set() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn global_externref() -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct CountFinalize(Arc<AtomicUsize>);
+    impl HostInfo for CountFinalize {
+        fn finalize(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let store = Store::default();
+    let finalized = Arc::new(AtomicUsize::new(0));
+    let r = ExternRef::new_with_host_info(
+        String::from("host data"),
+        Some(Box::new(CountFinalize(finalized.clone()))),
+    );
+
+    let global = Global::new_mut(&store, Value::ExternRef(ExternRef::Null));
+    global.set(Value::ExternRef(r.clone()))?;
+    drop(r);
+    let r = global.get().unwrap_externref();
+    assert_eq!(
+        r.downcast::<String>().map(String::as_str),
+        Some("host data")
+    );
+    drop(r);
+    assert_eq!(finalized.load(Ordering::SeqCst), 0);
+
+    // The global held the last reference.
+    global.set(Value::ExternRef(ExternRef::Null))?;
+    assert_eq!(finalized.load(Ordering::SeqCst), 1);
+    drop(global);
+    assert_eq!(finalized.load(Ordering::SeqCst), 1);
+
+    Ok(())
+}
+
 #[test]
 fn table_new() -> Result<()> {
     let store = Store::default();
@@ -70,14 +109,13 @@ fn table_new() -> Result<()> {
     let table = Table::new(&store, table_type, Value::FuncRef(f))?;
     assert_eq!(*table.ty(), table_type);
 
-    // Anyrefs not yet supported
-    // let table_type = TableType {
-    //     ty: Type::ExternRef,
-    //     minimum: 0,
-    //     maximum: None,
-    // };
-    // let table = Table::new(&store, table_type, Value::ExternRef(ExternRef::Null))?;
-    // assert_eq!(*table.ty(), table_type);
+    let table_type = TableType {
+        ty: Type::ExternRef,
+        minimum: 0,
+        maximum: None,
+    };
+    let table = Table::new(&store, table_type, Value::ExternRef(ExternRef::Null))?;
+    assert_eq!(*table.ty(), table_type);
 
     Ok(())
 }
@@ -127,6 +165,34 @@ fn table_grow() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn table_externref() -> Result<()> {
+    let store = Store::default();
+    let table_type = TableType {
+        ty: Type::ExternRef,
+        minimum: 2,
+        maximum: Some(4),
+    };
+    let table = Table::new(&store, table_type, Value::ExternRef(ExternRef::Null))?;
+    assert_eq!(table.get(0), Some(Value::ExternRef(ExternRef::Null)));
+
+    let r = ExternRef::new(42u32);
+    table.set(1, Value::ExternRef(r.clone()))?;
+    assert_eq!(table.get(1), Some(Value::ExternRef(r.clone())));
+    assert!(table.set(2, Value::ExternRef(r.clone())).is_err());
+
+    let old_len = table.grow(2, Value::ExternRef(r.clone()))?;
+    assert_eq!(old_len, 2);
+    assert_eq!(table.get(3), Some(Value::ExternRef(r.clone())));
+    assert!(table.grow(1, Value::ExternRef(r)).is_err());
+
+    // A table of `externref`s can't hold functions.
+    let f = Function::new_native(&store, || {});
+    assert!(table.set(0, Value::FuncRef(f)).is_err());
+
+    Ok(())
+}
+
 #[test]
 #[ignore]
 fn table_copy() -> Result<()> {
@@ -7,9 +7,13 @@ use crate::warning;
 use anyhow::{anyhow, Context, Result};
 use std::path::PathBuf;
 use std::str::FromStr;
+#[cfg(all(feature = "compiler", feature = "engine"))]
+use std::sync::Arc;
 use wasmer::*;
 #[cfg(feature = "cache")]
 use wasmer_cache::{Cache, FileSystemCache, Hash};
+#[cfg(all(feature = "compiler", feature = "engine"))]
+use wasmer_middlewares::profiling::{get_function_profile, Profiling};
 
 use clap::Clap;
 
@@ -49,6 +53,15 @@ pub struct Run {
     #[clap(flatten)]
     store: StoreOptions,
 
+    /// Count the calls and executed operators of every function, and print
+    /// the functions that executed the most operators on exit
+    #[clap(long = "profile")]
+    profile: bool,
+
+    /// How many functions `--profile` prints
+    #[clap(long = "profile-top", default_value = "20")]
+    profile_top: usize,
+
     // TODO: refactor WASI structure to allow shared options with Emscripten
     #[cfg(feature = "wasi")]
     #[clap(flatten)]
@@ -95,7 +108,9 @@ impl Run {
         if let Some(ref invoke) = self.invoke {
             let imports = imports! {};
             let instance = Instance::new(&module, &imports)?;
-            let result = self.invoke_function(&instance, &invoke, &self.args)?;
+            let result = self.invoke_function(&instance, &invoke, &self.args);
+            self.print_profile(&instance);
+            let result = result?;
             println!(
                 "{}",
                 result
@@ -133,7 +148,7 @@ impl Run {
                     }
                 };
 
-                run_emscripten_instance(
+                let result = run_emscripten_instance(
                     &mut instance,
                     &mut em_env,
                     &mut emscripten_globals,
@@ -144,7 +159,9 @@ impl Run {
                     },
                     self.args.iter().map(|arg| arg.as_str()).collect(),
                     None, //run.em_entrypoint.clone(),
-                )?;
+                );
+                self.print_profile(&instance);
+                result?;
                 return Ok(());
             }
         }
@@ -165,7 +182,9 @@ impl Run {
                     .unwrap_or_default();
                 return self
                     .wasi
-                    .execute(module, program_name, self.args.clone())
+                    .execute(module, program_name, self.args.clone(), |instance| {
+                        self.print_profile(instance)
+                    })
                     .with_context(|| "WASI execution failed");
             }
         }
@@ -174,7 +193,9 @@ impl Run {
         let imports = imports! {};
         let instance = Instance::new(&module, &imports)?;
         let start: Function = self.try_find_function(&instance, "_start", &[])?;
-        start.call(&[])?;
+        let result = start.call(&[]);
+        self.print_profile(&instance);
+        result?;
 
         Ok(())
     }
@@ -199,13 +220,15 @@ impl Run {
                 return Ok(module);
             }
         }
-        let (store, engine_type, compiler_type) = self.store.get_store()?;
+        let (store, engine_type, compiler_type) = self.get_store()?;
+        // Modules compiled for `--profile` aren't cached, as they have counters
         #[cfg(feature = "cache")]
-        let module_result: Result<Module> = if !self.disable_cache && contents.len() > 0x1000 {
-            self.get_module_from_cache(&store, &contents, &engine_type, &compiler_type)
-        } else {
-            Module::new(&store, &contents).map_err(|e| e.into())
-        };
+        let module_result: Result<Module> =
+            if !self.disable_cache && !self.profile && contents.len() > 0x1000 {
+                self.get_module_from_cache(&store, &contents, &engine_type, &compiler_type)
+            } else {
+                Module::new(&store, &contents).map_err(|e| e.into())
+            };
         #[cfg(not(feature = "cache"))]
         let module_result = Module::new(&store, &contents);
 
@@ -222,6 +245,62 @@ impl Run {
         Ok(module)
     }
 
+    /// Gets the store to compile the module with, which counts the calls and
+    /// operators of every function with `--profile`.
+    #[cfg(all(feature = "compiler", feature = "engine"))]
+    fn get_store(&self) -> Result<(Store, EngineType, CompilerType)> {
+        if self.profile {
+            let profiling: Arc<dyn ModuleMiddleware> =
+                Arc::new(Profiling::new(|_: &wasmparser::Operator| 1));
+            return self.store.get_store_with_middlewares(vec![profiling]);
+        }
+        self.store.get_store()
+    }
+
+    #[cfg(not(all(feature = "compiler", feature = "engine")))]
+    fn get_store(&self) -> Result<(Store, EngineType, CompilerType)> {
+        if self.profile {
+            bail!("`--profile` needs a compiler and an engine");
+        }
+        self.store.get_store()
+    }
+
+    /// Prints the functions that executed the most operators, for `--profile`.
+    #[cfg(all(feature = "compiler", feature = "engine"))]
+    fn print_profile(&self, instance: &Instance) {
+        if !self.profile {
+            return;
+        }
+        let mut profile = get_function_profile(instance);
+        if profile.is_empty() {
+            warning!("the module wasn't compiled with `--profile`, so it has no profile");
+            return;
+        }
+        let total_cost = profile
+            .iter()
+            .map(|function| function.cost)
+            .sum::<u64>()
+            .max(1);
+        profile.sort_by(|a, b| b.cost.cmp(&a.cost).then(b.calls.cmp(&a.calls)));
+        eprintln!("{:>14} {:>7} {:>12}  function", "operators", "%", "calls");
+        for function in profile.iter().take(self.profile_top) {
+            let name = function
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("<function {}>", function.index.as_u32()));
+            eprintln!(
+                "{:>14} {:>6.2}% {:>12}  {}",
+                function.cost,
+                100.0 * function.cost as f64 / total_cost as f64,
+                function.calls,
+                name
+            );
+        }
+    }
+
+    #[cfg(not(all(feature = "compiler", feature = "engine")))]
+    fn print_profile(&self, _instance: &Instance) {}
+
     #[cfg(feature = "cache")]
     fn get_module_from_cache(
         &self,
@@ -1,8 +1,11 @@
 use crate::utils::{parse_envvar, parse_mapdir};
 use anyhow::{Context, Result};
 use std::path::PathBuf;
+use std::sync::Arc;
 use wasmer::{Instance, Module};
-use wasmer_wasi::{get_wasi_version, WasiError, WasiState, WasiVersion};
+use wasmer_wasi::{
+    get_wasi_version, SyscallEvent, SyscallObserver, WasiError, WasiState, WasiVersion,
+};
 
 use clap::Clap;
 
@@ -21,6 +24,10 @@ pub struct Wasi {
     #[clap(long = "env", name = "KEY=VALUE", multiple = true, parse(try_from_str = parse_envvar))]
     env_vars: Vec<(String, String)>,
 
+    /// Print every WASI syscall to stderr, like `strace`
+    #[clap(long = "wasi-trace")]
+    trace: bool,
+
     /// Enable experimental IO devices
     #[cfg(feature = "experimental-io-devices")]
     #[clap(long = "enable-experimental-io-devices")]
@@ -44,7 +51,16 @@ impl Wasi {
     }
 
     /// Helper function for executing Wasi from the `Run` command.
-    pub fn execute(&self, module: Module, program_name: String, args: Vec<String>) -> Result<()> {
+    ///
+    /// `on_exit` is called with the instance once `_start` returns, before
+    /// the process exits with the code the program gave.
+    pub fn execute(
+        &self,
+        module: Module,
+        program_name: String,
+        args: Vec<String>,
+        on_exit: impl FnOnce(&Instance),
+    ) -> Result<()> {
         let args = args.iter().cloned().map(|arg| arg.into_bytes());
 
         let mut wasi_state_builder = WasiState::new(program_name);
@@ -63,20 +79,35 @@ impl Wasi {
         }
 
         let mut wasi_env = wasi_state_builder.finalize()?;
+        if self.trace {
+            wasi_env.set_syscall_observer(Arc::new(StderrTracer));
+        }
         let import_object = wasi_env.import_object(&module)?;
         let instance = Instance::new(&module, &import_object)?;
 
         let start = instance.exports.get_function("_start")?;
         let result = start.call(&[]);
+        // The program ends when `_start` returns, or when one of its threads
+        // exits or traps, which interrupts `_start`.
+        wasi_env.stop_threads();
+        let result = match wasi_env.join_threads() {
+            Ok(()) => result.map(drop),
+            Err(err) => Err(err),
+        };
+        on_exit(&instance);
 
         match result {
-            Ok(_) => Ok(()),
+            Ok(()) => Ok(()),
             Err(err) => {
                 let err: anyhow::Error = match err.downcast::<WasiError>() {
                     Ok(WasiError::Exit(exit_code)) => {
                         // We should exit with the provided exit code
                         std::process::exit(exit_code as _);
                     }
+                    Ok(WasiError::Signal(signal)) => {
+                        // Exit like a shell reports a process killed by a signal
+                        std::process::exit(128 + signal as i32);
+                    }
                     Ok(err) => err.into(),
                     Err(err) => err.into(),
                 };
@@ -86,3 +117,13 @@ impl Wasi {
         .with_context(|| "failed to run WASI `_start` function")
     }
 }
+
+/// Prints every syscall to stderr, for `--wasi-trace`.
+#[derive(Debug)]
+struct StderrTracer;
+
+impl SyscallObserver for StderrTracer {
+    fn on_syscall(&self, event: &SyscallEvent) {
+        eprintln!("{}", event);
+    }
+}
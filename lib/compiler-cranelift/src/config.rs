@@ -143,6 +143,13 @@ impl Cranelift {
             flags.enable("is_pic").expect("should be a valid flag");
         }
 
+        // Cranelift refuses to compile functions using reference types
+        // without safepoints. References are kept alive by the VM rather than
+        // by stack maps, so the stack maps themselves are not used.
+        flags
+            .enable("enable_safepoints")
+            .expect("should be a valid flag");
+
         // Invert cranelift's default-on verification to instead default off.
         let enable_verifier = if self.enable_verifier {
             "true"
@@ -2,7 +2,8 @@
 // Attributions: https://github.com/wasmerio/wasmer/blob/master/ATTRIBUTIONS.md
 
 use crate::translator::{
-    type_to_irtype, FuncEnvironment as BaseFuncEnvironment, GlobalVariable, TargetEnvironment,
+    type_to_irtype, FuncEnvironment as BaseFuncEnvironment, FuncTranslationState, GlobalVariable,
+    TargetEnvironment,
 };
 use cranelift_codegen::cursor::FuncCursor;
 use cranelift_codegen::ir;
@@ -83,6 +84,32 @@ pub struct FuncEnvironment<'module_environment> {
     /// The external function signature for implementing wasm's `data.drop`.
     data_drop_sig: Option<ir::SigRef>,
 
+    /// The external function signature for implementing wasm's `table.get`.
+    table_get_sig: Option<ir::SigRef>,
+
+    /// The external function signature for implementing wasm's `table.set`.
+    table_set_sig: Option<ir::SigRef>,
+
+    /// The external function signature for implementing wasm's `table.grow`.
+    table_grow_sig: Option<ir::SigRef>,
+
+    /// The external function signature for implementing wasm's `table.fill`.
+    table_fill_sig: Option<ir::SigRef>,
+
+    /// The external function signature for implementing wasm's `ref.func`.
+    func_ref_sig: Option<ir::SigRef>,
+
+    /// The external function signature for implementing wasm's `global.get`
+    /// of a global of a reference type.
+    global_get_ref_sig: Option<ir::SigRef>,
+
+    /// The external function signature for implementing wasm's `global.set`
+    /// of a global of a reference type.
+    global_set_ref_sig: Option<ir::SigRef>,
+
+    /// The external function signature for implementing the interrupt check.
+    interrupt_sig: Option<ir::SigRef>,
+
     /// Offsets to struct fields accessed by JIT code.
     offsets: VMOffsets,
 
@@ -115,6 +142,14 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
             memory_fill_sig: None,
             memory_init_sig: None,
             data_drop_sig: None,
+            table_get_sig: None,
+            table_set_sig: None,
+            table_grow_sig: None,
+            table_fill_sig: None,
+            func_ref_sig: None,
+            global_get_ref_sig: None,
+            global_set_ref_sig: None,
+            interrupt_sig: None,
             offsets: VMOffsets::new(target_config.pointer_bytes(), module),
             memory_styles,
             table_styles,
@@ -445,6 +480,314 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
         (sig, VMBuiltinFunctionIndex::get_data_drop_index())
     }
 
+    fn get_table_get_sig(&mut self, func: &mut Function) -> ir::SigRef {
+        let sig = self.table_get_sig.unwrap_or_else(|| {
+            func.import_signature(Signature {
+                params: vec![
+                    AbiParam::special(self.pointer_type(), ArgumentPurpose::VMContext),
+                    // Table index.
+                    AbiParam::new(I32),
+                    // Index within table.
+                    AbiParam::new(I32),
+                ],
+                returns: vec![AbiParam::new(self.reference_type())],
+                call_conv: self.target_config.default_call_conv,
+            })
+        });
+        self.table_get_sig = Some(sig);
+        sig
+    }
+
+    fn get_table_get_func(
+        &mut self,
+        func: &mut Function,
+        table_index: TableIndex,
+    ) -> (ir::SigRef, usize, VMBuiltinFunctionIndex) {
+        let sig = self.get_table_get_sig(func);
+        (
+            sig,
+            table_index.as_u32() as usize,
+            VMBuiltinFunctionIndex::get_table_get_index(),
+        )
+    }
+
+    fn get_table_set_sig(&mut self, func: &mut Function) -> ir::SigRef {
+        let sig = self.table_set_sig.unwrap_or_else(|| {
+            func.import_signature(Signature {
+                params: vec![
+                    AbiParam::special(self.pointer_type(), ArgumentPurpose::VMContext),
+                    // Table index.
+                    AbiParam::new(I32),
+                    // Index within table.
+                    AbiParam::new(I32),
+                    // Value to set.
+                    AbiParam::new(self.reference_type()),
+                ],
+                returns: vec![],
+                call_conv: self.target_config.default_call_conv,
+            })
+        });
+        self.table_set_sig = Some(sig);
+        sig
+    }
+
+    fn get_table_set_func(
+        &mut self,
+        func: &mut Function,
+        table_index: TableIndex,
+    ) -> (ir::SigRef, usize, VMBuiltinFunctionIndex) {
+        let sig = self.get_table_set_sig(func);
+        (
+            sig,
+            table_index.as_u32() as usize,
+            VMBuiltinFunctionIndex::get_table_set_index(),
+        )
+    }
+
+    fn get_table_grow_sig(&mut self, func: &mut Function) -> ir::SigRef {
+        let sig = self.table_grow_sig.unwrap_or_else(|| {
+            func.import_signature(Signature {
+                params: vec![
+                    AbiParam::special(self.pointer_type(), ArgumentPurpose::VMContext),
+                    // Value to initialize the new elements with.
+                    AbiParam::new(self.reference_type()),
+                    // Number of elements to grow by.
+                    AbiParam::new(I32),
+                    // Table index.
+                    AbiParam::new(I32),
+                ],
+                returns: vec![AbiParam::new(I32)],
+                call_conv: self.target_config.default_call_conv,
+            })
+        });
+        self.table_grow_sig = Some(sig);
+        sig
+    }
+
+    fn get_table_grow_func(
+        &mut self,
+        func: &mut Function,
+        table_index: TableIndex,
+    ) -> (ir::SigRef, usize, VMBuiltinFunctionIndex) {
+        let sig = self.get_table_grow_sig(func);
+        (
+            sig,
+            table_index.as_u32() as usize,
+            VMBuiltinFunctionIndex::get_table_grow_index(),
+        )
+    }
+
+    fn get_table_fill_sig(&mut self, func: &mut Function) -> ir::SigRef {
+        let sig = self.table_fill_sig.unwrap_or_else(|| {
+            func.import_signature(Signature {
+                params: vec![
+                    AbiParam::special(self.pointer_type(), ArgumentPurpose::VMContext),
+                    // Table index.
+                    AbiParam::new(I32),
+                    // Start index within table.
+                    AbiParam::new(I32),
+                    // Value to fill with.
+                    AbiParam::new(self.reference_type()),
+                    // Number of elements to fill.
+                    AbiParam::new(I32),
+                ],
+                returns: vec![],
+                call_conv: self.target_config.default_call_conv,
+            })
+        });
+        self.table_fill_sig = Some(sig);
+        sig
+    }
+
+    fn get_table_fill_func(
+        &mut self,
+        func: &mut Function,
+        table_index: TableIndex,
+    ) -> (ir::SigRef, usize, VMBuiltinFunctionIndex) {
+        let sig = self.get_table_fill_sig(func);
+        (
+            sig,
+            table_index.as_u32() as usize,
+            VMBuiltinFunctionIndex::get_table_fill_index(),
+        )
+    }
+
+    fn get_func_ref_sig(&mut self, func: &mut Function) -> ir::SigRef {
+        let sig = self.func_ref_sig.unwrap_or_else(|| {
+            func.import_signature(Signature {
+                params: vec![
+                    AbiParam::special(self.pointer_type(), ArgumentPurpose::VMContext),
+                    // Function index.
+                    AbiParam::new(I32),
+                ],
+                returns: vec![AbiParam::new(self.reference_type())],
+                call_conv: self.target_config.default_call_conv,
+            })
+        });
+        self.func_ref_sig = Some(sig);
+        sig
+    }
+
+    fn get_func_ref_func(
+        &mut self,
+        func: &mut Function,
+        function_index: FunctionIndex,
+    ) -> (ir::SigRef, usize, VMBuiltinFunctionIndex) {
+        let sig = self.get_func_ref_sig(func);
+        (
+            sig,
+            function_index.as_u32() as usize,
+            VMBuiltinFunctionIndex::get_func_ref_index(),
+        )
+    }
+
+    fn get_global_get_ref_sig(&mut self, func: &mut Function) -> ir::SigRef {
+        let sig = self.global_get_ref_sig.unwrap_or_else(|| {
+            func.import_signature(Signature {
+                params: vec![
+                    AbiParam::special(self.pointer_type(), ArgumentPurpose::VMContext),
+                    // Global index.
+                    AbiParam::new(I32),
+                ],
+                returns: vec![AbiParam::new(self.reference_type())],
+                call_conv: self.target_config.default_call_conv,
+            })
+        });
+        self.global_get_ref_sig = Some(sig);
+        sig
+    }
+
+    fn get_global_get_ref_func(
+        &mut self,
+        func: &mut Function,
+        global_index: GlobalIndex,
+    ) -> (ir::SigRef, usize, VMBuiltinFunctionIndex) {
+        let sig = self.get_global_get_ref_sig(func);
+        (
+            sig,
+            global_index.as_u32() as usize,
+            VMBuiltinFunctionIndex::get_global_get_ref_index(),
+        )
+    }
+
+    fn get_global_set_ref_sig(&mut self, func: &mut Function) -> ir::SigRef {
+        let sig = self.global_set_ref_sig.unwrap_or_else(|| {
+            func.import_signature(Signature {
+                params: vec![
+                    AbiParam::special(self.pointer_type(), ArgumentPurpose::VMContext),
+                    // Global index.
+                    AbiParam::new(I32),
+                    // Value to set.
+                    AbiParam::new(self.reference_type()),
+                ],
+                returns: vec![],
+                call_conv: self.target_config.default_call_conv,
+            })
+        });
+        self.global_set_ref_sig = Some(sig);
+        sig
+    }
+
+    fn get_global_set_ref_func(
+        &mut self,
+        func: &mut Function,
+        global_index: GlobalIndex,
+    ) -> (ir::SigRef, usize, VMBuiltinFunctionIndex) {
+        let sig = self.get_global_set_ref_sig(func);
+        (
+            sig,
+            global_index.as_u32() as usize,
+            VMBuiltinFunctionIndex::get_global_set_ref_index(),
+        )
+    }
+
+    fn get_interrupt_sig(&mut self, func: &mut Function) -> ir::SigRef {
+        let sig = self.interrupt_sig.unwrap_or_else(|| {
+            func.import_signature(Signature {
+                params: vec![AbiParam::special(
+                    self.pointer_type(),
+                    ArgumentPurpose::VMContext,
+                )],
+                returns: vec![],
+                call_conv: self.target_config.default_call_conv,
+            })
+        });
+        self.interrupt_sig = Some(sig);
+        sig
+    }
+
+    fn get_interrupt_func(&mut self, func: &mut Function) -> (ir::SigRef, VMBuiltinFunctionIndex) {
+        let sig = self.get_interrupt_sig(func);
+        (sig, VMBuiltinFunctionIndex::get_interrupt_index())
+    }
+
+    /// Emits a check of the interrupt flag and of the epoch deadline, calling
+    /// into the runtime when the flag is set or the deadline is reached.
+    fn translate_interrupt_check(&mut self, builder: &mut FunctionBuilder) {
+        let pointer_type = self.pointer_type();
+        let vmctx = self.vmctx(&mut builder.func);
+        let base = builder.ins().global_value(pointer_type, vmctx);
+
+        let mut mem_flags = ir::MemFlags::trusted();
+        mem_flags.set_readonly();
+        let interrupts_offset = i32::try_from(self.offsets.vmctx_interrupts()).unwrap();
+        let interrupts = builder
+            .ins()
+            .load(pointer_type, mem_flags, base, interrupts_offset);
+
+        // The flag, the deadline and the epoch are written by other threads,
+        // so they must not be considered read-only.
+        let interrupted = builder.ins().load(
+            I32,
+            ir::MemFlags::trusted(),
+            interrupts,
+            i32::from(self.offsets.vminterrupts_interrupted()),
+        );
+
+        let epoch_block = builder.create_block();
+        let interrupted_block = builder.create_block();
+        let continuation_block = builder.create_block();
+        builder.ins().brnz(interrupted, interrupted_block, &[]);
+        builder.ins().jump(epoch_block, &[]);
+        builder.seal_block(epoch_block);
+
+        builder.switch_to_block(epoch_block);
+        let deadline = builder.ins().load(
+            I64,
+            ir::MemFlags::trusted(),
+            interrupts,
+            i32::from(self.offsets.vminterrupts_epoch_deadline()),
+        );
+        let epoch_ptr = builder.ins().load(
+            pointer_type,
+            mem_flags,
+            interrupts,
+            i32::from(self.offsets.vminterrupts_epoch()),
+        );
+        let epoch = builder
+            .ins()
+            .load(I64, ir::MemFlags::trusted(), epoch_ptr, 0);
+        builder.ins().br_icmp(
+            IntCC::UnsignedGreaterThanOrEqual,
+            epoch,
+            deadline,
+            interrupted_block,
+            &[],
+        );
+        builder.ins().jump(continuation_block, &[]);
+        builder.seal_block(interrupted_block);
+
+        builder.switch_to_block(interrupted_block);
+        let (func_sig, func_idx) = self.get_interrupt_func(&mut builder.func);
+        let (vmctx, func_addr) =
+            self.translate_load_builtin_function_address(&mut builder.cursor(), func_idx);
+        builder.ins().call_indirect(func_sig, func_addr, &[vmctx]);
+        builder.ins().jump(continuation_block, &[]);
+        builder.seal_block(continuation_block);
+
+        builder.switch_to_block(continuation_block);
+    }
+
     /// Translates load of builtin function and returns a pair of values `vmctx`
     /// and address of the loaded function.
     fn translate_load_builtin_function_address(
@@ -540,115 +883,164 @@ impl<'module_environment> BaseFuncEnvironment for FuncEnvironment<'module_enviro
 
     fn translate_table_grow(
         &mut self,
-        mut _pos: cranelift_codegen::cursor::FuncCursor<'_>,
-        _table_index: TableIndex,
+        mut pos: cranelift_codegen::cursor::FuncCursor<'_>,
+        table_index: TableIndex,
         _table: ir::Table,
-        _delta: ir::Value,
-        _init_value: ir::Value,
+        delta: ir::Value,
+        init_value: ir::Value,
     ) -> WasmResult<ir::Value> {
-        Err(WasmError::Unsupported(
-            "the `table.grow` instruction is not supported yet".into(),
-        ))
+        let (func_sig, table_index_arg, func_idx) =
+            self.get_table_grow_func(&mut pos.func, table_index);
+
+        let table_index_arg = pos.ins().iconst(I32, table_index_arg as i64);
+
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(&mut pos, func_idx);
+
+        let call_inst = pos.ins().call_indirect(
+            func_sig,
+            func_addr,
+            &[vmctx, init_value, delta, table_index_arg],
+        );
+
+        Ok(*pos.func.dfg.inst_results(call_inst).first().unwrap())
     }
 
     fn translate_table_get(
         &mut self,
-        _builder: &mut FunctionBuilder,
-        _table_index: TableIndex,
+        builder: &mut FunctionBuilder,
+        table_index: TableIndex,
         _table: ir::Table,
-        _index: ir::Value,
+        index: ir::Value,
     ) -> WasmResult<ir::Value> {
-        Err(WasmError::Unsupported(
-            "the `table.get` instruction is not supported yet".into(),
-        ))
+        let mut pos = builder.cursor();
+
+        let (func_sig, table_index_arg, func_idx) =
+            self.get_table_get_func(&mut pos.func, table_index);
+
+        let table_index_arg = pos.ins().iconst(I32, table_index_arg as i64);
+
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(&mut pos, func_idx);
+
+        let call_inst =
+            pos.ins()
+                .call_indirect(func_sig, func_addr, &[vmctx, table_index_arg, index]);
+
+        Ok(*pos.func.dfg.inst_results(call_inst).first().unwrap())
     }
 
     fn translate_table_set(
         &mut self,
-        _builder: &mut FunctionBuilder,
-        _table_index: TableIndex,
+        builder: &mut FunctionBuilder,
+        table_index: TableIndex,
         _table: ir::Table,
-        _value: ir::Value,
-        _index: ir::Value,
+        value: ir::Value,
+        index: ir::Value,
     ) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "the `table.set` instruction is not supported yet".into(),
-        ))
+        let mut pos = builder.cursor();
+
+        let (func_sig, table_index_arg, func_idx) =
+            self.get_table_set_func(&mut pos.func, table_index);
+
+        let table_index_arg = pos.ins().iconst(I32, table_index_arg as i64);
+
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(&mut pos, func_idx);
+
+        pos.ins()
+            .call_indirect(func_sig, func_addr, &[vmctx, table_index_arg, index, value]);
+
+        Ok(())
     }
 
     fn translate_table_fill(
         &mut self,
-        mut _pos: cranelift_codegen::cursor::FuncCursor<'_>,
-        _table_index: TableIndex,
-        _dst: ir::Value,
-        _val: ir::Value,
-        _len: ir::Value,
+        mut pos: cranelift_codegen::cursor::FuncCursor<'_>,
+        table_index: TableIndex,
+        dst: ir::Value,
+        val: ir::Value,
+        len: ir::Value,
     ) -> WasmResult<()> {
-        Err(WasmError::Unsupported(
-            "the `table.fill` instruction is not supported yet".into(),
-        ))
+        let (func_sig, table_index_arg, func_idx) =
+            self.get_table_fill_func(&mut pos.func, table_index);
+
+        let table_index_arg = pos.ins().iconst(I32, table_index_arg as i64);
+
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(&mut pos, func_idx);
+
+        pos.ins().call_indirect(
+            func_sig,
+            func_addr,
+            &[vmctx, table_index_arg, dst, val, len],
+        );
+
+        Ok(())
     }
 
     fn translate_ref_null(
         &mut self,
         mut pos: cranelift_codegen::cursor::FuncCursor,
-        ty: Type,
+        _ty: Type,
     ) -> WasmResult<ir::Value> {
-        Ok(match ty {
-            Type::FuncRef => pos.ins().iconst(self.pointer_type(), 0),
-            // Type::ExternRef => pos.ins().null(self.reference_type(ty)),
-            _ => {
-                return Err(WasmError::Unsupported(
-                    "`ref.null T` that is not a `funcref`".into(),
-                ));
-            }
-        })
+        // Both `funcref` and `externref` are pointers, where null is the null
+        // reference.
+        Ok(pos.ins().null(self.reference_type()))
     }
 
-    fn translate_ref_is_null(
+    fn translate_ref_func(
         &mut self,
-        mut pos: cranelift_codegen::cursor::FuncCursor,
-        value: ir::Value,
+        mut pos: cranelift_codegen::cursor::FuncCursor<'_>,
+        func_index: FunctionIndex,
     ) -> WasmResult<ir::Value> {
-        let bool_is_null = match pos.func.dfg.value_type(value) {
-            // `externref`
-            ty if ty.is_ref() => pos.ins().is_null(value),
-            // `funcref`
-            ty if ty == self.pointer_type() => {
-                pos.ins()
-                    .icmp_imm(cranelift_codegen::ir::condcodes::IntCC::Equal, value, 0)
-            }
-            _ => unreachable!(),
-        };
+        let (func_sig, func_index_arg, func_idx) =
+            self.get_func_ref_func(&mut pos.func, func_index);
 
-        Ok(pos.ins().bint(ir::types::I32, bool_is_null))
-    }
+        let func_index_arg = pos.ins().iconst(I32, func_index_arg as i64);
 
-    fn translate_ref_func(
-        &mut self,
-        mut _pos: cranelift_codegen::cursor::FuncCursor<'_>,
-        _func_index: FunctionIndex,
-    ) -> WasmResult<ir::Value> {
-        Err(WasmError::Unsupported(
-            "the `ref.func` instruction is not supported yet".into(),
-        ))
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(&mut pos, func_idx);
+
+        let call_inst = pos
+            .ins()
+            .call_indirect(func_sig, func_addr, &[vmctx, func_index_arg]);
+
+        Ok(*pos.func.dfg.inst_results(call_inst).first().unwrap())
     }
 
     fn translate_custom_global_get(
         &mut self,
-        mut _pos: cranelift_codegen::cursor::FuncCursor<'_>,
-        _index: GlobalIndex,
+        mut pos: cranelift_codegen::cursor::FuncCursor<'_>,
+        index: GlobalIndex,
     ) -> WasmResult<ir::Value> {
-        unreachable!("we don't make any custom globals")
+        // Only globals of reference types are custom, see `make_global`.
+        let (func_sig, global_index_arg, func_idx) =
+            self.get_global_get_ref_func(&mut pos.func, index);
+
+        let global_index_arg = pos.ins().iconst(I32, global_index_arg as i64);
+
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(&mut pos, func_idx);
+
+        let call_inst = pos
+            .ins()
+            .call_indirect(func_sig, func_addr, &[vmctx, global_index_arg]);
+
+        Ok(*pos.func.dfg.inst_results(call_inst).first().unwrap())
     }
 
     fn translate_custom_global_set(
         &mut self,
-        mut _pos: cranelift_codegen::cursor::FuncCursor<'_>,
-        _index: GlobalIndex,
-        _value: ir::Value,
+        mut pos: cranelift_codegen::cursor::FuncCursor<'_>,
+        index: GlobalIndex,
+        value: ir::Value,
     ) -> WasmResult<()> {
-        unreachable!("we don't make any custom globals")
+        let (func_sig, global_index_arg, func_idx) =
+            self.get_global_set_ref_func(&mut pos.func, index);
+
+        let global_index_arg = pos.ins().iconst(I32, global_index_arg as i64);
+
+        let (vmctx, func_addr) = self.translate_load_builtin_function_address(&mut pos, func_idx);
+
+        pos.ins()
+            .call_indirect(func_sig, func_addr, &[vmctx, global_index_arg, value]);
+
+        Ok(())
     }
 
     fn make_heap(&mut self, func: &mut ir::Function, index: MemoryIndex) -> WasmResult<ir::Heap> {
@@ -730,6 +1122,12 @@ impl<'module_environment> BaseFuncEnvironment for FuncEnvironment<'module_enviro
         func: &mut ir::Function,
         index: GlobalIndex,
     ) -> WasmResult<GlobalVariable> {
+        // Globals of reference types own the references they hold, so they
+        // are read and written through libcalls.
+        if self.module.globals[index].ty.is_ref() {
+            return Ok(GlobalVariable::Custom);
+        }
+
         let pointer_type = self.pointer_type();
 
         let (ptr, offset) = {
@@ -1019,13 +1417,12 @@ impl<'module_environment> BaseFuncEnvironment for FuncEnvironment<'module_enviro
 
     fn translate_table_size(
         &mut self,
-        mut _pos: FuncCursor,
+        mut pos: FuncCursor,
         _table_index: TableIndex,
-        _table: ir::Table,
+        table: ir::Table,
     ) -> WasmResult<ir::Value> {
-        Err(WasmError::Unsupported(
-            "bulk memory: `table.size`".to_string(),
-        ))
+        let bound_gv = pos.func.tables[table].bound_gv;
+        Ok(pos.ins().global_value(I32, bound_gv))
     }
 
     fn translate_table_copy(
@@ -1117,6 +1514,20 @@ impl<'module_environment> BaseFuncEnvironment for FuncEnvironment<'module_enviro
         ))
     }
 
+    fn translate_loop_header(&mut self, builder: &mut FunctionBuilder) -> WasmResult<()> {
+        self.translate_interrupt_check(builder);
+        Ok(())
+    }
+
+    fn before_translate_function(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        _state: &FuncTranslationState,
+    ) -> WasmResult<()> {
+        self.translate_interrupt_check(builder);
+        Ok(())
+    }
+
     fn translate_atomic_notify(
         &mut self,
         _pos: FuncCursor,
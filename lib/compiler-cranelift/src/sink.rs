@@ -111,6 +111,17 @@ impl binemit::TrapSink for TrapSink {
     }
 }
 
+/// Translates the trap code of an `Unreachable` operator into Cranelift IR.
+///
+/// The codes pushed by middlewares are kept as user trap codes, which
+/// `translate_ir_trapcode` turns back into the same code.
+pub(crate) fn translate_unreachable_trapcode(trap: TrapCode) -> ir::TrapCode {
+    match trap {
+        TrapCode::UnreachableCodeReached => ir::TrapCode::UnreachableCodeReached,
+        trap => ir::TrapCode::User(trap as u16),
+    }
+}
+
 /// Translates the Cranelift IR TrapCode into generic Trap Code
 fn translate_ir_trapcode(trap: ir::TrapCode) -> TrapCode {
     match trap {
@@ -125,7 +136,9 @@ fn translate_ir_trapcode(trap: ir::TrapCode) -> TrapCode {
         ir::TrapCode::BadConversionToInteger => TrapCode::BadConversionToInteger,
         ir::TrapCode::UnreachableCodeReached => TrapCode::UnreachableCodeReached,
         ir::TrapCode::Interrupt => TrapCode::Interrupt,
-        ir::TrapCode::User(_user_code) => unimplemented!("User trap code not supported"),
-        // ir::TrapCode::User(user_code) => TrapCode::User(user_code),
+        // User trap codes only come from `translate_unreachable_trapcode`.
+        ir::TrapCode::User(user_code) => {
+            TrapCode::from_u32(user_code.into()).unwrap_or(TrapCode::UnreachableCodeReached)
+        }
     }
 }
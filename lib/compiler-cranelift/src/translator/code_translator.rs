@@ -156,6 +156,7 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
         }
         Operator::Select => {
             let (arg1, arg2, cond) = state.pop3();
+            let (arg1, arg2) = bitcast_select_arguments(arg1, arg2, builder);
             state.push1(builder.ins().select(cond, arg1, arg2));
         }
         Operator::TypedSelect { ty: _ } => {
@@ -163,13 +164,14 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
             // validation, which we require to have been performed before
             // translation.
             let (arg1, arg2, cond) = state.pop3();
+            let (arg1, arg2) = bitcast_select_arguments(arg1, arg2, builder);
             state.push1(builder.ins().select(cond, arg1, arg2));
         }
         Operator::Nop => {
             // We do nothing
         }
         Operator::Unreachable => {
-            builder.ins().trap(ir::TrapCode::UnreachableCodeReached);
+            builder.ins().trap(state.unreachable_trap_code);
             state.reachable = false;
         }
         /***************************** Control flow blocks **********************************
@@ -203,7 +205,7 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
                 .extend_from_slice(builder.block_params(loop_body));
 
             builder.switch_to_block(loop_body);
-            environ.translate_loop_header(builder.cursor())?;
+            environ.translate_loop_header(builder)?;
         }
         Operator::If { ty } => {
             let val = state.pop1();
@@ -2610,6 +2612,23 @@ fn optionally_bitcast_vector(
     }
 }
 
+/// `select` needs both of its arguments to have the same type, but two `v128` values may have
+/// different CLIF vector types, so cast them to I8X16.
+fn bitcast_select_arguments(
+    arg1: Value,
+    arg2: Value,
+    builder: &mut FunctionBuilder,
+) -> (Value, Value) {
+    if builder.func.dfg.value_type(arg1).is_vector() {
+        (
+            optionally_bitcast_vector(arg1, I8X16, builder),
+            optionally_bitcast_vector(arg2, I8X16, builder),
+        )
+    } else {
+        (arg1, arg2)
+    }
+}
+
 #[inline(always)]
 fn is_non_canonical_v128(ty: ir::Type) -> bool {
     matches!(
@@ -227,6 +227,8 @@ pub struct FuncTranslationState {
     /// Is the current translation state still reachable? This is false when translating operators
     /// like End, Return, or Unreachable.
     pub(crate) reachable: bool,
+    /// The trap code of the `Unreachable` operator being translated.
+    pub(crate) unreachable_trap_code: ir::TrapCode,
 
     // Map of global variables that have already been created by `FuncEnvironment::make_global`.
     globals: HashMap<GlobalIndex, GlobalVariable>,
@@ -265,6 +267,7 @@ impl FuncTranslationState {
             stack: Vec::new(),
             control_stack: Vec::new(),
             reachable: true,
+            unreachable_trap_code: ir::TrapCode::UnreachableCodeReached,
             globals: HashMap::new(),
             heaps: HashMap::new(),
             tables: HashMap::new(),
@@ -277,6 +280,7 @@ impl FuncTranslationState {
         debug_assert!(self.stack.is_empty());
         debug_assert!(self.control_stack.is_empty());
         self.reachable = true;
+        self.unreachable_trap_code = ir::TrapCode::UnreachableCodeReached;
         self.globals.clear();
         self.heaps.clear();
         self.tables.clear();
@@ -34,7 +34,7 @@ use wasmer_types::{
     FunctionIndex, FunctionType, GlobalIndex, LocalFunctionIndex, MemoryIndex, SignatureIndex,
     TableIndex, Type,
 };
-use wasmer_vm::{MemoryStyle, ModuleInfo, TableStyle, VMOffsets};
+use wasmer_vm::{MemoryStyle, ModuleInfo, TableStyle, TrapCode, VMOffsets};
 
 const FUNCTION_SECTION: &str = "__TEXT,wasmer_function";
 
@@ -194,6 +194,7 @@ impl FuncTranslator {
             locals: params_locals,
             ctx: CtxType::new(wasm_module, &func, &cache_builder, &*self.abi),
             unreachable_depth: 0,
+            unreachable_trap_code: TrapCode::UnreachableCodeReached,
             memory_styles,
             _table_styles,
             module: &module,
@@ -208,10 +209,12 @@ impl FuncTranslator {
             fcg.ctx.basic(),
             &func_attrs,
         );
+        fcg.interrupt_check();
 
         while fcg.state.has_control_frames() {
             let pos = reader.current_position() as u32;
             let op = reader.read_operator()?;
+            fcg.unreachable_trap_code = reader.unreachable_trap_code();
             fcg.translate_operator(op, pos)?;
         }
 
@@ -749,6 +752,69 @@ impl<'ctx, 'a> LLVMFunctionCodeGenerator<'ctx, 'a> {
         self.builder.position_at_end(shouldnt_trap_block);
     }
 
+    /// Calls into the runtime if the interrupt flag is set or if the epoch
+    /// deadline is reached.
+    fn interrupt_check(&mut self) {
+        let interrupts = self.ctx.interrupts(self.intrinsics);
+        // These are written by other threads, the loads must not be hoisted
+        // out of loops.
+        let volatile_load = |ptr: PointerValue<'ctx>, name: &str| {
+            let value = self.builder.build_load(ptr, name).into_int_value();
+            value
+                .as_instruction_value()
+                .unwrap()
+                .set_volatile(true)
+                .unwrap();
+            value
+        };
+        let interrupted = volatile_load(interrupts.ptr_to_interrupted, "interrupted");
+        let epoch_deadline = volatile_load(interrupts.ptr_to_epoch_deadline, "epoch_deadline");
+        let epoch = volatile_load(interrupts.ptr_to_epoch, "epoch");
+
+        let is_interrupted = self.builder.build_int_compare(
+            IntPredicate::NE,
+            interrupted,
+            self.intrinsics.i32_zero,
+            "is_interrupted",
+        );
+        let is_expired =
+            self.builder
+                .build_int_compare(IntPredicate::UGE, epoch, epoch_deadline, "is_expired");
+        let should_check = self
+            .builder
+            .build_or(is_interrupted, is_expired, "should_check");
+
+        let should_check = self
+            .builder
+            .build_call(
+                self.intrinsics.expect_i1,
+                &[
+                    should_check.as_basic_value_enum(),
+                    self.intrinsics.i1_ty.const_zero().as_basic_value_enum(),
+                ],
+                "should_check_expect",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+
+        let continue_block = self
+            .context
+            .append_basic_block(self.function, "interrupt_continue_block");
+        let check_block = self
+            .context
+            .append_basic_block(self.function, "interrupt_check_block");
+        self.builder
+            .build_conditional_branch(should_check, check_block, continue_block);
+        self.builder.position_at_end(check_block);
+        let interrupt_fn = self.ctx.interrupt(self.intrinsics);
+        self.builder
+            .build_call(interrupt_fn, &[self.ctx.basic()], "interrupt");
+        self.builder.build_unconditional_branch(continue_block);
+        self.builder.position_at_end(continue_block);
+    }
+
     fn v128_into_int_vec(
         &self,
         value: BasicValueEnum<'ctx>,
@@ -1285,6 +1351,8 @@ pub struct LLVMFunctionCodeGenerator<'ctx, 'a> {
     locals: Vec<PointerValue<'ctx>>, // Contains params and locals
     ctx: CtxType<'ctx, 'a>,
     unreachable_depth: usize,
+    /// The trap code of the `Unreachable` operator being translated.
+    unreachable_trap_code: TrapCode,
     memory_styles: &'a PrimaryMap<MemoryIndex, MemoryStyle>,
     _table_styles: &'a PrimaryMap<TableIndex, TableStyle>,
 
@@ -1407,6 +1475,7 @@ impl<'ctx, 'a> LLVMFunctionCodeGenerator<'ctx, 'a> {
                 for phi in &loop_phis {
                     self.state.push1(phi.as_basic_value());
                 }
+                self.interrupt_check();
 
                 /*
                 if self.track_state {
@@ -1792,11 +1861,16 @@ impl<'ctx, 'a> LLVMFunctionCodeGenerator<'ctx, 'a> {
                 }
                 */
 
-                self.builder.build_call(
-                    self.intrinsics.throw_trap,
-                    &[self.intrinsics.trap_unreachable],
-                    "throw",
-                );
+                let trap_code = match self.unreachable_trap_code {
+                    TrapCode::UnreachableCodeReached => self.intrinsics.trap_unreachable,
+                    trap_code => self
+                        .intrinsics
+                        .i32_ty
+                        .const_int(trap_code as _, false)
+                        .as_basic_value_enum(),
+                };
+                self.builder
+                    .build_call(self.intrinsics.throw_trap, &[trap_code], "throw");
                 self.builder.build_unreachable();
 
                 self.state.reachable = false;
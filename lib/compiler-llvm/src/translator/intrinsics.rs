@@ -184,6 +184,7 @@ pub struct Intrinsics<'ctx> {
     pub imported_memory32_grow_ptr_ty: PointerType<'ctx>,
     pub memory32_size_ptr_ty: PointerType<'ctx>,
     pub imported_memory32_size_ptr_ty: PointerType<'ctx>,
+    pub interrupt_ptr_ty: PointerType<'ctx>,
 
     pub ctx_ptr_ty: PointerType<'ctx>,
 }
@@ -486,6 +487,9 @@ impl<'ctx> Intrinsics<'ctx> {
             imported_memory32_size_ptr_ty: i32_ty
                 .fn_type(&[ctx_ptr_ty.as_basic_type_enum(), i32_ty_basic], false)
                 .ptr_type(AddressSpace::Generic),
+            interrupt_ptr_ty: void_ty
+                .fn_type(&[ctx_ptr_ty.as_basic_type_enum()], false)
+                .ptr_type(AddressSpace::Generic),
 
             ctx_ptr_ty,
         };
@@ -524,6 +528,14 @@ pub enum GlobalCache<'ctx> {
     Const { value: BasicValueEnum<'ctx> },
 }
 
+/// Pointers to the fields of the `VMInterrupts` structure.
+#[derive(Clone, Copy)]
+pub struct InterruptsCache<'ctx> {
+    pub ptr_to_interrupted: PointerValue<'ctx>,
+    pub ptr_to_epoch_deadline: PointerValue<'ctx>,
+    pub ptr_to_epoch: PointerValue<'ctx>,
+}
+
 #[derive(Clone)]
 pub struct FunctionCache<'ctx> {
     pub func: PointerValue<'ctx>,
@@ -545,6 +557,8 @@ pub struct CtxType<'ctx, 'a> {
     cached_functions: HashMap<FunctionIndex, FunctionCache<'ctx>>,
     cached_memory_grow: HashMap<MemoryIndex, PointerValue<'ctx>>,
     cached_memory_size: HashMap<MemoryIndex, PointerValue<'ctx>>,
+    cached_interrupts: Option<InterruptsCache<'ctx>>,
+    cached_interrupt: Option<PointerValue<'ctx>>,
 
     offsets: VMOffsets,
 }
@@ -570,6 +584,8 @@ impl<'ctx, 'a> CtxType<'ctx, 'a> {
             cached_functions: HashMap::new(),
             cached_memory_grow: HashMap::new(),
             cached_memory_size: HashMap::new(),
+            cached_interrupts: None,
+            cached_interrupt: None,
 
             // TODO: pointer width
             offsets: VMOffsets::new(8, &wasm_module),
@@ -1104,6 +1120,85 @@ impl<'ctx, 'a> CtxType<'ctx, 'a> {
         })
     }
 
+    /// Returns pointers to the fields of the `VMInterrupts` structure
+    /// referenced by the vmctx.
+    pub fn interrupts(&mut self, intrinsics: &Intrinsics<'ctx>) -> InterruptsCache<'ctx> {
+        let (cached_interrupts, offsets, cache_builder, ctx_ptr_value) = (
+            &mut self.cached_interrupts,
+            &self.offsets,
+            &self.cache_builder,
+            &self.ctx_ptr_value,
+        );
+        *cached_interrupts.get_or_insert_with(|| {
+            let offset = offsets.vmctx_interrupts();
+            let offset = intrinsics.i32_ty.const_int(offset.into(), false);
+            let interrupts_ptr_ptr =
+                unsafe { cache_builder.build_gep(*ctx_ptr_value, &[offset], "") };
+            let interrupts_ptr_ptr = cache_builder
+                .build_bitcast(
+                    interrupts_ptr_ptr,
+                    intrinsics.i8_ptr_ty.ptr_type(AddressSpace::Generic),
+                    "",
+                )
+                .into_pointer_value();
+            let interrupts_ptr = cache_builder
+                .build_load(interrupts_ptr_ptr, "")
+                .into_pointer_value();
+
+            let field_ptr = |offset: u8, ty: PointerType<'ctx>| {
+                let offset = intrinsics.i32_ty.const_int(offset.into(), false);
+                let ptr = unsafe { cache_builder.build_gep(interrupts_ptr, &[offset], "") };
+                cache_builder
+                    .build_bitcast(ptr, ty, "")
+                    .into_pointer_value()
+            };
+            let ptr_to_interrupted =
+                field_ptr(offsets.vminterrupts_interrupted(), intrinsics.i32_ptr_ty);
+            let ptr_to_epoch_deadline =
+                field_ptr(offsets.vminterrupts_epoch_deadline(), intrinsics.i64_ptr_ty);
+            let ptr_to_epoch_ptr = field_ptr(
+                offsets.vminterrupts_epoch(),
+                intrinsics.i64_ptr_ty.ptr_type(AddressSpace::Generic),
+            );
+            let ptr_to_epoch = cache_builder
+                .build_load(ptr_to_epoch_ptr, "")
+                .into_pointer_value();
+
+            InterruptsCache {
+                ptr_to_interrupted,
+                ptr_to_epoch_deadline,
+                ptr_to_epoch,
+            }
+        })
+    }
+
+    pub fn interrupt(&mut self, intrinsics: &Intrinsics<'ctx>) -> PointerValue<'ctx> {
+        let (cached_interrupt, offsets, cache_builder, ctx_ptr_value) = (
+            &mut self.cached_interrupt,
+            &self.offsets,
+            &self.cache_builder,
+            &self.ctx_ptr_value,
+        );
+        *cached_interrupt.get_or_insert_with(|| {
+            let offset =
+                offsets.vmctx_builtin_function(VMBuiltinFunctionIndex::get_interrupt_index());
+            let offset = intrinsics.i32_ty.const_int(offset.into(), false);
+            let interrupt_fn_ptr_ptr =
+                unsafe { cache_builder.build_gep(*ctx_ptr_value, &[offset], "") };
+
+            let interrupt_fn_ptr_ptr = cache_builder
+                .build_bitcast(
+                    interrupt_fn_ptr_ptr,
+                    intrinsics.interrupt_ptr_ty.ptr_type(AddressSpace::Generic),
+                    "",
+                )
+                .into_pointer_value();
+            cache_builder
+                .build_load(interrupt_fn_ptr_ptr, "")
+                .into_pointer_value()
+        })
+    }
+
     pub fn get_offsets(&self) -> &VMOffsets {
         &self.offsets
     }
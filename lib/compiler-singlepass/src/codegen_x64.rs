@@ -55,12 +55,30 @@ pub struct FuncGen<'a> {
     /// Types of local variables, including arguments.
     local_types: Vec<WpType>,
 
+    /// Index into `locals` of the first slot of each local variable.
+    ///
+    /// A `v128` local occupies two consecutive slots holding its low and high halves.
+    local_slots: Vec<usize>,
+
+    /// Index into `locals` of the pointer to the area that the results of a function returning
+    /// multiple values are stored to.
+    ///
+    /// The caller passes this pointer as an additional argument after the wasm arguments. The
+    /// area holds one 8-byte slot per value stack slot of the results, at decreasing addresses
+    /// starting from the pointer.
+    return_area_slot: Option<usize>,
+
     /// Value stack.
     value_stack: Vec<Location>,
 
     /// Metadata about floating point values on the stack.
     fp_stack: Vec<FloatValue>,
 
+    /// Depths in the main value stack at which `v128` values start.
+    ///
+    /// A `v128` value occupies two consecutive value stack slots holding its low and high halves.
+    v128_stack: Vec<usize>,
+
     /// A list of frames describing the current control stack.
     control_stack: Vec<ControlFrame>,
 
@@ -85,6 +103,9 @@ pub struct FuncGen<'a> {
     /// The source location for the current operator.
     src_loc: u32,
 
+    /// The trap code of the current operator, if it's an `Unreachable`.
+    unreachable_trap_code: TrapCode,
+
     /// Map from byte offset into wasm function to range of native instructions.
     ///
     // Ordered by increasing InstructionAddressMap::srcloc.
@@ -226,7 +247,14 @@ pub struct ControlFrame {
     pub label: DynamicLabel,
     pub loop_like: bool,
     pub if_else: IfElseState,
+    pub params: SmallVec<[WpType; 1]>,
     pub returns: SmallVec<[WpType; 1]>,
+    /// Stack slots that the results of a block with a function type are moved to before jumping to
+    /// its end. Blocks with a single result type pass it in RAX instead.
+    pub return_slots: SmallVec<[Location; 1]>,
+    /// Stack slots holding the parameters of a loop or an `if` with a function type, so that they
+    /// can be passed again when branching back to the loop or entering the `else` branch.
+    pub param_slots: SmallVec<[Location; 1]>,
     pub value_stack_depth: usize,
     pub fp_stack_depth: usize,
     pub state: MachineState,
@@ -259,6 +287,11 @@ impl<'a> FuncGen<'a> {
         self.src_loc = offset;
     }
 
+    /// Set the trap code the next `Unreachable` operator traps with.
+    pub fn set_unreachable_trap_code(&mut self, trap_code: TrapCode) {
+        self.unreachable_trap_code = trap_code;
+    }
+
     fn get_location_released(&mut self, loc: Location) -> Location {
         self.machine.release_locations(&mut self.assembler, &[loc]);
         loc
@@ -950,216 +983,1111 @@ impl<'a> FuncGen<'a> {
         Ok(())
     }
 
-    /// Emits a System V call sequence.
-    ///
-    /// This function will not use RAX before `cb` is called.
-    ///
-    /// The caller MUST NOT hold any temporary registers allocated by `acquire_temp_gpr` when calling
-    /// this function.
-    fn emit_call_sysv<I: Iterator<Item = Location>, F: FnOnce(&mut Self)>(
-        &mut self,
-        cb: F,
-        params: I,
-    ) -> Result<(), CodegenError> {
-        // Values pushed in this function are above the shadow region.
-        self.machine
-            .state
-            .stack_values
-            .push(MachineValue::ExplicitShadow);
+    // SIMD operators load their `v128` operands into XMM8-XMM15, which are never handed out by
+    // `Machine`. Values never stay in these registers across operators, so they don't need to be
+    // saved around calls.
 
-        let params: Vec<_> = params.collect();
+    /// Returns whether the value on top of the value stack is a `v128` value.
+    fn top_is_v128(&self) -> bool {
+        self.v128_stack
+            .last()
+            .map_or(false, |&depth| depth + 2 == self.value_stack.len())
+    }
 
-        // Save used GPRs.
-        let used_gprs = self.machine.get_used_gprs();
-        for r in used_gprs.iter() {
-            self.assembler.emit_push(Size::S64, Location::GPR(*r));
-            let content =
-                self.machine.state.register_values[X64Register::GPR(*r).to_index().0].clone();
-            if content == MachineValue::Undefined {
-                return Err(CodegenError {
-                    message: "emit_call_sysv: Undefined used_gprs content".to_string(),
-                });
-            }
-            self.machine.state.stack_values.push(content);
+    /// Forgets about all `v128` values starting at or above `depth` in the value stack.
+    fn truncate_v128_stack(&mut self, depth: usize) {
+        while self.v128_stack.last().map_or(false, |&x| x >= depth) {
+            self.v128_stack.pop();
         }
+    }
 
-        // Save used XMM registers.
-        let used_xmms = self.machine.get_used_xmms();
-        if used_xmms.len() > 0 {
-            self.assembler.emit_sub(
-                Size::S64,
-                Location::Imm32((used_xmms.len() * 8) as u32),
-                Location::GPR(GPR::RSP),
-            );
+    /// Pops a `v128` value off the value stack, returning the locations of its low and high halves.
+    fn pop_v128_released(&mut self) -> Result<(Location, Location), CodegenError> {
+        if !self.top_is_v128() {
+            return Err(CodegenError {
+                message: "pop_v128_released: value on top of the stack is not a v128".to_string(),
+            });
+        }
+        self.v128_stack.pop();
+        let hi = self.pop_value_released();
+        let lo = self.pop_value_released();
+        Ok((lo, hi))
+    }
 
-            for (i, r) in used_xmms.iter().enumerate() {
-                self.assembler.emit_mov(
-                    Size::S64,
-                    Location::XMM(*r),
-                    Location::Memory(GPR::RSP, (i * 8) as i32),
-                );
-            }
-            for r in used_xmms.iter().rev() {
-                let content =
-                    self.machine.state.register_values[X64Register::XMM(*r).to_index().0].clone();
-                if content == MachineValue::Undefined {
-                    return Err(CodegenError {
-                        message: "emit_call_sysv: Undefined used_xmms content".to_string(),
-                    });
+    /// Acquires the locations of the low and high halves of a new `v128` value and pushes them
+    /// to the value stack.
+    fn acquire_v128(&mut self) -> (Location, Location) {
+        let depth = self.value_stack.len();
+        let locs = self.machine.acquire_locations(
+            &mut self.assembler,
+            &[
+                (WpType::I64, MachineValue::WasmStack(depth)),
+                (WpType::I64, MachineValue::WasmStack(depth + 1)),
+            ],
+            false,
+        );
+        self.value_stack.push(locs[0]);
+        self.value_stack.push(locs[1]);
+        self.v128_stack.push(depth);
+        (locs[0], locs[1])
+    }
+
+    /// Pops a scalar operand of a SIMD operator into `dst`, canonicalizing it first if it is a
+    /// float with a pending canonicalization.
+    fn pop_simd_scalar_to_gpr(&mut self, ty: WpType, dst: GPR) -> Result<(), CodegenError> {
+        let loc = self.pop_value_released();
+        if ty.is_float() {
+            let fp = self.fp_stack.pop1()?;
+            if self.assembler.arch_supports_canonicalize_nan()
+                && self.config.enable_nan_canonicalization
+            {
+                if let Some(cncl) = fp.canonicalization {
+                    self.canonicalize_nan(cncl.to_size(), loc, Location::GPR(dst));
+                    return Ok(());
                 }
-                self.machine.state.stack_values.push(content);
             }
         }
+        self.assembler.emit_mov(Size::S64, loc, Location::GPR(dst));
+        Ok(())
+    }
 
-        let mut stack_offset: usize = 0;
+    /// Loads the `v128` value with halves `lo` and `hi` into `dst`.
+    fn emit_v128_load(&mut self, (lo, hi): (Location, Location), dst: XMM) {
+        self.assembler.emit_mov(Size::S64, lo, Location::XMM(dst));
+        self.assembler
+            .emit_vpinsrq(dst, to_gpr_or_memory(hi), 1, dst);
+    }
 
-        // Calculate stack offset.
-        for (i, _param) in params.iter().enumerate() {
-            if let Location::Memory(_, _) = Machine::get_param_location(1 + i) {
-                stack_offset += 8;
-            }
-        }
+    /// Stores `src` into the halves `lo` and `hi` of a `v128` value.
+    fn emit_v128_store(&mut self, src: XMM, (lo, hi): (Location, Location)) {
+        self.assembler.emit_mov(Size::S64, Location::XMM(src), lo);
+        self.assembler.emit_vpextrq(src, 1, to_gpr_or_memory(hi));
+    }
 
-        // Align stack to 16 bytes.
-        if (self.machine.get_stack_offset()
-            + used_gprs.len() * 8
-            + used_xmms.len() * 8
-            + stack_offset)
-            % 16
-            != 0
-        {
+    /// Materializes the constant `value` in `dst`.
+    fn emit_v128_const(&mut self, value: u128, dst: XMM) {
+        let lo = value as u64;
+        let hi = (value >> 64) as u64;
+        let tmp = self.machine.acquire_temp_gpr().unwrap();
+        self.assembler
+            .emit_mov(Size::S64, Location::Imm64(lo), Location::GPR(tmp));
+        self.assembler
+            .emit_mov(Size::S64, Location::GPR(tmp), Location::XMM(dst));
+        if hi == lo {
             self.assembler
-                .emit_sub(Size::S64, Location::Imm32(8), Location::GPR(GPR::RSP));
-            stack_offset += 8;
-            self.machine
-                .state
-                .stack_values
-                .push(MachineValue::Undefined);
+                .emit_vpshufd(XMMOrMemory::XMM(dst), 0x44, dst);
+        } else {
+            self.assembler
+                .emit_mov(Size::S64, Location::Imm64(hi), Location::GPR(tmp));
+            self.assembler
+                .emit_vpinsrq(dst, GPROrMemory::GPR(tmp), 1, dst);
         }
+        self.machine.release_temp_gpr(tmp);
+    }
 
-        let mut call_movs: Vec<(Location, GPR)> = vec![];
-
-        // Prepare register & stack parameters.
-        for (i, param) in params.iter().enumerate().rev() {
-            let loc = Machine::get_param_location(1 + i);
-            match loc {
-                Location::GPR(x) => {
-                    call_movs.push((*param, x));
-                }
-                Location::Memory(_, _) => {
-                    match *param {
-                        Location::GPR(x) => {
-                            let content = self.machine.state.register_values
-                                [X64Register::GPR(x).to_index().0]
-                                .clone();
-                            // FIXME: There might be some corner cases (release -> emit_call_sysv -> acquire?) that cause this assertion to fail.
-                            // Hopefully nothing would be incorrect at runtime.
-
-                            //assert!(content != MachineValue::Undefined);
-                            self.machine.state.stack_values.push(content);
-                        }
-                        Location::XMM(x) => {
-                            let content = self.machine.state.register_values
-                                [X64Register::XMM(x).to_index().0]
-                                .clone();
-                            //assert!(content != MachineValue::Undefined);
-                            self.machine.state.stack_values.push(content);
-                        }
-                        Location::Memory(reg, offset) => {
-                            if reg != GPR::RBP {
-                                return Err(CodegenError {
-                                    message: "emit_call_sysv loc param: unreachable code"
-                                        .to_string(),
-                                });
-                            }
-                            self.machine
-                                .state
-                                .stack_values
-                                .push(MachineValue::CopyStackBPRelative(offset));
-                            // TODO: Read value at this offset
-                        }
-                        _ => {
-                            self.machine
-                                .state
-                                .stack_values
-                                .push(MachineValue::Undefined);
-                        }
-                    }
-                    match *param {
-                        Location::Imm64(_) => {
-                            // Dummy value slot to be filled with `mov`.
-                            self.assembler.emit_push(Size::S64, Location::GPR(GPR::RAX));
-
-                            // Use RCX as the temporary register here, since:
-                            // - It is a temporary register that is not used for any persistent value.
-                            // - This register as an argument location is only written to after `sort_call_movs`.'
-                            self.machine.reserve_unused_temp_gpr(GPR::RCX);
-                            self.assembler
-                                .emit_mov(Size::S64, *param, Location::GPR(GPR::RCX));
-                            self.assembler.emit_mov(
-                                Size::S64,
-                                Location::GPR(GPR::RCX),
-                                Location::Memory(GPR::RSP, 0),
-                            );
-                            self.machine.release_temp_gpr(GPR::RCX);
-                        }
-                        Location::XMM(_) => {
-                            // Dummy value slot to be filled with `mov`.
-                            self.assembler.emit_push(Size::S64, Location::GPR(GPR::RAX));
-
-                            // XMM registers can be directly stored to memory.
-                            self.assembler.emit_mov(
-                                Size::S64,
-                                *param,
-                                Location::Memory(GPR::RSP, 0),
-                            );
-                        }
-                        _ => self.assembler.emit_push(Size::S64, *param),
-                    }
-                }
-                _ => {
-                    return Err(CodegenError {
-                        message: "emit_call_sysv loc: unreachable code".to_string(),
-                    })
-                }
+    /// Replaces NaN lanes of the `f32x4` (`Size::S32`) or `f64x2` (`Size::S64`) value in `reg`
+    /// with the canonical NaN, if NaN canonicalization is enabled.
+    fn canonicalize_nan_v128(&mut self, sz: Size, reg: XMM) {
+        if !self.assembler.arch_supports_canonicalize_nan()
+            || !self.config.enable_nan_canonicalization
+        {
+            return;
+        }
+        let mask = XMM::XMM14;
+        let canonical = XMM::XMM15;
+        match sz {
+            Size::S32 => {
+                self.assembler
+                    .emit_vcmpunordps(reg, XMMOrMemory::XMM(reg), mask);
+                self.emit_v128_const(0x7FC0_0000_7FC0_0000_7FC0_0000_7FC0_0000, canonical);
+                self.assembler
+                    .emit_vblendvps(mask, XMMOrMemory::XMM(canonical), reg, reg);
+            }
+            Size::S64 => {
+                self.assembler
+                    .emit_vcmpunordpd(reg, XMMOrMemory::XMM(reg), mask);
+                self.emit_v128_const(0x7FF8_0000_0000_0000_7FF8_0000_0000_0000, canonical);
+                self.assembler
+                    .emit_vblendvpd(mask, XMMOrMemory::XMM(canonical), reg, reg);
             }
+            _ => unreachable!(),
         }
+    }
 
-        // Sort register moves so that register are not overwritten before read.
-        sort_call_movs(&mut call_movs);
+    /// Emits a SIMD operator with one `v128` input and a `v128` output.
+    ///
+    /// `f` is called with the input and the output register, which may be the same.
+    fn emit_v128_unop<F: FnOnce(&mut Self, XMM, XMM)>(&mut self, f: F) -> Result<(), CodegenError> {
+        let a = self.pop_v128_released()?;
+        self.emit_v128_load(a, XMM::XMM8);
+        let ret = self.acquire_v128();
+        f(self, XMM::XMM8, XMM::XMM8);
+        self.emit_v128_store(XMM::XMM8, ret);
+        Ok(())
+    }
 
-        // Emit register moves.
-        for (loc, gpr) in call_movs {
-            if loc != Location::GPR(gpr) {
-                self.assembler.emit_mov(Size::S64, loc, Location::GPR(gpr));
-            }
-        }
+    /// Emits a SIMD operator with two `v128` inputs and a `v128` output.
+    ///
+    /// `f` is called with the two input registers and the output register, which may be the same
+    /// as the first input.
+    fn emit_v128_binop<F: FnOnce(&mut Self, XMM, XMM, XMM)>(
+        &mut self,
+        f: F,
+    ) -> Result<(), CodegenError> {
+        let b = self.pop_v128_released()?;
+        let a = self.pop_v128_released()?;
+        self.emit_v128_load(a, XMM::XMM8);
+        self.emit_v128_load(b, XMM::XMM9);
+        let ret = self.acquire_v128();
+        f(self, XMM::XMM8, XMM::XMM9, XMM::XMM8);
+        self.emit_v128_store(XMM::XMM8, ret);
+        Ok(())
+    }
 
-        // Put vmctx as the first parameter.
-        self.assembler.emit_mov(
-            Size::S64,
-            Location::GPR(Machine::get_vmctx_reg()),
-            Machine::get_param_location(0),
-        ); // vmctx
+    fn emit_v128_unop_avx(
+        &mut self,
+        op: fn(&mut Assembler, XMMOrMemory, XMM),
+    ) -> Result<(), CodegenError> {
+        self.emit_v128_unop(|this, a, dst| op(&mut this.assembler, XMMOrMemory::XMM(a), dst))
+    }
 
-        if (self.machine.state.stack_values.len() % 2) != 1 {
-            return Err(CodegenError {
-                message: "emit_call_sysv: explicit shadow takes one slot".to_string(),
-            });
-        }
+    fn emit_v128_binop_avx(
+        &mut self,
+        op: fn(&mut Assembler, XMM, XMMOrMemory, XMM),
+    ) -> Result<(), CodegenError> {
+        self.emit_v128_binop(|this, a, b, dst| op(&mut this.assembler, a, XMMOrMemory::XMM(b), dst))
+    }
 
-        cb(self);
+    /// Emits a floating point SIMD operator whose result may need NaN canonicalization.
+    fn emit_v128_fp_unop_avx(
+        &mut self,
+        sz: Size,
+        op: fn(&mut Assembler, XMMOrMemory, XMM),
+    ) -> Result<(), CodegenError> {
+        self.emit_v128_unop(|this, a, dst| {
+            op(&mut this.assembler, XMMOrMemory::XMM(a), dst);
+            this.canonicalize_nan_v128(sz, dst);
+        })
+    }
 
-        // Offset needs to be after the 'call' instruction.
-        // TODO: Now the state information is also inserted for internal calls (e.g. MemoryGrow). Is this expected?
-        {
-            let state_diff_id = self.get_state_diff();
-            let offset = self.assembler.get_offset().0;
-            self.fsm.call_offsets.insert(
-                offset,
-                OffsetInfo {
-                    end_offset: offset + 1,
-                    activate_offset: offset,
+    /// Emits a floating point SIMD operator whose result may need NaN canonicalization.
+    fn emit_v128_fp_binop_avx(
+        &mut self,
+        sz: Size,
+        op: fn(&mut Assembler, XMM, XMMOrMemory, XMM),
+    ) -> Result<(), CodegenError> {
+        self.emit_v128_binop(|this, a, b, dst| {
+            op(&mut this.assembler, a, XMMOrMemory::XMM(b), dst);
+            this.canonicalize_nan_v128(sz, dst);
+        })
+    }
+
+    /// Emits a floating point SIMD operator whose result may need NaN canonicalization.
+    fn emit_v128_fp_unop<F: FnOnce(&mut Self, XMM, XMM)>(
+        &mut self,
+        sz: Size,
+        f: F,
+    ) -> Result<(), CodegenError> {
+        self.emit_v128_unop(|this, a, dst| {
+            f(this, a, dst);
+            this.canonicalize_nan_v128(sz, dst);
+        })
+    }
+
+    /// Emits `f32x4.min` (`Size::S32`) or `f64x2.min` (`Size::S64`).
+    ///
+    /// `vminps` returns its second operand if either operand is NaN or both are zero, so the
+    /// minimum is computed in both orders and the results are merged.
+    fn emit_v128_fp_min(&mut self, sz: Size) -> Result<(), CodegenError> {
+        self.emit_v128_binop(|this, a, b, dst| {
+            let (min, cmpunord, shift): (
+                fn(&mut Assembler, XMM, XMMOrMemory, XMM),
+                fn(&mut Assembler, XMM, XMMOrMemory, XMM),
+                fn(&mut Assembler, XMM, XMM),
+            ) = match sz {
+                Size::S32 => (
+                    Assembler::emit_vminps,
+                    Assembler::emit_vcmpunordps,
+                    |a, src, dst| a.emit_vpsrld_imm(src, 10, dst),
+                ),
+                Size::S64 => (
+                    Assembler::emit_vminpd,
+                    Assembler::emit_vcmpunordpd,
+                    |a, src, dst| a.emit_vpsrlq_imm(src, 13, dst),
+                ),
+                _ => unreachable!(),
+            };
+            min(&mut this.assembler, a, XMMOrMemory::XMM(b), XMM::XMM10);
+            min(&mut this.assembler, b, XMMOrMemory::XMM(a), XMM::XMM11);
+            // Propagate -0.0 and NaNs.
+            this.assembler
+                .emit_vpor(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM11), XMM::XMM10);
+            // Canonicalize NaNs by setting the quiet bit and clearing the payload.
+            cmpunord(
+                &mut this.assembler,
+                XMM::XMM11,
+                XMMOrMemory::XMM(XMM::XMM10),
+                XMM::XMM11,
+            );
+            this.assembler
+                .emit_vpor(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM11), XMM::XMM10);
+            shift(&mut this.assembler, XMM::XMM11, XMM::XMM11);
+            this.assembler
+                .emit_vpandn(XMM::XMM11, XMMOrMemory::XMM(XMM::XMM10), dst);
+        })
+    }
+
+    /// Emits `f32x4.max` (`Size::S32`) or `f64x2.max` (`Size::S64`).
+    ///
+    /// Like in `emit_v128_fp_min`, the maximum is computed in both orders and the results are
+    /// merged.
+    fn emit_v128_fp_max(&mut self, sz: Size) -> Result<(), CodegenError> {
+        self.emit_v128_binop(|this, a, b, dst| {
+            let (max, sub, cmpunord, shift): (
+                fn(&mut Assembler, XMM, XMMOrMemory, XMM),
+                fn(&mut Assembler, XMM, XMMOrMemory, XMM),
+                fn(&mut Assembler, XMM, XMMOrMemory, XMM),
+                fn(&mut Assembler, XMM, XMM),
+            ) = match sz {
+                Size::S32 => (
+                    Assembler::emit_vmaxps,
+                    Assembler::emit_vsubps,
+                    Assembler::emit_vcmpunordps,
+                    |a, src, dst| a.emit_vpsrld_imm(src, 10, dst),
+                ),
+                Size::S64 => (
+                    Assembler::emit_vmaxpd,
+                    Assembler::emit_vsubpd,
+                    Assembler::emit_vcmpunordpd,
+                    |a, src, dst| a.emit_vpsrlq_imm(src, 13, dst),
+                ),
+                _ => unreachable!(),
+            };
+            max(&mut this.assembler, a, XMMOrMemory::XMM(b), XMM::XMM10);
+            max(&mut this.assembler, b, XMMOrMemory::XMM(a), XMM::XMM11);
+            // Find the lanes where both orders disagree.
+            this.assembler
+                .emit_vpxor(XMM::XMM11, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM11);
+            // Propagate NaNs.
+            this.assembler
+                .emit_vpor(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM11), XMM::XMM10);
+            // Propagate the sign discrepancy of +0.0 and -0.0, and quiet NaNs.
+            sub(
+                &mut this.assembler,
+                XMM::XMM10,
+                XMMOrMemory::XMM(XMM::XMM11),
+                XMM::XMM10,
+            );
+            // Canonicalize NaNs by clearing the payload.
+            cmpunord(
+                &mut this.assembler,
+                XMM::XMM11,
+                XMMOrMemory::XMM(XMM::XMM10),
+                XMM::XMM11,
+            );
+            shift(&mut this.assembler, XMM::XMM11, XMM::XMM11);
+            this.assembler
+                .emit_vpandn(XMM::XMM11, XMMOrMemory::XMM(XMM::XMM10), dst);
+        })
+    }
+
+    /// Builds in `mask` the byte-wise mask `0xff >> count` used by the `i8x16` shifts, leaving
+    /// `count` in XMM9.
+    fn emit_v128_i8x16_shift_mask(&mut self, count: GPR, mask: XMM) {
+        self.assembler
+            .emit_mov(Size::S64, Location::GPR(count), Location::XMM(XMM::XMM9));
+        self.assembler
+            .emit_vpcmpeqd(mask, XMMOrMemory::XMM(mask), mask);
+        self.assembler.emit_vpsrlw_imm(mask, 8, mask);
+        self.assembler
+            .emit_vpsrlw(mask, XMMOrMemory::XMM(XMM::XMM9), mask);
+        self.assembler
+            .emit_vpackuswb(mask, XMMOrMemory::XMM(mask), mask);
+    }
+
+    /// Emits a comparison that is the negation of `op`.
+    fn emit_v128_cmpop_not(
+        &mut self,
+        op: fn(&mut Assembler, XMM, XMMOrMemory, XMM),
+        swap: bool,
+    ) -> Result<(), CodegenError> {
+        self.emit_v128_binop(|this, a, b, dst| {
+            let (a, b) = if swap { (b, a) } else { (a, b) };
+            op(&mut this.assembler, a, XMMOrMemory::XMM(b), dst);
+            this.assembler
+                .emit_vpcmpeqd(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+            this.assembler
+                .emit_vpxor(dst, XMMOrMemory::XMM(XMM::XMM10), dst);
+        })
+    }
+
+    /// Emits an unsigned "less than or equal" (`min` is `vpminu*`) or "greater than or equal"
+    /// (`min` is `vpmaxu*`) comparison, optionally negated.
+    fn emit_v128_cmpop_unsigned(
+        &mut self,
+        min_or_max: fn(&mut Assembler, XMM, XMMOrMemory, XMM),
+        eq: fn(&mut Assembler, XMM, XMMOrMemory, XMM),
+        negate: bool,
+    ) -> Result<(), CodegenError> {
+        self.emit_v128_binop(|this, a, b, dst| {
+            min_or_max(&mut this.assembler, a, XMMOrMemory::XMM(b), XMM::XMM10);
+            eq(&mut this.assembler, a, XMMOrMemory::XMM(XMM::XMM10), dst);
+            if negate {
+                this.assembler
+                    .emit_vpcmpeqd(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                this.assembler
+                    .emit_vpxor(dst, XMMOrMemory::XMM(XMM::XMM10), dst);
+            }
+        })
+    }
+
+    /// Emits an `extmul` operator, which extends the low or high halves of both inputs and
+    /// multiplies them with `op`.
+    fn emit_v128_extmul(
+        &mut self,
+        high: bool,
+        extend: fn(&mut Assembler, XMMOrMemory, XMM),
+        op: fn(&mut Assembler, XMM, XMMOrMemory, XMM),
+    ) -> Result<(), CodegenError> {
+        self.emit_v128_binop(|this, a, b, dst| {
+            if high {
+                this.assembler.emit_vpshufd(XMMOrMemory::XMM(a), 0xEE, a);
+                this.assembler.emit_vpshufd(XMMOrMemory::XMM(b), 0xEE, b);
+            }
+            extend(&mut this.assembler, XMMOrMemory::XMM(a), a);
+            extend(&mut this.assembler, XMMOrMemory::XMM(b), b);
+            op(&mut this.assembler, a, XMMOrMemory::XMM(b), dst);
+        })
+    }
+
+    /// Emits a widening operator that extends the low or high half of its input.
+    fn emit_v128_widen(
+        &mut self,
+        high: bool,
+        extend: fn(&mut Assembler, XMMOrMemory, XMM),
+    ) -> Result<(), CodegenError> {
+        self.emit_v128_unop(|this, a, dst| {
+            if high {
+                this.assembler.emit_vpshufd(XMMOrMemory::XMM(a), 0xEE, a);
+            }
+            extend(&mut this.assembler, XMMOrMemory::XMM(a), dst);
+        })
+    }
+
+    /// Emits a SIMD shift operator. `f` is called with the input register, a GPR holding the shift
+    /// amount modulo `lane_bits` and the output register.
+    fn emit_v128_shift<F: FnOnce(&mut Self, XMM, GPR, XMM)>(
+        &mut self,
+        lane_bits: u32,
+        f: F,
+    ) -> Result<(), CodegenError> {
+        let count = self.pop_value_released();
+        let a = self.pop_v128_released()?;
+        self.emit_v128_load(a, XMM::XMM8);
+        let tmp = self.machine.acquire_temp_gpr().unwrap();
+        self.assembler
+            .emit_mov(Size::S32, count, Location::GPR(tmp));
+        self.assembler.emit_and(
+            Size::S32,
+            Location::Imm32(lane_bits - 1),
+            Location::GPR(tmp),
+        );
+        let ret = self.acquire_v128();
+        f(self, XMM::XMM8, tmp, XMM::XMM8);
+        self.emit_v128_store(XMM::XMM8, ret);
+        self.machine.release_temp_gpr(tmp);
+        Ok(())
+    }
+
+    /// Emits a SIMD shift operator that maps directly to an instruction taking the shift amount in
+    /// an XMM register.
+    fn emit_v128_shift_avx(
+        &mut self,
+        lane_bits: u32,
+        op: fn(&mut Assembler, XMM, XMMOrMemory, XMM),
+    ) -> Result<(), CodegenError> {
+        self.emit_v128_shift(lane_bits, |this, a, count, dst| {
+            this.assembler
+                .emit_mov(Size::S64, Location::GPR(count), Location::XMM(XMM::XMM9));
+            op(&mut this.assembler, a, XMMOrMemory::XMM(XMM::XMM9), dst);
+        })
+    }
+
+    /// Emits a SIMD operator producing a scalar `i32` from a `v128` input. `f` is called with the
+    /// input register and a GPR to write the result to.
+    fn emit_v128_to_i32<F: FnOnce(&mut Self, XMM, GPR)>(
+        &mut self,
+        f: F,
+    ) -> Result<(), CodegenError> {
+        self.emit_v128_extract(WpType::I32, f)
+    }
+
+    /// Emits a SIMD operator producing a scalar of type `ty` from a `v128` input. `f` is called
+    /// with the input register and a GPR to write the result bits to.
+    fn emit_v128_extract<F: FnOnce(&mut Self, XMM, GPR)>(
+        &mut self,
+        ty: WpType,
+        f: F,
+    ) -> Result<(), CodegenError> {
+        let a = self.pop_v128_released()?;
+        self.emit_v128_load(a, XMM::XMM8);
+        let tmp = self.machine.acquire_temp_gpr().unwrap();
+        f(self, XMM::XMM8, tmp);
+        let ret = self.machine.acquire_locations(
+            &mut self.assembler,
+            &[(ty, MachineValue::WasmStack(self.value_stack.len()))],
+            false,
+        )[0];
+        self.value_stack.push(ret);
+        if ty.is_float() {
+            self.fp_stack
+                .push(FloatValue::new(self.value_stack.len() - 1));
+        }
+        self.assembler.emit_mov(Size::S64, Location::GPR(tmp), ret);
+        self.machine.release_temp_gpr(tmp);
+        Ok(())
+    }
+
+    /// Emits a `*.any_true` or `*.all_true` operator.
+    ///
+    /// For `all_true`, `cmpeq` is the lane-wise comparison used to find zero lanes.
+    fn emit_v128_test(
+        &mut self,
+        cmpeq: Option<fn(&mut Assembler, XMM, XMMOrMemory, XMM)>,
+    ) -> Result<(), CodegenError> {
+        self.emit_v128_to_i32(|this, a, ret| {
+            let cond = if let Some(cmpeq) = cmpeq {
+                this.assembler
+                    .emit_vpxor(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                cmpeq(
+                    &mut this.assembler,
+                    a,
+                    XMMOrMemory::XMM(XMM::XMM10),
+                    XMM::XMM10,
+                );
+                this.assembler
+                    .emit_vptest(XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                Condition::Equal
+            } else {
+                this.assembler.emit_vptest(XMMOrMemory::XMM(a), a);
+                Condition::NotEqual
+            };
+            this.assembler.emit_set(cond, ret);
+            this.assembler
+                .emit_and(Size::S32, Location::Imm32(0xff), Location::GPR(ret));
+        })
+    }
+
+    /// Emits a `splat` operator. `f` is called with a register holding the scalar in its low bits
+    /// and the output register.
+    fn emit_v128_splat<F: FnOnce(&mut Self, XMM, XMM)>(
+        &mut self,
+        ty: WpType,
+        f: F,
+    ) -> Result<(), CodegenError> {
+        let tmp = self.machine.acquire_temp_gpr().unwrap();
+        self.pop_simd_scalar_to_gpr(ty, tmp)?;
+        self.assembler
+            .emit_mov(Size::S64, Location::GPR(tmp), Location::XMM(XMM::XMM8));
+        self.machine.release_temp_gpr(tmp);
+        let ret = self.acquire_v128();
+        f(self, XMM::XMM8, XMM::XMM8);
+        self.emit_v128_store(XMM::XMM8, ret);
+        Ok(())
+    }
+
+    /// Emits a `replace_lane` operator.
+    fn emit_v128_replace_lane(
+        &mut self,
+        ty: WpType,
+        op: fn(&mut Assembler, XMM, GPROrMemory, u8, XMM),
+        lane: u8,
+    ) -> Result<(), CodegenError> {
+        let tmp = self.machine.acquire_temp_gpr().unwrap();
+        self.pop_simd_scalar_to_gpr(ty, tmp)?;
+        let a = self.pop_v128_released()?;
+        self.emit_v128_load(a, XMM::XMM8);
+        let ret = self.acquire_v128();
+        op(
+            &mut self.assembler,
+            XMM::XMM8,
+            GPROrMemory::GPR(tmp),
+            lane,
+            XMM::XMM8,
+        );
+        self.emit_v128_store(XMM::XMM8, ret);
+        self.machine.release_temp_gpr(tmp);
+        Ok(())
+    }
+
+    /// Emits a `v128` load that reads `value_size` bytes. `f` is called with the address of the
+    /// data and the output register.
+    fn emit_v128_load_op<F: FnOnce(&mut Self, GPR, XMM)>(
+        &mut self,
+        memarg: &MemoryImmediate,
+        value_size: usize,
+        f: F,
+    ) -> Result<(), CodegenError> {
+        let target = self.pop_value_released();
+        let ret = self.acquire_v128();
+        self.emit_memory_op(target, memarg, false, value_size, |this, addr| {
+            f(this, addr, XMM::XMM8);
+            Ok(())
+        })?;
+        self.emit_v128_store(XMM::XMM8, ret);
+        Ok(())
+    }
+
+    /// Emits a `v128.loadN_splat` operator.
+    fn emit_v128_load_splat(
+        &mut self,
+        memarg: &MemoryImmediate,
+        value_size: usize,
+    ) -> Result<(), CodegenError> {
+        self.emit_v128_load_op(memarg, value_size, |this, addr, dst| {
+            let src = GPROrMemory::Memory(addr, 0);
+            match value_size {
+                1 => {
+                    this.assembler.emit_vpinsrb(dst, src, 0, dst);
+                    this.assembler
+                        .emit_vpxor(XMM::XMM9, XMMOrMemory::XMM(XMM::XMM9), XMM::XMM9);
+                    this.assembler
+                        .emit_vpshufb(dst, XMMOrMemory::XMM(XMM::XMM9), dst);
+                }
+                2 => {
+                    this.assembler.emit_vpinsrw(dst, src, 0, dst);
+                    this.assembler.emit_vpshuflw(XMMOrMemory::XMM(dst), 0, dst);
+                    this.assembler.emit_vpshufd(XMMOrMemory::XMM(dst), 0, dst);
+                }
+                4 => {
+                    this.assembler.emit_vpinsrd(dst, src, 0, dst);
+                    this.assembler.emit_vpshufd(XMMOrMemory::XMM(dst), 0, dst);
+                }
+                _ => {
+                    this.assembler.emit_vpinsrq(dst, src, 0, dst);
+                    this.assembler
+                        .emit_vpshufd(XMMOrMemory::XMM(dst), 0x44, dst);
+                }
+            }
+        })
+    }
+
+    /// Emits a `v128.loadNxM_{s,u}` operator.
+    fn emit_v128_load_extend(
+        &mut self,
+        memarg: &MemoryImmediate,
+        extend: fn(&mut Assembler, XMMOrMemory, XMM),
+    ) -> Result<(), CodegenError> {
+        self.emit_v128_load_op(memarg, 8, |this, addr, dst| {
+            extend(&mut this.assembler, XMMOrMemory::Memory(addr, 0), dst);
+        })
+    }
+
+    /// Emits a `v128.loadN_lane` operator.
+    fn emit_v128_load_lane(
+        &mut self,
+        memarg: &MemoryImmediate,
+        value_size: usize,
+        op: fn(&mut Assembler, XMM, GPROrMemory, u8, XMM),
+        lane: u8,
+    ) -> Result<(), CodegenError> {
+        let a = self.pop_v128_released()?;
+        let target = self.pop_value_released();
+        self.emit_v128_load(a, XMM::XMM8);
+        let ret = self.acquire_v128();
+        self.emit_memory_op(target, memarg, false, value_size, |this, addr| {
+            op(
+                &mut this.assembler,
+                XMM::XMM8,
+                GPROrMemory::Memory(addr, 0),
+                lane,
+                XMM::XMM8,
+            );
+            Ok(())
+        })?;
+        self.emit_v128_store(XMM::XMM8, ret);
+        Ok(())
+    }
+
+    /// Emits a `v128.storeN_lane` operator.
+    fn emit_v128_store_lane(
+        &mut self,
+        memarg: &MemoryImmediate,
+        value_size: usize,
+        op: fn(&mut Assembler, XMM, u8, GPROrMemory),
+        lane: u8,
+    ) -> Result<(), CodegenError> {
+        let a = self.pop_v128_released()?;
+        let target = self.pop_value_released();
+        self.emit_v128_load(a, XMM::XMM8);
+        self.emit_memory_op(target, memarg, false, value_size, |this, addr| {
+            op(
+                &mut this.assembler,
+                XMM::XMM8,
+                lane,
+                GPROrMemory::Memory(addr, 0),
+            );
+            Ok(())
+        })
+    }
+
+    /// Moves the `v128` value on top of the value stack into RAX (low half) and RDX (high half),
+    /// which is where `v128` block and function results are passed.
+    fn emit_v128_to_result_regs(&mut self) {
+        let hi = self.value_stack[self.value_stack.len() - 1];
+        let lo = self.value_stack[self.value_stack.len() - 2];
+        self.assembler
+            .emit_mov(Size::S64, lo, Location::GPR(GPR::RAX));
+        self.assembler
+            .emit_mov(Size::S64, hi, Location::GPR(GPR::RDX));
+    }
+
+    /// Pushes a `v128` result passed in RAX (low half) and RDX (high half) to the value stack.
+    fn push_v128_from_result_regs(&mut self) {
+        let (lo, hi) = self.acquire_v128();
+        self.assembler
+            .emit_mov(Size::S64, Location::GPR(GPR::RAX), lo);
+        self.assembler
+            .emit_mov(Size::S64, Location::GPR(GPR::RDX), hi);
+    }
+
+    /// Returns the parameter and result types of a block.
+    fn block_signature(
+        &self,
+        ty: WpTypeOrFuncType,
+    ) -> (SmallVec<[WpType; 1]>, SmallVec<[WpType; 1]>) {
+        match ty {
+            WpTypeOrFuncType::Type(WpType::EmptyBlockType) => (smallvec![], smallvec![]),
+            WpTypeOrFuncType::Type(inner_ty) => (smallvec![], smallvec![inner_ty]),
+            WpTypeOrFuncType::FuncType(index) => {
+                let sig = &self.module.signatures[SignatureIndex::new(index as usize)];
+                (
+                    sig.params().iter().cloned().map(type_to_wp_type).collect(),
+                    sig.results().iter().cloned().map(type_to_wp_type).collect(),
+                )
+            }
+        }
+    }
+
+    /// Reserves `n` machine stack slots below the top `depth` values of the value stack.
+    ///
+    /// Stack slots have to be released in the reverse order of their acquisition, so the values in
+    /// that range that live on the machine stack are moved up to make room.
+    fn reserve_slots_below(&mut self, depth: usize, n: usize) -> SmallVec<[Location; 1]> {
+        let in_memory: SmallVec<[usize; 8]> = (self.value_stack.len() - depth
+            ..self.value_stack.len())
+            .filter(|&i| matches!(self.value_stack[i], Location::Memory(GPR::RBP, _)))
+            .collect();
+        let mut slots: SmallVec<[Location; 8]> =
+            in_memory.iter().map(|&i| self.value_stack[i]).collect();
+        slots.extend(
+            self.machine
+                .acquire_stack_locations(&mut self.assembler, &vec![MachineValue::Undefined; n]),
+        );
+
+        // Moving from the top down never overwrites a value that is yet to be moved.
+        for (j, &i) in in_memory.iter().enumerate().rev() {
+            let (src, dst) = (self.value_stack[i], slots[n + j]);
+            self.emit_relaxed_binop(Assembler::emit_mov, Size::S64, src, dst);
+            self.value_stack[i] = dst;
+        }
+        slots[..n].iter().cloned().collect()
+    }
+
+    /// Copies the top `dsts.len()` values of the value stack to `dsts`, canonicalizing NaNs
+    /// where needed. The values are left on the value stack.
+    fn emit_move_top_values(&mut self, dsts: &[Location]) {
+        let start = self.value_stack.len() - dsts.len();
+        for (i, &dst) in dsts.iter().enumerate() {
+            let src = self.value_stack[start + i];
+            let canonicalization = self
+                .fp_stack
+                .iter()
+                .rev()
+                .take_while(|fp| fp.depth >= start)
+                .find(|fp| fp.depth == start + i)
+                .and_then(|fp| fp.canonicalization);
+            match canonicalization {
+                Some(fp)
+                    if self.assembler.arch_supports_canonicalize_nan()
+                        && self.config.enable_nan_canonicalization =>
+                {
+                    self.canonicalize_nan(fp.to_size(), src, dst);
+                }
+                _ => self.emit_relaxed_binop(Assembler::emit_mov, Size::S64, src, dst),
+            }
+        }
+    }
+
+    /// Pops the top `n` values off the value stack and releases their locations.
+    fn release_top_values(&mut self, n: usize) {
+        let depth = self.value_stack.len() - n;
+        let released: SmallVec<[Location; 8]> = self.value_stack.drain(depth..).collect();
+        self.machine
+            .release_locations(&mut self.assembler, &released);
+        while self.fp_stack.last().map_or(false, |fp| fp.depth >= depth) {
+            self.fp_stack.pop();
+        }
+        self.truncate_v128_stack(depth);
+    }
+
+    /// Pushes values of types `tys`, which are held in `slots`, to the value stack.
+    fn push_slots(&mut self, tys: &[WpType], slots: &[Location]) {
+        let mut slots = slots.iter().cloned();
+        for &ty in tys {
+            let depth = self.value_stack.len();
+            if ty == WpType::V128 {
+                self.v128_stack.push(depth);
+                self.value_stack.extend(slots.by_ref().take(2));
+            } else {
+                self.value_stack.extend(slots.next());
+                if ty.is_float() {
+                    self.fp_stack.push(FloatValue::new(depth));
+                }
+            }
+        }
+    }
+
+    /// Pushes copies of values of types `tys`, which are held in `slots`, to the value stack.
+    fn push_slot_copies(&mut self, tys: &[WpType], slots: &[Location]) {
+        let depth = self.value_stack.len();
+        for &ty in tys {
+            if ty == WpType::V128 {
+                self.acquire_v128();
+            } else {
+                let loc = self.machine.acquire_locations(
+                    &mut self.assembler,
+                    &[(ty, MachineValue::WasmStack(self.value_stack.len()))],
+                    false,
+                )[0];
+                self.value_stack.push(loc);
+                if ty.is_float() {
+                    self.fp_stack
+                        .push(FloatValue::new(self.value_stack.len() - 1));
+                }
+            }
+        }
+        for (i, &src) in slots.iter().enumerate() {
+            let dst = self.value_stack[depth + i];
+            self.emit_relaxed_binop(Assembler::emit_mov, Size::S64, src, dst);
+        }
+    }
+
+    /// Moves the results on top of the value stack to where the control frame at `frame_index`
+    /// expects them when its end is reached.
+    fn emit_frame_results(&mut self, frame_index: usize) -> Result<(), CodegenError> {
+        let frame = &self.control_stack[frame_index];
+        if !frame.return_slots.is_empty() {
+            let return_slots = frame.return_slots.clone();
+            self.emit_move_top_values(&return_slots);
+        } else if frame_index == 0 && frame.returns.len() > 1 {
+            let n_slots: usize = frame.returns.iter().map(|&x| wp_type_slots(x)).sum();
+            let return_area = self.locals[self.return_area_slot.unwrap()];
+            let tmp = self.machine.acquire_temp_gpr().unwrap();
+            self.assembler
+                .emit_mov(Size::S64, return_area, Location::GPR(tmp));
+            let dsts: SmallVec<[Location; 8]> = (0..n_slots)
+                .map(|i| Location::Memory(tmp, -8 * i as i32))
+                .collect();
+            self.emit_move_top_values(&dsts);
+            self.machine.release_temp_gpr(tmp);
+        } else if !frame.returns.is_empty() {
+            if frame.returns.len() != 1 {
+                return Err(CodegenError {
+                    message: "emit_frame_results: incorrect frame.returns".to_string(),
+                });
+            }
+            let first_return = frame.returns[0];
+            let loc = *self.value_stack.last().unwrap();
+            if first_return == WpType::V128 {
+                self.emit_v128_to_result_regs();
+            } else if first_return.is_float() {
+                let fp = self.fp_stack.peek1()?;
+                if self.assembler.arch_supports_canonicalize_nan()
+                    && self.config.enable_nan_canonicalization
+                    && fp.canonicalization.is_some()
+                {
+                    self.canonicalize_nan(
+                        match first_return {
+                            WpType::F32 => Size::S32,
+                            WpType::F64 => Size::S64,
+                            _ => unreachable!(),
+                        },
+                        loc,
+                        Location::GPR(GPR::RAX),
+                    );
+                } else {
+                    self.emit_relaxed_binop(
+                        Assembler::emit_mov,
+                        Size::S64,
+                        loc,
+                        Location::GPR(GPR::RAX),
+                    );
+                }
+            } else {
+                self.emit_relaxed_binop(
+                    Assembler::emit_mov,
+                    Size::S64,
+                    loc,
+                    Location::GPR(GPR::RAX),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the values that a branch to the control frame at `frame_index` carries to where the
+    /// frame expects them: the parameters of a loop, or the results of any other block.
+    fn emit_branch_values(&mut self, frame_index: usize) -> Result<(), CodegenError> {
+        let frame = &self.control_stack[frame_index];
+        if frame.loop_like {
+            let param_slots = frame.param_slots.clone();
+            self.emit_move_top_values(&param_slots);
+            Ok(())
+        } else {
+            self.emit_frame_results(frame_index)
+        }
+    }
+
+    /// Reserves the area that a call to a function returning multiple values stores its results
+    /// to, below the top `depth` values of the value stack which hold the operands of the call.
+    ///
+    /// Returns the slots of the area, and a stack slot holding the pointer to the area that is
+    /// passed as the last argument of the call.
+    fn emit_return_area(
+        &mut self,
+        depth: usize,
+        returns: &[WpType],
+    ) -> (SmallVec<[Location; 1]>, Location) {
+        let n_slots: usize = returns.iter().map(|&x| wp_type_slots(x)).sum();
+        let mut slots = self.reserve_slots_below(depth, n_slots + 1);
+        let ptr = slots.pop().unwrap();
+        let tmp = self.machine.acquire_temp_gpr().unwrap();
+        self.assembler
+            .emit_lea(Size::S64, slots[0], Location::GPR(tmp));
+        self.assembler.emit_mov(Size::S64, Location::GPR(tmp), ptr);
+        self.machine.release_temp_gpr(tmp);
+        (slots, ptr)
+    }
+
+    /// Emits a System V call sequence.
+    ///
+    /// This function will not use RAX before `cb` is called.
+    ///
+    /// The caller MUST NOT hold any temporary registers allocated by `acquire_temp_gpr` when calling
+    /// this function.
+    fn emit_call_sysv<I: Iterator<Item = Location>, F: FnOnce(&mut Self)>(
+        &mut self,
+        cb: F,
+        params: I,
+    ) -> Result<(), CodegenError> {
+        // Values pushed in this function are above the shadow region.
+        self.machine
+            .state
+            .stack_values
+            .push(MachineValue::ExplicitShadow);
+
+        let params: Vec<_> = params.collect();
+
+        // Saving registers and passing arguments grows the stack, which may overflow it.
+        let begin = self.assembler.get_offset().0;
+
+        // Save used GPRs.
+        let used_gprs = self.machine.get_used_gprs();
+        for r in used_gprs.iter() {
+            self.assembler.emit_push(Size::S64, Location::GPR(*r));
+            let content =
+                self.machine.state.register_values[X64Register::GPR(*r).to_index().0].clone();
+            if content == MachineValue::Undefined {
+                return Err(CodegenError {
+                    message: "emit_call_sysv: Undefined used_gprs content".to_string(),
+                });
+            }
+            self.machine.state.stack_values.push(content);
+        }
+
+        // Save used XMM registers.
+        let used_xmms = self.machine.get_used_xmms();
+        if used_xmms.len() > 0 {
+            self.assembler.emit_sub(
+                Size::S64,
+                Location::Imm32((used_xmms.len() * 8) as u32),
+                Location::GPR(GPR::RSP),
+            );
+
+            for (i, r) in used_xmms.iter().enumerate() {
+                self.assembler.emit_mov(
+                    Size::S64,
+                    Location::XMM(*r),
+                    Location::Memory(GPR::RSP, (i * 8) as i32),
+                );
+            }
+            for r in used_xmms.iter().rev() {
+                let content =
+                    self.machine.state.register_values[X64Register::XMM(*r).to_index().0].clone();
+                if content == MachineValue::Undefined {
+                    return Err(CodegenError {
+                        message: "emit_call_sysv: Undefined used_xmms content".to_string(),
+                    });
+                }
+                self.machine.state.stack_values.push(content);
+            }
+        }
+
+        let mut stack_offset: usize = 0;
+
+        // Calculate stack offset.
+        for (i, _param) in params.iter().enumerate() {
+            if let Location::Memory(_, _) = Machine::get_param_location(1 + i) {
+                stack_offset += 8;
+            }
+        }
+
+        // Align stack to 16 bytes.
+        if (self.machine.get_stack_offset()
+            + used_gprs.len() * 8
+            + used_xmms.len() * 8
+            + stack_offset)
+            % 16
+            != 0
+        {
+            self.assembler
+                .emit_sub(Size::S64, Location::Imm32(8), Location::GPR(GPR::RSP));
+            stack_offset += 8;
+            self.machine
+                .state
+                .stack_values
+                .push(MachineValue::Undefined);
+        }
+
+        let mut call_movs: Vec<(Location, GPR)> = vec![];
+
+        // Prepare register & stack parameters.
+        for (i, param) in params.iter().enumerate().rev() {
+            let loc = Machine::get_param_location(1 + i);
+            match loc {
+                Location::GPR(x) => {
+                    call_movs.push((*param, x));
+                }
+                Location::Memory(_, _) => {
+                    match *param {
+                        Location::GPR(x) => {
+                            let content = self.machine.state.register_values
+                                [X64Register::GPR(x).to_index().0]
+                                .clone();
+                            // FIXME: There might be some corner cases (release -> emit_call_sysv -> acquire?) that cause this assertion to fail.
+                            // Hopefully nothing would be incorrect at runtime.
+
+                            //assert!(content != MachineValue::Undefined);
+                            self.machine.state.stack_values.push(content);
+                        }
+                        Location::XMM(x) => {
+                            let content = self.machine.state.register_values
+                                [X64Register::XMM(x).to_index().0]
+                                .clone();
+                            //assert!(content != MachineValue::Undefined);
+                            self.machine.state.stack_values.push(content);
+                        }
+                        Location::Memory(reg, offset) => {
+                            if reg != GPR::RBP {
+                                return Err(CodegenError {
+                                    message: "emit_call_sysv loc param: unreachable code"
+                                        .to_string(),
+                                });
+                            }
+                            self.machine
+                                .state
+                                .stack_values
+                                .push(MachineValue::CopyStackBPRelative(offset));
+                            // TODO: Read value at this offset
+                        }
+                        _ => {
+                            self.machine
+                                .state
+                                .stack_values
+                                .push(MachineValue::Undefined);
+                        }
+                    }
+                    match *param {
+                        Location::Imm64(_) => {
+                            // Dummy value slot to be filled with `mov`.
+                            self.assembler.emit_push(Size::S64, Location::GPR(GPR::RAX));
+
+                            // Use RCX as the temporary register here, since:
+                            // - It is a temporary register that is not used for any persistent value.
+                            // - This register as an argument location is only written to after `sort_call_movs`.'
+                            self.machine.reserve_unused_temp_gpr(GPR::RCX);
+                            self.assembler
+                                .emit_mov(Size::S64, *param, Location::GPR(GPR::RCX));
+                            self.assembler.emit_mov(
+                                Size::S64,
+                                Location::GPR(GPR::RCX),
+                                Location::Memory(GPR::RSP, 0),
+                            );
+                            self.machine.release_temp_gpr(GPR::RCX);
+                        }
+                        Location::XMM(_) => {
+                            // Dummy value slot to be filled with `mov`.
+                            self.assembler.emit_push(Size::S64, Location::GPR(GPR::RAX));
+
+                            // XMM registers can be directly stored to memory.
+                            self.assembler.emit_mov(
+                                Size::S64,
+                                *param,
+                                Location::Memory(GPR::RSP, 0),
+                            );
+                        }
+                        _ => self.assembler.emit_push(Size::S64, *param),
+                    }
+                }
+                _ => {
+                    return Err(CodegenError {
+                        message: "emit_call_sysv loc: unreachable code".to_string(),
+                    })
+                }
+            }
+        }
+
+        // Sort register moves so that register are not overwritten before read.
+        sort_call_movs(&mut call_movs);
+
+        // Emit register moves.
+        for (loc, gpr) in call_movs {
+            if loc != Location::GPR(gpr) {
+                self.assembler.emit_mov(Size::S64, loc, Location::GPR(gpr));
+            }
+        }
+
+        // Put vmctx as the first parameter.
+        self.assembler.emit_mov(
+            Size::S64,
+            Location::GPR(Machine::get_vmctx_reg()),
+            Machine::get_param_location(0),
+        ); // vmctx
+
+        if (self.machine.state.stack_values.len() % 2) != 1 {
+            return Err(CodegenError {
+                message: "emit_call_sysv: explicit shadow takes one slot".to_string(),
+            });
+        }
+
+        self.trap_table
+            .offset_to_code
+            .insert(begin, TrapCode::StackOverflow);
+        self.mark_instruction_address_end(begin);
+
+        cb(self);
+
+        // Offset needs to be after the 'call' instruction.
+        // TODO: Now the state information is also inserted for internal calls (e.g. MemoryGrow). Is this expected?
+        {
+            let state_diff_id = self.get_state_diff();
+            let offset = self.assembler.get_offset().0;
+            self.fsm.call_offsets.insert(
+                offset,
+                OffsetInfo {
+                    end_offset: offset + 1,
+                    activate_offset: offset,
                     diff_id: state_diff_id,
                 },
             );
@@ -1730,6 +2658,78 @@ impl<'a> FuncGen<'a> {
         id
     }
 
+    /// Emits a check of the interrupt flag and of the epoch deadline,
+    /// calling into the runtime when the flag is set or the deadline is
+    /// reached.
+    fn emit_interrupt_check(&mut self) -> Result<(), CodegenError> {
+        let check = self.assembler.get_label();
+        let not_interrupted = self.assembler.get_label();
+
+        let tmp_interrupts = self.machine.acquire_temp_gpr().unwrap();
+        let tmp_epoch = self.machine.acquire_temp_gpr().unwrap();
+        self.assembler.emit_mov(
+            Size::S64,
+            Location::Memory(
+                Machine::get_vmctx_reg(),
+                self.vmoffsets.vmctx_interrupts() as i32,
+            ),
+            Location::GPR(tmp_interrupts),
+        );
+        self.assembler.emit_cmp(
+            Size::S32,
+            Location::Imm32(0),
+            Location::Memory(
+                tmp_interrupts,
+                self.vmoffsets.vminterrupts_interrupted() as i32,
+            ),
+        );
+        self.assembler.emit_jmp(Condition::NotEqual, check);
+
+        self.assembler.emit_mov(
+            Size::S64,
+            Location::Memory(tmp_interrupts, self.vmoffsets.vminterrupts_epoch() as i32),
+            Location::GPR(tmp_epoch),
+        );
+        self.assembler.emit_mov(
+            Size::S64,
+            Location::Memory(tmp_epoch, 0),
+            Location::GPR(tmp_epoch),
+        );
+        self.assembler.emit_cmp(
+            Size::S64,
+            Location::Memory(
+                tmp_interrupts,
+                self.vmoffsets.vminterrupts_epoch_deadline() as i32,
+            ),
+            Location::GPR(tmp_epoch),
+        );
+        self.machine.release_temp_gpr(tmp_epoch);
+        self.machine.release_temp_gpr(tmp_interrupts);
+        self.assembler.emit_jmp(Condition::Below, not_interrupted);
+
+        self.assembler.emit_label(check);
+        self.assembler.emit_mov(
+            Size::S64,
+            Location::Memory(
+                Machine::get_vmctx_reg(),
+                self.vmoffsets
+                    .vmctx_builtin_function(VMBuiltinFunctionIndex::get_interrupt_index())
+                    as i32,
+            ),
+            Location::GPR(GPR::RAX),
+        );
+        self.emit_call_sysv(
+            |this| {
+                this.assembler.emit_call_register(GPR::RAX);
+            },
+            // [vmctx]
+            iter::empty(),
+        )?;
+
+        self.assembler.emit_label(not_interrupted);
+        Ok(())
+    }
+
     fn emit_head(&mut self) -> Result<(), CodegenError> {
         // TODO: Patchpoint is not emitted for now, and ARM trampoline is not prepended.
 
@@ -1739,11 +2739,23 @@ impl<'a> FuncGen<'a> {
             .emit_mov(Size::S64, Location::GPR(GPR::RSP), Location::GPR(GPR::RBP));
 
         // Initialize locals.
-        self.locals = self.machine.init_locals(
-            &mut self.assembler,
-            self.local_types.len(),
-            self.signature.params().len(),
-        );
+        let n_return_area_slots = self.return_area_slot.is_some() as usize;
+        let n_param_slots = self
+            .signature
+            .params()
+            .iter()
+            .map(|&x| wp_type_slots(type_to_wp_type(x)))
+            .sum::<usize>()
+            + n_return_area_slots;
+        let n_slots = self
+            .local_types
+            .iter()
+            .map(|&x| wp_type_slots(x))
+            .sum::<usize>()
+            + n_return_area_slots;
+        self.locals = self
+            .machine
+            .init_locals(&mut self.assembler, n_slots, n_param_slots);
 
         // Mark vmctx register. The actual loading of the vmctx value is handled by init_local.
         self.machine.state.register_values
@@ -1761,20 +2773,21 @@ impl<'a> FuncGen<'a> {
             label: self.assembler.get_label(),
             loop_like: false,
             if_else: IfElseState::None,
+            params: smallvec![],
             returns: self
                 .signature
                 .results()
                 .iter()
                 .map(|&x| type_to_wp_type(x))
                 .collect(),
+            return_slots: smallvec![],
+            param_slots: smallvec![],
             value_stack_depth: 0,
             fp_stack_depth: 0,
             state: self.machine.state.clone(),
             state_diff_id,
         });
 
-        // TODO: Full preemption by explicit signal checking
-
         // We insert set StackOverflow as the default trap that can happen
         // anywhere in the function prologue.
         let offset = 0;
@@ -1783,6 +2796,8 @@ impl<'a> FuncGen<'a> {
             .insert(offset, TrapCode::StackOverflow);
         self.mark_instruction_address_end(offset);
 
+        self.emit_interrupt_check()?;
+
         if self.machine.state.wasm_inst_offset != std::usize::MAX {
             return Err(CodegenError {
                 message: "emit_head: wasm_inst_offset not std::usize::MAX".to_string(),
@@ -1821,6 +2836,24 @@ impl<'a> FuncGen<'a> {
             .collect();
         local_types.extend_from_slice(&local_types_excluding_arguments);
 
+        let num_params = signature.params().len();
+        let mut local_slots = Vec::with_capacity(local_types.len());
+        let mut n_slots = 0;
+        for ty in local_types[..num_params].iter() {
+            local_slots.push(n_slots);
+            n_slots += wp_type_slots(*ty);
+        }
+        let return_area_slot = if signature.results().len() > 1 {
+            n_slots += 1;
+            Some(n_slots - 1)
+        } else {
+            None
+        };
+        for ty in local_types[num_params..].iter() {
+            local_slots.push(n_slots);
+            n_slots += wp_type_slots(*ty);
+        }
+
         let fsm = FunctionStateMap::new(
             new_machine_state(),
             local_func_index.index() as usize,
@@ -1849,8 +2882,11 @@ impl<'a> FuncGen<'a> {
             assembler,
             locals: vec![], // initialization deferred to emit_head
             local_types,
+            local_slots,
+            return_area_slot,
             value_stack: vec![],
             fp_stack: vec![],
+            v128_stack: vec![],
             control_stack: vec![],
             machine: Machine::new(),
             unreachable_depth: 0,
@@ -1859,6 +2895,7 @@ impl<'a> FuncGen<'a> {
             relocations: vec![],
             special_labels,
             src_loc: 0,
+            unreachable_trap_code: TrapCode::UnreachableCodeReached,
             instructions_address_map: vec![],
         };
         fg.emit_head()?;
@@ -1914,12 +2951,18 @@ impl<'a> FuncGen<'a> {
                 if ty.is_float() {
                     self.fp_stack.push(FloatValue::new(self.value_stack.len()));
                 }
-                let loc = self.machine.acquire_locations(
-                    &mut self.assembler,
-                    &[(ty, MachineValue::WasmStack(self.value_stack.len()))],
-                    false,
-                )[0];
-                self.value_stack.push(loc);
+                let (loc, hi) = if ty == WpType::V128 {
+                    let (lo, hi) = self.acquire_v128();
+                    (lo, Some(hi))
+                } else {
+                    let loc = self.machine.acquire_locations(
+                        &mut self.assembler,
+                        &[(ty, MachineValue::WasmStack(self.value_stack.len()))],
+                        false,
+                    )[0];
+                    self.value_stack.push(loc);
+                    (loc, None)
+                };
 
                 let tmp = self.machine.acquire_temp_gpr().unwrap();
 
@@ -1949,6 +2992,14 @@ impl<'a> FuncGen<'a> {
                 };
 
                 self.emit_relaxed_binop(Assembler::emit_mov, Size::S64, src, loc);
+                if let Some(hi) = hi {
+                    self.emit_relaxed_binop(
+                        Assembler::emit_mov,
+                        Size::S64,
+                        Location::Memory(tmp, 8),
+                        hi,
+                    );
+                }
 
                 self.machine.release_temp_gpr(tmp);
             }
@@ -1980,6 +3031,18 @@ impl<'a> FuncGen<'a> {
                     Location::Memory(tmp, 0)
                 };
                 let ty = type_to_wp_type(self.module.globals[global_index].ty);
+                if ty == WpType::V128 {
+                    let (lo, hi) = self.pop_v128_released()?;
+                    self.emit_relaxed_binop(Assembler::emit_mov, Size::S64, lo, dst);
+                    self.emit_relaxed_binop(
+                        Assembler::emit_mov,
+                        Size::S64,
+                        hi,
+                        Location::Memory(tmp, 8),
+                    );
+                    self.machine.release_temp_gpr(tmp);
+                    return Ok(());
+                }
                 let loc = self.pop_value_released();
                 if ty.is_float() {
                     let fp = self.fp_stack.pop1()?;
@@ -2006,6 +3069,18 @@ impl<'a> FuncGen<'a> {
             }
             Operator::LocalGet { local_index } => {
                 let local_index = local_index as usize;
+                if self.local_types[local_index] == WpType::V128 {
+                    let slot = self.local_slots[local_index];
+                    let (lo, hi) = self.acquire_v128();
+                    self.emit_relaxed_binop(Assembler::emit_mov, Size::S64, self.locals[slot], lo);
+                    self.emit_relaxed_binop(
+                        Assembler::emit_mov,
+                        Size::S64,
+                        self.locals[slot + 1],
+                        hi,
+                    );
+                    return Ok(());
+                }
                 let ret = self.machine.acquire_locations(
                     &mut self.assembler,
                     &[(WpType::I64, MachineValue::WasmStack(self.value_stack.len()))],
@@ -2014,7 +3089,7 @@ impl<'a> FuncGen<'a> {
                 self.emit_relaxed_binop(
                     Assembler::emit_mov,
                     Size::S64,
-                    self.locals[local_index],
+                    self.locals[self.local_slots[local_index]],
                     ret,
                 );
                 self.value_stack.push(ret);
@@ -2025,6 +3100,18 @@ impl<'a> FuncGen<'a> {
             }
             Operator::LocalSet { local_index } => {
                 let local_index = local_index as usize;
+                if self.local_types[local_index] == WpType::V128 {
+                    let slot = self.local_slots[local_index];
+                    let (lo, hi) = self.pop_v128_released()?;
+                    self.emit_relaxed_binop(Assembler::emit_mov, Size::S64, lo, self.locals[slot]);
+                    self.emit_relaxed_binop(
+                        Assembler::emit_mov,
+                        Size::S64,
+                        hi,
+                        self.locals[slot + 1],
+                    );
+                    return Ok(());
+                }
                 let loc = self.pop_value_released();
 
                 if self.local_types[local_index].is_float() {
@@ -2040,14 +3127,14 @@ impl<'a> FuncGen<'a> {
                                 _ => unreachable!(),
                             },
                             loc,
-                            self.locals[local_index],
+                            self.locals[self.local_slots[local_index]],
                         );
                     } else {
                         self.emit_relaxed_binop(
                             Assembler::emit_mov,
                             Size::S64,
                             loc,
-                            self.locals[local_index],
+                            self.locals[self.local_slots[local_index]],
                         );
                     }
                 } else {
@@ -2055,12 +3142,25 @@ impl<'a> FuncGen<'a> {
                         Assembler::emit_mov,
                         Size::S64,
                         loc,
-                        self.locals[local_index],
+                        self.locals[self.local_slots[local_index]],
                     );
                 }
             }
             Operator::LocalTee { local_index } => {
                 let local_index = local_index as usize;
+                if self.local_types[local_index] == WpType::V128 {
+                    let slot = self.local_slots[local_index];
+                    let hi = self.value_stack[self.value_stack.len() - 1];
+                    let lo = self.value_stack[self.value_stack.len() - 2];
+                    self.emit_relaxed_binop(Assembler::emit_mov, Size::S64, lo, self.locals[slot]);
+                    self.emit_relaxed_binop(
+                        Assembler::emit_mov,
+                        Size::S64,
+                        hi,
+                        self.locals[slot + 1],
+                    );
+                    return Ok(());
+                }
                 let loc = *self.value_stack.last().unwrap();
 
                 if self.local_types[local_index].is_float() {
@@ -2076,14 +3176,14 @@ impl<'a> FuncGen<'a> {
                                 _ => unreachable!(),
                             },
                             loc,
-                            self.locals[local_index],
+                            self.locals[self.local_slots[local_index]],
                         );
                     } else {
                         self.emit_relaxed_binop(
                             Assembler::emit_mov,
                             Size::S64,
                             loc,
-                            self.locals[local_index],
+                            self.locals[self.local_slots[local_index]],
                         );
                     }
                 } else {
@@ -2091,7 +3191,7 @@ impl<'a> FuncGen<'a> {
                         Assembler::emit_mov,
                         Size::S64,
                         loc,
-                        self.locals[local_index],
+                        self.locals[self.local_slots[local_index]],
                     );
                 }
             }
@@ -5151,10 +6251,17 @@ impl<'a> FuncGen<'a> {
                 let return_types: SmallVec<[WpType; 1]> =
                     sig.results().iter().cloned().map(type_to_wp_type).collect();
 
+                let param_slots: usize = param_types.iter().map(|&x| wp_type_slots(x)).sum();
+                let return_area = if return_types.len() > 1 {
+                    Some(self.emit_return_area(param_slots, &return_types))
+                } else {
+                    None
+                };
                 let params: SmallVec<[_; 8]> = self
                     .value_stack
-                    .drain(self.value_stack.len() - param_types.len()..)
+                    .drain(self.value_stack.len() - param_slots..)
                     .collect();
+                self.truncate_v128_stack(self.value_stack.len());
                 self.machine.release_locations_only_regs(&params);
 
                 self.machine.release_locations_only_osr_state(params.len());
@@ -5213,13 +6320,21 @@ impl<'a> FuncGen<'a> {
                         this.assembler.emit_call_location(Location::GPR(GPR::RAX));
                         this.mark_instruction_address_end(offset);
                     },
-                    params.iter().copied(),
+                    params
+                        .iter()
+                        .copied()
+                        .chain(return_area.as_ref().map(|(_, ptr)| *ptr)),
                 )?;
 
                 self.machine
                     .release_locations_only_stack(&mut self.assembler, &params);
 
-                if !return_types.is_empty() {
+                if let Some((slots, ptr)) = return_area {
+                    self.machine.release_locations(&mut self.assembler, &[ptr]);
+                    self.push_slots(&return_types, &slots);
+                } else if return_types.first() == Some(&WpType::V128) {
+                    self.push_v128_from_result_regs();
+                } else if !return_types.is_empty() {
                     let ret = self.machine.acquire_locations(
                         &mut self.assembler,
                         &[(
@@ -5254,12 +6369,20 @@ impl<'a> FuncGen<'a> {
                 let return_types: SmallVec<[WpType; 1]> =
                     sig.results().iter().cloned().map(type_to_wp_type).collect();
 
+                let param_slots: usize = param_types.iter().map(|&x| wp_type_slots(x)).sum();
+                let return_area = if return_types.len() > 1 {
+                    Some(self.emit_return_area(param_slots + 1, &return_types))
+                } else {
+                    None
+                };
+
                 let func_index = self.pop_value_released();
 
                 let params: SmallVec<[_; 8]> = self
                     .value_stack
-                    .drain(self.value_stack.len() - param_types.len()..)
+                    .drain(self.value_stack.len() - param_slots..)
                     .collect();
+                self.truncate_v128_stack(self.value_stack.len());
                 self.machine.release_locations_only_regs(&params);
 
                 // Pop arguments off the FP stack and canonicalize them if needed.
@@ -5393,9 +6516,18 @@ impl<'a> FuncGen<'a> {
 
                 let vmcaller_checked_anyfunc_func_ptr =
                     self.vmoffsets.vmcaller_checked_anyfunc_func_ptr() as usize;
+                let vmcaller_checked_anyfunc_vmctx =
+                    self.vmoffsets.vmcaller_checked_anyfunc_vmctx() as usize;
 
                 self.emit_call_sysv(
                     |this| {
+                        // The callee may belong to another instance, or be a host function.
+                        this.assembler.emit_mov(
+                            Size::S64,
+                            Location::Memory(GPR::RAX, vmcaller_checked_anyfunc_vmctx as i32),
+                            Machine::get_param_location(0),
+                        );
+
                         if this.assembler.arch_requires_indirect_call_trampoline() {
                             this.assembler.arch_emit_indirect_call_with_trampoline(
                                 Location::Memory(
@@ -5415,13 +6547,21 @@ impl<'a> FuncGen<'a> {
                             this.mark_instruction_address_end(offset);
                         }
                     },
-                    params.iter().copied(),
+                    params
+                        .iter()
+                        .copied()
+                        .chain(return_area.as_ref().map(|(_, ptr)| *ptr)),
                 )?;
 
                 self.machine
                     .release_locations_only_stack(&mut self.assembler, &params);
 
-                if !return_types.is_empty() {
+                if let Some((slots, ptr)) = return_area {
+                    self.machine.release_locations(&mut self.assembler, &[ptr]);
+                    self.push_slots(&return_types, &slots);
+                } else if return_types.first() == Some(&WpType::V128) {
+                    self.push_v128_from_result_regs();
+                } else if !return_types.is_empty() {
                     let ret = self.machine.acquire_locations(
                         &mut self.assembler,
                         &[(
@@ -5446,76 +6586,61 @@ impl<'a> FuncGen<'a> {
                 let label_end = self.assembler.get_label();
                 let label_else = self.assembler.get_label();
 
+                let (params, returns) = self.block_signature(ty);
+                let n_param_slots: usize = params.iter().map(|&x| wp_type_slots(x)).sum();
+                let (return_slots, param_slots) = match ty {
+                    WpTypeOrFuncType::FuncType(_) => {
+                        let n_return_slots: usize = returns.iter().map(|&x| wp_type_slots(x)).sum();
+                        let mut slots = self
+                            .reserve_slots_below(n_param_slots + 1, n_return_slots + n_param_slots);
+                        let param_slots: SmallVec<[Location; 1]> =
+                            slots.drain(n_return_slots..).collect();
+                        (slots, param_slots)
+                    }
+                    WpTypeOrFuncType::Type(_) => (smallvec![], smallvec![]),
+                };
+
                 let cond = self.pop_value_released();
 
+                // Both branches take the parameters from the frame's parameter slots.
+                self.emit_move_top_values(&param_slots);
+                self.release_top_values(n_param_slots);
+
                 let frame = ControlFrame {
                     label: label_end,
                     loop_like: false,
-                    if_else: IfElseState::If(label_else),
-                    returns: match ty {
-                        WpTypeOrFuncType::Type(WpType::EmptyBlockType) => smallvec![],
-                        WpTypeOrFuncType::Type(inner_ty) => smallvec![inner_ty],
-                        _ => {
-                            return Err(CodegenError {
-                                message: "If: multi-value returns not yet implemented".to_string(),
-                            })
-                        }
-                    },
-                    value_stack_depth: self.value_stack.len(),
-                    fp_stack_depth: self.fp_stack.len(),
-                    state: self.machine.state.clone(),
-                    state_diff_id: self.get_state_diff(),
-                };
-                self.control_stack.push(frame);
-                self.emit_relaxed_binop(Assembler::emit_cmp, Size::S32, Location::Imm32(0), cond);
-                self.assembler.emit_jmp(Condition::Equal, label_else);
-            }
-            Operator::Else => {
-                let frame = self.control_stack.last_mut().unwrap();
-
-                if !was_unreachable && !frame.returns.is_empty() {
-                    let first_return = frame.returns[0];
-                    let loc = *self.value_stack.last().unwrap();
-                    if first_return.is_float() {
-                        let fp = self.fp_stack.peek1()?;
-                        if self.assembler.arch_supports_canonicalize_nan()
-                            && self.config.enable_nan_canonicalization
-                            && fp.canonicalization.is_some()
-                        {
-                            self.canonicalize_nan(
-                                match first_return {
-                                    WpType::F32 => Size::S32,
-                                    WpType::F64 => Size::S64,
-                                    _ => unreachable!(),
-                                },
-                                loc,
-                                Location::GPR(GPR::RAX),
-                            );
-                        } else {
-                            self.emit_relaxed_binop(
-                                Assembler::emit_mov,
-                                Size::S64,
-                                loc,
-                                Location::GPR(GPR::RAX),
-                            );
-                        }
-                    } else {
-                        self.emit_relaxed_binop(
-                            Assembler::emit_mov,
-                            Size::S64,
-                            loc,
-                            Location::GPR(GPR::RAX),
-                        );
-                    }
+                    if_else: IfElseState::If(label_else),
+                    params: params.clone(),
+                    returns,
+                    return_slots,
+                    param_slots: param_slots.clone(),
+                    value_stack_depth: self.value_stack.len(),
+                    fp_stack_depth: self.fp_stack.len(),
+                    state: self.machine.state.clone(),
+                    state_diff_id: self.get_state_diff(),
+                };
+                self.control_stack.push(frame);
+                self.emit_relaxed_binop(Assembler::emit_cmp, Size::S32, Location::Imm32(0), cond);
+                self.assembler.emit_jmp(Condition::Equal, label_else);
+                self.push_slot_copies(&params, &param_slots);
+            }
+            Operator::Else => {
+                if !was_unreachable {
+                    self.emit_frame_results(self.control_stack.len() - 1)?;
                 }
 
-                let mut frame = self.control_stack.last_mut().unwrap();
+                let frame = self.control_stack.last().unwrap();
+                let (value_stack_depth, fp_stack_depth) =
+                    (frame.value_stack_depth, frame.fp_stack_depth);
 
-                let released: &[Location] = &self.value_stack[frame.value_stack_depth..];
+                let released: &[Location] = &self.value_stack[value_stack_depth..];
                 self.machine
                     .release_locations(&mut self.assembler, released);
-                self.value_stack.truncate(frame.value_stack_depth);
-                self.fp_stack.truncate(frame.fp_stack_depth);
+                self.value_stack.truncate(value_stack_depth);
+                self.fp_stack.truncate(fp_stack_depth);
+                self.truncate_v128_stack(value_stack_depth);
+
+                let mut frame = self.control_stack.last_mut().unwrap();
 
                 match frame.if_else {
                     IfElseState::If(label) => {
@@ -5529,9 +6654,36 @@ impl<'a> FuncGen<'a> {
                         })
                     }
                 }
+
+                let (params, param_slots) = (frame.params.clone(), frame.param_slots.clone());
+                self.push_slot_copies(&params, &param_slots);
             }
             Operator::Select => {
                 let cond = self.pop_value_released();
+                if self.top_is_v128() {
+                    let b = self.pop_v128_released()?;
+                    let a = self.pop_v128_released()?;
+                    self.emit_v128_load(a, XMM::XMM8);
+                    self.emit_v128_load(b, XMM::XMM9);
+                    let ret = self.acquire_v128();
+
+                    let end_label = self.assembler.get_label();
+                    let zero_label = self.assembler.get_label();
+
+                    self.emit_relaxed_binop(
+                        Assembler::emit_cmp,
+                        Size::S32,
+                        Location::Imm32(0),
+                        cond,
+                    );
+                    self.assembler.emit_jmp(Condition::Equal, zero_label);
+                    self.emit_v128_store(XMM::XMM8, ret);
+                    self.assembler.emit_jmp(Condition::None, end_label);
+                    self.assembler.emit_label(zero_label);
+                    self.emit_v128_store(XMM::XMM9, ret);
+                    self.assembler.emit_label(end_label);
+                    return Ok(());
+                }
                 let v_b = self.pop_value_released();
                 let v_a = self.pop_value_released();
                 let cncl: Option<(Option<CanonicalizeType>, Option<CanonicalizeType>)> =
@@ -5589,28 +6741,54 @@ impl<'a> FuncGen<'a> {
                 self.assembler.emit_label(end_label);
             }
             Operator::Block { ty } => {
+                let (params, returns) = self.block_signature(ty);
+                let n_param_slots: usize = params.iter().map(|&x| wp_type_slots(x)).sum();
+                let return_slots = match ty {
+                    WpTypeOrFuncType::FuncType(_) => {
+                        let n_return_slots: usize = returns.iter().map(|&x| wp_type_slots(x)).sum();
+                        self.reserve_slots_below(n_param_slots, n_return_slots)
+                    }
+                    WpTypeOrFuncType::Type(_) => smallvec![],
+                };
+                let value_stack_depth = self.value_stack.len() - n_param_slots;
                 let frame = ControlFrame {
                     label: self.assembler.get_label(),
                     loop_like: false,
                     if_else: IfElseState::None,
-                    returns: match ty {
-                        WpTypeOrFuncType::Type(WpType::EmptyBlockType) => smallvec![],
-                        WpTypeOrFuncType::Type(inner_ty) => smallvec![inner_ty],
-                        _ => {
-                            return Err(CodegenError {
-                                message: "Block: multi-value returns not yet implemented"
-                                    .to_string(),
-                            })
-                        }
-                    },
-                    value_stack_depth: self.value_stack.len(),
-                    fp_stack_depth: self.fp_stack.len(),
+                    params,
+                    returns,
+                    return_slots,
+                    param_slots: smallvec![],
+                    value_stack_depth,
+                    fp_stack_depth: self
+                        .fp_stack
+                        .iter()
+                        .take_while(|fp| fp.depth < value_stack_depth)
+                        .count(),
                     state: self.machine.state.clone(),
                     state_diff_id: self.get_state_diff(),
                 };
                 self.control_stack.push(frame);
             }
             Operator::Loop { ty } => {
+                let (params, returns) = self.block_signature(ty);
+                let n_param_slots: usize = params.iter().map(|&x| wp_type_slots(x)).sum();
+                let (return_slots, param_slots) = match ty {
+                    WpTypeOrFuncType::FuncType(_) => {
+                        let n_return_slots: usize = returns.iter().map(|&x| wp_type_slots(x)).sum();
+                        let mut slots =
+                            self.reserve_slots_below(n_param_slots, n_return_slots + n_param_slots);
+                        let param_slots: SmallVec<[Location; 1]> =
+                            slots.drain(n_return_slots..).collect();
+                        (slots, param_slots)
+                    }
+                    WpTypeOrFuncType::Type(_) => (smallvec![], smallvec![]),
+                };
+
+                // Branches back to the loop pass the parameters in the frame's parameter slots.
+                self.emit_move_top_values(&param_slots);
+                self.release_top_values(n_param_slots);
+
                 // Pad with NOPs to the next 16-byte boundary.
                 // Here we don't use the dynasm `.align 16` attribute because it pads the alignment with single-byte nops
                 // which may lead to efficiency problems.
@@ -5630,24 +6808,19 @@ impl<'a> FuncGen<'a> {
                     label,
                     loop_like: true,
                     if_else: IfElseState::None,
-                    returns: match ty {
-                        WpTypeOrFuncType::Type(WpType::EmptyBlockType) => smallvec![],
-                        WpTypeOrFuncType::Type(inner_ty) => smallvec![inner_ty],
-                        _ => {
-                            return Err(CodegenError {
-                                message: "Loop: multi-value returns not yet implemented"
-                                    .to_string(),
-                            })
-                        }
-                    },
+                    params: params.clone(),
+                    returns,
+                    return_slots,
+                    param_slots: param_slots.clone(),
                     value_stack_depth: self.value_stack.len(),
                     fp_stack_depth: self.fp_stack.len(),
                     state: self.machine.state.clone(),
                     state_diff_id,
                 });
                 self.assembler.emit_label(label);
+                self.push_slot_copies(&params, &param_slots);
 
-                // TODO: Re-enable interrupt signal check without branching
+                self.emit_interrupt_check()?;
             }
             Operator::Nop => {}
             Operator::MemorySize { mem, mem_byte: _ } => {
@@ -6170,53 +7343,13 @@ impl<'a> FuncGen<'a> {
                 let offset = self.assembler.get_offset().0;
                 self.trap_table
                     .offset_to_code
-                    .insert(offset, TrapCode::UnreachableCodeReached);
+                    .insert(offset, self.unreachable_trap_code);
                 self.assembler.emit_ud2();
                 self.mark_instruction_address_end(offset);
                 self.unreachable_depth = 1;
             }
             Operator::Return => {
-                let frame = &self.control_stack[0];
-                if !frame.returns.is_empty() {
-                    if frame.returns.len() != 1 {
-                        return Err(CodegenError {
-                            message: "Return: incorrect frame.returns".to_string(),
-                        });
-                    }
-                    let first_return = frame.returns[0];
-                    let loc = *self.value_stack.last().unwrap();
-                    if first_return.is_float() {
-                        let fp = self.fp_stack.peek1()?;
-                        if self.assembler.arch_supports_canonicalize_nan()
-                            && self.config.enable_nan_canonicalization
-                            && fp.canonicalization.is_some()
-                        {
-                            self.canonicalize_nan(
-                                match first_return {
-                                    WpType::F32 => Size::S32,
-                                    WpType::F64 => Size::S64,
-                                    _ => unreachable!(),
-                                },
-                                loc,
-                                Location::GPR(GPR::RAX),
-                            );
-                        } else {
-                            self.emit_relaxed_binop(
-                                Assembler::emit_mov,
-                                Size::S64,
-                                loc,
-                                Location::GPR(GPR::RAX),
-                            );
-                        }
-                    } else {
-                        self.emit_relaxed_binop(
-                            Assembler::emit_mov,
-                            Size::S64,
-                            loc,
-                            Location::GPR(GPR::RAX),
-                        );
-                    }
-                }
+                self.emit_frame_results(0)?;
                 let frame = &self.control_stack[0];
                 let released = &self.value_stack[frame.value_stack_depth..];
                 self.machine
@@ -6225,48 +7358,9 @@ impl<'a> FuncGen<'a> {
                 self.unreachable_depth = 1;
             }
             Operator::Br { relative_depth } => {
-                let frame =
-                    &self.control_stack[self.control_stack.len() - 1 - (relative_depth as usize)];
-                if !frame.loop_like && !frame.returns.is_empty() {
-                    if frame.returns.len() != 1 {
-                        return Err(CodegenError {
-                            message: "Br: incorrect frame.returns".to_string(),
-                        });
-                    }
-                    let first_return = frame.returns[0];
-                    let loc = *self.value_stack.last().unwrap();
-
-                    if first_return.is_float() {
-                        let fp = self.fp_stack.peek1()?;
-                        if self.assembler.arch_supports_canonicalize_nan()
-                            && self.config.enable_nan_canonicalization
-                            && fp.canonicalization.is_some()
-                        {
-                            self.canonicalize_nan(
-                                match first_return {
-                                    WpType::F32 => Size::S32,
-                                    WpType::F64 => Size::S64,
-                                    _ => unreachable!(),
-                                },
-                                loc,
-                                Location::GPR(GPR::RAX),
-                            );
-                        } else {
-                            self.emit_relaxed_binop(
-                                Assembler::emit_mov,
-                                Size::S64,
-                                loc,
-                                Location::GPR(GPR::RAX),
-                            );
-                        }
-                    } else {
-                        self.assembler
-                            .emit_mov(Size::S64, loc, Location::GPR(GPR::RAX));
-                    }
-                }
-                let frame =
-                    &self.control_stack[self.control_stack.len() - 1 - (relative_depth as usize)];
-
+                let frame_index = self.control_stack.len() - 1 - (relative_depth as usize);
+                self.emit_branch_values(frame_index)?;
+                let frame = &self.control_stack[frame_index];
                 let released = &self.value_stack[frame.value_stack_depth..];
                 self.machine
                     .release_locations_keep_state(&mut self.assembler, released);
@@ -6279,47 +7373,9 @@ impl<'a> FuncGen<'a> {
                 self.emit_relaxed_binop(Assembler::emit_cmp, Size::S32, Location::Imm32(0), cond);
                 self.assembler.emit_jmp(Condition::Equal, after);
 
-                let frame =
-                    &self.control_stack[self.control_stack.len() - 1 - (relative_depth as usize)];
-                if !frame.loop_like && !frame.returns.is_empty() {
-                    if frame.returns.len() != 1 {
-                        return Err(CodegenError {
-                            message: "BrIf: incorrect frame.returns".to_string(),
-                        });
-                    }
-
-                    let first_return = frame.returns[0];
-                    let loc = *self.value_stack.last().unwrap();
-                    if first_return.is_float() {
-                        let fp = self.fp_stack.peek1()?;
-                        if self.assembler.arch_supports_canonicalize_nan()
-                            && self.config.enable_nan_canonicalization
-                            && fp.canonicalization.is_some()
-                        {
-                            self.canonicalize_nan(
-                                match first_return {
-                                    WpType::F32 => Size::S32,
-                                    WpType::F64 => Size::S64,
-                                    _ => unreachable!(),
-                                },
-                                loc,
-                                Location::GPR(GPR::RAX),
-                            );
-                        } else {
-                            self.emit_relaxed_binop(
-                                Assembler::emit_mov,
-                                Size::S64,
-                                loc,
-                                Location::GPR(GPR::RAX),
-                            );
-                        }
-                    } else {
-                        self.assembler
-                            .emit_mov(Size::S64, loc, Location::GPR(GPR::RAX));
-                    }
-                }
-                let frame =
-                    &self.control_stack[self.control_stack.len() - 1 - (relative_depth as usize)];
+                let frame_index = self.control_stack.len() - 1 - (relative_depth as usize);
+                self.emit_branch_values(frame_index)?;
+                let frame = &self.control_stack[frame_index];
                 let released = &self.value_stack[frame.value_stack_depth..];
                 self.machine
                     .release_locations_keep_state(&mut self.assembler, released);
@@ -6366,50 +7422,9 @@ impl<'a> FuncGen<'a> {
                     let label = self.assembler.get_label();
                     self.assembler.emit_label(label);
                     table.push(label);
-                    let frame =
-                        &self.control_stack[self.control_stack.len() - 1 - (*target as usize)];
-                    if !frame.loop_like && !frame.returns.is_empty() {
-                        if frame.returns.len() != 1 {
-                            return Err(CodegenError {
-                                message: format!(
-                                    "BrTable: incorrect frame.returns for {:?}",
-                                    target
-                                ),
-                            });
-                        }
-
-                        let first_return = frame.returns[0];
-                        let loc = *self.value_stack.last().unwrap();
-                        if first_return.is_float() {
-                            let fp = self.fp_stack.peek1()?;
-                            if self.assembler.arch_supports_canonicalize_nan()
-                                && self.config.enable_nan_canonicalization
-                                && fp.canonicalization.is_some()
-                            {
-                                self.canonicalize_nan(
-                                    match first_return {
-                                        WpType::F32 => Size::S32,
-                                        WpType::F64 => Size::S64,
-                                        _ => unreachable!(),
-                                    },
-                                    loc,
-                                    Location::GPR(GPR::RAX),
-                                );
-                            } else {
-                                self.emit_relaxed_binop(
-                                    Assembler::emit_mov,
-                                    Size::S64,
-                                    loc,
-                                    Location::GPR(GPR::RAX),
-                                );
-                            }
-                        } else {
-                            self.assembler
-                                .emit_mov(Size::S64, loc, Location::GPR(GPR::RAX));
-                        }
-                    }
-                    let frame =
-                        &self.control_stack[self.control_stack.len() - 1 - (*target as usize)];
+                    let frame_index = self.control_stack.len() - 1 - (*target as usize);
+                    self.emit_branch_values(frame_index)?;
+                    let frame = &self.control_stack[frame_index];
                     let released = &self.value_stack[frame.value_stack_depth..];
                     self.machine
                         .release_locations_keep_state(&mut self.assembler, released);
@@ -6418,47 +7433,9 @@ impl<'a> FuncGen<'a> {
                 self.assembler.emit_label(default_br);
 
                 {
-                    let frame = &self.control_stack
-                        [self.control_stack.len() - 1 - (default_target as usize)];
-                    if !frame.loop_like && !frame.returns.is_empty() {
-                        if frame.returns.len() != 1 {
-                            return Err(CodegenError {
-                                message: "BrTable: incorrect frame.returns".to_string(),
-                            });
-                        }
-
-                        let first_return = frame.returns[0];
-                        let loc = *self.value_stack.last().unwrap();
-                        if first_return.is_float() {
-                            let fp = self.fp_stack.peek1()?;
-                            if self.assembler.arch_supports_canonicalize_nan()
-                                && self.config.enable_nan_canonicalization
-                                && fp.canonicalization.is_some()
-                            {
-                                self.canonicalize_nan(
-                                    match first_return {
-                                        WpType::F32 => Size::S32,
-                                        WpType::F64 => Size::S64,
-                                        _ => unreachable!(),
-                                    },
-                                    loc,
-                                    Location::GPR(GPR::RAX),
-                                );
-                            } else {
-                                self.emit_relaxed_binop(
-                                    Assembler::emit_mov,
-                                    Size::S64,
-                                    loc,
-                                    Location::GPR(GPR::RAX),
-                                );
-                            }
-                        } else {
-                            self.assembler
-                                .emit_mov(Size::S64, loc, Location::GPR(GPR::RAX));
-                        }
-                    }
-                    let frame = &self.control_stack
-                        [self.control_stack.len() - 1 - (default_target as usize)];
+                    let frame_index = self.control_stack.len() - 1 - (default_target as usize);
+                    self.emit_branch_values(frame_index)?;
+                    let frame = &self.control_stack[frame_index];
                     let released = &self.value_stack[frame.value_stack_depth..];
                     self.machine
                         .release_locations_keep_state(&mut self.assembler, released);
@@ -6472,6 +7449,10 @@ impl<'a> FuncGen<'a> {
                 self.unreachable_depth = 1;
             }
             Operator::Drop => {
+                if self.top_is_v128() {
+                    self.pop_v128_released()?;
+                    return Ok(());
+                }
                 self.pop_value_released();
                 if let Some(x) = self.fp_stack.last() {
                     if x.depth == self.value_stack.len() {
@@ -6480,42 +7461,10 @@ impl<'a> FuncGen<'a> {
                 }
             }
             Operator::End => {
-                let frame = self.control_stack.pop().unwrap();
-
-                if !was_unreachable && !frame.returns.is_empty() {
-                    let loc = *self.value_stack.last().unwrap();
-                    if frame.returns[0].is_float() {
-                        let fp = self.fp_stack.peek1()?;
-                        if self.assembler.arch_supports_canonicalize_nan()
-                            && self.config.enable_nan_canonicalization
-                            && fp.canonicalization.is_some()
-                        {
-                            self.canonicalize_nan(
-                                match frame.returns[0] {
-                                    WpType::F32 => Size::S32,
-                                    WpType::F64 => Size::S64,
-                                    _ => unreachable!(),
-                                },
-                                loc,
-                                Location::GPR(GPR::RAX),
-                            );
-                        } else {
-                            self.emit_relaxed_binop(
-                                Assembler::emit_mov,
-                                Size::S64,
-                                loc,
-                                Location::GPR(GPR::RAX),
-                            );
-                        }
-                    } else {
-                        self.emit_relaxed_binop(
-                            Assembler::emit_mov,
-                            Size::S64,
-                            loc,
-                            Location::GPR(GPR::RAX),
-                        );
-                    }
+                if !was_unreachable {
+                    self.emit_frame_results(self.control_stack.len() - 1)?;
                 }
+                let frame = self.control_stack.pop().unwrap();
 
                 if self.control_stack.is_empty() {
                     self.assembler.emit_label(frame.label);
@@ -6546,21 +7495,42 @@ impl<'a> FuncGen<'a> {
                         .release_locations(&mut self.assembler, released);
                     self.value_stack.truncate(frame.value_stack_depth);
                     self.fp_stack.truncate(frame.fp_stack_depth);
-
-                    if !frame.loop_like {
-                        self.assembler.emit_label(frame.label);
-                    }
-
-                    if let IfElseState::If(label) = frame.if_else {
-                        self.assembler.emit_label(label);
+                    self.truncate_v128_stack(frame.value_stack_depth);
+
+                    match frame.if_else {
+                        IfElseState::If(label) if !frame.return_slots.is_empty() => {
+                            // Without an `else` branch, the parameters are passed on as results.
+                            self.assembler.emit_jmp(Condition::None, frame.label);
+                            self.assembler.emit_label(label);
+                            for (&src, &dst) in frame.param_slots.iter().zip(&frame.return_slots) {
+                                self.emit_relaxed_binop(Assembler::emit_mov, Size::S64, src, dst);
+                            }
+                            self.assembler.emit_label(frame.label);
+                        }
+                        IfElseState::If(label) => {
+                            self.assembler.emit_label(frame.label);
+                            self.assembler.emit_label(label);
+                        }
+                        _ if !frame.loop_like => {
+                            self.assembler.emit_label(frame.label);
+                        }
+                        _ => {}
                     }
 
-                    if !frame.returns.is_empty() {
+                    self.machine
+                        .release_locations(&mut self.assembler, &frame.param_slots);
+                    if !frame.return_slots.is_empty() {
+                        self.push_slots(&frame.returns, &frame.return_slots);
+                    } else if !frame.returns.is_empty() {
                         if frame.returns.len() != 1 {
                             return Err(CodegenError {
                                 message: "End: incorrect frame.returns".to_string(),
                             });
                         }
+                        if frame.returns[0] == WpType::V128 {
+                            self.push_v128_from_result_regs();
+                            return Ok(());
+                        }
                         let loc = self.machine.acquire_locations(
                             &mut self.assembler,
                             &[(
@@ -8108,53 +9078,865 @@ impl<'a> FuncGen<'a> {
                         Location::GPR(value),
                         Location::Memory(addr, 0),
                     );
-                    this.assembler
-                        .emit_movzx(Size::S16, Location::GPR(compare), Size::S64, ret);
-                    Ok(())
-                })?;
-                self.assembler.emit_pop(Size::S64, Location::GPR(value));
-                self.machine.release_temp_gpr(compare);
+                    this.assembler
+                        .emit_movzx(Size::S16, Location::GPR(compare), Size::S64, ret);
+                    Ok(())
+                })?;
+                self.assembler.emit_pop(Size::S64, Location::GPR(value));
+                self.machine.release_temp_gpr(compare);
+            }
+            Operator::I64AtomicRmw32CmpxchgU { ref memarg } => {
+                let new = self.pop_value_released();
+                let cmp = self.pop_value_released();
+                let target = self.pop_value_released();
+                let ret = self.machine.acquire_locations(
+                    &mut self.assembler,
+                    &[(WpType::I64, MachineValue::WasmStack(self.value_stack.len()))],
+                    false,
+                )[0];
+                self.value_stack.push(ret);
+
+                let compare = self.machine.reserve_unused_temp_gpr(GPR::RAX);
+                let value = if cmp == Location::GPR(GPR::R14) {
+                    if new == Location::GPR(GPR::R13) {
+                        GPR::R12
+                    } else {
+                        GPR::R13
+                    }
+                } else {
+                    GPR::R14
+                };
+                self.assembler.emit_push(Size::S64, Location::GPR(value));
+                self.assembler
+                    .emit_mov(Size::S64, cmp, Location::GPR(compare));
+                self.assembler
+                    .emit_mov(Size::S64, new, Location::GPR(value));
+
+                self.emit_memory_op(target, memarg, true, 1, |this, addr| {
+                    this.assembler.emit_lock_cmpxchg(
+                        Size::S32,
+                        Location::GPR(value),
+                        Location::Memory(addr, 0),
+                    );
+                    this.assembler
+                        .emit_mov(Size::S32, Location::GPR(compare), ret);
+                    Ok(())
+                })?;
+                self.assembler.emit_pop(Size::S64, Location::GPR(value));
+                self.machine.release_temp_gpr(compare);
+            }
+            Operator::V128Const { value } => {
+                let value = u128::from_le_bytes(*value.bytes());
+                let (lo, hi) = self.acquire_v128();
+                self.emit_relaxed_binop(
+                    Assembler::emit_mov,
+                    Size::S64,
+                    Location::Imm64(value as u64),
+                    lo,
+                );
+                self.emit_relaxed_binop(
+                    Assembler::emit_mov,
+                    Size::S64,
+                    Location::Imm64((value >> 64) as u64),
+                    hi,
+                );
+            }
+            Operator::V128Load { ref memarg } => {
+                self.emit_v128_load_op(memarg, 16, |this, addr, dst| {
+                    this.assembler.emit_mov(
+                        Size::S64,
+                        Location::Memory(addr, 0),
+                        Location::XMM(dst),
+                    );
+                    this.assembler
+                        .emit_vpinsrq(dst, GPROrMemory::Memory(addr, 8), 1, dst);
+                })?;
+            }
+            Operator::V128Store { ref memarg } => {
+                let a = self.pop_v128_released()?;
+                let target = self.pop_value_released();
+                self.emit_v128_load(a, XMM::XMM8);
+                self.emit_memory_op(target, memarg, false, 16, |this, addr| {
+                    this.assembler.emit_mov(
+                        Size::S64,
+                        Location::XMM(XMM::XMM8),
+                        Location::Memory(addr, 0),
+                    );
+                    this.assembler
+                        .emit_vpextrq(XMM::XMM8, 1, GPROrMemory::Memory(addr, 8));
+                    Ok(())
+                })?;
+            }
+            Operator::V128Load8Splat { ref memarg } => self.emit_v128_load_splat(memarg, 1)?,
+            Operator::V128Load16Splat { ref memarg } => self.emit_v128_load_splat(memarg, 2)?,
+            Operator::V128Load32Splat { ref memarg } => self.emit_v128_load_splat(memarg, 4)?,
+            Operator::V128Load64Splat { ref memarg } => self.emit_v128_load_splat(memarg, 8)?,
+            Operator::V128Load32Zero { ref memarg } => {
+                self.emit_v128_load_op(memarg, 4, |this, addr, dst| {
+                    this.assembler.emit_mov(
+                        Size::S32,
+                        Location::Memory(addr, 0),
+                        Location::XMM(dst),
+                    );
+                })?;
+            }
+            Operator::V128Load64Zero { ref memarg } => {
+                self.emit_v128_load_op(memarg, 8, |this, addr, dst| {
+                    this.assembler.emit_mov(
+                        Size::S64,
+                        Location::Memory(addr, 0),
+                        Location::XMM(dst),
+                    );
+                })?;
+            }
+            Operator::V128Load8x8S { ref memarg } => {
+                self.emit_v128_load_extend(memarg, Assembler::emit_vpmovsxbw)?
+            }
+            Operator::V128Load8x8U { ref memarg } => {
+                self.emit_v128_load_extend(memarg, Assembler::emit_vpmovzxbw)?
+            }
+            Operator::V128Load16x4S { ref memarg } => {
+                self.emit_v128_load_extend(memarg, Assembler::emit_vpmovsxwd)?
+            }
+            Operator::V128Load16x4U { ref memarg } => {
+                self.emit_v128_load_extend(memarg, Assembler::emit_vpmovzxwd)?
+            }
+            Operator::V128Load32x2S { ref memarg } => {
+                self.emit_v128_load_extend(memarg, Assembler::emit_vpmovsxdq)?
+            }
+            Operator::V128Load32x2U { ref memarg } => {
+                self.emit_v128_load_extend(memarg, Assembler::emit_vpmovzxdq)?
+            }
+            Operator::V128Load8Lane { ref memarg, lane } => {
+                self.emit_v128_load_lane(memarg, 1, Assembler::emit_vpinsrb, lane)?
+            }
+            Operator::V128Load16Lane { ref memarg, lane } => {
+                self.emit_v128_load_lane(memarg, 2, Assembler::emit_vpinsrw, lane)?
+            }
+            Operator::V128Load32Lane { ref memarg, lane } => {
+                self.emit_v128_load_lane(memarg, 4, Assembler::emit_vpinsrd, lane)?
+            }
+            Operator::V128Load64Lane { ref memarg, lane } => {
+                self.emit_v128_load_lane(memarg, 8, Assembler::emit_vpinsrq, lane)?
+            }
+            Operator::V128Store8Lane { ref memarg, lane } => {
+                self.emit_v128_store_lane(memarg, 1, Assembler::emit_vpextrb, lane)?
+            }
+            Operator::V128Store16Lane { ref memarg, lane } => {
+                self.emit_v128_store_lane(memarg, 2, Assembler::emit_vpextrw, lane)?
+            }
+            Operator::V128Store32Lane { ref memarg, lane } => {
+                self.emit_v128_store_lane(memarg, 4, Assembler::emit_vpextrd, lane)?
+            }
+            Operator::V128Store64Lane { ref memarg, lane } => {
+                self.emit_v128_store_lane(memarg, 8, Assembler::emit_vpextrq, lane)?
+            }
+            Operator::I8x16Splat => self.emit_v128_splat(WpType::I32, |this, a, dst| {
+                this.assembler
+                    .emit_vpxor(XMM::XMM9, XMMOrMemory::XMM(XMM::XMM9), XMM::XMM9);
+                this.assembler
+                    .emit_vpshufb(a, XMMOrMemory::XMM(XMM::XMM9), dst);
+            })?,
+            Operator::I16x8Splat => self.emit_v128_splat(WpType::I32, |this, a, dst| {
+                this.assembler.emit_vpshuflw(XMMOrMemory::XMM(a), 0, dst);
+                this.assembler.emit_vpshufd(XMMOrMemory::XMM(dst), 0, dst);
+            })?,
+            Operator::I32x4Splat => self.emit_v128_splat(WpType::I32, |this, a, dst| {
+                this.assembler.emit_vpshufd(XMMOrMemory::XMM(a), 0, dst);
+            })?,
+            Operator::F32x4Splat => self.emit_v128_splat(WpType::F32, |this, a, dst| {
+                this.assembler.emit_vpshufd(XMMOrMemory::XMM(a), 0, dst);
+            })?,
+            Operator::I64x2Splat => self.emit_v128_splat(WpType::I64, |this, a, dst| {
+                this.assembler.emit_vpshufd(XMMOrMemory::XMM(a), 0x44, dst);
+            })?,
+            Operator::F64x2Splat => self.emit_v128_splat(WpType::F64, |this, a, dst| {
+                this.assembler.emit_vpshufd(XMMOrMemory::XMM(a), 0x44, dst);
+            })?,
+            Operator::I8x16ExtractLaneS { lane } => {
+                self.emit_v128_extract(WpType::I32, |this, a, ret| {
+                    this.assembler.emit_vpextrb(a, lane, GPROrMemory::GPR(ret));
+                    this.assembler.emit_movsx(
+                        Size::S8,
+                        Location::GPR(ret),
+                        Size::S32,
+                        Location::GPR(ret),
+                    );
+                })?
+            }
+            Operator::I8x16ExtractLaneU { lane } => {
+                self.emit_v128_extract(WpType::I32, |this, a, ret| {
+                    this.assembler.emit_vpextrb(a, lane, GPROrMemory::GPR(ret));
+                })?
+            }
+            Operator::I16x8ExtractLaneS { lane } => {
+                self.emit_v128_extract(WpType::I32, |this, a, ret| {
+                    this.assembler.emit_vpextrw(a, lane, GPROrMemory::GPR(ret));
+                    this.assembler.emit_movsx(
+                        Size::S16,
+                        Location::GPR(ret),
+                        Size::S32,
+                        Location::GPR(ret),
+                    );
+                })?
             }
-            Operator::I64AtomicRmw32CmpxchgU { ref memarg } => {
-                let new = self.pop_value_released();
-                let cmp = self.pop_value_released();
-                let target = self.pop_value_released();
-                let ret = self.machine.acquire_locations(
-                    &mut self.assembler,
-                    &[(WpType::I64, MachineValue::WasmStack(self.value_stack.len()))],
-                    false,
-                )[0];
-                self.value_stack.push(ret);
-
-                let compare = self.machine.reserve_unused_temp_gpr(GPR::RAX);
-                let value = if cmp == Location::GPR(GPR::R14) {
-                    if new == Location::GPR(GPR::R13) {
-                        GPR::R12
+            Operator::I16x8ExtractLaneU { lane } => {
+                self.emit_v128_extract(WpType::I32, |this, a, ret| {
+                    this.assembler.emit_vpextrw(a, lane, GPROrMemory::GPR(ret));
+                })?
+            }
+            Operator::I32x4ExtractLane { lane } => {
+                self.emit_v128_extract(WpType::I32, |this, a, ret| {
+                    this.assembler.emit_vpextrd(a, lane, GPROrMemory::GPR(ret));
+                })?
+            }
+            Operator::F32x4ExtractLane { lane } => {
+                self.emit_v128_extract(WpType::F32, |this, a, ret| {
+                    this.assembler.emit_vpextrd(a, lane, GPROrMemory::GPR(ret));
+                })?
+            }
+            Operator::I64x2ExtractLane { lane } => {
+                self.emit_v128_extract(WpType::I64, |this, a, ret| {
+                    this.assembler.emit_vpextrq(a, lane, GPROrMemory::GPR(ret));
+                })?
+            }
+            Operator::F64x2ExtractLane { lane } => {
+                self.emit_v128_extract(WpType::F64, |this, a, ret| {
+                    this.assembler.emit_vpextrq(a, lane, GPROrMemory::GPR(ret));
+                })?
+            }
+            Operator::I8x16ReplaceLane { lane } => {
+                self.emit_v128_replace_lane(WpType::I32, Assembler::emit_vpinsrb, lane)?
+            }
+            Operator::I16x8ReplaceLane { lane } => {
+                self.emit_v128_replace_lane(WpType::I32, Assembler::emit_vpinsrw, lane)?
+            }
+            Operator::I32x4ReplaceLane { lane } => {
+                self.emit_v128_replace_lane(WpType::I32, Assembler::emit_vpinsrd, lane)?
+            }
+            Operator::F32x4ReplaceLane { lane } => {
+                self.emit_v128_replace_lane(WpType::F32, Assembler::emit_vpinsrd, lane)?
+            }
+            Operator::I64x2ReplaceLane { lane } => {
+                self.emit_v128_replace_lane(WpType::I64, Assembler::emit_vpinsrq, lane)?
+            }
+            Operator::F64x2ReplaceLane { lane } => {
+                self.emit_v128_replace_lane(WpType::F64, Assembler::emit_vpinsrq, lane)?
+            }
+            Operator::I8x16Swizzle => self.emit_v128_binop(|this, a, s, dst| {
+                // Indices of 16 and above must select zero, which `vpshufb` does when the top bit
+                // of the index is set.
+                this.emit_v128_const(0x7070_7070_7070_7070_7070_7070_7070_7070, XMM::XMM10);
+                this.assembler
+                    .emit_vpaddusb(s, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                this.assembler
+                    .emit_vpshufb(a, XMMOrMemory::XMM(XMM::XMM10), dst);
+            })?,
+            Operator::I8x16Shuffle { lanes } => {
+                let mut mask_a = [0x80u8; 16];
+                let mut mask_b = [0x80u8; 16];
+                for (i, &lane) in lanes.iter().enumerate() {
+                    if lane < 16 {
+                        mask_a[i] = lane;
                     } else {
-                        GPR::R13
+                        mask_b[i] = lane - 16;
                     }
-                } else {
-                    GPR::R14
-                };
-                self.assembler.emit_push(Size::S64, Location::GPR(value));
+                }
+                self.emit_v128_binop(|this, a, b, dst| {
+                    this.emit_v128_const(u128::from_le_bytes(mask_a), XMM::XMM10);
+                    this.emit_v128_const(u128::from_le_bytes(mask_b), XMM::XMM11);
+                    this.assembler
+                        .emit_vpshufb(a, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                    this.assembler
+                        .emit_vpshufb(b, XMMOrMemory::XMM(XMM::XMM11), XMM::XMM11);
+                    this.assembler
+                        .emit_vpor(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM11), dst);
+                })?
+            }
+            Operator::I8x16Eq => self.emit_v128_binop_avx(Assembler::emit_vpcmpeqb)?,
+            Operator::I8x16Ne => self.emit_v128_cmpop_not(Assembler::emit_vpcmpeqb, false)?,
+            Operator::I8x16GtS => self.emit_v128_binop_avx(Assembler::emit_vpcmpgtb)?,
+            Operator::I8x16LtS => self.emit_v128_binop(|this, a, b, dst| {
+                this.assembler.emit_vpcmpgtb(b, XMMOrMemory::XMM(a), dst);
+            })?,
+            Operator::I8x16LeS => self.emit_v128_cmpop_not(Assembler::emit_vpcmpgtb, false)?,
+            Operator::I8x16GeS => self.emit_v128_cmpop_not(Assembler::emit_vpcmpgtb, true)?,
+            Operator::I8x16LeU => self.emit_v128_cmpop_unsigned(
+                Assembler::emit_vpminub,
+                Assembler::emit_vpcmpeqb,
+                false,
+            )?,
+            Operator::I8x16GeU => self.emit_v128_cmpop_unsigned(
+                Assembler::emit_vpmaxub,
+                Assembler::emit_vpcmpeqb,
+                false,
+            )?,
+            Operator::I8x16GtU => self.emit_v128_cmpop_unsigned(
+                Assembler::emit_vpminub,
+                Assembler::emit_vpcmpeqb,
+                true,
+            )?,
+            Operator::I8x16LtU => self.emit_v128_cmpop_unsigned(
+                Assembler::emit_vpmaxub,
+                Assembler::emit_vpcmpeqb,
+                true,
+            )?,
+            Operator::I16x8Eq => self.emit_v128_binop_avx(Assembler::emit_vpcmpeqw)?,
+            Operator::I16x8Ne => self.emit_v128_cmpop_not(Assembler::emit_vpcmpeqw, false)?,
+            Operator::I16x8GtS => self.emit_v128_binop_avx(Assembler::emit_vpcmpgtw)?,
+            Operator::I16x8LtS => self.emit_v128_binop(|this, a, b, dst| {
+                this.assembler.emit_vpcmpgtw(b, XMMOrMemory::XMM(a), dst);
+            })?,
+            Operator::I16x8LeS => self.emit_v128_cmpop_not(Assembler::emit_vpcmpgtw, false)?,
+            Operator::I16x8GeS => self.emit_v128_cmpop_not(Assembler::emit_vpcmpgtw, true)?,
+            Operator::I16x8LeU => self.emit_v128_cmpop_unsigned(
+                Assembler::emit_vpminuw,
+                Assembler::emit_vpcmpeqw,
+                false,
+            )?,
+            Operator::I16x8GeU => self.emit_v128_cmpop_unsigned(
+                Assembler::emit_vpmaxuw,
+                Assembler::emit_vpcmpeqw,
+                false,
+            )?,
+            Operator::I16x8GtU => self.emit_v128_cmpop_unsigned(
+                Assembler::emit_vpminuw,
+                Assembler::emit_vpcmpeqw,
+                true,
+            )?,
+            Operator::I16x8LtU => self.emit_v128_cmpop_unsigned(
+                Assembler::emit_vpmaxuw,
+                Assembler::emit_vpcmpeqw,
+                true,
+            )?,
+            Operator::I32x4Eq => self.emit_v128_binop_avx(Assembler::emit_vpcmpeqd)?,
+            Operator::I32x4Ne => self.emit_v128_cmpop_not(Assembler::emit_vpcmpeqd, false)?,
+            Operator::I32x4GtS => self.emit_v128_binop_avx(Assembler::emit_vpcmpgtd)?,
+            Operator::I32x4LtS => self.emit_v128_binop(|this, a, b, dst| {
+                this.assembler.emit_vpcmpgtd(b, XMMOrMemory::XMM(a), dst);
+            })?,
+            Operator::I32x4LeS => self.emit_v128_cmpop_not(Assembler::emit_vpcmpgtd, false)?,
+            Operator::I32x4GeS => self.emit_v128_cmpop_not(Assembler::emit_vpcmpgtd, true)?,
+            Operator::I32x4LeU => self.emit_v128_cmpop_unsigned(
+                Assembler::emit_vpminud,
+                Assembler::emit_vpcmpeqd,
+                false,
+            )?,
+            Operator::I32x4GeU => self.emit_v128_cmpop_unsigned(
+                Assembler::emit_vpmaxud,
+                Assembler::emit_vpcmpeqd,
+                false,
+            )?,
+            Operator::I32x4GtU => self.emit_v128_cmpop_unsigned(
+                Assembler::emit_vpminud,
+                Assembler::emit_vpcmpeqd,
+                true,
+            )?,
+            Operator::I32x4LtU => self.emit_v128_cmpop_unsigned(
+                Assembler::emit_vpmaxud,
+                Assembler::emit_vpcmpeqd,
+                true,
+            )?,
+            Operator::I64x2Eq => self.emit_v128_binop_avx(Assembler::emit_vpcmpeqq)?,
+            Operator::I64x2Ne => self.emit_v128_cmpop_not(Assembler::emit_vpcmpeqq, false)?,
+            Operator::F32x4Eq => self.emit_v128_binop_avx(Assembler::emit_vcmpeqps)?,
+            Operator::F32x4Ne => self.emit_v128_binop_avx(Assembler::emit_vcmpneqps)?,
+            Operator::F32x4Lt => self.emit_v128_binop_avx(Assembler::emit_vcmpltps)?,
+            Operator::F32x4Le => self.emit_v128_binop_avx(Assembler::emit_vcmpleps)?,
+            Operator::F32x4Gt => self.emit_v128_binop(|this, a, b, dst| {
+                this.assembler.emit_vcmpltps(b, XMMOrMemory::XMM(a), dst);
+            })?,
+            Operator::F32x4Ge => self.emit_v128_binop(|this, a, b, dst| {
+                this.assembler.emit_vcmpleps(b, XMMOrMemory::XMM(a), dst);
+            })?,
+            Operator::F64x2Eq => self.emit_v128_binop_avx(Assembler::emit_vcmpeqpd)?,
+            Operator::F64x2Ne => self.emit_v128_binop_avx(Assembler::emit_vcmpneqpd)?,
+            Operator::F64x2Lt => self.emit_v128_binop_avx(Assembler::emit_vcmpltpd)?,
+            Operator::F64x2Le => self.emit_v128_binop_avx(Assembler::emit_vcmplepd)?,
+            Operator::F64x2Gt => self.emit_v128_binop(|this, a, b, dst| {
+                this.assembler.emit_vcmpltpd(b, XMMOrMemory::XMM(a), dst);
+            })?,
+            Operator::F64x2Ge => self.emit_v128_binop(|this, a, b, dst| {
+                this.assembler.emit_vcmplepd(b, XMMOrMemory::XMM(a), dst);
+            })?,
+            Operator::V128Not => self.emit_v128_unop(|this, a, dst| {
+                this.assembler
+                    .emit_vpcmpeqd(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                this.assembler
+                    .emit_vpxor(a, XMMOrMemory::XMM(XMM::XMM10), dst);
+            })?,
+            Operator::V128And => self.emit_v128_binop_avx(Assembler::emit_vpand)?,
+            Operator::V128AndNot => self.emit_v128_binop(|this, a, b, dst| {
+                this.assembler.emit_vpandn(b, XMMOrMemory::XMM(a), dst);
+            })?,
+            Operator::V128Or => self.emit_v128_binop_avx(Assembler::emit_vpor)?,
+            Operator::V128Xor => self.emit_v128_binop_avx(Assembler::emit_vpxor)?,
+            Operator::V128Bitselect => {
+                let c = self.pop_v128_released()?;
+                let b = self.pop_v128_released()?;
+                let a = self.pop_v128_released()?;
+                self.emit_v128_load(a, XMM::XMM8);
+                self.emit_v128_load(b, XMM::XMM9);
+                self.emit_v128_load(c, XMM::XMM10);
+                let ret = self.acquire_v128();
                 self.assembler
-                    .emit_mov(Size::S64, cmp, Location::GPR(compare));
+                    .emit_vpand(XMM::XMM8, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM8);
                 self.assembler
-                    .emit_mov(Size::S64, new, Location::GPR(value));
-
-                self.emit_memory_op(target, memarg, true, 1, |this, addr| {
-                    this.assembler.emit_lock_cmpxchg(
-                        Size::S32,
-                        Location::GPR(value),
-                        Location::Memory(addr, 0),
-                    );
-                    this.assembler
-                        .emit_mov(Size::S32, Location::GPR(compare), ret);
-                    Ok(())
-                })?;
-                self.assembler.emit_pop(Size::S64, Location::GPR(value));
-                self.machine.release_temp_gpr(compare);
+                    .emit_vpandn(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM9), XMM::XMM9);
+                self.assembler
+                    .emit_vpor(XMM::XMM8, XMMOrMemory::XMM(XMM::XMM9), XMM::XMM8);
+                self.emit_v128_store(XMM::XMM8, ret);
+            }
+            Operator::V128AnyTrue => self.emit_v128_test(None)?,
+            Operator::I8x16AllTrue => self.emit_v128_test(Some(Assembler::emit_vpcmpeqb))?,
+            Operator::I16x8AllTrue => self.emit_v128_test(Some(Assembler::emit_vpcmpeqw))?,
+            Operator::I32x4AllTrue => self.emit_v128_test(Some(Assembler::emit_vpcmpeqd))?,
+            Operator::I64x2AllTrue => self.emit_v128_test(Some(Assembler::emit_vpcmpeqq))?,
+            Operator::I8x16Bitmask => self.emit_v128_to_i32(|this, a, ret| {
+                this.assembler.emit_pmovmskb(a, ret);
+            })?,
+            Operator::I16x8Bitmask => self.emit_v128_to_i32(|this, a, ret| {
+                this.assembler
+                    .emit_vpacksswb(a, XMMOrMemory::XMM(a), XMM::XMM10);
+                this.assembler.emit_pmovmskb(XMM::XMM10, ret);
+                this.assembler
+                    .emit_and(Size::S32, Location::Imm32(0xff), Location::GPR(ret));
+            })?,
+            Operator::I32x4Bitmask => self.emit_v128_to_i32(|this, a, ret| {
+                this.assembler.emit_movmskps(a, ret);
+            })?,
+            Operator::I64x2Bitmask => self.emit_v128_to_i32(|this, a, ret| {
+                this.assembler.emit_movmskpd(a, ret);
+            })?,
+            Operator::I8x16Abs => self.emit_v128_unop_avx(Assembler::emit_vpabsb)?,
+            Operator::I16x8Abs => self.emit_v128_unop_avx(Assembler::emit_vpabsw)?,
+            Operator::I32x4Abs => self.emit_v128_unop_avx(Assembler::emit_vpabsd)?,
+            Operator::I8x16Neg => self.emit_v128_unop(|this, a, dst| {
+                this.assembler
+                    .emit_vpxor(XMM::XMM9, XMMOrMemory::XMM(XMM::XMM9), XMM::XMM9);
+                this.assembler
+                    .emit_vpsubb(XMM::XMM9, XMMOrMemory::XMM(a), dst);
+            })?,
+            Operator::I16x8Neg => self.emit_v128_unop(|this, a, dst| {
+                this.assembler
+                    .emit_vpxor(XMM::XMM9, XMMOrMemory::XMM(XMM::XMM9), XMM::XMM9);
+                this.assembler
+                    .emit_vpsubw(XMM::XMM9, XMMOrMemory::XMM(a), dst);
+            })?,
+            Operator::I32x4Neg => self.emit_v128_unop(|this, a, dst| {
+                this.assembler
+                    .emit_vpxor(XMM::XMM9, XMMOrMemory::XMM(XMM::XMM9), XMM::XMM9);
+                this.assembler
+                    .emit_vpsubd(XMM::XMM9, XMMOrMemory::XMM(a), dst);
+            })?,
+            Operator::I64x2Neg => self.emit_v128_unop(|this, a, dst| {
+                this.assembler
+                    .emit_vpxor(XMM::XMM9, XMMOrMemory::XMM(XMM::XMM9), XMM::XMM9);
+                this.assembler
+                    .emit_vpsubq(XMM::XMM9, XMMOrMemory::XMM(a), dst);
+            })?,
+            Operator::I8x16Shl => self.emit_v128_shift(8, |this, a, count, dst| {
+                // There is no byte-wise shift, so shift words after clearing the bits that would
+                // be shifted into the neighbouring byte.
+                this.emit_v128_i8x16_shift_mask(count, XMM::XMM10);
+                this.assembler
+                    .emit_vpand(a, XMMOrMemory::XMM(XMM::XMM10), dst);
+                this.assembler
+                    .emit_vpsllw(dst, XMMOrMemory::XMM(XMM::XMM9), dst);
+            })?,
+            Operator::I8x16ShrU => self.emit_v128_shift(8, |this, a, count, dst| {
+                this.emit_v128_i8x16_shift_mask(count, XMM::XMM10);
+                this.assembler
+                    .emit_vpsrlw(a, XMMOrMemory::XMM(XMM::XMM9), dst);
+                this.assembler
+                    .emit_vpand(dst, XMMOrMemory::XMM(XMM::XMM10), dst);
+            })?,
+            Operator::I8x16ShrS => self.emit_v128_shift(8, |this, a, count, dst| {
+                // Widen each byte to the high byte of a word, shift the words arithmetically and
+                // narrow them back.
+                this.assembler
+                    .emit_vpunpcklbw(a, XMMOrMemory::XMM(a), XMM::XMM10);
+                this.assembler
+                    .emit_vpunpckhbw(a, XMMOrMemory::XMM(a), XMM::XMM11);
+                this.assembler
+                    .emit_add(Size::S32, Location::Imm32(8), Location::GPR(count));
+                this.assembler
+                    .emit_mov(Size::S64, Location::GPR(count), Location::XMM(XMM::XMM9));
+                this.assembler
+                    .emit_vpsraw(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM9), XMM::XMM10);
+                this.assembler
+                    .emit_vpsraw(XMM::XMM11, XMMOrMemory::XMM(XMM::XMM9), XMM::XMM11);
+                this.assembler
+                    .emit_vpacksswb(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM11), dst);
+            })?,
+            Operator::I16x8Shl => self.emit_v128_shift_avx(16, Assembler::emit_vpsllw)?,
+            Operator::I16x8ShrS => self.emit_v128_shift_avx(16, Assembler::emit_vpsraw)?,
+            Operator::I16x8ShrU => self.emit_v128_shift_avx(16, Assembler::emit_vpsrlw)?,
+            Operator::I32x4Shl => self.emit_v128_shift_avx(32, Assembler::emit_vpslld)?,
+            Operator::I32x4ShrS => self.emit_v128_shift_avx(32, Assembler::emit_vpsrad)?,
+            Operator::I32x4ShrU => self.emit_v128_shift_avx(32, Assembler::emit_vpsrld)?,
+            Operator::I64x2Shl => self.emit_v128_shift_avx(64, Assembler::emit_vpsllq)?,
+            Operator::I64x2ShrU => self.emit_v128_shift_avx(64, Assembler::emit_vpsrlq)?,
+            Operator::I64x2ShrS => self.emit_v128_shift(64, |this, a, count, dst| {
+                // There is no 64-bit arithmetic shift before AVX-512, so use
+                // `((a ^ s) >> n) ^ s` where `s` is the sign mask of `a`.
+                this.assembler
+                    .emit_mov(Size::S64, Location::GPR(count), Location::XMM(XMM::XMM9));
+                this.assembler
+                    .emit_vpxor(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                this.assembler
+                    .emit_vpcmpgtq(XMM::XMM10, XMMOrMemory::XMM(a), XMM::XMM10);
+                this.assembler
+                    .emit_vpxor(a, XMMOrMemory::XMM(XMM::XMM10), dst);
+                this.assembler
+                    .emit_vpsrlq(dst, XMMOrMemory::XMM(XMM::XMM9), dst);
+                this.assembler
+                    .emit_vpxor(dst, XMMOrMemory::XMM(XMM::XMM10), dst);
+            })?,
+            Operator::I8x16Add => self.emit_v128_binop_avx(Assembler::emit_vpaddb)?,
+            Operator::I8x16AddSatS => self.emit_v128_binop_avx(Assembler::emit_vpaddsb)?,
+            Operator::I8x16AddSatU => self.emit_v128_binop_avx(Assembler::emit_vpaddusb)?,
+            Operator::I8x16Sub => self.emit_v128_binop_avx(Assembler::emit_vpsubb)?,
+            Operator::I8x16SubSatS => self.emit_v128_binop_avx(Assembler::emit_vpsubsb)?,
+            Operator::I8x16SubSatU => self.emit_v128_binop_avx(Assembler::emit_vpsubusb)?,
+            Operator::I8x16MinS => self.emit_v128_binop_avx(Assembler::emit_vpminsb)?,
+            Operator::I8x16MinU => self.emit_v128_binop_avx(Assembler::emit_vpminub)?,
+            Operator::I8x16MaxS => self.emit_v128_binop_avx(Assembler::emit_vpmaxsb)?,
+            Operator::I8x16MaxU => self.emit_v128_binop_avx(Assembler::emit_vpmaxub)?,
+            Operator::I8x16RoundingAverageU => self.emit_v128_binop_avx(Assembler::emit_vpavgb)?,
+            Operator::I16x8Add => self.emit_v128_binop_avx(Assembler::emit_vpaddw)?,
+            Operator::I16x8AddSatS => self.emit_v128_binop_avx(Assembler::emit_vpaddsw)?,
+            Operator::I16x8AddSatU => self.emit_v128_binop_avx(Assembler::emit_vpaddusw)?,
+            Operator::I16x8Sub => self.emit_v128_binop_avx(Assembler::emit_vpsubw)?,
+            Operator::I16x8SubSatS => self.emit_v128_binop_avx(Assembler::emit_vpsubsw)?,
+            Operator::I16x8SubSatU => self.emit_v128_binop_avx(Assembler::emit_vpsubusw)?,
+            Operator::I16x8Mul => self.emit_v128_binop_avx(Assembler::emit_vpmullw)?,
+            Operator::I16x8MinS => self.emit_v128_binop_avx(Assembler::emit_vpminsw)?,
+            Operator::I16x8MinU => self.emit_v128_binop_avx(Assembler::emit_vpminuw)?,
+            Operator::I16x8MaxS => self.emit_v128_binop_avx(Assembler::emit_vpmaxsw)?,
+            Operator::I16x8MaxU => self.emit_v128_binop_avx(Assembler::emit_vpmaxuw)?,
+            Operator::I16x8RoundingAverageU => self.emit_v128_binop_avx(Assembler::emit_vpavgw)?,
+            Operator::I16x8Q15MulrSatS => self.emit_v128_binop(|this, a, b, dst| {
+                // `vpmulhrsw` only overflows for `0x8000 * 0x8000`, producing `0x8000` where
+                // `0x7fff` is expected.
+                this.assembler.emit_vpmulhrsw(a, XMMOrMemory::XMM(b), dst);
+                this.assembler
+                    .emit_vpcmpeqd(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                this.assembler.emit_vpsllw_imm(XMM::XMM10, 15, XMM::XMM10);
+                this.assembler
+                    .emit_vpcmpeqw(dst, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                this.assembler
+                    .emit_vpxor(dst, XMMOrMemory::XMM(XMM::XMM10), dst);
+            })?,
+            Operator::I32x4Add => self.emit_v128_binop_avx(Assembler::emit_vpaddd)?,
+            Operator::I32x4Sub => self.emit_v128_binop_avx(Assembler::emit_vpsubd)?,
+            Operator::I32x4Mul => self.emit_v128_binop_avx(Assembler::emit_vpmulld)?,
+            Operator::I32x4MinS => self.emit_v128_binop_avx(Assembler::emit_vpminsd)?,
+            Operator::I32x4MinU => self.emit_v128_binop_avx(Assembler::emit_vpminud)?,
+            Operator::I32x4MaxS => self.emit_v128_binop_avx(Assembler::emit_vpmaxsd)?,
+            Operator::I32x4MaxU => self.emit_v128_binop_avx(Assembler::emit_vpmaxud)?,
+            Operator::I32x4DotI16x8S => self.emit_v128_binop_avx(Assembler::emit_vpmaddwd)?,
+            Operator::I64x2Add => self.emit_v128_binop_avx(Assembler::emit_vpaddq)?,
+            Operator::I64x2Sub => self.emit_v128_binop_avx(Assembler::emit_vpsubq)?,
+            Operator::I64x2Mul => self.emit_v128_binop(|this, a, b, dst| {
+                // a * b = lo(a) * lo(b) + ((hi(a) * lo(b) + lo(a) * hi(b)) << 32)
+                this.assembler.emit_vpsrlq_imm(a, 32, XMM::XMM10);
+                this.assembler
+                    .emit_vpmuludq(XMM::XMM10, XMMOrMemory::XMM(b), XMM::XMM10);
+                this.assembler.emit_vpsrlq_imm(b, 32, XMM::XMM11);
+                this.assembler
+                    .emit_vpmuludq(XMM::XMM11, XMMOrMemory::XMM(a), XMM::XMM11);
+                this.assembler
+                    .emit_vpaddq(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM11), XMM::XMM10);
+                this.assembler.emit_vpsllq_imm(XMM::XMM10, 32, XMM::XMM10);
+                this.assembler.emit_vpmuludq(a, XMMOrMemory::XMM(b), dst);
+                this.assembler
+                    .emit_vpaddq(dst, XMMOrMemory::XMM(XMM::XMM10), dst);
+            })?,
+            Operator::I8x16NarrowI16x8S => self.emit_v128_binop_avx(Assembler::emit_vpacksswb)?,
+            Operator::I8x16NarrowI16x8U => self.emit_v128_binop_avx(Assembler::emit_vpackuswb)?,
+            Operator::I16x8NarrowI32x4S => self.emit_v128_binop_avx(Assembler::emit_vpackssdw)?,
+            Operator::I16x8NarrowI32x4U => self.emit_v128_binop_avx(Assembler::emit_vpackusdw)?,
+            Operator::I16x8WidenLowI8x16S => {
+                self.emit_v128_widen(false, Assembler::emit_vpmovsxbw)?
+            }
+            Operator::I16x8WidenHighI8x16S => {
+                self.emit_v128_widen(true, Assembler::emit_vpmovsxbw)?
+            }
+            Operator::I16x8WidenLowI8x16U => {
+                self.emit_v128_widen(false, Assembler::emit_vpmovzxbw)?
+            }
+            Operator::I16x8WidenHighI8x16U => {
+                self.emit_v128_widen(true, Assembler::emit_vpmovzxbw)?
+            }
+            Operator::I32x4WidenLowI16x8S => {
+                self.emit_v128_widen(false, Assembler::emit_vpmovsxwd)?
+            }
+            Operator::I32x4WidenHighI16x8S => {
+                self.emit_v128_widen(true, Assembler::emit_vpmovsxwd)?
+            }
+            Operator::I32x4WidenLowI16x8U => {
+                self.emit_v128_widen(false, Assembler::emit_vpmovzxwd)?
+            }
+            Operator::I32x4WidenHighI16x8U => {
+                self.emit_v128_widen(true, Assembler::emit_vpmovzxwd)?
+            }
+            Operator::I64x2WidenLowI32x4S => {
+                self.emit_v128_widen(false, Assembler::emit_vpmovsxdq)?
+            }
+            Operator::I64x2WidenHighI32x4S => {
+                self.emit_v128_widen(true, Assembler::emit_vpmovsxdq)?
+            }
+            Operator::I64x2WidenLowI32x4U => {
+                self.emit_v128_widen(false, Assembler::emit_vpmovzxdq)?
+            }
+            Operator::I64x2WidenHighI32x4U => {
+                self.emit_v128_widen(true, Assembler::emit_vpmovzxdq)?
+            }
+            Operator::I16x8ExtMulLowI8x16S => {
+                self.emit_v128_extmul(false, Assembler::emit_vpmovsxbw, Assembler::emit_vpmullw)?
+            }
+            Operator::I16x8ExtMulHighI8x16S => {
+                self.emit_v128_extmul(true, Assembler::emit_vpmovsxbw, Assembler::emit_vpmullw)?
+            }
+            Operator::I16x8ExtMulLowI8x16U => {
+                self.emit_v128_extmul(false, Assembler::emit_vpmovzxbw, Assembler::emit_vpmullw)?
+            }
+            Operator::I16x8ExtMulHighI8x16U => {
+                self.emit_v128_extmul(true, Assembler::emit_vpmovzxbw, Assembler::emit_vpmullw)?
+            }
+            Operator::I32x4ExtMulLowI16x8S => {
+                self.emit_v128_extmul(false, Assembler::emit_vpmovsxwd, Assembler::emit_vpmulld)?
+            }
+            Operator::I32x4ExtMulHighI16x8S => {
+                self.emit_v128_extmul(true, Assembler::emit_vpmovsxwd, Assembler::emit_vpmulld)?
+            }
+            Operator::I32x4ExtMulLowI16x8U => {
+                self.emit_v128_extmul(false, Assembler::emit_vpmovzxwd, Assembler::emit_vpmulld)?
+            }
+            Operator::I32x4ExtMulHighI16x8U => {
+                self.emit_v128_extmul(true, Assembler::emit_vpmovzxwd, Assembler::emit_vpmulld)?
+            }
+            Operator::I64x2ExtMulLowI32x4S => {
+                self.emit_v128_extmul(false, Assembler::emit_vpmovsxdq, Assembler::emit_vpmuldq)?
+            }
+            Operator::I64x2ExtMulHighI32x4S => {
+                self.emit_v128_extmul(true, Assembler::emit_vpmovsxdq, Assembler::emit_vpmuldq)?
+            }
+            Operator::I64x2ExtMulLowI32x4U => {
+                self.emit_v128_extmul(false, Assembler::emit_vpmovzxdq, Assembler::emit_vpmuludq)?
+            }
+            Operator::I64x2ExtMulHighI32x4U => {
+                self.emit_v128_extmul(true, Assembler::emit_vpmovzxdq, Assembler::emit_vpmuludq)?
+            }
+            Operator::F32x4Add => self.emit_v128_fp_binop_avx(Size::S32, Assembler::emit_vaddps)?,
+            Operator::F32x4Sub => self.emit_v128_fp_binop_avx(Size::S32, Assembler::emit_vsubps)?,
+            Operator::F32x4Mul => self.emit_v128_fp_binop_avx(Size::S32, Assembler::emit_vmulps)?,
+            Operator::F32x4Div => self.emit_v128_fp_binop_avx(Size::S32, Assembler::emit_vdivps)?,
+            Operator::F32x4Sqrt => {
+                self.emit_v128_fp_unop_avx(Size::S32, Assembler::emit_vsqrtps)?
+            }
+            Operator::F32x4Min => self.emit_v128_fp_min(Size::S32)?,
+            Operator::F32x4Max => self.emit_v128_fp_max(Size::S32)?,
+            Operator::F32x4PMin => self.emit_v128_binop(|this, a, b, dst| {
+                this.assembler.emit_vminps(b, XMMOrMemory::XMM(a), dst);
+            })?,
+            Operator::F32x4PMax => self.emit_v128_binop(|this, a, b, dst| {
+                this.assembler.emit_vmaxps(b, XMMOrMemory::XMM(a), dst);
+            })?,
+            Operator::F32x4Abs => self.emit_v128_unop(|this, a, dst| {
+                this.assembler
+                    .emit_vpcmpeqd(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                this.assembler.emit_vpsrld_imm(XMM::XMM10, 1, XMM::XMM10);
+                this.assembler
+                    .emit_vpand(a, XMMOrMemory::XMM(XMM::XMM10), dst);
+            })?,
+            Operator::F32x4Neg => self.emit_v128_unop(|this, a, dst| {
+                this.assembler
+                    .emit_vpcmpeqd(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                this.assembler.emit_vpslld_imm(XMM::XMM10, 31, XMM::XMM10);
+                this.assembler
+                    .emit_vpxor(a, XMMOrMemory::XMM(XMM::XMM10), dst);
+            })?,
+            Operator::F32x4Nearest => self.emit_v128_fp_unop(Size::S32, |this, a, dst| {
+                this.assembler.emit_vroundps(XMMOrMemory::XMM(a), 0, dst);
+            })?,
+            Operator::F32x4Floor => self.emit_v128_fp_unop(Size::S32, |this, a, dst| {
+                this.assembler.emit_vroundps(XMMOrMemory::XMM(a), 1, dst);
+            })?,
+            Operator::F32x4Ceil => self.emit_v128_fp_unop(Size::S32, |this, a, dst| {
+                this.assembler.emit_vroundps(XMMOrMemory::XMM(a), 2, dst);
+            })?,
+            Operator::F32x4Trunc => self.emit_v128_fp_unop(Size::S32, |this, a, dst| {
+                this.assembler.emit_vroundps(XMMOrMemory::XMM(a), 3, dst);
+            })?,
+            Operator::F64x2Add => self.emit_v128_fp_binop_avx(Size::S64, Assembler::emit_vaddpd)?,
+            Operator::F64x2Sub => self.emit_v128_fp_binop_avx(Size::S64, Assembler::emit_vsubpd)?,
+            Operator::F64x2Mul => self.emit_v128_fp_binop_avx(Size::S64, Assembler::emit_vmulpd)?,
+            Operator::F64x2Div => self.emit_v128_fp_binop_avx(Size::S64, Assembler::emit_vdivpd)?,
+            Operator::F64x2Sqrt => {
+                self.emit_v128_fp_unop_avx(Size::S64, Assembler::emit_vsqrtpd)?
+            }
+            Operator::F64x2Min => self.emit_v128_fp_min(Size::S64)?,
+            Operator::F64x2Max => self.emit_v128_fp_max(Size::S64)?,
+            Operator::F64x2PMin => self.emit_v128_binop(|this, a, b, dst| {
+                this.assembler.emit_vminpd(b, XMMOrMemory::XMM(a), dst);
+            })?,
+            Operator::F64x2PMax => self.emit_v128_binop(|this, a, b, dst| {
+                this.assembler.emit_vmaxpd(b, XMMOrMemory::XMM(a), dst);
+            })?,
+            Operator::F64x2Abs => self.emit_v128_unop(|this, a, dst| {
+                this.assembler
+                    .emit_vpcmpeqd(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                this.assembler.emit_vpsrlq_imm(XMM::XMM10, 1, XMM::XMM10);
+                this.assembler
+                    .emit_vpand(a, XMMOrMemory::XMM(XMM::XMM10), dst);
+            })?,
+            Operator::F64x2Neg => self.emit_v128_unop(|this, a, dst| {
+                this.assembler
+                    .emit_vpcmpeqd(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                this.assembler.emit_vpsllq_imm(XMM::XMM10, 63, XMM::XMM10);
+                this.assembler
+                    .emit_vpxor(a, XMMOrMemory::XMM(XMM::XMM10), dst);
+            })?,
+            Operator::F64x2Nearest => self.emit_v128_fp_unop(Size::S64, |this, a, dst| {
+                this.assembler.emit_vroundpd(XMMOrMemory::XMM(a), 0, dst);
+            })?,
+            Operator::F64x2Floor => self.emit_v128_fp_unop(Size::S64, |this, a, dst| {
+                this.assembler.emit_vroundpd(XMMOrMemory::XMM(a), 1, dst);
+            })?,
+            Operator::F64x2Ceil => self.emit_v128_fp_unop(Size::S64, |this, a, dst| {
+                this.assembler.emit_vroundpd(XMMOrMemory::XMM(a), 2, dst);
+            })?,
+            Operator::F64x2Trunc => self.emit_v128_fp_unop(Size::S64, |this, a, dst| {
+                this.assembler.emit_vroundpd(XMMOrMemory::XMM(a), 3, dst);
+            })?,
+            Operator::F32x4DemoteF64x2Zero => {
+                self.emit_v128_fp_unop(Size::S32, |this, a, dst| {
+                    this.assembler.emit_vcvtpd2ps(a, dst);
+                })?
+            }
+            Operator::F64x2PromoteLowF32x4 => {
+                self.emit_v128_fp_unop_avx(Size::S64, Assembler::emit_vcvtps2pd)?
+            }
+            Operator::F32x4ConvertI32x4S => self.emit_v128_unop_avx(Assembler::emit_vcvtdq2ps)?,
+            Operator::F32x4ConvertI32x4U => self.emit_v128_unop(|this, a, dst| {
+                // Convert the low 16 bits and the remaining high bits separately, both of which
+                // are exact, and add them up. The high bits are halved so that they fit in an
+                // `i32` and doubled again afterwards.
+                this.assembler
+                    .emit_vpcmpeqd(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                this.assembler.emit_vpsrld_imm(XMM::XMM10, 16, XMM::XMM10);
+                this.assembler
+                    .emit_vpand(a, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM11);
+                this.assembler
+                    .emit_vpsubd(a, XMMOrMemory::XMM(XMM::XMM11), XMM::XMM12);
+                this.assembler
+                    .emit_vcvtdq2ps(XMMOrMemory::XMM(XMM::XMM11), XMM::XMM11);
+                this.assembler.emit_vpsrld_imm(XMM::XMM12, 1, XMM::XMM12);
+                this.assembler
+                    .emit_vcvtdq2ps(XMMOrMemory::XMM(XMM::XMM12), XMM::XMM12);
+                this.assembler
+                    .emit_vaddps(XMM::XMM12, XMMOrMemory::XMM(XMM::XMM12), XMM::XMM12);
+                this.assembler
+                    .emit_vaddps(XMM::XMM12, XMMOrMemory::XMM(XMM::XMM11), dst);
+            })?,
+            Operator::F64x2ConvertLowI32x4S => {
+                self.emit_v128_unop_avx(Assembler::emit_vcvtdq2pd)?
             }
+            Operator::F64x2ConvertLowI32x4U => self.emit_v128_unop(|this, a, dst| {
+                // Place each `u32` in the mantissa of 2^52 and subtract 2^52.
+                this.assembler
+                    .emit_vpmovzxdq(XMMOrMemory::XMM(a), XMM::XMM10);
+                this.emit_v128_const(0x4330_0000_0000_0000_4330_0000_0000_0000, XMM::XMM11);
+                this.assembler
+                    .emit_vpor(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM11), XMM::XMM10);
+                this.assembler
+                    .emit_vsubpd(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM11), dst);
+            })?,
+            Operator::I32x4TruncSatF32x4S => self.emit_v128_unop(|this, a, dst| {
+                // Zero out NaN lanes.
+                this.assembler
+                    .emit_vcmpeqps(a, XMMOrMemory::XMM(a), XMM::XMM10);
+                this.assembler
+                    .emit_vpand(a, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM11);
+                // Set the top bit of lanes which are not negative.
+                this.assembler
+                    .emit_vpxor(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM11), XMM::XMM10);
+                this.assembler
+                    .emit_vcvttps2dq(XMMOrMemory::XMM(XMM::XMM11), XMM::XMM11);
+                // Lanes which were not negative but converted to `0x80000000` overflowed, and
+                // must become `0x7fffffff`.
+                this.assembler
+                    .emit_vpand(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM11), XMM::XMM10);
+                this.assembler.emit_vpsrad_imm(XMM::XMM10, 31, XMM::XMM10);
+                this.assembler
+                    .emit_vpxor(XMM::XMM11, XMMOrMemory::XMM(XMM::XMM10), dst);
+            })?,
+            Operator::I32x4TruncSatF32x4U => self.emit_v128_unop(|this, a, dst| {
+                // Clamp NaN and negative lanes to zero.
+                this.assembler
+                    .emit_vpxor(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                this.assembler
+                    .emit_vmaxps(a, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM11);
+                // Build 2^31 in each lane.
+                this.assembler
+                    .emit_vpcmpeqd(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                this.assembler.emit_vpsrld_imm(XMM::XMM10, 1, XMM::XMM10);
+                this.assembler
+                    .emit_vcvtdq2ps(XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                // Convert `x - 2^31`, saturating lanes where `x >= 2^32` and dropping negative
+                // results.
+                this.assembler
+                    .emit_vsubps(XMM::XMM11, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM12);
+                this.assembler
+                    .emit_vcmpleps(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM12), XMM::XMM10);
+                this.assembler
+                    .emit_vcvttps2dq(XMMOrMemory::XMM(XMM::XMM12), XMM::XMM12);
+                this.assembler
+                    .emit_vpxor(XMM::XMM12, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM12);
+                this.assembler
+                    .emit_vpxor(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                this.assembler
+                    .emit_vpmaxsd(XMM::XMM12, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM12);
+                // Add the conversion of `x`, which is `0x80000000` for `x >= 2^31`.
+                this.assembler
+                    .emit_vcvttps2dq(XMMOrMemory::XMM(XMM::XMM11), XMM::XMM11);
+                this.assembler
+                    .emit_vpaddd(XMM::XMM11, XMMOrMemory::XMM(XMM::XMM12), dst);
+            })?,
+            Operator::I32x4TruncSatF64x2SZero => self.emit_v128_unop(|this, a, dst| {
+                // Zero out NaN lanes and clamp the rest to `i32::MAX`. Lanes below `i32::MIN`
+                // convert to `0x80000000`, which is the saturated result.
+                this.assembler
+                    .emit_vcmpeqpd(a, XMMOrMemory::XMM(a), XMM::XMM10);
+                this.emit_v128_const(0x41DF_FFFF_FFC0_0000_41DF_FFFF_FFC0_0000, XMM::XMM11);
+                this.assembler
+                    .emit_vpand(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM11), XMM::XMM10);
+                this.assembler
+                    .emit_vminpd(a, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                this.assembler.emit_vcvttpd2dq(XMM::XMM10, dst);
+            })?,
+            Operator::I32x4TruncSatF64x2UZero => self.emit_v128_unop(|this, a, dst| {
+                // Clamp to `[0, u32::MAX]`, zeroing NaN lanes, truncate, then add 2^52 so that the
+                // integer ends up in the low 32 bits of each lane.
+                this.assembler
+                    .emit_vpxor(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                this.assembler
+                    .emit_vmaxpd(a, XMMOrMemory::XMM(XMM::XMM10), XMM::XMM10);
+                this.emit_v128_const(0x41EF_FFFF_FFE0_0000_41EF_FFFF_FFE0_0000, XMM::XMM11);
+                this.assembler
+                    .emit_vminpd(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM11), XMM::XMM10);
+                this.assembler
+                    .emit_vroundpd(XMMOrMemory::XMM(XMM::XMM10), 3, XMM::XMM10);
+                this.emit_v128_const(0x4330_0000_0000_0000_4330_0000_0000_0000, XMM::XMM11);
+                this.assembler
+                    .emit_vaddpd(XMM::XMM10, XMMOrMemory::XMM(XMM::XMM11), XMM::XMM10);
+                this.assembler
+                    .emit_vpshufd(XMMOrMemory::XMM(XMM::XMM10), 0x08, XMM::XMM10);
+                this.assembler
+                    .emit_mov(Size::S64, Location::XMM(XMM::XMM10), Location::XMM(dst));
+            })?,
             _ => {
                 return Err(CodegenError {
                     message: format!("not yet implemented: {:?}", op),
@@ -8233,6 +10015,23 @@ fn type_to_wp_type(ty: Type) -> WpType {
     }
 }
 
+/// Converts a register or memory location to a `GPROrMemory` operand.
+fn to_gpr_or_memory(loc: Location) -> GPROrMemory {
+    match loc {
+        Location::GPR(x) => GPROrMemory::GPR(x),
+        Location::Memory(base, disp) => GPROrMemory::Memory(base, disp),
+        _ => unreachable!("to_gpr_or_memory: unexpected location {:?}", loc),
+    }
+}
+
+/// Returns the number of 8-byte value slots used by a value of type `ty`.
+fn wp_type_slots(ty: WpType) -> usize {
+    match ty {
+        WpType::V128 => 2,
+        _ => 1,
+    }
+}
+
 // FIXME: This implementation seems to be not enough to resolve all kinds of register dependencies
 // at call place.
 fn sort_call_movs(movs: &mut [(Location, GPR)]) {
@@ -8282,18 +10081,49 @@ fn sort_call_movs(movs: &mut [(Location, GPR)]) {
     */
 }
 
+/// Returns the offsets into a trampoline's values array of the 8-byte slots that singlepass passes
+/// values of types `tys` in. A `v128` value is passed as two slots holding its low and high halves.
+fn values_vec_slot_offsets(tys: &[Type]) -> Vec<usize> {
+    tys.iter()
+        .enumerate()
+        .flat_map(|(i, &ty)| {
+            if ty == Type::V128 {
+                vec![i * 16, i * 16 + 8]
+            } else {
+                vec![i * 16]
+            }
+        })
+        .collect()
+}
+
 // Standard entry trampoline.
 pub fn gen_std_trampoline(sig: &FunctionType) -> FunctionBody {
     let mut a = Assembler::new().unwrap();
 
+    let arg_offsets = values_vec_slot_offsets(sig.params());
+
+    // A function returning multiple values takes a pointer to the area its results are stored to
+    // as an additional argument.
+    let ret_offsets = if sig.results().len() > 1 {
+        values_vec_slot_offsets(sig.results())
+    } else {
+        vec![]
+    };
+    let n_args = arg_offsets.len() + !ret_offsets.is_empty() as usize;
+
     // Calculate stack offset.
     let mut stack_offset: u32 = 0;
-    for (i, _param) in sig.params().iter().enumerate() {
+    for i in 0..n_args {
         if let Location::Memory(_, _) = Machine::get_param_location(1 + i) {
             stack_offset += 8;
         }
     }
 
+    // The return area is placed above the stack arguments. Its first slot is at the highest
+    // address.
+    let return_area_offset = stack_offset + 8 * ret_offsets.len().saturating_sub(1) as u32;
+    stack_offset += 8 * ret_offsets.len() as u32;
+
     // Align to 16 bytes. We push two 8-byte registers below, so here we need to ensure stack_offset % 16 == 8.
     if stack_offset % 16 != 8 {
         stack_offset += 8;
@@ -8326,8 +10156,8 @@ pub fn gen_std_trampoline(sig: &FunctionType) -> FunctionBody {
     // `callee_vmctx` is already in the first argument register, so no need to move.
     {
         let mut n_stack_args: usize = 0;
-        for (i, _param) in sig.params().iter().enumerate() {
-            let src_loc = Location::Memory(GPR::R14, (i * 16) as _); // args_rets[i]
+        for (i, &offset) in arg_offsets.iter().enumerate() {
+            let src_loc = Location::Memory(GPR::R14, offset as _); // args_rets[i]
             let dst_loc = Machine::get_param_location(1 + i);
 
             match dst_loc {
@@ -8348,11 +10178,43 @@ pub fn gen_std_trampoline(sig: &FunctionType) -> FunctionBody {
                 _ => unreachable!(),
             }
         }
+
+        if !ret_offsets.is_empty() {
+            let return_area = Location::Memory(GPR::RSP, return_area_offset as _);
+            match Machine::get_param_location(1 + arg_offsets.len()) {
+                dst_loc @ Location::GPR(_) => {
+                    a.emit_lea(Size::S64, return_area, dst_loc);
+                }
+                Location::Memory(_, _) => {
+                    a.emit_lea(Size::S64, return_area, Location::GPR(GPR::RAX));
+                    a.emit_mov(
+                        Size::S64,
+                        Location::GPR(GPR::RAX),
+                        Location::Memory(GPR::RSP, (n_stack_args * 8) as _),
+                    );
+                }
+                _ => unreachable!(),
+            }
+        }
     }
 
     // Call.
     a.emit_call_location(Location::GPR(GPR::R15));
 
+    // Copy return values out of the return area.
+    for (i, &offset) in ret_offsets.iter().enumerate() {
+        a.emit_mov(
+            Size::S64,
+            Location::Memory(GPR::RSP, return_area_offset as i32 - (i * 8) as i32),
+            Location::GPR(GPR::RAX),
+        );
+        a.emit_mov(
+            Size::S64,
+            Location::GPR(GPR::RAX),
+            Location::Memory(GPR::R14, offset as _),
+        );
+    }
+
     // Restore stack.
     a.emit_add(
         Size::S64,
@@ -8361,12 +10223,19 @@ pub fn gen_std_trampoline(sig: &FunctionType) -> FunctionBody {
     );
 
     // Write return value.
-    if !sig.results().is_empty() {
+    if sig.results().len() == 1 {
         a.emit_mov(
             Size::S64,
             Location::GPR(GPR::RAX),
             Location::Memory(GPR::R14, 0),
         );
+        if sig.results()[0] == Type::V128 {
+            a.emit_mov(
+                Size::S64,
+                Location::GPR(GPR::RDX),
+                Location::Memory(GPR::R14, 8),
+            );
+        }
     }
 
     // Restore callee-saved registers.
@@ -8388,8 +10257,17 @@ pub fn gen_std_dynamic_import_trampoline(
 ) -> FunctionBody {
     let mut a = Assembler::new().unwrap();
 
+    // A function returning multiple values takes a pointer to the area its results are stored to
+    // as an additional argument, which is saved after the values array.
+    let ret_offsets = if sig.results().len() > 1 {
+        values_vec_slot_offsets(sig.results())
+    } else {
+        vec![]
+    };
+    let return_area_offset = 16 * std::cmp::max(sig.params().len(), sig.results().len());
+
     // Allocate argument array.
-    let stack_offset: usize = 16 * std::cmp::max(sig.params().len(), sig.results().len()) + 8; // 16 bytes each + 8 bytes sysv call padding
+    let stack_offset: usize = return_area_offset + if ret_offsets.is_empty() { 0 } else { 16 } + 8; // 16 bytes each + 8 bytes sysv call padding
     a.emit_sub(
         Size::S64,
         Location::Imm32(stack_offset as _),
@@ -8397,33 +10275,48 @@ pub fn gen_std_dynamic_import_trampoline(
     );
 
     // Copy arguments.
-    if !sig.params().is_empty() {
-        let mut argalloc = ArgumentRegisterAllocator::default();
-        argalloc.next(Type::I64).unwrap(); // skip VMContext
+    let mut argalloc = ArgumentRegisterAllocator::default();
+    argalloc.next(Type::I64).unwrap(); // skip VMContext
 
-        let mut stack_param_count: usize = 0;
+    // A `v128` argument is passed as two 8-byte slots holding its low and high halves.
+    let mut arg_slots: Vec<(Type, usize)> = sig
+        .params()
+        .iter()
+        .enumerate()
+        .flat_map(|(i, &ty)| match ty {
+            Type::V128 => vec![(Type::I64, i * 16), (Type::I64, i * 16 + 8)],
+            ty => vec![(ty, i * 16)],
+        })
+        .collect();
+    if !ret_offsets.is_empty() {
+        arg_slots.push((Type::I64, return_area_offset));
+    }
 
-        for (i, ty) in sig.params().iter().enumerate() {
-            let source_loc = match argalloc.next(*ty) {
-                Some(X64Register::GPR(gpr)) => Location::GPR(gpr),
-                Some(X64Register::XMM(xmm)) => Location::XMM(xmm),
-                None => {
-                    a.emit_mov(
-                        Size::S64,
-                        Location::Memory(GPR::RSP, (stack_offset + 8 + stack_param_count * 8) as _),
-                        Location::GPR(GPR::RAX),
-                    );
-                    stack_param_count += 1;
-                    Location::GPR(GPR::RAX)
-                }
-            };
-            a.emit_mov(
-                Size::S64,
-                source_loc,
-                Location::Memory(GPR::RSP, (i * 16) as _),
-            );
+    let mut stack_param_count: usize = 0;
+    for (ty, offset) in arg_slots {
+        let source_loc = match argalloc.next(ty) {
+            Some(X64Register::GPR(gpr)) => Location::GPR(gpr),
+            Some(X64Register::XMM(xmm)) => Location::XMM(xmm),
+            None => {
+                a.emit_mov(
+                    Size::S64,
+                    Location::Memory(GPR::RSP, (stack_offset + 8 + stack_param_count * 8) as _),
+                    Location::GPR(GPR::RAX),
+                );
+                stack_param_count += 1;
+                Location::GPR(GPR::RAX)
+            }
+        };
+        a.emit_mov(
+            Size::S64,
+            source_loc,
+            Location::Memory(GPR::RSP, offset as _),
+        );
+    }
 
-            // Zero upper 64 bits.
+    // Zero upper 64 bits.
+    for (i, ty) in sig.params().iter().enumerate() {
+        if *ty != Type::V128 {
             a.emit_mov(
                 Size::S64,
                 Location::Imm32(0),
@@ -8449,13 +10342,37 @@ pub fn gen_std_dynamic_import_trampoline(
     a.emit_call_location(Location::GPR(GPR::RAX));
 
     // Fetch return value.
-    if !sig.results().is_empty() {
-        assert_eq!(sig.results().len(), 1);
+    if !ret_offsets.is_empty() {
+        a.emit_mov(
+            Size::S64,
+            Location::Memory(GPR::RSP, return_area_offset as _),
+            Location::GPR(GPR::RCX),
+        );
+        for (i, &offset) in ret_offsets.iter().enumerate() {
+            a.emit_mov(
+                Size::S64,
+                Location::Memory(GPR::RSP, offset as _),
+                Location::GPR(GPR::RAX),
+            );
+            a.emit_mov(
+                Size::S64,
+                Location::GPR(GPR::RAX),
+                Location::Memory(GPR::RCX, -((i * 8) as i32)),
+            );
+        }
+    } else if !sig.results().is_empty() {
         a.emit_mov(
             Size::S64,
             Location::Memory(GPR::RSP, 0),
             Location::GPR(GPR::RAX),
         );
+        if sig.results()[0] == Type::V128 {
+            a.emit_mov(
+                Size::S64,
+                Location::Memory(GPR::RSP, 8),
+                Location::GPR(GPR::RDX),
+            );
+        }
     }
 
     // Release values array.
@@ -8495,13 +10412,29 @@ pub fn gen_import_call_trampoline(
         .iter()
         .any(|&x| x == Type::F32 || x == Type::F64)
     {
+        // A `v128` argument is passed as two 8-byte integer slots holding its low and high halves.
+        let mut params: Vec<Type> = sig
+            .params()
+            .iter()
+            .flat_map(|&ty| {
+                if ty == Type::V128 {
+                    vec![Type::I64, Type::I64]
+                } else {
+                    vec![ty]
+                }
+            })
+            .collect();
+        // The pointer to the return area of a function returning multiple values.
+        if sig.results().len() > 1 {
+            params.push(Type::I64);
+        }
         let mut param_locations: Vec<Location> = vec![];
 
         // Allocate stack space for arguments.
-        let stack_offset: i32 = if sig.params().len() > 5 {
+        let stack_offset: i32 = if params.len() > 5 {
             5 * 8
         } else {
-            (sig.params().len() as i32) * 8
+            (params.len() as i32) * 8
         };
         if stack_offset > 0 {
             a.emit_sub(
@@ -8512,7 +10445,7 @@ pub fn gen_import_call_trampoline(
         }
 
         // Store all arguments to the stack to prevent overwrite.
-        for i in 0..sig.params().len() {
+        for i in 0..params.len() {
             let loc = match i {
                 0..=4 => {
                     static PARAM_REGS: &[GPR] = &[GPR::RSI, GPR::RDX, GPR::RCX, GPR::R8, GPR::R9];
@@ -8529,7 +10462,7 @@ pub fn gen_import_call_trampoline(
         let mut argalloc = ArgumentRegisterAllocator::default();
         argalloc.next(Type::I64).unwrap(); // skip VMContext
         let mut caller_stack_offset: i32 = 0;
-        for (i, ty) in sig.params().iter().enumerate() {
+        for (i, ty) in params.iter().enumerate() {
             let prev_loc = param_locations[i];
             let target = match argalloc.next(*ty) {
                 Some(X64Register::GPR(gpr)) => Location::GPR(gpr),
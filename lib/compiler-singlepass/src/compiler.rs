@@ -12,8 +12,8 @@ use rayon::prelude::{IntoParallelIterator, IntoParallelRefIterator, ParallelIter
 use std::sync::Arc;
 use wasmer_compiler::TrapInformation;
 use wasmer_compiler::{
-    Architecture, CompileModuleInfo, CompilerConfig, MiddlewareBinaryReader, ModuleMiddlewareChain,
-    ModuleTranslationState, OperatingSystem, Target,
+    Architecture, CompileModuleInfo, CompilerConfig, CpuFeature, MiddlewareBinaryReader,
+    ModuleMiddlewareChain, ModuleTranslationState, OperatingSystem, Target,
 };
 use wasmer_compiler::{Compilation, CompileError, CompiledFunction, Compiler, SectionIndex};
 use wasmer_compiler::{FunctionBody, FunctionBodyData};
@@ -58,8 +58,13 @@ impl Compiler for SinglepassCompiler {
         if let Architecture::X86_32(arch) = target.triple().architecture {
             return Err(CompileError::UnsupportedTarget(arch.to_string()));
         }
-        if compile_info.features.multi_value {
-            return Err(CompileError::UnsupportedFeature("multivalue".to_string()));
+        if compile_info.features.simd
+            && !(target.cpu_features().contains(CpuFeature::SSE41)
+                && target.cpu_features().contains(CpuFeature::AVX))
+        {
+            return Err(CompileError::UnsupportedFeature(
+                "simd without SSE4.1 and AVX".to_string(),
+            ));
         }
         let memory_styles = &compile_info.memory_styles;
         let table_styles = &compile_info.table_styles;
@@ -115,6 +120,7 @@ impl Compiler for SinglepassCompiler {
                 while generator.has_control_frames() {
                     generator.set_srcloc(reader.original_position() as u32);
                     let op = reader.read_operator()?;
+                    generator.set_unreachable_trap_code(reader.unreachable_trap_code());
                     generator.feed_operator(op).map_err(to_compile_error)?;
                 }
 
@@ -173,7 +179,7 @@ mod tests {
     use super::*;
     use std::str::FromStr;
     use target_lexicon::triple;
-    use wasmer_compiler::{CpuFeature, Features, Triple};
+    use wasmer_compiler::{Features, Triple};
     use wasmer_vm::{MemoryStyle, TableStyle};
 
     fn dummy_compilation_ingredients<'a>() -> (
@@ -223,4 +229,22 @@ mod tests {
             error => panic!("Unexpected error: {:?}", error),
         };
     }
+
+    #[test]
+    fn errors_for_simd_without_avx() {
+        let compiler = SinglepassCompiler::new(Singlepass::default());
+
+        let mut cpu_features = CpuFeature::for_host();
+        cpu_features.remove(CpuFeature::AVX);
+        let target = Target::new(triple!("x86_64-unknown-linux-gnu"), cpu_features);
+        let (mut info, translation, inputs) = dummy_compilation_ingredients();
+        info.features.simd(true);
+        let result = compiler.compile_module(&target, &mut info, &translation, inputs);
+        match result.unwrap_err() {
+            CompileError::UnsupportedFeature(name) => {
+                assert_eq!(name, "simd without SSE4.1 and AVX")
+            }
+            error => panic!("Unexpected error: {:?}", error),
+        };
+    }
 }
@@ -194,6 +194,33 @@ impl Machine {
         ret
     }
 
+    /// Acquires locations on the machine stack, whatever the types of the values stored there are.
+    ///
+    /// The returned locations need to be released with `release_location` like any other stack value.
+    pub fn acquire_stack_locations<E: Emitter>(
+        &mut self,
+        assembler: &mut E,
+        mvs: &[MachineValue],
+    ) -> SmallVec<[Location; 1]> {
+        let mut ret = smallvec![];
+
+        for mv in mvs {
+            self.stack_offset.0 += 8;
+            self.state.stack_values.push(mv.clone());
+            self.state.wasm_stack.push(WasmAbstractValue::Runtime);
+            ret.push(Location::Memory(GPR::RBP, -(self.stack_offset.0 as i32)));
+        }
+
+        if !mvs.is_empty() {
+            assembler.emit_sub(
+                Size::S64,
+                Location::Imm32((mvs.len() * 8) as u32),
+                Location::GPR(GPR::RSP),
+            );
+        }
+        ret
+    }
+
     /// Releases locations used for stack value.
     pub fn release_locations<E: Emitter>(&mut self, assembler: &mut E, locs: &[Location]) {
         let mut delta_stack_offset: usize = 0;
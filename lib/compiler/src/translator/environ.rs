@@ -335,7 +335,7 @@ impl<'data> ModuleEnvironment<'data> {
         self.result
             .module
             .passive_elements
-            .reserve_exact(usize::try_from(num).unwrap());
+            .reserve(usize::try_from(num).unwrap());
         Ok(())
     }
 
@@ -363,7 +363,15 @@ impl<'data> ModuleEnvironment<'data> {
         elem_index: ElemIndex,
         segments: Box<[FunctionIndex]>,
     ) -> WasmResult<()> {
-        self.result.module.passive_elements[elem_index] = segments;
+        let old = self
+            .result
+            .module
+            .passive_elements
+            .insert(elem_index, segments);
+        debug_assert!(
+            old.is_none(),
+            "a module can't have duplicate indices, this would be a wasmer-compiler bug"
+        );
         Ok(())
     }
 
@@ -415,7 +423,15 @@ impl<'data> ModuleEnvironment<'data> {
         data_index: DataIndex,
         data: &'data [u8],
     ) -> WasmResult<()> {
-        self.result.module.passive_data[data_index] = Arc::from(data);
+        let old = self
+            .result
+            .module
+            .passive_data
+            .insert(data_index, Arc::from(data));
+        debug_assert!(
+            old.is_none(),
+            "a module can't have duplicate indices, this would be a wasmer-compiler bug"
+        );
         Ok(())
     }
 
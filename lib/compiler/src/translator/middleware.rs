@@ -7,7 +7,7 @@ use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::ops::Deref;
 use wasmer_types::LocalFunctionIndex;
-use wasmer_vm::ModuleInfo;
+use wasmer_vm::{ModuleInfo, TrapCode};
 use wasmparser::{BinaryReader, Operator, Type};
 
 use crate::error::{MiddlewareError, WasmResult};
@@ -49,6 +49,10 @@ pub struct MiddlewareBinaryReader<'a> {
 
     /// The backing middleware chain for this reader.
     chain: Vec<Box<dyn FunctionMiddleware>>,
+
+    /// The trap code of the last operator returned, if a middleware pushed it
+    /// as a trap.
+    trap_code: Option<TrapCode>,
 }
 
 /// The state of the binary reader. Exposed to middlewares to push their outputs.
@@ -57,8 +61,25 @@ pub struct MiddlewareReaderState<'a> {
     /// Raw binary reader.
     inner: BinaryReader<'a>,
 
-    /// The pending operations added by the middleware.
-    pending_operations: VecDeque<Operator<'a>>,
+    /// A reader at the start of the function body, for looking ahead.
+    body: BinaryReader<'a>,
+
+    /// The pending operations added by the middleware, with the trap code of
+    /// the ones pushed as traps.
+    pending_operations: VecDeque<(Operator<'a>, Option<TrapCode>)>,
+
+    /// The offset in the module of the operator being processed.
+    operator_offset: usize,
+
+    /// The trap code of the operator being processed, if a previous middleware
+    /// of the chain pushed it as a trap.
+    trap_code: Option<TrapCode>,
+
+    /// The index in the chain of the middleware processing the operator.
+    stage: usize,
+
+    /// The operators each middleware of the chain holds back, if it does.
+    held_operations: Vec<Option<VecDeque<(Operator<'a>, Option<TrapCode>)>>>,
 }
 
 /// Trait for generating middleware chains from "prototype" (generator) chains.
@@ -94,20 +115,72 @@ impl<T: Deref<Target = dyn ModuleMiddleware>> ModuleMiddlewareChain for [T] {
 
 impl<'a> MiddlewareReaderState<'a> {
     /// Push an operator.
+    ///
+    /// An `Unreachable` operator keeps the trap code of the trap being
+    /// processed, so a trap pushed by a previous middleware can be passed on.
     pub fn push_operator(&mut self, operator: Operator<'a>) {
-        self.pending_operations.push_back(operator);
+        let trap_code = match operator {
+            Operator::Unreachable => self.trap_code,
+            _ => None,
+        };
+        self.pending_operations.push_back((operator, trap_code));
+    }
+
+    /// Push an `Unreachable` operator that traps with `trap_code` instead of
+    /// `TrapCode::UnreachableCodeReached`.
+    pub fn push_trap(&mut self, trap_code: TrapCode) {
+        self.pending_operations
+            .push_back((Operator::Unreachable, Some(trap_code)));
+    }
+
+    /// Returns the offset in the module of the operator read from the function
+    /// body. The operators added by the previous middlewares of the chain while
+    /// processing it share its offset.
+    pub fn operator_offset(&self) -> usize {
+        self.operator_offset
+    }
+
+    /// Returns a reader over the whole function body, starting with its local
+    /// declarations, for middlewares that need to look ahead of the operator
+    /// being processed.
+    pub fn function_body(&self) -> BinaryReader<'a> {
+        self.body.clone()
+    }
+
+    /// Holds back the operators the current middleware pushes from now on,
+    /// instead of passing them to the next middleware of the chain, until
+    /// `release_operators` is called.
+    ///
+    /// This lets a middleware add operators before code it hasn't processed
+    /// yet. It must release them before the end of the function body.
+    pub fn hold_operators(&mut self) {
+        let held = &mut self.held_operations[self.stage];
+        if held.is_none() {
+            *held = Some(VecDeque::new());
+        }
+    }
+
+    /// Pushes the operators held back by the current middleware, after the
+    /// ones it pushed so far while processing this operator, and stops
+    /// holding them back.
+    pub fn release_operators(&mut self) {
+        if let Some(held) = self.held_operations[self.stage].take() {
+            self.pending_operations.extend(held);
+        }
     }
 }
 
 impl<'a> Extend<Operator<'a>> for MiddlewareReaderState<'a> {
     fn extend<I: IntoIterator<Item = Operator<'a>>>(&mut self, iter: I) {
-        self.pending_operations.extend(iter);
+        for operator in iter {
+            self.push_operator(operator);
+        }
     }
 }
 
 impl<'a: 'b, 'b> Extend<&'b Operator<'a>> for MiddlewareReaderState<'a> {
     fn extend<I: IntoIterator<Item = &'b Operator<'a>>>(&mut self, iter: I) {
-        self.pending_operations.extend(iter.into_iter().cloned());
+        self.extend(iter.into_iter().cloned());
     }
 }
 
@@ -117,15 +190,22 @@ impl<'a> MiddlewareBinaryReader<'a> {
         let inner = BinaryReader::new_with_offset(data, original_offset);
         Self {
             state: MiddlewareReaderState {
+                body: inner.clone(),
                 inner,
                 pending_operations: VecDeque::new(),
+                operator_offset: original_offset,
+                trap_code: None,
+                stage: 0,
+                held_operations: vec![],
             },
             chain: vec![],
+            trap_code: None,
         }
     }
 
     /// Replaces the middleware chain with a new one.
     pub fn set_middleware_chain(&mut self, stages: Vec<Box<dyn FunctionMiddleware>>) {
+        self.state.held_operations = stages.iter().map(|_| None).collect();
         self.chain = stages;
     }
 
@@ -150,25 +230,43 @@ impl<'a> MiddlewareBinaryReader<'a> {
 
         // Try to fill the `self.pending_operations` buffer, until it is non-empty.
         while self.state.pending_operations.is_empty() {
+            self.state.operator_offset = self.state.inner.original_position();
             let raw_op = self.state.inner.read_operator()?;
 
             // Fill the initial raw operator into pending buffer.
-            self.state.pending_operations.push_back(raw_op);
+            self.state.pending_operations.push_back((raw_op, None));
 
             // Run the operator through each stage.
-            for stage in &mut self.chain {
+            for (index, stage) in self.chain.iter_mut().enumerate() {
                 // Take the outputs from the previous stage.
-                let pending: SmallVec<[Operator<'a>; 2]> =
+                let pending: SmallVec<[(Operator<'a>, Option<TrapCode>); 2]> =
                     self.state.pending_operations.drain(0..).collect();
 
                 // ...and feed them into the current stage.
-                for pending_op in pending {
+                self.state.stage = index;
+                for (pending_op, trap_code) in pending {
+                    self.state.trap_code = trap_code;
                     stage.feed(pending_op, &mut self.state)?;
+
+                    // Keep the outputs of a stage that holds them back.
+                    if let Some(held) = &mut self.state.held_operations[index] {
+                        held.extend(self.state.pending_operations.drain(0..));
+                    }
                 }
+                self.state.trap_code = None;
             }
         }
 
-        Ok(self.state.pending_operations.pop_front().unwrap())
+        let (operator, trap_code) = self.state.pending_operations.pop_front().unwrap();
+        self.trap_code = trap_code;
+        Ok(operator)
+    }
+
+    /// Returns the trap code of the last `Unreachable` operator read, which
+    /// is `TrapCode::UnreachableCodeReached` unless a middleware pushed it
+    /// with `MiddlewareReaderState::push_trap`.
+    pub fn unreachable_trap_code(&self) -> TrapCode {
+        self.trap_code.unwrap_or(TrapCode::UnreachableCodeReached)
     }
 
     /// Returns the inner `BinaryReader`'s current position.
@@ -336,7 +336,9 @@ pub fn parse_element_section<'data>(
 
     for (index, entry) in elements.into_iter().enumerate() {
         let Element { kind, items, ty } = entry?;
-        if ty != wasmparser::Type::FuncRef {
+        // Segments of `externref`s can only hold null references, which are
+        // represented like null `funcref`s.
+        if ty != wasmparser::Type::FuncRef && ty != wasmparser::Type::ExternRef {
             return Err(wasm_unsupported!(
                 "unsupported table element type: {:?}",
                 ty
@@ -372,7 +374,9 @@ pub fn parse_element_section<'data>(
                 let index = ElemIndex::from_u32(index as u32);
                 environ.declare_passive_element(index, segments)?;
             }
-            ElementKind::Declared => return Err(wasm_unsupported!("element kind declared")),
+            // Declared segments only make their functions referenceable with
+            // `ref.func`, which validation takes care of.
+            ElementKind::Declared => {}
         }
     }
     Ok(())
@@ -19,11 +19,12 @@ use wasmer_engine::{
 use wasmer_engine::{Engine, SerializableFunctionFrameInfo, Tunables};
 use wasmer_types::entity::{BoxedSlice, PrimaryMap};
 use wasmer_types::{
-    FunctionIndex, LocalFunctionIndex, MemoryIndex, OwnedDataInitializer, SignatureIndex,
-    TableIndex,
+    DataInitializer, FunctionIndex, LocalFunctionIndex, MemoryIndex, OwnedDataInitializer,
+    SignatureIndex, TableIndex,
 };
 use wasmer_vm::{
-    FunctionBodyPtr, MemoryStyle, ModuleInfo, TableStyle, VMSharedSignatureIndex, VMTrampoline,
+    FunctionBodyPtr, MemoryImages, MemoryStyle, ModuleInfo, TableStyle, VMSharedSignatureIndex,
+    VMTrampoline,
 };
 
 /// A compiled wasm module, ready to be instantiated.
@@ -37,6 +38,7 @@ pub struct JITArtifact {
     signatures: BoxedSlice<SignatureIndex, VMSharedSignatureIndex>,
     frame_info_registration: Mutex<Option<GlobalFrameInfoRegistration>>,
     finished_function_lengths: BoxedSlice<LocalFunctionIndex, usize>,
+    memory_images: Option<MemoryImages>,
 }
 
 impl JITArtifact {
@@ -227,6 +229,24 @@ impl JITArtifact {
             finished_dynamic_function_trampolines.into_boxed_slice();
         let signatures = signatures.into_boxed_slice();
 
+        let memory_images = if inner_jit.memory_images() {
+            let data_initializers = serializable
+                .data_initializers
+                .iter()
+                .map(|init| DataInitializer {
+                    location: init.location.clone(),
+                    data: &*init.data,
+                })
+                .collect::<Vec<_>>();
+            let memory_images =
+                MemoryImages::new(&serializable.compile_info.module, &data_initializers).map_err(
+                    |e| CompileError::Resource(format!("Failed to build memory images: {}", e)),
+                )?;
+            Some(memory_images)
+        } else {
+            None
+        };
+
         Ok(Self {
             serializable,
             finished_functions,
@@ -235,6 +255,7 @@ impl JITArtifact {
             signatures,
             frame_info_registration: Mutex::new(None),
             finished_function_lengths,
+            memory_images,
         })
     }
 
@@ -290,6 +311,10 @@ impl Artifact for JITArtifact {
         &*self.serializable.data_initializers
     }
 
+    fn memory_images(&self) -> Option<&MemoryImages> {
+        self.memory_images.as_ref()
+    }
+
     fn memory_styles(&self) -> &PrimaryMap<MemoryIndex, MemoryStyle> {
         &self.serializable.compile_info.memory_styles
     }
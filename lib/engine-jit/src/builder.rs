@@ -7,6 +7,7 @@ pub struct JIT {
     compiler_config: Option<Box<dyn CompilerConfig>>,
     target: Option<Target>,
     features: Option<Features>,
+    memory_images: bool,
 }
 
 impl JIT {
@@ -19,6 +20,7 @@ impl JIT {
             compiler_config: Some(compiler_config.into()),
             target: None,
             features: None,
+            memory_images: false,
         }
     }
 
@@ -28,6 +30,7 @@ impl JIT {
             compiler_config: None,
             target: None,
             features: None,
+            memory_images: false,
         }
     }
 
@@ -43,6 +46,17 @@ impl JIT {
         self
     }
 
+    /// Build copy-on-write images of the memories of the compiled modules
+    /// from their static data segments, so that instantiating them maps
+    /// the images instead of copying the data segments.
+    ///
+    /// Memory images are only supported on Linux, and are ignored on other
+    /// platforms.
+    pub fn memory_images(mut self, enable: bool) -> Self {
+        self.memory_images = enable;
+        self
+    }
+
     /// Build the `JITEngine` for this configuration
     #[cfg(feature = "compiler")]
     pub fn engine(self) -> JITEngine {
@@ -52,15 +66,23 @@ impl JIT {
                 .features
                 .unwrap_or_else(|| compiler_config.default_features_for_target(&target));
             let compiler = compiler_config.compiler();
-            JITEngine::new(compiler, target, features)
+            let engine = JITEngine::new(compiler, target, features);
+            engine.inner_mut().set_memory_images(self.memory_images);
+            engine
         } else {
-            JITEngine::headless()
+            self.headless_engine()
         }
     }
 
     /// Build the `JITEngine` for this configuration
     #[cfg(not(feature = "compiler"))]
     pub fn engine(self) -> JITEngine {
-        JITEngine::headless()
+        self.headless_engine()
+    }
+
+    fn headless_engine(self) -> JITEngine {
+        let engine = JITEngine::headless();
+        engine.inner_mut().set_memory_images(self.memory_images);
+        engine
     }
 }
@@ -8,7 +8,9 @@ use wasmer_compiler::Compiler;
 use wasmer_compiler::{
     CompileError, CustomSection, CustomSectionProtection, FunctionBody, SectionIndex, Target,
 };
-use wasmer_engine::{Artifact, DeserializeError, Engine, EngineId, FunctionExtent, Tunables};
+use wasmer_engine::{
+    Artifact, DeserializeError, Engine, EngineEpoch, EngineId, FunctionExtent, Tunables,
+};
 use wasmer_types::entity::PrimaryMap;
 use wasmer_types::Features;
 use wasmer_types::{FunctionIndex, FunctionType, LocalFunctionIndex, SignatureIndex};
@@ -24,6 +26,7 @@ pub struct JITEngine {
     /// The target for the compiler
     target: Arc<Target>,
     engine_id: EngineId,
+    epoch: EngineEpoch,
 }
 
 impl JITEngine {
@@ -36,9 +39,11 @@ impl JITEngine {
                 code_memory: vec![],
                 signatures: SignatureRegistry::new(),
                 features,
+                memory_images: false,
             })),
             target: Arc::new(target),
             engine_id: EngineId::default(),
+            epoch: EngineEpoch::default(),
         }
     }
 
@@ -63,9 +68,11 @@ impl JITEngine {
                 code_memory: vec![],
                 signatures: SignatureRegistry::new(),
                 features: Features::default(),
+                memory_images: false,
             })),
             target: Arc::new(Target::default()),
             engine_id: EngineId::default(),
+            epoch: EngineEpoch::default(),
         }
     }
 
@@ -133,6 +140,10 @@ impl Engine for JITEngine {
         &self.engine_id
     }
 
+    fn epoch(&self) -> &EngineEpoch {
+        &self.epoch
+    }
+
     fn cloned(&self) -> Arc<dyn Engine + Send + Sync> {
         Arc::new(self.clone())
     }
@@ -152,6 +163,8 @@ pub struct JITEngineInner {
     /// The signature registry is used mainly to operate with trampolines
     /// performantly.
     signatures: SignatureRegistry,
+    /// Whether to build memory images for the compiled modules.
+    memory_images: bool,
 }
 
 impl JITEngineInner {
@@ -184,6 +197,16 @@ impl JITEngineInner {
         &self.features
     }
 
+    /// Whether to build memory images for the compiled modules.
+    pub(crate) fn memory_images(&self) -> bool {
+        self.memory_images
+    }
+
+    /// Set whether to build memory images for the compiled modules.
+    pub(crate) fn set_memory_images(&mut self, enable: bool) {
+        self.memory_images = enable;
+    }
+
     /// Allocate compiled functions into memory
     #[allow(clippy::type_complexity)]
     pub(crate) fn allocate(
@@ -9,7 +9,7 @@ use std::sync::Mutex;
 use wasmer_compiler::{CompileError, Target};
 #[cfg(feature = "compiler")]
 use wasmer_compiler::{Compiler, Triple};
-use wasmer_engine::{Artifact, DeserializeError, Engine, EngineId, Tunables};
+use wasmer_engine::{Artifact, DeserializeError, Engine, EngineEpoch, EngineId, Tunables};
 #[cfg(feature = "compiler")]
 use wasmer_types::Features;
 use wasmer_types::FunctionType;
@@ -22,6 +22,7 @@ pub struct NativeEngine {
     /// The target for the compiler
     target: Arc<Target>,
     engine_id: EngineId,
+    epoch: EngineEpoch,
 }
 
 impl NativeEngine {
@@ -43,6 +44,7 @@ impl NativeEngine {
             })),
             target: Arc::new(target),
             engine_id: EngineId::default(),
+            epoch: EngineEpoch::default(),
         }
     }
 
@@ -74,6 +76,7 @@ impl NativeEngine {
             })),
             target: Arc::new(Target::default()),
             engine_id: EngineId::default(),
+            epoch: EngineEpoch::default(),
         }
     }
 
@@ -170,6 +173,10 @@ impl Engine for NativeEngine {
         &self.engine_id
     }
 
+    fn epoch(&self) -> &EngineEpoch {
+        &self.epoch
+    }
+
     fn cloned(&self) -> Arc<dyn Engine + Send + Sync> {
         Arc::new(self.clone())
     }
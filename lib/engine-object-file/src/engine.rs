@@ -6,7 +6,7 @@ use std::sync::{Arc, Mutex};
 #[cfg(feature = "compiler")]
 use wasmer_compiler::Compiler;
 use wasmer_compiler::{CompileError, Target};
-use wasmer_engine::{Artifact, DeserializeError, Engine, EngineId, Tunables};
+use wasmer_engine::{Artifact, DeserializeError, Engine, EngineEpoch, EngineId, Tunables};
 #[cfg(feature = "compiler")]
 use wasmer_types::Features;
 use wasmer_types::FunctionType;
@@ -19,6 +19,7 @@ pub struct ObjectFileEngine {
     /// The target for the compiler
     target: Arc<Target>,
     engine_id: EngineId,
+    epoch: EngineEpoch,
 }
 
 impl ObjectFileEngine {
@@ -34,6 +35,7 @@ impl ObjectFileEngine {
             })),
             target: Arc::new(target),
             engine_id: EngineId::default(),
+            epoch: EngineEpoch::default(),
         }
     }
 
@@ -62,6 +64,7 @@ impl ObjectFileEngine {
             })),
             target: Arc::new(Target::default()),
             engine_id: EngineId::default(),
+            epoch: EngineEpoch::default(),
         }
     }
 
@@ -160,6 +163,10 @@ impl Engine for ObjectFileEngine {
         &self.engine_id
     }
 
+    fn epoch(&self) -> &EngineEpoch {
+        &self.epoch
+    }
+
     fn cloned(&self) -> Arc<dyn Engine + Send + Sync> {
         Arc::new(self.clone())
     }
@@ -13,8 +13,8 @@ use wasmer_types::{
     SignatureIndex, TableIndex,
 };
 use wasmer_vm::{
-    FunctionBodyPtr, InstanceAllocator, InstanceHandle, MemoryStyle, ModuleInfo, TableStyle,
-    VMSharedSignatureIndex, VMTrampoline,
+    FunctionBodyPtr, InstanceHandle, MemoryImages, MemoryStyle, ModuleInfo, TableStyle,
+    VMInterrupts, VMSharedSignatureIndex, VMTrampoline,
 };
 
 /// An `Artifact` is the product that the `Engine`
@@ -52,6 +52,12 @@ pub trait Artifact: Send + Sync + Upcastable + MemoryUsage {
     /// Returns data initializers to pass to `InstanceHandle::initialize`
     fn data_initializers(&self) -> &[OwnedDataInitializer];
 
+    /// Returns the memory images built from the data initializers, if
+    /// any, to map into memories instead of copying the data.
+    fn memory_images(&self) -> Option<&MemoryImages> {
+        None
+    }
+
     /// Returns the functions allocated in memory or this `Artifact`
     /// ready to be run.
     fn finished_functions(&self) -> &BoxedSlice<LocalFunctionIndex, FunctionBodyPtr>;
@@ -84,6 +90,8 @@ pub trait Artifact: Send + Sync + Upcastable + MemoryUsage {
 
     /// Crate an `Instance` from this `Artifact`.
     ///
+    /// The compiled code of the instance traps when `interrupts` is set.
+    ///
     /// # Safety
     ///
     /// See [`InstanceHandle::new`].
@@ -92,6 +100,7 @@ pub trait Artifact: Send + Sync + Upcastable + MemoryUsage {
         tunables: &dyn Tunables,
         resolver: &dyn Resolver,
         host_state: Box<dyn Any>,
+        interrupts: Arc<VMInterrupts>,
     ) -> Result<InstanceHandle, InstantiationError> {
         self.preinstantiate()?;
 
@@ -116,8 +125,9 @@ pub trait Artifact: Send + Sync + Upcastable + MemoryUsage {
         // Get pointers to where metadata about local memories should live in VM memory.
         // Get pointers to where metadata about local tables should live in VM memory.
 
-        let (allocator, memory_definition_locations, table_definition_locations) =
-            InstanceAllocator::new(&*module);
+        let (allocator, memory_definition_locations, table_definition_locations) = tunables
+            .allocate_instance(&*module)
+            .map_err(InstantiationError::Link)?;
         let finished_memories = tunables
             .create_memories(&module, self.memory_styles(), &memory_definition_locations)
             .map_err(InstantiationError::Link)?
@@ -145,6 +155,7 @@ pub trait Artifact: Send + Sync + Upcastable + MemoryUsage {
             self.signatures().clone(),
             host_state,
             import_function_envs,
+            interrupts,
         )
         .map_err(|trap| InstantiationError::Start(RuntimeError::from_trap(trap)))?;
         Ok(handle)
@@ -168,7 +179,7 @@ pub trait Artifact: Send + Sync + Upcastable + MemoryUsage {
             })
             .collect::<Vec<_>>();
         handle
-            .finish_instantiation(&data_initializers)
+            .finish_instantiation(&data_initializers, self.memory_images())
             .map_err(|trap| InstantiationError::Start(RuntimeError::from_trap(trap)))
     }
 }
@@ -2,10 +2,11 @@
 
 use crate::tunables::Tunables;
 use crate::{Artifact, DeserializeError};
-use loupe::MemoryUsage;
+use loupe::{MemoryUsage, MemoryUsageTracker};
 use memmap2::Mmap;
+use std::mem;
 use std::path::Path;
-use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::SeqCst};
 use std::sync::Arc;
 use wasmer_compiler::{CompileError, Target};
 use wasmer_types::FunctionType;
@@ -65,6 +66,20 @@ pub trait Engine: MemoryUsage {
     /// of trait representation.
     fn id(&self) -> &EngineId;
 
+    /// The epoch counter of this engine.
+    ///
+    /// Stores created from this engine compare it against their epoch
+    /// deadline while running WebAssembly code.
+    fn epoch(&self) -> &EngineEpoch;
+
+    /// Increments the epoch counter of this engine by one.
+    ///
+    /// This is cheap and can be called from any thread, typically a
+    /// timer thread that time-slices the running stores.
+    fn increment_epoch(&self) {
+        self.epoch().increment();
+    }
+
     /// Clone the engine
     fn cloned(&self) -> Arc<dyn Engine + Send + Sync>;
 }
@@ -97,3 +112,34 @@ impl Default for EngineId {
         }
     }
 }
+
+/// The epoch counter of an [`Engine`].
+///
+/// Clones of an engine share the same counter.
+#[derive(Debug, Clone, Default)]
+pub struct EngineEpoch {
+    counter: Arc<AtomicU64>,
+}
+
+impl EngineEpoch {
+    /// Increments the epoch by one.
+    pub fn increment(&self) {
+        self.counter.fetch_add(1, SeqCst);
+    }
+
+    /// Returns the current epoch.
+    pub fn current(&self) -> u64 {
+        self.counter.load(SeqCst)
+    }
+
+    /// Returns the counter read by compiled code.
+    pub fn counter(&self) -> &Arc<AtomicU64> {
+        &self.counter
+    }
+}
+
+impl MemoryUsage for EngineEpoch {
+    fn size_of_val(&self, _: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self) + mem::size_of::<AtomicU64>()
+    }
+}
@@ -30,7 +30,7 @@ mod trap;
 mod tunables;
 
 pub use crate::artifact::Artifact;
-pub use crate::engine::{Engine, EngineId};
+pub use crate::engine::{Engine, EngineEpoch, EngineId};
 pub use crate::error::{
     DeserializeError, ImportError, InstantiationError, LinkError, SerializeError,
 };
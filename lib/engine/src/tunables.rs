@@ -8,7 +8,7 @@ use wasmer_types::{
     TableIndex, TableType,
 };
 use wasmer_vm::MemoryError;
-use wasmer_vm::{Global, Memory, ModuleInfo, Table};
+use wasmer_vm::{Global, InstanceAllocator, Memory, ModuleInfo, Table};
 use wasmer_vm::{MemoryStyle, TableStyle};
 use wasmer_vm::{VMMemoryDefinition, VMTableDefinition};
 
@@ -62,6 +62,23 @@ pub trait Tunables: MemoryUsage {
         Ok(Arc::new(Global::new(ty)))
     }
 
+    /// Allocate the memory of a new instance of `module`.
+    ///
+    /// See [`InstanceAllocator::new`].
+    fn allocate_instance(
+        &self,
+        module: &ModuleInfo,
+    ) -> Result<
+        (
+            InstanceAllocator,
+            Vec<NonNull<VMMemoryDefinition>>,
+            Vec<NonNull<VMTableDefinition>>,
+        ),
+        LinkError,
+    > {
+        Ok(InstanceAllocator::new(module))
+    }
+
     /// Allocate memory for just the memories of the current module.
     unsafe fn create_memories(
         &self,
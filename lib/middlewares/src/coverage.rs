@@ -0,0 +1,567 @@
+//! `coverage` is a middleware for finding out which basic blocks of a module were
+//! executed, and which source lines they belong to according to the DWARF debug
+//! information of the module.
+
+use gimli::{EndianSlice, LittleEndian, SectionId};
+use loupe::MemoryUsage;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use wasmer::wasmparser::{BinaryReaderError, Operator, Parser, Payload};
+use wasmer::{
+    ExportIndex, FunctionMiddleware, GlobalInit, GlobalType, Instance, LocalFunctionIndex,
+    MiddlewareError, MiddlewareReaderState, ModuleMiddleware, Mutability, Type,
+};
+use wasmer_types::entity::EntityRef;
+use wasmer_types::GlobalIndex;
+use wasmer_vm::ModuleInfo;
+
+/// The prefix of the exported globals holding the bitmap.
+const BITMAP_PREFIX: &str = "wasmer_coverage_";
+/// The blocks whose bits are stored in each global of the bitmap.
+const BLOCKS_PER_GLOBAL: usize = 64;
+
+/// A basic block, as the range of offsets of its operators in the module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, MemoryUsage)]
+struct Block {
+    start: usize,
+    end: usize,
+}
+
+/// The module-level coverage middleware.
+///
+/// Every basic block of the module sets its bit in a bitmap when it runs. The bitmap
+/// is stored in exported `i64` globals, see [`get_coverage_bitmap`], and can be turned
+/// into an lcov tracefile with [`Coverage::write_lcov`].
+///
+/// # Panic
+///
+/// A `Coverage` is created from the bytes of a module and can only be used to compile
+/// that module. Attempts to use it for another module, or for more than one, will
+/// result in a panic.
+#[derive(Debug, MemoryUsage)]
+pub struct Coverage {
+    /// The blocks of the module, numbered across all the functions.
+    blocks: Vec<Block>,
+
+    /// The number of the first block of every local function, followed by the
+    /// number of blocks.
+    first_blocks: Vec<usize>,
+
+    /// The offset of the contents of the code section, which DWARF addresses are
+    /// relative to.
+    code_offset: usize,
+
+    /// The global index of the first word of the bitmap.
+    bitmap: Mutex<Option<GlobalIndex>>,
+}
+
+/// The function-level coverage middleware.
+#[derive(Debug)]
+pub struct FunctionCoverage {
+    /// The offsets at which the blocks of the function start.
+    starts: Vec<usize>,
+
+    /// The number of the first block of the function.
+    first_block: usize,
+
+    /// The block of the function to mark next.
+    next: usize,
+
+    /// The global index of the first word of the bitmap.
+    bitmap: GlobalIndex,
+}
+
+/// An error while reading a module for coverage, or writing its tracefile.
+#[derive(Debug)]
+pub enum CoverageError {
+    /// The module could not be parsed.
+    Wasm(BinaryReaderError),
+    /// The module has no `.debug_line` custom section.
+    NoDebugInfo,
+    /// The DWARF debug information could not be parsed.
+    Dwarf(gimli::Error),
+    /// The tracefile could not be written.
+    Io(io::Error),
+}
+
+impl fmt::Display for CoverageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Wasm(error) => write!(f, "the module could not be parsed: {}", error),
+            Self::NoDebugInfo => write!(f, "the module has no DWARF line information"),
+            Self::Dwarf(error) => write!(f, "the DWARF information could not be parsed: {}", error),
+            Self::Io(error) => write!(f, "the tracefile could not be written: {}", error),
+        }
+    }
+}
+
+impl Error for CoverageError {}
+
+impl From<BinaryReaderError> for CoverageError {
+    fn from(error: BinaryReaderError) -> Self {
+        Self::Wasm(error)
+    }
+}
+
+impl From<gimli::Error> for CoverageError {
+    fn from(error: gimli::Error) -> Self {
+        Self::Dwarf(error)
+    }
+}
+
+impl From<io::Error> for CoverageError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+/// Whether the operator after `operator` starts a new basic block.
+fn ends_block(operator: &Operator) -> bool {
+    matches!(
+        operator,
+        Operator::Loop { .. } // loop headers are branch targets
+            | Operator::If { .. } // the "then" branch starts after it
+            | Operator::Else // the "else" branch starts after it
+            | Operator::End // block ends are branch targets
+            | Operator::Br { .. }
+            | Operator::BrIf { .. }
+            | Operator::BrTable { .. }
+            | Operator::Return
+            | Operator::Unreachable
+            | Operator::Call { .. } // the callee may not return
+            | Operator::CallIndirect { .. }
+    )
+}
+
+impl Coverage {
+    /// Creates a `Coverage` middleware for the module in `wasm`, finding its basic
+    /// blocks.
+    pub fn new(wasm: &[u8]) -> Result<Self, CoverageError> {
+        let mut blocks = Vec::<Block>::new();
+        let mut first_blocks = Vec::new();
+        let mut code_offset = 0;
+        for payload in Parser::new(0).parse_all(wasm) {
+            match payload? {
+                Payload::CodeSectionStart { range, .. } => code_offset = range.start,
+                Payload::CodeSectionEntry(body) => {
+                    let first_block = blocks.len();
+                    first_blocks.push(first_block);
+                    let reader = body.get_binary_reader();
+                    let end = reader.original_position() + reader.bytes_remaining();
+                    let mut operators = body.get_operators_reader()?;
+                    let mut starts_block = true;
+                    while !operators.eof() {
+                        let (operator, offset) = operators.read_with_offset()?;
+                        if starts_block {
+                            if blocks.len() > first_block {
+                                blocks.last_mut().unwrap().end = offset;
+                            }
+                            blocks.push(Block { start: offset, end });
+                        }
+                        starts_block = ends_block(&operator);
+                    }
+                }
+                _ => {}
+            }
+        }
+        first_blocks.push(blocks.len());
+
+        Ok(Self {
+            blocks,
+            first_blocks,
+            code_offset,
+            bitmap: Mutex::new(None),
+        })
+    }
+
+    /// The number of basic blocks in the module.
+    pub fn blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Write the source lines of the blocks that `bitmap` marks as executed as an lcov
+    /// tracefile. Lines of blocks that didn't run are listed with 0 hits.
+    ///
+    /// `wasm` must be the module this middleware was created for, with its DWARF
+    /// debug information in `.debug_*` custom sections.
+    pub fn write_lcov(
+        &self,
+        wasm: &[u8],
+        bitmap: &[u64],
+        out: &mut dyn Write,
+    ) -> Result<(), CoverageError> {
+        let lines = LineTable::parse(wasm)?;
+        self.write_lines(&lines, bitmap, out)
+    }
+
+    fn write_lines(
+        &self,
+        lines: &LineTable,
+        bitmap: &[u64],
+        out: &mut dyn Write,
+    ) -> Result<(), CoverageError> {
+        let mut hits = BTreeMap::<&Path, BTreeMap<u64, u64>>::new();
+        for (index, block) in self.blocks.iter().enumerate() {
+            let executed = bitmap
+                .get(index / BLOCKS_PER_GLOBAL)
+                .map_or(false, |word| word & (1 << (index % BLOCKS_PER_GLOBAL)) != 0);
+            let start = (block.start - self.code_offset) as u64;
+            let end = (block.end - self.code_offset) as u64;
+            for (file, line) in lines.locations(start, end) {
+                let count = hits
+                    .entry(&lines.files[file])
+                    .or_default()
+                    .entry(line)
+                    .or_default();
+                *count = (*count).max(executed as u64);
+            }
+        }
+
+        for (file, lines) in hits {
+            writeln!(out, "TN:")?;
+            writeln!(out, "SF:{}", file.display())?;
+            for (line, count) in &lines {
+                writeln!(out, "DA:{},{}", line, count)?;
+            }
+            writeln!(out, "LF:{}", lines.len())?;
+            writeln!(
+                out,
+                "LH:{}",
+                lines.values().filter(|count| **count > 0).count()
+            )?;
+            writeln!(out, "end_of_record")?;
+        }
+        Ok(())
+    }
+}
+
+impl ModuleMiddleware for Coverage {
+    /// Generates a `FunctionMiddleware` for a given function.
+    fn generate_function_middleware(
+        &self,
+        local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        let index = local_function_index.index();
+        let (first, last) = (self.first_blocks[index], self.first_blocks[index + 1]);
+        Box::new(FunctionCoverage {
+            starts: self.blocks[first..last]
+                .iter()
+                .map(|block| block.start)
+                .collect(),
+            first_block: first,
+            next: 0,
+            bitmap: self.bitmap.lock().unwrap().unwrap(),
+        })
+    }
+
+    /// Transforms a `ModuleInfo` struct in-place. This is called before application on functions begins.
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        let mut bitmap = self.bitmap.lock().unwrap();
+
+        if bitmap.is_some() {
+            panic!("Coverage::transform_module_info: Attempting to use a `Coverage` middleware from multiple modules.");
+        }
+        let local_functions = module_info.functions.len() - module_info.num_imported_functions;
+        if local_functions != self.first_blocks.len() - 1 {
+            panic!("Coverage::transform_module_info: Attempting to use a `Coverage` middleware with another module than the one it was created for.");
+        }
+
+        // Append a global for every 64 blocks and export it. The globals are
+        // consecutive, so only the index of the first one is needed.
+        let words = (self.blocks.len() + BLOCKS_PER_GLOBAL - 1) / BLOCKS_PER_GLOBAL;
+        let mut first_word = None;
+        for word in 0..words {
+            let global_index = module_info
+                .globals
+                .push(GlobalType::new(Type::I64, Mutability::Var));
+            module_info
+                .global_initializers
+                .push(GlobalInit::I64Const(0));
+            module_info.exports.insert(
+                format!("{}{}", BITMAP_PREFIX, word),
+                ExportIndex::Global(global_index),
+            );
+            first_word.get_or_insert(global_index);
+        }
+
+        // A module without blocks has no functions, so the index is never used.
+        *bitmap = Some(first_word.unwrap_or_else(|| GlobalIndex::new(0)));
+    }
+}
+
+impl FunctionMiddleware for FunctionCoverage {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        // The operators the previous middlewares added for the first operator of a
+        // block come before it, so the block is marked before any of them.
+        let offset = state.operator_offset();
+        while let Some(&start) = self.starts.get(self.next) {
+            if start > offset {
+                break;
+            }
+            let block = self.first_block + self.next;
+            let global_index = self.bitmap.as_u32() + (block / BLOCKS_PER_GLOBAL) as u32;
+            let mask = 1u64 << (block % BLOCKS_PER_GLOBAL);
+            state.extend(&[
+                // globals[bitmap + block / 64] |= 1 << (block % 64);
+                Operator::GlobalGet { global_index },
+                Operator::I64Const { value: mask as i64 },
+                Operator::I64Or,
+                Operator::GlobalSet { global_index },
+            ]);
+            self.next += 1;
+        }
+        state.push_operator(operator);
+
+        Ok(())
+    }
+}
+
+/// Get the coverage bitmap of an `Instance`: block `i` was executed if bit `i % 64`
+/// of word `i / 64` is set.
+///
+/// This can be used in a headless engine after an ahead-of-time compilation
+/// as all required state lives in the instance.
+///
+/// Modules that weren't processed with the [`Coverage`] middleware at compile time
+/// have no bitmap, so it's empty.
+pub fn get_coverage_bitmap(instance: &Instance) -> Vec<u64> {
+    let mut words = instance
+        .exports
+        .iter()
+        .filter_map(|(name, _)| {
+            let word: usize = name.strip_prefix(BITMAP_PREFIX)?.parse().ok()?;
+            let value: i64 = instance
+                .exports
+                .get_global(name)
+                .unwrap()
+                .get()
+                .try_into()
+                .unwrap_or_else(|_| panic!("`{}` from Instance has wrong type", name));
+            Some((word, value as u64))
+        })
+        .collect::<Vec<_>>();
+    words.sort_by_key(|(word, _)| *word);
+    words.into_iter().map(|(_, value)| value).collect()
+}
+
+/// Clear the coverage bitmap of an `Instance`, so only the blocks executed from now
+/// on are reported.
+pub fn reset_coverage_bitmap(instance: &Instance) {
+    for (name, _) in instance.exports.iter() {
+        if name.starts_with(BITMAP_PREFIX) {
+            instance
+                .exports
+                .get_global(name)
+                .unwrap()
+                .set(0i64.into())
+                .unwrap_or_else(|_| panic!("Can't set `{}` in Instance", name));
+        }
+    }
+}
+
+/// The source locations of the code section, read from `.debug_line`.
+#[derive(Debug, Default)]
+struct LineTable {
+    files: Vec<PathBuf>,
+    /// The address of every row with its file and line, sorted by address. Rows
+    /// without a location end a sequence.
+    rows: Vec<(u64, Option<(usize, u64)>)>,
+}
+
+impl LineTable {
+    fn parse(wasm: &[u8]) -> Result<Self, CoverageError> {
+        let mut sections = HashMap::new();
+        for payload in Parser::new(0).parse_all(wasm) {
+            if let Payload::CustomSection { name, data, .. } = payload? {
+                if name.starts_with(".debug_") {
+                    sections.insert(name, data);
+                }
+            }
+        }
+        if !sections.contains_key(".debug_line") {
+            return Err(CoverageError::NoDebugInfo);
+        }
+        let load = |id: SectionId| -> Result<_, gimli::Error> {
+            let data = sections.get(id.name()).copied().unwrap_or(&[]);
+            Ok(EndianSlice::new(data, LittleEndian))
+        };
+        let load_sup = |_| Ok(EndianSlice::new(&[][..], LittleEndian));
+        let dwarf = gimli::Dwarf::load(load, load_sup)?;
+
+        let mut table = Self::default();
+        let mut file_indexes = HashMap::new();
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            let program = match unit.line_program.clone() {
+                Some(program) => program,
+                None => continue,
+            };
+            let mut rows = program.rows();
+            while let Some((header, row)) = rows.next_row()? {
+                let location = match (row.end_sequence(), row.file(header), row.line()) {
+                    (false, Some(file), Some(line)) if line > 0 => {
+                        let path = file_path(&dwarf, &unit, header, file)?;
+                        let next_index = file_indexes.len();
+                        let index = *file_indexes.entry(path).or_insert(next_index);
+                        Some((index, line))
+                    }
+                    _ => None,
+                };
+                table.rows.push((row.address(), location));
+            }
+        }
+
+        table.files = vec![PathBuf::new(); file_indexes.len()];
+        for (path, index) in file_indexes {
+            table.files[index] = path;
+        }
+        // A sequence may start where another ends
+        table
+            .rows
+            .sort_by_key(|(address, location)| (*address, location.is_some()));
+        Ok(table)
+    }
+
+    /// The files and lines of the code between the addresses `start` and `end`.
+    fn locations(&self, start: u64, end: u64) -> impl Iterator<Item = (usize, u64)> + '_ {
+        // Find the first row at or after `start`, then step back to the row in effect
+        // at `start` if it's before
+        let mut first = match self
+            .rows
+            .binary_search_by_key(&start, |(address, _)| *address)
+        {
+            Ok(index) | Err(index) => index,
+        };
+        while first > 0 && self.rows[first - 1].0 >= start {
+            first -= 1;
+        }
+        if first > 0
+            && self
+                .rows
+                .get(first)
+                .map_or(true, |(address, _)| *address > start)
+        {
+            first -= 1;
+        }
+        self.rows[first..]
+            .iter()
+            .take_while(move |(address, _)| *address < end)
+            .filter_map(|(_, location)| *location)
+    }
+}
+
+fn file_path<R: gimli::Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    unit: &gimli::Unit<R>,
+    header: &gimli::LineProgramHeader<R>,
+    file: &gimli::FileEntry<R>,
+) -> Result<PathBuf, gimli::Error> {
+    let mut path = PathBuf::new();
+    if let Some(comp_dir) = &unit.comp_dir {
+        path.push(&*comp_dir.to_string_lossy()?);
+    }
+    if file.directory_index() != 0 {
+        if let Some(directory) = file.directory(header) {
+            path.push(&*dwarf.attr_string(unit, directory)?.to_string_lossy()?);
+        }
+    }
+    path.push(
+        &*dwarf
+            .attr_string(unit, file.path_name())?
+            .to_string_lossy()?,
+    );
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use wasmer::{imports, wat2wasm, CompilerConfig, Cranelift, Module, Store, JIT};
+
+    fn bytecode() -> Vec<u8> {
+        wat2wasm(
+            br#"
+            (module
+            (func $choose (param $value i32) (result i32)
+                local.get $value
+                if (result i32)
+                    i32.const 1
+                else
+                    i32.const 2
+                end)
+            (export "choose" (func $choose)))
+            "#,
+        )
+        .unwrap()
+        .into()
+    }
+
+    #[test]
+    fn get_coverage_bitmap_works() {
+        let bytecode = bytecode();
+        let coverage = Arc::new(Coverage::new(&bytecode).unwrap());
+        // The body, the two branches, and the end after the `if`.
+        assert_eq!(coverage.blocks(), 4);
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(coverage);
+        let store = Store::new(&JIT::new(compiler_config).engine());
+        let module = Module::new(&store, bytecode).unwrap();
+        let instance = Instance::new(&module, &imports! {}).unwrap();
+        assert_eq!(get_coverage_bitmap(&instance), vec![0]);
+
+        let choose = instance
+            .exports
+            .get_native_function::<i32, i32>("choose")
+            .unwrap();
+        assert_eq!(choose.call(1).unwrap(), 1);
+        assert_eq!(get_coverage_bitmap(&instance), vec![0b1011]);
+        assert_eq!(choose.call(0).unwrap(), 2);
+        assert_eq!(get_coverage_bitmap(&instance), vec![0b1111]);
+
+        reset_coverage_bitmap(&instance);
+        assert_eq!(get_coverage_bitmap(&instance), vec![0]);
+    }
+
+    #[test]
+    fn lcov_lists_the_lines_of_every_block() {
+        let coverage = Coverage {
+            blocks: vec![Block { start: 10, end: 14 }, Block { start: 14, end: 20 }],
+            first_blocks: vec![0, 2],
+            code_offset: 10,
+            bitmap: Mutex::new(None),
+        };
+        let lines = LineTable {
+            files: vec![PathBuf::from("/src/lib.rs")],
+            rows: vec![
+                (0, Some((0, 1))),
+                (2, Some((0, 2))),
+                (4, Some((0, 3))),
+                (8, Some((0, 2))),
+                (10, None),
+            ],
+        };
+
+        let mut out = Vec::new();
+        coverage.write_lines(&lines, &[0b01], &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "TN:\nSF:/src/lib.rs\nDA:1,1\nDA:2,1\nDA:3,0\nLF:3\nLH:2\nend_of_record\n"
+        );
+        assert!(matches!(
+            coverage.write_lcov(&bytecode(), &[0b01], &mut Vec::new()),
+            Err(CoverageError::NoDebugInfo)
+        ));
+    }
+}
@@ -1,5 +1,11 @@
+pub mod coverage;
 pub mod metering;
+pub mod profiling;
+pub mod stack_limit;
 
 // The most commonly used symbol are exported at top level of the module. Others are available
 // via modules, e.g. `wasmer_middlewares::metering::get_remaining_points`
+pub use coverage::Coverage;
 pub use metering::Metering;
+pub use profiling::Profiling;
+pub use stack_limit::StackLimit;
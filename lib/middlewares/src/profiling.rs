@@ -0,0 +1,361 @@
+//! `profiling` is a middleware for finding out where the time of a module goes: it
+//! counts the invocations of every function, and the cost of the operators each of
+//! them executed.
+
+use loupe::{MemoryUsage, MemoryUsageTracker};
+use std::convert::TryInto;
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use wasmer::wasmparser::Operator;
+use wasmer::{
+    ExportIndex, FunctionMiddleware, GlobalInit, GlobalType, Instance, LocalFunctionIndex,
+    MiddlewareError, MiddlewareReaderState, ModuleMiddleware, Mutability, Type,
+};
+use wasmer_types::entity::EntityRef;
+use wasmer_types::{FunctionIndex, GlobalIndex};
+use wasmer_vm::ModuleInfo;
+
+/// The prefix of the exported globals counting the calls of each function.
+const CALLS_PREFIX: &str = "wasmer_profiling_calls_";
+/// The prefix of the exported globals accumulating the cost of each function.
+const COST_PREFIX: &str = "wasmer_profiling_cost_";
+
+#[derive(Clone, MemoryUsage)]
+struct ProfilingGlobalIndexes(GlobalIndex, GlobalIndex);
+
+impl ProfilingGlobalIndexes {
+    /// The global index in the current module for the number of calls of a function.
+    fn calls(&self) -> GlobalIndex {
+        self.0
+    }
+
+    /// The global index in the current module for the cost a function accumulated.
+    fn cost(&self) -> GlobalIndex {
+        self.1
+    }
+}
+
+impl fmt::Debug for ProfilingGlobalIndexes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProfilingGlobalIndexes")
+            .field("calls", &self.calls())
+            .field("cost", &self.cost())
+            .finish()
+    }
+}
+
+/// The module-level profiling middleware.
+///
+/// The cost of a function only covers its own operators, not the ones of the
+/// functions it calls.
+///
+/// # Panic
+///
+/// An instance of `Profiling` should not be shared among different modules, since it tracks
+/// module-specific information like the global indexes to store the counters. Attempts to use
+/// a `Profiling` instance from multiple modules will result in a panic.
+pub struct Profiling<F: Fn(&Operator) -> u64 + Send + Sync> {
+    /// Function that maps each operator to a cost in "points".
+    cost_function: Arc<F>,
+
+    /// The global indexes for the counters of each local function.
+    global_indexes: Mutex<Option<Vec<ProfilingGlobalIndexes>>>,
+}
+
+/// The function-level profiling middleware.
+pub struct FunctionProfiling<F: Fn(&Operator) -> u64 + Send + Sync> {
+    /// Function that maps each operator to a cost in "points".
+    cost_function: Arc<F>,
+
+    /// The global indexes for the counters of this function.
+    global_indexes: ProfilingGlobalIndexes,
+
+    /// Whether the call counter was incremented at the start of the body.
+    counted_call: bool,
+
+    /// Accumulated cost of the current basic block.
+    accumulated_cost: u64,
+}
+
+/// The counters of a function, as returned by [`get_function_profile`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+    /// The index of the function in the module.
+    pub index: FunctionIndex,
+    /// The name of the function in the name section of the module, if any.
+    pub name: Option<String>,
+    /// How many times the function was called.
+    pub calls: u64,
+    /// The cost of the operators the function executed.
+    pub cost: u64,
+}
+
+impl<F: Fn(&Operator) -> u64 + Send + Sync> Profiling<F> {
+    /// Creates a `Profiling` middleware.
+    pub fn new(cost_function: F) -> Self {
+        Self {
+            cost_function: Arc::new(cost_function),
+            global_indexes: Mutex::new(None),
+        }
+    }
+}
+
+impl<F: Fn(&Operator) -> u64 + Send + Sync> fmt::Debug for Profiling<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Profiling")
+            .field("cost_function", &"<function>")
+            .field("global_indexes", &self.global_indexes)
+            .finish()
+    }
+}
+
+impl<F: Fn(&Operator) -> u64 + Send + Sync + 'static> ModuleMiddleware for Profiling<F> {
+    /// Generates a `FunctionMiddleware` for a given function.
+    fn generate_function_middleware(
+        &self,
+        local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        let global_indexes = self.global_indexes.lock().unwrap();
+        Box::new(FunctionProfiling {
+            cost_function: self.cost_function.clone(),
+            global_indexes: global_indexes.as_ref().unwrap()[local_function_index.index()].clone(),
+            counted_call: false,
+            accumulated_cost: 0,
+        })
+    }
+
+    /// Transforms a `ModuleInfo` struct in-place. This is called before application on functions begins.
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        let mut global_indexes = self.global_indexes.lock().unwrap();
+
+        if global_indexes.is_some() {
+            panic!("Profiling::transform_module_info: Attempting to use a `Profiling` middleware from multiple modules.");
+        }
+
+        // Append two globals for every local function and export them, so the counters
+        // can be read from an instance.
+        let local_functions = module_info.functions.len() - module_info.num_imported_functions;
+        let indexes = (0..local_functions)
+            .map(|local_index| {
+                let function_index = module_info.func_index(LocalFunctionIndex::new(local_index));
+                let mut counter = |prefix: &str| {
+                    let global_index = module_info
+                        .globals
+                        .push(GlobalType::new(Type::I64, Mutability::Var));
+                    module_info
+                        .global_initializers
+                        .push(GlobalInit::I64Const(0));
+                    module_info.exports.insert(
+                        format!("{}{}", prefix, function_index.as_u32()),
+                        ExportIndex::Global(global_index),
+                    );
+                    global_index
+                };
+                ProfilingGlobalIndexes(counter(CALLS_PREFIX), counter(COST_PREFIX))
+            })
+            .collect();
+
+        *global_indexes = Some(indexes);
+    }
+}
+
+impl<F: Fn(&Operator) -> u64 + Send + Sync + 'static> MemoryUsage for Profiling<F> {
+    fn size_of_val(&self, tracker: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self) + self.global_indexes.size_of_val(tracker)
+            - mem::size_of_val(&self.global_indexes)
+    }
+}
+
+impl<F: Fn(&Operator) -> u64 + Send + Sync> fmt::Debug for FunctionProfiling<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FunctionProfiling")
+            .field("cost_function", &"<function>")
+            .field("global_indexes", &self.global_indexes)
+            .finish()
+    }
+}
+
+impl<F: Fn(&Operator) -> u64 + Send + Sync> FunctionMiddleware for FunctionProfiling<F> {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        // The first operator starts the body, so the call is counted before it.
+        if !self.counted_call {
+            self.counted_call = true;
+            state.extend(&[
+                // globals[calls_index] += 1;
+                Operator::GlobalGet {
+                    global_index: self.global_indexes.calls().as_u32(),
+                },
+                Operator::I64Const { value: 1 },
+                Operator::I64Add,
+                Operator::GlobalSet {
+                    global_index: self.global_indexes.calls().as_u32(),
+                },
+            ]);
+        }
+
+        // Like in `Metering`, the cost is added to the accumulator before the operator
+        // can leave the basic block.
+        self.accumulated_cost += (self.cost_function)(&operator);
+
+        // Possible sources and targets of a branch. Add the cost of the previous basic block.
+        match operator {
+            Operator::Loop { .. } // loop headers are branch targets
+            | Operator::End // block ends are branch targets
+            | Operator::Else // "else" is the "end" of an if branch
+            | Operator::Br { .. } // branch source
+            | Operator::BrTable { .. } // branch source
+            | Operator::BrIf { .. } // branch source
+            | Operator::Call { .. } // function call - branch source
+            | Operator::CallIndirect { .. } // function call - branch source
+            | Operator::Return // end of function - branch source
+            | Operator::Unreachable // trap - the cost so far was still spent
+            => {
+                if self.accumulated_cost > 0 {
+                    state.extend(&[
+                        // globals[cost_index] += self.accumulated_cost;
+                        Operator::GlobalGet { global_index: self.global_indexes.cost().as_u32() },
+                        Operator::I64Const { value: self.accumulated_cost as i64 },
+                        Operator::I64Add,
+                        Operator::GlobalSet { global_index: self.global_indexes.cost().as_u32() },
+                    ]);
+
+                    self.accumulated_cost = 0;
+                }
+            }
+            _ => {}
+        }
+        state.push_operator(operator);
+
+        Ok(())
+    }
+}
+
+/// Get the counters of every function of an `Instance`, in the order of their indexes.
+/// Names are taken from the name section of the module.
+///
+/// This can be used in a headless engine after an ahead-of-time compilation
+/// as all required state lives in the instance.
+///
+/// Modules that weren't processed with the [`Profiling`] middleware at compile time
+/// have no counters, so their profile is empty.
+pub fn get_function_profile(instance: &Instance) -> Vec<FunctionProfile> {
+    let function_names = &instance.module().info().function_names;
+    let read = |name: String| -> u64 {
+        let value: i64 = instance
+            .exports
+            .get_global(&name)
+            .unwrap_or_else(|_| panic!("Can't get `{}` from Instance", name))
+            .get()
+            .try_into()
+            .unwrap_or_else(|_| panic!("`{}` from Instance has wrong type", name));
+        value as u64
+    };
+
+    let mut profile = instance
+        .exports
+        .iter()
+        .filter_map(|(name, _)| name.strip_prefix(CALLS_PREFIX)?.parse().ok())
+        .map(|index: u32| {
+            let index = FunctionIndex::from_u32(index);
+            FunctionProfile {
+                index,
+                name: function_names.get(&index).cloned(),
+                calls: read(format!("{}{}", CALLS_PREFIX, index.as_u32())),
+                cost: read(format!("{}{}", COST_PREFIX, index.as_u32())),
+            }
+        })
+        .collect::<Vec<_>>();
+    profile.sort_by_key(|function| function.index);
+    profile
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use wasmer::{imports, wat2wasm, CompilerConfig, Cranelift, Module, Store, JIT};
+
+    fn cost_function(operator: &Operator) -> u64 {
+        match operator {
+            Operator::LocalGet { .. } | Operator::I32Const { .. } => 1,
+            Operator::I32Add { .. } => 2,
+            _ => 0,
+        }
+    }
+
+    fn bytecode() -> Vec<u8> {
+        wat2wasm(
+            br#"
+            (module
+            (import "env" "nothing" (func))
+            (func $add_one (param $value i32) (result i32)
+                local.get $value
+                i32.const 1
+                i32.add)
+            (func $add_two (param $value i32) (result i32)
+                local.get $value
+                call $add_one
+                call $add_one)
+            (func (result i32)
+                i32.const 0)
+            (export "add_two" (func $add_two)))
+            "#,
+        )
+        .unwrap()
+        .into()
+    }
+
+    #[test]
+    fn get_function_profile_works() {
+        let profiling = Arc::new(Profiling::new(cost_function));
+        let mut compiler_config = Cranelift::default();
+        compiler_config.push_middleware(profiling);
+        let store = Store::new(&JIT::new(compiler_config).engine());
+        let module = Module::new(&store, bytecode()).unwrap();
+        let imports = imports! {
+            "env" => {
+                "nothing" => wasmer::Function::new_native(&store, || {}),
+            },
+        };
+        let instance = Instance::new(&module, &imports).unwrap();
+
+        let add_two = instance
+            .exports
+            .get_native_function::<i32, i32>("add_two")
+            .unwrap();
+        add_two.call(1).unwrap();
+        add_two.call(1).unwrap();
+
+        // The imported function has no counters, and the cost of `add_two` doesn't
+        // include the cost of the calls to `add_one`.
+        assert_eq!(
+            get_function_profile(&instance),
+            vec![
+                FunctionProfile {
+                    index: FunctionIndex::new(1),
+                    name: Some("add_one".to_string()),
+                    calls: 4,
+                    cost: 16,
+                },
+                FunctionProfile {
+                    index: FunctionIndex::new(2),
+                    name: Some("add_two".to_string()),
+                    calls: 2,
+                    cost: 2,
+                },
+                FunctionProfile {
+                    index: FunctionIndex::new(3),
+                    name: None,
+                    calls: 0,
+                    cost: 0,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,673 @@
+//! `stack_limit` is a middleware for limiting the depth of calls the same way on
+//! every compiler and platform, instead of relying on native stack overflows.
+
+use loupe::MemoryUsage;
+use std::convert::TryInto;
+use std::sync::{Arc, Mutex};
+use wasmer::wasmparser::{
+    BinaryReader, BinaryReaderError, Operator, Type as WpType, TypeOrFuncType as WpTypeOrFuncType,
+};
+use wasmer::{
+    ExportIndex, FunctionMiddleware, FunctionType, GlobalInit, GlobalType, Instance,
+    LocalFunctionIndex, MiddlewareError, MiddlewareReaderState, ModuleMiddleware, Mutability,
+    TrapCode, Type,
+};
+use wasmer_types::entity::{EntityRef, PrimaryMap};
+use wasmer_types::{FunctionIndex, GlobalIndex, SignatureIndex};
+use wasmer_vm::ModuleInfo;
+
+/// The name of the exported global holding the current depth.
+const DEPTH_EXPORT: &str = "wasmer_stack_limit_depth";
+
+/// What the function middlewares need to know about the module.
+#[derive(Debug, MemoryUsage)]
+struct StackLimitModule {
+    /// The global index in the current module for the depth.
+    depth: GlobalIndex,
+
+    /// The signatures of the module, for the arity of blocks and calls.
+    signatures: PrimaryMap<SignatureIndex, FunctionType>,
+
+    /// The signature of every function.
+    functions: PrimaryMap<FunctionIndex, SignatureIndex>,
+
+    /// The number of imported functions, which come first in `functions`.
+    num_imported_functions: usize,
+}
+
+impl StackLimitModule {
+    /// The number of parameters and results of a signature.
+    fn arity(&self, signature: SignatureIndex) -> (u64, u64) {
+        let signature = &self.signatures[signature];
+        (
+            signature.params().len() as u64,
+            signature.results().len() as u64,
+        )
+    }
+
+    /// The number of parameters and results of a block.
+    fn block_arity(&self, ty: WpTypeOrFuncType) -> (u64, u64) {
+        match ty {
+            WpTypeOrFuncType::Type(WpType::EmptyBlockType) => (0, 0),
+            WpTypeOrFuncType::Type(_) => (0, 1),
+            WpTypeOrFuncType::FuncType(index) => self.arity(SignatureIndex::from_u32(index)),
+        }
+    }
+}
+
+/// The module-level stack limit middleware.
+///
+/// Every call adds the weight of the frame of the called function to a depth,
+/// and traps with [`TrapCode::StackLimitExceeded`] when the depth exceeds the
+/// limit. The weight of a frame is 1, plus the number of parameters and locals
+/// of the function, plus an upper bound of the height of its operand stack, so
+/// it only depends on the bytecode of the module. The operand stack includes
+/// the operators added by the middlewares before this one in the chain.
+///
+/// The depth is stored in an exported `i64` global, see [`get_stack_depth`].
+///
+/// # Traps
+///
+/// The depth is reset to 0 when the limit is exceeded, as the trap unwinds all
+/// the frames. **Any other trap leaves the weights of the frames it unwinds in
+/// the depth**, which must then be reset with [`reset_stack_depth`] before
+/// calling into the instance again.
+///
+/// A trap only unwinds the frames up to the host function that called into the
+/// instance, though. **A host function that calls into the instance and
+/// catches a trap must restore the depth** it had before the call with
+/// [`set_stack_depth`], as the frames below it are still there and release
+/// their weights when they return.
+///
+/// # Panic
+///
+/// An instance of `StackLimit` should not be shared among different modules, since it tracks
+/// module-specific information like the global index to store the depth. Attempts to use
+/// a `StackLimit` instance from multiple modules will result in a panic.
+#[derive(Debug, MemoryUsage)]
+pub struct StackLimit {
+    /// The highest depth calls can reach.
+    limit: u64,
+
+    /// The module being compiled.
+    module: Mutex<Option<Arc<StackLimitModule>>>,
+}
+
+/// The function-level stack limit middleware.
+#[derive(Debug)]
+pub struct FunctionStackLimit {
+    /// The highest depth calls can reach.
+    limit: u64,
+
+    /// The module being compiled.
+    module: Arc<StackLimitModule>,
+
+    /// The signature of the function.
+    signature: SignatureIndex,
+
+    /// The number of declared locals, once the body started.
+    locals: Option<u64>,
+
+    /// The operand stack of the operators seen so far.
+    stack: OperandStack,
+}
+
+impl StackLimit {
+    /// Creates a `StackLimit` middleware.
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            module: Mutex::new(None),
+        }
+    }
+}
+
+impl ModuleMiddleware for StackLimit {
+    /// Generates a `FunctionMiddleware` for a given function.
+    fn generate_function_middleware(
+        &self,
+        local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
+        let module = self.module.lock().unwrap().clone().unwrap();
+        let function_index =
+            FunctionIndex::new(module.num_imported_functions + local_function_index.index());
+        let signature = module.functions[function_index];
+        let (_, results) = module.arity(signature);
+        Box::new(FunctionStackLimit {
+            limit: self.limit,
+            signature,
+            module,
+            locals: None,
+            stack: OperandStack::new(results),
+        })
+    }
+
+    /// Transforms a `ModuleInfo` struct in-place. This is called before application on functions begins.
+    fn transform_module_info(&self, module_info: &mut ModuleInfo) {
+        let mut module = self.module.lock().unwrap();
+
+        if module.is_some() {
+            panic!("StackLimit::transform_module_info: Attempting to use a `StackLimit` middleware from multiple modules.");
+        }
+
+        // Append a global for the depth and export it.
+        let depth = module_info
+            .globals
+            .push(GlobalType::new(Type::I64, Mutability::Var));
+        module_info
+            .global_initializers
+            .push(GlobalInit::I64Const(0));
+        module_info
+            .exports
+            .insert(DEPTH_EXPORT.to_string(), ExportIndex::Global(depth));
+
+        *module = Some(Arc::new(StackLimitModule {
+            depth,
+            signatures: module_info.signatures.clone(),
+            functions: module_info.functions.clone(),
+            num_imported_functions: module_info.num_imported_functions,
+        }));
+    }
+}
+
+impl FunctionStackLimit {
+    /// Pushes the operators that add the frame to the depth, and trap if it
+    /// exceeds the limit.
+    fn enter(&self, weight: u64, state: &mut MiddlewareReaderState) {
+        let depth = self.module.depth.as_u32();
+        state.extend(&[
+            // globals[depth] += weight;
+            Operator::GlobalGet {
+                global_index: depth,
+            },
+            Operator::I64Const {
+                value: weight as i64,
+            },
+            Operator::I64Add,
+            Operator::GlobalSet {
+                global_index: depth,
+            },
+            // if unsigned(globals[depth]) > unsigned(self.limit) { globals[depth] = 0; throw(); }
+            Operator::GlobalGet {
+                global_index: depth,
+            },
+            Operator::I64Const {
+                value: self.limit as i64,
+            },
+            Operator::I64GtU,
+            Operator::If {
+                ty: WpTypeOrFuncType::Type(WpType::EmptyBlockType),
+            },
+            Operator::I64Const { value: 0 },
+            Operator::GlobalSet {
+                global_index: depth,
+            },
+        ]);
+        state.push_trap(TrapCode::StackLimitExceeded);
+        state.push_operator(Operator::End);
+    }
+
+    /// Pushes the operators that take the frame off the depth.
+    fn release(&self, weight: u64, state: &mut MiddlewareReaderState) {
+        let depth = self.module.depth.as_u32();
+        state.extend(&[
+            // globals[depth] -= weight;
+            Operator::GlobalGet {
+                global_index: depth,
+            },
+            Operator::I64Const {
+                value: weight as i64,
+            },
+            Operator::I64Sub,
+            Operator::GlobalSet {
+                global_index: depth,
+            },
+        ]);
+    }
+}
+
+impl FunctionMiddleware for FunctionStackLimit {
+    fn feed<'a>(
+        &mut self,
+        operator: Operator<'a>,
+        state: &mut MiddlewareReaderState<'a>,
+    ) -> Result<(), MiddlewareError> {
+        // The weight of the frame is only known at the end of the body, so the
+        // body is held back until then.
+        let locals = match self.locals {
+            Some(locals) => locals,
+            None => {
+                let locals = count_locals(state.function_body())
+                    .map_err(|e| MiddlewareError::new("stack_limit", e.to_string()))?;
+                self.locals = Some(locals);
+                state.hold_operators();
+                locals
+            }
+        };
+
+        let open_blocks = self.stack.open_blocks();
+        self.stack.feed(&operator, &self.module);
+        match operator {
+            Operator::Return => {
+                // Returns branch to the block wrapping the body instead, where
+                // the frame is released.
+                state.push_operator(Operator::Br {
+                    relative_depth: open_blocks as u32 - 1,
+                });
+            }
+            Operator::End if self.stack.open_blocks() == 0 => {
+                let (params, _) = self.module.arity(self.signature);
+                let weight = 1 + params + locals + self.stack.highest;
+                self.enter(weight, state);
+
+                // The body is wrapped in a block, so the branches out of it land
+                // before the final `End`, where the frame is released. The block
+                // has the type of the function to have its results, so it takes
+                // the parameters, which are dropped right away.
+                let params = params as u32;
+                state.extend((0..params).map(|local_index| Operator::LocalGet { local_index }));
+                state.push_operator(Operator::Block {
+                    ty: WpTypeOrFuncType::FuncType(self.signature.as_u32()),
+                });
+                state.extend((0..params).map(|_| Operator::Drop));
+                state.release_operators();
+                state.push_operator(Operator::End);
+
+                self.release(weight, state);
+                state.push_operator(operator);
+            }
+            operator => state.push_operator(operator),
+        }
+
+        Ok(())
+    }
+}
+
+/// Counts the locals declared at the start of a function body.
+fn count_locals(mut body: BinaryReader) -> Result<u64, BinaryReaderError> {
+    let mut locals = 0;
+    for _ in 0..body.read_var_u32()? {
+        locals += u64::from(body.read_var_u32()?);
+        body.read_type()?;
+    }
+    Ok(locals)
+}
+
+/// A block of a function body, while following its operand stack.
+#[derive(Debug, Clone, Copy)]
+struct Block {
+    /// The height of the operand stack below the block.
+    base: u64,
+    params: u64,
+    results: u64,
+}
+
+/// Follows the height of the operand stack of a function body, operator by
+/// operator, to find an upper bound of its highest point.
+#[derive(Debug)]
+struct OperandStack {
+    /// The blocks open at the current operator, starting with the body.
+    blocks: Vec<Block>,
+    height: u64,
+    highest: u64,
+}
+
+impl OperandStack {
+    fn new(results: u64) -> Self {
+        Self {
+            blocks: vec![Block {
+                base: 0,
+                params: 0,
+                results,
+            }],
+            height: 0,
+            highest: 0,
+        }
+    }
+
+    /// The number of blocks open, including the body until its final `End`.
+    fn open_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    fn feed(&mut self, operator: &Operator, module: &StackLimitModule) {
+        let block = match self.blocks.last() {
+            Some(&block) => block,
+            None => return,
+        };
+        // The operands of unreachable code may come from below the block, so
+        // the height is kept at or above its base.
+        let pop = |height: u64, n: u64| height.saturating_sub(n).max(block.base);
+        let height = self.height;
+        self.height = match *operator {
+            Operator::Block { ty } | Operator::Loop { ty } => {
+                let (params, results) = module.block_arity(ty);
+                self.blocks.push(Block {
+                    base: pop(height, params),
+                    params,
+                    results,
+                });
+                height
+            }
+            Operator::If { ty } => {
+                let height = pop(height, 1);
+                let (params, results) = module.block_arity(ty);
+                self.blocks.push(Block {
+                    base: pop(height, params),
+                    params,
+                    results,
+                });
+                height
+            }
+            Operator::Else => block.base + block.params,
+            Operator::End => {
+                self.blocks.pop();
+                block.base + block.results
+            }
+            Operator::Br { .. }
+            | Operator::BrTable { .. }
+            | Operator::Return
+            | Operator::ReturnCall { .. }
+            | Operator::ReturnCallIndirect { .. }
+            | Operator::Unreachable => block.base,
+            Operator::Call { function_index } => {
+                let (params, results) =
+                    module.arity(module.functions[FunctionIndex::from_u32(function_index)]);
+                pop(height, params) + results
+            }
+            Operator::CallIndirect { index, .. } => {
+                let (params, results) = module.arity(SignatureIndex::from_u32(index));
+                pop(height, params + 1) + results
+            }
+            ref operator => {
+                let (pops, pushes) = stack_effect(operator);
+                pop(height, pops) + pushes
+            }
+        };
+        self.highest = self.highest.max(self.height);
+    }
+}
+
+/// The number of operands an operator pops and pushes, for the operators that
+/// don't deal with blocks or calls.
+///
+/// The operators that aren't listed are counted as replacing their operands
+/// with a single result, which is an upper bound of the height for all of
+/// them.
+fn stack_effect(operator: &Operator) -> (u64, u64) {
+    match operator {
+        Operator::I32Const { .. }
+        | Operator::I64Const { .. }
+        | Operator::F32Const { .. }
+        | Operator::F64Const { .. }
+        | Operator::V128Const { .. }
+        | Operator::LocalGet { .. }
+        | Operator::GlobalGet { .. }
+        | Operator::MemorySize { .. }
+        | Operator::TableSize { .. }
+        | Operator::RefNull { .. }
+        | Operator::RefFunc { .. } => (0, 1),
+
+        Operator::Drop
+        | Operator::LocalSet { .. }
+        | Operator::GlobalSet { .. }
+        | Operator::BrIf { .. } => (1, 0),
+
+        Operator::I32Store { .. }
+        | Operator::I64Store { .. }
+        | Operator::F32Store { .. }
+        | Operator::F64Store { .. }
+        | Operator::I32Store8 { .. }
+        | Operator::I32Store16 { .. }
+        | Operator::I64Store8 { .. }
+        | Operator::I64Store16 { .. }
+        | Operator::I64Store32 { .. }
+        | Operator::V128Store { .. }
+        | Operator::TableSet { .. } => (2, 0),
+
+        Operator::MemoryCopy { .. }
+        | Operator::MemoryFill { .. }
+        | Operator::MemoryInit { .. }
+        | Operator::TableCopy { .. }
+        | Operator::TableFill { .. }
+        | Operator::TableInit { .. } => (3, 0),
+
+        Operator::Select | Operator::TypedSelect { .. } => (3, 1),
+
+        Operator::I32Eq
+        | Operator::I32Ne
+        | Operator::I32LtS
+        | Operator::I32LtU
+        | Operator::I32GtS
+        | Operator::I32GtU
+        | Operator::I32LeS
+        | Operator::I32LeU
+        | Operator::I32GeS
+        | Operator::I32GeU
+        | Operator::I64Eq
+        | Operator::I64Ne
+        | Operator::I64LtS
+        | Operator::I64LtU
+        | Operator::I64GtS
+        | Operator::I64GtU
+        | Operator::I64LeS
+        | Operator::I64LeU
+        | Operator::I64GeS
+        | Operator::I64GeU
+        | Operator::F32Eq
+        | Operator::F32Ne
+        | Operator::F32Lt
+        | Operator::F32Gt
+        | Operator::F32Le
+        | Operator::F32Ge
+        | Operator::F64Eq
+        | Operator::F64Ne
+        | Operator::F64Lt
+        | Operator::F64Gt
+        | Operator::F64Le
+        | Operator::F64Ge
+        | Operator::I32Add
+        | Operator::I32Sub
+        | Operator::I32Mul
+        | Operator::I32DivS
+        | Operator::I32DivU
+        | Operator::I32RemS
+        | Operator::I32RemU
+        | Operator::I32And
+        | Operator::I32Or
+        | Operator::I32Xor
+        | Operator::I32Shl
+        | Operator::I32ShrS
+        | Operator::I32ShrU
+        | Operator::I32Rotl
+        | Operator::I32Rotr
+        | Operator::I64Add
+        | Operator::I64Sub
+        | Operator::I64Mul
+        | Operator::I64DivS
+        | Operator::I64DivU
+        | Operator::I64RemS
+        | Operator::I64RemU
+        | Operator::I64And
+        | Operator::I64Or
+        | Operator::I64Xor
+        | Operator::I64Shl
+        | Operator::I64ShrS
+        | Operator::I64ShrU
+        | Operator::I64Rotl
+        | Operator::I64Rotr
+        | Operator::F32Add
+        | Operator::F32Sub
+        | Operator::F32Mul
+        | Operator::F32Div
+        | Operator::F32Min
+        | Operator::F32Max
+        | Operator::F32Copysign
+        | Operator::F64Add
+        | Operator::F64Sub
+        | Operator::F64Mul
+        | Operator::F64Div
+        | Operator::F64Min
+        | Operator::F64Max
+        | Operator::F64Copysign
+        | Operator::TableGrow { .. } => (2, 1),
+
+        _ => (0, 0),
+    }
+}
+
+/// Get the current depth of an `Instance`.
+///
+/// This can be used in a headless engine after an ahead-of-time compilation
+/// as all required state lives in the instance.
+///
+/// # Panic
+///
+/// The instance Module must have been processed with the [`StackLimit`] middleware
+/// at compile time, otherwise this will panic.
+pub fn get_stack_depth(instance: &Instance) -> u64 {
+    let depth: i64 = instance
+        .exports
+        .get_global(DEPTH_EXPORT)
+        .expect("Can't get `wasmer_stack_limit_depth` from Instance")
+        .get()
+        .try_into()
+        .expect("`wasmer_stack_limit_depth` from Instance has wrong type");
+    depth as u64
+}
+
+/// Set the depth of an `Instance`, for example to restore it after a host
+/// function catches a trap of a call into the instance.
+///
+/// # Panic
+///
+/// The instance Module must have been processed with the [`StackLimit`] middleware
+/// at compile time, otherwise this will panic.
+pub fn set_stack_depth(instance: &Instance, depth: u64) {
+    instance
+        .exports
+        .get_global(DEPTH_EXPORT)
+        .expect("Can't get `wasmer_stack_limit_depth` from Instance")
+        .set((depth as i64).into())
+        .expect("Can't set `wasmer_stack_limit_depth` in Instance");
+}
+
+/// Reset the depth of an `Instance` after a trap, which leaves the weights of
+/// the frames it unwound in it.
+///
+/// # Panic
+///
+/// The instance Module must have been processed with the [`StackLimit`] middleware
+/// at compile time, otherwise this will panic.
+pub fn reset_stack_depth(instance: &Instance) {
+    set_stack_depth(instance, 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Metering;
+    use wasmer::{imports, wat2wasm, CompilerConfig, Cranelift, Module, Store, JIT};
+
+    fn bytecode() -> Vec<u8> {
+        wat2wasm(
+            br#"
+            (module
+            (func $count (export "count") (param $n i32) (result i32)
+                local.get $n
+                i32.eqz
+                if (result i32)
+                    i32.const 0
+                else
+                    local.get $n
+                    i32.const 1
+                    i32.sub
+                    call $count
+                    i32.const 1
+                    i32.add
+                end)
+            (func (export "leave_by_branch") (result i32)
+                (local i64 i64)
+                i32.const 7
+                br 0)
+            (func (export "leave_by_return") (param i32) (result i32)
+                local.get 0
+                return))
+            "#,
+        )
+        .unwrap()
+        .into()
+    }
+
+    fn instance(limit: u64) -> Instance {
+        instance_with_middlewares(vec![Arc::new(StackLimit::new(limit))])
+    }
+
+    fn instance_with_middlewares(middlewares: Vec<Arc<dyn ModuleMiddleware>>) -> Instance {
+        let mut compiler_config = Cranelift::default();
+        for middleware in middlewares {
+            compiler_config.push_middleware(middleware);
+        }
+        let store = Store::new(&JIT::new(compiler_config).engine());
+        let module = Module::new(&store, bytecode()).unwrap();
+        Instance::new(&module, &imports! {}).unwrap()
+    }
+
+    #[test]
+    fn traps_when_the_limit_is_exceeded() {
+        // A frame of `count` weighs 1, plus 1 parameter, plus 2 operands.
+        let instance = instance(40);
+        let count = instance
+            .exports
+            .get_native_function::<i32, i32>("count")
+            .unwrap();
+
+        // 10 frames fit in the limit, 11 don't.
+        assert_eq!(count.call(9).unwrap(), 9);
+        assert_eq!(get_stack_depth(&instance), 0);
+        let error = count.call(10).unwrap_err();
+        assert_eq!(error.to_trap(), Some(TrapCode::StackLimitExceeded));
+
+        // The trap resets the depth.
+        assert_eq!(get_stack_depth(&instance), 0);
+        assert_eq!(count.call(9).unwrap(), 9);
+        assert_eq!(get_stack_depth(&instance), 0);
+    }
+
+    #[test]
+    fn weighs_the_operators_of_previous_middlewares() {
+        // `Metering` checks the remaining points with 2 more operands on the
+        // stack, so a frame of `count` weighs 1 more.
+        let instance = instance_with_middlewares(vec![
+            Arc::new(Metering::new(u64::MAX, |_: &Operator| 1)),
+            Arc::new(StackLimit::new(50)),
+        ]);
+        let count = instance
+            .exports
+            .get_native_function::<i32, i32>("count")
+            .unwrap();
+
+        assert_eq!(count.call(9).unwrap(), 9);
+        let error = count.call(10).unwrap_err();
+        assert_eq!(error.to_trap(), Some(TrapCode::StackLimitExceeded));
+    }
+
+    #[test]
+    fn releases_the_frame_on_every_exit() {
+        let instance = instance(100);
+        let leave_by_branch = instance
+            .exports
+            .get_native_function::<(), i32>("leave_by_branch")
+            .unwrap();
+        let leave_by_return = instance
+            .exports
+            .get_native_function::<i32, i32>("leave_by_return")
+            .unwrap();
+
+        assert_eq!(leave_by_branch.call().unwrap(), 7);
+        assert_eq!(get_stack_depth(&instance), 0);
+        assert_eq!(leave_by_return.call(3).unwrap(), 3);
+        assert_eq!(get_stack_depth(&instance), 0);
+    }
+}
@@ -41,7 +41,13 @@ pub mod lib {
     #[cfg(feature = "core")]
     pub mod std {
         pub use alloc::{borrow, boxed, format, iter, rc, slice, string, vec};
-        pub use core::{any, cell, cmp, convert, fmt, hash, marker, mem, ops, ptr, sync, u32};
+        pub use core::{any, cell, cmp, convert, fmt, hash, marker, mem, ops, ptr, u32};
+
+        /// Synchronization primitives, from both `core` and `alloc`.
+        pub mod sync {
+            pub use alloc::sync::*;
+            pub use core::sync::*;
+        }
     }
 
     /// Custom `std` module.
@@ -77,7 +83,7 @@ pub use crate::initializers::{
 };
 pub use crate::memory_view::{Atomically, MemoryView};
 pub use crate::native::{NativeWasmType, ValueType};
-pub use crate::r#ref::{ExternRef, HostInfo, HostRef};
+pub use crate::r#ref::{ExternRef, HostInfo};
 pub use crate::units::{
     Bytes, PageCountOutOfRange, Pages, WASM_MAX_PAGES, WASM_MIN_PAGES, WASM_PAGE_SIZE,
 };
@@ -1,45 +1,22 @@
-#![allow(missing_docs)]
-
 use crate::lib::std::any::Any;
 use crate::lib::std::boxed::Box;
-use crate::lib::std::cell::{self, RefCell};
 use crate::lib::std::fmt;
 use crate::lib::std::hash;
-use crate::lib::std::rc::{Rc, Weak};
+use crate::lib::std::sync::Arc;
 
-pub trait HostInfo {
+/// Information attached by the host to an [`ExternRef`].
+pub trait HostInfo: Send + Sync {
+    /// Called exactly once, when the last reference to the data, held either
+    /// by the host or by WebAssembly, is dropped.
     fn finalize(&mut self) {}
 }
 
-trait InternalRefBase: Any {
-    fn as_any(&self) -> &dyn Any;
-    fn host_info(&self) -> Option<cell::RefMut<Box<dyn HostInfo>>>;
-    fn set_host_info(&self, info: Option<Box<dyn HostInfo>>);
-    fn ptr_eq(&self, other: &dyn InternalRefBase) -> bool;
-}
-
-#[derive(Clone)]
-pub struct InternalRef(Rc<dyn InternalRefBase>);
-
-impl InternalRef {
-    pub fn is_ref<T: 'static>(&self) -> bool {
-        let r = self.0.as_any();
-        Any::is::<HostRef<T>>(r)
-    }
-    pub fn get_ref<T: 'static>(&self) -> HostRef<T> {
-        let r = self.0.as_any();
-        r.downcast_ref::<HostRef<T>>()
-            .expect("reference is not T type")
-            .clone()
-    }
-}
-
-struct AnyAndHostInfo {
-    any: Box<dyn Any>,
+struct ExternRefData {
+    value: Box<dyn Any + Send + Sync>,
     host_info: Option<Box<dyn HostInfo>>,
 }
 
-impl Drop for AnyAndHostInfo {
+impl Drop for ExternRefData {
     fn drop(&mut self) {
         if let Some(info) = &mut self.host_info {
             info.finalize();
@@ -47,16 +24,18 @@ impl Drop for AnyAndHostInfo {
     }
 }
 
+/// The data of a non-null [`ExternRef`].
 #[derive(Clone)]
-pub struct OtherRef(Rc<RefCell<AnyAndHostInfo>>);
+pub struct OtherRef(Arc<ExternRefData>);
 
 /// Represents an opaque reference to any data within WebAssembly.
+///
+/// The data is reference-counted atomically, so an `ExternRef` can be shared
+/// between threads and passed in and out of WebAssembly freely.
 #[derive(Clone)]
 pub enum ExternRef {
     /// A reference to no data.
     Null,
-    /// A reference to data stored internally.
-    Ref(InternalRef),
     /// A reference to data located outside.
     Other(OtherRef),
 }
@@ -74,13 +53,22 @@ impl PartialEq for ExternRef {
 impl Eq for ExternRef {}
 
 impl ExternRef {
-    /// Creates a new instance of `ExternRef` from `Box<dyn Any>`.
-    pub fn new(data: Box<dyn Any>) -> Self {
-        let info = AnyAndHostInfo {
-            any: data,
-            host_info: None,
+    /// Creates a new `ExternRef` holding `value`.
+    pub fn new<T: Any + Send + Sync>(value: T) -> Self {
+        Self::new_with_host_info(value, None)
+    }
+
+    /// Creates a new `ExternRef` holding `value`, with some host information
+    /// that is finalized when the last reference to `value` is dropped.
+    pub fn new_with_host_info<T: Any + Send + Sync>(
+        value: T,
+        host_info: Option<Box<dyn HostInfo>>,
+    ) -> Self {
+        let data = ExternRefData {
+            value: Box::new(value),
+            host_info,
         };
-        Self::Other(OtherRef(Rc::new(RefCell::new(info))))
+        Self::Other(OtherRef(Arc::new(data)))
     }
 
     /// Creates a `Null` reference.
@@ -88,60 +76,40 @@ impl ExternRef {
         Self::Null
     }
 
-    /// Returns the data stored in the reference if available.
+    /// Returns true if this is a `Null` reference.
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
+
+    /// Returns the data stored in the reference.
     ///
     /// # Panics
     ///
-    /// Panics if the variant isn't `ExternRef::Other`.
-    pub fn data(&self) -> cell::Ref<Box<dyn Any>> {
+    /// Panics if the reference is `Null`.
+    pub fn data(&self) -> &(dyn Any + Send + Sync) {
         match self {
-            Self::Other(OtherRef(r)) => cell::Ref::map(r.borrow(), |r| &r.any),
-            _ => panic!("expected ExternRef::Other"),
-        }
-    }
-
-    /// Returns true if the two `ExternRef<T>`'s point to the same value (not just
-    /// values that compare as equal).
-    pub fn ptr_eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Self::Null, Self::Null) => true,
-            (Self::Ref(InternalRef(ref a)), Self::Ref(InternalRef(ref b))) => a.ptr_eq(b.as_ref()),
-            (Self::Other(OtherRef(ref a)), Self::Other(OtherRef(ref b))) => Rc::ptr_eq(a, b),
-            _ => false,
+            Self::Other(OtherRef(r)) => &*r.value,
+            Self::Null => panic!("expected ExternRef::Other"),
         }
     }
 
-    /// Returns a mutable reference to the host information if available.
-    ///
-    /// # Panics
+    /// Returns the data stored in the reference if it is of type `T`.
     ///
-    /// Panics if `ExternRef` is already borrowed or `ExternRef` is `Null`.
-    pub fn host_info(&self) -> Option<cell::RefMut<Box<dyn HostInfo>>> {
+    /// Returns `None` if the reference is `Null` or holds another type.
+    pub fn downcast<T: Any>(&self) -> Option<&T> {
         match self {
-            Self::Null => panic!("null"),
-            Self::Ref(r) => r.0.host_info(),
-            Self::Other(r) => {
-                let info = cell::RefMut::map(r.0.borrow_mut(), |b| &mut b.host_info);
-                if info.is_none() {
-                    return None;
-                }
-                Some(cell::RefMut::map(info, |info| info.as_mut().unwrap()))
-            }
+            Self::Other(OtherRef(r)) => r.value.downcast_ref(),
+            Self::Null => None,
         }
     }
 
-    /// Sets the host information for an `ExternRef`.
-    ///
-    /// # Panics
-    ///
-    /// Panics if `ExternRef` is already borrowed or `ExternRef` is `Null`.
-    pub fn set_host_info(&self, info: Option<Box<dyn HostInfo>>) {
-        match self {
-            Self::Null => panic!("null"),
-            Self::Ref(r) => r.0.set_host_info(info),
-            Self::Other(r) => {
-                r.0.borrow_mut().host_info = info;
-            }
+    /// Returns true if the two `ExternRef`'s point to the same value (not just
+    /// values that compare as equal).
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Null, Self::Null) => true,
+            (Self::Other(OtherRef(ref a)), Self::Other(OtherRef(ref b))) => Arc::ptr_eq(a, b),
+            _ => false,
         }
     }
 }
@@ -150,118 +118,47 @@ impl fmt::Debug for ExternRef {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Null => write!(f, "null"),
-            Self::Ref(_) => write!(f, "externref"),
             Self::Other(_) => write!(f, "other ref"),
         }
     }
 }
 
-struct ContentBox<T> {
-    content: T,
-    host_info: Option<Box<dyn HostInfo>>,
-    externref_data: Weak<dyn InternalRefBase>,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::std::sync::atomic::{AtomicUsize, Ordering};
 
-impl<T> Drop for ContentBox<T> {
-    fn drop(&mut self) {
-        if let Some(info) = &mut self.host_info {
-            info.finalize();
-        }
-    }
-}
-
-/// Represents a piece of data located in the host environment.
-pub struct HostRef<T>(Rc<RefCell<ContentBox<T>>>);
-
-impl<T: 'static> HostRef<T> {
-    /// Creates a new `HostRef<T>` from `T`.
-    pub fn new(item: T) -> Self {
-        let externref_data: Weak<Self> = Weak::new();
-        let content = ContentBox {
-            content: item,
-            host_info: None,
-            externref_data,
-        };
-        Self(Rc::new(RefCell::new(content)))
-    }
-
-    /// Immutably borrows the wrapped data.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the value is currently mutably borrowed.
-    pub fn borrow(&self) -> cell::Ref<T> {
-        cell::Ref::map(self.0.borrow(), |b| &b.content)
-    }
+    struct CountFinalize(Arc<AtomicUsize>);
 
-    /// Mutably borrows the wrapped data.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the `HostRef<T>` is already borrowed.
-    pub fn borrow_mut(&self) -> cell::RefMut<T> {
-        cell::RefMut::map(self.0.borrow_mut(), |b| &mut b.content)
-    }
-
-    /// Returns true if the two `HostRef<T>`'s point to the same value (not just
-    /// values that compare as equal).
-    pub fn ptr_eq(&self, other: &Self) -> bool {
-        Rc::ptr_eq(&self.0, &other.0)
-    }
-
-    /// Returns an opaque reference to the wrapped data in the form of
-    /// an `ExternRef`.
-    ///
-    /// # Panics
-    ///
-    /// Panics if `HostRef<T>` is already mutably borrowed.
-    pub fn externref(&self) -> ExternRef {
-        let r = self.0.borrow_mut().externref_data.upgrade();
-        if let Some(r) = r {
-            return ExternRef::Ref(InternalRef(r));
+    impl HostInfo for CountFinalize {
+        fn finalize(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
         }
-        let externref_data: Rc<dyn InternalRefBase> = Rc::new(self.clone());
-        self.0.borrow_mut().externref_data = Rc::downgrade(&externref_data);
-        ExternRef::Ref(InternalRef(externref_data))
     }
-}
 
-impl<T: 'static> InternalRefBase for HostRef<T> {
-    fn ptr_eq(&self, other: &dyn InternalRefBase) -> bool {
-        if let Some(other) = other.as_any().downcast_ref() {
-            self.ptr_eq(other)
-        } else {
-            false
-        }
+    #[test]
+    fn externref_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ExternRef>();
     }
 
-    fn as_any(&self) -> &dyn Any {
-        self
+    #[test]
+    fn downcast() {
+        let r = ExternRef::new(42u32);
+        assert_eq!(r.downcast::<u32>(), Some(&42));
+        assert_eq!(r.downcast::<u64>(), None);
+        assert_eq!(ExternRef::null().downcast::<u32>(), None);
     }
 
-    fn host_info(&self) -> Option<cell::RefMut<Box<dyn HostInfo>>> {
-        let info = cell::RefMut::map(self.0.borrow_mut(), |b| &mut b.host_info);
-        if info.is_none() {
-            return None;
-        }
-        Some(cell::RefMut::map(info, |info| info.as_mut().unwrap()))
-    }
-
-    fn set_host_info(&self, info: Option<Box<dyn HostInfo>>) {
-        self.0.borrow_mut().host_info = info;
-    }
-}
-
-impl<T> Clone for HostRef<T> {
-    fn clone(&self) -> Self {
-        Self(self.0.clone())
-    }
-}
-
-impl<T: fmt::Debug> fmt::Debug for HostRef<T> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Ref(")?;
-        self.0.borrow().content.fmt(f)?;
-        write!(f, ")")
+    #[test]
+    fn finalize_runs_once_on_last_drop() {
+        let finalized = Arc::new(AtomicUsize::new(0));
+        let r =
+            ExternRef::new_with_host_info("data", Some(Box::new(CountFinalize(finalized.clone()))));
+        let r2 = r.clone();
+        drop(r);
+        assert_eq!(finalized.load(Ordering::SeqCst), 0);
+        drop(r2);
+        assert_eq!(finalized.load(Ordering::SeqCst), 1);
     }
 }
@@ -1,7 +1,8 @@
-use crate::vmcontext::VMGlobalDefinition;
+use crate::reference::{VMExternRef, VMRawReference, VMReference};
+use crate::vmcontext::{VMCallerCheckedAnyfunc, VMGlobalDefinition};
 use loupe::MemoryUsage;
 use std::cell::UnsafeCell;
-use std::ptr::NonNull;
+use std::ptr::{self, NonNull};
 use std::sync::Mutex;
 use thiserror::Error;
 use wasmer_types::{GlobalType, Mutability, Type, Value};
@@ -117,4 +118,80 @@ impl Global {
         }
         Ok(())
     }
+
+    /// Get the reference held by a global of type `funcref` or `externref`.
+    pub fn get_ref(&self) -> VMReference {
+        let _global_guard = self.lock.lock().unwrap();
+        unsafe { VMReference::from_raw(self.ty.ty, self.raw_ref()) }
+    }
+
+    /// Set the reference held by a global of type `funcref` or `externref`.
+    ///
+    /// # Safety
+    /// The caller should check that the `val` comes from the same store as this global.
+    pub unsafe fn set_ref(&self, val: VMReference) -> Result<(), GlobalError> {
+        let _global_guard = self.lock.lock().unwrap();
+        if self.ty().mutability != Mutability::Var {
+            return Err(GlobalError::ImmutableGlobalCannotBeSet);
+        }
+        let found = match val {
+            VMReference::FuncRef(_) => Type::FuncRef,
+            VMReference::ExternRef(_) => Type::ExternRef,
+        };
+        if found != self.ty().ty {
+            return Err(GlobalError::IncorrectType {
+                expected: self.ty.ty,
+                found,
+            });
+        }
+        self.set_ref_unchecked(val);
+        Ok(())
+    }
+
+    /// Set the reference held by a global of type `funcref` or `externref`
+    /// (unchecked).
+    ///
+    /// # Safety
+    /// The caller should check that the `val` comes from the same store as this global,
+    /// and that it has the type of this global.
+    /// The caller should also ensure that this global is synchronized. Otherwise, use
+    /// `set_ref` instead.
+    pub unsafe fn set_ref_unchecked(&self, val: VMReference) {
+        // A `funcref` global owns a copy of the function's anyfunc, and an
+        // `externref` global owns a count of its reference.
+        let raw = match val {
+            VMReference::FuncRef(anyfunc) if anyfunc.func_ptr.is_null() => ptr::null_mut(),
+            VMReference::FuncRef(anyfunc) => Box::into_raw(Box::new(anyfunc)) as VMRawReference,
+            VMReference::ExternRef(r) => VMExternRef::into_raw(r),
+        };
+        let definition = &mut *self.vm_global_definition.get();
+        let old = definition.to_u64() as usize as VMRawReference;
+        *definition.as_u64_mut() = raw as usize as u64;
+        self.release_raw_ref(old);
+    }
+
+    /// The reference held by a global of reference type, in its raw form.
+    unsafe fn raw_ref(&self) -> VMRawReference {
+        let definition = &*self.vm_global_definition.get();
+        definition.to_u64() as usize as VMRawReference
+    }
+
+    /// Release a reference that was held by this global.
+    unsafe fn release_raw_ref(&self, raw: VMRawReference) {
+        match self.ty.ty {
+            Type::FuncRef if !raw.is_null() => {
+                drop(Box::from_raw(raw as *mut VMCallerCheckedAnyfunc));
+            }
+            Type::ExternRef => drop(VMExternRef::from_raw(raw)),
+            _ => {}
+        }
+    }
+}
+
+impl Drop for Global {
+    fn drop(&mut self) {
+        if self.ty.ty.is_ref() {
+            unsafe { self.release_raw_ref(self.raw_ref()) }
+        }
+    }
 }
@@ -1,8 +1,9 @@
 use super::{Instance, InstanceRef};
+use crate::pooling::InstanceSlot;
 use crate::vmcontext::{VMMemoryDefinition, VMTableDefinition};
 use crate::{ModuleInfo, VMOffsets};
 use std::alloc::{self, Layout};
-use std::convert::TryFrom;
+use std::convert::{Infallible, TryFrom};
 use std::mem;
 use std::ptr::{self, NonNull};
 use wasmer_types::entity::EntityRef;
@@ -33,6 +34,10 @@ pub struct InstanceAllocator {
     /// The layout of the `instance_ptr` buffer.
     instance_layout: Layout,
 
+    /// The pool slot holding the `instance_ptr` buffer, if it has
+    /// been allocated by a `PoolingAllocator`.
+    slot: Option<InstanceSlot>,
+
     /// Information about the offsets into the `instance_ptr` buffer for
     /// the dynamic fields.
     offsets: VMOffsets,
@@ -51,7 +56,10 @@ impl Drop for InstanceAllocator {
             let instance_ptr = self.instance_ptr.as_ptr();
 
             unsafe {
-                std::alloc::dealloc(instance_ptr as *mut u8, self.instance_layout);
+                match &self.slot {
+                    Some(slot) => slot.release(self.instance_layout),
+                    None => std::alloc::dealloc(instance_ptr as *mut u8, self.instance_layout),
+                }
             }
         }
     }
@@ -74,21 +82,43 @@ impl InstanceAllocator {
         Vec<NonNull<VMMemoryDefinition>>,
         Vec<NonNull<VMTableDefinition>>,
     ) {
+        let result: Result<_, Infallible> = Self::new_with(module, |instance_layout| {
+            #[allow(clippy::cast_ptr_alignment)]
+            let instance_ptr = unsafe { alloc::alloc(instance_layout) as *mut Instance };
+
+            if let Some(ptr) = NonNull::new(instance_ptr) {
+                Ok((ptr, None))
+            } else {
+                alloc::handle_alloc_error(instance_layout);
+            }
+        });
+        match result {
+            Ok(result) => result,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Like [`InstanceAllocator::new`], but allocates the buffer with
+    /// `allocate`, which is given the layout the buffer must have.
+    pub(crate) fn new_with<E>(
+        module: &ModuleInfo,
+        allocate: impl FnOnce(Layout) -> Result<(NonNull<Instance>, Option<InstanceSlot>), E>,
+    ) -> Result<
+        (
+            Self,
+            Vec<NonNull<VMMemoryDefinition>>,
+            Vec<NonNull<VMTableDefinition>>,
+        ),
+        E,
+    > {
         let offsets = VMOffsets::new(mem::size_of::<usize>() as u8, module);
         let instance_layout = Self::instance_layout(&offsets);
-
-        #[allow(clippy::cast_ptr_alignment)]
-        let instance_ptr = unsafe { alloc::alloc(instance_layout) as *mut Instance };
-
-        let instance_ptr = if let Some(ptr) = NonNull::new(instance_ptr) {
-            ptr
-        } else {
-            alloc::handle_alloc_error(instance_layout);
-        };
+        let (instance_ptr, slot) = allocate(instance_layout)?;
 
         let allocator = Self {
             instance_ptr,
             instance_layout,
+            slot,
             offsets,
             consumed: false,
         };
@@ -100,7 +130,7 @@ impl InstanceAllocator {
         let memories = unsafe { allocator.memory_definition_locations() };
         let tables = unsafe { allocator.table_definition_locations() };
 
-        (allocator, memories, tables)
+        Ok((allocator, memories, tables))
     }
 
     /// Calculate the appropriate layout for the [`Instance`].
@@ -201,10 +231,11 @@ impl InstanceAllocator {
         }
         let instance = self.instance_ptr;
         let instance_layout = self.instance_layout;
+        let slot = self.slot.take();
 
         // This is correct because of the invariants of `Self` and
         // because we write `Instance` to the pointer in this function.
-        unsafe { InstanceRef::new(instance, instance_layout) }
+        unsafe { InstanceRef::new(instance, instance_layout, slot) }
     }
 
     /// Get the [`VMOffsets`] for the allocated buffer.
@@ -17,13 +17,15 @@ use crate::export::VMExport;
 use crate::global::Global;
 use crate::imports::Imports;
 use crate::memory::{Memory, MemoryError};
+use crate::memory_image::MemoryImages;
+use crate::reference::{ReferenceScope, VMReference};
 use crate::table::Table;
 use crate::trap::{catch_traps, init_traps, Trap, TrapCode};
 use crate::vmcontext::{
     VMBuiltinFunctionsArray, VMCallerCheckedAnyfunc, VMContext, VMFunctionBody,
     VMFunctionEnvironment, VMFunctionImport, VMFunctionKind, VMGlobalDefinition, VMGlobalImport,
-    VMMemoryDefinition, VMMemoryImport, VMSharedSignatureIndex, VMTableDefinition, VMTableImport,
-    VMTrampoline,
+    VMInterrupts, VMMemoryDefinition, VMMemoryImport, VMSharedSignatureIndex, VMTableDefinition,
+    VMTableImport, VMTrampoline,
 };
 use crate::{FunctionBodyPtr, ModuleInfo, VMOffsets};
 use crate::{VMExportFunction, VMExportGlobal, VMExportMemory, VMExportTable};
@@ -32,6 +34,7 @@ use memoffset::offset_of;
 use more_asserts::assert_lt;
 use std::any::Any;
 use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
 use std::ffi;
 use std::fmt;
@@ -43,7 +46,7 @@ use wasmer_types::entity::{packed_option::ReservedValue, BoxedSlice, EntityRef,
 use wasmer_types::{
     DataIndex, DataInitializer, ElemIndex, ExportIndex, FunctionIndex, GlobalIndex, GlobalInit,
     LocalFunctionIndex, LocalGlobalIndex, LocalMemoryIndex, LocalTableIndex, MemoryIndex, Pages,
-    SignatureIndex, TableIndex, TableInitializer,
+    SignatureIndex, TableIndex, TableInitializer, Type,
 };
 
 /// The function pointer to call with data and an [`Instance`] pointer to
@@ -84,15 +87,18 @@ pub(crate) struct Instance {
 
     /// Passive elements in this instantiation. As `elem.drop`s happen, these
     /// entries get removed.
-    passive_elements: RefCell<PrimaryMap<ElemIndex, Option<Box<[VMCallerCheckedAnyfunc]>>>>,
+    passive_elements: RefCell<HashMap<ElemIndex, Box<[VMCallerCheckedAnyfunc]>>>,
 
     /// Passive data segments from our module. As `data.drop`s happen, entries
     /// get removed. A missing entry is considered equivalent to an empty slice.
-    passive_data: RefCell<PrimaryMap<DataIndex, Option<Arc<[u8]>>>>,
+    passive_data: RefCell<HashMap<DataIndex, Arc<[u8]>>>,
 
     /// Hosts can store arbitrary per-instance information here.
     host_state: Box<dyn Any>,
 
+    /// The interrupt flag checked by compiled code, shared with the store.
+    interrupts: Arc<VMInterrupts>,
+
     /// Handler run when `SIGBUS`, `SIGFPE`, `SIGILL`, or `SIGSEGV` are caught by the instance thread.
     #[loupe(skip)]
     pub(crate) signal_handler: Cell<Option<Box<SignalHandler>>>,
@@ -235,6 +241,16 @@ impl Instance {
         &self.offsets
     }
 
+    /// Return the interrupt flag shared with the store.
+    pub(crate) fn interrupts(&self) -> &VMInterrupts {
+        &self.interrupts
+    }
+
+    /// Return a pointer to the slot holding the `VMInterrupts` pointer.
+    fn interrupts_ptr(&self) -> *mut *const VMInterrupts {
+        unsafe { self.vmctx_plus_offset(self.offsets.vmctx_interrupts()) }
+    }
+
     /// Return a pointer to the `VMSharedSignatureIndex`s.
     fn signature_ids_ptr(&self) -> *mut VMSharedSignatureIndex {
         unsafe { self.vmctx_plus_offset(self.offsets.vmctx_signature_ids_begin()) }
@@ -426,6 +442,8 @@ impl Instance {
         };
 
         // Make the call.
+        let _scope = ReferenceScope::enter();
+        let _running = self.interrupts.enter();
         unsafe {
             catch_traps(callee_vmctx, || {
                 mem::transmute::<*const VMFunctionBody, unsafe extern "C" fn(VMFunctionEnvironment)>(
@@ -528,22 +546,23 @@ impl Instance {
     ///
     /// Returns `None` if table can't be grown by the specified amount
     /// of elements.
-    pub(crate) fn table_grow(&self, table_index: LocalTableIndex, delta: u32) -> Option<u32> {
+    pub(crate) fn table_grow(
+        &self,
+        table_index: LocalTableIndex,
+        delta: u32,
+        init_value: VMReference,
+    ) -> Option<u32> {
         let result = self
             .tables
             .get(table_index)
             .unwrap_or_else(|| panic!("no table for index {}", table_index.index()))
-            .grow(delta);
+            .grow(delta, init_value);
 
         result
     }
 
     /// Get table element by index.
-    fn table_get(
-        &self,
-        table_index: LocalTableIndex,
-        index: u32,
-    ) -> Option<VMCallerCheckedAnyfunc> {
+    fn table_get(&self, table_index: LocalTableIndex, index: u32) -> Option<VMReference> {
         self.tables
             .get(table_index)
             .unwrap_or_else(|| panic!("no table for index {}", table_index.index()))
@@ -555,7 +574,7 @@ impl Instance {
         &self,
         table_index: LocalTableIndex,
         index: u32,
-        val: VMCallerCheckedAnyfunc,
+        val: VMReference,
     ) -> Result<(), Trap> {
         self.tables
             .get(table_index)
@@ -609,11 +628,11 @@ impl Instance {
         // https://webassembly.github.io/bulk-memory-operations/core/exec/instructions.html#exec-table-init
 
         let table = self.get_table(table_index);
+        let ty = table.ty().ty;
         let passive_elements = self.passive_elements.borrow();
         let elem = passive_elements
-            .get(elem_index)
-            .and_then(|e| e.as_ref().map(|e| &**e))
-            .unwrap_or(&[]);
+            .get(&elem_index)
+            .map_or(&[][..], |elem| &**elem);
 
         if src
             .checked_add(len)
@@ -625,7 +644,7 @@ impl Instance {
 
         for (dst, src) in (dst..dst + len).zip(src..src + len) {
             table
-                .set(dst, elem[src as usize].clone())
+                .set(dst, table_element(ty, &elem[src as usize]))
                 .expect("should never panic because we already did the bounds check above");
         }
 
@@ -637,7 +656,7 @@ impl Instance {
         // https://webassembly.github.io/reference-types/core/exec/instructions.html#exec-elem-drop
 
         let mut passive_elements = self.passive_elements.borrow_mut();
-        passive_elements[elem_index] = None;
+        passive_elements.remove(&elem_index);
         // Note that we don't check that we actually removed an element because
         // dropping a non-passive element is a no-op (not a trap).
     }
@@ -731,9 +750,8 @@ impl Instance {
         let memory = self.get_memory(memory_index);
         let passive_data = self.passive_data.borrow();
         let data = passive_data
-            .get(data_index)
-            .and_then(|data| data.as_ref().map(|d| &**d))
-            .unwrap_or(&[][..]);
+            .get(&data_index)
+            .map_or(&[][..], |data| &**data);
 
         if src
             .checked_add(len)
@@ -759,7 +777,7 @@ impl Instance {
     /// Drop the given data segment, truncating its length to zero.
     pub(crate) fn data_drop(&self, data_index: DataIndex) {
         let mut passive_data = self.passive_data.borrow_mut();
-        passive_data[data_index] = None;
+        passive_data.remove(&data_index);
     }
 
     /// Get a table by index regardless of whether it is locally-defined or an
@@ -782,6 +800,104 @@ impl Instance {
         let import = self.imported_table(index);
         &*import.from
     }
+
+    /// Get a global by index regardless of whether it is locally-defined or
+    /// an imported, foreign global.
+    pub(crate) fn get_global(&self, global_index: GlobalIndex) -> &Global {
+        if let Some(local_global_index) = self.module.local_global_index(global_index) {
+            &self.globals[local_global_index]
+        } else {
+            &self.imported_global(global_index).from
+        }
+    }
+
+    /// The `table.get` operation: get the reference at `index` in a table.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Trap` error when the index is out of bounds.
+    pub(crate) fn table_get_ref(
+        &self,
+        table_index: TableIndex,
+        index: u32,
+    ) -> Result<VMReference, Trap> {
+        self.get_table(table_index)
+            .get(index)
+            .ok_or_else(|| Trap::new_from_runtime(TrapCode::TableAccessOutOfBounds))
+    }
+
+    /// The `table.set` operation: set the reference at `index` in a table.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Trap` error when the index is out of bounds.
+    pub(crate) fn table_set_ref(
+        &self,
+        table_index: TableIndex,
+        index: u32,
+        val: VMReference,
+    ) -> Result<(), Trap> {
+        self.get_table(table_index).set(index, val)
+    }
+
+    /// The `table.grow` operation: grow a table by `delta` elements set to
+    /// `init_value`.
+    ///
+    /// Returns the previous size of the table, or `None` if the table can't
+    /// be grown by the specified amount of elements.
+    pub(crate) fn table_grow_ref(
+        &self,
+        table_index: TableIndex,
+        delta: u32,
+        init_value: VMReference,
+    ) -> Option<u32> {
+        self.get_table(table_index).grow(delta, init_value)
+    }
+
+    /// The `table.fill` operation: set `len` elements of a table, starting
+    /// at `start`, to `val`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Trap` error when the range is out of bounds.
+    pub(crate) fn table_fill(
+        &self,
+        table_index: TableIndex,
+        start: u32,
+        val: VMReference,
+        len: u32,
+    ) -> Result<(), Trap> {
+        let table = self.get_table(table_index);
+        if start
+            .checked_add(len)
+            .map_or(true, |end| end > table.size())
+        {
+            return Err(Trap::new_from_runtime(TrapCode::TableAccessOutOfBounds));
+        }
+
+        for index in start..start + len {
+            table
+                .set(index, val.clone())
+                .expect("the range is in bounds");
+        }
+        Ok(())
+    }
+
+    /// The `ref.func` operation: get a reference to a function.
+    pub(crate) fn func_ref(&self, function_index: FunctionIndex) -> VMReference {
+        VMReference::FuncRef(self.get_caller_checked_anyfunc(function_index))
+    }
+}
+
+/// Converts an element of a segment into an element of a table of type `ty`.
+///
+/// Segments of `externref`s can only hold null references, which are
+/// represented like null `funcref`s.
+fn table_element(ty: Type, anyfunc: &VMCallerCheckedAnyfunc) -> VMReference {
+    match ty {
+        Type::ExternRef => VMReference::ExternRef(None),
+        _ => VMReference::FuncRef(anyfunc.clone()),
+    }
 }
 
 /// A handle holding an `InstanceRef`, which holds an `Instance`
@@ -830,19 +946,14 @@ impl InstanceHandle {
         vmshared_signatures: BoxedSlice<SignatureIndex, VMSharedSignatureIndex>,
         host_state: Box<dyn Any>,
         imported_function_envs: BoxedSlice<FunctionIndex, ImportFunctionEnv>,
+        interrupts: Arc<VMInterrupts>,
     ) -> Result<Self, Trap> {
         let vmctx_globals = finished_globals
             .values()
             .map(|m| m.vmglobal())
             .collect::<PrimaryMap<LocalGlobalIndex, _>>()
             .into_boxed_slice();
-        let passive_data = RefCell::new(
-            module
-                .passive_data
-                .values()
-                .map(|data| Some(data.clone()))
-                .collect(),
-        );
+        let passive_data = RefCell::new(module.passive_data.clone());
 
         let handle = {
             let offsets = allocator.offsets().clone();
@@ -858,6 +969,7 @@ impl InstanceHandle {
                 passive_elements: Default::default(),
                 passive_data,
                 host_state,
+                interrupts,
                 signal_handler: Cell::new(None),
                 imported_function_envs,
                 vmctx: VMContext {},
@@ -871,6 +983,10 @@ impl InstanceHandle {
         };
         let instance = handle.instance().as_ref();
 
+        ptr::write(
+            instance.interrupts_ptr(),
+            &*instance.interrupts as *const VMInterrupts,
+        );
         ptr::copy(
             vmshared_signatures.values().as_slice().as_ptr(),
             instance.signature_ids_ptr() as *mut VMSharedSignatureIndex,
@@ -927,12 +1043,16 @@ impl InstanceHandle {
 
     /// Finishes the instantiation process started by `Instance::new`.
     ///
+    /// `memory_images` must have been built from `data_initializers`, if
+    /// given.
+    ///
     /// # Safety
     ///
     /// Only safe to call immediately after instantiation.
     pub unsafe fn finish_instantiation(
         &self,
         data_initializers: &[DataInitializer<'_>],
+        memory_images: Option<&MemoryImages>,
     ) -> Result<(), Trap> {
         let instance = self.instance().as_ref();
         check_table_init_bounds(instance)?;
@@ -940,7 +1060,7 @@ impl InstanceHandle {
 
         // Apply the initializers.
         initialize_tables(instance)?;
-        initialize_memories(instance, data_initializers)?;
+        initialize_memories(instance, data_initializers, memory_images)?;
 
         // The WebAssembly spec specifies that the start function is
         // invoked automatically at instantiation time.
@@ -1109,18 +1229,21 @@ impl InstanceHandle {
     ///
     /// Returns `None` if memory can't be grown by the specified amount
     /// of pages.
-    pub fn table_grow(&self, table_index: LocalTableIndex, delta: u32) -> Option<u32> {
-        self.instance().as_ref().table_grow(table_index, delta)
+    pub fn table_grow(
+        &self,
+        table_index: LocalTableIndex,
+        delta: u32,
+        init_value: VMReference,
+    ) -> Option<u32> {
+        self.instance()
+            .as_ref()
+            .table_grow(table_index, delta, init_value)
     }
 
     /// Get table element reference.
     ///
     /// Returns `None` if index is out of bounds.
-    pub fn table_get(
-        &self,
-        table_index: LocalTableIndex,
-        index: u32,
-    ) -> Option<VMCallerCheckedAnyfunc> {
+    pub fn table_get(&self, table_index: LocalTableIndex, index: u32) -> Option<VMReference> {
         self.instance().as_ref().table_get(table_index, index)
     }
 
@@ -1131,7 +1254,7 @@ impl InstanceHandle {
         &self,
         table_index: LocalTableIndex,
         index: u32,
-        val: VMCallerCheckedAnyfunc,
+        val: VMReference,
     ) -> Result<(), Trap> {
         self.instance().as_ref().table_set(table_index, index, val)
     }
@@ -1298,6 +1421,7 @@ fn initialize_tables(instance: &Instance) -> Result<(), Trap> {
     for init in &module.table_initializers {
         let start = get_table_init_start(init, instance);
         let table = instance.get_table(init.table_index);
+        let ty = table.ty().ty;
 
         if start
             .checked_add(init.elements.len())
@@ -1309,7 +1433,10 @@ fn initialize_tables(instance: &Instance) -> Result<(), Trap> {
         for (i, func_idx) in init.elements.iter().enumerate() {
             let anyfunc = instance.get_caller_checked_anyfunc(*func_idx);
             table
-                .set(u32::try_from(start + i).unwrap(), anyfunc)
+                .set(
+                    u32::try_from(start + i).unwrap(),
+                    table_element(ty, &anyfunc),
+                )
                 .unwrap();
         }
     }
@@ -1327,28 +1454,55 @@ fn initialize_passive_elements(instance: &Instance) {
         "should only be called once, at initialization time"
     );
 
-    for (segments, passive_element) in instance
-        .module
-        .passive_elements
-        .values()
-        .zip(passive_elements.values_mut())
-    {
-        if segments.is_empty() {
-            continue;
-        }
-        *passive_element = segments
+    passive_elements.extend(
+        instance
+            .module
+            .passive_elements
             .iter()
-            .map(|s| Some(instance.get_caller_checked_anyfunc(*s)))
-            .collect();
-    }
+            .filter(|(_, segments)| !segments.is_empty())
+            .map(|(idx, segments)| {
+                (
+                    *idx,
+                    segments
+                        .iter()
+                        .map(|s| instance.get_caller_checked_anyfunc(*s))
+                        .collect(),
+                )
+            }),
+    );
 }
 
 /// Initialize the table memory from the provided initializers.
 fn initialize_memories(
     instance: &Instance,
     data_initializers: &[DataInitializer<'_>],
+    memory_images: Option<&MemoryImages>,
 ) -> Result<(), Trap> {
-    for init in data_initializers {
+    // Map the memory images first: the data initializers they don't hold
+    // come after the ones they hold.
+    let mut mapped_images = vec![false; instance.memories.len()];
+    if let Some(memory_images) = memory_images {
+        for (index, memory) in instance.memories.iter() {
+            if let Some(image) = memory_images.get(index) {
+                mapped_images[index.index()] = memory
+                    .map_image(image)
+                    .map_err(|e| Trap::new_from_user(Box::new(e)))?;
+            }
+        }
+    }
+
+    for (i, init) in data_initializers.iter().enumerate() {
+        let in_mapped_image = memory_images.map_or(false, |memory_images| {
+            memory_images.in_image(i)
+                && instance
+                    .module
+                    .local_memory_index(init.location.memory_index)
+                    .map_or(false, |index| mapped_images[index.index()])
+        });
+        if in_mapped_image {
+            continue;
+        }
+
         let memory = instance.get_memory(init.location.memory_index);
 
         let start = get_memory_init_start(init, instance);
@@ -1381,6 +1535,10 @@ fn initialize_globals(instance: &Instance) {
                 GlobalInit::F32Const(x) => *(*to).as_f32_mut() = *x,
                 GlobalInit::F64Const(x) => *(*to).as_f64_mut() = *x,
                 GlobalInit::V128Const(x) => *(*to).as_bytes_mut() = *x.bytes(),
+                GlobalInit::GetGlobal(x) if module.globals[*x].ty.is_ref() => {
+                    let from = instance.get_global(*x).get_ref();
+                    instance.globals[index].set_ref_unchecked(from);
+                }
                 GlobalInit::GetGlobal(x) => {
                     let from: VMGlobalDefinition =
                         if let Some(def_x) = module.local_global_index(*x) {
@@ -1390,7 +1548,11 @@ fn initialize_globals(instance: &Instance) {
                         };
                     *to = from;
                 }
-                GlobalInit::RefNullConst | GlobalInit::RefFunc(_) => unimplemented!(),
+                // Globals start out zeroed, which is the null reference.
+                GlobalInit::RefNullConst => {}
+                GlobalInit::RefFunc(x) => {
+                    instance.globals[index].set_ref_unchecked(instance.func_ref(*x));
+                }
             }
         }
     }
@@ -1,4 +1,5 @@
 use super::Instance;
+use crate::pooling::InstanceSlot;
 use loupe::{MemoryUsage, MemoryUsageTracker};
 use std::alloc::Layout;
 use std::mem;
@@ -42,6 +43,10 @@ pub struct InstanceRef {
     /// The layout of `Instance` (which can vary).
     instance_layout: Layout,
 
+    /// The pool slot holding `Instance`, if it has been allocated by
+    /// a `PoolingAllocator`.
+    slot: Option<InstanceSlot>,
+
     /// The `Instance` itself. It must be the last field of
     /// `InstanceRef` since `Instance` is dyamically-sized.
     ///
@@ -66,10 +71,15 @@ impl InstanceRef {
     /// and correctly initialized pointer to `Instance`. See
     /// [`InstanceAllocator`] for an example of how to correctly use
     /// this API.
-    pub(super) unsafe fn new(instance: NonNull<Instance>, instance_layout: Layout) -> Self {
+    pub(super) unsafe fn new(
+        instance: NonNull<Instance>,
+        instance_layout: Layout,
+        slot: Option<InstanceSlot>,
+    ) -> Self {
         Self {
             strong: Arc::new(atomic::AtomicUsize::new(1)),
             instance_layout,
+            slot,
             instance,
         }
     }
@@ -90,7 +100,10 @@ impl InstanceRef {
         let instance_ptr = self.instance.as_ptr();
 
         ptr::drop_in_place(instance_ptr);
-        std::alloc::dealloc(instance_ptr as *mut u8, self.instance_layout);
+        match &self.slot {
+            Some(slot) => slot.release(self.instance_layout),
+            None => std::alloc::dealloc(instance_ptr as *mut u8, self.instance_layout),
+        }
     }
 
     /// Get the number of strong references pointing to this
@@ -158,6 +171,7 @@ impl Clone for InstanceRef {
         Self {
             strong: self.strong.clone(),
             instance_layout: self.instance_layout,
+            slot: self.slot.clone(),
             instance: self.instance.clone(),
         }
     }
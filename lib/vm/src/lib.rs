@@ -25,9 +25,12 @@ mod global;
 mod imports;
 mod instance;
 mod memory;
+mod memory_image;
 mod mmap;
 mod module;
+mod pooling;
 mod probestack;
+mod reference;
 mod sig_registry;
 mod table;
 mod trap;
@@ -43,17 +46,20 @@ pub use crate::instance::{
     ImportFunctionEnv, ImportInitializerFuncPtr, InstanceAllocator, InstanceHandle,
 };
 pub use crate::memory::{LinearMemory, Memory, MemoryError, MemoryStyle};
+pub use crate::memory_image::{MemoryImage, MemoryImages};
 pub use crate::mmap::Mmap;
 pub use crate::module::{ExportsIterator, ImportsIterator, ModuleInfo};
+pub use crate::pooling::{PoolingAllocator, PoolingLimits};
 pub use crate::probestack::PROBESTACK;
+pub use crate::reference::{ReferenceScope, VMExternRef, VMRawReference, VMReference};
 pub use crate::sig_registry::SignatureRegistry;
 pub use crate::table::{LinearTable, Table, TableStyle};
 pub use crate::trap::*;
 pub use crate::vmcontext::{
-    VMBuiltinFunctionIndex, VMCallerCheckedAnyfunc, VMContext, VMDynamicFunctionContext,
-    VMFunctionBody, VMFunctionEnvironment, VMFunctionImport, VMFunctionKind, VMGlobalDefinition,
-    VMGlobalImport, VMMemoryDefinition, VMMemoryImport, VMSharedSignatureIndex, VMTableDefinition,
-    VMTableImport, VMTrampoline,
+    EpochDeadlineCallback, InterruptScope, VMBuiltinFunctionIndex, VMCallerCheckedAnyfunc,
+    VMContext, VMDynamicFunctionContext, VMFunctionBody, VMFunctionEnvironment, VMFunctionImport,
+    VMFunctionKind, VMGlobalDefinition, VMGlobalImport, VMInterrupts, VMMemoryDefinition,
+    VMMemoryImport, VMSharedSignatureIndex, VMTableDefinition, VMTableImport, VMTrampoline,
 };
 pub use crate::vmoffsets::{TargetSharedSignatureIndex, VMOffsets};
 use loupe::MemoryUsage;
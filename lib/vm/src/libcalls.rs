@@ -36,12 +36,15 @@
 //!   ```
 
 use crate::probestack::PROBESTACK;
+use crate::reference::{VMRawReference, VMReference};
 use crate::trap::{raise_lib_trap, Trap, TrapCode};
 use crate::vmcontext::VMContext;
 use loupe::MemoryUsage;
 use serde::{Deserialize, Serialize};
 use std::fmt;
-use wasmer_types::{DataIndex, ElemIndex, LocalMemoryIndex, MemoryIndex, TableIndex};
+use wasmer_types::{
+    DataIndex, ElemIndex, FunctionIndex, GlobalIndex, LocalMemoryIndex, MemoryIndex, TableIndex,
+};
 
 /// Implementation of f32.ceil
 #[no_mangle]
@@ -259,6 +262,145 @@ pub unsafe extern "C" fn wasmer_elem_drop(vmctx: *mut VMContext, elem_index: u32
     instance.elem_drop(elem_index);
 }
 
+/// Implementation of `table.get`.
+///
+/// # Safety
+///
+/// `vmctx` must be valid and not null.
+pub unsafe extern "C" fn wasmer_table_get(
+    vmctx: *mut VMContext,
+    table_index: u32,
+    elem_index: u32,
+) -> VMRawReference {
+    let result = {
+        let table_index = TableIndex::from_u32(table_index);
+        let instance = (&*vmctx).instance();
+        instance.table_get_ref(table_index, elem_index)
+    };
+    match result {
+        Ok(value) => value.into_raw(),
+        Err(trap) => raise_lib_trap(trap),
+    }
+}
+
+/// Implementation of `table.set`.
+///
+/// # Safety
+///
+/// `vmctx` must be valid and not null, and `value` must be a live reference
+/// of the table's element type.
+pub unsafe extern "C" fn wasmer_table_set(
+    vmctx: *mut VMContext,
+    table_index: u32,
+    elem_index: u32,
+    value: VMRawReference,
+) {
+    let result = {
+        let table_index = TableIndex::from_u32(table_index);
+        let instance = (&*vmctx).instance();
+        let ty = instance.get_table(table_index).ty().ty;
+        let value = VMReference::from_raw(ty, value);
+        instance.table_set_ref(table_index, elem_index, value)
+    };
+    if let Err(trap) = result {
+        raise_lib_trap(trap);
+    }
+}
+
+/// Implementation of `table.grow`.
+///
+/// # Safety
+///
+/// `vmctx` must be valid and not null, and `init_value` must be a live
+/// reference of the table's element type.
+pub unsafe extern "C" fn wasmer_table_grow(
+    vmctx: *mut VMContext,
+    init_value: VMRawReference,
+    delta: u32,
+    table_index: u32,
+) -> u32 {
+    let table_index = TableIndex::from_u32(table_index);
+    let instance = (&*vmctx).instance();
+    let ty = instance.get_table(table_index).ty().ty;
+    let init_value = VMReference::from_raw(ty, init_value);
+    instance
+        .table_grow_ref(table_index, delta, init_value)
+        .unwrap_or(u32::max_value())
+}
+
+/// Implementation of `table.fill`.
+///
+/// # Safety
+///
+/// `vmctx` must be valid and not null, and `item` must be a live reference of
+/// the table's element type.
+pub unsafe extern "C" fn wasmer_table_fill(
+    vmctx: *mut VMContext,
+    table_index: u32,
+    start: u32,
+    item: VMRawReference,
+    len: u32,
+) {
+    let result = {
+        let table_index = TableIndex::from_u32(table_index);
+        let instance = (&*vmctx).instance();
+        let ty = instance.get_table(table_index).ty().ty;
+        let item = VMReference::from_raw(ty, item);
+        instance.table_fill(table_index, start, item, len)
+    };
+    if let Err(trap) = result {
+        raise_lib_trap(trap);
+    }
+}
+
+/// Implementation of `ref.func`.
+///
+/// # Safety
+///
+/// `vmctx` must be valid and not null.
+pub unsafe extern "C" fn wasmer_func_ref(
+    vmctx: *mut VMContext,
+    function_index: u32,
+) -> VMRawReference {
+    let function_index = FunctionIndex::from_u32(function_index);
+    let instance = (&*vmctx).instance();
+    instance.func_ref(function_index).into_raw()
+}
+
+/// Implementation of `global.get` for globals of reference types.
+///
+/// # Safety
+///
+/// `vmctx` must be valid and not null.
+pub unsafe extern "C" fn wasmer_global_get_ref(
+    vmctx: *mut VMContext,
+    global_index: u32,
+) -> VMRawReference {
+    let global_index = GlobalIndex::from_u32(global_index);
+    let instance = (&*vmctx).instance();
+    instance.get_global(global_index).get_ref().into_raw()
+}
+
+/// Implementation of `global.set` for globals of reference types.
+///
+/// # Safety
+///
+/// `vmctx` must be valid and not null, and `value` must be a live reference
+/// of the global's type.
+pub unsafe extern "C" fn wasmer_global_set_ref(
+    vmctx: *mut VMContext,
+    global_index: u32,
+    value: VMRawReference,
+) {
+    let global_index = GlobalIndex::from_u32(global_index);
+    let instance = (&*vmctx).instance();
+    let global = instance.get_global(global_index);
+    let value = VMReference::from_raw(global.ty().ty, value);
+    global
+        .set_ref(value)
+        .expect("validation ensures that the global is mutable and of the value's type");
+}
+
 /// Implementation of `memory.copy` for locally defined memories.
 ///
 /// # Safety
@@ -382,6 +524,23 @@ pub unsafe extern "C" fn wasmer_data_drop(vmctx: *mut VMContext, data_index: u32
     instance.data_drop(data_index)
 }
 
+/// Implementation of the interrupt and epoch deadline check done at function
+/// entries and loop headers.
+///
+/// Compiled code only calls this after observing the interrupt flag set or
+/// the epoch deadline reached. The interrupt flag is left set, so that every
+/// call running in the store traps.
+///
+/// # Safety
+///
+/// `vmctx` must be valid and not null.
+pub unsafe extern "C" fn wasmer_interrupt(vmctx: *mut VMContext) {
+    let instance = (&*vmctx).instance();
+    if let Err(trap) = instance.interrupts().check() {
+        raise_lib_trap(trap);
+    }
+}
+
 /// Implementation for raising a trap
 ///
 /// # Safety
@@ -5,6 +5,7 @@
 //!
 //! `LinearMemory` is to WebAssembly linear memories what `Table` is to WebAssembly tables.
 
+use crate::memory_image::MemoryImage;
 use crate::mmap::Mmap;
 use crate::vmcontext::VMMemoryDefinition;
 use loupe::MemoryUsage;
@@ -114,6 +115,15 @@ pub trait Memory: fmt::Debug + Send + Sync + MemoryUsage {
     ///
     /// The pointer returned in [`VMMemoryDefinition`] must be valid for the lifetime of this memory.
     fn vmmemory(&self) -> NonNull<VMMemoryDefinition>;
+
+    /// Map `image` copy-on-write into the memory.
+    ///
+    /// Returns `false` if the memory doesn't support memory images, in which
+    /// case the data it holds must be copied instead.
+    fn map_image(&self, image: &MemoryImage) -> Result<bool, MemoryError> {
+        let _ = image;
+        Ok(false)
+    }
 }
 
 /// A linear memory instance.
@@ -412,4 +422,17 @@ impl Memory for LinearMemory {
         let _mmap_guard = self.mmap.lock().unwrap();
         unsafe { self.get_vm_memory_definition() }
     }
+
+    /// Map `image` copy-on-write into the memory.
+    fn map_image(&self, image: &MemoryImage) -> Result<bool, MemoryError> {
+        let mut mmap_guard = self.mmap.lock().unwrap();
+        let mmap = mmap_guard.borrow_mut();
+        if image.offset() + image.len() > mmap.size.bytes().0 {
+            return Err(MemoryError::InvalidMemory {
+                reason: "the memory image doesn't fit in the memory".to_string(),
+            });
+        }
+        unsafe { image.map_at(mmap.alloc.as_mut_ptr()) }.map_err(MemoryError::Region)?;
+        Ok(true)
+    }
 }
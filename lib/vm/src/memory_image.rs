@@ -0,0 +1,239 @@
+//! Copy-on-write images of the initial contents of linear memories.
+//!
+//! Instantiating a module normally copies each of its data segments into
+//! linear memory. A [`MemoryImage`] holds the contents of the static data
+//! segments of a memory in an in-memory file instead, which is mapped
+//! copy-on-write into the memory at instantiation: pages are only copied
+//! when written to.
+//!
+//! Memory images are only supported on Linux, where the file is created
+//! with `memfd_create`. On other platforms, no images are built and data
+//! segments are always copied.
+
+use crate::mmap::round_up_to_page_size;
+use crate::module::ModuleInfo;
+use loupe::{MemoryUsage, MemoryUsageTracker};
+#[cfg(target_os = "linux")]
+use std::fs::File;
+use std::io;
+use std::mem;
+use wasmer_types::entity::{EntityRef, PrimaryMap};
+use wasmer_types::{DataInitializer, LocalMemoryIndex, MemoryIndex};
+
+/// The initial contents of a linear memory, built from its static data
+/// segments.
+#[derive(Debug)]
+pub struct MemoryImage {
+    /// The file holding the contents of the image.
+    #[cfg(target_os = "linux")]
+    file: File,
+    /// The offset in bytes of the image in the memory, page-aligned.
+    offset: usize,
+    /// The length in bytes of the image, page-aligned.
+    len: usize,
+}
+
+impl MemoryImage {
+    /// Builds the image of the given `(offset, data)` segments, applied in
+    /// order.
+    #[cfg(target_os = "linux")]
+    fn new(segments: &[(usize, &[u8])]) -> io::Result<Option<Self>> {
+        use std::os::unix::fs::FileExt;
+        use std::os::unix::io::FromRawFd;
+
+        let page_size = region::page::size();
+        let start = match segments.iter().map(|(offset, _)| *offset).min() {
+            Some(start) => start & !(page_size - 1),
+            None => return Ok(None),
+        };
+        let end = segments
+            .iter()
+            .map(|(offset, data)| offset + data.len())
+            .max()
+            .unwrap();
+        let len = round_up_to_page_size(end - start, page_size);
+        if len == 0 {
+            return Ok(None);
+        }
+
+        let fd =
+            unsafe { libc::memfd_create(b"wasm-memory-image\0".as_ptr() as _, libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let file = unsafe { File::from_raw_fd(fd) };
+        file.set_len(len as u64)?;
+        for (offset, data) in segments {
+            file.write_all_at(data, (offset - start) as u64)?;
+        }
+
+        Ok(Some(Self {
+            file,
+            offset: start,
+            len,
+        }))
+    }
+
+    /// Memory images are not supported on this platform.
+    #[cfg(not(target_os = "linux"))]
+    fn new(_segments: &[(usize, &[u8])]) -> io::Result<Option<Self>> {
+        Ok(None)
+    }
+
+    /// The offset in bytes of the image in the memory.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The length in bytes of the image.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return whether the image is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Maps the image copy-on-write into the memory starting at `base`.
+    ///
+    /// # Safety
+    /// - `base` must be page-aligned, and the `offset() + len()` bytes
+    ///   starting at `base` must be an accessible part of a private
+    ///   mapping owned by the memory.
+    #[cfg(target_os = "linux")]
+    pub(crate) unsafe fn map_at(&self, base: *mut u8) -> Result<(), String> {
+        use std::os::unix::io::AsRawFd;
+
+        let ptr = libc::mmap(
+            base.add(self.offset) as _,
+            self.len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_FIXED,
+            self.file.as_raw_fd(),
+            0,
+        );
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error().to_string());
+        }
+        Ok(())
+    }
+
+    /// Maps the image copy-on-write into the memory starting at `base`.
+    ///
+    /// # Safety
+    /// - `base` must be page-aligned, and the `offset() + len()` bytes
+    ///   starting at `base` must be an accessible part of a private
+    ///   mapping owned by the memory.
+    #[cfg(not(target_os = "linux"))]
+    pub(crate) unsafe fn map_at(&self, _base: *mut u8) -> Result<(), String> {
+        Err("memory images are not supported on this platform".to_string())
+    }
+}
+
+/// The memory images of the local memories of a module.
+///
+/// The image of a memory is built from the leading data segments of that
+/// memory that have a constant offset and fit in its minimum size. The
+/// other segments are left to be copied at instantiation, after the image
+/// is mapped, which keeps the order in which segments are applied.
+#[derive(Debug, Default)]
+pub struct MemoryImages {
+    images: PrimaryMap<LocalMemoryIndex, Option<MemoryImage>>,
+    /// Whether each data initializer is part of the image of its memory.
+    in_image: Vec<bool>,
+}
+
+impl MemoryImages {
+    /// Builds the memory images of `module` from its data initializers.
+    pub fn new(module: &ModuleInfo, data_initializers: &[DataInitializer<'_>]) -> io::Result<Self> {
+        let mut images = PrimaryMap::with_capacity(module.memories.len());
+        let mut in_image = vec![false; data_initializers.len()];
+        for index in module.num_imported_memories..module.memories.len() {
+            let memory_index = MemoryIndex::new(index);
+            let minimum = module.memories[memory_index].minimum.bytes().0;
+            let mut segments = Vec::new();
+            for (i, init) in data_initializers.iter().enumerate() {
+                if init.location.memory_index != memory_index {
+                    continue;
+                }
+                let fits = init
+                    .location
+                    .offset
+                    .checked_add(init.data.len())
+                    .map_or(false, |end| end <= minimum);
+                if init.location.base.is_some() || !fits {
+                    break;
+                }
+                segments.push((init.location.offset, init.data));
+                in_image[i] = true;
+            }
+            let image = MemoryImage::new(&segments)?;
+            if image.is_none() {
+                for (i, init) in data_initializers.iter().enumerate() {
+                    if init.location.memory_index == memory_index {
+                        in_image[i] = false;
+                    }
+                }
+            }
+            images.push(image);
+        }
+        Ok(Self { images, in_image })
+    }
+
+    /// The image of a local memory, if it has one.
+    pub fn get(&self, index: LocalMemoryIndex) -> Option<&MemoryImage> {
+        self.images.get(index).and_then(Option::as_ref)
+    }
+
+    /// Whether the data initializer at `index` is part of the image of its
+    /// memory.
+    pub(crate) fn in_image(&self, index: usize) -> bool {
+        self.in_image.get(index).cloned().unwrap_or(false)
+    }
+}
+
+impl MemoryUsage for MemoryImages {
+    fn size_of_val(&self, _: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self)
+            + self
+                .images
+                .values()
+                .flatten()
+                .map(|image| mem::size_of_val(image) + image.len)
+                .sum::<usize>()
+            + self.in_image.len() * mem::size_of::<bool>()
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::mmap::Mmap;
+
+    #[test]
+    fn map_image() {
+        let page_size = region::page::size();
+        let image = MemoryImage::new(&[
+            (page_size + 1, &[1, 2, 3][..]),
+            (2 * page_size, &[4][..]),
+            (page_size + 2, &[5][..]),
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(image.offset(), page_size);
+        assert_eq!(image.len(), 2 * page_size);
+
+        let mut mmap = Mmap::with_at_least(4 * page_size).unwrap();
+        unsafe { image.map_at(mmap.as_mut_ptr()).unwrap() };
+        let memory = mmap.as_mut_slice();
+        assert_eq!(memory[page_size..page_size + 4], [0, 1, 5, 3]);
+        assert_eq!(memory[2 * page_size], 4);
+
+        // Writes are private to the mapping.
+        memory[page_size + 1] = 42;
+        let mut other = Mmap::with_at_least(4 * page_size).unwrap();
+        unsafe { image.map_at(other.as_mut_ptr()).unwrap() };
+        assert_eq!(other.as_slice()[page_size + 1], 1);
+    }
+}
@@ -6,13 +6,12 @@
 
 use loupe::{MemoryUsage, MemoryUsageTracker};
 use more_asserts::assert_le;
-use more_asserts::assert_lt;
 use std::io;
 use std::ptr;
 use std::slice;
 
 /// Round `size` up to the nearest multiple of `page_size`.
-fn round_up_to_page_size(size: usize, page_size: usize) -> usize {
+pub(crate) fn round_up_to_page_size(size: usize, page_size: usize) -> usize {
     (size + (page_size - 1)) & !(page_size - 1)
 }
 
@@ -187,8 +186,8 @@ impl Mmap {
         let page_size = region::page::size();
         assert_eq!(start & (page_size - 1), 0);
         assert_eq!(len & (page_size - 1), 0);
-        assert_lt!(len, self.len);
-        assert_lt!(start, self.len - len);
+        assert_le!(len, self.len);
+        assert_le!(start, self.len - len);
 
         // Commit the accessible size.
         let ptr = self.ptr as *const u8;
@@ -207,8 +206,8 @@ impl Mmap {
         let page_size = region::page::size();
         assert_eq!(start & (page_size - 1), 0);
         assert_eq!(len & (page_size - 1), 0);
-        assert_lt!(len, self.len);
-        assert_lt!(start, self.len - len);
+        assert_le!(len, self.len);
+        assert_le!(start, self.len - len);
 
         // Commit the accessible size.
         let ptr = self.ptr as *const u8;
@@ -228,6 +227,51 @@ impl Mmap {
         Ok(())
     }
 
+    /// Make the memory starting at `start` and extending for `len` bytes
+    /// inaccessible again, releasing its physical pages: if made accessible
+    /// again, it reads as zeros. `start` and `len` must be native page-size
+    /// multiples and describe a range within `self`'s reserved memory.
+    #[cfg(not(target_os = "windows"))]
+    pub fn decommit(&mut self, start: usize, len: usize) -> Result<(), String> {
+        let page_size = region::page::size();
+        assert_eq!(start & (page_size - 1), 0);
+        assert_eq!(len & (page_size - 1), 0);
+        assert_le!(len, self.len);
+        assert_le!(start, self.len - len);
+
+        let ptr = self.ptr as *mut u8;
+        unsafe {
+            if libc::madvise(ptr.add(start) as _, len, libc::MADV_DONTNEED) != 0 {
+                return Err(io::Error::last_os_error().to_string());
+            }
+            region::protect(ptr.add(start), len, region::Protection::NONE)
+                .map_err(|e| e.to_string())
+        }
+    }
+
+    /// Make the memory starting at `start` and extending for `len` bytes
+    /// inaccessible again, releasing its physical pages: if made accessible
+    /// again, it reads as zeros. `start` and `len` must be native page-size
+    /// multiples and describe a range within `self`'s reserved memory.
+    #[cfg(target_os = "windows")]
+    pub fn decommit(&mut self, start: usize, len: usize) -> Result<(), String> {
+        use winapi::ctypes::c_void;
+        use winapi::um::memoryapi::VirtualFree;
+        use winapi::um::winnt::MEM_DECOMMIT;
+        let page_size = region::page::size();
+        assert_eq!(start & (page_size - 1), 0);
+        assert_eq!(len & (page_size - 1), 0);
+        assert_le!(len, self.len);
+        assert_le!(start, self.len - len);
+
+        let ptr = self.ptr as *const u8;
+        if unsafe { VirtualFree(ptr.add(start) as *mut c_void, len, MEM_DECOMMIT) } == 0 {
+            return Err(io::Error::last_os_error().to_string());
+        }
+
+        Ok(())
+    }
+
     /// Return the allocated memory as a slice of u8.
     pub fn as_slice(&self) -> &[u8] {
         unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
@@ -0,0 +1,708 @@
+//! A pooling allocator for instances, linear memories and tables.
+//!
+//! Creating an instance normally allocates its `Instance` on the heap and
+//! maps fresh memory for each of its linear memories. When instances are
+//! short-lived and created at a high rate, the cost of the `mmap`/`munmap`
+//! system calls dominates. The [`PoolingAllocator`] instead reserves slabs
+//! of fixed-size slots up front, and reuses a slot once whatever was
+//! allocated in it is dropped, after resetting its pages.
+
+use crate::instance::{Instance, InstanceAllocator};
+use crate::memory::{Memory, MemoryError, MemoryStyle};
+use crate::mmap::{round_up_to_page_size, Mmap};
+use crate::reference::{VMExternRef, VMReference};
+use crate::table::{Table, TableStyle};
+use crate::trap::{Trap, TrapCode};
+use crate::vmcontext::{VMCallerCheckedAnyfunc, VMMemoryDefinition, VMTableDefinition};
+use crate::ModuleInfo;
+use loupe::{MemoryUsage, MemoryUsageTracker};
+use std::alloc::Layout;
+use std::convert::TryFrom;
+use std::fmt;
+use std::mem;
+use std::ptr::{self, NonNull};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+use wasmer_types::{MemoryType, Pages, TableType, Type as ValType};
+
+/// The limits of a [`PoolingAllocator`], which determine the number and
+/// size of its slots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolingLimits {
+    /// The maximum number of instances alive at the same time.
+    pub max_instances: usize,
+    /// The maximum size in bytes of an `Instance`, including its `VMContext`.
+    pub max_instance_size: usize,
+    /// The maximum number of local memories of an instance.
+    pub max_memories_per_instance: usize,
+    /// The maximum size in wasm pages of a linear memory.
+    pub max_memory_pages: Pages,
+    /// The size in bytes of the offset guard after each linear memory.
+    pub memory_offset_guard_size: u64,
+    /// The maximum number of local tables of an instance.
+    pub max_tables_per_instance: usize,
+    /// The maximum number of elements of a table.
+    pub max_table_elements: u32,
+}
+
+impl Default for PoolingLimits {
+    fn default() -> Self {
+        Self {
+            max_instances: 1000,
+            max_instance_size: 1 << 20,
+            max_memories_per_instance: 1,
+            max_memory_pages: Pages(160),
+            memory_offset_guard_size: 0x1_0000,
+            max_tables_per_instance: 1,
+            max_table_elements: 10_000,
+        }
+    }
+}
+
+/// A slab of equally-sized slots.
+struct Slab {
+    kind: &'static str,
+    slot_size: usize,
+    num_slots: usize,
+    inner: Mutex<SlabInner>,
+}
+
+struct SlabInner {
+    mmap: Mmap,
+    free: Vec<usize>,
+}
+
+impl Slab {
+    fn new(kind: &'static str, slot_size: usize, num_slots: usize) -> Result<Self, String> {
+        let slot_size = round_up_to_page_size(slot_size, region::page::size());
+        let size = slot_size
+            .checked_mul(num_slots)
+            .ok_or_else(|| format!("the {} pool is too large", kind))?;
+        Ok(Self {
+            kind,
+            slot_size,
+            num_slots,
+            inner: Mutex::new(SlabInner {
+                mmap: Mmap::accessible_reserved(0, size)?,
+                // Hand out the lowest slots first.
+                free: (0..num_slots).rev().collect(),
+            }),
+        })
+    }
+
+    /// Takes a free slot, making its first `accessible_size` bytes
+    /// accessible.
+    fn acquire(&self, accessible_size: usize) -> Result<(usize, *mut u8), String> {
+        let mut inner = self.inner.lock().unwrap();
+        let index = inner.free.pop().ok_or_else(|| {
+            format!(
+                "the {} pool is exhausted: all {} slots are in use",
+                self.kind, self.num_slots
+            )
+        })?;
+        let start = index * self.slot_size;
+        let accessible_size = round_up_to_page_size(accessible_size, region::page::size());
+        if accessible_size > 0 {
+            if let Err(e) = inner.mmap.make_accessible(start, accessible_size) {
+                inner.free.push(index);
+                return Err(e);
+            }
+        }
+        let ptr = unsafe { inner.mmap.as_mut_ptr().add(start) };
+        Ok((index, ptr))
+    }
+
+    /// Makes `len` more bytes accessible in slot `index`, from `offset`.
+    fn make_accessible(&self, index: usize, offset: usize, len: usize) -> Result<(), String> {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .mmap
+            .make_accessible(index * self.slot_size + offset, len)
+    }
+
+    /// Resets the first `used_size` bytes of slot `index` and returns it
+    /// to the pool.
+    ///
+    /// A slot that can't be reset may still hold the data of its previous
+    /// user, so it is left out of the pool instead.
+    fn release(&self, index: usize, used_size: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        if used_size > 0 {
+            if let Err(e) = inner.mmap.decommit(index * self.slot_size, used_size) {
+                warn!(
+                    "failed to reset slot {} of the {} pool, it won't be reused: {}",
+                    index, self.kind, e
+                );
+                return;
+            }
+        }
+        inner.free.push(index);
+    }
+
+    fn num_used(&self) -> usize {
+        self.num_slots - self.inner.lock().unwrap().free.len()
+    }
+}
+
+struct Pool {
+    limits: PoolingLimits,
+    instances: Slab,
+    memories: Slab,
+    tables: Slab,
+}
+
+/// An allocator reserving slabs of instance, memory and table slots up
+/// front.
+///
+/// Allocations fail once all slots of their kind are in use, until
+/// something is dropped. A slot's pages are reset when it is released, so
+/// it reads as zeros when reused; a slot that can't be reset is never reused
+/// and stays counted as in use.
+///
+/// Cloning a `PoolingAllocator` gives another handle to the same pool.
+#[derive(Clone)]
+pub struct PoolingAllocator {
+    pool: Arc<Pool>,
+}
+
+impl PoolingAllocator {
+    /// Reserves the slabs for the given limits.
+    pub fn new(limits: PoolingLimits) -> Result<Self, String> {
+        let memory_slot_size = usize::try_from(limits.memory_offset_guard_size)
+            .ok()
+            .and_then(|guard_size| limits.max_memory_pages.bytes().0.checked_add(guard_size))
+            .ok_or_else(|| "the memory slots are too large".to_string())?;
+        let table_slot_size = (limits.max_table_elements as usize)
+            .checked_mul(mem::size_of::<VMCallerCheckedAnyfunc>())
+            .ok_or_else(|| "the table slots are too large".to_string())?;
+        let num_memories = limits
+            .max_instances
+            .checked_mul(limits.max_memories_per_instance)
+            .ok_or_else(|| "the memory pool is too large".to_string())?;
+        let num_tables = limits
+            .max_instances
+            .checked_mul(limits.max_tables_per_instance)
+            .ok_or_else(|| "the table pool is too large".to_string())?;
+        Ok(Self {
+            pool: Arc::new(Pool {
+                limits,
+                instances: Slab::new("instance", limits.max_instance_size, limits.max_instances)?,
+                memories: Slab::new("memory", memory_slot_size, num_memories)?,
+                tables: Slab::new("table", table_slot_size, num_tables)?,
+            }),
+        })
+    }
+
+    /// The limits of this pool.
+    pub fn limits(&self) -> &PoolingLimits {
+        &self.pool.limits
+    }
+
+    /// The number of instance slots in use.
+    pub fn instances_in_use(&self) -> usize {
+        self.pool.instances.num_used()
+    }
+
+    /// The number of memory slots in use.
+    pub fn memories_in_use(&self) -> usize {
+        self.pool.memories.num_used()
+    }
+
+    /// The number of table slots in use.
+    pub fn tables_in_use(&self) -> usize {
+        self.pool.tables.num_used()
+    }
+
+    /// The style that compiled code must use to access the memories of
+    /// this pool.
+    pub fn memory_style(&self) -> MemoryStyle {
+        MemoryStyle::Static {
+            bound: self.pool.limits.max_memory_pages,
+            offset_guard_size: self.pool.limits.memory_offset_guard_size,
+        }
+    }
+
+    /// Allocates an instance of `module` in a slot of the pool.
+    ///
+    /// See [`InstanceAllocator::new`].
+    pub fn allocate_instance(
+        &self,
+        module: &ModuleInfo,
+    ) -> Result<
+        (
+            InstanceAllocator,
+            Vec<NonNull<VMMemoryDefinition>>,
+            Vec<NonNull<VMTableDefinition>>,
+        ),
+        String,
+    > {
+        let num_memories = module.memories.len() - module.num_imported_memories;
+        if num_memories > self.pool.limits.max_memories_per_instance {
+            return Err(format!(
+                "the module defines {} memories, but the pool allows {} per instance",
+                num_memories, self.pool.limits.max_memories_per_instance
+            ));
+        }
+        let num_tables = module.tables.len() - module.num_imported_tables;
+        if num_tables > self.pool.limits.max_tables_per_instance {
+            return Err(format!(
+                "the module defines {} tables, but the pool allows {} per instance",
+                num_tables, self.pool.limits.max_tables_per_instance
+            ));
+        }
+
+        InstanceAllocator::new_with(module, |layout| {
+            if layout.size() > self.pool.instances.slot_size
+                || layout.align() > region::page::size()
+            {
+                return Err(format!(
+                    "the instance needs {} bytes, but the pool slots have {}",
+                    layout.size(),
+                    self.pool.instances.slot_size
+                ));
+            }
+            let (index, ptr) = self.pool.instances.acquire(layout.size())?;
+            Ok((
+                NonNull::new(ptr).unwrap().cast::<Instance>(),
+                Some(InstanceSlot {
+                    pool: self.pool.clone(),
+                    index,
+                }),
+            ))
+        })
+    }
+
+    /// Creates a memory owned by the VM in a slot of the pool.
+    ///
+    /// # Safety
+    /// - `vm_definition_location` must point to a valid location in VM memory.
+    pub unsafe fn create_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+        vm_definition_location: NonNull<VMMemoryDefinition>,
+    ) -> Result<Arc<dyn Memory>, MemoryError> {
+        let max_allowed = self.pool.limits.max_memory_pages;
+        if ty.minimum > max_allowed {
+            return Err(MemoryError::MinimumMemoryTooLarge {
+                min_requested: ty.minimum,
+                max_allowed,
+            });
+        }
+        if *style != self.memory_style() {
+            return Err(MemoryError::InvalidMemory {
+                reason: format!("the style {:?} is not the pool's memory style", style),
+            });
+        }
+        let size = ty.minimum.bytes().0;
+        let (index, base) = self
+            .pool
+            .memories
+            .acquire(size)
+            .map_err(MemoryError::Region)?;
+        {
+            let mut ptr = vm_definition_location;
+            let md = ptr.as_mut();
+            md.base = base;
+            md.current_length = u32::try_from(size).unwrap();
+        }
+        Ok(Arc::new(PooledMemory {
+            pool: self.pool.clone(),
+            index,
+            memory: *ty,
+            style: style.clone(),
+            size: Mutex::new(ty.minimum),
+            vm_memory_definition: vm_definition_location,
+        }))
+    }
+
+    /// Creates a table owned by the VM in a slot of the pool.
+    ///
+    /// # Safety
+    /// - `vm_definition_location` must point to a valid location in VM memory.
+    pub unsafe fn create_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+        vm_definition_location: NonNull<VMTableDefinition>,
+    ) -> Result<Arc<dyn Table>, String> {
+        if let Some(max) = ty.maximum {
+            if max < ty.minimum {
+                return Err(format!(
+                    "Table minimum ({}) is larger than maximum ({})!",
+                    ty.minimum, max
+                ));
+            }
+        }
+        if ty.minimum > self.pool.limits.max_table_elements {
+            return Err(format!(
+                "the table needs {} elements, but the pool allows {}",
+                ty.minimum, self.pool.limits.max_table_elements
+            ));
+        }
+        match ty.ty {
+            ValType::FuncRef | ValType::ExternRef => {}
+            ty => return Err(format!("tables of type {} are not supported", ty)),
+        }
+        let (index, base) = self.pool.tables.acquire(self.pool.tables.slot_size)?;
+        let table = PooledTable {
+            pool: self.pool.clone(),
+            index,
+            table: *ty,
+            style: style.clone(),
+            len: Mutex::new(0),
+            vm_table_definition: vm_definition_location,
+        };
+        {
+            let mut ptr = vm_definition_location;
+            let td = ptr.as_mut();
+            td.base = base;
+            td.current_elements = 0;
+        }
+        table
+            .grow(ty.minimum, VMReference::null(ty.ty))
+            .expect("the table fits in its slot");
+        Ok(Arc::new(table))
+    }
+}
+
+impl fmt::Debug for PoolingAllocator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PoolingAllocator")
+            .field("limits", &self.pool.limits)
+            .finish()
+    }
+}
+
+impl MemoryUsage for PoolingAllocator {
+    fn size_of_val(&self, _: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self)
+    }
+}
+
+/// The instance slot holding an `Instance`, released when the instance is
+/// deallocated.
+#[derive(Clone)]
+pub(crate) struct InstanceSlot {
+    pool: Arc<Pool>,
+    index: usize,
+}
+
+impl InstanceSlot {
+    /// Returns the slot to the pool.
+    ///
+    /// # Safety
+    ///
+    /// The `Instance` in the slot must have been dropped, and no clone of
+    /// this slot may be released again.
+    pub(crate) unsafe fn release(&self, layout: Layout) {
+        let used_size = round_up_to_page_size(layout.size(), region::page::size());
+        self.pool.instances.release(self.index, used_size);
+    }
+}
+
+impl fmt::Debug for InstanceSlot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InstanceSlot")
+            .field("index", &self.index)
+            .finish()
+    }
+}
+
+/// A linear memory in a slot of a [`PoolingAllocator`].
+///
+/// The memory never moves: it grows in place, up to the size of the slot.
+struct PooledMemory {
+    pool: Arc<Pool>,
+    index: usize,
+    memory: MemoryType,
+    style: MemoryStyle,
+    size: Mutex<Pages>,
+    vm_memory_definition: NonNull<VMMemoryDefinition>,
+}
+
+/// This is correct because the memory is owned by the pool, and the
+/// `VMMemoryDefinition` is only written while holding the `size` lock.
+unsafe impl Send for PooledMemory {}
+/// This is correct because all internal mutability is protected by a mutex.
+unsafe impl Sync for PooledMemory {}
+
+impl Memory for PooledMemory {
+    fn ty(&self) -> &MemoryType {
+        &self.memory
+    }
+
+    fn style(&self) -> &MemoryStyle {
+        &self.style
+    }
+
+    fn size(&self) -> Pages {
+        *self.size.lock().unwrap()
+    }
+
+    fn grow(&self, delta: Pages) -> Result<Pages, MemoryError> {
+        let mut size = self.size.lock().unwrap();
+        let prev_pages = *size;
+        if delta.0 == 0 {
+            return Ok(prev_pages);
+        }
+        let maximum = self
+            .memory
+            .maximum
+            .map_or(self.pool.limits.max_memory_pages, |max| {
+                max.min(self.pool.limits.max_memory_pages)
+            });
+        let new_pages = prev_pages
+            .checked_add(delta)
+            .filter(|new_pages| *new_pages <= maximum)
+            .ok_or(MemoryError::CouldNotGrow {
+                current: prev_pages,
+                attempted_delta: delta,
+            })?;
+
+        self.pool
+            .memories
+            .make_accessible(self.index, prev_pages.bytes().0, delta.bytes().0)
+            .map_err(MemoryError::Region)?;
+        *size = new_pages;
+        unsafe {
+            let mut md_ptr = self.vm_memory_definition;
+            md_ptr.as_mut().current_length = u32::try_from(new_pages.bytes().0).unwrap();
+        }
+        Ok(prev_pages)
+    }
+
+    fn vmmemory(&self) -> NonNull<VMMemoryDefinition> {
+        self.vm_memory_definition
+    }
+}
+
+impl Drop for PooledMemory {
+    fn drop(&mut self) {
+        let used_size = self.size.get_mut().unwrap().bytes().0;
+        self.pool.memories.release(self.index, used_size);
+    }
+}
+
+impl fmt::Debug for PooledMemory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PooledMemory")
+            .field("index", &self.index)
+            .field("memory", &self.memory)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl MemoryUsage for PooledMemory {
+    fn size_of_val(&self, _: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self) + self.size().bytes().0
+    }
+}
+
+/// A table in a slot of a [`PoolingAllocator`].
+///
+/// Elements are laid out as in a `LinearTable`: `funcref`s inline, as
+/// `VMCallerCheckedAnyfunc`s, and `externref`s as owned
+/// `Option<VMExternRef>`s. The table never moves: it grows in place, up to
+/// the size of the slot.
+struct PooledTable {
+    pool: Arc<Pool>,
+    index: usize,
+    table: TableType,
+    style: TableStyle,
+    len: Mutex<u32>,
+    vm_table_definition: NonNull<VMTableDefinition>,
+}
+
+/// This is correct because the table is owned by the pool, and the
+/// `VMTableDefinition` is only written while holding the `len` lock.
+unsafe impl Send for PooledTable {}
+/// This is correct because all internal mutability is protected by a mutex.
+unsafe impl Sync for PooledTable {}
+
+impl PooledTable {
+    fn base(&self) -> *mut u8 {
+        unsafe { self.vm_table_definition.as_ref().base }
+    }
+
+    /// Writes `value` at `index`, dropping the previous element if `init`
+    /// is false.
+    ///
+    /// # Safety
+    /// - `index` must be within the slot, and within the table unless
+    ///   `init` is true.
+    unsafe fn write(&self, index: u32, value: VMReference, init: bool) {
+        match (self.table.ty, value) {
+            (ValType::FuncRef, VMReference::FuncRef(anyfunc)) => {
+                let slot = (self.base() as *mut VMCallerCheckedAnyfunc).add(index as usize);
+                ptr::write(slot, anyfunc);
+            }
+            (ValType::ExternRef, VMReference::ExternRef(r)) => {
+                let slot = (self.base() as *mut Option<VMExternRef>).add(index as usize);
+                if init {
+                    ptr::write(slot, r);
+                } else {
+                    *slot = r;
+                }
+            }
+            (_, value) => panic!("{:?} doesn't match the table's element type", value),
+        }
+    }
+}
+
+impl Table for PooledTable {
+    fn ty(&self) -> &TableType {
+        &self.table
+    }
+
+    fn style(&self) -> &TableStyle {
+        &self.style
+    }
+
+    fn size(&self) -> u32 {
+        *self.len.lock().unwrap()
+    }
+
+    fn grow(&self, delta: u32, init_value: VMReference) -> Option<u32> {
+        let mut len = self.len.lock().unwrap();
+        let prev_len = *len;
+        let new_len = prev_len.checked_add(delta)?;
+        let maximum = self
+            .table
+            .maximum
+            .map_or(self.pool.limits.max_table_elements, |max| {
+                max.min(self.pool.limits.max_table_elements)
+            });
+        if new_len > maximum {
+            return None;
+        }
+        for index in prev_len..new_len {
+            unsafe { self.write(index, init_value.clone(), true) };
+        }
+        *len = new_len;
+        unsafe {
+            let mut td_ptr = self.vm_table_definition;
+            td_ptr.as_mut().current_elements = new_len;
+        }
+        Some(prev_len)
+    }
+
+    fn get(&self, index: u32) -> Option<VMReference> {
+        let len = self.len.lock().unwrap();
+        if index >= *len {
+            return None;
+        }
+        unsafe {
+            Some(match self.table.ty {
+                ValType::FuncRef => VMReference::FuncRef(
+                    (*(self.base() as *const VMCallerCheckedAnyfunc).add(index as usize)).clone(),
+                ),
+                _ => VMReference::ExternRef(
+                    (*(self.base() as *const Option<VMExternRef>).add(index as usize)).clone(),
+                ),
+            })
+        }
+    }
+
+    fn set(&self, index: u32, reference: VMReference) -> Result<(), Trap> {
+        let len = self.len.lock().unwrap();
+        if index >= *len {
+            return Err(Trap::new_from_runtime(TrapCode::TableAccessOutOfBounds));
+        }
+        unsafe { self.write(index, reference, false) };
+        Ok(())
+    }
+
+    fn vmtable(&self) -> NonNull<VMTableDefinition> {
+        self.vm_table_definition
+    }
+}
+
+impl Drop for PooledTable {
+    fn drop(&mut self) {
+        let len = *self.len.get_mut().unwrap() as usize;
+        if self.table.ty == ValType::ExternRef {
+            unsafe {
+                let refs = self.base() as *mut Option<VMExternRef>;
+                ptr::drop_in_place(std::slice::from_raw_parts_mut(refs, len));
+            }
+        }
+        let used_size = round_up_to_page_size(
+            len * mem::size_of::<VMCallerCheckedAnyfunc>(),
+            region::page::size(),
+        );
+        self.pool.tables.release(self.index, used_size);
+    }
+}
+
+impl fmt::Debug for PooledTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PooledTable")
+            .field("index", &self.index)
+            .field("table", &self.table)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl MemoryUsage for PooledTable {
+    fn size_of_val(&self, _: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self) + self.size() as usize * mem::size_of::<VMCallerCheckedAnyfunc>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> PoolingLimits {
+        PoolingLimits {
+            max_instances: 2,
+            max_memory_pages: Pages(2),
+            max_table_elements: 10,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn memory_slots_are_reset_and_reused() {
+        let pool = PoolingAllocator::new(limits()).unwrap();
+        let ty = MemoryType::new(1, None, false);
+        let style = pool.memory_style();
+        let mut definitions = [VMMemoryDefinition {
+            base: ptr::null_mut(),
+            current_length: 0,
+        }; 3];
+        let location = |definitions: &mut [VMMemoryDefinition; 3], i: usize| {
+            NonNull::new(&mut definitions[i] as *mut _).unwrap()
+        };
+
+        let memory =
+            unsafe { pool.create_memory(&ty, &style, location(&mut definitions, 0)) }.unwrap();
+        let other =
+            unsafe { pool.create_memory(&ty, &style, location(&mut definitions, 1)) }.unwrap();
+        assert_eq!(pool.memories_in_use(), 2);
+        assert!(matches!(
+            unsafe { pool.create_memory(&ty, &style, location(&mut definitions, 2)) },
+            Err(MemoryError::Region(_))
+        ));
+
+        assert_eq!(memory.grow(Pages(1)), Ok(Pages(1)));
+        assert!(memory.grow(Pages(1)).is_err());
+        unsafe {
+            let md = memory.vmmemory().as_ref();
+            *md.base.add(Pages(1).bytes().0) = 42;
+        }
+        let base = unsafe { memory.vmmemory().as_ref().base };
+        drop(other);
+        drop(memory);
+        assert_eq!(pool.memories_in_use(), 0);
+
+        let memory =
+            unsafe { pool.create_memory(&ty, &style, location(&mut definitions, 0)) }.unwrap();
+        assert_eq!(unsafe { memory.vmmemory().as_ref().base }, base);
+        memory.grow(Pages(1)).unwrap();
+        assert_eq!(unsafe { *base.add(Pages(1).bytes().0) }, 0);
+    }
+}
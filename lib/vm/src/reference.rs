@@ -0,0 +1,252 @@
+//! References handed to and received from compiled code.
+//!
+//! Compiled code represents both `funcref` and `externref` values as a single
+//! pointer, where a null pointer is the null reference:
+//!
+//! * a `funcref` points to a [`VMCallerCheckedAnyfunc`],
+//! * an `externref` points to the data of a reference-counted [`VMExternRef`].
+//!
+//! References on the wasm stack are not counted. Instead, whenever the VM
+//! hands a reference out to compiled code, it keeps it alive until the
+//! innermost [`ReferenceScope`] ends, which is when the call into wasm that
+//! entered the scope returns. Tables and globals own the references they hold.
+
+use crate::vmcontext::VMCallerCheckedAnyfunc;
+use loupe::{MemoryUsage, MemoryUsageTracker};
+use std::cell::RefCell;
+use std::fmt;
+use std::mem::{self, ManuallyDrop};
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{self, AtomicUsize, Ordering};
+use wasmer_types::{ExternRef, Type};
+
+/// The raw form of a reference, as seen by compiled code.
+pub type VMRawReference = *mut u8;
+
+struct VMExternData {
+    strong: AtomicUsize,
+    value: ExternRef,
+}
+
+/// A reference-counted `externref` as stored by the VM.
+///
+/// A null `externref` is represented by `None` in an `Option<VMExternRef>`,
+/// which has the same size as a pointer.
+#[repr(transparent)]
+pub struct VMExternRef(NonNull<VMExternData>);
+
+impl VMExternRef {
+    /// Wraps a host `ExternRef`, returning `None` if it is null.
+    pub fn new(value: ExternRef) -> Option<Self> {
+        if let ExternRef::Null = value {
+            return None;
+        }
+        let data = Box::new(VMExternData {
+            strong: AtomicUsize::new(1),
+            value,
+        });
+        Some(Self(NonNull::from(Box::leak(data))))
+    }
+
+    /// Returns the host `ExternRef` that this wraps.
+    pub fn extern_ref(&self) -> ExternRef {
+        unsafe { self.0.as_ref().value.clone() }
+    }
+
+    /// Converts a nullable reference into its raw form, transferring its
+    /// ownership to the raw pointer.
+    pub fn into_raw(this: Option<Self>) -> VMRawReference {
+        match this {
+            Some(r) => ManuallyDrop::new(r).0.as_ptr() as VMRawReference,
+            None => ptr::null_mut(),
+        }
+    }
+
+    /// Converts a raw reference back into a nullable reference, taking back
+    /// the ownership given to it by [`VMExternRef::into_raw`].
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be null or come from `VMExternRef::into_raw`, and must not
+    /// be used again.
+    pub unsafe fn from_raw(raw: VMRawReference) -> Option<Self> {
+        NonNull::new(raw as *mut VMExternData).map(Self)
+    }
+
+    /// Returns a new counted reference to the value that a raw reference
+    /// points to, without taking the raw reference's ownership.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be null or point to a live `externref`.
+    pub unsafe fn clone_from_raw(raw: VMRawReference) -> Option<Self> {
+        let borrowed = ManuallyDrop::new(Self::from_raw(raw));
+        (*borrowed).clone()
+    }
+}
+
+/// This is correct because `ExternRef` is `Send` and its count is atomic.
+unsafe impl Send for VMExternRef {}
+/// This is correct because `ExternRef` is `Sync` and its count is atomic.
+unsafe impl Sync for VMExternRef {}
+
+impl Clone for VMExternRef {
+    fn clone(&self) -> Self {
+        // Like `Arc`, a relaxed increment is enough since a new reference can
+        // only be created from an existing one.
+        unsafe { self.0.as_ref() }
+            .strong
+            .fetch_add(1, Ordering::Relaxed);
+        Self(self.0)
+    }
+}
+
+impl Drop for VMExternRef {
+    fn drop(&mut self) {
+        if unsafe { self.0.as_ref() }
+            .strong
+            .fetch_sub(1, Ordering::Release)
+            != 1
+        {
+            return;
+        }
+        atomic::fence(Ordering::Acquire);
+        drop(unsafe { Box::from_raw(self.0.as_ptr()) });
+    }
+}
+
+impl fmt::Debug for VMExternRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("VMExternRef").field(&self.0).finish()
+    }
+}
+
+impl MemoryUsage for VMExternRef {
+    fn size_of_val(&self, _: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self)
+    }
+}
+
+/// A `funcref` or an `externref` held by the VM.
+#[derive(Clone, Debug)]
+pub enum VMReference {
+    /// A `funcref`. The null reference has a null `func_ptr`.
+    FuncRef(VMCallerCheckedAnyfunc),
+    /// An `externref`.
+    ExternRef(Option<VMExternRef>),
+}
+
+impl VMReference {
+    /// Returns the null reference of type `ty`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ty` is not a reference type.
+    pub fn null(ty: Type) -> Self {
+        match ty {
+            Type::FuncRef => Self::FuncRef(VMCallerCheckedAnyfunc::default()),
+            Type::ExternRef => Self::ExternRef(None),
+            ty => panic!("`{}` is not a reference type", ty),
+        }
+    }
+
+    /// Returns whether this is a null reference.
+    pub fn is_null(&self) -> bool {
+        match self {
+            Self::FuncRef(anyfunc) => anyfunc.func_ptr.is_null(),
+            Self::ExternRef(r) => r.is_none(),
+        }
+    }
+
+    /// Reads a reference of type `ty` that compiled code handed out. The
+    /// reference is borrowed from the caller, which keeps it alive.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be null or point to a live reference of type `ty`.
+    pub unsafe fn from_raw(ty: Type, raw: VMRawReference) -> Self {
+        match ty {
+            Type::FuncRef if raw.is_null() => Self::null(ty),
+            Type::FuncRef => Self::FuncRef((*(raw as *const VMCallerCheckedAnyfunc)).clone()),
+            Type::ExternRef => Self::ExternRef(VMExternRef::clone_from_raw(raw)),
+            ty => panic!("`{}` is not a reference type", ty),
+        }
+    }
+
+    /// Hands this reference out to compiled code, keeping it alive until the
+    /// current [`ReferenceScope`] ends.
+    pub fn into_raw(self) -> VMRawReference {
+        if self.is_null() {
+            return ptr::null_mut();
+        }
+        let kept_alive = match self {
+            Self::FuncRef(anyfunc) => KeptAlive::FuncRef(Box::new(anyfunc)),
+            Self::ExternRef(r) => KeptAlive::ExternRef(r.unwrap()),
+        };
+        let raw = match &kept_alive {
+            KeptAlive::FuncRef(anyfunc) => &**anyfunc as *const VMCallerCheckedAnyfunc as _,
+            KeptAlive::ExternRef(r) => r.0.as_ptr() as _,
+        };
+        KEPT_ALIVE.with(|kept_alive_refs| kept_alive_refs.borrow_mut().push(kept_alive));
+        raw
+    }
+}
+
+enum KeptAlive {
+    FuncRef(Box<VMCallerCheckedAnyfunc>),
+    ExternRef(VMExternRef),
+}
+
+thread_local! {
+    /// The references handed out to compiled code running on this thread.
+    static KEPT_ALIVE: RefCell<Vec<KeptAlive>> = RefCell::new(Vec::new());
+}
+
+/// References kept alive for a call that has been suspended, set aside from
+/// the thread while the call isn't running.
+#[derive(Default)]
+pub(crate) struct KeptAliveRefs(Vec<KeptAlive>);
+
+impl KeptAliveRefs {
+    /// Swaps the set-aside references with the ones of the current thread.
+    pub(crate) fn swap(&mut self) {
+        KEPT_ALIVE
+            .with(|kept_alive_refs| mem::swap(&mut self.0, &mut *kept_alive_refs.borrow_mut()));
+    }
+}
+
+/// Keeps the references handed out to compiled code alive while it exists.
+///
+/// A scope should be entered around each call into wasm that can pass
+/// references in or out, including the conversion of its results. Scopes
+/// nest: dropping a scope releases the references handed out since it was
+/// entered, and only those.
+///
+/// Note that references handed out are only released when the scope ends, so
+/// a long-running call that keeps reading references out of tables or globals
+/// keeps accumulating them.
+#[derive(Debug)]
+pub struct ReferenceScope {
+    base: usize,
+}
+
+impl ReferenceScope {
+    /// Enters a new scope on the current thread.
+    pub fn enter() -> Self {
+        let base = KEPT_ALIVE.with(|kept_alive_refs| kept_alive_refs.borrow().len());
+        Self { base }
+    }
+}
+
+impl Drop for ReferenceScope {
+    fn drop(&mut self) {
+        // Dropping an `externref` may run host code, so don't hold the borrow
+        // while releasing the references.
+        let released = KEPT_ALIVE.with(|kept_alive_refs| {
+            let mut kept_alive_refs = kept_alive_refs.borrow_mut();
+            let base = self.base.min(kept_alive_refs.len());
+            kept_alive_refs.split_off(base)
+        });
+        drop(released);
+    }
+}
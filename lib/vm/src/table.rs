@@ -5,6 +5,7 @@
 //!
 //! `Table` is to WebAssembly tables what `LinearMemory` is to WebAssembly linear memories.
 
+use crate::reference::{VMExternRef, VMReference};
 use crate::trap::{Trap, TrapCode};
 use crate::vmcontext::{VMCallerCheckedAnyfunc, VMTableDefinition};
 use loupe::MemoryUsage;
@@ -35,23 +36,32 @@ pub trait Table: fmt::Debug + Send + Sync + MemoryUsage {
     /// Returns the number of allocated elements.
     fn size(&self) -> u32;
 
-    /// Grow table by the specified amount of elements.
+    /// Grow table by the specified amount of elements, setting the new
+    /// elements to `init_value`.
     ///
     /// Returns `None` if table can't be grown by the specified amount
     /// of elements, otherwise returns the previous size of the table.
-    fn grow(&self, delta: u32) -> Option<u32>;
+    ///
+    /// # Panics
+    ///
+    /// Panics if `init_value` is not of the table's element type.
+    fn grow(&self, delta: u32, init_value: VMReference) -> Option<u32>;
 
     /// Get reference to the specified element.
     ///
     /// Returns `None` if the index is out of bounds.
-    fn get(&self, index: u32) -> Option<VMCallerCheckedAnyfunc>;
+    fn get(&self, index: u32) -> Option<VMReference>;
 
     /// Set reference to the specified element.
     ///
     /// # Errors
     ///
     /// Returns an error if the index is out of bounds.
-    fn set(&self, index: u32, func: VMCallerCheckedAnyfunc) -> Result<(), Trap>;
+    ///
+    /// # Panics
+    ///
+    /// Panics if `reference` is not of the table's element type.
+    fn set(&self, index: u32, reference: VMReference) -> Result<(), Trap>;
 
     /// Return a `VMTableDefinition` for exposing the table to compiled wasm code.
     fn vmtable(&self) -> NonNull<VMTableDefinition>;
@@ -107,7 +117,7 @@ pub trait Table: fmt::Debug + Send + Sync + MemoryUsage {
 #[derive(Debug, MemoryUsage)]
 pub struct LinearTable {
     // TODO: we can remove the mutex by using atomic swaps and preallocating the max table size
-    vec: Mutex<Vec<VMCallerCheckedAnyfunc>>,
+    vec: Mutex<TableElements>,
     maximum: Option<u32>,
     /// The WebAssembly table description.
     table: TableType,
@@ -116,6 +126,66 @@ pub struct LinearTable {
     vm_table_definition: VMTableDefinitionOwnership,
 }
 
+/// The elements of a `LinearTable`.
+#[derive(Debug, MemoryUsage)]
+enum TableElements {
+    /// `funcref`s are stored inline, as compiled code reads them directly
+    /// for `call_indirect`.
+    FuncRefs(Vec<VMCallerCheckedAnyfunc>),
+    /// `externref`s are only accessed through libcalls, and each element owns
+    /// a count of its reference.
+    ExternRefs(Vec<Option<VMExternRef>>),
+}
+
+impl TableElements {
+    /// Create `len` null elements of type `ty`.
+    fn new(ty: ValType, len: usize) -> Result<Self, String> {
+        match ty {
+            ValType::FuncRef => Ok(Self::FuncRefs(vec![VMCallerCheckedAnyfunc::default(); len])),
+            ValType::ExternRef => Ok(Self::ExternRefs(vec![None; len])),
+            ty => Err(format!("tables of type {} are not supported", ty)),
+        }
+    }
+
+    /// A pointer to the first element, as seen by compiled code.
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        match self {
+            Self::FuncRefs(funcs) => funcs.as_mut_ptr() as _,
+            Self::ExternRefs(refs) => refs.as_mut_ptr() as _,
+        }
+    }
+
+    fn resize(&mut self, new_len: usize, value: VMReference) {
+        match (self, value) {
+            (Self::FuncRefs(funcs), VMReference::FuncRef(anyfunc)) => {
+                funcs.resize(new_len, anyfunc)
+            }
+            (Self::ExternRefs(refs), VMReference::ExternRef(r)) => refs.resize(new_len, r),
+            (_, value) => panic!("{:?} doesn't match the table's element type", value),
+        }
+    }
+
+    fn get(&self, index: usize) -> Option<VMReference> {
+        match self {
+            Self::FuncRefs(funcs) => funcs.get(index).cloned().map(VMReference::FuncRef),
+            Self::ExternRefs(refs) => refs.get(index).cloned().map(VMReference::ExternRef),
+        }
+    }
+
+    /// Set the element at `index`, returning `false` if it is out of bounds.
+    fn set(&mut self, index: usize, value: VMReference) -> bool {
+        match (self, value) {
+            (Self::FuncRefs(funcs), VMReference::FuncRef(anyfunc)) => {
+                funcs.get_mut(index).map(|slot| *slot = anyfunc).is_some()
+            }
+            (Self::ExternRefs(refs), VMReference::ExternRef(r)) => {
+                refs.get_mut(index).map(|slot| *slot = r).is_some()
+            }
+            (_, value) => panic!("{:?} doesn't match the table's element type", value),
+        }
+    }
+}
+
 /// A type to help manage who is responsible for the backing table of the
 /// `VMTableDefinition`.
 #[derive(Debug, MemoryUsage)]
@@ -164,10 +234,6 @@ impl LinearTable {
         style: &TableStyle,
         vm_table_location: Option<NonNull<VMTableDefinition>>,
     ) -> Result<Self, String> {
-        match table.ty {
-            ValType::FuncRef => (),
-            ty => return Err(format!("tables of types other than anyfunc ({})", ty)),
-        };
         if let Some(max) = table.maximum {
             if max < table.minimum {
                 return Err(format!(
@@ -178,7 +244,7 @@ impl LinearTable {
         }
         let table_minimum = usize::try_from(table.minimum)
             .map_err(|_| "Table minimum is bigger than usize".to_string())?;
-        let mut vec = vec![VMCallerCheckedAnyfunc::default(); table_minimum];
+        let mut vec = TableElements::new(table.ty, table_minimum)?;
         let base = vec.as_mut_ptr();
         match style {
             TableStyle::CallerChecksSignature => Ok(Self {
@@ -242,11 +308,12 @@ impl Table for LinearTable {
         }
     }
 
-    /// Grow table by the specified amount of elements.
+    /// Grow table by the specified amount of elements, setting the new
+    /// elements to `init_value`.
     ///
     /// Returns `None` if table can't be grown by the specified amount
     /// of elements, otherwise returns the previous size of the table.
-    fn grow(&self, delta: u32) -> Option<u32> {
+    fn grow(&self, delta: u32, init_value: VMReference) -> Option<u32> {
         let mut vec_guard = self.vec.lock().unwrap();
         let vec = vec_guard.borrow_mut();
         let size = self.size();
@@ -254,10 +321,7 @@ impl Table for LinearTable {
         if self.maximum.map_or(false, |max| new_len > max) {
             return None;
         }
-        vec.resize(
-            usize::try_from(new_len).unwrap(),
-            VMCallerCheckedAnyfunc::default(),
-        );
+        vec.resize(usize::try_from(new_len).unwrap(), init_value);
 
         // update table definition
         unsafe {
@@ -272,9 +336,9 @@ impl Table for LinearTable {
     /// Get reference to the specified element.
     ///
     /// Returns `None` if the index is out of bounds.
-    fn get(&self, index: u32) -> Option<VMCallerCheckedAnyfunc> {
+    fn get(&self, index: u32) -> Option<VMReference> {
         let vec_guard = self.vec.lock().unwrap();
-        vec_guard.borrow().get(index as usize).cloned()
+        vec_guard.borrow().get(index as usize)
     }
 
     /// Set reference to the specified element.
@@ -282,15 +346,13 @@ impl Table for LinearTable {
     /// # Errors
     ///
     /// Returns an error if the index is out of bounds.
-    fn set(&self, index: u32, func: VMCallerCheckedAnyfunc) -> Result<(), Trap> {
+    fn set(&self, index: u32, reference: VMReference) -> Result<(), Trap> {
         let mut vec_guard = self.vec.lock().unwrap();
         let vec = vec_guard.borrow_mut();
-        match vec.get_mut(index as usize) {
-            Some(slot) => {
-                *slot = func;
-                Ok(())
-            }
-            None => Err(Trap::new_from_runtime(TrapCode::TableAccessOutOfBounds)),
+        if vec.set(index as usize, reference) {
+            Ok(())
+        } else {
+            Err(Trap::new_from_runtime(TrapCode::TableAccessOutOfBounds))
         }
     }
 
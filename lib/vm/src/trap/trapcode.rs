@@ -74,6 +74,12 @@ pub enum TrapCode {
 
     /// A trap indicating that the runtime was unable to allocate sufficient memory.
     VMOutOfMemory = 15,
+
+    /// The call depth limit of a `StackLimit` middleware was exceeded.
+    ///
+    /// Unlike `StackOverflow`, this is deterministic: it happens at the same
+    /// point of the execution on every compiler and platform.
+    StackLimitExceeded = 16,
     // /// A user-defined trap code.
     // User(u16),
 }
@@ -98,9 +104,36 @@ impl TrapCode {
             Self::Interrupt => "interrupt",
             Self::UnalignedAtomic => "unaligned atomic access",
             Self::VMOutOfMemory => "out of memory",
+            Self::StackLimitExceeded => "stack limit exceeded",
             // Self::User(_) => unreachable!(),
         }
     }
+
+    /// Gets the trap code with the given number, the inverse of `code as u32`.
+    pub fn from_u32(code: u32) -> Option<Self> {
+        use self::TrapCode::*;
+
+        Some(match code {
+            0 => StackOverflow,
+            1 => HeapSetterOutOfBounds,
+            2 => HeapAccessOutOfBounds,
+            3 => HeapMisaligned,
+            4 => TableSetterOutOfBounds,
+            5 => TableAccessOutOfBounds,
+            6 => OutOfBounds,
+            7 => IndirectCallToNull,
+            8 => BadSignature,
+            9 => IntegerOverflow,
+            10 => IntegerDivisionByZero,
+            11 => BadConversionToInteger,
+            12 => UnreachableCodeReached,
+            13 => Interrupt,
+            14 => UnalignedAtomic,
+            15 => VMOutOfMemory,
+            16 => StackLimitExceeded,
+            _ => return None,
+        })
+    }
 }
 
 impl Display for TrapCode {
@@ -122,6 +155,7 @@ impl Display for TrapCode {
             Self::Interrupt => "interrupt",
             Self::UnalignedAtomic => "unalign_atom",
             Self::VMOutOfMemory => "oom",
+            Self::StackLimitExceeded => "stk_limit",
             // User(x) => return write!(f, "user{}", x),
         };
         f.write_str(identifier)
@@ -150,6 +184,7 @@ impl FromStr for TrapCode {
             "interrupt" => Ok(Interrupt),
             "unalign_atom" => Ok(UnalignedAtomic),
             "oom" => Ok(VMOutOfMemory),
+            "stk_limit" => Ok(StackLimitExceeded),
             // _ if s.starts_with("user") => s[4..].parse().map(User).map_err(|_| ()),
             _ => Err(()),
         }
@@ -161,7 +196,7 @@ mod tests {
     use super::*;
 
     // Everything but user-defined codes.
-    const CODES: [TrapCode; 15] = [
+    const CODES: [TrapCode; 16] = [
         TrapCode::StackOverflow,
         TrapCode::HeapSetterOutOfBounds,
         TrapCode::HeapAccessOutOfBounds,
@@ -177,6 +212,7 @@ mod tests {
         TrapCode::UnreachableCodeReached,
         TrapCode::Interrupt,
         TrapCode::UnalignedAtomic,
+        TrapCode::StackLimitExceeded,
     ];
 
     #[test]
@@ -193,4 +229,13 @@ mod tests {
         assert_eq!("user-1".parse::<TrapCode>(), Err(()));
         assert_eq!("users".parse::<TrapCode>(), Err(()));
     }
+
+    #[test]
+    fn from_u32() {
+        for r in &CODES {
+            let tc = *r;
+            assert_eq!(TrapCode::from_u32(tc as u32), Some(tc));
+        }
+        assert_eq!(TrapCode::from_u32(17), None);
+    }
 }
@@ -6,6 +6,7 @@
 
 use super::trapcode::TrapCode;
 use crate::instance::{Instance, SignalHandler};
+use crate::reference::KeptAliveRefs;
 use crate::vmcontext::{VMFunctionBody, VMFunctionEnvironment, VMTrampoline};
 use backtrace::Backtrace;
 use std::any::Any;
@@ -566,11 +567,96 @@ pub struct CallThreadState {
     unwind: Cell<UnwindReason>,
     jmp_buf: Cell<*const u8>,
     reset_guard_page: Cell<bool>,
-    prev: Option<*const CallThreadState>,
+    prev: Cell<*const CallThreadState>,
     vmctx: VMFunctionEnvironment,
     handling_trap: Cell<bool>,
 }
 
+/// The part of a thread's call state owned by a call that can be suspended,
+/// such as a call running on a separate stack.
+///
+/// Calls into wasm push a `CallThreadState` onto a thread-local list, and
+/// calls that hand out references keep them alive in a thread-local list as
+/// well. When a call is suspended, the states it pushed must be taken off
+/// the thread, since the thread goes on to run other code, and put back when
+/// the call is resumed, possibly on another thread. `enter` and `leave`
+/// must be called around each resumption of the suspendable call.
+pub struct AsyncCallState {
+    outer: *const CallThreadState,
+    first: *const CallThreadState,
+    last: *const CallThreadState,
+    kept_alive: KeptAliveRefs,
+}
+
+// The saved `CallThreadState`s live on the suspended stack, which is only
+// resumed through `enter`.
+unsafe impl Send for AsyncCallState {}
+
+impl AsyncCallState {
+    /// Creates the state of a call that has not started yet.
+    pub fn new() -> Self {
+        Self {
+            outer: ptr::null(),
+            first: ptr::null(),
+            last: ptr::null(),
+            kept_alive: KeptAliveRefs::default(),
+        }
+    }
+
+    /// Installs the saved call state on the current thread, on top of the
+    /// calls already running on it.
+    ///
+    /// # Safety
+    ///
+    /// Must be called right before resuming the suspendable call, and be
+    /// followed by a call to `leave` on the same thread right after it
+    /// suspends or returns.
+    pub unsafe fn enter(&mut self) -> Result<(), Trap> {
+        // Traps on the resumed stack are handled on this thread.
+        #[cfg(unix)]
+        setup_unix_sigaltstack()?;
+
+        self.outer = tls::raw_get();
+        if !self.first.is_null() {
+            (*self.first).prev.set(self.outer);
+            tls::raw_set(self.last);
+        }
+        self.kept_alive.swap();
+        Ok(())
+    }
+
+    /// Takes the call state pushed since `enter` off the current thread, and
+    /// restores the state of the calls that were already running.
+    ///
+    /// # Safety
+    ///
+    /// Must be called right after the suspendable call suspends or returns.
+    pub unsafe fn leave(&mut self) {
+        self.kept_alive.swap();
+
+        let last = tls::raw_get();
+        self.first = ptr::null();
+        self.last = ptr::null();
+        let mut state = last;
+        while state != self.outer {
+            let prev = (*state).prev.get();
+            if prev == self.outer {
+                self.first = state;
+                self.last = last;
+                break;
+            }
+            state = prev;
+        }
+        tls::raw_set(self.outer);
+    }
+}
+
+impl Default for AsyncCallState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 enum UnwindReason {
     None,
     Panic(Box<dyn Any + Send>),
@@ -590,39 +676,36 @@ impl CallThreadState {
             vmctx,
             jmp_buf: Cell::new(ptr::null()),
             reset_guard_page: Cell::new(false),
-            prev: None,
+            prev: Cell::new(ptr::null()),
             handling_trap: Cell::new(false),
         }
     }
 
-    fn with(mut self, closure: impl FnOnce(&Self) -> i32) -> Result<(), Trap> {
-        tls::with(|prev| {
-            self.prev = prev.map(|p| p as *const _);
-            let ret = tls::set(&self, || closure(&self));
-            match self.unwind.replace(UnwindReason::None) {
-                UnwindReason::None => {
-                    debug_assert_eq!(ret, 1);
-                    Ok(())
-                }
-                UnwindReason::UserTrap(data) => {
-                    debug_assert_eq!(ret, 0);
-                    Err(Trap::new_from_user(data))
-                }
-                UnwindReason::LibTrap(trap) => Err(trap),
-                UnwindReason::RuntimeTrap {
-                    backtrace,
-                    pc,
-                    signal_trap,
-                } => {
-                    debug_assert_eq!(ret, 0);
-                    Err(Trap::new_from_wasm(pc, backtrace, signal_trap))
-                }
-                UnwindReason::Panic(panic) => {
-                    debug_assert_eq!(ret, 0);
-                    std::panic::resume_unwind(panic)
-                }
+    fn with(self, closure: impl FnOnce(&Self) -> i32) -> Result<(), Trap> {
+        let ret = tls::set(&self, || closure(&self));
+        match self.unwind.replace(UnwindReason::None) {
+            UnwindReason::None => {
+                debug_assert_eq!(ret, 1);
+                Ok(())
             }
-        })
+            UnwindReason::UserTrap(data) => {
+                debug_assert_eq!(ret, 0);
+                Err(Trap::new_from_user(data))
+            }
+            UnwindReason::LibTrap(trap) => Err(trap),
+            UnwindReason::RuntimeTrap {
+                backtrace,
+                pc,
+                signal_trap,
+            } => {
+                debug_assert_eq!(ret, 0);
+                Err(Trap::new_from_wasm(pc, backtrace, signal_trap))
+            }
+            UnwindReason::Panic(panic) => {
+                debug_assert_eq!(ret, 0);
+                std::panic::resume_unwind(panic)
+            }
+        }
     }
 
     fn any_instance(&self, func: impl Fn(&Instance) -> bool) -> bool {
@@ -636,9 +719,11 @@ impl CallThreadState {
             ) {
                 return true;
             }
-            match self.prev {
-                Some(prev) => (*prev).any_instance(func),
-                None => false,
+            let prev = self.prev.get();
+            if prev.is_null() {
+                false
+            } else {
+                (*prev).any_instance(func)
             }
         }
     }
@@ -747,19 +832,22 @@ mod tls {
     /// Configures thread local state such that for the duration of the
     /// execution of `closure` any call to `with` will yield `ptr`, unless this
     /// is recursively called again.
+    ///
+    /// The previous state is recorded in `ptr.prev` and restored from there,
+    /// rather than from this stack frame, because the call may be suspended
+    /// and resumed under a different previous state (see `AsyncCallState`).
     pub fn set<R>(ptr: &CallThreadState, closure: impl FnOnce() -> R) -> R {
-        struct Reset<'a, T: Copy>(&'a Cell<T>, T);
+        struct Reset<'a>(&'a CallThreadState);
 
-        impl<T: Copy> Drop for Reset<'_, T> {
+        impl Drop for Reset<'_> {
             fn drop(&mut self) {
-                self.0.set(self.1);
+                raw_set(self.0.prev.get());
             }
         }
 
-        PTR.with(|p| {
-            let _r = Reset(p, p.replace(ptr));
-            closure()
-        })
+        ptr.prev.set(PTR.with(|p| p.replace(ptr)));
+        let _r = Reset(ptr);
+        closure()
     }
 
     /// Returns the last pointer configured with `set` above. Panics if `set`
@@ -770,6 +858,16 @@ mod tls {
             unsafe { closure(if p.is_null() { None } else { Some(&*p) }) }
         })
     }
+
+    /// Returns the raw pointer to the innermost `CallThreadState`.
+    pub fn raw_get() -> *const CallThreadState {
+        PTR.with(|p| p.get())
+    }
+
+    /// Replaces the raw pointer to the innermost `CallThreadState`.
+    pub fn raw_set(ptr: *const CallThreadState) {
+        PTR.with(|p| p.set(ptr))
+    }
 }
 
 /// A module for registering a custom alternate signal stack (sigaltstack).
@@ -15,7 +15,8 @@ use std::convert::TryFrom;
 use std::fmt;
 use std::mem;
 use std::ptr::{self, NonNull};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::u32;
 
 /// Union representing the first parameter passed when calling a function.
@@ -838,6 +839,289 @@ impl Default for VMCallerCheckedAnyfunc {
     }
 }
 
+/// A callback run when the epoch deadline of a `Store` is reached.
+///
+/// It returns the number of epoch ticks to extend the deadline by, or an
+/// error to trap with.
+pub type EpochDeadlineCallback =
+    dyn FnMut() -> Result<u64, Box<dyn std::error::Error + Send + Sync>> + Send;
+
+/// The interruption state shared between a `Store` and the instances created
+/// in it.
+///
+/// Compiled code checks `interrupted` and compares the engine epoch against
+/// `epoch_deadline` at function entries and loop headers. It calls into the
+/// runtime when the flag is set or the deadline is reached.
+///
+/// An interruption stops every call running in the store. The flag is
+/// cleared when the first call starts and when the last one returns, so that
+/// it never reaches a call started afterwards.
+#[repr(C)]
+pub struct VMInterrupts {
+    /// Non-zero when execution has been requested to stop.
+    pub interrupted: AtomicU32,
+
+    /// The epoch at which execution stops or the deadline callback runs.
+    pub epoch_deadline: AtomicU64,
+
+    /// The epoch counter of the engine.
+    pub epoch: *const AtomicU64,
+    // If more elements accessed by compiled code are added here, remember to
+    // add offset_of tests below!
+    /// Keeps `epoch` alive.
+    epoch_counter: Arc<AtomicU64>,
+
+    /// Run when the deadline is reached; the code traps when there is none.
+    epoch_deadline_callback: Mutex<EpochDeadlineCallbackSlot>,
+
+    /// The number of calls into WebAssembly currently running.
+    running_calls: AtomicUsize,
+}
+
+// The raw `epoch` pointer points into `epoch_counter`.
+unsafe impl Send for VMInterrupts {}
+unsafe impl Sync for VMInterrupts {}
+
+impl VMInterrupts {
+    /// Create a new `VMInterrupts` reading the epoch from `epoch_counter`,
+    /// with no deadline.
+    pub fn new(epoch_counter: Arc<AtomicU64>) -> Self {
+        Self {
+            interrupted: AtomicU32::new(0),
+            epoch_deadline: AtomicU64::new(u64::MAX),
+            epoch: Arc::as_ptr(&epoch_counter),
+            epoch_counter,
+            epoch_deadline_callback: Mutex::new(EpochDeadlineCallbackSlot::default()),
+            running_calls: AtomicUsize::new(0),
+        }
+    }
+
+    /// Request that the running WebAssembly calls trap at their next check.
+    ///
+    /// This has no effect when no call is running.
+    pub fn interrupt(&self) {
+        self.interrupted.store(1, Ordering::SeqCst);
+    }
+
+    /// Returns whether the running calls have been requested to stop.
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst) != 0
+    }
+
+    /// Mark a call into WebAssembly as running until the returned scope is
+    /// dropped.
+    ///
+    /// The interrupt flag is cleared when the first call enters and when the
+    /// last one exits, so a request made while nothing runs, or racing with
+    /// the end of the last call, is dropped.
+    pub fn enter(&self) -> InterruptScope<'_> {
+        if self.running_calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            self.interrupted.store(0, Ordering::SeqCst);
+        }
+        InterruptScope { interrupts: self }
+    }
+
+    /// Set the deadline to `ticks_beyond_current` ticks after the current
+    /// epoch.
+    pub fn set_epoch_deadline(&self, ticks_beyond_current: u64) {
+        let current = self.epoch_counter.load(Ordering::SeqCst);
+        self.epoch_deadline.store(
+            current.saturating_add(ticks_beyond_current),
+            Ordering::SeqCst,
+        );
+    }
+
+    /// Set the callback to run when the deadline is reached. With `None`,
+    /// the running code traps with [`TrapCode::Interrupt`] instead.
+    pub fn set_epoch_deadline_callback(&self, callback: Option<Box<EpochDeadlineCallback>>) {
+        let mut slot = self.epoch_deadline_callback.lock().unwrap();
+        slot.callback = callback;
+        slot.generation += 1;
+    }
+
+    /// Handle a check from compiled code, returning the trap to raise if
+    /// any.
+    pub(crate) fn check(&self) -> Result<(), Trap> {
+        if self.is_interrupted() {
+            return Err(Trap::new_from_runtime(TrapCode::Interrupt));
+        }
+        let current = self.epoch_counter.load(Ordering::SeqCst);
+        if current < self.epoch_deadline.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        // The callback runs without holding the lock, so that it can change
+        // the deadline behavior or call into WebAssembly again. Until it
+        // returns, there is no deadline.
+        let (mut callback, generation, deadline) = {
+            let mut slot = self.epoch_deadline_callback.lock().unwrap();
+            let deadline = self.epoch_deadline.load(Ordering::SeqCst);
+            if current < deadline {
+                // Another call ran the callback while this one was waiting.
+                return Ok(());
+            }
+            match slot.callback.take() {
+                Some(callback) => {
+                    self.epoch_deadline.store(u64::MAX, Ordering::SeqCst);
+                    (callback, slot.generation, deadline)
+                }
+                None => return Err(Trap::new_from_runtime(TrapCode::Interrupt)),
+            }
+        };
+        let result = callback();
+
+        let mut slot = self.epoch_deadline_callback.lock().unwrap();
+        // Put the callback back, unless it was replaced while it ran.
+        if slot.generation == generation {
+            slot.callback = Some(callback);
+        }
+        match result {
+            Ok(ticks) => {
+                self.set_epoch_deadline(ticks);
+                Ok(())
+            }
+            Err(error) => {
+                self.epoch_deadline.store(deadline, Ordering::SeqCst);
+                Err(Trap::new_from_user(error))
+            }
+        }
+    }
+}
+
+/// The epoch deadline callback of a store, with the number of times it was
+/// set so that a callback replaced while it runs isn't put back.
+#[derive(Default)]
+struct EpochDeadlineCallbackSlot {
+    callback: Option<Box<EpochDeadlineCallback>>,
+    generation: u64,
+}
+
+/// A call into WebAssembly running in a store, see [`VMInterrupts::enter`].
+#[derive(Debug)]
+pub struct InterruptScope<'a> {
+    interrupts: &'a VMInterrupts,
+}
+
+impl Drop for InterruptScope<'_> {
+    fn drop(&mut self) {
+        let interrupts = self.interrupts;
+        if interrupts.running_calls.fetch_sub(1, Ordering::SeqCst) == 1 {
+            interrupts.interrupted.store(0, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Default for VMInterrupts {
+    fn default() -> Self {
+        Self::new(Arc::new(AtomicU64::new(0)))
+    }
+}
+
+impl fmt::Debug for VMInterrupts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VMInterrupts")
+            .field("interrupted", &self.interrupted)
+            .field("epoch_deadline", &self.epoch_deadline)
+            .field("epoch", &self.epoch_counter)
+            .finish()
+    }
+}
+
+impl MemoryUsage for VMInterrupts {
+    fn size_of_val(&self, _: &mut dyn MemoryUsageTracker) -> usize {
+        mem::size_of_val(self)
+    }
+}
+
+#[cfg(test)]
+mod test_vminterrupts {
+    use super::VMInterrupts;
+    use crate::{ModuleInfo, VMOffsets};
+    use memoffset::offset_of;
+    use std::mem::size_of;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn check_vminterrupts_offsets() {
+        let module = ModuleInfo::new();
+        let offsets = VMOffsets::new(size_of::<*mut u8>() as u8, &module);
+        assert_eq!(
+            offset_of!(VMInterrupts, interrupted),
+            usize::from(offsets.vminterrupts_interrupted())
+        );
+        assert_eq!(
+            size_of::<u32>(),
+            usize::from(offsets.size_of_vminterrupts_interrupted())
+        );
+        assert_eq!(
+            offset_of!(VMInterrupts, epoch_deadline),
+            usize::from(offsets.vminterrupts_epoch_deadline())
+        );
+        assert_eq!(
+            offset_of!(VMInterrupts, epoch),
+            usize::from(offsets.vminterrupts_epoch())
+        );
+    }
+
+    #[test]
+    fn flag_is_scoped_to_running_calls() {
+        let interrupts = VMInterrupts::default();
+
+        // A request made while nothing runs is dropped by the next call.
+        interrupts.interrupt();
+        let outer = interrupts.enter();
+        assert!(!interrupts.is_interrupted());
+
+        // A request reaches every running call, and stays set until the
+        // last one exits.
+        let inner = interrupts.enter();
+        interrupts.interrupt();
+        assert!(interrupts.check().is_err());
+        drop(inner);
+        assert!(interrupts.check().is_err());
+        drop(outer);
+        assert!(!interrupts.is_interrupted());
+    }
+
+    #[test]
+    fn epoch_deadline() {
+        let epoch = Arc::new(AtomicU64::new(0));
+        let interrupts = VMInterrupts::new(epoch.clone());
+        assert!(interrupts.check().is_ok());
+
+        interrupts.set_epoch_deadline(2);
+        epoch.fetch_add(1, Ordering::SeqCst);
+        assert!(interrupts.check().is_ok());
+        epoch.fetch_add(1, Ordering::SeqCst);
+        assert!(interrupts.check().is_err());
+
+        interrupts.set_epoch_deadline_callback(Some(Box::new(|| Ok(3))));
+        assert!(interrupts.check().is_ok());
+        assert_eq!(interrupts.epoch_deadline.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn epoch_deadline_callback_can_replace_itself() {
+        let epoch = Arc::new(AtomicU64::new(0));
+        let interrupts = Arc::new(VMInterrupts::new(epoch.clone()));
+        interrupts.set_epoch_deadline(1);
+        let weak = Arc::downgrade(&interrupts);
+        interrupts.set_epoch_deadline_callback(Some(Box::new(move || {
+            let interrupts = weak.upgrade().unwrap();
+            // Nested checks see no deadline while the callback runs.
+            assert!(interrupts.check().is_ok());
+            interrupts.set_epoch_deadline_callback(None);
+            Ok(1)
+        })));
+
+        epoch.fetch_add(1, Ordering::SeqCst);
+        assert!(interrupts.check().is_ok());
+        epoch.fetch_add(1, Ordering::SeqCst);
+        assert!(interrupts.check().is_err());
+    }
+}
+
 /// An index type for builtin functions.
 #[derive(Copy, Clone, Debug)]
 pub struct VMBuiltinFunctionIndex(u32);
@@ -900,9 +1184,42 @@ impl VMBuiltinFunctionIndex {
     pub const fn get_raise_trap_index() -> Self {
         Self(13)
     }
+    /// Returns an index for wasm's `table.get` instruction.
+    pub const fn get_table_get_index() -> Self {
+        Self(14)
+    }
+    /// Returns an index for wasm's `table.set` instruction.
+    pub const fn get_table_set_index() -> Self {
+        Self(15)
+    }
+    /// Returns an index for wasm's `table.grow` instruction.
+    pub const fn get_table_grow_index() -> Self {
+        Self(16)
+    }
+    /// Returns an index for wasm's `table.fill` instruction.
+    pub const fn get_table_fill_index() -> Self {
+        Self(17)
+    }
+    /// Returns an index for wasm's `ref.func` instruction.
+    pub const fn get_func_ref_index() -> Self {
+        Self(18)
+    }
+    /// Returns an index for wasm's `global.get` of a reference type.
+    pub const fn get_global_get_ref_index() -> Self {
+        Self(19)
+    }
+    /// Returns an index for wasm's `global.set` of a reference type.
+    pub const fn get_global_set_ref_index() -> Self {
+        Self(20)
+    }
+    /// Returns an index for the interrupt and epoch deadline check performed
+    /// at function entries and loop headers.
+    pub const fn get_interrupt_index() -> Self {
+        Self(21)
+    }
     /// Returns the total number of builtin functions.
     pub const fn builtin_functions_total_number() -> u32 {
-        14
+        22
     }
 
     /// Return the index as an u32 number.
@@ -960,6 +1277,22 @@ impl VMBuiltinFunctionsArray {
             wasmer_data_drop as usize;
         ptrs[VMBuiltinFunctionIndex::get_raise_trap_index().index() as usize] =
             wasmer_raise_trap as usize;
+        ptrs[VMBuiltinFunctionIndex::get_table_get_index().index() as usize] =
+            wasmer_table_get as usize;
+        ptrs[VMBuiltinFunctionIndex::get_table_set_index().index() as usize] =
+            wasmer_table_set as usize;
+        ptrs[VMBuiltinFunctionIndex::get_table_grow_index().index() as usize] =
+            wasmer_table_grow as usize;
+        ptrs[VMBuiltinFunctionIndex::get_table_fill_index().index() as usize] =
+            wasmer_table_fill as usize;
+        ptrs[VMBuiltinFunctionIndex::get_func_ref_index().index() as usize] =
+            wasmer_func_ref as usize;
+        ptrs[VMBuiltinFunctionIndex::get_global_get_ref_index().index() as usize] =
+            wasmer_global_get_ref as usize;
+        ptrs[VMBuiltinFunctionIndex::get_global_set_ref_index().index() as usize] =
+            wasmer_global_set_ref as usize;
+        ptrs[VMBuiltinFunctionIndex::get_interrupt_index().index() as usize] =
+            wasmer_interrupt as usize;
 
         debug_assert!(ptrs.iter().cloned().all(|p| p != 0));
 
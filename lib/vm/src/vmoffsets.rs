@@ -335,13 +335,47 @@ impl VMOffsets {
     }
 }
 
+/// Offsets for [`VMInterrupts`].
+///
+/// [`VMInterrupts`]: crate::vmcontext::VMInterrupts
+impl VMOffsets {
+    /// The offset of the `interrupted` field.
+    pub const fn vminterrupts_interrupted(&self) -> u8 {
+        0
+    }
+
+    /// The size of the `interrupted` field.
+    pub const fn size_of_vminterrupts_interrupted(&self) -> u8 {
+        4
+    }
+
+    /// The offset of the `epoch_deadline` field.
+    pub const fn vminterrupts_epoch_deadline(&self) -> u8 {
+        8
+    }
+
+    /// The offset of the `epoch` field.
+    pub const fn vminterrupts_epoch(&self) -> u8 {
+        16
+    }
+}
+
 /// Offsets for [`VMContext`].
 ///
 /// [`VMContext`]: crate::vmcontext::VMContext
 impl VMOffsets {
+    /// The offset of the pointer to the [`VMInterrupts`] structure.
+    ///
+    /// [`VMInterrupts`]: crate::vmcontext::VMInterrupts
+    pub fn vmctx_interrupts(&self) -> u32 {
+        0
+    }
+
     /// The offset of the `signature_ids` array.
     pub fn vmctx_signature_ids_begin(&self) -> u32 {
-        0
+        self.vmctx_interrupts()
+            .checked_add(u32::from(self.pointer_size))
+            .unwrap()
     }
 
     /// The offset of the `tables` array.
@@ -0,0 +1,113 @@
+use super::{DirEntry, FileSystem, Metadata, OpenOptions};
+use crate::state::{HostFile, WasiFile, WasiFsError};
+use crate::syscalls::types::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A [`FileSystem`] passing everything through to the host filesystem.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct HostFileSystem;
+
+#[typetag::serde]
+impl FileSystem for HostFileSystem {
+    fn open(&self, path: &Path, options: &OpenOptions) -> Result<Box<dyn WasiFile>, WasiFsError> {
+        let file = fs::OpenOptions::new()
+            .read(options.read)
+            .write(options.write)
+            .append(options.append)
+            .truncate(options.truncate)
+            .create(options.create)
+            .create_new(options.create_new)
+            .open(path)?;
+        Ok(Box::new(HostFile::new(
+            file,
+            path.to_path_buf(),
+            options.is_read(),
+            options.is_write(),
+            options.is_append(),
+        )))
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>, WasiFsError> {
+        fs::read_dir(path)?
+            .map(|entry| {
+                let entry = entry?;
+                Ok(DirEntry {
+                    name: entry.file_name().to_string_lossy().into_owned(),
+                    filetype: host_file_type_to_wasi_file_type(entry.file_type()?),
+                })
+            })
+            .collect()
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata, WasiFsError> {
+        Ok(host_metadata(fs::metadata(path)?))
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> Result<Metadata, WasiFsError> {
+        Ok(host_metadata(fs::symlink_metadata(path)?))
+    }
+
+    fn read_link(&self, path: &Path) -> Result<PathBuf, WasiFsError> {
+        fs::read_link(path).map_err(Into::into)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), WasiFsError> {
+        fs::rename(from, to).map_err(Into::into)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), WasiFsError> {
+        fs::remove_file(path).map_err(Into::into)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), WasiFsError> {
+        fs::create_dir(path).map_err(Into::into)
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<(), WasiFsError> {
+        fs::remove_dir(path).map_err(Into::into)
+    }
+}
+
+fn host_metadata(md: fs::Metadata) -> Metadata {
+    let timestamp = |time: std::io::Result<SystemTime>| {
+        time.ok()
+            .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or(0)
+    };
+    Metadata {
+        filetype: host_file_type_to_wasi_file_type(md.file_type()),
+        len: md.len(),
+        accessed: timestamp(md.accessed()),
+        modified: timestamp(md.modified()),
+        created: timestamp(md.created()),
+    }
+}
+
+fn host_file_type_to_wasi_file_type(file_type: fs::FileType) -> __wasi_filetype_t {
+    if file_type.is_dir() {
+        return __WASI_FILETYPE_DIRECTORY;
+    } else if file_type.is_file() {
+        return __WASI_FILETYPE_REGULAR_FILE;
+    } else if file_type.is_symlink() {
+        return __WASI_FILETYPE_SYMBOLIC_LINK;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if file_type.is_char_device() {
+            return __WASI_FILETYPE_CHARACTER_DEVICE;
+        } else if file_type.is_block_device() {
+            return __WASI_FILETYPE_BLOCK_DEVICE;
+        } else if file_type.is_socket() {
+            // TODO: how do we know if it's a `__WASI_FILETYPE_SOCKET_STREAM` or
+            // a `__WASI_FILETYPE_SOCKET_DGRAM`?
+            return __WASI_FILETYPE_SOCKET_STREAM;
+        }
+    }
+    // FIFOs don't seem to fit any other type, so unknown
+    __WASI_FILETYPE_UNKNOWN
+}
@@ -0,0 +1,607 @@
+use super::{components, DirEntry, FileSystem, Metadata, OpenOptions};
+use crate::state::{WasiFile, WasiFsError};
+use crate::syscalls::types::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// A [`FileSystem`] keeping all of its files and directories in memory.
+///
+/// Clones of a `MemFileSystem` share the same files and directories, so an
+/// embedder can keep a clone around to inspect or prepare the files a WASI
+/// program sees.
+///
+/// Serializing a `MemFileSystem` serializes its contents. Note that files
+/// which are open when the [`WasiState`] is frozen get their own copy of
+/// their contents when it's unfrozen.
+///
+/// [`WasiState`]: crate::WasiState
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemFileSystem {
+    root: Arc<Mutex<Node>>,
+    #[serde(default = "default_max_file_size")]
+    max_file_size: u64,
+}
+
+/// The size files of a [`MemFileSystem`] can grow to by default, 1 GiB.
+const DEFAULT_MAX_FILE_SIZE: u64 = 1 << 30;
+
+fn default_max_file_size() -> u64 {
+    DEFAULT_MAX_FILE_SIZE
+}
+
+impl MemFileSystem {
+    /// Creates an empty in-memory filesystem.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the size in bytes its files can grow to, 1 GiB by default. Writing or resizing a file beyond it fails with
+    /// `__WASI_EFBIG`, instead of allocating the memory on the host.
+    pub fn with_max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = max_file_size;
+        self
+    }
+}
+
+impl Default for MemFileSystem {
+    fn default() -> Self {
+        Self {
+            root: Arc::new(Mutex::new(Node::Dir(DirData::new()))),
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Node {
+    File(Arc<Mutex<FileData>>),
+    Dir(DirData),
+}
+
+impl Node {
+    fn metadata(&self) -> Metadata {
+        match self {
+            Node::File(data) => {
+                let data = data.lock().unwrap();
+                data.times
+                    .metadata(__WASI_FILETYPE_REGULAR_FILE, data.contents.len())
+            }
+            Node::Dir(dir) => dir.times.metadata(__WASI_FILETYPE_DIRECTORY, 0),
+        }
+    }
+
+    fn as_dir_mut(&mut self) -> Result<&mut DirData, WasiFsError> {
+        match self {
+            Node::Dir(dir) => Ok(dir),
+            Node::File(_) => Err(WasiFsError::BaseNotDirectory),
+        }
+    }
+
+    /// Looks up the node at the given path components.
+    fn lookup(&self, components: &[String]) -> Result<&Node, WasiFsError> {
+        components.iter().try_fold(self, |node, name| match node {
+            Node::Dir(dir) => dir.entries.get(name).ok_or(WasiFsError::EntityNotFound),
+            Node::File(_) => Err(WasiFsError::BaseNotDirectory),
+        })
+    }
+
+    /// Looks up the directory containing the node at the given path
+    /// components, and the name of the node in it.
+    fn lookup_parent_mut<'a>(
+        &mut self,
+        components: &'a [String],
+    ) -> Result<(&mut DirData, &'a str), WasiFsError> {
+        let (name, parent) = components.split_last().ok_or(WasiFsError::InvalidInput)?;
+        let mut node = self;
+        for component in parent {
+            node = node
+                .as_dir_mut()?
+                .entries
+                .get_mut(component)
+                .ok_or(WasiFsError::EntityNotFound)?;
+        }
+        Ok((node.as_dir_mut()?, name))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DirData {
+    entries: BTreeMap<String, Node>,
+    times: Times,
+}
+
+impl DirData {
+    fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            times: Times::now(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FileData {
+    contents: Vec<u8>,
+    times: Times,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Times {
+    accessed: __wasi_timestamp_t,
+    modified: __wasi_timestamp_t,
+    created: __wasi_timestamp_t,
+}
+
+impl Times {
+    fn now() -> Self {
+        let now = now();
+        Self {
+            accessed: now,
+            modified: now,
+            created: now,
+        }
+    }
+
+    fn metadata(&self, filetype: __wasi_filetype_t, len: usize) -> Metadata {
+        Metadata {
+            filetype,
+            len: len as u64,
+            accessed: self.accessed,
+            modified: self.modified,
+            created: self.created,
+        }
+    }
+}
+
+fn now() -> __wasi_timestamp_t {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|now| now.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+#[typetag::serde]
+impl FileSystem for MemFileSystem {
+    fn open(&self, path: &Path, options: &OpenOptions) -> Result<Box<dyn WasiFile>, WasiFsError> {
+        let components = components(path);
+        let mut root = self.root.lock().unwrap();
+        let (parent, name) = root.lookup_parent_mut(&components)?;
+        let data = match parent.entries.get(name) {
+            Some(Node::File(data)) => {
+                if options.create_new {
+                    return Err(WasiFsError::AlreadyExists);
+                }
+                if options.truncate && options.is_write() {
+                    let mut data = data.lock().unwrap();
+                    data.contents.clear();
+                    data.times.modified = now();
+                }
+                data.clone()
+            }
+            Some(Node::Dir(_)) => return Err(WasiFsError::NotAFile),
+            None if options.create || options.create_new => {
+                let data = Arc::new(Mutex::new(FileData {
+                    contents: Vec::new(),
+                    times: Times::now(),
+                }));
+                parent
+                    .entries
+                    .insert(name.to_string(), Node::File(data.clone()));
+                parent.times.modified = now();
+                data
+            }
+            None => return Err(WasiFsError::EntityNotFound),
+        };
+        Ok(Box::new(MemFile {
+            data,
+            position: 0,
+            read: options.is_read(),
+            write: options.is_write(),
+            append: options.is_append(),
+            max_size: self.max_file_size,
+        }))
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>, WasiFsError> {
+        let root = self.root.lock().unwrap();
+        match root.lookup(&components(path))? {
+            Node::Dir(dir) => Ok(dir
+                .entries
+                .iter()
+                .map(|(name, node)| DirEntry {
+                    name: name.clone(),
+                    filetype: match node {
+                        Node::File(_) => __WASI_FILETYPE_REGULAR_FILE,
+                        Node::Dir(_) => __WASI_FILETYPE_DIRECTORY,
+                    },
+                })
+                .collect()),
+            Node::File(_) => Err(WasiFsError::BaseNotDirectory),
+        }
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata, WasiFsError> {
+        let root = self.root.lock().unwrap();
+        Ok(root.lookup(&components(path))?.metadata())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), WasiFsError> {
+        let from = components(from);
+        let to = components(to);
+        if from.is_empty() || to.is_empty() {
+            return Err(WasiFsError::InvalidInput);
+        }
+        if from == to {
+            return Ok(());
+        }
+        // a directory can't be moved into itself
+        if to.starts_with(&from) {
+            return Err(WasiFsError::InvalidInput);
+        }
+
+        let mut root = self.root.lock().unwrap();
+        let source_is_dir = matches!(root.lookup(&from)?, Node::Dir(_));
+        let (target_parent, target_name) = root.lookup_parent_mut(&to)?;
+        match target_parent.entries.get(target_name) {
+            None => (),
+            Some(Node::File(_)) if !source_is_dir => (),
+            Some(Node::Dir(dir)) if source_is_dir => {
+                if !dir.entries.is_empty() {
+                    return Err(WasiFsError::DirectoryNotEmpty);
+                }
+            }
+            Some(Node::File(_)) => return Err(WasiFsError::BaseNotDirectory),
+            Some(Node::Dir(_)) => return Err(WasiFsError::NotAFile),
+        }
+
+        let (source_parent, source_name) = root.lookup_parent_mut(&from)?;
+        let node = source_parent.entries.remove(source_name).unwrap();
+        source_parent.times.modified = now();
+        let (target_parent, target_name) = root.lookup_parent_mut(&to)?;
+        target_parent.entries.insert(target_name.to_string(), node);
+        target_parent.times.modified = now();
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), WasiFsError> {
+        let components = components(path);
+        let mut root = self.root.lock().unwrap();
+        let (parent, name) = root.lookup_parent_mut(&components)?;
+        match parent.entries.get(name) {
+            Some(Node::File(_)) => {
+                parent.entries.remove(name);
+                parent.times.modified = now();
+                Ok(())
+            }
+            Some(Node::Dir(_)) => Err(WasiFsError::NotAFile),
+            None => Err(WasiFsError::EntityNotFound),
+        }
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), WasiFsError> {
+        let components = components(path);
+        let mut root = self.root.lock().unwrap();
+        let (parent, name) = root.lookup_parent_mut(&components)?;
+        if parent.entries.contains_key(name) {
+            return Err(WasiFsError::AlreadyExists);
+        }
+        parent
+            .entries
+            .insert(name.to_string(), Node::Dir(DirData::new()));
+        parent.times.modified = now();
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<(), WasiFsError> {
+        let components = components(path);
+        let mut root = self.root.lock().unwrap();
+        let (parent, name) = root.lookup_parent_mut(&components)?;
+        match parent.entries.get(name) {
+            Some(Node::Dir(dir)) => {
+                if !dir.entries.is_empty() {
+                    return Err(WasiFsError::DirectoryNotEmpty);
+                }
+                parent.entries.remove(name);
+                parent.times.modified = now();
+                Ok(())
+            }
+            Some(Node::File(_)) => Err(WasiFsError::BaseNotDirectory),
+            None => Err(WasiFsError::EntityNotFound),
+        }
+    }
+}
+
+/// An open file of a [`MemFileSystem`].
+#[derive(Debug, Serialize, Deserialize)]
+struct MemFile {
+    data: Arc<Mutex<FileData>>,
+    position: u64,
+    read: bool,
+    write: bool,
+    append: bool,
+    #[serde(default = "default_max_file_size")]
+    max_size: u64,
+}
+
+impl MemFile {
+    /// Fails with `__WASI_EFBIG` if the file can't grow to `size` bytes.
+    fn check_size(&self, size: u64) -> Result<(), WasiFsError> {
+        if size > self.max_size {
+            return Err(WasiFsError::UnknownError(__WASI_EFBIG));
+        }
+        Ok(())
+    }
+}
+
+impl Read for MemFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.read {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "file is not open for reading",
+            ));
+        }
+        let mut data = self.data.lock().unwrap();
+        let start = std::cmp::min(self.position, data.contents.len() as u64) as usize;
+        let amt = std::cmp::min(buf.len(), data.contents.len() - start);
+        buf[..amt].copy_from_slice(&data.contents[start..start + amt]);
+        data.times.accessed = now();
+        self.position += amt as u64;
+        Ok(amt)
+    }
+}
+
+impl Write for MemFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.write {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "file is not open for writing",
+            ));
+        }
+        let mut data = self.data.lock().unwrap();
+        if self.append {
+            self.position = data.contents.len() as u64;
+        }
+        let end = self
+            .position
+            .checked_add(buf.len() as u64)
+            .ok_or(WasiFsError::UnknownError(__WASI_EFBIG))
+            .and_then(|end| self.check_size(end).map(|()| end))
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let (start, end) = (self.position as usize, end as usize);
+        if end > data.contents.len() {
+            data.contents.resize(end, 0);
+        }
+        data.contents[start..end].copy_from_slice(buf);
+        data.times.modified = now();
+        self.position = end as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => {
+                self.position = offset;
+                return Ok(offset);
+            }
+            SeekFrom::End(offset) => (self.data.lock().unwrap().contents.len() as u64, offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+        let position = if offset >= 0 {
+            base.checked_add(offset as u64)
+        } else {
+            base.checked_sub(offset.wrapping_neg() as u64)
+        };
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+#[typetag::serde]
+impl WasiFile for MemFile {
+    fn last_accessed(&self) -> __wasi_timestamp_t {
+        self.data.lock().unwrap().times.accessed
+    }
+
+    fn set_last_accessed(&self, last_accessed: __wasi_timestamp_t) {
+        self.data.lock().unwrap().times.accessed = last_accessed;
+    }
+
+    fn last_modified(&self) -> __wasi_timestamp_t {
+        self.data.lock().unwrap().times.modified
+    }
+
+    fn set_last_modified(&self, last_modified: __wasi_timestamp_t) {
+        self.data.lock().unwrap().times.modified = last_modified;
+    }
+
+    fn created_time(&self) -> __wasi_timestamp_t {
+        self.data.lock().unwrap().times.created
+    }
+
+    fn set_created_time(&self, created_time: __wasi_timestamp_t) {
+        self.data.lock().unwrap().times.created = created_time;
+    }
+
+    fn size(&self) -> u64 {
+        self.data.lock().unwrap().contents.len() as u64
+    }
+
+    fn set_len(&mut self, new_size: __wasi_filesize_t) -> Result<(), WasiFsError> {
+        self.check_size(new_size)?;
+        let mut data = self.data.lock().unwrap();
+        data.contents.resize(new_size as usize, 0);
+        data.times.modified = now();
+        Ok(())
+    }
+
+    /// The file is removed from its directory by [`FileSystem::remove_file`],
+    /// so there's nothing left to do.
+    fn unlink(&mut self) -> Result<(), WasiFsError> {
+        Ok(())
+    }
+
+    /// The file is moved by [`FileSystem::rename`], so there's nothing left
+    /// to do.
+    fn rename_file(&self, _new_name: &Path) -> Result<(), WasiFsError> {
+        Ok(())
+    }
+
+    fn bytes_available(&self) -> Result<usize, WasiFsError> {
+        let len = self.data.lock().unwrap().contents.len() as u64;
+        Ok(len.saturating_sub(self.position) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_file(fs: &MemFileSystem, path: &str) -> Vec<u8> {
+        let mut file = fs
+            .open(Path::new(path), OpenOptions::new().read(true))
+            .unwrap();
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn files_and_directories() {
+        let fs = MemFileSystem::new();
+        fs.create_dir(Path::new("/a")).unwrap();
+        assert_eq!(
+            fs.create_dir(Path::new("/a")),
+            Err(WasiFsError::AlreadyExists)
+        );
+        assert_eq!(
+            fs.create_dir(Path::new("/b/c")),
+            Err(WasiFsError::EntityNotFound)
+        );
+
+        let mut file = fs
+            .open(
+                Path::new("/a/file"),
+                OpenOptions::new().write(true).create(true),
+            )
+            .unwrap();
+        file.write_all(b"hello world").unwrap();
+        file.seek(SeekFrom::Start(6)).unwrap();
+        file.write_all(b"there").unwrap();
+        assert_eq!(read_file(&fs, "a/./file"), b"hello there");
+
+        let metadata = fs.metadata(Path::new("/a/file")).unwrap();
+        assert!(metadata.is_file());
+        assert_eq!(metadata.len, 11);
+        assert!(fs.metadata(Path::new("/a")).unwrap().is_dir());
+        assert_eq!(
+            fs.read_dir(Path::new("/")).unwrap(),
+            vec![DirEntry {
+                name: "a".to_string(),
+                filetype: __WASI_FILETYPE_DIRECTORY,
+            }]
+        );
+
+        assert_eq!(
+            fs.remove_dir(Path::new("/a")),
+            Err(WasiFsError::DirectoryNotEmpty)
+        );
+        fs.rename(Path::new("/a/file"), Path::new("/file")).unwrap();
+        assert_eq!(read_file(&fs, "/file"), b"hello there");
+        fs.remove_dir(Path::new("/a")).unwrap();
+        fs.remove_file(Path::new("/file")).unwrap();
+        assert!(fs.read_dir(Path::new("/")).unwrap().is_empty());
+
+        // the removed file is still usable through its handle
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.write_all(b"H").unwrap();
+        assert_eq!(file.size(), 11);
+    }
+
+    #[test]
+    fn open_options() {
+        let fs = MemFileSystem::new();
+        assert_eq!(
+            fs.open(Path::new("/file"), OpenOptions::new().read(true))
+                .unwrap_err(),
+            WasiFsError::EntityNotFound
+        );
+        fs.open(
+            Path::new("/file"),
+            OpenOptions::new().write(true).create_new(true),
+        )
+        .unwrap()
+        .write_all(b"abc")
+        .unwrap();
+        assert_eq!(
+            fs.open(
+                Path::new("/file"),
+                OpenOptions::new().write(true).create_new(true)
+            )
+            .unwrap_err(),
+            WasiFsError::AlreadyExists
+        );
+
+        fs.open(Path::new("/file"), OpenOptions::new().append(true))
+            .unwrap()
+            .write_all(b"def")
+            .unwrap();
+        assert_eq!(read_file(&fs, "/file"), b"abcdef");
+
+        let mut file = fs
+            .open(Path::new("/file"), OpenOptions::new().read(true))
+            .unwrap();
+        assert!(file.write_all(b"ghi").is_err());
+
+        fs.open(
+            Path::new("/file"),
+            OpenOptions::new().write(true).truncate(true),
+        )
+        .unwrap();
+        assert_eq!(read_file(&fs, "/file"), b"");
+    }
+
+    #[test]
+    fn max_file_size() {
+        let fs = MemFileSystem::new().with_max_file_size(8);
+        let mut file = fs
+            .open(
+                Path::new("/file"),
+                OpenOptions::new().write(true).create(true),
+            )
+            .unwrap();
+        file.write_all(b"12345678").unwrap();
+        assert!(file.write_all(b"9").is_err());
+        file.seek(SeekFrom::Start(1 << 40)).unwrap();
+        assert!(file.write_all(b"9").is_err());
+        assert_eq!(
+            file.set_len(9),
+            Err(WasiFsError::UnknownError(__WASI_EFBIG))
+        );
+        assert_eq!(
+            file.set_len(1 << 62),
+            Err(WasiFsError::UnknownError(__WASI_EFBIG))
+        );
+        file.set_len(4).unwrap();
+        assert_eq!(read_file(&fs, "/file"), b"1234");
+    }
+}
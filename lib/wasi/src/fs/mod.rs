@@ -0,0 +1,227 @@
+//! Filesystems that back the directories preopened for a WASI program.
+//!
+//! The [`WasiFs`] only keeps track of the inodes and file descriptors the
+//! program knows about; every access to the files and directories under a
+//! preopened directory goes through the [`FileSystem`] it was preopened
+//! from. [`HostFileSystem`] passes these accesses through to the host
+//! filesystem, and [`MemFileSystem`] keeps everything in memory, which allows
+//! running programs without any access to the host filesystem.
+//! [`OverlayFileSystem`] combines two filesystems to give programs a writable
+//! view of a directory without modifying it.
+//!
+//! [`WasiFs`]: crate::WasiFs
+
+mod host;
+mod mem;
+mod overlay;
+
+pub use self::host::HostFileSystem;
+pub use self::mem::MemFileSystem;
+pub use self::overlay::OverlayFileSystem;
+
+use crate::state::{WasiFile, WasiFsError};
+use crate::syscalls::types::*;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// A filesystem that directories can be preopened from.
+///
+/// Paths given to a `FileSystem` are the path of the preopened directory
+/// joined with the path of the file relative to it, so they are only
+/// meaningful to the filesystem itself.
+#[typetag::serde(tag = "type")]
+pub trait FileSystem: fmt::Debug + Send + Sync + 'static {
+    /// Opens the file at `path`.
+    fn open(&self, path: &Path, options: &OpenOptions) -> Result<Box<dyn WasiFile>, WasiFsError>;
+
+    /// Returns the entries of the directory at `path`, excluding `.` and
+    /// `..`, in no particular order.
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>, WasiFsError>;
+
+    /// Returns the metadata of the file or directory at `path`, following
+    /// symlinks.
+    fn metadata(&self, path: &Path) -> Result<Metadata, WasiFsError>;
+
+    /// Returns the metadata of the file, directory or symlink at `path`,
+    /// without following symlinks.
+    ///
+    /// The default implementation is for filesystems without symlinks.
+    fn symlink_metadata(&self, path: &Path) -> Result<Metadata, WasiFsError> {
+        self.metadata(path)
+    }
+
+    /// Returns the value of the symlink at `path`.
+    ///
+    /// The default implementation is for filesystems without symlinks.
+    fn read_link(&self, _path: &Path) -> Result<PathBuf, WasiFsError> {
+        Err(WasiFsError::InvalidInput)
+    }
+
+    /// Moves the file or directory at `from` to `to`.
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), WasiFsError>;
+
+    /// Removes the file at `path`.
+    ///
+    /// Files that are still open must remain usable until they're closed.
+    fn remove_file(&self, path: &Path) -> Result<(), WasiFsError>;
+
+    /// Creates an empty directory at `path`.
+    fn create_dir(&self, path: &Path) -> Result<(), WasiFsError>;
+
+    /// Removes the empty directory at `path`.
+    fn remove_dir(&self, path: &Path) -> Result<(), WasiFsError>;
+}
+
+/// Options for [`FileSystem::open`], in the style of
+/// [`std::fs::OpenOptions`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpenOptions {
+    pub(crate) read: bool,
+    pub(crate) write: bool,
+    pub(crate) append: bool,
+    pub(crate) truncate: bool,
+    pub(crate) create: bool,
+    pub(crate) create_new: bool,
+}
+
+impl OpenOptions {
+    /// Creates a blank set of options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the option for read access.
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    /// Sets the option for write access.
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    /// Sets the option for appending to the file.
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.append = append;
+        self
+    }
+
+    /// Sets the option for truncating the file when it's opened.
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Sets the option for creating the file if it doesn't exist.
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Sets the option for creating the file, failing if it already exists.
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Whether the file is opened for reading.
+    pub fn is_read(&self) -> bool {
+        self.read
+    }
+
+    /// Whether the file is opened for writing.
+    pub fn is_write(&self) -> bool {
+        self.write || self.append
+    }
+
+    /// Whether writes to the file append to it.
+    pub fn is_append(&self) -> bool {
+        self.append
+    }
+
+    /// Whether the file is truncated when it's opened.
+    pub fn is_truncate(&self) -> bool {
+        self.truncate
+    }
+
+    /// Whether the file is created if it doesn't exist.
+    pub fn is_create(&self) -> bool {
+        self.create
+    }
+
+    /// Whether opening fails if the file already exists.
+    pub fn is_create_new(&self) -> bool {
+        self.create_new
+    }
+}
+
+/// Metadata about a file, directory or symlink of a [`FileSystem`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    /// The type of the file.
+    pub filetype: __wasi_filetype_t,
+    /// The size of the file in bytes.
+    pub len: u64,
+    /// The last time the file was accessed in nanoseconds as a UNIX timestamp.
+    pub accessed: __wasi_timestamp_t,
+    /// The last time the file was modified in nanoseconds as a UNIX timestamp.
+    pub modified: __wasi_timestamp_t,
+    /// The time at which the file was created in nanoseconds as a UNIX
+    /// timestamp, or 0 if unknown.
+    pub created: __wasi_timestamp_t,
+}
+
+impl Metadata {
+    /// Whether this is the metadata of a directory.
+    pub fn is_dir(&self) -> bool {
+        self.filetype == __WASI_FILETYPE_DIRECTORY
+    }
+
+    /// Whether this is the metadata of a regular file.
+    pub fn is_file(&self) -> bool {
+        self.filetype == __WASI_FILETYPE_REGULAR_FILE
+    }
+
+    /// Whether this is the metadata of a symlink.
+    pub fn is_symlink(&self) -> bool {
+        self.filetype == __WASI_FILETYPE_SYMBOLIC_LINK
+    }
+
+    /// Converts the metadata into a WASI filestat.
+    pub(crate) fn to_filestat(&self) -> __wasi_filestat_t {
+        __wasi_filestat_t {
+            st_filetype: self.filetype,
+            st_size: self.len,
+            st_atim: self.accessed,
+            st_mtim: self.modified,
+            st_ctim: self.created,
+            ..__wasi_filestat_t::default()
+        }
+    }
+}
+
+/// An entry of a directory of a [`FileSystem`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    /// The name of the entry.
+    pub name: String,
+    /// The type of the entry.
+    pub filetype: __wasi_filetype_t,
+}
+
+/// Splits `path` into its normal components, resolving `..` lexically.
+fn components(path: &Path) -> Vec<String> {
+    let mut components = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => components.push(name.to_string_lossy().into_owned()),
+            Component::ParentDir => {
+                components.pop();
+            }
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => (),
+        }
+    }
+    components
+}
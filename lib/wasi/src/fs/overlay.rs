@@ -0,0 +1,488 @@
+use super::{
+    components, DirEntry, FileSystem, HostFileSystem, MemFileSystem, Metadata, OpenOptions,
+};
+use crate::state::{WasiFile, WasiFsError};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// A copy-on-write [`FileSystem`] layering a writable upper directory on top
+/// of a lower directory which is never modified.
+///
+/// Reads fall through to the lower directory for everything the upper
+/// directory doesn't contain. Files of the lower directory are copied to the
+/// upper directory before they're written to, new files and directories are
+/// created in the upper directory, and removing an entry of the lower
+/// directory records a whiteout which hides it and everything below it.
+///
+/// Clones of an `OverlayFileSystem` share the same layers and whiteouts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OverlayFileSystem {
+    lower: Layer,
+    upper: Layer,
+    /// The paths of the lower directory that were removed
+    whiteouts: Arc<Mutex<BTreeSet<Vec<String>>>>,
+}
+
+/// A directory of a filesystem used as one of the layers of an overlay.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Layer {
+    fs: Arc<dyn FileSystem>,
+    root: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Origin {
+    Upper,
+    Lower,
+}
+
+impl Layer {
+    fn path(&self, components: &[String]) -> PathBuf {
+        let mut path = self.root.clone();
+        path.extend(components);
+        path
+    }
+
+    /// Returns the metadata of the entry at `components`, or `None` if there's
+    /// no such entry.
+    fn lookup(&self, components: &[String], follow: bool) -> Result<Option<Metadata>, WasiFsError> {
+        let path = self.path(components);
+        let metadata = if follow {
+            self.fs.metadata(&path)
+        } else {
+            self.fs.symlink_metadata(&path)
+        };
+        match metadata {
+            Ok(metadata) => Ok(Some(metadata)),
+            Err(WasiFsError::EntityNotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl OverlayFileSystem {
+    /// Creates an overlay of the host directory `lower` which keeps all of
+    /// the changes in memory.
+    pub fn new<P>(lower: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self::from_layers(HostFileSystem, lower, MemFileSystem::new(), "/")
+    }
+
+    /// Creates an overlay of the host directory `lower` which stores all of
+    /// the changes in the host directory `upper`.
+    pub fn with_upper_dir<P, Q>(lower: P, upper: Q) -> Self
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        Self::from_layers(HostFileSystem, lower, HostFileSystem, upper)
+    }
+
+    /// Creates an overlay of the directory `lower_root` of `lower` which
+    /// stores all of the changes in the directory `upper_root` of `upper`.
+    pub fn from_layers<L, P, U, Q>(lower: L, lower_root: P, upper: U, upper_root: Q) -> Self
+    where
+        L: FileSystem,
+        P: AsRef<Path>,
+        U: FileSystem,
+        Q: AsRef<Path>,
+    {
+        Self {
+            lower: Layer {
+                fs: Arc::new(lower),
+                root: lower_root.as_ref().to_path_buf(),
+            },
+            upper: Layer {
+                fs: Arc::new(upper),
+                root: upper_root.as_ref().to_path_buf(),
+            },
+            whiteouts: Default::default(),
+        }
+    }
+
+    fn is_whited_out(&self, components: &[String]) -> bool {
+        let whiteouts = self.whiteouts.lock().unwrap();
+        (1..=components.len()).any(|len| whiteouts.contains(&components[..len]))
+    }
+
+    fn lookup_lower(
+        &self,
+        components: &[String],
+        follow: bool,
+    ) -> Result<Option<Metadata>, WasiFsError> {
+        if self.is_whited_out(components) {
+            return Ok(None);
+        }
+        self.lower.lookup(components, follow)
+    }
+
+    /// Returns the layer the entry at `components` is visible from and its
+    /// metadata.
+    fn lookup(
+        &self,
+        components: &[String],
+        follow: bool,
+    ) -> Result<Option<(Origin, Metadata)>, WasiFsError> {
+        if let Some(metadata) = self.upper.lookup(components, follow)? {
+            return Ok(Some((Origin::Upper, metadata)));
+        }
+        Ok(self
+            .lookup_lower(components, follow)?
+            .map(|metadata| (Origin::Lower, metadata)))
+    }
+
+    /// Hides the entry at `components` of the lower directory, if there's one.
+    fn whiteout(&self, components: &[String]) -> Result<(), WasiFsError> {
+        if self.lookup_lower(components, false)?.is_some() {
+            self.whiteouts.lock().unwrap().insert(components.to_vec());
+        }
+        Ok(())
+    }
+
+    /// Creates the parent directories of `components` in the upper directory.
+    fn copy_up_parents(&self, components: &[String]) -> Result<(), WasiFsError> {
+        for len in 1..components.len() {
+            let dir = &components[..len];
+            match self.lookup(dir, true)? {
+                Some((_, metadata)) if !metadata.is_dir() => {
+                    return Err(WasiFsError::BaseNotDirectory)
+                }
+                Some((Origin::Upper, _)) => (),
+                Some((Origin::Lower, _)) => self.upper.fs.create_dir(&self.upper.path(dir))?,
+                None => return Err(WasiFsError::EntityNotFound),
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the file at `components` of the lower directory to the upper
+    /// directory, leaving it empty if `contents` is false.
+    fn copy_up_file(&self, components: &[String], contents: bool) -> Result<(), WasiFsError> {
+        self.copy_up_parents(components)?;
+        let mut upper = self.upper.fs.open(
+            &self.upper.path(components),
+            OpenOptions::new().write(true).create(true).truncate(true),
+        )?;
+        if contents {
+            let mut lower = self
+                .lower
+                .fs
+                .open(&self.lower.path(components), OpenOptions::new().read(true))?;
+            io::copy(&mut lower, &mut upper)?;
+        }
+        Ok(())
+    }
+
+    /// Copies the entry at `components` and everything below it to the upper
+    /// directory.
+    fn copy_up(&self, components: &[String]) -> Result<(), WasiFsError> {
+        match self.lookup(components, false)? {
+            Some((origin, metadata)) if metadata.is_dir() => {
+                if origin == Origin::Lower {
+                    self.copy_up_parents(components)?;
+                    self.upper.fs.create_dir(&self.upper.path(components))?;
+                }
+                for entry in self.read_dir_components(components)? {
+                    let mut child = components.to_vec();
+                    child.push(entry.name);
+                    self.copy_up(&child)?;
+                }
+                Ok(())
+            }
+            Some((Origin::Upper, _)) => Ok(()),
+            Some((Origin::Lower, metadata)) if metadata.is_file() => {
+                self.copy_up_file(components, true)
+            }
+            // there's no way to create symlinks or special files in the upper directory
+            Some((Origin::Lower, _)) => Err(WasiFsError::PermissionDenied),
+            None => Err(WasiFsError::EntityNotFound),
+        }
+    }
+
+    fn read_dir_components(&self, components: &[String]) -> Result<Vec<DirEntry>, WasiFsError> {
+        let mut entries = BTreeMap::new();
+        let mut found = false;
+        if let Some(metadata) = self.upper.lookup(components, true)? {
+            if !metadata.is_dir() {
+                return Err(WasiFsError::BaseNotDirectory);
+            }
+            found = true;
+            for entry in self.upper.fs.read_dir(&self.upper.path(components))? {
+                entries.insert(entry.name, entry.filetype);
+            }
+        }
+        match self.lookup_lower(components, true)? {
+            Some(metadata) if metadata.is_dir() => {
+                found = true;
+                for entry in self.lower.fs.read_dir(&self.lower.path(components))? {
+                    let mut child = components.to_vec();
+                    child.push(entry.name);
+                    if !self.is_whited_out(&child) {
+                        let name = child.pop().unwrap();
+                        entries.entry(name).or_insert(entry.filetype);
+                    }
+                }
+            }
+            Some(_) if !found => return Err(WasiFsError::BaseNotDirectory),
+            _ => (),
+        }
+        if !found {
+            return Err(WasiFsError::EntityNotFound);
+        }
+        Ok(entries
+            .into_iter()
+            .map(|(name, filetype)| DirEntry { name, filetype })
+            .collect())
+    }
+}
+
+#[typetag::serde]
+impl FileSystem for OverlayFileSystem {
+    fn open(&self, path: &Path, options: &OpenOptions) -> Result<Box<dyn WasiFile>, WasiFsError> {
+        let components = components(path);
+        match self.lookup(&components, true)? {
+            Some((Origin::Upper, _)) => self.upper.fs.open(&self.upper.path(&components), options),
+            Some((Origin::Lower, _)) if options.is_create_new() => Err(WasiFsError::AlreadyExists),
+            Some((Origin::Lower, metadata)) if metadata.is_dir() => Err(WasiFsError::NotAFile),
+            Some((Origin::Lower, _)) => {
+                if options.is_write() || options.is_truncate() {
+                    self.copy_up_file(&components, !options.is_truncate())?;
+                    self.upper.fs.open(&self.upper.path(&components), options)
+                } else {
+                    self.lower.fs.open(&self.lower.path(&components), options)
+                }
+            }
+            None if options.is_create() || options.is_create_new() => {
+                self.copy_up_parents(&components)?;
+                self.upper.fs.open(&self.upper.path(&components), options)
+            }
+            None => Err(WasiFsError::EntityNotFound),
+        }
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>, WasiFsError> {
+        self.read_dir_components(&components(path))
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata, WasiFsError> {
+        self.lookup(&components(path), true)?
+            .map(|(_, metadata)| metadata)
+            .ok_or(WasiFsError::EntityNotFound)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> Result<Metadata, WasiFsError> {
+        self.lookup(&components(path), false)?
+            .map(|(_, metadata)| metadata)
+            .ok_or(WasiFsError::EntityNotFound)
+    }
+
+    fn read_link(&self, path: &Path) -> Result<PathBuf, WasiFsError> {
+        let components = components(path);
+        match self.lookup(&components, false)? {
+            Some((Origin::Upper, _)) => self.upper.fs.read_link(&self.upper.path(&components)),
+            Some((Origin::Lower, _)) => self.lower.fs.read_link(&self.lower.path(&components)),
+            None => Err(WasiFsError::EntityNotFound),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), WasiFsError> {
+        let from_components = components(from);
+        let to_components = components(to);
+        if from_components.is_empty()
+            || to_components.is_empty()
+            || (to_components.starts_with(&from_components)
+                && to_components.len() > from_components.len())
+        {
+            return Err(WasiFsError::InvalidInput);
+        }
+        let (_, from_metadata) = self
+            .lookup(&from_components, false)?
+            .ok_or(WasiFsError::EntityNotFound)?;
+        if from_components == to_components {
+            return Ok(());
+        }
+
+        // replace the target, like renaming on the host does
+        if let Some((_, to_metadata)) = self.lookup(&to_components, false)? {
+            match (from_metadata.is_dir(), to_metadata.is_dir()) {
+                (true, true) => self.remove_dir(to)?,
+                (false, false) => self.remove_file(to)?,
+                (true, false) => return Err(WasiFsError::BaseNotDirectory),
+                (false, true) => return Err(WasiFsError::NotAFile),
+            }
+        }
+
+        self.copy_up(&from_components)?;
+        self.copy_up_parents(&to_components)?;
+        self.upper.fs.rename(
+            &self.upper.path(&from_components),
+            &self.upper.path(&to_components),
+        )?;
+        self.whiteout(&from_components)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), WasiFsError> {
+        let components = components(path);
+        match self.lookup(&components, false)? {
+            Some((_, metadata)) if metadata.is_dir() => return Err(WasiFsError::NotAFile),
+            Some((Origin::Upper, _)) => self.upper.fs.remove_file(&self.upper.path(&components))?,
+            Some((Origin::Lower, _)) => (),
+            None => return Err(WasiFsError::EntityNotFound),
+        }
+        self.whiteout(&components)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), WasiFsError> {
+        let components = components(path);
+        if self.lookup(&components, false)?.is_some() {
+            return Err(WasiFsError::AlreadyExists);
+        }
+        self.copy_up_parents(&components)?;
+        self.upper.fs.create_dir(&self.upper.path(&components))
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<(), WasiFsError> {
+        let components = components(path);
+        if components.is_empty() {
+            return Err(WasiFsError::InvalidInput);
+        }
+        let origin = match self.lookup(&components, false)? {
+            Some((_, metadata)) if !metadata.is_dir() => return Err(WasiFsError::BaseNotDirectory),
+            Some((origin, _)) => origin,
+            None => return Err(WasiFsError::EntityNotFound),
+        };
+        if !self.read_dir_components(&components)?.is_empty() {
+            return Err(WasiFsError::DirectoryNotEmpty);
+        }
+        if origin == Origin::Upper {
+            self.upper.fs.remove_dir(&self.upper.path(&components))?;
+        }
+        self.whiteout(&components)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    fn write_file(fs: &dyn FileSystem, path: &str, contents: &[u8]) {
+        fs.open(
+            Path::new(path),
+            OpenOptions::new().write(true).create(true).truncate(true),
+        )
+        .unwrap()
+        .write_all(contents)
+        .unwrap();
+    }
+
+    fn read_file(fs: &dyn FileSystem, path: &str) -> Result<Vec<u8>, WasiFsError> {
+        let mut file = fs.open(Path::new(path), OpenOptions::new().read(true))?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    fn names(fs: &dyn FileSystem, path: &str) -> Vec<String> {
+        let mut names: Vec<_> = fs
+            .read_dir(Path::new(path))
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Returns an overlay of `/lower` of an in-memory filesystem and the
+    /// lower filesystem.
+    fn overlay() -> (OverlayFileSystem, MemFileSystem) {
+        let lower = MemFileSystem::new();
+        lower.create_dir(Path::new("/lower")).unwrap();
+        lower.create_dir(Path::new("/lower/dir")).unwrap();
+        write_file(&lower, "/lower/dir/file", b"lower file");
+        write_file(&lower, "/lower/file", b"top file");
+        let overlay =
+            OverlayFileSystem::from_layers(lower.clone(), "/lower", MemFileSystem::new(), "/");
+        (overlay, lower)
+    }
+
+    #[test]
+    fn writes_go_to_the_upper_layer() {
+        let (overlay, lower) = overlay();
+        assert_eq!(read_file(&overlay, "/dir/file").unwrap(), b"lower file");
+
+        let mut file = overlay
+            .open(Path::new("/dir/file"), OpenOptions::new().append(true))
+            .unwrap();
+        file.write_all(b", changed").unwrap();
+        write_file(&overlay, "/dir/new", b"new file");
+        overlay.create_dir(Path::new("/new_dir")).unwrap();
+
+        assert_eq!(
+            read_file(&overlay, "/dir/file").unwrap(),
+            b"lower file, changed"
+        );
+        assert_eq!(names(&overlay, "/"), ["dir", "file", "new_dir"]);
+        assert_eq!(names(&overlay, "/dir"), ["file", "new"]);
+
+        assert_eq!(read_file(&lower, "/lower/dir/file").unwrap(), b"lower file");
+        assert_eq!(names(&lower, "/lower"), ["dir", "file"]);
+        assert_eq!(names(&lower, "/lower/dir"), ["file"]);
+    }
+
+    #[test]
+    fn whiteouts_hide_removed_files() {
+        let (overlay, lower) = overlay();
+        assert_eq!(
+            overlay.remove_dir(Path::new("/dir")),
+            Err(WasiFsError::DirectoryNotEmpty)
+        );
+        overlay.remove_file(Path::new("/dir/file")).unwrap();
+        overlay.remove_dir(Path::new("/dir")).unwrap();
+        assert_eq!(
+            overlay.metadata(Path::new("/dir/file")),
+            Err(WasiFsError::EntityNotFound)
+        );
+        assert_eq!(names(&overlay, "/"), ["file"]);
+
+        // a new directory in place of a removed one doesn't show its old contents
+        overlay.create_dir(Path::new("/dir")).unwrap();
+        assert!(names(&overlay, "/dir").is_empty());
+
+        // a copied up file can be removed too
+        write_file(&overlay, "/file", b"changed");
+        overlay.remove_file(Path::new("/file")).unwrap();
+        assert_eq!(
+            read_file(&overlay, "/file"),
+            Err(WasiFsError::EntityNotFound)
+        );
+
+        assert_eq!(names(&lower, "/lower"), ["dir", "file"]);
+        assert_eq!(read_file(&lower, "/lower/file").unwrap(), b"top file");
+    }
+
+    #[test]
+    fn rename_copies_up() {
+        let (overlay, lower) = overlay();
+        overlay
+            .rename(Path::new("/dir"), Path::new("/moved"))
+            .unwrap();
+        overlay
+            .rename(Path::new("/file"), Path::new("/moved/file"))
+            .unwrap();
+        assert_eq!(names(&overlay, "/"), ["moved"]);
+        assert_eq!(read_file(&overlay, "/moved/file").unwrap(), b"top file");
+        assert_eq!(
+            overlay.rename(Path::new("/moved"), Path::new("/moved/inner")),
+            Err(WasiFsError::InvalidInput)
+        );
+
+        assert_eq!(names(&lower, "/lower"), ["dir", "file"]);
+    }
+}
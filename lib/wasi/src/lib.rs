@@ -8,41 +8,61 @@
 //! can be combined with a module to create an `Instance` which can execute WASI
 //! Wasm functions.
 //!
-//! See `state` for the experimental WASI FS API.  Also see the
+//! See `state` for the experimental WASI FS API, and [`FileSystem`] for backing
+//! preopened directories with something other than the host filesystem.  Also see the
 //! [WASI plugin example](https://github.com/wasmerio/wasmer/blob/master/examples/plugin.rs)
 //! for an example of how to extend WASI using the WASI FS API.
 
 #[macro_use]
 mod macros;
+mod fs;
 mod ptr;
+mod snapshot;
 mod state;
 mod syscalls;
+mod threads;
+mod trace;
 mod utils;
 
 use crate::syscalls::*;
+use crate::threads::WasiThreads;
 
+pub use crate::fs::{
+    DirEntry, FileSystem, HostFileSystem, MemFileSystem, Metadata, OpenOptions, OverlayFileSystem,
+};
+pub use crate::snapshot::WasiSnapshotError;
 pub use crate::state::{
-    Fd, Pipe, Stderr, Stdin, Stdout, WasiFile, WasiFs, WasiFsError, WasiState, WasiStateBuilder,
-    WasiStateCreationError, ALL_RIGHTS, VIRTUAL_ROOT_FD,
+    Fd, HostClock, HostRandom, HostTcpListener, HostTcpStream, Pipe, Stderr, Stdin, Stdout,
+    WasiClock, WasiFile, WasiFs, WasiFsError, WasiRandom, WasiResourceUsage, WasiSignalHandler,
+    WasiSocket, WasiState, WasiStateBuilder, WasiStateCreationError, ALL_RIGHTS, VIRTUAL_ROOT_FD,
 };
 pub use crate::syscalls::types;
+pub use crate::trace::{errno_name, SyscallArg, SyscallEvent, SyscallObserver};
 pub use crate::utils::{get_wasi_version, is_wasi_module, WasiVersion};
 
 use thiserror::Error;
-use wasmer::{imports, Function, ImportObject, LazyInit, Memory, Module, Store, WasmerEnv};
+use wasmer::{
+    imports, Exports, Extern, Function, ImportObject, Instance, LazyInit, Memory, Module,
+    RuntimeError, Store, WasmerEnv,
+};
 #[cfg(all(target_os = "macos", target_arch = "aarch64",))]
 use wasmer::{FunctionType, ValType};
 
 use std::sync::{Arc, Mutex, MutexGuard};
 
 /// This is returned in `RuntimeError`.
-/// Use `downcast` or `downcast_ref` to retrieve the `ExitCode`.
+/// Use `downcast` or `downcast_ref` to retrieve the `ExitCode` or the signal
+/// that terminated the instance.
 #[derive(Error, Debug)]
 pub enum WasiError {
     #[error("WASI exited with code: {0}")]
     Exit(syscalls::types::__wasi_exitcode_t),
+    #[error("WASI was terminated by signal: {0}")]
+    Signal(syscalls::types::__wasi_signal_t),
     #[error("The WASI version could not be determined")]
     UnknownWasiVersion,
+    #[error("WASI threads could not be set up: {0}")]
+    Threads(String),
 }
 
 /// The environment provided to the WASI imports.
@@ -57,18 +77,49 @@ pub struct WasiEnv {
     pub state: Arc<Mutex<WasiState>>,
     #[wasmer(export)]
     memory: LazyInit<Memory>,
+    /// The source of time, taken from the state so reading the clocks
+    /// doesn't need the lock.
+    clock: Arc<dyn WasiClock>,
+    /// The source of entropy, taken from the state like `clock`.
+    random: Arc<dyn WasiRandom>,
+    /// Receives an event for every syscall the program makes, if set.
+    observer: Option<Arc<dyn SyscallObserver>>,
+    /// Starts threads, if the program imports `thread-spawn`.
+    threads: Option<WasiThreads>,
 }
 
 impl WasiEnv {
     pub fn new(state: WasiState) -> Self {
         Self {
+            clock: state.clock.clone(),
+            random: state.random.clone(),
             state: Arc::new(Mutex::new(state)),
             memory: LazyInit::new(),
+            observer: None,
+            threads: None,
         }
     }
 
+    /// Set an observer that is told about every syscall the program makes,
+    /// along with its decoded arguments, its result and how long it took.
+    ///
+    /// This must be called before the import object is created, as the
+    /// imported functions capture a copy of the environment.
+    pub fn set_syscall_observer(&mut self, observer: Arc<dyn SyscallObserver>) {
+        self.observer = Some(observer);
+    }
+
+    /// Create the [`ImportObject`] for `module`.
+    ///
+    /// Programs that import `thread-spawn` from the `wasi` namespace can start
+    /// threads, following the wasi-threads proposal. They must import a shared
+    /// memory, which is created here and added to the import object along
+    /// with `thread-spawn`; see [`WasiEnv::join_threads`].
     pub fn import_object(&mut self, module: &Module) -> Result<ImportObject, WasiError> {
         let wasi_version = get_wasi_version(module, false).ok_or(WasiError::UnknownWasiVersion)?;
+        if self.threads.is_none() && threads::spawns_threads(module) {
+            self.threads = Some(WasiThreads::new(module, wasi_version)?);
+        }
         Ok(generate_import_object_from_env(
             module.store(),
             self.clone(),
@@ -92,6 +143,68 @@ impl WasiEnv {
         self.state.lock().unwrap()
     }
 
+    /// Wait for the threads the program started to finish, and return the
+    /// error of the first one that exited or trapped.
+    ///
+    /// A thread that exits or traps ends the program: the WebAssembly code
+    /// running in the store, `_start` included, is interrupted and traps with
+    /// `TrapCode::Interrupt`. The error of the thread is the one to report.
+    pub fn join_threads(&self) -> Result<(), RuntimeError> {
+        match &self.threads {
+            Some(threads) => threads.join(),
+            None => Ok(()),
+        }
+    }
+
+    /// Interrupt the threads the program started that are still running.
+    /// The syscalls waiting in them, e.g. `poll_oneoff` or `sock_accept`,
+    /// return `__WASI_EINTR`.
+    ///
+    /// The threads aren't stopped when `_start` returns, so embedders that
+    /// end the program there should stop them and then wait for them with
+    /// [`WasiEnv::join_threads`].
+    pub fn stop_threads(&self) {
+        if let Some(threads) = &self.threads {
+            threads.stop();
+        }
+    }
+
+    /// Whether the threads of the program must stop, see
+    /// [`WasiEnv::stop_threads`].
+    pub(crate) fn threads_stopped(&self) -> bool {
+        self.threads
+            .as_ref()
+            .map_or(false, |threads| threads.is_stopped())
+    }
+
+    /// The resources the program is using, to compare against the limits
+    /// set with the `max_*` methods of [`WasiStateBuilder`].
+    pub fn resource_usage(&self) -> WasiResourceUsage {
+        self.state().fs.resource_usage()
+    }
+
+    /// Capture the program running in `instance` into a versioned blob: the
+    /// [`WasiState`], the linear memories and the values of the mutable
+    /// globals, including the ones that aren't exported.
+    ///
+    /// Take snapshots while the program isn't running, e.g. after `_start`
+    /// returned or from a host function, and not while other threads do.
+    /// Files of the host are reopened by path on restore, like with
+    /// [`WasiState::unfreeze`].
+    pub fn snapshot(&self, instance: &Instance) -> Result<Vec<u8>, WasiSnapshotError> {
+        snapshot::take(&self.state(), instance)
+    }
+
+    /// Restore a snapshot taken with [`WasiEnv::snapshot`] into a fresh
+    /// `instance` of the same module, created with the import object of this
+    /// environment.
+    ///
+    /// The signal handlers, clock and random source of this environment are
+    /// kept, as they aren't part of the snapshot.
+    pub fn restore(&self, instance: &Instance, bytes: &[u8]) -> Result<(), WasiSnapshotError> {
+        snapshot::restore(&mut self.state(), instance, bytes)
+    }
+
     /// Get a reference to the memory
     pub fn memory(&self) -> &Memory {
         self.memory_ref()
@@ -111,24 +224,141 @@ impl WasiEnv {
 /// Create an [`ImportObject`] with an existing [`WasiEnv`]. `WasiEnv`
 /// needs a [`WasiState`], that can be constructed from a
 /// [`WasiStateBuilder`](state::WasiStateBuilder).
+///
+/// Besides the WASI namespace, the import object contains the
+/// `wasmer_sockets` namespace, which lets programs open sockets to the
+/// addresses allowed with [`WasiStateBuilder::allow_socket_addr`], and
+/// `thread-spawn` with the shared memory if [`WasiEnv::import_object`] set
+/// up threads.
 pub fn generate_import_object_from_env(
     store: &Store,
     wasi_env: WasiEnv,
     version: WasiVersion,
 ) -> ImportObject {
-    match version {
-        WasiVersion::Snapshot0 => generate_import_object_snapshot0(store, wasi_env),
+    let mut import_object = match version {
+        WasiVersion::Snapshot0 => generate_import_object_snapshot0(store, wasi_env.clone()),
         WasiVersion::Snapshot1 | WasiVersion::Latest => {
-            generate_import_object_snapshot1(store, wasi_env)
+            generate_import_object_snapshot1(store, wasi_env.clone())
         }
+    };
+    if let Some(threads) = &wasi_env.threads {
+        for (namespace, exports) in threads.imports(store, wasi_env.clone()) {
+            merge_namespace(store, &mut import_object, &namespace, exports);
+        }
+    }
+    merge_namespace(
+        store,
+        &mut import_object,
+        "wasmer_sockets",
+        generate_socket_exports(store, wasi_env),
+    );
+    import_object
+}
+
+/// Add `exports` to the `namespace` of `import_object`, keeping the exports
+/// already in it, as the shared memory may be imported from any namespace.
+fn merge_namespace(
+    store: &Store,
+    import_object: &mut ImportObject,
+    namespace: &str,
+    exports: Exports,
+) {
+    let mut merged: Exports = import_object
+        .clone()
+        .into_iter()
+        .filter(|((module, _), _)| module == namespace)
+        .map(|((_, name), export)| (name, Extern::from_vm_export(store, export)))
+        .collect();
+    for (name, value) in exports.iter() {
+        merged.insert(name.as_str(), value.clone());
     }
+    import_object.register(namespace, merged);
+}
+
+/// The socket extension, see [`syscalls::sockets`]
+fn generate_socket_exports(store: &Store, env: WasiEnv) -> Exports {
+    use crate::syscalls::sockets::*;
+
+    let mut exports = Exports::new();
+    exports.insert(
+        "sock_connect",
+        traced_syscall!(store, env, sock_connect { addr: ptr, fd: ptr }),
+    );
+    exports.insert(
+        "sock_bind",
+        traced_syscall!(store, env, sock_bind { addr: ptr, fd: ptr }),
+    );
+    exports.insert(
+        "sock_listen",
+        traced_syscall!(
+            store,
+            env,
+            sock_listen {
+                sock: fd,
+                backlog: int
+            }
+        ),
+    );
+    exports.insert(
+        "sock_accept",
+        traced_syscall!(
+            store,
+            env,
+            sock_accept {
+                sock: fd,
+                fd: ptr,
+                addr: ptr
+            }
+        ),
+    );
+    exports.insert(
+        "sock_addr_local",
+        traced_syscall!(
+            store,
+            env,
+            sock_addr_local {
+                sock: fd,
+                addr: ptr
+            }
+        ),
+    );
+    exports.insert(
+        "getaddrinfo",
+        traced_syscall!(
+            store,
+            env,
+            getaddrinfo {
+                node: path(node_len),
+                node_len: int,
+                port: int,
+                addrs: ptr,
+                addrs_len: int,
+                naddrs: ptr,
+            }
+        ),
+    );
+    exports
 }
 
 // Note: we use this wrapper because native functions with more than 9 params
 // fail on Apple Silicon (with Cranelift).
 fn get_path_open_for_store(store: &Store, env: WasiEnv) -> Function {
     #[cfg(not(all(target_os = "macos", target_arch = "aarch64",)))]
-    let path_open = Function::new_native_with_env(store, env, path_open);
+    let path_open = traced_syscall!(
+        store,
+        env,
+        path_open {
+            dirfd: fd,
+            dirflags: int,
+            path: path(path_len),
+            path_len: int,
+            o_flags: int,
+            fs_rights_base: int,
+            fs_rights_inheriting: int,
+            fs_flags: int,
+            fd: ptr,
+        }
+    );
     #[cfg(all(target_os = "macos", target_arch = "aarch64",))]
     let path_open = Function::new_with_env(
         store,
@@ -154,53 +384,124 @@ fn get_path_open_for_store(store: &Store, env: WasiEnv) -> Function {
 
 /// Combines a state generating function with the import list for legacy WASI
 fn generate_import_object_snapshot0(store: &Store, env: WasiEnv) -> ImportObject {
+    use crate::syscalls::legacy::snapshot0::{
+        fd_filestat_get, fd_seek, path_filestat_get, poll_oneoff,
+    };
+
     imports! {
         "wasi_unstable" => {
-            "args_get" => Function::new_native_with_env(store, env.clone(), args_get),
-            "args_sizes_get" => Function::new_native_with_env(store, env.clone(), args_sizes_get),
-            "clock_res_get" => Function::new_native_with_env(store, env.clone(), clock_res_get),
-            "clock_time_get" => Function::new_native_with_env(store, env.clone(), clock_time_get),
-            "environ_get" => Function::new_native_with_env(store, env.clone(), environ_get),
-            "environ_sizes_get" => Function::new_native_with_env(store, env.clone(), environ_sizes_get),
-            "fd_advise" => Function::new_native_with_env(store, env.clone(), fd_advise),
-            "fd_allocate" => Function::new_native_with_env(store, env.clone(), fd_allocate),
-            "fd_close" => Function::new_native_with_env(store, env.clone(), fd_close),
-            "fd_datasync" => Function::new_native_with_env(store, env.clone(), fd_datasync),
-            "fd_fdstat_get" => Function::new_native_with_env(store, env.clone(), fd_fdstat_get),
-            "fd_fdstat_set_flags" => Function::new_native_with_env(store, env.clone(), fd_fdstat_set_flags),
-            "fd_fdstat_set_rights" => Function::new_native_with_env(store, env.clone(), fd_fdstat_set_rights),
-            "fd_filestat_get" => Function::new_native_with_env(store, env.clone(), legacy::snapshot0::fd_filestat_get),
-            "fd_filestat_set_size" => Function::new_native_with_env(store, env.clone(), fd_filestat_set_size),
-            "fd_filestat_set_times" => Function::new_native_with_env(store, env.clone(), fd_filestat_set_times),
-            "fd_pread" => Function::new_native_with_env(store, env.clone(), fd_pread),
-            "fd_prestat_get" => Function::new_native_with_env(store, env.clone(), fd_prestat_get),
-            "fd_prestat_dir_name" => Function::new_native_with_env(store, env.clone(), fd_prestat_dir_name),
-            "fd_pwrite" => Function::new_native_with_env(store, env.clone(), fd_pwrite),
-            "fd_read" => Function::new_native_with_env(store, env.clone(), fd_read),
-            "fd_readdir" => Function::new_native_with_env(store, env.clone(), fd_readdir),
-            "fd_renumber" => Function::new_native_with_env(store, env.clone(), fd_renumber),
-            "fd_seek" => Function::new_native_with_env(store, env.clone(), legacy::snapshot0::fd_seek),
-            "fd_sync" => Function::new_native_with_env(store, env.clone(), fd_sync),
-            "fd_tell" => Function::new_native_with_env(store, env.clone(), fd_tell),
-            "fd_write" => Function::new_native_with_env(store, env.clone(), fd_write),
-            "path_create_directory" => Function::new_native_with_env(store, env.clone(), path_create_directory),
-            "path_filestat_get" => Function::new_native_with_env(store, env.clone(), legacy::snapshot0::path_filestat_get),
-            "path_filestat_set_times" => Function::new_native_with_env(store, env.clone(), path_filestat_set_times),
-            "path_link" => Function::new_native_with_env(store, env.clone(), path_link),
+            "args_get" => traced_syscall!(store, env, args_get { argv: ptr, argv_buf: ptr }),
+            "args_sizes_get" => traced_syscall!(store, env, args_sizes_get {
+                argc: ptr, argv_buf_size: ptr
+            }),
+            "clock_res_get" => traced_syscall!(store, env, clock_res_get {
+                clock_id: int, resolution: ptr
+            }),
+            "clock_time_get" => traced_syscall!(store, env, clock_time_get {
+                clock_id: int, precision: int, time: ptr
+            }),
+            "environ_get" => traced_syscall!(store, env, environ_get {
+                environ: ptr, environ_buf: ptr
+            }),
+            "environ_sizes_get" => traced_syscall!(store, env, environ_sizes_get {
+                environ_count: ptr, environ_buf_size: ptr
+            }),
+            "fd_advise" => traced_syscall!(store, env, fd_advise {
+                fd: fd, offset: int, len: int, advice: int
+            }),
+            "fd_allocate" => traced_syscall!(store, env, fd_allocate {
+                fd: fd, offset: int, len: int
+            }),
+            "fd_close" => traced_syscall!(store, env, fd_close { fd: fd }),
+            "fd_datasync" => traced_syscall!(store, env, fd_datasync { fd: fd }),
+            "fd_fdstat_get" => traced_syscall!(store, env, fd_fdstat_get { fd: fd, buf_ptr: ptr }),
+            "fd_fdstat_set_flags" => traced_syscall!(store, env, fd_fdstat_set_flags {
+                fd: fd, flags: int
+            }),
+            "fd_fdstat_set_rights" => traced_syscall!(store, env, fd_fdstat_set_rights {
+                fd: fd, fs_rights_base: int, fs_rights_inheriting: int
+            }),
+            "fd_filestat_get" => traced_syscall!(store, env, fd_filestat_get { fd: fd, buf: ptr }),
+            "fd_filestat_set_size" => traced_syscall!(store, env, fd_filestat_set_size {
+                fd: fd, st_size: int
+            }),
+            "fd_filestat_set_times" => traced_syscall!(store, env, fd_filestat_set_times {
+                fd: fd, st_atim: int, st_mtim: int, fst_flags: int
+            }),
+            "fd_pread" => traced_syscall!(store, env, fd_pread {
+                fd: fd, iovs: iovecs(iovs_len), iovs_len: int, offset: int, nread: ptr
+            }),
+            "fd_prestat_get" => traced_syscall!(store, env, fd_prestat_get { fd: fd, buf: ptr }),
+            "fd_prestat_dir_name" => traced_syscall!(store, env, fd_prestat_dir_name {
+                fd: fd, path: ptr, path_len: int
+            }),
+            "fd_pwrite" => traced_syscall!(store, env, fd_pwrite {
+                fd: fd, iovs: iovecs(iovs_len), iovs_len: int, offset: int, nwritten: ptr
+            }),
+            "fd_read" => traced_syscall!(store, env, fd_read {
+                fd: fd, iovs: iovecs(iovs_len), iovs_len: int, nread: ptr
+            }),
+            "fd_readdir" => traced_syscall!(store, env, fd_readdir {
+                fd: fd, buf: ptr, buf_len: int, cookie: int, bufused: ptr
+            }),
+            "fd_renumber" => traced_syscall!(store, env, fd_renumber { from: fd, to: fd }),
+            "fd_seek" => traced_syscall!(store, env, fd_seek {
+                fd: fd, offset: int, whence: int, newoffset: ptr
+            }),
+            "fd_sync" => traced_syscall!(store, env, fd_sync { fd: fd }),
+            "fd_tell" => traced_syscall!(store, env, fd_tell { fd: fd, offset: ptr }),
+            "fd_write" => traced_syscall!(store, env, fd_write {
+                fd: fd, iovs: iovecs(iovs_len), iovs_len: int, nwritten: ptr
+            }),
+            "path_create_directory" => traced_syscall!(store, env, path_create_directory {
+                fd: fd, path: path(path_len), path_len: int
+            }),
+            "path_filestat_get" => traced_syscall!(store, env, path_filestat_get {
+                fd: fd, flags: int, path: path(path_len), path_len: int, buf: ptr
+            }),
+            "path_filestat_set_times" => traced_syscall!(store, env, path_filestat_set_times {
+                fd: fd, flags: int, path: path(path_len), path_len: int, st_atim: int, st_mtim: int,
+                fst_flags: int
+            }),
+            "path_link" => traced_syscall!(store, env, path_link {
+                old_fd: fd, old_flags: int, old_path: path(old_path_len), old_path_len: int,
+                new_fd: fd, new_path: path(new_path_len), new_path_len: int
+            }),
             "path_open" => get_path_open_for_store(store, env.clone()),
-            "path_readlink" => Function::new_native_with_env(store, env.clone(), path_readlink),
-            "path_remove_directory" => Function::new_native_with_env(store, env.clone(), path_remove_directory),
-            "path_rename" => Function::new_native_with_env(store, env.clone(), path_rename),
-            "path_symlink" => Function::new_native_with_env(store, env.clone(), path_symlink),
-            "path_unlink_file" => Function::new_native_with_env(store, env.clone(), path_unlink_file),
-            "poll_oneoff" => Function::new_native_with_env(store, env.clone(), legacy::snapshot0::poll_oneoff),
-            "proc_exit" => Function::new_native_with_env(store, env.clone(), proc_exit),
-            "proc_raise" => Function::new_native_with_env(store, env.clone(), proc_raise),
-            "random_get" => Function::new_native_with_env(store, env.clone(), random_get),
-            "sched_yield" => Function::new_native_with_env(store, env.clone(), sched_yield),
-            "sock_recv" => Function::new_native_with_env(store, env.clone(), sock_recv),
-            "sock_send" => Function::new_native_with_env(store, env.clone(), sock_send),
-            "sock_shutdown" => Function::new_native_with_env(store, env.clone(), sock_shutdown),
+            "path_readlink" => traced_syscall!(store, env, path_readlink {
+                dir_fd: fd, path: path(path_len), path_len: int, buf: ptr, buf_len: int,
+                buf_used: ptr
+            }),
+            "path_remove_directory" => traced_syscall!(store, env, path_remove_directory {
+                fd: fd, path: path(path_len), path_len: int
+            }),
+            "path_rename" => traced_syscall!(store, env, path_rename {
+                old_fd: fd, old_path: path(old_path_len), old_path_len: int, new_fd: fd,
+                new_path: path(new_path_len), new_path_len: int
+            }),
+            "path_symlink" => traced_syscall!(store, env, path_symlink {
+                old_path: path(old_path_len), old_path_len: int, fd: fd,
+                new_path: path(new_path_len), new_path_len: int
+            }),
+            "path_unlink_file" => traced_syscall!(store, env, path_unlink_file {
+                fd: fd, path: path(path_len), path_len: int
+            }),
+            "poll_oneoff" => traced_syscall!(store, env, poll_oneoff {
+                in_: ptr, out_: ptr, nsubscriptions: int, nevents: ptr
+            }),
+            "proc_exit" => traced_syscall!(store, env, noreturn proc_exit { code: int }),
+            "proc_raise" => traced_syscall!(store, env, proc_raise { sig: int }),
+            "random_get" => traced_syscall!(store, env, random_get { buf: ptr, buf_len: int }),
+            "sched_yield" => traced_syscall!(store, env, sched_yield {  }),
+            "sock_recv" => traced_syscall!(store, env, sock_recv {
+                sock: fd, ri_data: iovecs(ri_data_len), ri_data_len: int, ri_flags: int,
+                ro_datalen: ptr, ro_flags: ptr
+            }),
+            "sock_send" => traced_syscall!(store, env, sock_send {
+                sock: fd, si_data: iovecs(si_data_len), si_data_len: int, si_flags: int,
+                so_datalen: ptr
+            }),
+            "sock_shutdown" => traced_syscall!(store, env, sock_shutdown { sock: fd, how: int }),
         },
     }
 }
@@ -209,51 +510,118 @@ fn generate_import_object_snapshot0(store: &Store, env: WasiEnv) -> ImportObject
 fn generate_import_object_snapshot1(store: &Store, env: WasiEnv) -> ImportObject {
     imports! {
         "wasi_snapshot_preview1" => {
-            "args_get" => Function::new_native_with_env(store, env.clone(), args_get),
-            "args_sizes_get" => Function::new_native_with_env(store, env.clone(), args_sizes_get),
-            "clock_res_get" => Function::new_native_with_env(store, env.clone(), clock_res_get),
-            "clock_time_get" => Function::new_native_with_env(store, env.clone(), clock_time_get),
-            "environ_get" => Function::new_native_with_env(store, env.clone(), environ_get),
-            "environ_sizes_get" => Function::new_native_with_env(store, env.clone(), environ_sizes_get),
-            "fd_advise" => Function::new_native_with_env(store, env.clone(), fd_advise),
-            "fd_allocate" => Function::new_native_with_env(store, env.clone(), fd_allocate),
-            "fd_close" => Function::new_native_with_env(store, env.clone(), fd_close),
-            "fd_datasync" => Function::new_native_with_env(store, env.clone(), fd_datasync),
-            "fd_fdstat_get" => Function::new_native_with_env(store, env.clone(), fd_fdstat_get),
-            "fd_fdstat_set_flags" => Function::new_native_with_env(store, env.clone(), fd_fdstat_set_flags),
-            "fd_fdstat_set_rights" => Function::new_native_with_env(store, env.clone(), fd_fdstat_set_rights),
-            "fd_filestat_get" => Function::new_native_with_env(store, env.clone(), fd_filestat_get),
-            "fd_filestat_set_size" => Function::new_native_with_env(store, env.clone(), fd_filestat_set_size),
-            "fd_filestat_set_times" => Function::new_native_with_env(store, env.clone(), fd_filestat_set_times),
-            "fd_pread" => Function::new_native_with_env(store, env.clone(), fd_pread),
-            "fd_prestat_get" => Function::new_native_with_env(store, env.clone(), fd_prestat_get),
-            "fd_prestat_dir_name" => Function::new_native_with_env(store, env.clone(), fd_prestat_dir_name),
-            "fd_pwrite" => Function::new_native_with_env(store, env.clone(), fd_pwrite),
-            "fd_read" => Function::new_native_with_env(store, env.clone(), fd_read),
-            "fd_readdir" => Function::new_native_with_env(store, env.clone(), fd_readdir),
-            "fd_renumber" => Function::new_native_with_env(store, env.clone(), fd_renumber),
-            "fd_seek" => Function::new_native_with_env(store, env.clone(), fd_seek),
-            "fd_sync" => Function::new_native_with_env(store, env.clone(), fd_sync),
-            "fd_tell" => Function::new_native_with_env(store, env.clone(), fd_tell),
-            "fd_write" => Function::new_native_with_env(store, env.clone(), fd_write),
-            "path_create_directory" => Function::new_native_with_env(store, env.clone(), path_create_directory),
-            "path_filestat_get" => Function::new_native_with_env(store, env.clone(), path_filestat_get),
-            "path_filestat_set_times" => Function::new_native_with_env(store, env.clone(), path_filestat_set_times),
-            "path_link" => Function::new_native_with_env(store, env.clone(), path_link),
+            "args_get" => traced_syscall!(store, env, args_get { argv: ptr, argv_buf: ptr }),
+            "args_sizes_get" => traced_syscall!(store, env, args_sizes_get {
+                argc: ptr, argv_buf_size: ptr
+            }),
+            "clock_res_get" => traced_syscall!(store, env, clock_res_get {
+                clock_id: int, resolution: ptr
+            }),
+            "clock_time_get" => traced_syscall!(store, env, clock_time_get {
+                clock_id: int, precision: int, time: ptr
+            }),
+            "environ_get" => traced_syscall!(store, env, environ_get {
+                environ: ptr, environ_buf: ptr
+            }),
+            "environ_sizes_get" => traced_syscall!(store, env, environ_sizes_get {
+                environ_count: ptr, environ_buf_size: ptr
+            }),
+            "fd_advise" => traced_syscall!(store, env, fd_advise {
+                fd: fd, offset: int, len: int, advice: int
+            }),
+            "fd_allocate" => traced_syscall!(store, env, fd_allocate {
+                fd: fd, offset: int, len: int
+            }),
+            "fd_close" => traced_syscall!(store, env, fd_close { fd: fd }),
+            "fd_datasync" => traced_syscall!(store, env, fd_datasync { fd: fd }),
+            "fd_fdstat_get" => traced_syscall!(store, env, fd_fdstat_get { fd: fd, buf_ptr: ptr }),
+            "fd_fdstat_set_flags" => traced_syscall!(store, env, fd_fdstat_set_flags {
+                fd: fd, flags: int
+            }),
+            "fd_fdstat_set_rights" => traced_syscall!(store, env, fd_fdstat_set_rights {
+                fd: fd, fs_rights_base: int, fs_rights_inheriting: int
+            }),
+            "fd_filestat_get" => traced_syscall!(store, env, fd_filestat_get { fd: fd, buf: ptr }),
+            "fd_filestat_set_size" => traced_syscall!(store, env, fd_filestat_set_size {
+                fd: fd, st_size: int
+            }),
+            "fd_filestat_set_times" => traced_syscall!(store, env, fd_filestat_set_times {
+                fd: fd, st_atim: int, st_mtim: int, fst_flags: int
+            }),
+            "fd_pread" => traced_syscall!(store, env, fd_pread {
+                fd: fd, iovs: iovecs(iovs_len), iovs_len: int, offset: int, nread: ptr
+            }),
+            "fd_prestat_get" => traced_syscall!(store, env, fd_prestat_get { fd: fd, buf: ptr }),
+            "fd_prestat_dir_name" => traced_syscall!(store, env, fd_prestat_dir_name {
+                fd: fd, path: ptr, path_len: int
+            }),
+            "fd_pwrite" => traced_syscall!(store, env, fd_pwrite {
+                fd: fd, iovs: iovecs(iovs_len), iovs_len: int, offset: int, nwritten: ptr
+            }),
+            "fd_read" => traced_syscall!(store, env, fd_read {
+                fd: fd, iovs: iovecs(iovs_len), iovs_len: int, nread: ptr
+            }),
+            "fd_readdir" => traced_syscall!(store, env, fd_readdir {
+                fd: fd, buf: ptr, buf_len: int, cookie: int, bufused: ptr
+            }),
+            "fd_renumber" => traced_syscall!(store, env, fd_renumber { from: fd, to: fd }),
+            "fd_seek" => traced_syscall!(store, env, fd_seek {
+                fd: fd, offset: int, whence: int, newoffset: ptr
+            }),
+            "fd_sync" => traced_syscall!(store, env, fd_sync { fd: fd }),
+            "fd_tell" => traced_syscall!(store, env, fd_tell { fd: fd, offset: ptr }),
+            "fd_write" => traced_syscall!(store, env, fd_write {
+                fd: fd, iovs: iovecs(iovs_len), iovs_len: int, nwritten: ptr
+            }),
+            "path_create_directory" => traced_syscall!(store, env, path_create_directory {
+                fd: fd, path: path(path_len), path_len: int
+            }),
+            "path_filestat_get" => traced_syscall!(store, env, path_filestat_get {
+                fd: fd, flags: int, path: path(path_len), path_len: int, buf: ptr
+            }),
+            "path_filestat_set_times" => traced_syscall!(store, env, path_filestat_set_times {
+                fd: fd, flags: int, path: path(path_len), path_len: int, st_atim: int, st_mtim: int,
+                fst_flags: int
+            }),
+            "path_link" => traced_syscall!(store, env, path_link {
+                old_fd: fd, old_flags: int, old_path: path(old_path_len), old_path_len: int,
+                new_fd: fd, new_path: path(new_path_len), new_path_len: int
+            }),
             "path_open" => get_path_open_for_store(store, env.clone()),
-            "path_readlink" => Function::new_native_with_env(store, env.clone(), path_readlink),
-            "path_remove_directory" => Function::new_native_with_env(store, env.clone(), path_remove_directory),
-            "path_rename" => Function::new_native_with_env(store, env.clone(), path_rename),
-            "path_symlink" => Function::new_native_with_env(store, env.clone(), path_symlink),
-            "path_unlink_file" => Function::new_native_with_env(store, env.clone(), path_unlink_file),
-            "poll_oneoff" => Function::new_native_with_env(store, env.clone(), poll_oneoff),
-            "proc_exit" => Function::new_native_with_env(store, env.clone(), proc_exit),
-            "proc_raise" => Function::new_native_with_env(store, env.clone(), proc_raise),
-            "random_get" => Function::new_native_with_env(store, env.clone(), random_get),
-            "sched_yield" => Function::new_native_with_env(store, env.clone(), sched_yield),
-            "sock_recv" => Function::new_native_with_env(store, env.clone(), sock_recv),
-            "sock_send" => Function::new_native_with_env(store, env.clone(), sock_send),
-            "sock_shutdown" => Function::new_native_with_env(store, env.clone(), sock_shutdown),
+            "path_readlink" => traced_syscall!(store, env, path_readlink {
+                dir_fd: fd, path: path(path_len), path_len: int, buf: ptr, buf_len: int,
+                buf_used: ptr
+            }),
+            "path_remove_directory" => traced_syscall!(store, env, path_remove_directory {
+                fd: fd, path: path(path_len), path_len: int
+            }),
+            "path_rename" => traced_syscall!(store, env, path_rename {
+                old_fd: fd, old_path: path(old_path_len), old_path_len: int, new_fd: fd,
+                new_path: path(new_path_len), new_path_len: int
+            }),
+            "path_symlink" => traced_syscall!(store, env, path_symlink {
+                old_path: path(old_path_len), old_path_len: int, fd: fd,
+                new_path: path(new_path_len), new_path_len: int
+            }),
+            "path_unlink_file" => traced_syscall!(store, env, path_unlink_file {
+                fd: fd, path: path(path_len), path_len: int
+            }),
+            "poll_oneoff" => traced_syscall!(store, env, poll_oneoff {
+                in_: ptr, out_: ptr, nsubscriptions: int, nevents: ptr
+            }),
+            "proc_exit" => traced_syscall!(store, env, noreturn proc_exit { code: int }),
+            "proc_raise" => traced_syscall!(store, env, proc_raise { sig: int }),
+            "random_get" => traced_syscall!(store, env, random_get { buf: ptr, buf_len: int }),
+            "sched_yield" => traced_syscall!(store, env, sched_yield {  }),
+            "sock_recv" => traced_syscall!(store, env, sock_recv {
+                sock: fd, ri_data: iovecs(ri_data_len), ri_data_len: int, ri_flags: int,
+                ro_datalen: ptr, ro_flags: ptr
+            }),
+            "sock_send" => traced_syscall!(store, env, sock_send {
+                sock: fd, si_data: iovecs(si_data_len), si_data_len: int, si_flags: int,
+                so_datalen: ptr
+            }),
+            "sock_shutdown" => traced_syscall!(store, env, sock_shutdown { sock: fd, how: int }),
         }
     }
 }
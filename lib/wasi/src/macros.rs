@@ -34,3 +34,41 @@ macro_rules! get_input_str {
         wasi_try!($data.get_utf8_str($memory, $len), __WASI_EINVAL)
     }};
 }
+
+/// Creates the import of a syscall that reports every call to the
+/// [`SyscallObserver`](crate::SyscallObserver) of the env.
+///
+/// Each parameter is listed with the decoder of `crate::trace` to use for
+/// it; `path` and `iovecs` also take the name of their length parameter.
+macro_rules! traced_syscall {
+    ($store:expr, $env:expr, noreturn $syscall:ident {
+        $( $arg:ident : $kind:ident $( ( $len:ident ) )? ),* $(,)?
+    }) => {
+        wasmer::Function::new_native_with_env(
+            $store,
+            $env.clone(),
+            |env: &crate::WasiEnv, $( $arg ),*| {
+                crate::trace::traced_noreturn(env, stringify!($syscall), || {
+                    vec![$( crate::trace::$kind(env, $arg $(, $len)?) ),*]
+                });
+                $syscall(env, $( $arg ),*)
+            },
+        )
+    };
+    ($store:expr, $env:expr, $syscall:ident {
+        $( $arg:ident : $kind:ident $( ( $len:ident ) )? ),* $(,)?
+    }) => {
+        wasmer::Function::new_native_with_env(
+            $store,
+            $env.clone(),
+            |env: &crate::WasiEnv, $( $arg ),*| {
+                crate::trace::traced(
+                    env,
+                    stringify!($syscall),
+                    || vec![$( crate::trace::$kind(env, $arg $(, $len)?) ),*],
+                    || $syscall(env, $( $arg ),*),
+                )
+            },
+        )
+    };
+}
@@ -0,0 +1,332 @@
+//! Checkpoints of a running program, see [`WasiEnv::snapshot`] and
+//! [`WasiEnv::restore`].
+//!
+//! A snapshot holds the [`WasiState`] along with the linear memories and the
+//! mutable globals of the instance, so the program can carry on in a fresh
+//! instance of the same module. It records a hash of the module, so it can't
+//! be restored into an instance of another one. The blob starts with [`MAGIC`] and the
+//! format [`VERSION`], followed by the bincode of [`Snapshot`].
+//!
+//! [`WasiEnv::snapshot`]: crate::WasiEnv::snapshot
+//! [`WasiEnv::restore`]: crate::WasiEnv::restore
+
+use crate::WasiState;
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+use std::io::{self, Write};
+use thiserror::Error;
+use wasmer::{Instance, MemoryError, Module, Pages, Val, WASM_PAGE_SIZE};
+
+const MAGIC: &[u8; 8] = b"\0wasisnp";
+/// The version of the format, to bump when [`Snapshot`] changes.
+const VERSION: u32 = 2;
+
+/// An error while taking or restoring a snapshot.
+#[derive(Error, Debug)]
+pub enum WasiSnapshotError {
+    #[error("the bytes are not a WASI snapshot")]
+    NotASnapshot,
+    #[error(
+        "the snapshot has version {0}, but only version {} is supported",
+        VERSION
+    )]
+    UnsupportedVersion(u32),
+    #[error("the snapshot could not be encoded or decoded: {0}")]
+    Encoding(#[from] bincode::Error),
+    #[error("global {0} holds a reference, which can't be saved")]
+    ReferenceGlobal(usize),
+    #[error("the snapshot was taken from another module: {0}")]
+    ModuleMismatch(String),
+    #[error("memory {0} could not be restored: {1}")]
+    Memory(usize, MemoryError),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot {
+    /// The hash of the module of the instance, see [`module_hash`]
+    module: u64,
+    /// The bincode of the [`WasiState`]
+    state: Vec<u8>,
+    /// The contents of every memory, in index order
+    memories: Vec<Vec<u8>>,
+    /// Every global in index order, with the value of the mutable ones
+    globals: Vec<Option<GlobalValue>>,
+}
+
+/// The value of a mutable global, with floats kept as bits so NaNs survive.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum GlobalValue {
+    I32(i32),
+    I64(i64),
+    F32(u32),
+    F64(u64),
+    V128(u128),
+}
+
+impl GlobalValue {
+    fn from_val(val: Val) -> Option<Self> {
+        match val {
+            Val::I32(v) => Some(Self::I32(v)),
+            Val::I64(v) => Some(Self::I64(v)),
+            Val::F32(v) => Some(Self::F32(v.to_bits())),
+            Val::F64(v) => Some(Self::F64(v.to_bits())),
+            Val::V128(v) => Some(Self::V128(v)),
+            _ => None,
+        }
+    }
+
+    fn to_val(self) -> Val {
+        match self {
+            Self::I32(v) => Val::I32(v),
+            Self::I64(v) => Val::I64(v),
+            Self::F32(v) => Val::F32(f32::from_bits(v)),
+            Self::F64(v) => Val::F64(f64::from_bits(v)),
+            Self::V128(v) => Val::V128(v),
+        }
+    }
+}
+
+/// Hashes the parts of `module` that a snapshot depends on: its imports,
+/// exports, types, and the initializers of its globals and tables.
+///
+/// The function bodies aren't available once the module is compiled, so
+/// modules that only differ in their code have the same hash.
+fn module_hash(module: &Module) -> Result<u64, WasiSnapshotError> {
+    let info = module.info();
+    let mut hasher = Fnv1a::default();
+    // The maps of `ModuleInfo` that aren't ordered are left out, as their
+    // order changes from one process to the next.
+    bincode::serialize_into(
+        &mut hasher,
+        &(
+            &info.imports,
+            &info.exports,
+            &info.start_function,
+            &info.table_initializers,
+            &info.global_initializers,
+            &info.signatures,
+            &info.functions,
+            &info.tables,
+            &info.memories,
+            &info.globals,
+        ),
+    )?;
+    Ok(hasher.0)
+}
+
+/// The 64-bit FNV-1a hash of the bytes written to it.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Write for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Captures `state` together with the memories and globals of `instance`.
+pub(crate) fn take(state: &WasiState, instance: &Instance) -> Result<Vec<u8>, WasiSnapshotError> {
+    let memories = instance
+        .all_memories()
+        .iter()
+        // Safety: the snapshot is taken while the program isn't running, so
+        // nothing writes to the memory while it's copied
+        .map(|memory| unsafe { memory.data_unchecked() }.to_vec())
+        .collect();
+    let globals = instance
+        .all_globals()
+        .iter()
+        .enumerate()
+        .map(|(index, global)| {
+            if !global.ty().mutability.is_mutable() {
+                return Ok(None);
+            }
+            GlobalValue::from_val(global.get())
+                .map(Some)
+                .ok_or(WasiSnapshotError::ReferenceGlobal(index))
+        })
+        .collect::<Result<_, _>>()?;
+    let snapshot = Snapshot {
+        module: module_hash(instance.module())?,
+        state: bincode::serialize(state)?,
+        memories,
+        globals,
+    };
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bincode::serialize_into(&mut bytes, &snapshot)?;
+    Ok(bytes)
+}
+
+/// Restores a snapshot into `instance` and `state`. Nothing is changed if
+/// the snapshot doesn't match the module of `instance`, or if its memories
+/// can't grow to the size of the snapshot.
+///
+/// The handlers, clock and random source of `state` are kept, as they are
+/// not part of the snapshot.
+pub(crate) fn restore(
+    state: &mut WasiState,
+    instance: &Instance,
+    bytes: &[u8],
+) -> Result<(), WasiSnapshotError> {
+    let snapshot = decode(bytes)?;
+    let mut restored: WasiState = bincode::deserialize(&snapshot.state)?;
+    if snapshot.module != module_hash(instance.module())? {
+        return Err(WasiSnapshotError::ModuleMismatch(
+            "the hash of the module differs".to_string(),
+        ));
+    }
+
+    let memories = instance.all_memories();
+    if memories.len() != snapshot.memories.len() {
+        return Err(WasiSnapshotError::ModuleMismatch(format!(
+            "it has {} memories instead of {}",
+            snapshot.memories.len(),
+            memories.len()
+        )));
+    }
+    for (index, (memory, data)) in memories.iter().zip(&snapshot.memories).enumerate() {
+        if data.len() % WASM_PAGE_SIZE != 0 || (data.len() as u64) < memory.data_size() {
+            return Err(WasiSnapshotError::ModuleMismatch(format!(
+                "memory {} is smaller than the memory of the instance",
+                index
+            )));
+        }
+        let pages = Pages((data.len() / WASM_PAGE_SIZE) as u32);
+        if let Some(maximum) = memory.ty().maximum {
+            if pages > maximum {
+                return Err(WasiSnapshotError::Memory(
+                    index,
+                    MemoryError::CouldNotGrow {
+                        current: memory.size(),
+                        attempted_delta: pages - memory.size(),
+                    },
+                ));
+            }
+        }
+    }
+    let globals = instance.all_globals();
+    if globals.len() != snapshot.globals.len() {
+        return Err(WasiSnapshotError::ModuleMismatch(format!(
+            "it has {} globals instead of {}",
+            snapshot.globals.len(),
+            globals.len()
+        )));
+    }
+    for (index, (global, value)) in globals.iter().zip(&snapshot.globals).enumerate() {
+        let matches = match value {
+            Some(value) => {
+                global.ty().mutability.is_mutable() && global.ty().ty == value.to_val().ty()
+            }
+            None => !global.ty().mutability.is_mutable(),
+        };
+        if !matches {
+            return Err(WasiSnapshotError::ModuleMismatch(format!(
+                "global {} has another type",
+                index
+            )));
+        }
+    }
+
+    // Every memory is grown before any is written, so a memory that can't
+    // grow leaves the contents of the instance untouched.
+    for (index, (memory, data)) in memories.iter().zip(&snapshot.memories).enumerate() {
+        let pages = Pages((data.len() / WASM_PAGE_SIZE) as u32);
+        if pages > memory.size() {
+            memory
+                .grow(pages - memory.size())
+                .map_err(|e| WasiSnapshotError::Memory(index, e))?;
+        }
+    }
+    for (memory, data) in memories.iter().zip(&snapshot.memories) {
+        // Safety: the instance isn't running, and the memory was grown to
+        // the size of the data
+        unsafe { memory.data_unchecked_mut() }.copy_from_slice(data);
+    }
+    for (global, value) in globals.iter().zip(&snapshot.globals) {
+        if let Some(value) = value {
+            global
+                .set(value.to_val())
+                .expect("the global was checked to be mutable");
+        }
+    }
+
+    restored.clock = state.clock.clone();
+    restored.random = state.random.clone();
+    restored.signal_handlers = std::mem::take(&mut state.signal_handlers);
+    *state = restored;
+    Ok(())
+}
+
+fn decode(bytes: &[u8]) -> Result<Snapshot, WasiSnapshotError> {
+    if bytes.len() < MAGIC.len() + 4 || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(WasiSnapshotError::NotASnapshot);
+    }
+    let (version, rest) = bytes[MAGIC.len()..].split_at(4);
+    let version = u32::from_le_bytes(version.try_into().unwrap());
+    if version != VERSION {
+        return Err(WasiSnapshotError::UnsupportedVersion(version));
+    }
+    Ok(bincode::deserialize(rest)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn global_values_keep_their_bits() {
+        let nan = f32::from_bits(0x7fc0_1234);
+        let value = GlobalValue::from_val(Val::F32(nan)).unwrap();
+        assert_eq!(value, GlobalValue::F32(0x7fc0_1234));
+        assert_eq!(value.to_val().unwrap_f32().to_bits(), 0x7fc0_1234);
+        assert_eq!(
+            GlobalValue::from_val(Val::I64(-3))
+                .unwrap()
+                .to_val()
+                .unwrap_i64(),
+            -3
+        );
+    }
+
+    #[test]
+    fn fnv1a() {
+        let mut hasher = Fnv1a::default();
+        hasher.write_all(b"a").unwrap();
+        assert_eq!(hasher.0, 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn rejects_foreign_bytes() {
+        assert!(matches!(
+            decode(b"\0asm\x01\0\0\0"),
+            Err(WasiSnapshotError::NotASnapshot)
+        ));
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            decode(&bytes),
+            Err(WasiSnapshotError::UnsupportedVersion(v)) if v == VERSION + 1
+        ));
+        bytes.truncate(MAGIC.len());
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.push(1);
+        assert!(matches!(
+            decode(&bytes),
+            Err(WasiSnapshotError::Encoding(_))
+        ));
+    }
+}
@@ -1,9 +1,21 @@
 //! Builder system for configuring a [`WasiState`] and creating it.
 
-use crate::state::{WasiFile, WasiFs, WasiFsError, WasiState};
-use crate::syscalls::types::{__WASI_STDERR_FILENO, __WASI_STDIN_FILENO, __WASI_STDOUT_FILENO};
+use crate::fs::{FileSystem, OverlayFileSystem};
+use crate::state::{
+    default_clock, default_random, Quota, SeededRandom, SignalAction, SocketAllowlist,
+    VirtualClock, WasiClock, WasiFile, WasiFs, WasiFsError, WasiRandom, WasiSignalHandler,
+    WasiSocket, WasiState,
+};
+use crate::syscalls::types::{
+    __wasi_signal_t, __WASI_STDERR_FILENO, __WASI_STDIN_FILENO, __WASI_STDOUT_FILENO,
+};
 use crate::WasiEnv;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 
 /// Creates an empty [`WasiStateBuilder`].
@@ -42,6 +54,13 @@ pub struct WasiStateBuilder {
     stdout_override: Option<Box<dyn WasiFile>>,
     stderr_override: Option<Box<dyn WasiFile>>,
     stdin_override: Option<Box<dyn WasiFile>>,
+    sockets: Vec<Box<dyn WasiSocket>>,
+    socket_allowlist: SocketAllowlist,
+    deterministic: Option<(u64, Duration)>,
+    clock: Option<Arc<dyn WasiClock>>,
+    random: Option<Arc<dyn WasiRandom>>,
+    quota: Quota,
+    signal_handlers: HashMap<__wasi_signal_t, Arc<dyn WasiSignalHandler>>,
 }
 
 impl std::fmt::Debug for WasiStateBuilder {
@@ -54,6 +73,13 @@ impl std::fmt::Debug for WasiStateBuilder {
             .field("stdout_override exists", &self.stdout_override.is_some())
             .field("stderr_override exists", &self.stderr_override.is_some())
             .field("stdin_override exists", &self.stdin_override.is_some())
+            .field("sockets", &self.sockets)
+            .field("socket_allowlist", &self.socket_allowlist)
+            .field("deterministic", &self.deterministic)
+            .field("clock", &self.clock)
+            .field("random", &self.random)
+            .field("quota", &self.quota)
+            .field("signal_handlers", &self.signal_handlers)
             .finish()
     }
 }
@@ -258,6 +284,51 @@ impl WasiStateBuilder {
         Ok(self)
     }
 
+    /// Preopen a copy-on-write view of a directory with the name `alias`
+    /// exposed to the WASI.
+    ///
+    /// The WASI can write to the directory, but the changes are kept in
+    /// memory and the directory itself is never modified. Use [`mount`] with
+    /// [`OverlayFileSystem::with_upper_dir`] to keep the changes in another
+    /// directory instead.
+    ///
+    /// [`mount`]: WasiStateBuilder::mount
+    pub fn overlay_dir<FilePath>(
+        &mut self,
+        alias: &str,
+        po_dir: FilePath,
+    ) -> Result<&mut Self, WasiStateCreationError>
+    where
+        FilePath: AsRef<Path>,
+    {
+        let path = po_dir.as_ref();
+        if !path.is_dir() {
+            return Err(WasiStateCreationError::PreopenedDirectoryNotFound(
+                path.to_path_buf(),
+            ));
+        }
+
+        self.mount(alias, OverlayFileSystem::new(path))
+    }
+
+    /// Preopen the root of `fs` with the name `alias` exposed to the WASI.
+    pub fn mount<F>(&mut self, alias: &str, fs: F) -> Result<&mut Self, WasiStateCreationError>
+    where
+        F: FileSystem,
+    {
+        let mut pdb = PreopenDirBuilder::new();
+        pdb.filesystem(fs)
+            .alias(alias)
+            .read(true)
+            .write(true)
+            .create(true);
+        let preopen = pdb.build()?;
+
+        self.preopens.push(preopen);
+
+        Ok(self)
+    }
+
     /// Overwrite the default WASI `stdout`, if you want to hold on to the
     /// original `stdout` use [`WasiFs::swap_file`] after building.
     pub fn stdout(&mut self, new_file: Box<dyn WasiFile>) -> &mut Self {
@@ -282,6 +353,127 @@ impl WasiStateBuilder {
         self
     }
 
+    /// Give the WASI an already opened socket. Sockets get the file
+    /// descriptors after the preopened directories, in the order they were
+    /// added.
+    pub fn preopen_socket(&mut self, socket: Box<dyn WasiSocket>) -> &mut Self {
+        self.sockets.push(socket);
+
+        self
+    }
+
+    /// Allow the WASI to connect to and bind to `addr` on any port in
+    /// `ports` through the `wasmer_sockets` namespace.
+    ///
+    /// Nothing is allowed by default. Resolved host names are checked too,
+    /// so allowing `127.0.0.1` doesn't allow `localhost` resolving to `::1`.
+    pub fn allow_socket_addr<A>(&mut self, addr: A, ports: RangeInclusive<u16>) -> &mut Self
+    where
+        A: Into<IpAddr>,
+    {
+        self.socket_allowlist.allow(addr.into(), ports);
+
+        self
+    }
+
+    /// Make the WASI behave identically on every run, for replay or
+    /// consensus.
+    ///
+    /// All clocks start at 0 and advance by `clock_step` each time they are
+    /// read or the program yields, and `poll_oneoff` timeouts move the
+    /// clocks instead of sleeping. File timestamps are taken from the same
+    /// clock. `random_get` returns bytes from a CSPRNG seeded with `seed`.
+    ///
+    /// Reads from host files, sockets and stdio can still differ between
+    /// runs; use virtual files or a [`MemFileSystem`] to avoid them.
+    ///
+    /// [`MemFileSystem`]: crate::MemFileSystem
+    pub fn deterministic(&mut self, seed: u64, clock_step: Duration) -> &mut Self {
+        self.deterministic = Some((seed, clock_step));
+
+        self
+    }
+
+    /// Read the WASI clocks from `clock` instead of the host's clocks.
+    ///
+    /// In deterministic mode the virtual clock is used instead.
+    pub fn clock(&mut self, clock: Box<dyn WasiClock>) -> &mut Self {
+        self.clock = Some(clock.into());
+
+        self
+    }
+
+    /// Fill `random_get` from `random` instead of the host's random number
+    /// generator.
+    ///
+    /// In deterministic mode the seeded generator is used instead.
+    pub fn random(&mut self, random: Box<dyn WasiRandom>) -> &mut Self {
+        self.random = Some(random.into());
+
+        self
+    }
+
+    /// Call `handler` when the WASI raises `signal` with `proc_raise`,
+    /// instead of terminating the instance or ignoring the signal.
+    ///
+    /// `SIGKILL` and `SIGSTOP` can't be handled, so their handlers are
+    /// never called.
+    pub fn signal_handler(
+        &mut self,
+        signal: __wasi_signal_t,
+        handler: Box<dyn WasiSignalHandler>,
+    ) -> &mut Self {
+        if SignalAction::can_handle(signal) {
+            self.signal_handlers.insert(signal, handler.into());
+        }
+
+        self
+    }
+
+    /// Limit the number of fds the WASI can have open at once, including
+    /// stdio and the preopened directories. Opening more fails with
+    /// `__WASI_EMFILE`.
+    pub fn max_open_fds(&mut self, max: u32) -> &mut Self {
+        self.quota.max_open_fds = Some(max);
+
+        self
+    }
+
+    /// Limit the number of bytes the WASI can write to the files of each
+    /// preopened directory. Writes past the limit fail with `__WASI_EDQUOT`.
+    pub fn max_bytes_written(&mut self, max: u64) -> &mut Self {
+        self.quota.max_bytes_written = Some(max);
+
+        self
+    }
+
+    /// Limit the size the WASI can grow files to, by writing or resizing
+    /// them. Growing a file past the limit fails with `__WASI_ENOSPC`.
+    pub fn max_file_size(&mut self, max: u64) -> &mut Self {
+        self.quota.max_file_size = Some(max);
+
+        self
+    }
+
+    /// Limit the number of files, directories and links the WASI can
+    /// create. Creating more fails with `__WASI_EDQUOT`.
+    pub fn max_entries_created(&mut self, max: u64) -> &mut Self {
+        self.quota.max_entries_created = Some(max);
+
+        self
+    }
+
+    /// Limit the number of inodes the WASI filesystem keeps in memory.
+    ///
+    /// Inodes are created for every file the WASI opens or looks up, and
+    /// they are kept until the file is removed. Going past the limit fails
+    /// with `__WASI_ENOSPC`.
+    pub fn max_inodes(&mut self, max: u64) -> &mut Self {
+        self.quota.max_inodes = Some(max);
+
+        self
+    }
+
     /// Setup the WASI filesystem before running
     // TODO: improve ergonomics on this function
     pub fn setup_fs(
@@ -381,9 +573,20 @@ impl WasiStateBuilder {
                 .swap_file(__WASI_STDERR_FILENO, stderr_override)
                 .map_err(WasiStateCreationError::WasiFsError)?;
         }
+        for socket in self.sockets.drain(..) {
+            wasi_fs
+                .create_socket_fd(socket)
+                .map_err(|e| WasiStateCreationError::WasiFsError(WasiFsError::from_wasi_err(e)))?;
+        }
         if let Some(f) = &self.setup_fs_fn {
             f(&mut wasi_fs).map_err(WasiStateCreationError::WasiFsSetupError)?;
         }
+        // the limits apply to the WASI, not to what the builder sets up
+        wasi_fs.quota = self.quota.clone();
+        let seeded_random = self.deterministic.map(|(seed, clock_step)| {
+            wasi_fs.use_virtual_clock(VirtualClock::new(clock_step));
+            SeededRandom::new(seed)
+        });
         Ok(WasiState {
             fs: wasi_fs,
             args: self.args.clone(),
@@ -399,6 +602,11 @@ impl WasiStateBuilder {
                     env
                 })
                 .collect(),
+            socket_allowlist: self.socket_allowlist.clone(),
+            seeded_random,
+            clock: self.clock.clone().unwrap_or_else(default_clock),
+            random: self.random.clone().unwrap_or_else(default_random),
+            signal_handlers: self.signal_handlers.clone(),
         })
     }
 
@@ -419,6 +627,7 @@ pub struct PreopenDirBuilder {
     read: bool,
     write: bool,
     create: bool,
+    fs: Option<Arc<dyn FileSystem>>,
 }
 
 /// The built version of `PreopenDirBuilder`
@@ -429,6 +638,8 @@ pub(crate) struct PreopenedDir {
     pub(crate) read: bool,
     pub(crate) write: bool,
     pub(crate) create: bool,
+    /// The filesystem `path` is located in, the host filesystem if `None`
+    pub(crate) fs: Option<Arc<dyn FileSystem>>,
 }
 
 impl PreopenDirBuilder {
@@ -448,6 +659,18 @@ impl PreopenDirBuilder {
         self
     }
 
+    /// Preopen the directory from `fs` instead of from the host filesystem
+    ///
+    /// The directory defaults to the root of `fs`, `/`.
+    pub fn filesystem<F>(&mut self, fs: F) -> &mut Self
+    where
+        F: FileSystem,
+    {
+        self.fs = Some(Arc::new(fs));
+
+        self
+    }
+
     /// Make this preopened directory appear to the WASI program as `alias`
     pub fn alias(&mut self, alias: &str) -> &mut Self {
         // We mount at preopened dirs at `/` by default and multiple `/` in a row
@@ -490,14 +713,21 @@ impl PreopenDirBuilder {
             return Err(WasiStateCreationError::PreopenedDirectoryError("Preopened directories must have at least one of read, write, create permissions set".to_string()));
         }
 
-        if self.path.is_none() {
-            return Err(WasiStateCreationError::PreopenedDirectoryError(
-                "Preopened directories must point to a host directory".to_string(),
-            ));
-        }
-        let path = self.path.clone().unwrap();
+        let path = match (&self.path, &self.fs) {
+            (Some(path), _) => path.clone(),
+            (None, Some(_)) => PathBuf::from("/"),
+            (None, None) => {
+                return Err(WasiStateCreationError::PreopenedDirectoryError(
+                    "Preopened directories must point to a host directory".to_string(),
+                ))
+            }
+        };
 
-        if !path.exists() {
+        let exists = match &self.fs {
+            Some(fs) => fs.metadata(&path).is_ok(),
+            None => path.exists(),
+        };
+        if !exists {
             return Err(WasiStateCreationError::PreopenedDirectoryNotFound(path));
         }
         if let Some(alias) = &self.alias {
@@ -510,6 +740,7 @@ impl PreopenDirBuilder {
             read: self.read,
             write: self.write,
             create: self.create,
+            fs: self.fs.clone(),
         })
     }
 }
@@ -517,6 +748,7 @@ impl PreopenDirBuilder {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::state::Kind;
 
     #[test]
     fn env_var_errors() {
@@ -557,6 +789,158 @@ mod test {
         );
     }
 
+    #[test]
+    fn mounted_filesystem() {
+        let mut state = create_wasi_state("test_prog")
+            .mount("data", crate::fs::MemFileSystem::new())
+            .unwrap()
+            .build()
+            .unwrap();
+        let fd = *state.fs.preopen_fds.last().unwrap();
+
+        assert!(state.fs.get_inode_at_path(fd, "file", false).is_err());
+        let inode = state.fs.get_inode_at_path(fd, ".", false).unwrap();
+        match &state.fs.inodes[inode].kind {
+            Kind::Dir { fs, path, .. } => {
+                let fs = state.fs.filesystem(*fs);
+                fs.create_dir(&path.join("dir")).unwrap();
+                fs.open(
+                    &path.join("dir/file"),
+                    crate::fs::OpenOptions::new().write(true).create(true),
+                )
+                .unwrap();
+            }
+            _ => panic!("mounted filesystem isn't preopened as a directory"),
+        }
+
+        let inode = state.fs.get_inode_at_path(fd, "dir/file", false).unwrap();
+        assert!(matches!(
+            state.fs.inodes[inode].kind,
+            Kind::File { fs: Some(_), .. }
+        ));
+        assert!(state
+            .fs
+            .get_inode_at_path(fd, "dir/missing", false)
+            .is_err());
+    }
+
+    #[test]
+    fn preopened_socket() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut state = create_wasi_state("test_prog")
+            .preopen_socket(Box::new(crate::state::HostTcpListener::new(listener)))
+            .allow_socket_addr(addr.ip(), addr.port()..=addr.port())
+            .build()
+            .unwrap();
+
+        assert!(state.socket_allowlist.is_allowed(&addr));
+        let fd = *state.fs.fd_map.keys().max().unwrap();
+        let inode = state.fs.get_fd(fd).unwrap().inode;
+        match &mut state.fs.inodes[inode].kind {
+            Kind::Socket { socket } => assert_eq!(socket.local_addr().unwrap(), addr),
+            _ => panic!("socket isn't opened as a socket"),
+        }
+    }
+
+    #[test]
+    fn deterministic_state() {
+        let build = || {
+            create_wasi_state("test_prog")
+                .mount("data", crate::fs::MemFileSystem::new())
+                .unwrap()
+                .deterministic(1, Duration::from_millis(1))
+                .build()
+                .unwrap()
+        };
+        let (mut a, mut b) = (build(), build());
+
+        let (mut buf_a, mut buf_b) = ([0; 16], [0; 16]);
+        a.seeded_random.as_mut().unwrap().fill(&mut buf_a);
+        b.seeded_random.as_mut().unwrap().fill(&mut buf_b);
+        assert_eq!(buf_a, buf_b);
+
+        let fd = *a.fs.preopen_fds.last().unwrap();
+        let inode = a.fs.get_inode_at_path(fd, ".", false).unwrap();
+        let stat = a.fs.inodes[inode].stat;
+        assert_eq!((stat.st_atim, stat.st_mtim, stat.st_ctim), (0, 0, 0));
+        assert_eq!(a.fs.current_time(&*a.clock), Ok(0));
+    }
+
+    #[test]
+    fn custom_clock_and_random() {
+        use crate::syscalls::types::*;
+
+        #[derive(Debug)]
+        struct FixedClock;
+        impl WasiClock for FixedClock {
+            fn resolution(&self, _: __wasi_clockid_t) -> Result<u64, __wasi_errno_t> {
+                Ok(1)
+            }
+            fn time(&self, _: __wasi_clockid_t, _: u64) -> Result<u64, __wasi_errno_t> {
+                Ok(42)
+            }
+        }
+        #[derive(Debug)]
+        struct Sevens;
+        impl WasiRandom for Sevens {
+            fn fill(&self, buf: &mut [u8]) -> Result<(), __wasi_errno_t> {
+                buf.iter_mut().for_each(|b| *b = 7);
+                Ok(())
+            }
+        }
+
+        let env = create_wasi_state("test_prog")
+            .clock(Box::new(FixedClock))
+            .random(Box::new(Sevens))
+            .finalize()
+            .unwrap();
+        assert_eq!(env.clock.time(__WASI_CLOCK_MONOTONIC, 1), Ok(42));
+        assert_eq!(env.state().fs.current_time(&*env.clock), Ok(42));
+        let mut buf = [0; 4];
+        env.random.fill(&mut buf).unwrap();
+        assert_eq!(buf, [7; 4]);
+
+        let env = create_wasi_state("test_prog").finalize().unwrap();
+        assert!(env.clock.time(__WASI_CLOCK_REALTIME, 1).unwrap() > 0);
+        assert_eq!(env.clock.time(42, 1), Err(__WASI_EINVAL));
+    }
+
+    #[test]
+    fn resource_limits() {
+        let mut state = create_wasi_state("test_prog")
+            .mount("data", crate::fs::MemFileSystem::new())
+            .unwrap()
+            .max_open_fds(6)
+            .max_inodes(6)
+            .build()
+            .unwrap();
+
+        // stdio, the root and `data` are already open
+        let usage = state.fs.resource_usage();
+        assert_eq!(usage.open_fds, 5);
+        assert_eq!(usage.inodes, 5);
+        assert_eq!(usage.bytes_written.get("data"), Some(&0));
+
+        let fd = *state.fs.preopen_fds.last().unwrap();
+        let inode = state.fs.get_fd(fd).unwrap().inode;
+        let rights = crate::state::ALL_RIGHTS;
+        state.fs.create_fd(rights, rights, 0, 0, inode).unwrap();
+        assert_eq!(
+            state.fs.create_fd(rights, rights, 0, 0, inode),
+            Err(crate::syscalls::types::__WASI_EMFILE)
+        );
+
+        assert!(state.fs.check_create_quota().is_ok());
+        state
+            .fs
+            .create_inode_with_default_stat(Kind::Buffer { buffer: vec![] }, false, "a".into());
+        assert_eq!(
+            state.fs.check_create_quota(),
+            Err(crate::syscalls::types::__WASI_ENOSPC)
+        );
+    }
+
     #[test]
     fn nul_character_in_args() {
         let output = create_wasi_state("test_prog").arg("--h\0elp").build();
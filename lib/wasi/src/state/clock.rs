@@ -0,0 +1,50 @@
+use crate::syscalls::types::*;
+use crate::syscalls::{platform_clock_res_get, platform_clock_time_get};
+use std::cell::Cell;
+use std::fmt;
+
+/// A source of time for the WASI clocks, set with
+/// [`WasiStateBuilder::clock`].
+///
+/// All times are in nanoseconds. Implementations should return
+/// `__WASI_EINVAL` for clocks they don't know.
+///
+/// [`WasiStateBuilder::clock`]: crate::WasiStateBuilder::clock
+pub trait WasiClock: fmt::Debug + Send + Sync + 'static {
+    /// The resolution of `clock_id`.
+    fn resolution(&self, clock_id: __wasi_clockid_t) -> Result<__wasi_timestamp_t, __wasi_errno_t>;
+
+    /// The current time of `clock_id`, which may be off by up to
+    /// `precision`.
+    fn time(
+        &self,
+        clock_id: __wasi_clockid_t,
+        precision: __wasi_timestamp_t,
+    ) -> Result<__wasi_timestamp_t, __wasi_errno_t>;
+}
+
+/// The clocks of the host, used by default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HostClock;
+
+impl WasiClock for HostClock {
+    fn resolution(&self, clock_id: __wasi_clockid_t) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+        let resolution = Cell::new(0);
+        match platform_clock_res_get(clock_id, &resolution) {
+            __WASI_ESUCCESS => Ok(resolution.get()),
+            err => Err(err),
+        }
+    }
+
+    fn time(
+        &self,
+        clock_id: __wasi_clockid_t,
+        precision: __wasi_timestamp_t,
+    ) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+        let time = Cell::new(0);
+        match platform_clock_time_get(clock_id, precision, &time) {
+            __WASI_ESUCCESS => Ok(time.get()),
+            err => Err(err),
+        }
+    }
+}
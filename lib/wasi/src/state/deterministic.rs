@@ -0,0 +1,153 @@
+//! Time and randomness for deterministic mode, see
+//! [`WasiStateBuilder::deterministic`].
+//!
+//! [`WasiStateBuilder::deterministic`]: crate::WasiStateBuilder::deterministic
+
+use crate::syscalls::types::*;
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::time::Duration;
+
+/// A clock that only moves in fixed steps, starting at 0.
+///
+/// Every clock id reads the same virtual time, which advances by one step
+/// each time the program reads it and when it yields or waits.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct VirtualClock {
+    now: __wasi_timestamp_t,
+    step: __wasi_timestamp_t,
+}
+
+impl VirtualClock {
+    pub fn new(step: Duration) -> Self {
+        Self {
+            now: 0,
+            // a clock that never moves would make timeouts hang forever
+            step: (step.as_nanos() as __wasi_timestamp_t).max(1),
+        }
+    }
+
+    /// Checks that `clock_id` names a clock, as all of them read this one.
+    pub fn check_clock_id(clock_id: __wasi_clockid_t) -> Result<(), __wasi_errno_t> {
+        match clock_id {
+            __WASI_CLOCK_REALTIME
+            | __WASI_CLOCK_MONOTONIC
+            | __WASI_CLOCK_PROCESS_CPUTIME_ID
+            | __WASI_CLOCK_THREAD_CPUTIME_ID => Ok(()),
+            _ => Err(__WASI_EINVAL),
+        }
+    }
+
+    /// The current time, without advancing the clock.
+    pub fn now(&self) -> __wasi_timestamp_t {
+        self.now
+    }
+
+    /// The step the clock advances by.
+    pub fn resolution(&self) -> __wasi_timestamp_t {
+        self.step
+    }
+
+    /// Reads the current time and advances the clock by one step.
+    pub fn tick(&mut self) -> __wasi_timestamp_t {
+        let now = self.now;
+        self.now = self.now.saturating_add(self.step);
+        now
+    }
+
+    /// Advances the clock by as many steps as it takes to reach `deadline`.
+    pub fn advance_to(&mut self, deadline: __wasi_timestamp_t) {
+        if deadline <= self.now {
+            return;
+        }
+        let behind = deadline - self.now;
+        let steps = behind / self.step + (behind % self.step != 0) as __wasi_timestamp_t;
+        self.now = self.now.saturating_add(steps.saturating_mul(self.step));
+    }
+}
+
+/// A cryptographically secure random number generator seeded by the embedder.
+#[derive(Debug, Clone)]
+pub(crate) struct SeededRandom {
+    rng: ChaCha20Rng,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: ChaCha20Rng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        self.rng.fill_bytes(buf)
+    }
+}
+
+// The generator is stored as its seed and position in the stream, so a
+// deserialized state continues with the same bytes.
+impl Serialize for SeededRandom {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.rng.get_seed(), self.rng.get_word_pos()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SeededRandom {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (seed, word_pos) = <([u8; 32], u128)>::deserialize(deserializer)?;
+        if word_pos >> 68 != 0 {
+            return Err(de::Error::custom("word position is out of range"));
+        }
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        rng.set_word_pos(word_pos);
+        Ok(Self { rng })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_moves_in_steps() {
+        let mut clock = VirtualClock::new(Duration::from_nanos(10));
+        assert_eq!(clock.tick(), 0);
+        assert_eq!(clock.tick(), 10);
+        assert_eq!(clock.now(), 20);
+
+        clock.advance_to(15);
+        assert_eq!(clock.now(), 20);
+        clock.advance_to(21);
+        assert_eq!(clock.now(), 30);
+        clock.advance_to(u64::MAX);
+        assert_eq!(clock.now(), u64::MAX);
+    }
+
+    #[test]
+    fn random_is_reproducible() {
+        let (mut a, mut b) = (SeededRandom::new(42), SeededRandom::new(42));
+        let (mut buf_a, mut buf_b) = ([0u8; 48], [0u8; 48]);
+        a.fill(&mut buf_a);
+        b.fill(&mut buf_b);
+        assert_eq!(buf_a, buf_b);
+        assert_ne!(buf_a, [0u8; 48]);
+
+        let mut other = [0u8; 48];
+        SeededRandom::new(43).fill(&mut other);
+        assert_ne!(buf_a, other);
+    }
+
+    #[test]
+    fn random_survives_serialization() {
+        let mut rng = SeededRandom::new(7);
+        rng.fill(&mut [0u8; 13]);
+        let mut restored: SeededRandom =
+            bincode::deserialize(&bincode::serialize(&rng).unwrap()).unwrap();
+
+        let (mut expected, mut actual) = ([0u8; 32], [0u8; 32]);
+        rng.fill(&mut expected);
+        restored.fill(&mut actual);
+        assert_eq!(expected, actual);
+    }
+}
@@ -16,10 +16,25 @@
 #![allow(clippy::cognitive_complexity, clippy::too_many_arguments)]
 
 mod builder;
+mod clock;
+mod deterministic;
+mod quota;
+mod random;
+mod signal;
+mod socket;
 mod types;
 
 pub use self::builder::*;
+pub use self::clock::*;
+pub(crate) use self::deterministic::{SeededRandom, VirtualClock};
+pub(crate) use self::quota::Quota;
+pub use self::quota::WasiResourceUsage;
+pub use self::random::*;
+pub(crate) use self::signal::SignalAction;
+pub use self::signal::WasiSignalHandler;
+pub use self::socket::*;
 pub use self::types::*;
+use crate::fs::{FileSystem, HostFileSystem, MemFileSystem};
 use crate::syscalls::types::*;
 use generational_arena::Arena;
 pub use generational_arena::Index as Inode;
@@ -28,10 +43,9 @@ use std::collections::HashMap;
 use std::{
     borrow::Borrow,
     cell::Cell,
-    fs,
     io::Write,
     path::{Path, PathBuf},
-    time::SystemTime,
+    sync::Arc,
 };
 use tracing::debug;
 
@@ -52,6 +66,12 @@ const STDOUT_DEFAULT_RIGHTS: __wasi_rights_t = __WASI_RIGHT_FD_DATASYNC
     | __WASI_RIGHT_FD_FILESTAT_GET
     | __WASI_RIGHT_POLL_FD_READWRITE;
 const STDERR_DEFAULT_RIGHTS: __wasi_rights_t = STDOUT_DEFAULT_RIGHTS;
+const SOCKET_RIGHTS: __wasi_rights_t = __WASI_RIGHT_FD_READ
+    | __WASI_RIGHT_FD_WRITE
+    | __WASI_RIGHT_FD_FDSTAT_SET_FLAGS
+    | __WASI_RIGHT_FD_FILESTAT_GET
+    | __WASI_RIGHT_POLL_FD_READWRITE
+    | __WASI_RIGHT_SOCK_SHUTDOWN;
 
 /// A completely aribtrary "big enough" number used as the upper limit for
 /// the number of symlinks that can be traversed when resolving a path
@@ -73,8 +93,9 @@ pub enum Kind {
     File {
         /// the open file, if it's open
         handle: Option<Box<dyn WasiFile>>,
-        /// The path on the host system where the file is located
-        /// This is deprecated and will be removed soon
+        /// The filesystem the file is located in, if any
+        fs: Option<FsId>,
+        /// The path in `fs` where the file is located
         path: PathBuf,
         /// Marks the file as a special file that only one `fd` can exist for
         /// This is useful when dealing with host-provided special files that
@@ -85,8 +106,9 @@ pub enum Kind {
     Dir {
         /// Parent directory
         parent: Option<Inode>,
-        /// The path on the host system where the directory is located
-        // TODO: wrap it like WasiFile
+        /// The filesystem the directory is located in
+        fs: FsId,
+        /// The path in `fs` where the directory is located
         path: PathBuf,
         /// The entries of a directory are lazily filled.
         entries: HashMap<String, Inode>,
@@ -115,8 +137,18 @@ pub enum Kind {
     Buffer {
         buffer: Vec<u8>,
     },
+    /// A socket opened through the socket extension or preopened by the
+    /// embedder. Sockets can't be serialized.
+    #[serde(skip)]
+    Socket {
+        socket: Box<dyn WasiSocket>,
+    },
 }
 
+/// Identifies one of the [`FileSystem`]s of a [`WasiFs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FsId(usize);
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Fd {
     pub rights: __wasi_rights_t,
@@ -163,6 +195,14 @@ pub struct WasiFs {
     inode_counter: Cell<u64>,
     /// for fds still open after the file has been deleted
     pub orphan_fds: HashMap<Inode, InodeVal>,
+    /// the filesystems backing the directories, indexed by `FsId`
+    filesystems: Vec<Arc<dyn FileSystem>>,
+    /// the clock of deterministic mode, which also provides the file timestamps
+    #[serde(default)]
+    pub(crate) virtual_clock: Option<VirtualClock>,
+    /// the resource limits set with the builder
+    #[serde(default)]
+    pub(crate) quota: Quota,
 }
 
 impl WasiFs {
@@ -193,6 +233,7 @@ impl WasiFs {
             let kind = if cur_dir_metadata.is_dir() {
                 Kind::Dir {
                     parent: Some(root_inode),
+                    fs: wasi_fs.add_filesystem(Arc::new(HostFileSystem)),
                     path: dir.clone(),
                     entries: Default::default(),
                 }
@@ -246,6 +287,7 @@ impl WasiFs {
             let kind = if cur_dir_metadata.is_dir() {
                 Kind::Dir {
                     parent: Some(root_inode),
+                    fs: wasi_fs.add_filesystem(Arc::new(HostFileSystem)),
                     path: real_dir.clone(),
                     entries: Default::default(),
                 }
@@ -299,6 +341,7 @@ impl WasiFs {
             read,
             write,
             create,
+            fs,
         } in preopens
         {
             debug!(
@@ -306,7 +349,10 @@ impl WasiFs {
                 &path.to_string_lossy(),
                 &alias
             );
-            let cur_dir_metadata = path.metadata().map_err(|e| {
+            let fs = fs
+                .clone()
+                .unwrap_or_else(|| Arc::new(HostFileSystem) as Arc<dyn FileSystem>);
+            let cur_dir_metadata = fs.metadata(path).map_err(|e| {
                 format!(
                     "Could not get metadata for file {:?}: {}",
                     path,
@@ -317,6 +363,7 @@ impl WasiFs {
             let kind = if cur_dir_metadata.is_dir() {
                 Kind::Dir {
                     parent: Some(root_inode),
+                    fs: wasi_fs.add_filesystem(fs),
                     path: path.clone(),
                     entries: Default::default(),
                 }
@@ -364,7 +411,8 @@ impl WasiFs {
                         | __WASI_RIGHT_PATH_CREATE_FILE
                         | __WASI_RIGHT_PATH_LINK_TARGET
                         | __WASI_RIGHT_PATH_OPEN
-                        | __WASI_RIGHT_PATH_RENAME_TARGET;
+                        | __WASI_RIGHT_PATH_RENAME_TARGET
+                        | __WASI_RIGHT_PATH_SYMLINK;
                 }
 
                 rights
@@ -428,6 +476,9 @@ impl WasiFs {
             next_fd: Cell::new(3),
             inode_counter: Cell::new(1024),
             orphan_fds: HashMap::new(),
+            filesystems: Vec::new(),
+            virtual_clock: None,
+            quota: Quota::default(),
         };
         wasi_fs.create_stdin();
         wasi_fs.create_stdout();
@@ -498,8 +549,8 @@ impl WasiFs {
             if let Kind::File { ref handle, .. } = self.inodes[fd.inode].kind {
                 Ok(handle)
             } else {
-                // Our public API should ensure that this is not possible
-                unreachable!("Non-file found in standard device location")
+                // `fd_renumber` can put anything in the standard device locations
+                Err(WasiFsError::NotAFile)
             }
         } else {
             // this should only trigger if we made a mistake in this crate
@@ -516,8 +567,8 @@ impl WasiFs {
             if let Kind::File { ref mut handle, .. } = self.inodes[fd.inode].kind {
                 Ok(handle)
             } else {
-                // Our public API should ensure that this is not possible
-                unreachable!("Non-file found in standard device location")
+                // `fd_renumber` can put anything in the standard device locations
+                Err(WasiFsError::NotAFile)
             }
         } else {
             // this should only trigger if we made a mistake in this crate
@@ -525,6 +576,17 @@ impl WasiFs {
         }
     }
 
+    /// Registers a filesystem backing directories of this [`WasiFs`].
+    pub(crate) fn add_filesystem(&mut self, fs: Arc<dyn FileSystem>) -> FsId {
+        self.filesystems.push(fs);
+        FsId(self.filesystems.len() - 1)
+    }
+
+    /// Returns the filesystem with the given id.
+    pub(crate) fn filesystem(&self, id: FsId) -> Arc<dyn FileSystem> {
+        self.filesystems[id.0].clone()
+    }
+
     /// Returns the next available inode index for creating a new inode.
     fn get_next_inode_index(&mut self) -> u64 {
         let next = self.inode_counter.get();
@@ -554,6 +616,8 @@ impl WasiFs {
         // TODO: check permissions here? probably not, but this should be
         // an explicit choice, so justify it in a comment when we remove this one
         let mut cur_inode = base_fd.inode;
+        // the new directories are virtual, so they're kept in memory
+        let mut new_dirs: Option<(FsId, PathBuf)> = None;
 
         let path: &Path = Path::new(&name);
         //let n_components = path.components().count();
@@ -566,9 +630,22 @@ impl WasiFs {
                         return Err(WasiFsError::AlreadyExists);
                     }
 
+                    let (fs, path) = match new_dirs.take() {
+                        Some((fs, mut path)) => {
+                            path.push(&segment_name);
+                            self.filesystem(fs).create_dir(&path)?;
+                            (fs, path)
+                        }
+                        None => (
+                            self.add_filesystem(Arc::new(MemFileSystem::new())),
+                            PathBuf::from("/"),
+                        ),
+                    };
+                    new_dirs = Some((fs, path.clone()));
                     let kind = Kind::Dir {
                         parent: Some(cur_inode),
-                        path: PathBuf::from(""),
+                        fs,
+                        path,
                         entries: HashMap::new(),
                     };
 
@@ -630,6 +707,7 @@ impl WasiFs {
 
                 let kind = Kind::File {
                     handle: Some(file),
+                    fs: None,
                     path: PathBuf::from(""),
                     fd: Some(self.next_fd.get()),
                 };
@@ -696,12 +774,13 @@ impl WasiFs {
         &mut self,
         fd: __wasi_fd_t,
     ) -> Result<__wasi_filesize_t, __wasi_errno_t> {
-        let fd = self.fd_map.get_mut(&fd).ok_or(__WASI_EBADF)?;
-        match &mut self.inodes[fd.inode].kind {
+        let inode = self.fd_map.get(&fd).ok_or(__WASI_EBADF)?.inode;
+        let inode = self.follow_symlinks(inode)?;
+        match &mut self.inodes[inode].kind {
             Kind::File { handle, .. } => {
                 if let Some(h) = handle {
                     let new_size = h.size();
-                    self.inodes[fd.inode].stat.st_size = new_size;
+                    self.inodes[inode].stat.st_size = new_size;
                     Ok(new_size as __wasi_filesize_t)
                 } else {
                     Err(__WASI_EBADF)
@@ -740,21 +819,19 @@ impl WasiFs {
         let path: &Path = Path::new(path);
 
         let mut cur_inode = base_dir.inode;
-        let n_components = path.components().count();
         // TODO: rights checks
-        'path_iter: for (i, component) in path.components().enumerate() {
-            // used to terminate symlink resolution properly
-            let last_component = i + 1 == n_components;
+        'path_iter: for component in path.components() {
             // for each component traverse file structure
             // loading inodes as necessary
             'symlink_resolution: while symlink_count < MAX_SYMLINKS {
                 match &mut self.inodes[cur_inode].kind {
-                    Kind::Buffer { .. } => unimplemented!("state::get_inode_at_path for buffers"),
+                    Kind::Buffer { .. } => return Err(__WASI_ENOTDIR),
+                    Kind::Socket { .. } => return Err(__WASI_ENOTDIR),
                     Kind::Dir {
                         ref mut entries,
                         ref path,
                         ref parent,
-                        ..
+                        fs,
                     } => {
                         match component.as_os_str().to_string_lossy().borrow() {
                             ".." => {
@@ -768,50 +845,53 @@ impl WasiFs {
                             "." => continue 'path_iter,
                             _ => (),
                         }
-                        // used for full resolution of symlinks
-                        let mut loop_for_symlink = false;
                         if let Some(entry) =
                             entries.get(component.as_os_str().to_string_lossy().as_ref())
                         {
                             cur_inode = *entry;
                         } else {
+                            let fs_id = *fs;
                             let file = {
                                 let mut cd = path.clone();
                                 cd.push(component);
                                 cd
                             };
-                            let metadata = file.symlink_metadata().ok().ok_or(__WASI_EINVAL)?;
-                            let file_type = metadata.file_type();
+                            let fs = self.filesystem(fs_id);
+                            let metadata = fs
+                                .symlink_metadata(&file)
+                                .map_err(WasiFsError::into_wasi_err)?;
                             // we want to insert newly opened dirs and files, but not transient symlinks
                             // TODO: explain why (think about this deeply when well rested)
                             let mut should_insert = false;
 
-                            let kind = if file_type.is_dir() {
+                            let kind = if metadata.is_dir() {
                                 should_insert = true;
                                 // load DIR
                                 Kind::Dir {
                                     parent: Some(cur_inode),
+                                    fs: fs_id,
                                     path: file.clone(),
                                     entries: Default::default(),
                                 }
-                            } else if file_type.is_file() {
+                            } else if metadata.is_file() {
                                 should_insert = true;
                                 // load file
                                 Kind::File {
                                     handle: None,
+                                    fs: Some(fs_id),
                                     path: file.clone(),
                                     fd: None,
                                 }
-                            } else if file_type.is_symlink() {
-                                let link_value = file.read_link().ok().ok_or(__WASI_EIO)?;
+                            } else if metadata.is_symlink() {
+                                let link_value = fs.read_link(&file).map_err(|_| __WASI_EIO)?;
                                 debug!("attempting to decompose path {:?}", link_value);
 
-                                let (pre_open_dir_fd, relative_path) = if link_value.is_relative() {
-                                    self.path_into_pre_open_and_relative_path(&file)?
-                                } else {
-                                    unimplemented!("Absolute symlinks are not yet supported");
-                                };
-                                loop_for_symlink = true;
+                                if !link_value.is_relative() {
+                                    // absolute symlinks are not yet supported
+                                    return Err(__WASI_ENOTSUP);
+                                }
+                                let (pre_open_dir_fd, relative_path) =
+                                    self.path_into_pre_open_and_relative_path(fs_id, &file)?;
                                 symlink_count += 1;
                                 Kind::Symlink {
                                     base_po_dir: pre_open_dir_fd,
@@ -819,57 +899,37 @@ impl WasiFs {
                                     relative_path: link_value,
                                 }
                             } else {
-                                #[cfg(unix)]
+                                // special files such as devices, fifos and sockets
+                                let kind = Kind::File {
+                                    handle: None,
+                                    fs: Some(fs_id),
+                                    path: file.clone(),
+                                    fd: None,
+                                };
+                                let new_inode = self.create_inode_with_stat(
+                                    kind,
+                                    false,
+                                    file.to_string_lossy().to_string(),
+                                    __wasi_filestat_t {
+                                        st_filetype: metadata.filetype,
+                                        ..__wasi_filestat_t::default()
+                                    },
+                                );
+                                if let Kind::Dir {
+                                    ref mut entries, ..
+                                } = &mut self.inodes[cur_inode].kind
                                 {
-                                    use std::os::unix::fs::FileTypeExt;
-                                    let file_type: __wasi_filetype_t = if file_type.is_char_device()
-                                    {
-                                        __WASI_FILETYPE_CHARACTER_DEVICE
-                                    } else if file_type.is_block_device() {
-                                        __WASI_FILETYPE_BLOCK_DEVICE
-                                    } else if file_type.is_fifo() {
-                                        // FIFO doesn't seem to fit any other type, so unknown
-                                        __WASI_FILETYPE_UNKNOWN
-                                    } else if file_type.is_socket() {
-                                        // TODO: how do we know if it's a `__WASI_FILETYPE_SOCKET_STREAM` or
-                                        // a `__WASI_FILETYPE_SOCKET_DGRAM`?
-                                        __WASI_FILETYPE_SOCKET_STREAM
-                                    } else {
-                                        unimplemented!("state::get_inode_at_path unknown file type: not file, directory, symlink, char device, block device, fifo, or socket");
-                                    };
-
-                                    let kind = Kind::File {
-                                        handle: None,
-                                        path: file.clone(),
-                                        fd: None,
-                                    };
-                                    let new_inode = self.create_inode_with_stat(
-                                        kind,
-                                        false,
-                                        file.to_string_lossy().to_string(),
-                                        __wasi_filestat_t {
-                                            st_filetype: file_type,
-                                            ..__wasi_filestat_t::default()
-                                        },
+                                    entries.insert(
+                                        component.as_os_str().to_string_lossy().to_string(),
+                                        new_inode,
+                                    );
+                                } else {
+                                    unreachable!(
+                                        "Attempted to insert special device into non-directory"
                                     );
-                                    if let Kind::Dir {
-                                        ref mut entries, ..
-                                    } = &mut self.inodes[cur_inode].kind
-                                    {
-                                        entries.insert(
-                                            component.as_os_str().to_string_lossy().to_string(),
-                                            new_inode,
-                                        );
-                                    } else {
-                                        unreachable!(
-                                            "Attempted to insert special device into non-directory"
-                                        );
-                                    }
-                                    // perhaps just continue with symlink resolution and return at the end
-                                    return Ok(new_inode);
                                 }
-                                #[cfg(not(unix))]
-                                unimplemented!("state::get_inode_at_path unknown file type: not file, directory, or symlink");
+                                // perhaps just continue with symlink resolution and return at the end
+                                return Ok(new_inode);
                             };
 
                             let new_inode =
@@ -886,11 +946,6 @@ impl WasiFs {
                                 }
                             }
                             cur_inode = new_inode;
-
-                            if loop_for_symlink && follow_symlinks {
-                                debug!("Following symlink to {:?}", cur_inode);
-                                continue 'symlink_resolution;
-                            }
                         }
                     }
                     Kind::Root { entries } => {
@@ -913,40 +968,11 @@ impl WasiFs {
                     Kind::File { .. } => {
                         return Err(__WASI_ENOTDIR);
                     }
-                    Kind::Symlink {
-                        base_po_dir,
-                        path_to_symlink,
-                        relative_path,
-                    } => {
-                        let new_base_dir = *base_po_dir;
-                        // allocate to reborrow mutabily to recur
-                        let new_path = {
-                            /*if let Kind::Root { .. } = self.inodes[base_po_dir].kind {
-                                assert!(false, "symlinks should never be relative to the root");
-                            }*/
-                            let mut base = path_to_symlink.clone();
-                            // remove the symlink file itself from the path, leaving just the path from the base
-                            // to the dir containing the symlink
-                            base.pop();
-                            base.push(relative_path);
-                            base.to_string_lossy().to_string()
-                        };
+                    Kind::Symlink { .. } => {
+                        // a symlink in the middle of the path is always followed, then the
+                        // current component is looked up in the directory it points to
                         debug!("Following symlink recursively");
-                        let symlink_inode = self.get_inode_at_path_inner(
-                            new_base_dir,
-                            &new_path,
-                            symlink_count + 1,
-                            follow_symlinks,
-                        )?;
-                        cur_inode = symlink_inode;
-                        // if we're at the very end and we found a file, then we're done
-                        // TODO: figure out if this should also happen for directories?
-                        if let Kind::File { .. } = &self.inodes[cur_inode].kind {
-                            // check if on last step
-                            if last_component {
-                                break 'symlink_resolution;
-                            }
-                        }
+                        cur_inode = self.follow_symlinks_inner(cur_inode, symlink_count + 1)?;
                         continue 'symlink_resolution;
                     }
                 }
@@ -954,9 +980,76 @@ impl WasiFs {
             }
         }
 
+        if follow_symlinks {
+            cur_inode = self.follow_symlinks_inner(cur_inode, symlink_count + 1)?;
+        }
         Ok(cur_inode)
     }
 
+    /// Returns the inode that `inode` points to, following symlinks until it
+    /// reaches something that's not a symlink.
+    pub(crate) fn follow_symlinks(&mut self, inode: Inode) -> Result<Inode, __wasi_errno_t> {
+        self.follow_symlinks_inner(inode, 0)
+    }
+
+    fn follow_symlinks_inner(
+        &mut self,
+        inode: Inode,
+        symlink_count: u32,
+    ) -> Result<Inode, __wasi_errno_t> {
+        match &self.inodes[inode].kind {
+            Kind::Symlink {
+                base_po_dir,
+                path_to_symlink,
+                relative_path,
+            } => {
+                let base_po_dir = *base_po_dir;
+                // remove the symlink file itself from the path, leaving just the path from the base
+                // to the dir containing the symlink
+                let mut path = path_to_symlink.clone();
+                path.pop();
+                path.push(relative_path);
+                self.get_inode_at_path_inner(
+                    base_po_dir,
+                    &path.to_string_lossy(),
+                    symlink_count,
+                    true,
+                )
+            }
+            _ => Ok(inode),
+        }
+    }
+
+    /// Updates the paths of the directory `inode` and everything loaded below
+    /// it after the directory was moved from `old_path` to `new_path`.
+    pub(crate) fn update_moved_dir_paths(
+        &mut self,
+        inode: Inode,
+        old_path: &Path,
+        new_path: &Path,
+    ) {
+        let update_path = |path: &mut PathBuf| {
+            if let Ok(rest) = path.strip_prefix(old_path) {
+                *path = if rest.as_os_str().is_empty() {
+                    new_path.to_path_buf()
+                } else {
+                    new_path.join(rest)
+                };
+            }
+        };
+        let mut to_update = vec![inode];
+        while let Some(inode) = to_update.pop() {
+            match &mut self.inodes[inode].kind {
+                Kind::Dir { path, entries, .. } => {
+                    update_path(path);
+                    to_update.extend(entries.values());
+                }
+                Kind::File { path, .. } => update_path(path),
+                _ => (),
+            }
+        }
+    }
+
     /// Splits a path into the first preopened directory that is a parent of it,
     /// if such a preopened directory exists, and the rest of the path.
     ///
@@ -967,15 +1060,20 @@ impl WasiFs {
     /// not the same as libpreopen or update its behavior to be the same.
     fn path_into_pre_open_and_relative_path(
         &self,
+        fs: FsId,
         path: &Path,
     ) -> Result<(__wasi_fd_t, PathBuf), __wasi_errno_t> {
-        // for each preopened directory
+        // for each preopened directory of the same filesystem
         for po_fd in &self.preopen_fds {
-            let po_inode = self.fd_map[po_fd].inode;
+            let po_inode = match self.fd_map.get(po_fd) {
+                Some(fd) => fd.inode,
+                None => continue,
+            };
             let po_path = match &self.inodes[po_inode].kind {
-                Kind::Dir { path, .. } => &**path,
-                Kind::Root { .. } => Path::new("/"),
-                _ => unreachable!("Preopened FD that's not a directory or the root"),
+                Kind::Dir {
+                    path, fs: po_fs, ..
+                } if *po_fs == fs => &**path,
+                _ => continue,
             };
             // stem path based on it
             if let Ok(rest) = path.strip_prefix(po_path) {
@@ -1035,11 +1133,9 @@ impl WasiFs {
         while cur_inode != base_inode {
             counter += 1;
             match &self.inodes[cur_inode].kind {
-                Kind::Dir { parent, .. } => {
-                    if let Some(p) = parent {
-                        cur_inode = *p;
-                    }
-                }
+                Kind::Dir {
+                    parent: Some(p), ..
+                } => cur_inode = *p,
                 _ => return Err(__WASI_EINVAL),
             }
         }
@@ -1081,8 +1177,13 @@ impl WasiFs {
         for comp in components.rev() {
             parent_dir.push(comp);
         }
-        self.get_inode_at_path(base, &parent_dir.to_string_lossy(), follow_symlinks)
-            .map(|v| (v, new_entity_name))
+        let parent_inode =
+            self.get_inode_at_path(base, &parent_dir.to_string_lossy(), follow_symlinks)?;
+        let parent_inode = self.follow_symlinks(parent_inode)?;
+        match self.inodes[parent_inode].kind {
+            Kind::Dir { .. } | Kind::Root { .. } => Ok((parent_inode, new_entity_name)),
+            _ => Err(__WASI_ENOTDIR),
+        }
     }
 
     pub fn get_fd(&self, fd: __wasi_fd_t) -> Result<&Fd, __wasi_errno_t> {
@@ -1151,6 +1252,7 @@ impl WasiFs {
                 Kind::File { .. } => __WASI_FILETYPE_REGULAR_FILE,
                 Kind::Dir { .. } => __WASI_FILETYPE_DIRECTORY,
                 Kind::Symlink { .. } => __WASI_FILETYPE_SYMBOLIC_LINK,
+                Kind::Socket { .. } => __WASI_FILETYPE_SOCKET_STREAM,
                 _ => __WASI_FILETYPE_UNKNOWN,
             },
             fs_flags: fd.flags,
@@ -1212,8 +1314,9 @@ impl WasiFs {
                     }
                     // TODO: verify this behavior
                     Kind::Dir { .. } => return Err(__WASI_EISDIR),
-                    Kind::Symlink { .. } => unimplemented!("WasiFs::flush Kind::Symlink"),
+                    Kind::Symlink { .. } => return Err(__WASI_ELOOP),
                     Kind::Buffer { .. } => (),
+                    Kind::Socket { socket } => socket.flush().map_err(|_| __WASI_EIO)?,
                     _ => return Err(__WASI_EIO),
                 }
             }
@@ -1221,6 +1324,29 @@ impl WasiFs {
         Ok(())
     }
 
+    /// Switches to the clock of deterministic mode, restamping the inodes
+    /// that already exist with its time.
+    pub(crate) fn use_virtual_clock(&mut self, clock: VirtualClock) {
+        for (_, inode) in self.inodes.iter_mut() {
+            inode.stat.st_atim = clock.now();
+            inode.stat.st_mtim = clock.now();
+            inode.stat.st_ctim = clock.now();
+        }
+        self.virtual_clock = Some(clock);
+    }
+
+    /// The current time for file timestamps, from the virtual clock in
+    /// deterministic mode and from the realtime clock of `clock` otherwise.
+    pub(crate) fn current_time(
+        &self,
+        clock: &dyn WasiClock,
+    ) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+        match &self.virtual_clock {
+            Some(virtual_clock) => Ok(virtual_clock.now()),
+            None => clock.time(__WASI_CLOCK_REALTIME, 1),
+        }
+    }
+
     /// Creates an inode and inserts it given a Kind and some extra data
     pub(crate) fn create_inode(
         &mut self,
@@ -1228,6 +1354,7 @@ impl WasiFs {
         is_preopened: bool,
         name: String,
     ) -> Result<Inode, __wasi_errno_t> {
+        self.quota.check_inodes(self.inodes.len())?;
         let stat = self.get_stat_for_kind(&kind).ok_or(__WASI_EIO)?;
         Ok(self.create_inode_with_stat(kind, is_preopened, name, stat))
     }
@@ -1252,6 +1379,11 @@ impl WasiFs {
         mut stat: __wasi_filestat_t,
     ) -> Inode {
         stat.st_ino = self.get_next_inode_index();
+        if let Some(clock) = &self.virtual_clock {
+            stat.st_atim = clock.now();
+            stat.st_mtim = clock.now();
+            stat.st_ctim = clock.now();
+        }
 
         self.inodes.insert(InodeVal {
             stat,
@@ -1269,6 +1401,7 @@ impl WasiFs {
         open_flags: u16,
         inode: Inode,
     ) -> Result<__wasi_fd_t, __wasi_errno_t> {
+        self.quota.check_open_fds(self.fd_map.len())?;
         let idx = self.next_fd.get();
         self.next_fd.set(idx + 1);
         self.fd_map.insert(
@@ -1285,6 +1418,75 @@ impl WasiFs {
         Ok(idx)
     }
 
+    /// Checks that the quota allows creating a file, directory or link.
+    pub(crate) fn check_create_quota(&self) -> Result<(), __wasi_errno_t> {
+        self.quota.check_entry()?;
+        self.quota.check_inodes(self.inodes.len())
+    }
+
+    /// Checks that the quota allows writing `len` bytes to `inode` at
+    /// `offset`.
+    pub(crate) fn check_write_quota(
+        &self,
+        inode: Inode,
+        offset: u64,
+        len: u64,
+    ) -> Result<(), __wasi_errno_t> {
+        self.check_resize_quota(inode, offset.saturating_add(len))?;
+        match &self.inodes[inode].kind {
+            Kind::File { fs: Some(fs), .. } => self.quota.check_bytes_written(*fs, len),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that the quota allows `inode` to grow to `new_size` bytes.
+    pub(crate) fn check_resize_quota(
+        &self,
+        inode: Inode,
+        new_size: u64,
+    ) -> Result<(), __wasi_errno_t> {
+        let size = match &self.inodes[inode].kind {
+            Kind::File {
+                handle: Some(handle),
+                ..
+            } => handle.size(),
+            Kind::Buffer { buffer } => buffer.len() as u64,
+            _ => return Ok(()),
+        };
+        self.quota.check_file_size(size, new_size)
+    }
+
+    /// Counts `len` bytes written to `inode` towards the quota of the
+    /// preopened directory it's in.
+    pub(crate) fn add_bytes_written(&mut self, inode: Inode, len: u64) {
+        if let Kind::File { fs: Some(fs), .. } = &self.inodes[inode].kind {
+            self.quota.add_bytes_written(*fs, len);
+        }
+    }
+
+    /// The resources the program is using.
+    pub fn resource_usage(&self) -> WasiResourceUsage {
+        let bytes_written = self
+            .preopen_fds
+            .iter()
+            .filter_map(|fd| {
+                let inode = &self.inodes[self.fd_map.get(fd)?.inode];
+                match &inode.kind {
+                    Kind::Dir { fs, .. } => {
+                        Some((inode.name.clone(), self.quota.bytes_written(*fs)))
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+        WasiResourceUsage {
+            open_fds: self.fd_map.len() as u32,
+            inodes: self.inodes.len() as u64,
+            entries_created: self.quota.entries_created(),
+            bytes_written,
+        }
+    }
+
     /// Low level function to remove an inode, that is it deletes the WASI FS's
     /// knowledge of a file.
     ///
@@ -1359,6 +1561,7 @@ impl WasiFs {
         let kind = Kind::File {
             fd: Some(raw_fd),
             handle: Some(handle),
+            fs: None,
             path: "".into(),
         };
         let inode = self.inodes.insert(InodeVal {
@@ -1383,7 +1586,9 @@ impl WasiFs {
 
     pub fn get_stat_for_kind(&self, kind: &Kind) -> Option<__wasi_filestat_t> {
         let md = match kind {
-            Kind::File { handle, path, .. } => match handle {
+            Kind::File {
+                handle, fs, path, ..
+            } => match handle {
                 Some(wf) => {
                     return Some(__wasi_filestat_t {
                         st_filetype: __WASI_FILETYPE_REGULAR_FILE,
@@ -1395,21 +1600,20 @@ impl WasiFs {
                         ..__wasi_filestat_t::default()
                     })
                 }
-                None => path.metadata().ok()?,
+                None => self.filesystem((*fs)?).metadata(path).ok()?,
             },
-            Kind::Dir { path, .. } => path.metadata().ok()?,
+            Kind::Dir { fs, path, .. } => self.filesystem(*fs).metadata(path).ok()?,
             Kind::Symlink {
                 base_po_dir,
                 path_to_symlink,
                 ..
             } => {
-                let base_po_inode = &self.fd_map[base_po_dir].inode;
+                let base_po_inode = &self.fd_map.get(base_po_dir)?.inode;
                 let base_po_inode_v = &self.inodes[*base_po_inode];
                 match &base_po_inode_v.kind {
-                    Kind::Root { .. } => {
-                        path_to_symlink.clone().symlink_metadata().ok()?
-                    }
-                    Kind::Dir { path, .. } => {
+                    // virtual symlinks relative to the root don't exist in any filesystem
+                    Kind::Root { .. } => return None,
+                    Kind::Dir { fs, path, .. } => {
                         let mut real_path = path.clone();
                         // PHASE 1: ignore all possible symlinks in `relative_path`
                         // TODO: walk the segments of `relative_path` via the entries of the Dir
@@ -1418,37 +1622,15 @@ impl WasiFs {
                         // TODO: adjust size of symlink, too
                         //      for all paths adjusted think about this
                         real_path.push(path_to_symlink);
-                        real_path.symlink_metadata().ok()?
+                        self.filesystem(*fs).symlink_metadata(&real_path).ok()?
                     }
-                    // if this triggers, there's a bug in the symlink code
-                    _ => unreachable!("Symlink pointing to something that's not a directory as its base preopened directory"),
+                    // the preopened directory was replaced with `fd_renumber`
+                    _ => return None,
                 }
             }
             _ => return None,
         };
-        Some(__wasi_filestat_t {
-            st_filetype: host_file_type_to_wasi_file_type(md.file_type()),
-            st_size: md.len(),
-            st_atim: md
-                .accessed()
-                .ok()?
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .ok()?
-                .as_nanos() as u64,
-            st_mtim: md
-                .modified()
-                .ok()?
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .ok()?
-                .as_nanos() as u64,
-            st_ctim: md
-                .created()
-                .ok()
-                .and_then(|ct| ct.duration_since(SystemTime::UNIX_EPOCH).ok())
-                .map(|ct| ct.as_nanos() as u64)
-                .unwrap_or(0),
-            ..__wasi_filestat_t::default()
-        })
+        Some(md.to_filestat())
     }
 
     /// Closes an open FD, handling all details such as FD being preopen
@@ -1463,11 +1645,7 @@ impl WasiFs {
             }
             Kind::Dir { parent, path, .. } => {
                 debug!("Closing dir {:?}", &path);
-                let key = path
-                    .file_name()
-                    .ok_or(__WASI_EINVAL)?
-                    .to_string_lossy()
-                    .to_string();
+                let key = inodeval_mut.name.clone();
                 if let Some(p) = *parent {
                     match &mut self.inodes[p].kind {
                         Kind::Dir { entries, .. } | Kind::Root { entries } => {
@@ -1501,10 +1679,31 @@ impl WasiFs {
             }
             Kind::Root { .. } => return Err(__WASI_EACCES),
             Kind::Symlink { .. } | Kind::Buffer { .. } => return Err(__WASI_EINVAL),
+            Kind::Socket { .. } => {
+                // sockets don't have a name, so they're gone once their fd is closed
+                let inode = self.fd_map.remove(&fd).ok_or(__WASI_EBADF)?.inode;
+                self.inodes.remove(inode);
+            }
         }
 
         Ok(())
     }
+
+    /// Creates an fd for a socket.
+    pub(crate) fn create_socket_fd(
+        &mut self,
+        socket: Box<dyn WasiSocket>,
+    ) -> Result<__wasi_fd_t, __wasi_errno_t> {
+        self.quota.check_open_fds(self.fd_map.len())?;
+        self.quota.check_inodes(self.inodes.len())?;
+        let stat = __wasi_filestat_t {
+            st_filetype: __WASI_FILETYPE_SOCKET_STREAM,
+            ..__wasi_filestat_t::default()
+        };
+        let inode =
+            self.create_inode_with_stat(Kind::Socket { socket }, false, String::new(), stat);
+        self.create_fd(SOCKET_RIGHTS, SOCKET_RIGHTS, 0, Fd::READ | Fd::WRITE, inode)
+    }
 }
 
 /// Top level data type containing all* the state with which WASI can
@@ -1540,6 +1739,29 @@ pub struct WasiState {
     pub fs: WasiFs,
     pub args: Vec<Vec<u8>>,
     pub envs: Vec<Vec<u8>>,
+    /// The addresses the socket extension may connect to and bind to
+    #[serde(default)]
+    pub(crate) socket_allowlist: SocketAllowlist,
+    /// The source of `random_get` in deterministic mode
+    #[serde(default)]
+    pub(crate) seeded_random: Option<SeededRandom>,
+    /// The source of time, handed to the [`WasiEnv`](crate::WasiEnv)
+    #[serde(skip, default = "default_clock")]
+    pub(crate) clock: Arc<dyn WasiClock>,
+    /// The source of entropy, handed to the [`WasiEnv`](crate::WasiEnv)
+    #[serde(skip, default = "default_random")]
+    pub(crate) random: Arc<dyn WasiRandom>,
+    /// The handlers of the signals raised with `proc_raise`
+    #[serde(skip)]
+    pub(crate) signal_handlers: HashMap<__wasi_signal_t, Arc<dyn WasiSignalHandler>>,
+}
+
+pub(crate) fn default_clock() -> Arc<dyn WasiClock> {
+    Arc::new(HostClock)
+}
+
+pub(crate) fn default_random() -> Arc<dyn WasiRandom> {
+    Arc::new(HostRandom)
 }
 
 impl WasiState {
@@ -1560,16 +1782,3 @@ impl WasiState {
         bincode::deserialize(bytes).ok()
     }
 }
-
-pub fn host_file_type_to_wasi_file_type(file_type: fs::FileType) -> __wasi_filetype_t {
-    // TODO: handle other file types
-    if file_type.is_dir() {
-        __WASI_FILETYPE_DIRECTORY
-    } else if file_type.is_file() {
-        __WASI_FILETYPE_REGULAR_FILE
-    } else if file_type.is_symlink() {
-        __WASI_FILETYPE_SYMBOLIC_LINK
-    } else {
-        __WASI_FILETYPE_UNKNOWN
-    }
-}
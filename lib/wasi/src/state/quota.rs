@@ -0,0 +1,152 @@
+//! Limits on the resources a program can use, set with the `max_*` methods
+//! of [`WasiStateBuilder`](crate::WasiStateBuilder).
+
+use super::FsId;
+use crate::syscalls::types::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The resource limits of a [`WasiFs`](super::WasiFs), along with the usage
+/// that can't be read from the filesystem itself.
+///
+/// Limits that are `None` are unlimited.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Quota {
+    pub max_open_fds: Option<u32>,
+    pub max_bytes_written: Option<u64>,
+    pub max_file_size: Option<u64>,
+    pub max_entries_created: Option<u64>,
+    pub max_inodes: Option<u64>,
+    /// The bytes written to the files of each filesystem. Every preopened
+    /// directory has a filesystem of its own.
+    bytes_written: HashMap<FsId, u64>,
+    entries_created: u64,
+}
+
+impl Quota {
+    /// Checks that another fd can be opened while `open` are.
+    pub fn check_open_fds(&self, open: usize) -> Result<(), __wasi_errno_t> {
+        check(
+            self.max_open_fds.map(u64::from),
+            open as u64 + 1,
+            __WASI_EMFILE,
+        )
+    }
+
+    /// Checks that another inode can be created while there are `count`.
+    pub fn check_inodes(&self, count: usize) -> Result<(), __wasi_errno_t> {
+        check(self.max_inodes, count as u64 + 1, __WASI_ENOSPC)
+    }
+
+    /// Checks that a file of `size` bytes can grow to `new_size` bytes.
+    /// Files that are already too big can still shrink.
+    pub fn check_file_size(&self, size: u64, new_size: u64) -> Result<(), __wasi_errno_t> {
+        if new_size <= size {
+            return Ok(());
+        }
+        check(self.max_file_size, new_size, __WASI_ENOSPC)
+    }
+
+    /// Checks that `len` more bytes can be written to the files of `fs`.
+    pub fn check_bytes_written(&self, fs: FsId, len: u64) -> Result<(), __wasi_errno_t> {
+        check(
+            self.max_bytes_written,
+            self.bytes_written(fs).saturating_add(len),
+            __WASI_EDQUOT,
+        )
+    }
+
+    pub fn add_bytes_written(&mut self, fs: FsId, len: u64) {
+        let written = self.bytes_written.entry(fs).or_default();
+        *written = written.saturating_add(len);
+    }
+
+    pub fn bytes_written(&self, fs: FsId) -> u64 {
+        self.bytes_written.get(&fs).copied().unwrap_or(0)
+    }
+
+    /// Checks that another file, directory or link can be created.
+    pub fn check_entry(&self) -> Result<(), __wasi_errno_t> {
+        check(
+            self.max_entries_created,
+            self.entries_created + 1,
+            __WASI_EDQUOT,
+        )
+    }
+
+    pub fn add_entry(&mut self) {
+        self.entries_created += 1;
+    }
+
+    pub fn entries_created(&self) -> u64 {
+        self.entries_created
+    }
+}
+
+fn check(max: Option<u64>, wanted: u64, errno: __wasi_errno_t) -> Result<(), __wasi_errno_t> {
+    match max {
+        Some(max) if wanted > max => Err(errno),
+        _ => Ok(()),
+    }
+}
+
+/// The resources a WASI program is using, see
+/// [`WasiEnv::resource_usage`](crate::WasiEnv::resource_usage).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WasiResourceUsage {
+    /// The open fds, including stdio and the preopened directories
+    pub open_fds: u32,
+    /// The inodes in memory, including those of files that were only looked up
+    pub inodes: u64,
+    /// The files, directories and links the program created
+    pub entries_created: u64,
+    /// The bytes written to the files of each preopened directory, by the
+    /// name the program sees it as
+    pub bytes_written: HashMap<String, u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlimited_by_default() {
+        let mut quota = Quota::default();
+        assert_eq!(quota.check_open_fds(usize::MAX - 1), Ok(()));
+        assert_eq!(quota.check_inodes(1 << 40), Ok(()));
+        assert_eq!(quota.check_file_size(0, u64::MAX), Ok(()));
+        quota.add_bytes_written(FsId(0), u64::MAX);
+        assert_eq!(quota.check_bytes_written(FsId(0), u64::MAX), Ok(()));
+        assert_eq!(quota.check_entry(), Ok(()));
+    }
+
+    #[test]
+    fn limits_are_inclusive() {
+        let mut quota = Quota {
+            max_open_fds: Some(4),
+            max_bytes_written: Some(10),
+            max_file_size: Some(100),
+            max_entries_created: Some(1),
+            max_inodes: Some(8),
+            ..Quota::default()
+        };
+        assert_eq!(quota.check_open_fds(3), Ok(()));
+        assert_eq!(quota.check_open_fds(4), Err(__WASI_EMFILE));
+        assert_eq!(quota.check_inodes(7), Ok(()));
+        assert_eq!(quota.check_inodes(8), Err(__WASI_ENOSPC));
+
+        assert_eq!(quota.check_file_size(0, 100), Ok(()));
+        assert_eq!(quota.check_file_size(0, 101), Err(__WASI_ENOSPC));
+        assert_eq!(quota.check_file_size(200, 150), Ok(()));
+
+        quota.add_bytes_written(FsId(0), 6);
+        assert_eq!(quota.check_bytes_written(FsId(0), 4), Ok(()));
+        assert_eq!(quota.check_bytes_written(FsId(0), 5), Err(__WASI_EDQUOT));
+        assert_eq!(quota.check_bytes_written(FsId(1), 10), Ok(()));
+
+        assert_eq!(quota.check_entry(), Ok(()));
+        quota.add_entry();
+        assert_eq!(quota.check_entry(), Err(__WASI_EDQUOT));
+        assert_eq!(quota.entries_created(), 1);
+    }
+}
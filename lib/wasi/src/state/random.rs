@@ -0,0 +1,21 @@
+use crate::syscalls::types::*;
+use std::fmt;
+
+/// A source of entropy for `random_get`, set with
+/// [`WasiStateBuilder::random`].
+///
+/// [`WasiStateBuilder::random`]: crate::WasiStateBuilder::random
+pub trait WasiRandom: fmt::Debug + Send + Sync + 'static {
+    /// Fills `buf` with random bytes.
+    fn fill(&self, buf: &mut [u8]) -> Result<(), __wasi_errno_t>;
+}
+
+/// The random number generator of the host, used by default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HostRandom;
+
+impl WasiRandom for HostRandom {
+    fn fill(&self, buf: &mut [u8]) -> Result<(), __wasi_errno_t> {
+        getrandom::getrandom(buf).map_err(|_| __WASI_EIO)
+    }
+}
@@ -0,0 +1,68 @@
+use crate::syscalls::types::*;
+use std::fmt;
+
+/// Handles a signal the WASI raises with `proc_raise`, set with
+/// [`WasiStateBuilder::signal_handler`].
+///
+/// A signal with a handler doesn't terminate the instance; `proc_raise`
+/// returns once the handler does.
+///
+/// [`WasiStateBuilder::signal_handler`]: crate::WasiStateBuilder::signal_handler
+pub trait WasiSignalHandler: fmt::Debug + Send + Sync + 'static {
+    fn handle(&self, signal: __wasi_signal_t);
+}
+
+/// What happens to a signal without a handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SignalAction {
+    Terminate,
+    Ignore,
+}
+
+impl SignalAction {
+    /// The default action for `signal` on POSIX, except that the signals
+    /// which stop the process are ignored as there's no one to continue it.
+    pub fn default_for(signal: __wasi_signal_t) -> Result<Self, __wasi_errno_t> {
+        match signal {
+            __WASI_SIGCHLD | __WASI_SIGCONT | __WASI_SIGURG | __WASI_SIGWINCH | __WASI_SIGSTOP
+            | __WASI_SIGTSTP | __WASI_SIGTTIN | __WASI_SIGTTOU => Ok(Self::Ignore),
+            __WASI_SIGHUP..=__WASI_SIGSYS => Ok(Self::Terminate),
+            _ => Err(__WASI_EINVAL),
+        }
+    }
+
+    /// Whether a handler can be registered for `signal`.
+    pub fn can_handle(signal: __wasi_signal_t) -> bool {
+        !matches!(signal, __WASI_SIGKILL | __WASI_SIGSTOP)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_actions() {
+        assert_eq!(
+            SignalAction::default_for(__WASI_SIGABRT),
+            Ok(SignalAction::Terminate)
+        );
+        assert_eq!(
+            SignalAction::default_for(__WASI_SIGKILL),
+            Ok(SignalAction::Terminate)
+        );
+        assert_eq!(
+            SignalAction::default_for(__WASI_SIGCHLD),
+            Ok(SignalAction::Ignore)
+        );
+        assert_eq!(
+            SignalAction::default_for(__WASI_SIGTSTP),
+            Ok(SignalAction::Ignore)
+        );
+        assert_eq!(SignalAction::default_for(0), Err(__WASI_EINVAL));
+        assert_eq!(SignalAction::default_for(31), Err(__WASI_EINVAL));
+
+        assert!(SignalAction::can_handle(__WASI_SIGUSR1));
+        assert!(!SignalAction::can_handle(__WASI_SIGKILL));
+    }
+}
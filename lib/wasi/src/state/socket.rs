@@ -0,0 +1,330 @@
+use crate::state::{host_poll_readiness, PollEventSet, Upcastable, WasiFsError};
+use crate::syscalls::types::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{
+    IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, SocketAddrV4, SocketAddrV6, TcpListener,
+    TcpStream,
+};
+use std::ops::RangeInclusive;
+
+/// A socket that WASI programs can send data to and receive data from.
+///
+/// This is the socket counterpart of [`WasiFile`]: reading from the socket
+/// receives data and writing to it sends data. The socket is closed when it
+/// goes out of scope via `Drop`.
+///
+/// [`WasiFile`]: crate::WasiFile
+pub trait WasiSocket: fmt::Debug + Send + Read + Write + 'static + Upcastable {
+    /// Receives data like `read`, but without removing it from the socket.
+    fn peek(&mut self, buf: &mut [u8]) -> Result<usize, WasiFsError>;
+
+    /// Shuts down the receiving half, the sending half or both halves of
+    /// the socket.
+    fn shutdown(&mut self, how: Shutdown) -> Result<(), WasiFsError>;
+
+    /// The address the socket is bound to.
+    fn local_addr(&self) -> Result<SocketAddr, WasiFsError>;
+
+    /// Starts listening for connections.
+    ///
+    /// The default implementation is for sockets which can't accept
+    /// connections.
+    fn listen(&mut self, _backlog: u32) -> Result<(), WasiFsError> {
+        Err(WasiFsError::InvalidInput)
+    }
+
+    /// Accepts a connection, returning the socket of the connection and the
+    /// address of its peer.
+    ///
+    /// The default implementation is for sockets which can't accept
+    /// connections.
+    fn accept(&mut self) -> Result<(Box<dyn WasiSocket>, SocketAddr), WasiFsError> {
+        Err(WasiFsError::InvalidInput)
+    }
+
+    /// Returns the underlying host fd, used for polling.
+    fn get_raw_fd(&self) -> Option<i32> {
+        None
+    }
+
+    /// Returns which of the events in `interest` the socket is ready for,
+    /// without blocking. A listening socket is ready to read when there is
+    /// a connection to accept.
+    ///
+    /// The default implementation polls the host fd if there is one,
+    /// otherwise the socket is always ready.
+    fn poll_readiness(&self, interest: PollEventSet) -> Result<PollEventSet, WasiFsError> {
+        host_poll_readiness(self.get_raw_fd(), interest)
+    }
+}
+
+/// A thin wrapper around `std::net::TcpStream`
+#[derive(Debug)]
+pub struct HostTcpStream {
+    pub inner: TcpStream,
+}
+
+impl HostTcpStream {
+    /// creates a new host socket from a connected `std::net::TcpStream`
+    pub fn new(inner: TcpStream) -> Self {
+        Self { inner }
+    }
+}
+
+impl Read for HostTcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl Write for HostTcpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl WasiSocket for HostTcpStream {
+    fn peek(&mut self, buf: &mut [u8]) -> Result<usize, WasiFsError> {
+        self.inner.peek(buf).map_err(Into::into)
+    }
+
+    fn shutdown(&mut self, how: Shutdown) -> Result<(), WasiFsError> {
+        self.inner.shutdown(how).map_err(Into::into)
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, WasiFsError> {
+        self.inner.local_addr().map_err(Into::into)
+    }
+
+    #[cfg(unix)]
+    fn get_raw_fd(&self) -> Option<i32> {
+        use std::os::unix::io::AsRawFd;
+        Some(self.inner.as_raw_fd())
+    }
+}
+
+/// A thin wrapper around `std::net::TcpListener`
+///
+/// `std` binds and starts listening in one go, so the listener already
+/// queues connections before [`WasiSocket::listen`] is called, but they can
+/// only be accepted afterwards.
+#[derive(Debug)]
+pub struct HostTcpListener {
+    pub inner: TcpListener,
+    listening: bool,
+}
+
+impl HostTcpListener {
+    /// creates a new host socket from a bound `std::net::TcpListener`
+    pub fn new(inner: TcpListener) -> Self {
+        Self {
+            inner,
+            listening: false,
+        }
+    }
+}
+
+impl Read for HostTcpListener {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(
+            io::ErrorKind::NotConnected,
+            "can not receive from a listening socket",
+        ))
+    }
+}
+
+impl Write for HostTcpListener {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(
+            io::ErrorKind::NotConnected,
+            "can not send to a listening socket",
+        ))
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl WasiSocket for HostTcpListener {
+    fn peek(&mut self, _buf: &mut [u8]) -> Result<usize, WasiFsError> {
+        Err(WasiFsError::NotConnected)
+    }
+
+    fn shutdown(&mut self, _how: Shutdown) -> Result<(), WasiFsError> {
+        Err(WasiFsError::NotConnected)
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, WasiFsError> {
+        self.inner.local_addr().map_err(Into::into)
+    }
+
+    #[cfg(unix)]
+    fn get_raw_fd(&self) -> Option<i32> {
+        use std::os::unix::io::AsRawFd;
+        Some(self.inner.as_raw_fd())
+    }
+
+    /// Before [`WasiSocket::listen`] is called, the listener is reported as
+    /// ready so that accepting fails straight away instead of blocking.
+    fn poll_readiness(&self, interest: PollEventSet) -> Result<PollEventSet, WasiFsError> {
+        if !self.listening {
+            return Ok(interest);
+        }
+        host_poll_readiness(self.get_raw_fd(), interest)
+    }
+
+    fn listen(&mut self, _backlog: u32) -> Result<(), WasiFsError> {
+        self.listening = true;
+        Ok(())
+    }
+
+    fn accept(&mut self) -> Result<(Box<dyn WasiSocket>, SocketAddr), WasiFsError> {
+        if !self.listening {
+            return Err(WasiFsError::InvalidInput);
+        }
+        let (stream, addr) = self.inner.accept()?;
+        Ok((Box::new(HostTcpStream::new(stream)), addr))
+    }
+}
+
+/// The addresses and ports WASI programs may connect to and bind to through
+/// the socket extension. Nothing is allowed by default.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct SocketAllowlist {
+    rules: Vec<(IpAddr, RangeInclusive<u16>)>,
+}
+
+impl SocketAllowlist {
+    pub(crate) fn allow(&mut self, addr: IpAddr, ports: RangeInclusive<u16>) {
+        self.rules.push((addr, ports));
+    }
+
+    pub(crate) fn is_allowed(&self, addr: &SocketAddr) -> bool {
+        self.rules
+            .iter()
+            .any(|(ip, ports)| *ip == addr.ip() && ports.contains(&addr.port()))
+    }
+
+    /// Whether an address with this port may be allowed.
+    pub(crate) fn allows_port(&self, port: u16) -> bool {
+        self.rules.iter().any(|(_, ports)| ports.contains(&port))
+    }
+}
+
+impl From<SocketAddr> for __wasi_sockaddr_t {
+    fn from(addr: SocketAddr) -> Self {
+        let mut out = __wasi_sockaddr_t {
+            family: __WASI_ADDRESS_FAMILY_INET4,
+            port: addr.port(),
+            addr: [0; 16],
+        };
+        match addr.ip() {
+            IpAddr::V4(ip) => out.addr[..4].copy_from_slice(&ip.octets()),
+            IpAddr::V6(ip) => {
+                out.family = __WASI_ADDRESS_FAMILY_INET6;
+                out.addr = ip.octets();
+            }
+        }
+        out
+    }
+}
+
+impl __wasi_sockaddr_t {
+    /// Converts the address to a `SocketAddr`, or returns `None` if its
+    /// family is invalid.
+    pub fn to_socket_addr(&self) -> Option<SocketAddr> {
+        match self.family {
+            __WASI_ADDRESS_FAMILY_INET4 => {
+                let ip = Ipv4Addr::new(self.addr[0], self.addr[1], self.addr[2], self.addr[3]);
+                Some(SocketAddr::V4(SocketAddrV4::new(ip, self.port)))
+            }
+            __WASI_ADDRESS_FAMILY_INET6 => Some(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(self.addr),
+                self.port,
+                0,
+                0,
+            ))),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loopback_connection() {
+        let mut listener = HostTcpListener::new(TcpListener::bind("127.0.0.1:0").unwrap());
+        let addr = listener.local_addr().unwrap();
+        assert_eq!(listener.accept().unwrap_err(), WasiFsError::InvalidInput);
+        listener.listen(1).unwrap();
+
+        let mut client = HostTcpStream::new(TcpStream::connect(addr).unwrap());
+        let (mut server, peer) = listener.accept().unwrap();
+        assert_eq!(peer, client.local_addr().unwrap());
+
+        client.write_all(b"ping").unwrap();
+        let mut buf = [0; 4];
+        // peeking returns whatever arrived so far
+        while server.peek(&mut buf).unwrap() < buf.len() {}
+        assert_eq!(&buf, b"ping");
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+
+        server.shutdown(Shutdown::Write).unwrap();
+        let mut rest = Vec::new();
+        client.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn readiness() {
+        use crate::state::PollEvent;
+        let read = PollEvent::PollIn as PollEventSet;
+        let write = PollEvent::PollOut as PollEventSet;
+
+        let mut listener = HostTcpListener::new(TcpListener::bind("127.0.0.1:0").unwrap());
+        listener.listen(1).unwrap();
+        assert_eq!(listener.poll_readiness(read).unwrap(), 0);
+
+        let mut client =
+            HostTcpStream::new(TcpStream::connect(listener.local_addr().unwrap()).unwrap());
+        while listener.poll_readiness(read).unwrap() == 0 {}
+        let (mut server, _) = listener.accept().unwrap();
+        assert_eq!(server.poll_readiness(read | write).unwrap(), write);
+
+        client.write_all(b"ping").unwrap();
+        while server.poll_readiness(read).unwrap() == 0 {}
+    }
+
+    #[test]
+    fn allowlist() {
+        let mut allowlist = SocketAllowlist::default();
+        let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        assert!(!allowlist.is_allowed(&addr));
+        assert!(!allowlist.allows_port(8080));
+
+        allowlist.allow(Ipv4Addr::LOCALHOST.into(), 8000..=8999);
+        assert!(allowlist.is_allowed(&addr));
+        assert!(!allowlist.is_allowed(&"127.0.0.1:9000".parse().unwrap()));
+        assert!(!allowlist.is_allowed(&"127.0.0.2:8080".parse().unwrap()));
+        assert!(!allowlist.is_allowed(&"[::1]:8080".parse().unwrap()));
+        assert!(allowlist.allows_port(8080));
+        assert!(!allowlist.allows_port(9000));
+    }
+
+    #[test]
+    fn sockaddr_conversion() {
+        for addr in &["127.0.0.1:80", "[::1]:443", "10.1.2.3:65535"] {
+            let addr: SocketAddr = addr.parse().unwrap();
+            assert_eq!(__wasi_sockaddr_t::from(addr).to_socket_addr(), Some(addr));
+        }
+    }
+}
@@ -72,6 +72,9 @@ pub enum WasiFsError {
     /// The requested device couldn't be accessed
     #[error("can't access device")]
     NoDevice,
+    /// The directory could not be removed or replaced because it's not empty
+    #[error("directory not empty")]
+    DirectoryNotEmpty,
     /// Caller was not allowed to perform this operation
     #[error("permission denied")]
     PermissionDenied,
@@ -110,6 +113,7 @@ impl WasiFsError {
             __WASI_EINVAL => WasiFsError::InvalidInput,
             __WASI_ENOTCONN => WasiFsError::NotConnected,
             __WASI_ENODEV => WasiFsError::NoDevice,
+            __WASI_ENOTEMPTY => WasiFsError::DirectoryNotEmpty,
             __WASI_ENOENT => WasiFsError::EntityNotFound,
             __WASI_EPERM => WasiFsError::PermissionDenied,
             __WASI_ETIMEDOUT => WasiFsError::TimedOut,
@@ -136,6 +140,7 @@ impl WasiFsError {
             WasiFsError::InvalidInput => __WASI_EINVAL,
             WasiFsError::IOError => __WASI_EIO,
             WasiFsError::NoDevice => __WASI_ENODEV,
+            WasiFsError::DirectoryNotEmpty => __WASI_ENOTEMPTY,
             WasiFsError::NotAFile => __WASI_EINVAL,
             WasiFsError::NotConnected => __WASI_ENOTCONN,
             WasiFsError::EntityNotFound => __WASI_ENOENT,
@@ -197,10 +202,11 @@ pub trait WasiFile: fmt::Debug + Send + Write + Read + Seek + 'static + Upcastab
     }
 
     /// Moves the file to a new location
+    /// Default implementation returns `ENOTSUP`, as most files can't be moved
     /// NOTE: the signature of this function will change before stabilization
     // TODO: stablizie this in 0.7.0 or 0.8.0 by removing default impl
     fn rename_file(&self, _new_name: &std::path::Path) -> Result<(), WasiFsError> {
-        panic!("Default implementation for now as this method is unstable; this default implementation or this entire method may be removed in a future release.");
+        Err(WasiFsError::UnknownError(__WASI_ENOTSUP))
     }
 
     /// Returns the number of bytes available.  This function must not block
@@ -211,6 +217,15 @@ pub trait WasiFile: fmt::Debug + Send + Write + Read + Seek + 'static + Upcastab
     fn get_raw_fd(&self) -> Option<i32> {
         None
     }
+
+    /// Returns which of the events in `interest` the file is ready for.  This function must
+    /// not block
+    ///
+    /// Default polls the host fd if there is one, otherwise the file is always ready like
+    /// regular files on the host
+    fn poll_readiness(&self, interest: PollEventSet) -> Result<PollEventSet, WasiFsError> {
+        host_poll_readiness(self.get_raw_fd(), interest)
+    }
 }
 
 // Implementation of `Upcastable` taken from https://users.rust-lang.org/t/why-does-downcasting-not-work-for-subtraits/33286/7 .
@@ -361,46 +376,36 @@ impl PollEventBuilder {
     }
 }
 
+/// Polls the host fd `raw_fd` for the events in `interest` without blocking, or reports all
+/// of them as ready if there is no host fd.
 #[cfg(unix)]
-pub(crate) fn poll(
-    selfs: &[&dyn WasiFile],
-    events: &[PollEventSet],
-    seen_events: &mut [PollEventSet],
-) -> Result<u32, WasiFsError> {
-    if !(selfs.len() == events.len() && events.len() == seen_events.len()) {
-        return Err(WasiFsError::InvalidInput);
-    }
-    let mut fds = selfs
-        .iter()
-        .enumerate()
-        .filter_map(|(i, s)| s.get_raw_fd().map(|rfd| (i, rfd)))
-        .map(|(i, host_fd)| libc::pollfd {
-            fd: host_fd,
-            events: poll_event_set_to_platform_poll_events(events[i]),
-            revents: 0,
-        })
-        .collect::<Vec<_>>();
-    let result = unsafe { libc::poll(fds.as_mut_ptr(), selfs.len() as _, 1) };
+pub(crate) fn host_poll_readiness(
+    raw_fd: Option<i32>,
+    interest: PollEventSet,
+) -> Result<PollEventSet, WasiFsError> {
+    let host_fd = match raw_fd {
+        Some(host_fd) => host_fd,
+        None => return Ok(interest),
+    };
+    let mut fd = libc::pollfd {
+        fd: host_fd,
+        events: poll_event_set_to_platform_poll_events(interest),
+        revents: 0,
+    };
+    let result = unsafe { libc::poll(&mut fd, 1, 0) };
 
     if result < 0 {
-        // TODO: check errno and return value
-        return Err(WasiFsError::IOError);
-    }
-    // convert result and write back values
-    for (i, fd) in fds.into_iter().enumerate() {
-        seen_events[i] = platform_poll_events_to_pollevent_set(fd.revents);
+        return Err(io::Error::last_os_error().into());
     }
-    // unwrap is safe because we check for negative values above
-    Ok(result.try_into().unwrap())
+    Ok(platform_poll_events_to_pollevent_set(fd.revents))
 }
 
 #[cfg(not(unix))]
-pub(crate) fn poll(
-    _selfs: &[&dyn WasiFile],
-    _events: &[PollEventSet],
-    _seen_events: &mut [PollEventSet],
-) -> Result<(), WasiFsError> {
-    unimplemented!("HostFile::poll in WasiFile is not implemented for non-Unix-like targets yet");
+pub(crate) fn host_poll_readiness(
+    _raw_fd: Option<i32>,
+    interest: PollEventSet,
+) -> Result<PollEventSet, WasiFsError> {
+    Ok(interest)
 }
 
 pub trait WasiPath {}
@@ -625,8 +630,9 @@ impl WasiFile for HostFile {
     }
 
     fn bytes_available(&self) -> Result<usize, WasiFsError> {
-        // unwrap is safe because of get_raw_fd implementation
-        let host_fd = self.get_raw_fd().unwrap();
+        let host_fd = self
+            .get_raw_fd()
+            .ok_or(WasiFsError::UnknownError(__WASI_ENOTSUP))?;
 
         host_file_bytes_available(host_fd)
     }
@@ -638,9 +644,7 @@ impl WasiFile for HostFile {
     }
     #[cfg(not(unix))]
     fn get_raw_fd(&self) -> Option<i32> {
-        unimplemented!(
-            "HostFile::get_raw_fd in WasiFile is not implemented for non-Unix-like targets yet"
-        );
+        None
     }
 }
 
@@ -688,7 +692,7 @@ fn host_file_bytes_available(host_fd: i32) -> Result<usize, WasiFsError> {
 
 #[cfg(not(unix))]
 fn host_file_bytes_available(_raw_fd: i32) -> Result<usize, WasiFsError> {
-    unimplemented!("host_file_bytes_available not yet implemented for non-Unix-like targets.  This probably means the program tried to use wasi::poll_oneoff")
+    Err(WasiFsError::UnknownError(__WASI_ENOTSUP))
 }
 
 /// A wrapper type around Stdout that implements `WasiFile` and
@@ -764,8 +768,9 @@ impl WasiFile for Stdout {
     }
 
     fn bytes_available(&self) -> Result<usize, WasiFsError> {
-        // unwrap is safe because of get_raw_fd implementation
-        let host_fd = self.get_raw_fd().unwrap();
+        let host_fd = self
+            .get_raw_fd()
+            .ok_or(WasiFsError::UnknownError(__WASI_ENOTSUP))?;
 
         host_file_bytes_available(host_fd)
     }
@@ -778,9 +783,7 @@ impl WasiFile for Stdout {
 
     #[cfg(not(unix))]
     fn get_raw_fd(&self) -> Option<i32> {
-        unimplemented!(
-            "Stdout::get_raw_fd in WasiFile is not implemented for non-Unix-like targets yet"
-        );
+        None
     }
 }
 
@@ -857,8 +860,9 @@ impl WasiFile for Stderr {
     }
 
     fn bytes_available(&self) -> Result<usize, WasiFsError> {
-        // unwrap is safe because of get_raw_fd implementation
-        let host_fd = self.get_raw_fd().unwrap();
+        let host_fd = self
+            .get_raw_fd()
+            .ok_or(WasiFsError::UnknownError(__WASI_ENOTSUP))?;
 
         host_file_bytes_available(host_fd)
     }
@@ -871,9 +875,7 @@ impl WasiFile for Stderr {
 
     #[cfg(not(unix))]
     fn get_raw_fd(&self) -> Option<i32> {
-        unimplemented!(
-            "Stderr::get_raw_fd in WasiFile is not implemented for non-Unix-like targets yet"
-        );
+        None
     }
 }
 
@@ -951,8 +953,9 @@ impl WasiFile for Stdin {
     }
 
     fn bytes_available(&self) -> Result<usize, WasiFsError> {
-        // unwrap is safe because of get_raw_fd implementation
-        let host_fd = self.get_raw_fd().unwrap();
+        let host_fd = self
+            .get_raw_fd()
+            .ok_or(WasiFsError::UnknownError(__WASI_ENOTSUP))?;
 
         host_file_bytes_available(host_fd)
     }
@@ -965,9 +968,7 @@ impl WasiFile for Stdin {
 
     #[cfg(not(unix))]
     fn get_raw_fd(&self) -> Option<i32> {
-        unimplemented!(
-            "Stdin::get_raw_fd in WasiFile is not implemented for non-Unix-like targets yet"
-        );
+        None
     }
 }
 
@@ -1036,6 +1037,14 @@ impl WasiFile for Pipe {
     fn bytes_available(&self) -> Result<usize, WasiFsError> {
         Ok(self.buffer.len())
     }
+    fn poll_readiness(&self, interest: PollEventSet) -> Result<PollEventSet, WasiFsError> {
+        // writing never blocks, but reading only returns data if something was written
+        if self.buffer.is_empty() {
+            Ok(interest & !(PollEvent::PollIn as PollEventSet))
+        } else {
+            Ok(interest)
+        }
+    }
 }
 
 /*
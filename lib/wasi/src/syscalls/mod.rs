@@ -12,14 +12,14 @@ pub mod unix;
 pub mod windows;
 
 pub mod legacy;
+pub mod sockets;
 
 use self::types::*;
 use crate::{
     ptr::{Array, WasmPtr},
     state::{
-        self, host_file_type_to_wasi_file_type, iterate_poll_events, poll, Fd, HostFile, Inode,
-        InodeVal, Kind, PollEvent, PollEventBuilder, WasiFile, WasiFsError, WasiState,
-        MAX_SYMLINKS,
+        self, iterate_poll_events, Fd, Inode, InodeVal, Kind, PollEvent, PollEventSet,
+        SignalAction, VirtualClock, WasiFile, WasiFsError, WasiState, MAX_SYMLINKS,
     },
     WasiEnv, WasiError,
 };
@@ -52,7 +52,12 @@ fn write_bytes_inner<T: Write>(
         let bytes = iov_inner.buf.deref(memory, 0, iov_inner.buf_len)?;
         write_loc
             .write_all(&bytes.iter().map(|b_cell| b_cell.get()).collect::<Vec<u8>>())
-            .map_err(|_| __WASI_EIO)?;
+            .map_err(|e| {
+                // files may fail with a WASI error, e.g. when they can't grow
+                e.get_ref()
+                    .and_then(|e| e.downcast_ref::<WasiFsError>())
+                    .map_or(__WASI_EIO, |e| e.into_wasi_err())
+            })?;
 
         // TODO: handle failure more accurately
         bytes_written += iov_inner.buf_len;
@@ -70,6 +75,14 @@ fn write_bytes<T: Write>(
     result
 }
 
+/// The number of bytes in the buffers of `iovs_arr_cell`.
+fn iovs_len(iovs_arr_cell: &[Cell<__wasi_ciovec_t>]) -> u64 {
+    iovs_arr_cell
+        .iter()
+        .map(|iov| iov.get().buf_len as u64)
+        .sum()
+}
+
 fn read_bytes<T: Read>(
     mut reader: T,
     memory: &Memory,
@@ -103,7 +116,9 @@ fn write_buffer_array(
 
     let mut current_buffer_offset = 0;
     for ((i, sub_buffer), ptr) in from.iter().enumerate().zip(ptrs.iter()) {
-        ptr.set(WasmPtr::new(buffer.offset() + current_buffer_offset));
+        ptr.set(WasmPtr::new(
+            buffer.offset().wrapping_add(current_buffer_offset),
+        ));
 
         let cells =
             wasi_try!(buffer.deref(memory, current_buffer_offset, sub_buffer.len() as u32 + 1));
@@ -117,14 +132,6 @@ fn write_buffer_array(
     __WASI_ESUCCESS
 }
 
-fn get_current_time_in_nanos() -> Result<__wasi_timestamp_t, __wasi_errno_t> {
-    let now = std::time::SystemTime::now();
-    let duration = now
-        .duration_since(std::time::SystemTime::UNIX_EPOCH)
-        .map_err(|_| __WASI_EIO)?;
-    Ok(duration.as_nanos() as __wasi_timestamp_t)
-}
-
 /// ### `args_get()`
 /// Read command-line argument data.
 /// The sizes of the buffers should match that returned by [`args_sizes_get()`](#args_sizes_get).
@@ -150,11 +157,7 @@ pub fn args_get(
             .args
             .iter()
             .enumerate()
-            .map(|(i, v)| format!(
-                "{:>20}: {}",
-                i,
-                ::std::str::from_utf8(v).unwrap().to_string()
-            ))
+            .map(|(i, v)| format!("{:>20}: {}", i, String::from_utf8_lossy(v)))
             .collect::<Vec<String>>()
             .join("\n")
     );
@@ -204,10 +207,18 @@ pub fn clock_res_get(
     resolution: WasmPtr<__wasi_timestamp_t>,
 ) -> __wasi_errno_t {
     debug!("wasi::clock_res_get");
-    let memory = env.memory();
+    let (memory, state) = env.get_memory_and_wasi_state(0);
 
     let out_addr = wasi_try!(resolution.deref(memory));
-    platform_clock_res_get(clock_id, out_addr)
+    let t_out = match &state.fs.virtual_clock {
+        Some(clock) => {
+            wasi_try!(VirtualClock::check_clock_id(clock_id));
+            clock.resolution()
+        }
+        None => wasi_try!(env.clock.resolution(clock_id)),
+    };
+    out_addr.set(t_out);
+    __WASI_ESUCCESS
 }
 
 /// ### `clock_time_get()`
@@ -230,10 +241,20 @@ pub fn clock_time_get(
         "wasi::clock_time_get clock_id: {}, precision: {}",
         clock_id, precision
     );
-    let memory = env.memory();
+    let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let out_addr = wasi_try!(time.deref(memory));
-    let result = platform_clock_time_get(clock_id, precision, out_addr);
+    let result = match &mut state.fs.virtual_clock {
+        Some(clock) => VirtualClock::check_clock_id(clock_id).map(|()| clock.tick()),
+        None => env.clock.time(clock_id, precision),
+    };
+    let result = match result {
+        Ok(t_out) => {
+            out_addr.set(t_out);
+            __WASI_ESUCCESS
+        }
+        Err(err) => err,
+    };
     debug!(
         "time: {} => {}",
         wasi_try!(time.deref(memory)).get(),
@@ -341,6 +362,7 @@ pub fn fd_allocate(
         return __WASI_EACCES;
     }
     let new_size = wasi_try!(offset.checked_add(len), __WASI_EINVAL);
+    wasi_try!(state.fs.check_resize_quota(inode, new_size));
 
     match &mut state.fs.inodes[inode].kind {
         Kind::File { handle, .. } => {
@@ -354,6 +376,7 @@ pub fn fd_allocate(
             buffer.resize(new_size as usize, 0);
         }
         Kind::Symlink { .. } => return __WASI_EBADF,
+        Kind::Socket { .. } => return __WASI_EINVAL,
         Kind::Dir { .. } | Kind::Root { .. } => return __WASI_EISDIR,
     }
     state.fs.inodes[inode].stat.st_size = new_size;
@@ -536,6 +559,7 @@ pub fn fd_filestat_set_size(
     if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_FILESTAT_SET_SIZE) {
         return __WASI_EACCES;
     }
+    wasi_try!(state.fs.check_resize_quota(inode, st_size));
 
     match &mut state.fs.inodes[inode].kind {
         Kind::File { handle, .. } => {
@@ -549,6 +573,7 @@ pub fn fd_filestat_set_size(
             buffer.resize(st_size as usize, 0);
         }
         Kind::Symlink { .. } => return __WASI_EBADF,
+        Kind::Socket { .. } => return __WASI_EINVAL,
         Kind::Dir { .. } | Kind::Root { .. } => return __WASI_EISDIR,
     }
     state.fs.inodes[inode].stat.st_size = st_size;
@@ -588,13 +613,14 @@ pub fn fd_filestat_set_times(
     }
 
     let inode_idx = fd_entry.inode;
+    let now = state.fs.current_time(&*env.clock);
     let inode = &mut state.fs.inodes[inode_idx];
 
     if fst_flags & __WASI_FILESTAT_SET_ATIM != 0 || fst_flags & __WASI_FILESTAT_SET_ATIM_NOW != 0 {
         let time_to_set = if fst_flags & __WASI_FILESTAT_SET_ATIM != 0 {
             st_atim
         } else {
-            wasi_try!(get_current_time_in_nanos())
+            wasi_try!(now)
         };
         inode.stat.st_atim = time_to_set;
         // TODO: set it for more than just files
@@ -609,7 +635,7 @@ pub fn fd_filestat_set_times(
         let time_to_set = if fst_flags & __WASI_FILESTAT_SET_MTIM != 0 {
             st_mtim
         } else {
-            wasi_try!(get_current_time_in_nanos())
+            wasi_try!(now)
         };
         inode.stat.st_mtim = time_to_set;
         // TODO: set it for more than just files
@@ -677,6 +703,7 @@ pub fn fd_pread(
                 );
                 return __WASI_EACCES;
             }
+            let inode = wasi_try!(state.fs.follow_symlinks(inode));
             match &mut state.fs.inodes[inode].kind {
                 Kind::File { handle, .. } => {
                     if let Some(h) = handle {
@@ -690,10 +717,14 @@ pub fn fd_pread(
                     }
                 }
                 Kind::Dir { .. } | Kind::Root { .. } => return __WASI_EISDIR,
-                Kind::Symlink { .. } => unimplemented!("Symlinks in wasi::fd_pread"),
-                Kind::Buffer { buffer } => {
-                    wasi_try!(read_bytes(&buffer[(offset as usize)..], memory, iov_cells))
-                }
+                // symlinks were followed above
+                Kind::Symlink { .. } => return __WASI_ELOOP,
+                Kind::Socket { .. } => return __WASI_ESPIPE,
+                Kind::Buffer { buffer } => wasi_try!(read_bytes(
+                    buffer.get(offset as usize..).unwrap_or_default(),
+                    memory,
+                    iov_cells
+                )),
             }
         }
     };
@@ -748,27 +779,24 @@ pub fn fd_prestat_dir_name(
         Kind::Dir { .. } | Kind::Root { .. } => {
             // TODO: verify this: null termination, etc
             if inode_val.name.len() <= path_len as usize {
-                let mut i = 0;
-                for c in inode_val.name.bytes() {
-                    path_chars[i].set(c);
-                    i += 1
+                for (cell, c) in path_chars.iter().zip(inode_val.name.bytes()) {
+                    cell.set(c);
+                }
+                // null terminate the name if there's room for it
+                if let Some(cell) = path_chars.get(inode_val.name.len()) {
+                    cell.set(0);
                 }
-                path_chars[i].set(0);
 
-                debug!(
-                    "=> result: \"{}\"",
-                    ::std::str::from_utf8(unsafe {
-                        &*(&path_chars[..] as *const [_] as *const [u8])
-                    })
-                    .unwrap()
-                );
+                debug!("=> result: \"{}\"", inode_val.name);
 
                 __WASI_ESUCCESS
             } else {
                 __WASI_EOVERFLOW
             }
         }
-        Kind::Symlink { .. } | Kind::Buffer { .. } | Kind::File { .. } => __WASI_ENOTDIR,
+        Kind::Symlink { .. } | Kind::Buffer { .. } | Kind::File { .. } | Kind::Socket { .. } => {
+            __WASI_ENOTDIR
+        }
     }
 }
 
@@ -830,9 +858,13 @@ pub fn fd_pwrite(
             }
 
             let inode_idx = fd_entry.inode;
+            let inode_idx = wasi_try!(state.fs.follow_symlinks(inode_idx));
+            wasi_try!(state
+                .fs
+                .check_write_quota(inode_idx, offset, iovs_len(iovs_arr_cell)));
             let inode = &mut state.fs.inodes[inode_idx];
 
-            match &mut inode.kind {
+            let bytes_written = match &mut inode.kind {
                 Kind::File { handle, .. } => {
                     if let Some(handle) = handle {
                         handle.seek(std::io::SeekFrom::Start(offset as u64));
@@ -845,13 +877,18 @@ pub fn fd_pwrite(
                     // TODO: verify
                     return __WASI_EISDIR;
                 }
-                Kind::Symlink { .. } => unimplemented!("Symlinks in wasi::fd_pwrite"),
+                // symlinks were followed above
+                Kind::Symlink { .. } => return __WASI_ELOOP,
+                Kind::Socket { .. } => return __WASI_ESPIPE,
                 Kind::Buffer { buffer } => wasi_try!(write_bytes(
-                    &mut buffer[(offset as usize)..],
+                    buffer.get_mut(offset as usize..).unwrap_or_default(),
                     memory,
                     iovs_arr_cell
                 )),
-            }
+            };
+            state.fs.add_bytes_written(inode_idx, bytes_written as u64);
+
+            bytes_written
         }
     };
 
@@ -880,6 +917,8 @@ pub fn fd_read(
     nread: WasmPtr<u32>,
 ) -> __wasi_errno_t {
     debug!("wasi::fd_read: fd={}", fd);
+    // reading stdin or a socket of the host may block
+    wasi_try!(wait_until_ready(env, fd, PollEvent::PollIn));
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let iovs_arr_cell = wasi_try!(iovs.deref(memory, 0, iovs_len));
@@ -906,6 +945,7 @@ pub fn fd_read(
 
             let offset = fd_entry.offset as usize;
             let inode_idx = fd_entry.inode;
+            let inode_idx = wasi_try!(state.fs.follow_symlinks(inode_idx));
             let inode = &mut state.fs.inodes[inode_idx];
 
             let bytes_read = match &mut inode.kind {
@@ -921,10 +961,18 @@ pub fn fd_read(
                     // TODO: verify
                     return __WASI_EISDIR;
                 }
-                Kind::Symlink { .. } => unimplemented!("Symlinks in wasi::fd_read"),
-                Kind::Buffer { buffer } => {
-                    wasi_try!(read_bytes(&buffer[offset..], memory, iovs_arr_cell))
+                // symlinks were followed above
+                Kind::Symlink { .. } => return __WASI_ELOOP,
+                Kind::Socket { socket } => {
+                    // sockets don't have an offset
+                    nread_cell.set(wasi_try!(read_bytes(socket, memory, iovs_arr_cell)));
+                    return __WASI_ESUCCESS;
                 }
+                Kind::Buffer { buffer } => wasi_try!(read_bytes(
+                    buffer.get(offset..).unwrap_or_default(),
+                    memory,
+                    iovs_arr_cell
+                )),
             };
 
             // reborrow
@@ -975,22 +1023,28 @@ pub fn fd_readdir(
     let mut buf_idx = 0;
 
     let entries: Vec<(String, u8, u64)> = match &state.fs.inodes[working_dir.inode].kind {
-        Kind::Dir { path, entries, .. } => {
+        Kind::Dir {
+            fs, path, entries, ..
+        } => {
             // TODO: refactor this code
             // we need to support multiple calls,
             // simple and obviously correct implementation for now:
             // maintain consistent order via lexacographic sorting
-            let fs_info = wasi_try!(wasi_try!(std::fs::read_dir(path).map_err(|_| __WASI_EIO))
-                .collect::<Result<Vec<_>, _>>()
-                .map_err(|_| __WASI_EIO));
-            let mut entry_vec = wasi_try!(fs_info
+            let fs_info = wasi_try!(state
+                .fs
+                .filesystem(*fs)
+                .read_dir(path)
+                .map_err(WasiFsError::into_wasi_err));
+            let mut entry_vec = fs_info
                 .into_iter()
-                .map(|entry| Ok((
-                    entry.file_name().to_string_lossy().to_string(),
-                    host_file_type_to_wasi_file_type(entry.file_type().map_err(|_| __WASI_EIO)?),
-                    0, // TODO: inode
-                )))
-                .collect::<Result<Vec<(String, u8, u64)>, _>>());
+                .map(|entry| {
+                    (
+                        entry.name,
+                        entry.filetype,
+                        0, // TODO: inode
+                    )
+                })
+                .collect::<Vec<(String, u8, u64)>>();
             entry_vec.extend(
                 entries
                     .iter()
@@ -1026,7 +1080,9 @@ pub fn fd_readdir(
                 })
                 .collect()
         }
-        Kind::File { .. } | Kind::Symlink { .. } | Kind::Buffer { .. } => return __WASI_ENOTDIR,
+        Kind::File { .. } | Kind::Symlink { .. } | Kind::Buffer { .. } | Kind::Socket { .. } => {
+            return __WASI_ENOTDIR
+        }
     };
 
     for (entry_path_str, wasi_file_type, ino) in entries.iter().skip(cookie as usize) {
@@ -1076,6 +1132,9 @@ pub fn fd_renumber(env: &WasiEnv, from: __wasi_fd_t, to: __wasi_fd_t) -> __wasi_
     debug!("wasi::fd_renumber: from={}, to={}", from, to);
     let (memory, mut state) = env.get_memory_and_wasi_state(0);
     let fd_entry = wasi_try!(state.fs.fd_map.get(&from).ok_or(__WASI_EBADF));
+    if from == to {
+        return __WASI_ESUCCESS;
+    }
     let new_fd_entry = Fd {
         // TODO: verify this is correct
         rights: fd_entry.rights_inheriting,
@@ -1084,6 +1143,11 @@ pub fn fd_renumber(env: &WasiEnv, from: __wasi_fd_t, to: __wasi_fd_t) -> __wasi_
 
     state.fs.fd_map.insert(to, new_fd_entry);
     state.fs.fd_map.remove(&from);
+    // `to` is no longer the preopened directory it may have been, but `from` moves along
+    state.fs.preopen_fds.retain(|fd| *fd != to);
+    for fd in state.fs.preopen_fds.iter_mut().filter(|fd| **fd == from) {
+        *fd = to;
+    }
     __WASI_ESUCCESS
 }
 
@@ -1117,27 +1181,23 @@ pub fn fd_seek(
     }
 
     // TODO: handle case if fd is a dir?
-    match whence {
-        __WASI_WHENCE_CUR => fd_entry.offset = (fd_entry.offset as i64 + offset) as u64,
+    let new_offset = match whence {
+        __WASI_WHENCE_CUR => (fd_entry.offset as i64).checked_add(offset),
         __WASI_WHENCE_END => {
             use std::io::SeekFrom;
             let inode_idx = fd_entry.inode;
+            let inode_idx = wasi_try!(state.fs.follow_symlinks(inode_idx));
             match state.fs.inodes[inode_idx].kind {
                 Kind::File { ref mut handle, .. } => {
                     if let Some(handle) = handle {
                         let end = wasi_try!(handle.seek(SeekFrom::End(0)).ok().ok_or(__WASI_EIO));
-                        // TODO: handle case if fd_entry.offset uses 64 bits of a u64
-
-                        // reborrow
-                        let fd_entry = wasi_try!(state.fs.fd_map.get_mut(&fd).ok_or(__WASI_EBADF));
-                        fd_entry.offset = (end as i64 + offset) as u64;
+                        (end as i64).checked_add(offset)
                     } else {
                         return __WASI_EINVAL;
                     }
                 }
-                Kind::Symlink { .. } => {
-                    unimplemented!("wasi::fd_seek not implemented for symlinks")
-                }
+                // symlinks were followed above
+                Kind::Symlink { .. } => return __WASI_ELOOP,
                 Kind::Dir { .. } | Kind::Root { .. } => {
                     // TODO: check this
                     return __WASI_EINVAL;
@@ -1147,13 +1207,17 @@ pub fn fd_seek(
                     // TODO: implement this
                     return __WASI_EINVAL;
                 }
+                Kind::Socket { .. } => return __WASI_ESPIPE,
             }
         }
-        __WASI_WHENCE_SET => fd_entry.offset = offset as u64,
+        __WASI_WHENCE_SET => Some(offset),
         _ => return __WASI_EINVAL,
-    }
+    };
+    // seeking before the start of the file is an error
+    let new_offset = wasi_try!(new_offset.filter(|offset| *offset >= 0), __WASI_EINVAL);
     // reborrow
     let fd_entry = wasi_try!(state.fs.fd_map.get_mut(&fd).ok_or(__WASI_EBADF));
+    fd_entry.offset = new_offset as u64;
     new_offset_cell.set(fd_entry.offset);
 
     __WASI_ESUCCESS
@@ -1188,7 +1252,7 @@ pub fn fd_sync(env: &WasiEnv, fd: __wasi_fd_t) -> __wasi_errno_t {
             }
         }
         Kind::Root { .. } | Kind::Dir { .. } => return __WASI_EISDIR,
-        Kind::Buffer { .. } | Kind::Symlink { .. } => return __WASI_EINVAL,
+        Kind::Buffer { .. } | Kind::Symlink { .. } | Kind::Socket { .. } => return __WASI_EINVAL,
     }
 
     __WASI_ESUCCESS
@@ -1284,6 +1348,12 @@ pub fn fd_write(
 
             let offset = fd_entry.offset as usize;
             let inode_idx = fd_entry.inode;
+            let inode_idx = wasi_try!(state.fs.follow_symlinks(inode_idx));
+            wasi_try!(state.fs.check_write_quota(
+                inode_idx,
+                offset as u64,
+                iovs_len(iovs_arr_cell)
+            ));
             let inode = &mut state.fs.inodes[inode_idx];
 
             let bytes_written = match &mut inode.kind {
@@ -1299,12 +1369,22 @@ pub fn fd_write(
                     // TODO: verify
                     return __WASI_EISDIR;
                 }
-                Kind::Symlink { .. } => unimplemented!("Symlinks in wasi::fd_write"),
-                Kind::Buffer { buffer } => {
-                    wasi_try!(write_bytes(&mut buffer[offset..], memory, iovs_arr_cell))
+                // symlinks were followed above
+                Kind::Symlink { .. } => return __WASI_ELOOP,
+                Kind::Socket { socket } => {
+                    // sockets don't have an offset or a size
+                    nwritten_cell.set(wasi_try!(write_bytes(socket, memory, iovs_arr_cell)));
+                    return __WASI_ESUCCESS;
                 }
+                Kind::Buffer { buffer } => wasi_try!(write_bytes(
+                    buffer.get_mut(offset..).unwrap_or_default(),
+                    memory,
+                    iovs_arr_cell
+                )),
             };
 
+            state.fs.add_bytes_written(inode_idx, bytes_written as u64);
+
             // reborrow
             let fd_entry = wasi_try!(state.fs.fd_map.get_mut(&fd).ok_or(__WASI_EBADF));
             fd_entry.offset += bytes_written as u64;
@@ -1375,6 +1455,7 @@ pub fn path_create_directory(
                 ref mut entries,
                 path,
                 parent,
+                fs,
             } => {
                 match comp.borrow() {
                     ".." => {
@@ -1389,16 +1470,25 @@ pub fn path_create_directory(
                 if let Some(child) = entries.get(comp) {
                     cur_dir_inode = *child;
                 } else {
+                    let fs_id = *fs;
                     let mut adjusted_path = path.clone();
                     // TODO: double check this doesn't risk breaking the sandbox
                     adjusted_path.push(comp);
-                    if adjusted_path.exists() && !adjusted_path.is_dir() {
-                        return __WASI_ENOTDIR;
-                    } else if !adjusted_path.exists() {
-                        wasi_try!(std::fs::create_dir(&adjusted_path).ok(), __WASI_EIO);
+                    let fs = state.fs.filesystem(fs_id);
+                    match fs.metadata(&adjusted_path) {
+                        Ok(metadata) if !metadata.is_dir() => return __WASI_ENOTDIR,
+                        Ok(_) => (),
+                        Err(_) => {
+                            wasi_try!(state.fs.check_create_quota());
+                            wasi_try!(fs
+                                .create_dir(&adjusted_path)
+                                .map_err(WasiFsError::into_wasi_err));
+                            state.fs.quota.add_entry();
+                        }
                     }
                     let kind = Kind::Dir {
                         parent: Some(cur_dir_inode),
+                        fs: fs_id,
                         path: adjusted_path,
                         entries: Default::default(),
                     };
@@ -1463,10 +1553,18 @@ pub fn path_filestat_get(
     let stat = if state.fs.inodes[file_inode].is_preopened {
         state.fs.inodes[file_inode].stat
     } else {
-        wasi_try!(state
+        let mut stat = wasi_try!(state
             .fs
             .get_stat_for_kind(&state.fs.inodes[file_inode].kind)
-            .ok_or(__WASI_EIO))
+            .ok_or(__WASI_EIO));
+        if state.fs.virtual_clock.is_some() {
+            // the timestamps of the backing filesystem differ between runs
+            let inode_stat = &state.fs.inodes[file_inode].stat;
+            stat.st_atim = inode_stat.st_atim;
+            stat.st_mtim = inode_stat.st_mtim;
+            stat.st_ctim = inode_stat.st_ctim;
+        }
+        stat
     };
 
     let buf_cell = wasi_try!(buf.deref(memory));
@@ -1529,13 +1627,14 @@ pub fn path_filestat_set_times(
         .get_stat_for_kind(&state.fs.inodes[file_inode].kind)
         .ok_or(__WASI_EIO));
 
+    let now = state.fs.current_time(&*env.clock);
     let inode = &mut state.fs.inodes[fd_inode];
 
     if fst_flags & __WASI_FILESTAT_SET_ATIM != 0 || fst_flags & __WASI_FILESTAT_SET_ATIM_NOW != 0 {
         let time_to_set = if fst_flags & __WASI_FILESTAT_SET_ATIM != 0 {
             st_atim
         } else {
-            wasi_try!(get_current_time_in_nanos())
+            wasi_try!(now)
         };
         inode.stat.st_atim = time_to_set;
         // TODO: set it for more than just files
@@ -1549,7 +1648,7 @@ pub fn path_filestat_set_times(
         let time_to_set = if fst_flags & __WASI_FILESTAT_SET_MTIM != 0 {
             st_mtim
         } else {
-            wasi_try!(get_current_time_in_nanos())
+            wasi_try!(now)
         };
         inode.stat.st_mtim = time_to_set;
         // TODO: set it for more than just files
@@ -1624,6 +1723,7 @@ pub fn path_link(
     if state.fs.inodes[source_inode].stat.st_nlink == __wasi_linkcount_t::max_value() {
         return __WASI_EMLINK;
     }
+    wasi_try!(state.fs.quota.check_entry());
     match &mut state.fs.inodes[target_parent_inode].kind {
         Kind::Dir { entries, .. } => {
             if entries.contains_key(&new_entry_name) {
@@ -1632,9 +1732,12 @@ pub fn path_link(
             entries.insert(new_entry_name, source_inode);
         }
         Kind::Root { .. } => return __WASI_EINVAL,
-        Kind::File { .. } | Kind::Symlink { .. } | Kind::Buffer { .. } => return __WASI_ENOTDIR,
+        Kind::File { .. } | Kind::Symlink { .. } | Kind::Buffer { .. } | Kind::Socket { .. } => {
+            return __WASI_ENOTDIR
+        }
     }
     state.fs.inodes[source_inode].stat.st_nlink += 1;
+    state.fs.quota.add_entry();
 
     __WASI_ESUCCESS
 }
@@ -1718,11 +1821,16 @@ pub fn path_open(
     let adjusted_rights = /*fs_rights_base &*/ working_dir_rights_inheriting;
     let inode = if let Ok(inode) = maybe_inode {
         // Happy path, we found the file we're trying to open
+        let file_fs = match &state.fs.inodes[inode].kind {
+            Kind::File { fs: Some(fs), .. } => Some(state.fs.filesystem(*fs)),
+            _ => None,
+        };
         match &mut state.fs.inodes[inode].kind {
             Kind::File {
                 ref mut handle,
                 path,
                 fd,
+                ..
             } => {
                 if let Some(special_fd) = fd {
                     // short circuit if we're dealing with a special file
@@ -1733,62 +1841,81 @@ pub fn path_open(
                 if o_flags & __WASI_O_DIRECTORY != 0 {
                     return __WASI_ENOTDIR;
                 }
-                if o_flags & __WASI_O_EXCL != 0 && path.exists() {
+                if o_flags & __WASI_O_EXCL != 0
+                    && file_fs
+                        .as_ref()
+                        .map_or(true, |file_fs| file_fs.metadata(path).is_ok())
+                {
                     return __WASI_EEXIST;
                 }
-                let mut open_options = std::fs::OpenOptions::new();
                 let write_permission = adjusted_rights & __WASI_RIGHT_FD_WRITE != 0;
-                // append, truncate, and create all require the permission to write
-                let (append_permission, truncate_permission, create_permission) =
-                    if write_permission {
-                        (
-                            fs_flags & __WASI_FDFLAG_APPEND != 0,
-                            o_flags & __WASI_O_TRUNC != 0,
-                            o_flags & __WASI_O_CREAT != 0,
-                        )
-                    } else {
-                        (false, false, false)
-                    };
-                let open_options = open_options
-                    .read(true)
-                    // TODO: ensure these rights are actually valid given parent, etc.
-                    .write(write_permission)
-                    .create(create_permission)
-                    .append(append_permission)
-                    .truncate(truncate_permission);
                 open_flags |= Fd::READ;
-                if adjusted_rights & __WASI_RIGHT_FD_WRITE != 0 {
+                if write_permission {
                     open_flags |= Fd::WRITE;
                 }
-                if o_flags & __WASI_O_CREAT != 0 {
-                    open_flags |= Fd::CREATE;
+                // files that aren't located in any filesystem can't be reopened, they're
+                // shared between all of their fds instead
+                if let Some(file_fs) = file_fs {
+                    let mut open_options = crate::fs::OpenOptions::new();
+                    // append, truncate, and create all require the permission to write
+                    let (append_permission, truncate_permission, create_permission) =
+                        if write_permission {
+                            (
+                                fs_flags & __WASI_FDFLAG_APPEND != 0,
+                                o_flags & __WASI_O_TRUNC != 0,
+                                o_flags & __WASI_O_CREAT != 0,
+                            )
+                        } else {
+                            (false, false, false)
+                        };
+                    let open_options = open_options
+                        .read(true)
+                        // TODO: ensure these rights are actually valid given parent, etc.
+                        .write(write_permission)
+                        .create(create_permission)
+                        .append(append_permission)
+                        .truncate(truncate_permission);
+                    if o_flags & __WASI_O_CREAT != 0 {
+                        open_flags |= Fd::CREATE;
+                    }
+                    if o_flags & __WASI_O_TRUNC != 0 {
+                        open_flags |= Fd::TRUNCATE;
+                    }
+                    *handle = Some(wasi_try!(file_fs
+                        .open(path, open_options)
+                        .map_err(WasiFsError::into_wasi_err)));
+                } else if handle.is_none() {
+                    return __WASI_EBADF;
+                }
+            }
+            Kind::Buffer { buffer } => {
+                // buffers live in memory, so there's nothing to open
+                if o_flags & __WASI_O_DIRECTORY != 0 {
+                    return __WASI_ENOTDIR;
+                }
+                if o_flags & __WASI_O_EXCL != 0 {
+                    return __WASI_EEXIST;
                 }
-                if o_flags & __WASI_O_TRUNC != 0 {
-                    open_flags |= Fd::TRUNCATE;
+                open_flags |= Fd::READ;
+                if adjusted_rights & __WASI_RIGHT_FD_WRITE != 0 {
+                    open_flags |= Fd::WRITE;
+                    if o_flags & __WASI_O_TRUNC != 0 {
+                        open_flags |= Fd::TRUNCATE;
+                        buffer.clear();
+                    }
                 }
-                *handle = Some(Box::new(HostFile::new(
-                    wasi_try!(open_options.open(&path).map_err(|_| __WASI_EIO)),
-                    path.to_path_buf(),
-                    true,
-                    adjusted_rights & __WASI_RIGHT_FD_WRITE != 0,
-                    false,
-                )));
             }
-            Kind::Buffer { .. } => unimplemented!("wasi::path_open for Buffer type files"),
+            Kind::Socket { .. } => return __WASI_ENOTSUP,
             Kind::Dir { .. } | Kind::Root { .. } => {
                 // TODO: adjust these to be correct
                 if o_flags & __WASI_O_EXCL != 0 && path_arg.exists() {
                     return __WASI_EEXIST;
                 }
             }
-            Kind::Symlink {
-                base_po_dir,
-                path_to_symlink,
-                relative_path,
-            } => {
-                // I think this should return an error (because symlinks should be resolved away by the path traversal)
-                // TODO: investigate this
-                unimplemented!("SYMLINKS IN PATH_OPEN");
+            Kind::Symlink { .. } => {
+                // symlinks are only left unresolved without `__WASI_LOOKUP_SYMLINK_FOLLOW`,
+                // opening them then fails like `O_NOFOLLOW` does
+                return __WASI_ELOOP;
             }
         }
         inode
@@ -1807,19 +1934,22 @@ pub fn path_open(
                 &path_arg,
                 dirflags & __WASI_LOOKUP_SYMLINK_FOLLOW != 0
             ));
-            let new_file_host_path = match &state.fs.inodes[parent_inode].kind {
-                Kind::Dir { path, .. } => {
+            let (parent_fs, new_file_path) = match &state.fs.inodes[parent_inode].kind {
+                Kind::Dir { fs, path, .. } => {
                     let mut new_path = path.clone();
                     new_path.push(&new_entity_name);
-                    new_path
+                    (*fs, new_path)
                 }
                 Kind::Root { .. } => return __WASI_EACCES,
                 _ => return __WASI_EINVAL,
             };
-            // once we got the data we need from the parent, we lookup the host file
+            // check the quota before the file exists, as it won't be removed on failure
+            wasi_try!(state.fs.check_create_quota());
+            wasi_try!(state.fs.quota.check_open_fds(state.fs.fd_map.len()));
+            // once we got the data we need from the parent, we create the file in its filesystem
             // todo: extra check that opening with write access is okay
             let handle = {
-                let mut open_options = std::fs::OpenOptions::new();
+                let mut open_options = crate::fs::OpenOptions::new();
                 let open_options = open_options
                     .read(true)
                     .append(fs_flags & __WASI_FDFLAG_APPEND != 0)
@@ -1829,22 +1959,21 @@ pub fn path_open(
                     .create_new(true);
                 open_flags |= Fd::READ | Fd::WRITE | Fd::CREATE | Fd::TRUNCATE;
 
-                Some(Box::new(HostFile::new(
-                    wasi_try!(open_options.open(&new_file_host_path).map_err(|e| {
+                Some(wasi_try!(state
+                    .fs
+                    .filesystem(parent_fs)
+                    .open(&new_file_path, open_options)
+                    .map_err(|e| {
                         debug!("Error opening file {}", e);
-                        __WASI_EIO
-                    })),
-                    new_file_host_path.clone(),
-                    true,
-                    true,
-                    true,
-                )) as Box<dyn WasiFile>)
+                        e.into_wasi_err()
+                    })))
             };
 
             let new_inode = {
                 let kind = Kind::File {
                     handle,
-                    path: new_file_host_path,
+                    fs: Some(parent_fs),
+                    path: new_file_path,
                     fd: None,
                 };
                 wasi_try!(state.fs.create_inode(kind, false, new_entity_name.clone()))
@@ -1856,6 +1985,7 @@ pub fn path_open(
             {
                 entries.insert(new_entity_name, new_inode);
             }
+            state.fs.quota.add_entry();
 
             new_inode
         } else {
@@ -1991,14 +2121,21 @@ pub fn path_remove_directory(
             .fs
             .get_parent_inode_at_path(fd, std::path::Path::new(path_str), false));
 
-    let host_path_to_remove = match &state.fs.inodes[inode].kind {
-        Kind::Dir { entries, path, .. } => {
+    let (dir_fs, path_to_remove) = match &state.fs.inodes[inode].kind {
+        Kind::Dir {
+            entries, fs, path, ..
+        } => {
             if !entries.is_empty()
-                || wasi_try!(std::fs::read_dir(path).ok(), __WASI_EIO).count() != 0
+                || !wasi_try!(state
+                    .fs
+                    .filesystem(*fs)
+                    .read_dir(path)
+                    .map_err(WasiFsError::into_wasi_err))
+                .is_empty()
             {
                 return __WASI_ENOTEMPTY;
             }
-            path.clone()
+            (*fs, path.clone())
         }
         Kind::Root { .. } => return __WASI_EACCES,
         _ => return __WASI_ENOTDIR,
@@ -2009,16 +2146,16 @@ pub fn path_remove_directory(
             ref mut entries, ..
         } => {
             let removed_inode = wasi_try!(entries.remove(&childs_name).ok_or(__WASI_EINVAL));
-            // TODO: make this a debug assert in the future
-            assert!(inode == removed_inode);
+            if inode != removed_inode {
+                entries.insert(childs_name, removed_inode);
+                return __WASI_EINVAL;
+            }
         }
         Kind::Root { .. } => return __WASI_EACCES,
-        _ => unreachable!(
-            "Internal logic error in wasi::path_remove_directory, parent is not a directory"
-        ),
+        _ => return __WASI_ENOTDIR,
     }
 
-    if std::fs::remove_dir(path_str).is_err() {
+    if let Err(e) = state.fs.filesystem(dir_fs).remove_dir(&path_to_remove) {
         // reinsert to prevent FS from being in bad state
         if let Kind::Dir {
             ref mut entries, ..
@@ -2026,8 +2163,7 @@ pub fn path_remove_directory(
         {
             entries.insert(childs_name, inode);
         }
-        // TODO: more intelligently return error value by inspecting returned error value
-        return __WASI_EIO;
+        return e.into_wasi_err();
     }
 
     __WASI_ESUCCESS
@@ -2079,47 +2215,60 @@ pub fn path_rename(
         }
     }
 
+    // make sure the source is loaded into its parent's entries
+    wasi_try!(state.fs.get_inode_at_path(old_fd, source_str, false));
     let (source_parent_inode, source_entry_name) =
         wasi_try!(state.fs.get_parent_inode_at_path(old_fd, source_path, true));
     let (target_parent_inode, target_entry_name) =
         wasi_try!(state.fs.get_parent_inode_at_path(new_fd, target_path, true));
 
-    let host_adjusted_target_path = match &state.fs.inodes[target_parent_inode].kind {
-        Kind::Dir { entries, path, .. } => {
+    let (target_fs, adjusted_target_path) = match &state.fs.inodes[target_parent_inode].kind {
+        Kind::Dir {
+            entries, fs, path, ..
+        } => {
             if entries.contains_key(&target_entry_name) {
                 return __WASI_EEXIST;
             }
             let mut out_path = path.clone();
-            out_path.push(target_path);
-            out_path
+            out_path.push(&target_entry_name);
+            (*fs, out_path)
         }
         Kind::Root { .. } => return __WASI_ENOTCAPABLE,
-        Kind::Symlink { .. } | Kind::File { .. } | Kind::Buffer { .. } => {
-            unreachable!("Fatal internal logic error: parent of inode is not a directory")
+        Kind::Symlink { .. } | Kind::File { .. } | Kind::Buffer { .. } | Kind::Socket { .. } => {
+            return __WASI_ENOTDIR
         }
     };
     let source_entry = match &mut state.fs.inodes[source_parent_inode].kind {
         Kind::Dir { entries, .. } => wasi_try!(entries.remove(&source_entry_name), __WASI_EINVAL),
         Kind::Root { .. } => return __WASI_ENOTCAPABLE,
-        Kind::Symlink { .. } | Kind::File { .. } | Kind::Buffer { .. } => {
-            unreachable!("Fatal internal logic error: parent of inode is not a directory")
+        Kind::Symlink { .. } | Kind::File { .. } | Kind::Buffer { .. } | Kind::Socket { .. } => {
+            return __WASI_ENOTDIR
         }
     };
 
+    let source_fs = match &state.fs.inodes[source_entry].kind {
+        Kind::File { fs: Some(fs), .. } | Kind::Dir { fs, .. } => {
+            Some((*fs, state.fs.filesystem(*fs)))
+        }
+        _ => None,
+    };
     match &mut state.fs.inodes[source_entry].kind {
         Kind::File {
             handle,
             ref mut path,
             ..
         } => {
-            let result = if let Some(h) = handle {
-                h.rename_file(&host_adjusted_target_path)
-                    .map_err(|e| e.into_wasi_err())
-            } else {
-                let out =
-                    std::fs::rename(&path, &host_adjusted_target_path).map_err(|_| __WASI_EIO);
-                *path = host_adjusted_target_path;
-                out
+            let result = match (source_fs, handle) {
+                // files can't be moved between filesystems
+                (Some((source_fs, _)), _) if source_fs != target_fs => Err(__WASI_EXDEV),
+                (Some((_, fs)), _) => fs
+                    .rename(path, &adjusted_target_path)
+                    .map(|()| *path = adjusted_target_path)
+                    .map_err(WasiFsError::into_wasi_err),
+                (None, Some(h)) => h
+                    .rename_file(&adjusted_target_path)
+                    .map_err(WasiFsError::into_wasi_err),
+                (None, None) => Err(__WASI_EBADF),
             };
             // if the above operation failed we have to revert the previous change and then fail
             if let Err(e) = result {
@@ -2129,10 +2278,44 @@ pub fn path_rename(
                 }
             }
         }
-        Kind::Dir { path, .. } => unimplemented!("wasi::path_rename on Directories"),
+        Kind::Dir { path, parent, .. } => {
+            let old_path = path.clone();
+            let result = match source_fs {
+                // directories can't be moved between filesystems either
+                Some((source_fs, _)) if source_fs != target_fs => Err(__WASI_EXDEV),
+                Some((_, fs)) => fs
+                    .rename(path, &adjusted_target_path)
+                    .map_err(WasiFsError::into_wasi_err),
+                None => Err(__WASI_EBADF),
+            };
+            match result {
+                Ok(()) => {
+                    *parent = Some(target_parent_inode);
+                    state.fs.inodes[source_entry].name = target_entry_name.clone();
+                    state
+                        .fs
+                        .update_moved_dir_paths(source_entry, &old_path, &adjusted_target_path);
+                }
+                Err(e) => {
+                    if let Kind::Dir { entries, .. } =
+                        &mut state.fs.inodes[source_parent_inode].kind
+                    {
+                        entries.insert(source_entry_name, source_entry);
+                    }
+                    return e;
+                }
+            }
+        }
         Kind::Buffer { .. } => {}
         Kind::Symlink { .. } => {}
-        Kind::Root { .. } => unreachable!("The root can not be moved"),
+        // sockets are not in any directory and the root can not be moved, but put the entry
+        // back in case something went very wrong
+        Kind::Socket { .. } | Kind::Root { .. } => {
+            if let Kind::Dir { entries, .. } = &mut state.fs.inodes[source_parent_inode].kind {
+                entries.insert(source_entry_name, source_entry);
+            }
+            return __WASI_EINVAL;
+        }
     }
 
     if let Kind::Dir { entries, .. } = &mut state.fs.inodes[target_parent_inode].kind {
@@ -2176,14 +2359,15 @@ pub fn path_symlink(
         return __WASI_EACCES;
     }
 
-    // get the depth of the parent + 1 (UNDER INVESTIGATION HMMMMMMMM THINK FISH ^ THINK FISH)
     let old_path_path = std::path::Path::new(old_path_str);
-    let (source_inode, _) = wasi_try!(state.fs.get_parent_inode_at_path(fd, old_path_path, true));
-    let depth = wasi_try!(state.fs.path_depth_from_fd(fd, source_inode)) - 1;
+    wasi_try!(state.fs.get_parent_inode_at_path(fd, old_path_path, true));
 
     let new_path_path = std::path::Path::new(new_path_str);
     let (target_parent_inode, entry_name) =
         wasi_try!(state.fs.get_parent_inode_at_path(fd, new_path_path, true));
+    // `old_path` is relative to `fd` while the symlink is resolved from the directory it's
+    // in, so go up from that directory to `fd` first
+    let depth = wasi_try!(state.fs.path_depth_from_fd(fd, target_parent_inode));
 
     // short circuit if anything is wrong, before we create an inode
     match &state.fs.inodes[target_parent_inode].kind {
@@ -2193,11 +2377,13 @@ pub fn path_symlink(
             }
         }
         Kind::Root { .. } => return __WASI_ENOTCAPABLE,
-        Kind::File { .. } | Kind::Symlink { .. } | Kind::Buffer { .. } => {
-            unreachable!("get_parent_inode_at_path returned something other than a Dir or Root")
+        Kind::File { .. } | Kind::Symlink { .. } | Kind::Buffer { .. } | Kind::Socket { .. } => {
+            return __WASI_ENOTDIR
         }
     }
 
+    wasi_try!(state.fs.check_create_quota());
+
     let mut source_path = std::path::Path::new(old_path_str);
     let mut relative_path = std::path::PathBuf::new();
     for _ in 0..depth {
@@ -2225,6 +2411,7 @@ pub fn path_symlink(
     {
         entries.insert(entry_name, new_inode);
     }
+    state.fs.quota.add_entry();
 
     __WASI_ESUCCESS
 }
@@ -2260,60 +2447,61 @@ pub fn path_unlink_file(
             .fs
             .get_parent_inode_at_path(fd, std::path::Path::new(path_str), false));
 
+    if let Kind::Dir { .. } | Kind::Root { .. } = state.fs.inodes[inode].kind {
+        return __WASI_EISDIR;
+    }
     let removed_inode = match &mut state.fs.inodes[parent_inode].kind {
         Kind::Dir {
             ref mut entries, ..
         } => {
             let removed_inode = wasi_try!(entries.remove(&childs_name).ok_or(__WASI_EINVAL));
-            // TODO: make this a debug assert in the future
-            assert!(inode == removed_inode);
+            if inode != removed_inode {
+                entries.insert(childs_name, removed_inode);
+                return __WASI_EINVAL;
+            }
             debug_assert!(state.fs.inodes[inode].stat.st_nlink > 0);
             removed_inode
         }
         Kind::Root { .. } => return __WASI_EACCES,
-        _ => unreachable!(
-            "Internal logic error in wasi::path_unlink_file, parent is not a directory"
-        ),
+        _ => return __WASI_ENOTDIR,
     };
 
     state.fs.inodes[removed_inode].stat.st_nlink -= 1;
     if state.fs.inodes[removed_inode].stat.st_nlink == 0 {
-        match &mut state.fs.inodes[removed_inode].kind {
+        let file_fs = match &state.fs.inodes[removed_inode].kind {
+            Kind::File { fs: Some(fs), .. } => Some(state.fs.filesystem(*fs)),
+            _ => None,
+        };
+        let result = match &mut state.fs.inodes[removed_inode].kind {
             Kind::File { handle, path, .. } => {
-                if let Some(h) = handle {
-                    wasi_try!(h.unlink().map_err(WasiFsError::into_wasi_err));
+                if let Some(fs) = file_fs {
+                    fs.remove_file(path).map_err(WasiFsError::into_wasi_err)
+                } else if let Some(h) = handle {
+                    h.unlink().map_err(WasiFsError::into_wasi_err)
                 } else {
-                    // File is closed
-                    // problem with the abstraction, we can't call unlink because there's no handle
-                    // TODO: replace this code
-                    wasi_try!(std::fs::remove_file(path).map_err(|_| __WASI_EIO));
+                    Ok(())
                 }
             }
-            Kind::Dir { .. } | Kind::Root { .. } => return __WASI_EISDIR,
             Kind::Symlink { .. } => {
                 // TODO: actually delete real symlinks and do nothing for virtual symlinks
+                Ok(())
             }
-            _ => unimplemented!("wasi::path_unlink_file for Buffer"),
-        }
-        // TODO: test this on Windows and actually make it portable
-        // make the file an orphan fd if the fd is still open
-        let fd_is_orphaned = if let Kind::File { handle, .. } = &state.fs.inodes[removed_inode].kind
-        {
-            handle.is_some()
-        } else {
-            false
+            // buffers only exist in memory
+            Kind::Buffer { .. } => Ok(()),
+            Kind::Dir { .. } | Kind::Root { .. } | Kind::Socket { .. } => Err(__WASI_EISDIR),
         };
-        let removed_inode_val = unsafe { state.fs.remove_inode(removed_inode) };
-        assert!(
-            removed_inode_val.is_some(),
-            "Inode could not be removed because it doesn't exist"
-        );
-
-        if fd_is_orphaned {
-            state
-                .fs
-                .orphan_fds
-                .insert(removed_inode, removed_inode_val.unwrap());
+        if let Err(e) = result {
+            // reinsert to prevent FS from being in bad state
+            state.fs.inodes[removed_inode].stat.st_nlink += 1;
+            if let Kind::Dir { entries, .. } = &mut state.fs.inodes[parent_inode].kind {
+                entries.insert(childs_name, removed_inode);
+            }
+            return e;
+        }
+        // fds that are still open keep using the inode, like unlinked files on the host
+        let fd_is_open = state.fs.fd_map.values().any(|fd| fd.inode == removed_inode);
+        if !fd_is_open {
+            unsafe { state.fs.remove_inode(removed_inode) };
         }
     }
 
@@ -2341,182 +2529,242 @@ pub fn poll_oneoff(
 ) -> __wasi_errno_t {
     debug!("wasi::poll_oneoff");
     debug!("  => nsubscriptions = {}", nsubscriptions);
-    let (memory, mut state) = env.get_memory_and_wasi_state(0);
+    let memory = env.memory();
 
     let subscription_array = wasi_try!(in_.deref(memory, 0, nsubscriptions));
     let event_array = wasi_try!(out_.deref(memory, 0, nsubscriptions));
-    let mut events_seen = 0;
     let out_ptr = wasi_try!(nevents.deref(memory));
+    if nsubscriptions == 0 {
+        return __WASI_EINVAL;
+    }
+
+    // deadlines are in nanoseconds, on the virtual clock in deterministic
+    // mode and since `start` otherwise
+    let start = std::time::Instant::now();
+    let poll_clock_now = |state: &WasiState| match &state.fs.virtual_clock {
+        Some(clock) => clock.now(),
+        None => start.elapsed().as_nanos() as __wasi_timestamp_t,
+    };
+    let mut subscriptions = Vec::with_capacity(subscription_array.len());
+    {
+        let state = env.state();
+        let now = poll_clock_now(&state);
+        for sub in subscription_array.iter() {
+            let s: WasiSubscription = wasi_try!(sub.get().try_into());
+            let deadline = match s.event_type {
+                EventType::Clock(clock_info) => {
+                    Some(wasi_try!(clock_deadline(env, &state, now, &clock_info)))
+                }
+                EventType::Read(_) | EventType::Write(_) => None,
+            };
+            subscriptions.push((s, deadline));
+        }
+    }
 
-    let mut fds = vec![];
-    let mut clock_subs = vec![];
-    let mut in_events = vec![];
-    let mut total_ns_slept = 0;
-
-    for sub in subscription_array.iter() {
-        let s: WasiSubscription = wasi_try!(sub.get().try_into());
-        let mut peb = PollEventBuilder::new();
-        let mut ns_to_sleep = 0;
-
-        let fd = match s.event_type {
-            EventType::Read(__wasi_subscription_fs_readwrite_t { fd }) => {
-                match fd {
-                    __WASI_STDIN_FILENO | __WASI_STDOUT_FILENO | __WASI_STDERR_FILENO => (),
-                    _ => {
-                        let fd_entry = wasi_try!(state.fs.get_fd(fd));
-                        if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_READ) {
-                            return __WASI_EACCES;
+    loop {
+        // the lock is released between rounds so other threads can make
+        // virtual files ready, for example by writing to a `Pipe`
+        let mut events_seen = 0;
+        {
+            let state = env.state();
+            let now = poll_clock_now(&state);
+            for (s, deadline) in subscriptions.iter() {
+                let (error, nbytes, flags) = match (&s.event_type, deadline) {
+                    (EventType::Clock(_), Some(deadline)) if *deadline <= now => {
+                        (__WASI_ESUCCESS, 0, 0)
+                    }
+                    (EventType::Clock(_), _) => continue,
+                    (EventType::Read(__wasi_subscription_fs_readwrite_t { fd }), _) => {
+                        match poll_fd_readiness(&state, *fd, PollEvent::PollIn) {
+                            Ok(Some((nbytes, flags))) => (__WASI_ESUCCESS, nbytes, flags),
+                            Ok(None) => continue,
+                            Err(err) => (err, 0, 0),
                         }
                     }
-                }
-                in_events.push(peb.add(PollEvent::PollIn).build());
-                Some(fd)
-            }
-            EventType::Write(__wasi_subscription_fs_readwrite_t { fd }) => {
-                match fd {
-                    __WASI_STDIN_FILENO | __WASI_STDOUT_FILENO | __WASI_STDERR_FILENO => (),
-                    _ => {
-                        let fd_entry = wasi_try!(state.fs.get_fd(fd));
-
-                        if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_WRITE) {
-                            return __WASI_EACCES;
+                    (EventType::Write(__wasi_subscription_fs_readwrite_t { fd }), _) => {
+                        match poll_fd_readiness(&state, *fd, PollEvent::PollOut) {
+                            Ok(Some((nbytes, flags))) => (__WASI_ESUCCESS, nbytes, flags),
+                            Ok(None) => continue,
+                            Err(err) => (err, 0, 0),
                         }
                     }
-                }
-                in_events.push(peb.add(PollEvent::PollOut).build());
-                Some(fd)
+                };
+                event_array[events_seen].set(__wasi_event_t {
+                    userdata: s.user_data,
+                    error,
+                    type_: s.event_type.raw_tag(),
+                    u: __wasi_event_u {
+                        fd_readwrite: __wasi_event_fd_readwrite_t { nbytes, flags },
+                    },
+                });
+                events_seen += 1;
             }
-            EventType::Clock(clock_info) => {
-                if clock_info.clock_id == __WASI_CLOCK_REALTIME {
-                    // this is a hack
-                    // TODO: do this properly
-                    ns_to_sleep = clock_info.timeout;
-                    clock_subs.push(clock_info);
-                    None
-                } else {
-                    unimplemented!("Polling not implemented for clocks yet");
-                }
+        }
+        if events_seen > 0 {
+            out_ptr.set(events_seen as u32);
+            return __WASI_ESUCCESS;
+        }
+        if env.threads_stopped() {
+            return __WASI_EINTR;
+        }
+
+        let next_deadline = subscriptions
+            .iter()
+            .filter_map(|(_, deadline)| *deadline)
+            .min();
+        // in deterministic mode time only passes by moving the virtual clock
+        if let Some(deadline) = next_deadline {
+            let mut state = env.state();
+            if let Some(clock) = &mut state.fs.virtual_clock {
+                clock.advance_to(deadline);
+                continue;
             }
-        };
+        }
 
-        if let Some(fd) = fd {
-            let wasi_file_ref: &dyn WasiFile = match fd {
-                __WASI_STDERR_FILENO => wasi_try!(
-                    wasi_try!(state.fs.stderr().map_err(WasiFsError::into_wasi_err)).as_ref(),
-                    __WASI_EBADF
-                )
-                .as_ref(),
-                __WASI_STDIN_FILENO => wasi_try!(
-                    wasi_try!(state.fs.stdin().map_err(WasiFsError::into_wasi_err)).as_ref(),
-                    __WASI_EBADF
-                )
-                .as_ref(),
-                __WASI_STDOUT_FILENO => wasi_try!(
-                    wasi_try!(state.fs.stdout().map_err(WasiFsError::into_wasi_err)).as_ref(),
-                    __WASI_EBADF
-                )
-                .as_ref(),
-                _ => {
-                    let fd_entry = wasi_try!(state.fs.get_fd(fd));
-                    let inode = fd_entry.inode;
-                    if !has_rights(fd_entry.rights, __WASI_RIGHT_POLL_FD_READWRITE) {
-                        return __WASI_EACCES;
-                    }
+        // sleep until the next timeout, checking the fds every millisecond
+        // as virtual files can't notify us when they become ready
+        let elapsed = start.elapsed().as_nanos() as __wasi_timestamp_t;
+        let mut wait = next_deadline
+            .map(|deadline| std::time::Duration::from_nanos(deadline.saturating_sub(elapsed)));
+        if subscriptions.iter().any(|(_, deadline)| deadline.is_none()) {
+            wait = Some(wait.map_or(FD_POLL_INTERVAL, |wait| wait.min(FD_POLL_INTERVAL)));
+        }
+        if let Some(wait) = wait {
+            trace!("wasi::poll_oneoff: sleeping for {:?}", wait);
+            std::thread::sleep(wait);
+        }
+    }
+}
 
-                    match &state.fs.inodes[inode].kind {
-                        Kind::File { handle, .. } => {
-                            if let Some(h) = handle {
-                                h.as_ref()
-                            } else {
-                                return __WASI_EBADF;
-                            }
-                        }
-                        Kind::Dir { .. }
-                        | Kind::Root { .. }
-                        | Kind::Buffer { .. }
-                        | Kind::Symlink { .. } => {
-                            unimplemented!("polling read on non-files not yet supported")
-                        }
-                    }
-                }
+/// How often `poll_oneoff` checks if fds became ready
+const FD_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(1);
+
+/// Waits until `fd` is ready for `interest` with the state unlocked, so a
+/// syscall blocking on a host fd doesn't stop the other threads from making
+/// syscalls, and fails with `__WASI_EINTR` if the threads are stopped.
+///
+/// Only the fds backed by a host fd can block, the others are ready straight
+/// away.
+pub(crate) fn wait_until_ready(
+    env: &WasiEnv,
+    fd: __wasi_fd_t,
+    interest: PollEvent,
+) -> Result<(), __wasi_errno_t> {
+    let interest = interest as PollEventSet;
+    loop {
+        {
+            let state = env.state();
+            let fd_entry = state.fs.get_fd(fd)?;
+            let ready = match &state.fs.inodes[fd_entry.inode].kind {
+                Kind::File {
+                    handle: Some(handle),
+                    ..
+                } if handle.get_raw_fd().is_some() => handle
+                    .poll_readiness(interest)
+                    .map_err(WasiFsError::into_wasi_err)?,
+                Kind::Socket { socket } if socket.get_raw_fd().is_some() => socket
+                    .poll_readiness(interest)
+                    .map_err(WasiFsError::into_wasi_err)?,
+                _ => return Ok(()),
             };
-            fds.push(wasi_file_ref);
-        } else {
-            let remaining_ns = ns_to_sleep as i64 - total_ns_slept as i64;
-            if remaining_ns > 0 {
-                debug!("Sleeping for {} nanoseconds", remaining_ns);
-                let duration = std::time::Duration::from_nanos(remaining_ns as u64);
-                std::thread::sleep(duration);
-                total_ns_slept += remaining_ns;
+            // errors and hang-ups are reported by the operation itself
+            if ready != 0 {
+                return Ok(());
             }
         }
+        if env.threads_stopped() {
+            return Err(__WASI_EINTR);
+        }
+        std::thread::sleep(FD_POLL_INTERVAL);
     }
-    let mut seen_events = vec![Default::default(); in_events.len()];
-    wasi_try!(poll(
-        fds.as_slice(),
-        in_events.as_slice(),
-        seen_events.as_mut_slice()
-    )
-    .map_err(|e| e.into_wasi_err()));
-
-    for (i, seen_event) in seen_events.into_iter().enumerate() {
-        let mut flags = 0;
-        let mut error = __WASI_EAGAIN;
-        let mut bytes_available = 0;
-        let event_iter = iterate_poll_events(seen_event);
-        for event in event_iter {
-            match event {
-                PollEvent::PollError => error = __WASI_EIO,
-                PollEvent::PollHangUp => flags = __WASI_EVENT_FD_READWRITE_HANGUP,
-                PollEvent::PollInvalid => error = __WASI_EINVAL,
-                PollEvent::PollIn => {
-                    bytes_available =
-                        wasi_try!(fds[i].bytes_available().map_err(|e| e.into_wasi_err()));
-                    error = __WASI_ESUCCESS;
-                }
-                PollEvent::PollOut => {
-                    bytes_available =
-                        wasi_try!(fds[i].bytes_available().map_err(|e| e.into_wasi_err()));
-                    error = __WASI_ESUCCESS;
-                }
-            }
+}
+
+/// Computes when a clock subscription times out, in nanoseconds on the
+/// same timeline as `now`.
+///
+/// The CPU-time clocks don't advance while the program sleeps, so their
+/// timeouts are treated as wall-clock time.
+fn clock_deadline(
+    env: &WasiEnv,
+    state: &WasiState,
+    now: __wasi_timestamp_t,
+    clock_info: &__wasi_subscription_clock_t,
+) -> Result<__wasi_timestamp_t, __wasi_errno_t> {
+    let current_time = match &state.fs.virtual_clock {
+        Some(clock) => {
+            VirtualClock::check_clock_id(clock_info.clock_id)?;
+            clock.now()
         }
-        let event = __wasi_event_t {
-            userdata: subscription_array[i].get().userdata,
-            error,
-            type_: subscription_array[i].get().type_,
-            u: unsafe {
-                __wasi_event_u {
-                    fd_readwrite: __wasi_event_fd_readwrite_t {
-                        nbytes: bytes_available as u64,
-                        flags,
-                    },
-                }
-            },
-        };
-        event_array[events_seen].set(event);
-        events_seen += 1;
-    }
-    for clock_info in clock_subs {
-        let event = __wasi_event_t {
-            // TOOD: review userdata value
-            userdata: 0,
-            error: __WASI_ESUCCESS,
-            type_: __WASI_EVENTTYPE_CLOCK,
-            u: unsafe {
-                __wasi_event_u {
-                    fd_readwrite: __wasi_event_fd_readwrite_t {
-                        nbytes: 0,
-                        flags: 0,
-                    },
-                }
-            },
-        };
-        event_array[events_seen].set(event);
-        events_seen += 1;
+        None => env.clock.time(clock_info.clock_id, 1)?,
+    };
+    let timeout = if clock_info.flags & __WASI_SUBSCRIPTION_CLOCK_ABSTIME != 0 {
+        clock_info.timeout.saturating_sub(current_time)
+    } else {
+        clock_info.timeout
+    };
+    now.checked_add(timeout).ok_or(__WASI_EOVERFLOW)
+}
+
+/// Checks without blocking if `fd` is ready for `interest`, returning the
+/// number of bytes available and the event flags if it is.
+fn poll_fd_readiness(
+    state: &WasiState,
+    fd: __wasi_fd_t,
+    interest: PollEvent,
+) -> Result<Option<(__wasi_filesize_t, __wasi_eventrwflags_t)>, __wasi_errno_t> {
+    let required_rights = match interest {
+        PollEvent::PollIn => __WASI_RIGHT_FD_READ,
+        _ => __WASI_RIGHT_FD_WRITE,
+    };
+    let fd_entry = state.fs.get_fd(fd)?;
+    if !has_rights(
+        fd_entry.rights,
+        required_rights | __WASI_RIGHT_POLL_FD_READWRITE,
+    ) {
+        return Err(__WASI_EACCES);
     }
-    out_ptr.set(events_seen as u32);
-    __WASI_ESUCCESS
+    let interest = interest as PollEventSet;
+
+    let (ready, bytes_available) = match &state.fs.inodes[fd_entry.inode].kind {
+        Kind::File { handle, .. } => {
+            let handle = handle.as_ref().ok_or(__WASI_EBADF)?;
+            let ready = handle
+                .poll_readiness(interest)
+                .map_err(WasiFsError::into_wasi_err)?;
+            let bytes_available = if ready & interest & PollEvent::PollIn as PollEventSet != 0 {
+                handle
+                    .bytes_available()
+                    .map_err(WasiFsError::into_wasi_err)?
+            } else {
+                0
+            };
+            (ready, bytes_available)
+        }
+        Kind::Socket { socket } => {
+            let ready = socket
+                .poll_readiness(interest)
+                .map_err(WasiFsError::into_wasi_err)?;
+            (ready, 0)
+        }
+        // buffers are in memory, so they can always be read and written
+        Kind::Buffer { buffer } => (interest, buffer.len()),
+        Kind::Dir { .. } | Kind::Root { .. } => return Err(__WASI_EISDIR),
+        Kind::Symlink { .. } => return Err(__WASI_EINVAL),
+    };
+
+    let mut flags = 0;
+    for event in iterate_poll_events(ready) {
+        match event {
+            PollEvent::PollError => return Err(__WASI_EIO),
+            PollEvent::PollInvalid => return Err(__WASI_EBADF),
+            PollEvent::PollHangUp => flags |= __WASI_EVENT_FD_READWRITE_HANGUP,
+            PollEvent::PollIn | PollEvent::PollOut => (),
+        }
+    }
+    if ready & interest == 0 && flags == 0 {
+        return Ok(None);
+    }
+    Ok(Some((bytes_available as __wasi_filesize_t, flags)))
 }
 
 pub fn proc_exit(env: &WasiEnv, code: __wasi_exitcode_t) {
@@ -2525,9 +2773,28 @@ pub fn proc_exit(env: &WasiEnv, code: __wasi_exitcode_t) {
     unreachable!();
 }
 
+/// ### `proc_raise()`
+/// Send a signal to the process of the calling thread.
+/// Signals with a handler set with `WasiStateBuilder::signal_handler` are
+/// passed to it. Without one, the signal is ignored or terminates the
+/// instance with `WasiError::Signal`, following the default action on POSIX.
+/// Inputs:
+/// - `__wasi_signal_t sig`
+///     The signal to raise
 pub fn proc_raise(env: &WasiEnv, sig: __wasi_signal_t) -> __wasi_errno_t {
-    debug!("wasi::proc_raise");
-    unimplemented!("wasi::proc_raise")
+    debug!("wasi::proc_raise: sig={}", sig);
+    let action = wasi_try!(SignalAction::default_for(sig));
+    // the lock must not be held when unwinding
+    let handler = env.state().signal_handlers.get(&sig).cloned();
+    match (handler, action) {
+        (Some(handler), _) => handler.handle(sig),
+        (None, SignalAction::Terminate) => {
+            RuntimeError::raise(Box::new(WasiError::Signal(sig)));
+        }
+        (None, SignalAction::Ignore) => (),
+    }
+
+    __WASI_ESUCCESS
 }
 
 /// ### `random_get()`
@@ -2539,28 +2806,50 @@ pub fn proc_raise(env: &WasiEnv, sig: __wasi_signal_t) -> __wasi_errno_t {
 ///     The number of bytes that will be written
 pub fn random_get(env: &WasiEnv, buf: WasmPtr<u8, Array>, buf_len: u32) -> __wasi_errno_t {
     debug!("wasi::random_get buf_len: {}", buf_len);
-    let memory = env.memory();
+    let (memory, mut state) = env.get_memory_and_wasi_state(0);
 
     let buf = wasi_try!(buf.deref(memory, 0, buf_len));
 
-    let res = unsafe {
-        let u8_buffer = &mut *(buf as *const [_] as *mut [_] as *mut [u8]);
-        getrandom::getrandom(u8_buffer)
-    };
-    match res {
-        Ok(()) => __WASI_ESUCCESS,
-        Err(_) => __WASI_EIO,
+    let u8_buffer = unsafe { &mut *(buf as *const [_] as *mut [_] as *mut [u8]) };
+    if let Some(rng) = &mut state.seeded_random {
+        rng.fill(u8_buffer);
+        return __WASI_ESUCCESS;
     }
+    wasi_try!(env.random.fill(u8_buffer));
+    __WASI_ESUCCESS
 }
 
 /// ### `sched_yield()`
 /// Yields execution of the thread
 pub fn sched_yield(env: &WasiEnv) -> __wasi_errno_t {
     debug!("wasi::sched_yield");
-    ::std::thread::yield_now();
+    match &mut env.state().fs.virtual_clock {
+        // the other threads don't exist as far as the virtual clock is
+        // concerned, so yielding just lets time pass
+        Some(clock) => {
+            clock.tick();
+        }
+        None => ::std::thread::yield_now(),
+    }
     __WASI_ESUCCESS
 }
 
+/// ### `sock_recv()`
+/// Receive a message from a socket
+/// Inputs:
+/// - `__wasi_fd_t sock`
+///     The socket to receive from
+/// - `const __wasi_iovec_t *ri_data`
+///     The buffers to store the received data in
+/// - `u32 ri_data_len`
+///     The number of buffers in `ri_data`
+/// - `__wasi_riflags_t ri_flags`
+///     Whether to peek at the data and whether to wait until all buffers are full
+/// Output:
+/// - `u32 *ro_datalen`
+///     The number of bytes received
+/// - `__wasi_roflags_t *ro_flags`
+///     Flags about the received data
 pub fn sock_recv(
     env: &WasiEnv,
     sock: __wasi_fd_t,
@@ -2570,21 +2859,166 @@ pub fn sock_recv(
     ro_datalen: WasmPtr<u32>,
     ro_flags: WasmPtr<__wasi_roflags_t>,
 ) -> __wasi_errno_t {
-    debug!("wasi::sock_recv");
-    unimplemented!("wasi::sock_recv")
+    debug!("wasi::sock_recv: sock={}, ri_flags={}", sock, ri_flags);
+    let memory = env.memory();
+    let iovs_arr_cell = wasi_try!(ri_data.deref(memory, 0, ri_data_len));
+    let ro_datalen_cell = wasi_try!(ro_datalen.deref(memory));
+    let ro_flags_cell = wasi_try!(ro_flags.deref(memory));
+
+    let mut buf = vec![
+        0;
+        iovs_arr_cell
+            .iter()
+            .map(|iov| iov.get().buf_len as usize)
+            .sum()
+    ];
+    let mut received = 0;
+    loop {
+        // the state is only locked once there is data to receive
+        wasi_try!(wait_until_ready(env, sock, PollEvent::PollIn));
+        let mut state = env.state();
+        let fd_entry = wasi_try!(state.fs.get_fd(sock));
+        if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_READ) {
+            return __WASI_EACCES;
+        }
+        let inode = fd_entry.inode;
+        let socket = match &mut state.fs.inodes[inode].kind {
+            Kind::Socket { socket } => socket,
+            _ => return __WASI_ENOTSOCK,
+        };
+
+        if ri_flags & __WASI_SOCK_RECV_PEEK != 0 {
+            received = wasi_try!(socket.peek(&mut buf).map_err(WasiFsError::into_wasi_err));
+            break;
+        }
+        let n = wasi_try!(socket
+            .read(&mut buf[received..])
+            .map_err(|e| WasiFsError::from(e).into_wasi_err()));
+        received += n;
+        if n == 0 || received == buf.len() || ri_flags & __WASI_SOCK_RECV_WAITALL == 0 {
+            break;
+        }
+    }
+
+    let mut data = &buf[..received];
+    for iov in iovs_arr_cell {
+        let iov_inner = iov.get();
+        let len = std::cmp::min(iov_inner.buf_len as usize, data.len());
+        let cells = wasi_try!(iov_inner.buf.deref(memory, 0, len as u32));
+        for (cell, &byte) in cells.iter().zip(&data[..len]) {
+            cell.set(byte);
+        }
+        data = &data[len..];
+    }
+
+    ro_datalen_cell.set(received as u32);
+    ro_flags_cell.set(0);
+    __WASI_ESUCCESS
 }
+
+/// ### `sock_send()`
+/// Send a message on a socket
+/// Inputs:
+/// - `__wasi_fd_t sock`
+///     The socket to send on
+/// - `const __wasi_ciovec_t *si_data`
+///     The buffers holding the data to send
+/// - `u32 si_data_len`
+///     The number of buffers in `si_data`
+/// - `__wasi_siflags_t si_flags`
+///     Unused, there are no flags yet
+/// Output:
+/// - `u32 *so_datalen`
+///     The number of bytes sent
 pub fn sock_send(
     env: &WasiEnv,
     sock: __wasi_fd_t,
     si_data: WasmPtr<__wasi_ciovec_t, Array>,
     si_data_len: u32,
-    si_flags: __wasi_siflags_t,
+    _si_flags: __wasi_siflags_t,
     so_datalen: WasmPtr<u32>,
 ) -> __wasi_errno_t {
-    debug!("wasi::sock_send");
-    unimplemented!("wasi::sock_send")
+    debug!("wasi::sock_send: sock={}", sock);
+    let (memory, mut state) = env.get_memory_and_wasi_state(0);
+    let iovs_arr_cell = wasi_try!(si_data.deref(memory, 0, si_data_len));
+    let so_datalen_cell = wasi_try!(so_datalen.deref(memory));
+
+    let fd_entry = wasi_try!(state.fs.get_fd(sock));
+    if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_WRITE) {
+        return __WASI_EACCES;
+    }
+    let inode = fd_entry.inode;
+    let socket = match &mut state.fs.inodes[inode].kind {
+        Kind::Socket { socket } => socket,
+        _ => return __WASI_ENOTSOCK,
+    };
+
+    let bytes_written = wasi_try!(write_bytes(socket, memory, iovs_arr_cell));
+    so_datalen_cell.set(bytes_written);
+    __WASI_ESUCCESS
 }
+
+/// ### `sock_shutdown()`
+/// Shut down the receiving and/or sending half of a socket
+/// Inputs:
+/// - `__wasi_fd_t sock`
+///     The socket to shut down
+/// - `__wasi_sdflags_t how`
+///     Which halves of the socket to shut down
 pub fn sock_shutdown(env: &WasiEnv, sock: __wasi_fd_t, how: __wasi_sdflags_t) -> __wasi_errno_t {
-    debug!("wasi::sock_shutdown");
-    unimplemented!("wasi::sock_shutdown")
+    debug!("wasi::sock_shutdown: sock={}, how={}", sock, how);
+    let mut state = env.state();
+
+    let how = match how {
+        __WASI_SHUT_RD => std::net::Shutdown::Read,
+        __WASI_SHUT_WR => std::net::Shutdown::Write,
+        x if x == __WASI_SHUT_RD | __WASI_SHUT_WR => std::net::Shutdown::Both,
+        _ => return __WASI_EINVAL,
+    };
+    let fd_entry = wasi_try!(state.fs.get_fd(sock));
+    if !has_rights(fd_entry.rights, __WASI_RIGHT_SOCK_SHUTDOWN) {
+        return __WASI_EACCES;
+    }
+    let inode = fd_entry.inode;
+    match &mut state.fs.inodes[inode].kind {
+        Kind::Socket { socket } => {
+            wasi_try!(socket.shutdown(how).map_err(WasiFsError::into_wasi_err));
+        }
+        _ => return __WASI_ENOTSOCK,
+    }
+
+    __WASI_ESUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_are_always_ready() {
+        let mut state = crate::state::WasiState::new("test_prog").build().unwrap();
+        let inode = state.fs.create_inode_with_default_stat(
+            Kind::Buffer {
+                buffer: b"hello".to_vec(),
+            },
+            false,
+            "buffer".into(),
+        );
+        let rights = crate::state::ALL_RIGHTS;
+        let fd = state.fs.create_fd(rights, rights, 0, 0, inode).unwrap();
+
+        assert_eq!(
+            poll_fd_readiness(&state, fd, PollEvent::PollIn),
+            Ok(Some((5, 0)))
+        );
+        assert_eq!(
+            poll_fd_readiness(&state, fd, PollEvent::PollOut),
+            Ok(Some((0, 0)))
+        );
+        let root = state.fs.preopen_fds[0];
+        assert_eq!(
+            poll_fd_readiness(&state, root, PollEvent::PollIn),
+            Err(__WASI_EISDIR)
+        );
+    }
 }
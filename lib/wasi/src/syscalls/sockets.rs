@@ -0,0 +1,252 @@
+//! Socket extension to WASI, importable from the `wasmer_sockets` namespace.
+//!
+//! WASI only lets programs use sockets they were given, so these functions
+//! allow programs to open sockets themselves.  They can only reach the
+//! addresses and ports allowed with [`WasiStateBuilder::allow_socket_addr`].
+//!
+//! [`WasiStateBuilder::allow_socket_addr`]: crate::WasiStateBuilder::allow_socket_addr
+
+use crate::ptr::{Array, WasmPtr};
+use crate::state::PollEvent;
+use crate::state::{HostTcpListener, HostTcpStream, Kind, WasiFsError};
+use crate::syscalls::types::*;
+use crate::syscalls::{has_rights, wait_until_ready};
+use crate::WasiEnv;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tracing::debug;
+
+/// ### `sock_connect()`
+/// Open a TCP connection
+/// Inputs:
+/// - `const __wasi_sockaddr_t *addr`
+///     The address to connect to
+/// Output:
+/// - `__wasi_fd_t *fd`
+///     The file descriptor of the connected socket
+/// Errors:
+/// - `__WASI_ENOTCAPABLE`
+///     The address is not allowed
+pub fn sock_connect(
+    env: &WasiEnv,
+    addr: WasmPtr<__wasi_sockaddr_t>,
+    fd: WasmPtr<__wasi_fd_t>,
+) -> __wasi_errno_t {
+    debug!("wasi::sock_connect");
+    let (memory, mut state) = env.get_memory_and_wasi_state(0);
+    let addr = wasi_try!(
+        wasi_try!(addr.deref(memory)).get().to_socket_addr(),
+        __WASI_EINVAL
+    );
+    let fd_cell = wasi_try!(fd.deref(memory));
+    debug!("=> addr={}", addr);
+    if !state.socket_allowlist.is_allowed(&addr) {
+        return __WASI_ENOTCAPABLE;
+    }
+
+    let stream =
+        wasi_try!(TcpStream::connect(addr).map_err(|e| WasiFsError::from(e).into_wasi_err()));
+    let new_fd = wasi_try!(state
+        .fs
+        .create_socket_fd(Box::new(HostTcpStream::new(stream))));
+    fd_cell.set(new_fd);
+
+    __WASI_ESUCCESS
+}
+
+/// ### `sock_bind()`
+/// Open a TCP socket bound to an address, ready to be listened on with
+/// `sock_listen`
+/// Inputs:
+/// - `const __wasi_sockaddr_t *addr`
+///     The address to bind to
+/// Output:
+/// - `__wasi_fd_t *fd`
+///     The file descriptor of the bound socket
+/// Errors:
+/// - `__WASI_ENOTCAPABLE`
+///     The address is not allowed
+pub fn sock_bind(
+    env: &WasiEnv,
+    addr: WasmPtr<__wasi_sockaddr_t>,
+    fd: WasmPtr<__wasi_fd_t>,
+) -> __wasi_errno_t {
+    debug!("wasi::sock_bind");
+    let (memory, mut state) = env.get_memory_and_wasi_state(0);
+    let addr = wasi_try!(
+        wasi_try!(addr.deref(memory)).get().to_socket_addr(),
+        __WASI_EINVAL
+    );
+    let fd_cell = wasi_try!(fd.deref(memory));
+    debug!("=> addr={}", addr);
+    if !state.socket_allowlist.is_allowed(&addr) {
+        return __WASI_ENOTCAPABLE;
+    }
+
+    let listener =
+        wasi_try!(TcpListener::bind(addr).map_err(|e| WasiFsError::from(e).into_wasi_err()));
+    let new_fd = wasi_try!(state
+        .fs
+        .create_socket_fd(Box::new(HostTcpListener::new(listener))));
+    fd_cell.set(new_fd);
+
+    __WASI_ESUCCESS
+}
+
+/// ### `sock_listen()`
+/// Start accepting connections on a bound socket
+/// Inputs:
+/// - `__wasi_fd_t sock`
+///     The bound socket
+/// - `u32 backlog`
+///     A hint for how many connections may be waiting to be accepted
+pub fn sock_listen(env: &WasiEnv, sock: __wasi_fd_t, backlog: u32) -> __wasi_errno_t {
+    debug!("wasi::sock_listen: sock={}, backlog={}", sock, backlog);
+    let mut state = env.state();
+    let fd_entry = wasi_try!(state.fs.get_fd(sock));
+    if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_READ) {
+        return __WASI_EACCES;
+    }
+    let inode = fd_entry.inode;
+
+    match &mut state.fs.inodes[inode].kind {
+        Kind::Socket { socket } => {
+            wasi_try!(socket.listen(backlog).map_err(WasiFsError::into_wasi_err));
+        }
+        _ => return __WASI_ENOTSOCK,
+    }
+
+    __WASI_ESUCCESS
+}
+
+/// ### `sock_accept()`
+/// Accept a connection on a listening socket, blocking until one arrives
+/// Inputs:
+/// - `__wasi_fd_t sock`
+///     The listening socket
+/// Output:
+/// - `__wasi_fd_t *fd`
+///     The file descriptor of the accepted connection
+/// - `__wasi_sockaddr_t *addr`
+///     The address of the peer
+pub fn sock_accept(
+    env: &WasiEnv,
+    sock: __wasi_fd_t,
+    fd: WasmPtr<__wasi_fd_t>,
+    addr: WasmPtr<__wasi_sockaddr_t>,
+) -> __wasi_errno_t {
+    debug!("wasi::sock_accept: sock={}", sock);
+    // the state is only locked once there is a connection to accept
+    wasi_try!(wait_until_ready(env, sock, PollEvent::PollIn));
+    let (memory, mut state) = env.get_memory_and_wasi_state(0);
+    let fd_cell = wasi_try!(fd.deref(memory));
+    let addr_cell = wasi_try!(addr.deref(memory));
+    let fd_entry = wasi_try!(state.fs.get_fd(sock));
+    if !has_rights(fd_entry.rights, __WASI_RIGHT_FD_READ) {
+        return __WASI_EACCES;
+    }
+    let inode = fd_entry.inode;
+
+    let (connection, peer) = match &mut state.fs.inodes[inode].kind {
+        Kind::Socket { socket } => wasi_try!(socket.accept().map_err(WasiFsError::into_wasi_err)),
+        _ => return __WASI_ENOTSOCK,
+    };
+    let new_fd = wasi_try!(state.fs.create_socket_fd(connection));
+    fd_cell.set(new_fd);
+    addr_cell.set(peer.into());
+
+    __WASI_ESUCCESS
+}
+
+/// ### `sock_addr_local()`
+/// Get the address a socket is bound to
+/// Inputs:
+/// - `__wasi_fd_t sock`
+///     The socket
+/// Output:
+/// - `__wasi_sockaddr_t *addr`
+///     The local address of the socket
+pub fn sock_addr_local(
+    env: &WasiEnv,
+    sock: __wasi_fd_t,
+    addr: WasmPtr<__wasi_sockaddr_t>,
+) -> __wasi_errno_t {
+    debug!("wasi::sock_addr_local: sock={}", sock);
+    let (memory, state) = env.get_memory_and_wasi_state(0);
+    let addr_cell = wasi_try!(addr.deref(memory));
+    let fd_entry = wasi_try!(state.fs.get_fd(sock));
+
+    match &state.fs.inodes[fd_entry.inode].kind {
+        Kind::Socket { socket } => {
+            let local = wasi_try!(socket.local_addr().map_err(WasiFsError::into_wasi_err));
+            addr_cell.set(local.into());
+        }
+        _ => return __WASI_ENOTSOCK,
+    }
+
+    __WASI_ESUCCESS
+}
+
+/// ### `getaddrinfo()`
+/// Resolve a host name to the addresses the program is allowed to use
+/// Inputs:
+/// - `const char *node`
+///     The host name or IP address to resolve
+/// - `u32 node_len`
+///     The length of `node`
+/// - `u32 port`
+///     The port to put in the resolved addresses
+/// - `__wasi_sockaddr_t *addrs`
+///     The buffer to write the resolved addresses to
+/// - `u32 addrs_len`
+///     The number of addresses that fit in `addrs`
+/// Output:
+/// - `u32 *naddrs`
+///     The number of addresses written to `addrs`
+/// Errors:
+/// - `__WASI_ENOTCAPABLE`
+///     None of the resolved addresses are allowed, or no address with this
+///     port is, in which case the name isn't resolved
+pub fn getaddrinfo(
+    env: &WasiEnv,
+    node: WasmPtr<u8, Array>,
+    node_len: u32,
+    port: u32,
+    addrs: WasmPtr<__wasi_sockaddr_t, Array>,
+    addrs_len: u32,
+    naddrs: WasmPtr<u32>,
+) -> __wasi_errno_t {
+    debug!("wasi::getaddrinfo");
+    let memory = env.memory();
+    let node = unsafe { get_input_str!(memory, node, node_len) };
+    let naddrs_cell = wasi_try!(naddrs.deref(memory));
+    let addrs_cells = wasi_try!(addrs.deref(memory, 0, addrs_len));
+    if port > u16::MAX as u32 {
+        return __WASI_EINVAL;
+    }
+    debug!("=> node={}, port={}", node, port);
+    // names are only sent to the host resolver if an address could be allowed
+    if !env.state().socket_allowlist.allows_port(port as u16) {
+        return __WASI_ENOTCAPABLE;
+    }
+
+    // resolving may take a while, so the state isn't locked meanwhile
+    let resolved = wasi_try!((node, port as u16)
+        .to_socket_addrs()
+        .map_err(|e| WasiFsError::from(e).into_wasi_err()));
+    let state = env.state();
+    let allowed = resolved
+        .filter(|addr| state.socket_allowlist.is_allowed(addr))
+        .collect::<Vec<_>>();
+    if allowed.is_empty() {
+        return __WASI_ENOTCAPABLE;
+    }
+
+    let mut written = 0;
+    for (cell, addr) in addrs_cells.iter().zip(allowed) {
+        cell.set(addr.into());
+        written += 1;
+    }
+    naddrs_cell.set(written);
+
+    __WASI_ESUCCESS
+}
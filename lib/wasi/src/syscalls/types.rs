@@ -522,6 +522,23 @@ pub const __WASI_SHUT_WR: u8 = 1 << 1;
 
 pub type __wasi_siflags_t = u16;
 
+pub type __wasi_addrfamily_t = u16;
+pub const __WASI_ADDRESS_FAMILY_INET4: u16 = 0;
+pub const __WASI_ADDRESS_FAMILY_INET6: u16 = 1;
+
+/// An IPv4 or IPv6 address and a port, as used by the socket extension.
+///
+/// IPv4 addresses are stored in the first 4 bytes of `addr`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub struct __wasi_sockaddr_t {
+    pub family: __wasi_addrfamily_t,
+    pub port: u16,
+    pub addr: [u8; 16],
+}
+
+unsafe impl ValueType for __wasi_sockaddr_t {}
+
 pub type __wasi_signal_t = u8;
 pub const __WASI_SIGHUP: u8 = 1;
 pub const __WASI_SIGINT: u8 = 2;
@@ -43,12 +43,8 @@ pub fn platform_clock_time_get(
                 }));
             duration.as_nanos() as u64
         }
-        __WASI_CLOCK_PROCESS_CPUTIME_ID => {
-            unimplemented!("wasi::platform_clock_time_get(__WASI_CLOCK_PROCESS_CPUTIME_ID, ..)")
-        }
-        __WASI_CLOCK_THREAD_CPUTIME_ID => {
-            unimplemented!("wasi::platform_clock_time_get(__WASI_CLOCK_THREAD_CPUTIME_ID, ..)")
-        }
+        __WASI_CLOCK_PROCESS_CPUTIME_ID => return __WASI_ENOTSUP,
+        __WASI_CLOCK_THREAD_CPUTIME_ID => return __WASI_ENOTSUP,
         _ => return __WASI_EINVAL,
     };
     time.set(nanos);
@@ -0,0 +1,202 @@
+//! Threads started by the program with `thread-spawn`, following the
+//! [wasi-threads](https://github.com/WebAssembly/wasi-threads) proposal.
+//!
+//! Every thread runs in an instance of its own of the same module, and all
+//! the instances import the same shared memory and share the [`WasiState`].
+//! A thread that exits or traps ends the whole program: the other threads are
+//! interrupted, and the error is reported when joining them.
+//!
+//! [`WasiState`]: crate::WasiState
+
+use crate::syscalls::types::*;
+use crate::{generate_import_object_from_env, WasiEnv, WasiError, WasiVersion};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use tracing::debug;
+use wasmer::{Exports, Function, Instance, Memory, Module, RuntimeError, Store, TrapCode};
+
+/// The namespace of the `thread-spawn` import.
+const NAMESPACE: &str = "wasi";
+/// The export a new thread starts running, with its id and the argument
+/// given to `thread-spawn`.
+const THREAD_START: &str = "wasi_thread_start";
+/// The highest thread id, as the ids of wasi-threads are 29 bits.
+const MAX_TID: u32 = 0x1FFF_FFFF;
+
+/// Whether `module` imports `thread-spawn`.
+pub(crate) fn spawns_threads(module: &Module) -> bool {
+    module
+        .imports()
+        .functions()
+        .any(|import| import.module() == NAMESPACE && import.name() == "thread-spawn")
+}
+
+/// What the environments of all the threads of a program need to start
+/// more threads.
+#[derive(Debug, Clone)]
+pub(crate) struct WasiThreads {
+    module: Module,
+    version: WasiVersion,
+    /// The shared memory, and the module and name it is imported as
+    memory: (String, String, Memory),
+    next_tid: Arc<AtomicU32>,
+    handles: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    /// The error of the first thread that exited or trapped
+    error: Arc<Mutex<Option<RuntimeError>>>,
+    /// Set when the threads must stop, for the syscalls that block on the
+    /// host and never see the interruption
+    stopped: Arc<AtomicBool>,
+}
+
+impl WasiThreads {
+    /// Creates the shared memory `module` imports, which the instances of
+    /// all the threads will share.
+    pub fn new(module: &Module, version: WasiVersion) -> Result<Self, WasiError> {
+        let import = module
+            .imports()
+            .memories()
+            .find(|import| import.ty().shared)
+            .ok_or_else(|| {
+                WasiError::Threads("the module doesn't import a shared memory".to_string())
+            })?;
+        let memory = Memory::new(module.store(), *import.ty())
+            .map_err(|e| WasiError::Threads(e.to_string()))?;
+        Ok(Self {
+            module: module.clone(),
+            version,
+            memory: (
+                import.module().to_string(),
+                import.name().to_string(),
+                memory,
+            ),
+            // the main thread has no id of its own
+            next_tid: Arc::new(AtomicU32::new(1)),
+            handles: Arc::new(Mutex::new(Vec::new())),
+            error: Arc::new(Mutex::new(None)),
+            stopped: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// The `thread-spawn` function and the shared memory, to merge into the
+    /// namespaces of the import object.
+    pub fn imports(&self, store: &Store, env: WasiEnv) -> Vec<(String, Exports)> {
+        let mut wasi = Exports::new();
+        wasi.insert(
+            "thread-spawn",
+            Function::new_native_with_env(store, env, thread_spawn),
+        );
+        let (module, name, memory) = &self.memory;
+        let mut memory_namespace = Exports::new();
+        memory_namespace.insert(name.as_str(), memory.clone());
+        vec![
+            (NAMESPACE.to_string(), wasi),
+            (module.clone(), memory_namespace),
+        ]
+    }
+
+    fn spawn(&self, env: &WasiEnv, start_arg: u32) -> Result<u32, __wasi_errno_t> {
+        let tid = self.next_tid.fetch_add(1, Ordering::Relaxed);
+        if tid > MAX_TID {
+            return Err(__WASI_EAGAIN);
+        }
+        let import_object =
+            generate_import_object_from_env(self.module.store(), env.clone(), self.version);
+        let instance = Instance::new(&self.module, &import_object).map_err(|e| {
+            debug!(
+                "wasi::thread-spawn: could not instantiate the module: {}",
+                e
+            );
+            __WASI_EAGAIN
+        })?;
+        let start = instance
+            .exports
+            .get_native_function::<(i32, i32), ()>(THREAD_START)
+            .map_err(|_| __WASI_ENOEXEC)?;
+        let threads = self.clone();
+        let handle = thread::Builder::new()
+            .name(format!("wasi-thread-{}", tid))
+            .spawn(move || {
+                // the instance must live as long as the thread runs it
+                let _instance = instance;
+                if let Err(e) = start.call(tid as i32, start_arg as i32) {
+                    threads.fail(e);
+                }
+            })
+            .map_err(|_| __WASI_EAGAIN)?;
+        self.handles.lock().unwrap().push(handle);
+        Ok(tid)
+    }
+
+    /// Ends the program with the error a thread exited or trapped with,
+    /// unless another thread already did, and interrupts the others.
+    fn fail(&self, error: RuntimeError) {
+        // the threads stopped by the interruption end with this trap
+        if error.clone().to_trap() == Some(TrapCode::Interrupt) {
+            return;
+        }
+        let mut first = self.error.lock().unwrap();
+        if first.is_none() {
+            *first = Some(error);
+            self.stopped.store(true, Ordering::SeqCst);
+            self.module.store().interrupt_handle().interrupt();
+        }
+    }
+
+    /// Interrupts the threads that are still running, and makes the
+    /// syscalls blocking in them return `__WASI_EINTR`.
+    pub fn stop(&self) {
+        if !self.handles.lock().unwrap().is_empty() {
+            self.stopped.store(true, Ordering::SeqCst);
+            self.module.store().interrupt_handle().interrupt();
+        }
+    }
+
+    /// Whether the threads must stop, which blocking syscalls check while
+    /// they wait.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Waits for every thread to finish, including the ones started while
+    /// waiting, and returns the error of the first one that exited or trapped.
+    pub fn join(&self) -> Result<(), RuntimeError> {
+        loop {
+            // the lock is released before joining, so the thread can spawn more
+            let handle = self.handles.lock().unwrap().pop();
+            let handle = match handle {
+                Some(handle) => handle,
+                None => break,
+            };
+            if handle.join().is_err() {
+                self.fail(RuntimeError::new("a WASI thread panicked"));
+            }
+        }
+        // the next threads the program starts can run
+        self.stopped.store(false, Ordering::SeqCst);
+        match self.error.lock().unwrap().take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+/// ### `thread-spawn()`
+/// Start a thread running the `wasi_thread_start` export of a new instance
+/// of the program, which shares its memory and WASI state.
+/// Inputs:
+/// - `u32 start_arg`
+///     Passed to `wasi_thread_start` along with the id of the thread
+/// Output:
+/// - The id of the new thread, or a negated errno if it couldn't be started
+fn thread_spawn(env: &WasiEnv, start_arg: u32) -> i32 {
+    debug!("wasi::thread-spawn: start_arg={}", start_arg);
+    let result = match &env.threads {
+        Some(threads) => threads.spawn(env, start_arg),
+        None => Err(__WASI_ENOTSUP),
+    };
+    match result {
+        Ok(tid) => tid as i32,
+        Err(errno) => -(errno as i32),
+    }
+}
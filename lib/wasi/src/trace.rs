@@ -0,0 +1,321 @@
+//! Observing the syscalls a WASI program makes, see [`SyscallObserver`].
+
+use crate::ptr::{Array, WasmPtr};
+use crate::syscalls::types::*;
+use crate::WasiEnv;
+use std::fmt;
+use std::time::{Duration, Instant};
+use wasmer::ValueType;
+
+/// Receives every syscall made through a [`WasiEnv`], set with
+/// [`WasiEnv::set_syscall_observer`].
+///
+/// The observer is called on the thread making the syscall, after it
+/// returns, so it should be quick.
+pub trait SyscallObserver: fmt::Debug + Send + Sync + 'static {
+    fn on_syscall(&self, event: &SyscallEvent);
+}
+
+/// A syscall made by a WASI program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyscallEvent {
+    /// The name of the syscall, like `fd_write`
+    pub name: &'static str,
+    /// The arguments, in the order of the syscall's parameters
+    pub args: Vec<SyscallArg>,
+    /// The errno returned, or `None` for `proc_exit` which never returns
+    pub result: Option<__wasi_errno_t>,
+    /// How long the syscall took
+    pub duration: Duration,
+}
+
+/// An argument of a syscall, decoded according to its meaning.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyscallArg {
+    /// A file descriptor
+    Fd(__wasi_fd_t),
+    /// A path or other string, read from memory before the syscall
+    Path(String),
+    /// The lengths of the buffers of an iovec array
+    Iovecs(Vec<u32>),
+    /// A pointer into memory, usually where the syscall writes its results
+    Ptr(u32),
+    /// Any other number, like flags, sizes and offsets
+    Int(i64),
+}
+
+impl fmt::Display for SyscallArg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fd(fd) => write!(f, "{}", fd),
+            Self::Path(path) => write!(f, "{:?}", path),
+            Self::Iovecs(lens) => write!(f, "{:?}", lens),
+            Self::Ptr(ptr) => write!(f, "{:#x}", ptr),
+            Self::Int(int) => write!(f, "{}", int),
+        }
+    }
+}
+
+/// Formats the event like `strace` does, for example
+/// `fd_write(1, 0x10, [13], 1, 0x8) = 0 ESUCCESS <0.000021>`.
+impl fmt::Display for SyscallEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.name)?;
+        for (i, arg) in self.args.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", arg)?;
+        }
+        match self.result {
+            Some(errno) => write!(
+                f,
+                ") = {} {} <{:.6}>",
+                errno,
+                errno_name(errno),
+                self.duration.as_secs_f64()
+            ),
+            None => write!(f, ") = ?"),
+        }
+    }
+}
+
+/// The name of `errno`, like `EBADF`.
+pub fn errno_name(errno: __wasi_errno_t) -> &'static str {
+    ERRNO_NAMES
+        .get(errno as usize)
+        .copied()
+        .unwrap_or("EUNKNOWN")
+}
+
+const ERRNO_NAMES: [&str; 77] = [
+    "ESUCCESS",
+    "E2BIG",
+    "EACCES",
+    "EADDRINUSE",
+    "EADDRNOTAVAIL",
+    "EAFNOSUPPORT",
+    "EAGAIN",
+    "EALREADY",
+    "EBADF",
+    "EBADMSG",
+    "EBUSY",
+    "ECANCELED",
+    "ECHILD",
+    "ECONNABORTED",
+    "ECONNREFUSED",
+    "ECONNRESET",
+    "EDEADLK",
+    "EDESTADDRREQ",
+    "EDOM",
+    "EDQUOT",
+    "EEXIST",
+    "EFAULT",
+    "EFBIG",
+    "EHOSTUNREACH",
+    "EIDRM",
+    "EILSEQ",
+    "EINPROGRESS",
+    "EINTR",
+    "EINVAL",
+    "EIO",
+    "EISCONN",
+    "EISDIR",
+    "ELOOP",
+    "EMFILE",
+    "EMLINK",
+    "EMSGSIZE",
+    "EMULTIHOP",
+    "ENAMETOOLONG",
+    "ENETDOWN",
+    "ENETRESET",
+    "ENETUNREACH",
+    "ENFILE",
+    "ENOBUFS",
+    "ENODEV",
+    "ENOENT",
+    "ENOEXEC",
+    "ENOLCK",
+    "ENOLINK",
+    "ENOMEM",
+    "ENOMSG",
+    "ENOPROTOOPT",
+    "ENOSPC",
+    "ENOSYS",
+    "ENOTCONN",
+    "ENOTDIR",
+    "ENOTEMPTY",
+    "ENOTRECOVERABLE",
+    "ENOTSOCK",
+    "ENOTSUP",
+    "ENOTTY",
+    "ENXIO",
+    "EOVERFLOW",
+    "EOWNERDEAD",
+    "EPERM",
+    "EPIPE",
+    "EPROTO",
+    "EPROTONOSUPPORT",
+    "EPROTOTYPE",
+    "ERANGE",
+    "EROFS",
+    "ESPIPE",
+    "ESRCH",
+    "ESTALE",
+    "ETIMEDOUT",
+    "ETXTBSY",
+    "EXDEV",
+    "ENOTCAPABLE",
+];
+
+/// Runs `syscall`, reporting it to the observer of `env` if there is one.
+///
+/// The arguments are only decoded when there's an observer.
+pub(crate) fn traced(
+    env: &WasiEnv,
+    name: &'static str,
+    args: impl FnOnce() -> Vec<SyscallArg>,
+    syscall: impl FnOnce() -> __wasi_errno_t,
+) -> __wasi_errno_t {
+    let observer = match &env.observer {
+        Some(observer) => observer,
+        None => return syscall(),
+    };
+    let args = args();
+    let start = Instant::now();
+    let result = syscall();
+    observer.on_syscall(&SyscallEvent {
+        name,
+        args,
+        result: Some(result),
+        duration: start.elapsed(),
+    });
+    result
+}
+
+/// Reports a syscall that never returns before it runs.
+pub(crate) fn traced_noreturn(
+    env: &WasiEnv,
+    name: &'static str,
+    args: impl FnOnce() -> Vec<SyscallArg>,
+) {
+    if let Some(observer) = &env.observer {
+        observer.on_syscall(&SyscallEvent {
+            name,
+            args: args(),
+            result: None,
+            duration: Duration::default(),
+        });
+    }
+}
+
+// The decoders used by `traced_syscall!`, named after the kinds of arguments.
+
+pub(crate) fn fd(_: &WasiEnv, fd: __wasi_fd_t) -> SyscallArg {
+    SyscallArg::Fd(fd)
+}
+
+pub(crate) fn int<T: TraceInt>(_: &WasiEnv, int: T) -> SyscallArg {
+    SyscallArg::Int(int.into_i64())
+}
+
+pub(crate) fn ptr<T: Copy, Ty>(_: &WasiEnv, ptr: WasmPtr<T, Ty>) -> SyscallArg {
+    SyscallArg::Ptr(ptr.offset())
+}
+
+pub(crate) fn path(env: &WasiEnv, ptr: WasmPtr<u8, Array>, len: u32) -> SyscallArg {
+    match ptr.deref(env.memory(), 0, len) {
+        Ok(cells) => {
+            let bytes = cells.iter().map(|cell| cell.get()).collect::<Vec<u8>>();
+            SyscallArg::Path(String::from_utf8_lossy(&bytes).into_owned())
+        }
+        Err(_) => SyscallArg::Ptr(ptr.offset()),
+    }
+}
+
+pub(crate) fn iovecs<T: Iovec>(env: &WasiEnv, ptr: WasmPtr<T, Array>, len: u32) -> SyscallArg {
+    match ptr.deref(env.memory(), 0, len) {
+        Ok(cells) => SyscallArg::Iovecs(cells.iter().map(|cell| cell.get().buf_len()).collect()),
+        Err(_) => SyscallArg::Ptr(ptr.offset()),
+    }
+}
+
+/// The iovec types, which only differ in whether the buffers are read or
+/// written.
+pub(crate) trait Iovec: Copy + ValueType {
+    fn buf_len(&self) -> u32;
+}
+
+impl Iovec for __wasi_iovec_t {
+    fn buf_len(&self) -> u32 {
+        self.buf_len
+    }
+}
+
+impl Iovec for __wasi_ciovec_t {
+    fn buf_len(&self) -> u32 {
+        self.buf_len
+    }
+}
+
+/// The integer types of syscall parameters. The 64-bit flags are shown as
+/// their bits in an `i64`.
+pub(crate) trait TraceInt {
+    fn into_i64(self) -> i64;
+}
+
+macro_rules! impl_trace_int {
+    ($($ty:ty),*) => {
+        $(
+            impl TraceInt for $ty {
+                fn into_i64(self) -> i64 {
+                    self as i64
+                }
+            }
+        )*
+    };
+}
+
+impl_trace_int!(u8, u16, u32, u64, i64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_formats_like_strace() {
+        let event = SyscallEvent {
+            name: "path_open",
+            args: vec![
+                SyscallArg::Fd(3),
+                SyscallArg::Path("a/b.txt".to_string()),
+                SyscallArg::Iovecs(vec![4, 16]),
+                SyscallArg::Ptr(0x400),
+                SyscallArg::Int(-1),
+            ],
+            result: Some(__WASI_ENOENT),
+            duration: Duration::from_micros(21),
+        };
+        assert_eq!(
+            event.to_string(),
+            "path_open(3, \"a/b.txt\", [4, 16], 0x400, -1) = 44 ENOENT <0.000021>"
+        );
+
+        let exit = SyscallEvent {
+            name: "proc_exit",
+            args: vec![SyscallArg::Int(1)],
+            result: None,
+            duration: Duration::default(),
+        };
+        assert_eq!(exit.to_string(), "proc_exit(1) = ?");
+    }
+
+    #[test]
+    fn errno_names() {
+        assert_eq!(errno_name(__WASI_ESUCCESS), "ESUCCESS");
+        assert_eq!(errno_name(__WASI_EBADF), "EBADF");
+        assert_eq!(errno_name(__WASI_EXDEV), "EXDEV");
+        assert_eq!(errno_name(__WASI_ENOTCAPABLE), "ENOTCAPABLE");
+        assert_eq!(errno_name(__WASI_ENOTCAPABLE + 1), "EUNKNOWN");
+    }
+}
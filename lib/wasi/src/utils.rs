@@ -36,16 +36,23 @@ const SNAPSHOT0_NAMESPACE: &str = "wasi_unstable";
 /// Namespace for the `Snapshot1` version.
 const SNAPSHOT1_NAMESPACE: &str = "wasi_snapshot_preview1";
 
+/// Namespaces of the extensions that are provided along with any version,
+/// the threads of wasi-threads and the sockets of `wasmer_sockets`.
+const EXTENSION_NAMESPACES: &[&str] = &["wasi", "wasmer_sockets"];
+
 /// Detect the version of WASI being used based on the import
 /// namespaces.
 ///
 /// A strict detection expects that all imports live in a single WASI
 /// namespace. A non-strict detection expects that at least one WASI
 /// namespace exits to detect the version. Note that the strict
-/// detection is faster than the non-strict one.
+/// detection is faster than the non-strict one. Imports from the
+/// extensions, like `thread-spawn`, are allowed in both.
 pub fn get_wasi_version(module: &Module, strict: bool) -> Option<WasiVersion> {
     let mut imports = module.imports().filter_map(|extern_| match extern_.ty() {
-        ExternType::Function(_f) => Some(extern_.module().to_owned()),
+        ExternType::Function(_f) if !EXTENSION_NAMESPACES.contains(&extern_.module()) => {
+            Some(extern_.module().to_owned())
+        }
         _ => None,
     });
 
@@ -0,0 +1,176 @@
+use crate::utils::get_store;
+use anyhow::Result;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use wasmer::*;
+
+#[test]
+fn interrupt_infinite_loop() -> Result<()> {
+    let store = get_store(false);
+    let wat = r#"
+        (module
+            (func (export "run")
+                (loop $l
+                    (br $l)))
+        )
+    "#;
+
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let run = instance.exports.get_function("run")?;
+
+    let handle = instance.interrupt_handle();
+    let interrupter = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        handle.interrupt();
+    });
+
+    let e = run.call(&[]).unwrap_err();
+    interrupter.join().unwrap();
+    assert_eq!(e.to_trap(), Some(TrapCode::Interrupt));
+
+    Ok(())
+}
+
+#[test]
+fn interrupt_before_call() -> Result<()> {
+    let store = get_store(false);
+    let wat = r#"
+        (module
+            (func $add_one (param i32) (result i32)
+                (i32.add (local.get 0) (i32.const 1)))
+            (func (export "run") (param i32) (result i32)
+                (call $add_one (local.get 0)))
+        )
+    "#;
+
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let run = instance.exports.get_native_function::<i32, i32>("run")?;
+
+    // Nothing is running, so the interruption doesn't reach the next call.
+    store.interrupt_handle().interrupt();
+    assert_eq!(run.call(1)?, 2);
+
+    Ok(())
+}
+
+#[test]
+fn interrupt_from_host() -> Result<()> {
+    let store = get_store(false);
+    let wat = r#"
+        (module
+            (func $interrupt (import "host" "interrupt"))
+            (func $add_one (export "add_one") (param i32) (result i32)
+                (i32.add (local.get 0) (i32.const 1)))
+            (func (export "run") (param i32) (result i32)
+                (call $interrupt)
+                (call $add_one (local.get 0)))
+        )
+    "#;
+
+    let module = Module::new(&store, wat)?;
+    let handle = store.interrupt_handle();
+    let interrupt = Function::new(&store, FunctionType::new(vec![], vec![]), move |_| {
+        handle.interrupt();
+        Ok(vec![])
+    });
+    let instance = Instance::new(
+        &module,
+        &imports! {
+            "host" => {
+                "interrupt" => interrupt,
+            },
+        },
+    )?;
+    let run = instance.exports.get_native_function::<i32, i32>("run")?;
+    let add_one = instance
+        .exports
+        .get_native_function::<i32, i32>("add_one")?;
+
+    // The interruption stops the running call once the host function
+    // returns, and is dropped when that call ends.
+    let e = run.call(1).unwrap_err();
+    assert_eq!(e.to_trap(), Some(TrapCode::Interrupt));
+    assert_eq!(add_one.call(1)?, 2);
+
+    Ok(())
+}
+
+#[test]
+fn epoch_deadline_trap() -> Result<()> {
+    let store = get_store(false);
+    let wat = r#"
+        (module
+            (func (export "run")
+                (loop $l
+                    (br $l)))
+        )
+    "#;
+
+    let module = Module::new(&store, wat)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let run = instance.exports.get_function("run")?;
+
+    store.set_epoch_deadline(1);
+    let engine = store.engine().clone();
+    let ticker = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        engine.increment_epoch();
+    });
+
+    let e = run.call(&[]).unwrap_err();
+    ticker.join().unwrap();
+    assert_eq!(e.to_trap(), Some(TrapCode::Interrupt));
+
+    Ok(())
+}
+
+#[test]
+fn epoch_deadline_callback() -> Result<()> {
+    let store = get_store(false);
+    let wat = r#"
+        (module
+            (func $tick (import "host" "tick"))
+            (func (export "run")
+                (loop $l
+                    (call $tick)
+                    (br $l)))
+        )
+    "#;
+
+    let module = Module::new(&store, wat)?;
+    let engine = store.engine().clone();
+    let tick = Function::new(&store, FunctionType::new(vec![], vec![]), move |_| {
+        engine.increment_epoch();
+        Ok(vec![])
+    });
+    let instance = Instance::new(
+        &module,
+        &imports! {
+            "host" => {
+                "tick" => tick,
+            },
+        },
+    )?;
+    let run = instance.exports.get_function("run")?;
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let calls2 = calls.clone();
+    store.set_epoch_deadline(1);
+    store.epoch_deadline_callback(move || {
+        if calls2.fetch_add(1, Ordering::SeqCst) < 3 {
+            Ok(1)
+        } else {
+            Err(RuntimeError::new("out of time"))
+        }
+    });
+
+    let e = run.call(&[]).unwrap_err();
+    assert_eq!(e.message(), "out of time");
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+    Ok(())
+}
@@ -5,14 +5,21 @@
 //! on what's available on the target.
 
 mod imports;
+mod interrupts;
+mod memory_images;
 mod metering;
 mod middlewares;
 mod multi_value_imports;
 mod native_functions;
+mod pooling;
 mod serialize;
+mod stack_limit;
 mod traps;
 mod utils;
 mod wasi;
+mod wasi_snapshot;
+mod wasi_syscalls;
+mod wasi_threads;
 mod wast;
 
 pub use crate::utils::get_compiler;
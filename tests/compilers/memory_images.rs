@@ -0,0 +1,67 @@
+#![cfg(feature = "test-jit")]
+
+use crate::utils::get_compiler;
+use anyhow::Result;
+use wasmer::*;
+use wasmer_engine_jit::JIT;
+
+const WAT: &str = r#"
+(module
+  (import "env" "offset" (global $offset i32))
+  (memory (export "memory") 2)
+  (data (i32.const 16) "hello")
+  (data (i32.const 0x1_0000) "world")
+  (data (global.get $offset) "dynamic")
+  (data (i32.const 18) "LL")
+  (func (export "load8") (param i32) (result i32)
+    (i32.load8_u (local.get 0)))
+  (func (export "store8") (param i32 i32)
+    (i32.store8 (local.get 0) (local.get 1))))
+"#;
+
+fn instantiate(module: &Module, offset: i32) -> Result<Instance> {
+    let store = module.store();
+    Ok(Instance::new(
+        module,
+        &imports! {
+            "env" => {
+                "offset" => Global::new(store, Value::I32(offset)),
+            },
+        },
+    )?)
+}
+
+fn read(instance: &Instance, offset: i32, len: i32) -> Result<Vec<u8>> {
+    let load8 = instance.exports.get_native_function::<i32, i32>("load8")?;
+    (offset..offset + len)
+        .map(|i| Ok(load8.call(i)? as u8))
+        .collect()
+}
+
+#[test]
+fn memory_images_initialize_memories() -> Result<()> {
+    let engine = JIT::new(get_compiler(false)).memory_images(true).engine();
+    let store = Store::new(&engine);
+    let module = Module::new(&store, WAT)?;
+
+    let instance = instantiate(&module, 32)?;
+    let other = instantiate(&module, 0x1_0003)?;
+
+    // Segments after a dynamic one are still applied in order.
+    assert_eq!(read(&instance, 16, 5)?, b"heLLo");
+    assert_eq!(read(&instance, 32, 7)?, b"dynamic");
+    assert_eq!(read(&instance, 0x1_0000, 5)?, b"world");
+    assert_eq!(read(&other, 16, 5)?, b"heLLo");
+    assert_eq!(read(&other, 0x1_0000, 10)?, b"wordynamic");
+
+    // Writes to the memory of an instance are private to it.
+    let store8 = instance
+        .exports
+        .get_native_function::<(i32, i32), ()>("store8")?;
+    store8.call(16, b'j' as i32)?;
+    assert_eq!(read(&instance, 16, 5)?, b"jeLLo");
+    assert_eq!(read(&other, 16, 5)?, b"heLLo");
+    assert_eq!(read(&instantiate(&module, 32)?, 16, 5)?, b"heLLo");
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use crate::utils::get_engine;
+use anyhow::Result;
+use wasmer::*;
+
+fn get_pooling_store(max_instances: usize) -> Result<(Store, PoolingAllocator)> {
+    let tunables = PoolingTunables::new(PoolingLimits {
+        max_instances,
+        max_memory_pages: Pages(2),
+        max_table_elements: 10,
+        ..Default::default()
+    })
+    .map_err(anyhow::Error::msg)?;
+    let allocator = tunables.allocator().clone();
+    Ok((
+        Store::new_with_tunables(&get_engine(false), tunables),
+        allocator,
+    ))
+}
+
+const WAT: &str = r#"
+(module
+  (memory (export "memory") 1 2)
+  (table 2 funcref)
+  (elem (i32.const 0) $load)
+  (func $load (export "load") (param i32) (result i32)
+    (i32.load (local.get 0)))
+  (func (export "store") (param i32 i32)
+    (i32.store (local.get 0) (local.get 1)))
+  (func (export "grow") (param i32) (result i32)
+    (memory.grow (local.get 0)))
+  (func (export "load_indirect") (param i32) (result i32)
+    (call_indirect (param i32) (result i32) (local.get 0) (i32.const 0))))
+"#;
+
+#[test]
+fn pooled_instances_are_reused() -> Result<()> {
+    let (store, allocator) = get_pooling_store(2)?;
+    let module = Module::new(&store, WAT)?;
+
+    let instance = Instance::new(&module, &imports! {})?;
+    let other = Instance::new(&module, &imports! {})?;
+    assert_eq!(allocator.instances_in_use(), 2);
+    assert_eq!(allocator.memories_in_use(), 2);
+    assert_eq!(allocator.tables_in_use(), 2);
+
+    // The pool is exhausted.
+    match Instance::new(&module, &imports! {}) {
+        Err(InstantiationError::Link(LinkError::Resource(message))) => {
+            assert!(message.contains("exhausted"), "{}", message)
+        }
+        result => panic!("unexpected result: {:?}", result.map(|_| ())),
+    }
+
+    let store_fn = instance
+        .exports
+        .get_native_function::<(i32, i32), ()>("store")?;
+    let grow = instance.exports.get_native_function::<i32, i32>("grow")?;
+    assert_eq!(grow.call(1)?, 1);
+    assert_eq!(grow.call(1)?, -1);
+    store_fn.call(0x1_0000, 42)?;
+    let load = instance
+        .exports
+        .get_native_function::<i32, i32>("load_indirect")?;
+    assert_eq!(load.call(0x1_0000)?, 42);
+
+    drop((store_fn, grow, load));
+    drop(instance);
+    drop(other);
+    assert_eq!(allocator.instances_in_use(), 0);
+    assert_eq!(allocator.memories_in_use(), 0);
+    assert_eq!(allocator.tables_in_use(), 0);
+
+    // Reused slots start out zeroed.
+    let instance = Instance::new(&module, &imports! {})?;
+    let grow = instance.exports.get_native_function::<i32, i32>("grow")?;
+    let load = instance.exports.get_native_function::<i32, i32>("load")?;
+    assert_eq!(grow.call(1)?, 1);
+    assert_eq!(load.call(0x1_0000)?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn pooled_memory_out_of_bounds() -> Result<()> {
+    let (store, _allocator) = get_pooling_store(1)?;
+    let module = Module::new(&store, WAT)?;
+    let instance = Instance::new(&module, &imports! {})?;
+    let load = instance.exports.get_native_function::<i32, i32>("load")?;
+
+    assert!(load.call(0x1_0000).is_err());
+    assert!(load.call(0x2_0000).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn module_exceeding_pool_limits() -> Result<()> {
+    let (store, _allocator) = get_pooling_store(1)?;
+    let module = Module::new(&store, r#"(module (table 11 funcref))"#)?;
+
+    assert!(Instance::new(&module, &imports! {}).is_err());
+
+    Ok(())
+}
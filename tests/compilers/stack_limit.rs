@@ -0,0 +1,139 @@
+use crate::utils::get_store_with_middlewares;
+use anyhow::Result;
+use wasmer_middlewares::stack_limit::{get_stack_depth, set_stack_depth};
+use wasmer_middlewares::{Metering, StackLimit};
+
+use std::sync::{Arc, Mutex};
+use wasmer::wasmparser::Operator;
+use wasmer::*;
+
+const WAT: &str = r#"(module
+    (func $depth (export "depth") (param i32) (result i32)
+        (local i64)
+        (drop (block $done (result i32)
+            (br_table $done 1
+                (i32.const 0)
+                (i32.lt_s (local.get 0) (i32.const 0)))))
+        (if (result i32) (i32.eqz (local.get 0))
+            (then (i32.const 1))
+            (else
+                (i32.add
+                    (i32.const 1)
+                    (call $depth (i32.sub (local.get 0) (i32.const 1))))))))"#;
+
+fn instance(middlewares: Vec<Arc<dyn ModuleMiddleware>>) -> Result<Instance> {
+    let store = get_store_with_middlewares(middlewares.into_iter());
+    let module = Module::new(&store, WAT)?;
+    Ok(Instance::new(&module, &imports! {})?)
+}
+
+/// Calls `depth` with larger and larger arguments, until it traps.
+fn deepest_call(instance: &Instance) -> Result<i32> {
+    let depth: NativeFunc<i32, i32> = instance.exports.get_native_function("depth")?;
+    for n in 0.. {
+        match depth.call(n) {
+            Ok(_) => assert_eq!(get_stack_depth(instance), 0),
+            Err(e) => {
+                assert_eq!(e.to_trap(), Some(TrapCode::StackLimitExceeded));
+                assert_eq!(get_stack_depth(instance), 0);
+                return Ok(n);
+            }
+        }
+    }
+    unreachable!()
+}
+
+#[test]
+fn stack_limit_is_deterministic() -> Result<()> {
+    // A frame of `depth` weighs 1, plus 1 parameter and 1 local, plus 3 operands.
+    let instance = instance(vec![Arc::new(StackLimit::new(6 * 100))])?;
+    assert_eq!(deepest_call(&instance)?, 100);
+
+    // The frame is released when `br_table` leaves the function early.
+    let depth: NativeFunc<i32, i32> = instance.exports.get_native_function("depth")?;
+    depth.call(-1)?;
+    assert_eq!(get_stack_depth(&instance), 0);
+    Ok(())
+}
+
+#[test]
+fn stack_limit_trap_goes_through_other_middlewares() -> Result<()> {
+    let instance = instance(vec![
+        Arc::new(StackLimit::new(6 * 10)),
+        Arc::new(Metering::new(u64::MAX, |_: &Operator| 1)),
+    ])?;
+    assert_eq!(deepest_call(&instance)?, 10);
+    Ok(())
+}
+
+/// `depth` again, with `outer` calling it through the host.
+const REENTRANT_WAT: &str = r#"(module
+    (import "env" "reenter" (func $reenter (param i32) (result i32)))
+    (func $depth (export "depth") (param i32) (result i32)
+        (local i64)
+        (drop (block $done (result i32)
+            (br_table $done 1
+                (i32.const 0)
+                (i32.lt_s (local.get 0) (i32.const 0)))))
+        (if (result i32) (i32.eqz (local.get 0))
+            (then (i32.const 1))
+            (else
+                (i32.add
+                    (i32.const 1)
+                    (call $depth (i32.sub (local.get 0) (i32.const 1)))))))
+    (func (export "outer") (param i32) (result i32)
+        (call $reenter (local.get 0))))"#;
+
+#[test]
+fn stack_limit_with_host_reentry() -> Result<()> {
+    let store = get_store_with_middlewares(std::iter::once(
+        Arc::new(StackLimit::new(6 * 100)) as Arc<dyn ModuleMiddleware>
+    ));
+    let module = Module::new(&store, REENTRANT_WAT)?;
+
+    // `reenter` calls `depth`, and returns -1 if it traps after restoring
+    // the depth of the frame of `outer`.
+    let shared: Arc<Mutex<Option<Instance>>> = Arc::new(Mutex::new(None));
+    let reenter = {
+        let shared = shared.clone();
+        Function::new(
+            &store,
+            FunctionType::new(vec![Type::I32], vec![Type::I32]),
+            move |args| {
+                let instance = shared.lock().unwrap().clone().unwrap();
+                let depth: NativeFunc<i32, i32> = instance
+                    .exports
+                    .get_native_function("depth")
+                    .map_err(|e| RuntimeError::new(e.to_string()))?;
+                let saved = get_stack_depth(&instance);
+                let result = match depth.call(args[0].unwrap_i32()) {
+                    Ok(result) => result,
+                    Err(e) => {
+                        assert_eq!(e.to_trap(), Some(TrapCode::StackLimitExceeded));
+                        set_stack_depth(&instance, saved);
+                        -1
+                    }
+                };
+                Ok(vec![Value::I32(result)])
+            },
+        )
+    };
+    let instance = Instance::new(&module, &imports! { "env" => { "reenter" => reenter } })?;
+    *shared.lock().unwrap() = Some(instance.clone());
+    let outer: NativeFunc<i32, i32> = instance.exports.get_native_function("outer")?;
+
+    // A frame of `outer` weighs 1, plus 1 parameter, plus 1 operand, so
+    // one frame of `depth` less fits under it.
+    assert_eq!(outer.call(98)?, 99);
+    assert_eq!(get_stack_depth(&instance), 0);
+    assert_eq!(outer.call(99)?, -1);
+    assert_eq!(get_stack_depth(&instance), 0);
+
+    // The frame of `outer` was released, so the whole limit is available.
+    assert_eq!(deepest_call(&instance)?, 100);
+    assert_eq!(outer.call(98)?, 99);
+
+    // `reenter` holds the instance, which holds `reenter`
+    shared.lock().unwrap().take();
+    Ok(())
+}
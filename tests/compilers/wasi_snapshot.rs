@@ -0,0 +1,89 @@
+#![cfg(feature = "wasi")]
+
+//! Take a snapshot of a WASI instance and restore it into a fresh one.
+
+use crate::utils::get_store;
+use anyhow::Result;
+use wasmer::*;
+use wasmer_wasi::{WasiEnv, WasiSnapshotError, WasiState};
+
+/// Counts the calls to `count` in a global that isn't exported, and keeps
+/// the count in memory too.
+const COUNTER: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "sched_yield" (func (result i32)))
+  (memory (export "memory") 1)
+  (global $count (mut i32) (i32.const 0))
+  (func (export "count") (result i32)
+    (global.set $count (i32.add (global.get $count) (i32.const 1)))
+    (i32.store (i32.const 100) (global.get $count))
+    (global.get $count)))
+"#;
+
+fn instantiate(module: &Module, wasi_env: &mut WasiEnv) -> Result<Instance> {
+    let import_object = wasi_env.import_object(module)?;
+    Ok(Instance::new(module, &import_object)?)
+}
+
+#[test]
+fn snapshot_restores_memory_globals_and_state() -> Result<()> {
+    let store = get_store(false);
+    let module = Module::new(&store, COUNTER)?;
+    let mut wasi_env = WasiState::new("counter").arg("--verbose").finalize()?;
+    let instance = instantiate(&module, &mut wasi_env)?;
+    let count = instance.exports.get_native_function::<(), i32>("count")?;
+    for _ in 0..3 {
+        count.call()?;
+    }
+    let snapshot = wasi_env.snapshot(&instance)?;
+
+    let mut fresh_env = WasiState::new("other").finalize()?;
+    let fresh = instantiate(&module, &mut fresh_env)?;
+    fresh_env.restore(&fresh, &snapshot)?;
+    let count = fresh.exports.get_native_function::<(), i32>("count")?;
+    assert_eq!(count.call()?, 4);
+    let memory = fresh.exports.get_memory("memory")?;
+    assert_eq!(memory.view::<u32>()[25].get(), 4);
+    assert_eq!(
+        fresh_env.state().args,
+        vec![b"counter".to_vec(), b"--verbose".to_vec()]
+    );
+    Ok(())
+}
+
+#[test]
+fn snapshot_needs_the_same_module() -> Result<()> {
+    let store = get_store(false);
+    let module = Module::new(&store, COUNTER)?;
+    let mut wasi_env = WasiState::new("counter").finalize()?;
+    let instance = instantiate(&module, &mut wasi_env)?;
+    let snapshot = wasi_env.snapshot(&instance)?;
+
+    let other = Module::new(
+        &store,
+        r#"(module
+             (import "wasi_snapshot_preview1" "sched_yield" (func (result i32)))
+             (memory 1))"#,
+    )?;
+    let mut other_env = WasiState::new("other").finalize()?;
+    let other_instance = instantiate(&other, &mut other_env)?;
+    assert!(matches!(
+        other_env.restore(&other_instance, &snapshot),
+        Err(WasiSnapshotError::ModuleMismatch(_))
+    ));
+
+    // Same memories and globals, but another export.
+    let renamed = Module::new(&store, COUNTER.replace("\"count\"", "\"tick\""))?;
+    let renamed_instance = instantiate(&renamed, &mut other_env)?;
+    assert!(matches!(
+        other_env.restore(&renamed_instance, &snapshot),
+        Err(WasiSnapshotError::ModuleMismatch(_))
+    ));
+
+    assert!(matches!(
+        other_env.restore(&other_instance, b"not a snapshot"),
+        Err(WasiSnapshotError::NotASnapshot)
+    ));
+    assert_eq!(other_env.state().args, vec![b"other".to_vec()]);
+    Ok(())
+}
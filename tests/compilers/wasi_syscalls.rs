@@ -0,0 +1,789 @@
+#![cfg(feature = "wasi")]
+
+//! Drive every WASI syscall with hostile arguments and check that the
+//! host never panics: every failure must surface as an errno.
+
+use crate::utils::get_store;
+use anyhow::Result;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use wasmer::*;
+use wasmer_wasi::types::{
+    __wasi_signal_t, __WASI_CLOCK_MONOTONIC, __WASI_CLOCK_PROCESS_CPUTIME_ID, __WASI_EBADF,
+    __WASI_EDQUOT, __WASI_EFBIG, __WASI_EINVAL, __WASI_EISDIR, __WASI_ELOOP, __WASI_ENOENT,
+    __WASI_ENOTSUP, __WASI_ESUCCESS, __WASI_EVENTTYPE_CLOCK, __WASI_EVENTTYPE_FD_READ,
+    __WASI_EVENTTYPE_FD_WRITE, __WASI_LOOKUP_SYMLINK_FOLLOW, __WASI_O_CREAT, __WASI_SIGCHLD,
+    __WASI_SIGKILL, __WASI_SIGTERM, __WASI_SIGUSR1, __WASI_SUBSCRIPTION_CLOCK_ABSTIME,
+    __WASI_WHENCE_END, __WASI_WHENCE_SET,
+};
+use wasmer_wasi::{
+    generate_import_object_from_env, Fd, FileSystem, MemFileSystem, OpenOptions, Pipe, SyscallArg,
+    SyscallEvent, SyscallObserver, WasiEnv, WasiError, WasiSignalHandler, WasiState, WasiVersion,
+    ALL_RIGHTS,
+};
+
+const NAMESPACE: &str = "wasi_snapshot_preview1";
+
+/// Functions that are skipped when calling with random arguments:
+/// `proc_exit` and `proc_raise` unwind on purpose and `poll_oneoff` may
+/// block for as long as a random clock subscription asks it to.
+const SKIPPED: &[&str] = &["proc_exit", "proc_raise", "poll_oneoff"];
+
+/// Interesting values for pointers, lengths and file descriptors.
+const HOSTILE: &[u32] = &[0, 1, 2, 3, 4, 5, 0x7fff_ffff, u32::MAX, 65532, 65535, 65536];
+
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u32 {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (self.0 >> 32) as u32
+    }
+
+    fn hostile(&mut self) -> u32 {
+        let pick = self.next() as usize % (HOSTILE.len() + 1);
+        match HOSTILE.get(pick) {
+            Some(value) => *value,
+            None => self.next(),
+        }
+    }
+}
+
+fn wasi_env() -> Result<WasiEnv> {
+    Ok(WasiState::new("hostile")
+        .stdin(Box::new(Pipe::new()))
+        .stdout(Box::new(Pipe::new()))
+        .stderr(Box::new(Pipe::new()))
+        .mount("mem", MemFileSystem::new())?
+        .finalize()?)
+}
+
+/// Build a module that imports every WASI syscall and re-exports it, so
+/// the syscalls can be called directly with arbitrary arguments.
+fn get_instance(store: &Store, wasi_env: &mut WasiEnv) -> Result<(Instance, Vec<String>)> {
+    let imports = generate_import_object_from_env(store, wasi_env.clone(), WasiVersion::Snapshot1);
+    let mut functions = imports
+        .into_iter()
+        .filter_map(|((namespace, name), export)| match export {
+            Export::Function(f) if namespace == NAMESPACE => Some((name, f.vm_function.signature)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    functions.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut wat = String::from("(module\n");
+    for (index, (name, signature)) in functions.iter().enumerate() {
+        write!(wat, "  (import \"{}\" \"{}\" (func", NAMESPACE, name)?;
+        for param in signature.params() {
+            write!(wat, " (param {})", param.to_string().to_lowercase())?;
+        }
+        for result in signature.results() {
+            write!(wat, " (result {})", result.to_string().to_lowercase())?;
+        }
+        writeln!(wat, "))\n  (export \"{}\" (func {}))", name, index)?;
+    }
+    wat.push_str("  (memory (export \"memory\") 1))");
+
+    let module = Module::new(store, &wat)?;
+    let import_object = wasi_env.import_object(&module)?;
+    let instance = Instance::new(&module, &import_object)?;
+    let names = functions.into_iter().map(|(name, _)| name).collect();
+    Ok((instance, names))
+}
+
+/// A WASI environment with `dir` of the host preopened as `host`, at fd 4.
+fn host_env(dir: &std::path::Path) -> Result<WasiEnv> {
+    Ok(WasiState::new("host").map_dir("host", dir)?.finalize()?)
+}
+
+fn store_bytes(memory: &Memory, offset: usize, bytes: &[u8]) {
+    for (cell, byte) in memory.view::<u8>()[offset..].iter().zip(bytes) {
+        cell.set(*byte);
+    }
+}
+
+fn load_bytes(memory: &Memory, offset: usize, len: usize) -> Vec<u8> {
+    memory.view::<u8>()[offset..offset + len]
+        .iter()
+        .map(|cell| cell.get())
+        .collect()
+}
+
+fn call(instance: &Instance, name: &str, args: &[u32]) -> Result<()> {
+    let function = instance.exports.get_function(name)?;
+    let params = function
+        .ty()
+        .params()
+        .iter()
+        .zip(args.iter().chain(std::iter::repeat(&0)))
+        .map(|(ty, arg)| match ty {
+            Type::I64 => Value::I64(*arg as i32 as i64),
+            _ => Value::I32(*arg as i32),
+        })
+        .collect::<Vec<_>>();
+    function.call(&params)?;
+    Ok(())
+}
+
+#[test]
+fn syscalls_survive_hostile_arguments() -> Result<()> {
+    let store = get_store(false);
+    let mut wasi_env = wasi_env()?;
+    let (instance, names) = get_instance(&store, &mut wasi_env)?;
+    let memory = instance.exports.get_memory("memory")?;
+    let mut rng = Rng(0x5eed);
+
+    for _ in 0..200 {
+        for cell in memory.view::<u8>().iter() {
+            cell.set(rng.next() as u8);
+        }
+        for name in names.iter().filter(|n| !SKIPPED.contains(&n.as_str())) {
+            let args = (0..8).map(|_| rng.hostile()).collect::<Vec<_>>();
+            call(&instance, name, &args)
+                .map_err(|e| anyhow::anyhow!("`{}{:?}` failed: {}", name, args, e))?;
+        }
+    }
+
+    // With zeroed memory every subscription is a relative clock timeout
+    // of zero, so `poll_oneoff` returns straight away.
+    for cell in memory.view::<u8>().iter() {
+        cell.set(0);
+    }
+    for _ in 0..200 {
+        let args = (0..4).map(|_| rng.hostile()).collect::<Vec<_>>();
+        call(&instance, "poll_oneoff", &args)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn read_from_renumbered_preopen() -> Result<()> {
+    let store = get_store(false);
+    let mut wasi_env = wasi_env()?;
+    let (instance, _) = get_instance(&store, &mut wasi_env)?;
+    let renumber = instance
+        .exports
+        .get_native_function::<(i32, i32), i32>("fd_renumber")?;
+    let read = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32), i32>("fd_read")?;
+
+    assert_eq!(renumber.call(4, 0)?, 0);
+    // The preopened directory now lives at fd 0, which cannot be read.
+    assert_ne!(read.call(0, 0, 1, 16)?, 0);
+    // Renumbering an fd onto itself is a no-op.
+    assert_eq!(renumber.call(0, 0)?, 0);
+    Ok(())
+}
+
+#[test]
+fn prestat_dir_name_without_room_for_nul() -> Result<()> {
+    let store = get_store(false);
+    let mut wasi_env = wasi_env()?;
+    let (instance, _) = get_instance(&store, &mut wasi_env)?;
+    let memory = instance.exports.get_memory("memory")?;
+    let dir_name = instance
+        .exports
+        .get_native_function::<(i32, i32, i32), i32>("fd_prestat_dir_name")?;
+
+    let end = memory.size().bytes().0 as i32;
+    assert_eq!(dir_name.call(4, end - 3, 3)?, 0);
+    let view = memory.view::<u8>();
+    let name = view[end as usize - 3..]
+        .iter()
+        .map(|c| c.get())
+        .collect::<Vec<_>>();
+    assert_eq!(name, b"mem");
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct Recorder(Mutex<Vec<SyscallEvent>>);
+
+impl SyscallObserver for Recorder {
+    fn on_syscall(&self, event: &SyscallEvent) {
+        self.0.lock().unwrap().push(event.clone());
+    }
+}
+
+#[test]
+fn observer_sees_decoded_syscalls() -> Result<()> {
+    let store = get_store(false);
+    let mut wasi_env = wasi_env()?;
+    let recorder = Arc::new(Recorder::default());
+    wasi_env.set_syscall_observer(recorder.clone());
+    let (instance, _) = get_instance(&store, &mut wasi_env)?;
+    let memory = instance.exports.get_memory("memory")?;
+    let path_open = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32, i32, i64, i64, i32, i32), i32>("path_open")?;
+
+    for (cell, byte) in memory.view::<u8>().iter().zip(b"missing") {
+        cell.set(*byte);
+    }
+    let errno = path_open.call(4, 0, 0, 7, 0, 0, 0, 0, 64)?;
+    assert_ne!(errno, 0);
+
+    let events = recorder.0.lock().unwrap();
+    assert_eq!(events.len(), 1);
+    let event = &events[0];
+    assert_eq!(event.name, "path_open");
+    assert_eq!(event.result, Some(errno as u16));
+    assert_eq!(event.args[0], SyscallArg::Fd(4));
+    assert_eq!(event.args[2], SyscallArg::Path("missing".to_string()));
+    assert_eq!(event.args[8], SyscallArg::Ptr(64));
+    Ok(())
+}
+
+#[test]
+fn quotas_limit_writes_and_created_files() -> Result<()> {
+    let store = get_store(false);
+    let mut wasi_env = WasiState::new("quota")
+        .mount("mem", MemFileSystem::new())?
+        .max_bytes_written(8)
+        .max_entries_created(1)
+        .finalize()?;
+    let (instance, _) = get_instance(&store, &mut wasi_env)?;
+    let memory = instance.exports.get_memory("memory")?;
+    let path_open = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32, i32, i64, i64, i32, i32), i32>("path_open")?;
+    let write = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32), i32>("fd_write")?;
+
+    // the path "a" at 0, one iovec of 5 bytes at 16, the fd at 64
+    let view = memory.view::<u8>();
+    view[0].set(b'a');
+    view[16].set(32);
+    view[20].set(5);
+    let open = |name: u8| {
+        memory.view::<u8>()[0].set(name);
+        path_open.call(
+            4,
+            0,
+            0,
+            1,
+            __WASI_O_CREAT as i32,
+            ALL_RIGHTS as i64,
+            ALL_RIGHTS as i64,
+            0,
+            64,
+        )
+    };
+
+    assert_eq!(open(b'a')?, __WASI_ESUCCESS as i32);
+    let fd = memory.view::<u32>()[16].get() as i32;
+    assert_eq!(write.call(fd, 16, 1, 68)?, __WASI_ESUCCESS as i32);
+    assert_eq!(write.call(fd, 16, 1, 68)?, __WASI_EDQUOT as i32);
+    assert_eq!(open(b'b')?, __WASI_EDQUOT as i32);
+
+    let usage = wasi_env.resource_usage();
+    assert_eq!(usage.bytes_written.get("mem"), Some(&5));
+    assert_eq!(usage.entries_created, 1);
+    Ok(())
+}
+
+#[test]
+fn mem_files_cannot_grow_unbounded() -> Result<()> {
+    let store = get_store(false);
+    let mut wasi_env = wasi_env()?;
+    let (instance, _) = get_instance(&store, &mut wasi_env)?;
+    let memory = instance.exports.get_memory("memory")?;
+    let path_open = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32, i32, i64, i64, i32, i32), i32>("path_open")?;
+    let set_size = instance
+        .exports
+        .get_native_function::<(i32, i64), i32>("fd_filestat_set_size")?;
+    let seek = instance
+        .exports
+        .get_native_function::<(i32, i64, i32, i32), i32>("fd_seek")?;
+    let write = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32), i32>("fd_write")?;
+
+    // the path "big" at 0, one iovec of 1 byte at 16, the fd at 64
+    store_bytes(memory, 0, b"big");
+    memory.view::<u32>()[4].set(32);
+    memory.view::<u32>()[5].set(1);
+    assert_eq!(
+        path_open.call(
+            4,
+            0,
+            0,
+            3,
+            __WASI_O_CREAT as i32,
+            ALL_RIGHTS as i64,
+            ALL_RIGHTS as i64,
+            0,
+            64,
+        )?,
+        __WASI_ESUCCESS as i32
+    );
+    let fd = memory.view::<u32>()[16].get() as i32;
+
+    assert_eq!(set_size.call(fd, 1 << 62)?, __WASI_EFBIG as i32);
+    assert_eq!(
+        seek.call(fd, 1 << 40, __WASI_WHENCE_SET as i32, 72)?,
+        __WASI_ESUCCESS as i32
+    );
+    assert_eq!(write.call(fd, 16, 1, 80)?, __WASI_EFBIG as i32);
+
+    // the file is still usable within the limit
+    assert_eq!(set_size.call(fd, 4)?, __WASI_ESUCCESS as i32);
+    assert_eq!(
+        seek.call(fd, 0, __WASI_WHENCE_SET as i32, 72)?,
+        __WASI_ESUCCESS as i32
+    );
+    assert_eq!(write.call(fd, 16, 1, 80)?, __WASI_ESUCCESS as i32);
+    Ok(())
+}
+
+#[derive(Debug)]
+struct SignalRecorder(Arc<Mutex<Vec<__wasi_signal_t>>>);
+
+impl WasiSignalHandler for SignalRecorder {
+    fn handle(&self, signal: __wasi_signal_t) {
+        self.0.lock().unwrap().push(signal);
+    }
+}
+
+#[test]
+fn raise_handled_ignored_and_fatal_signals() -> Result<()> {
+    let store = get_store(false);
+    let handled = Arc::new(Mutex::new(vec![]));
+    let mut wasi_env = WasiState::new("signals")
+        .signal_handler(__WASI_SIGUSR1, Box::new(SignalRecorder(handled.clone())))
+        .signal_handler(__WASI_SIGKILL, Box::new(SignalRecorder(handled.clone())))
+        .finalize()?;
+    let (instance, _) = get_instance(&store, &mut wasi_env)?;
+    let raise = instance
+        .exports
+        .get_native_function::<i32, i32>("proc_raise")?;
+
+    assert_eq!(raise.call(__WASI_SIGUSR1 as i32)?, __WASI_ESUCCESS as i32);
+    assert_eq!(raise.call(__WASI_SIGCHLD as i32)?, __WASI_ESUCCESS as i32);
+    assert_eq!(raise.call(0)?, __WASI_EINVAL as i32);
+    assert_eq!(*handled.lock().unwrap(), vec![__WASI_SIGUSR1]);
+
+    for signal in &[__WASI_SIGTERM, __WASI_SIGKILL] {
+        let err = raise.call(*signal as i32).unwrap_err();
+        match err.downcast::<WasiError>() {
+            Ok(WasiError::Signal(raised)) => assert_eq!(raised, *signal),
+            other => panic!("expected a signal, got {:?}", other),
+        }
+    }
+    assert_eq!(handled.lock().unwrap().len(), 1);
+    Ok(())
+}
+
+#[test]
+fn symlink_into_host_preopen() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    std::fs::create_dir(dir.path().join("sub"))?;
+    std::fs::write(dir.path().join("data.txt"), b"hello world")?;
+    let store = get_store(false);
+    let mut wasi_env = host_env(dir.path())?;
+    let (instance, _) = get_instance(&store, &mut wasi_env)?;
+    let memory = instance.exports.get_memory("memory")?;
+    let symlink = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32, i32), i32>("path_symlink")?;
+    let path_open = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32, i32, i64, i64, i32, i32), i32>("path_open")?;
+    let read = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32), i32>("fd_read")?;
+    let write = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32), i32>("fd_write")?;
+    let seek = instance
+        .exports
+        .get_native_function::<(i32, i64, i32, i32), i32>("fd_seek")?;
+    let close = instance
+        .exports
+        .get_native_function::<i32, i32>("fd_close")?;
+
+    // the target at 0, the link at 16, one iovec of 5 bytes at 32 pointing to 64, the
+    // results at 128
+    store_bytes(memory, 0, b"data.txt");
+    store_bytes(memory, 16, b"sub/link");
+    memory.view::<u32>()[8].set(64);
+    memory.view::<u32>()[9].set(5);
+    let open = |dirflags: u32| {
+        path_open.call(
+            4,
+            dirflags as i32,
+            16,
+            8,
+            0,
+            ALL_RIGHTS as i64,
+            ALL_RIGHTS as i64,
+            0,
+            128,
+        )
+    };
+
+    assert_eq!(symlink.call(0, 8, 4, 16, 8)?, __WASI_ESUCCESS as i32);
+    assert_eq!(open(0)?, __WASI_ELOOP as i32);
+    assert_eq!(open(__WASI_LOOKUP_SYMLINK_FOLLOW)?, __WASI_ESUCCESS as i32);
+    let fd = memory.view::<u32>()[32].get() as i32;
+
+    store_bytes(memory, 64, b"HELLO");
+    assert_eq!(write.call(fd, 32, 1, 136)?, __WASI_ESUCCESS as i32);
+    assert_eq!(memory.view::<u32>()[34].get(), 5);
+    assert_eq!(
+        seek.call(fd, 6, __WASI_WHENCE_SET as i32, 144)?,
+        __WASI_ESUCCESS as i32
+    );
+    assert_eq!(read.call(fd, 32, 1, 136)?, __WASI_ESUCCESS as i32);
+    assert_eq!(load_bytes(memory, 64, 5), b"world");
+    assert_eq!(
+        seek.call(fd, 0, __WASI_WHENCE_END as i32, 144)?,
+        __WASI_ESUCCESS as i32
+    );
+    assert_eq!(memory.view::<u64>()[18].get(), 11);
+    assert_eq!(close.call(fd)?, __WASI_ESUCCESS as i32);
+
+    assert_eq!(std::fs::read(dir.path().join("data.txt"))?, b"HELLO world");
+    Ok(())
+}
+
+#[test]
+fn rename_host_directory() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    std::fs::create_dir(dir.path().join("a"))?;
+    std::fs::write(dir.path().join("a").join("inner.txt"), b"inner")?;
+    let store = get_store(false);
+    let mut wasi_env = host_env(dir.path())?;
+    let (instance, _) = get_instance(&store, &mut wasi_env)?;
+    let memory = instance.exports.get_memory("memory")?;
+    let filestat = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32, i32), i32>("path_filestat_get")?;
+    let rename = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32, i32, i32), i32>("path_rename")?;
+    let path_open = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32, i32, i64, i64, i32, i32), i32>("path_open")?;
+    let read = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32), i32>("fd_read")?;
+    let close = instance
+        .exports
+        .get_native_function::<i32, i32>("fd_close")?;
+
+    // paths at 0 and 16, one iovec of 5 bytes at 32 pointing to 64, the results at 128
+    store_bytes(memory, 0, b"a/inner.txt");
+    memory.view::<u32>()[8].set(64);
+    memory.view::<u32>()[9].set(5);
+    let open = |path_len: i32| {
+        path_open.call(
+            4,
+            0,
+            0,
+            path_len,
+            0,
+            ALL_RIGHTS as i64,
+            ALL_RIGHTS as i64,
+            0,
+            128,
+        )
+    };
+
+    // load the directory and the file in it before moving them
+    assert_eq!(filestat.call(4, 0, 0, 11, 128)?, __WASI_ESUCCESS as i32);
+    store_bytes(memory, 16, b"b");
+    assert_eq!(rename.call(4, 0, 1, 4, 16, 1)?, __WASI_ESUCCESS as i32);
+    assert!(!dir.path().join("a").exists());
+    assert!(dir.path().join("b").join("inner.txt").exists());
+
+    assert_eq!(open(11)?, __WASI_ENOENT as i32);
+    store_bytes(memory, 0, b"b/inner.txt");
+    assert_eq!(open(11)?, __WASI_ESUCCESS as i32);
+    let fd = memory.view::<u32>()[32].get() as i32;
+    assert_eq!(read.call(fd, 32, 1, 136)?, __WASI_ESUCCESS as i32);
+    assert_eq!(load_bytes(memory, 64, 5), b"inner");
+    assert_eq!(close.call(fd)?, __WASI_ESUCCESS as i32);
+    Ok(())
+}
+
+#[test]
+fn unlink_buffer_backed_file() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let store = get_store(false);
+    let mut wasi_env = host_env(dir.path())?;
+    let (instance, _) = get_instance(&store, &mut wasi_env)?;
+    let memory = instance.exports.get_memory("memory")?;
+    let rename = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32, i32, i32), i32>("path_rename")?;
+    let unlink = instance
+        .exports
+        .get_native_function::<(i32, i32, i32), i32>("path_unlink_file")?;
+    let path_open = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32, i32, i64, i64, i32, i32), i32>("path_open")?;
+    let write = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32), i32>("fd_write")?;
+
+    // a file in the preopened directory that only lives in memory
+    let fd = wasi_env.state().fs.open_file_at(
+        4,
+        Box::new(Pipe::new()),
+        Fd::READ | Fd::WRITE,
+        "buffer".to_string(),
+        ALL_RIGHTS,
+        ALL_RIGHTS,
+        0,
+    )? as i32;
+
+    // paths at 0 and 16, one iovec of 5 bytes at 32 pointing to 64, the results at 128
+    store_bytes(memory, 0, b"buffer");
+    store_bytes(memory, 16, b"renamed");
+    memory.view::<u32>()[8].set(64);
+    memory.view::<u32>()[9].set(5);
+
+    // the file has nowhere to move to
+    assert_eq!(rename.call(4, 0, 6, 4, 16, 7)?, __WASI_ENOTSUP as i32);
+    assert_eq!(unlink.call(4, 0, 6)?, __WASI_ESUCCESS as i32);
+    assert!(!dir.path().join("buffer").exists());
+    assert_eq!(
+        path_open.call(4, 0, 0, 6, 0, ALL_RIGHTS as i64, ALL_RIGHTS as i64, 0, 128)?,
+        __WASI_ENOENT as i32
+    );
+    // the open fd keeps the file alive
+    assert_eq!(write.call(fd, 32, 1, 128)?, __WASI_ESUCCESS as i32);
+    Ok(())
+}
+
+const HOUR: u64 = 3_600_000_000_000;
+
+/// A subscription of `poll_oneoff`: a clock id, a timeout and flags for
+/// `__WASI_EVENTTYPE_CLOCK`, or an fd for the others.
+#[derive(Clone, Copy)]
+struct Subscription {
+    userdata: u64,
+    tag: u8,
+    clock_or_fd: u32,
+    timeout: u64,
+    flags: u16,
+}
+
+fn clock(userdata: u64, clock_id: u32, timeout: u64, flags: u16) -> Subscription {
+    Subscription {
+        userdata,
+        tag: __WASI_EVENTTYPE_CLOCK,
+        clock_or_fd: clock_id,
+        timeout,
+        flags,
+    }
+}
+
+fn fd_event(userdata: u64, tag: u8, fd: u32) -> Subscription {
+    Subscription {
+        userdata,
+        tag,
+        clock_or_fd: fd,
+        timeout: 0,
+        flags: 0,
+    }
+}
+
+/// Calls `poll_oneoff` with the subscriptions at 0, and returns the
+/// userdata, error and number of bytes of the events it wrote at 1024.
+fn poll(instance: &Instance, subscriptions: &[Subscription]) -> Result<Vec<(u64, u16, u64)>> {
+    let memory = instance.exports.get_memory("memory")?;
+    let poll_oneoff = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32), i32>("poll_oneoff")?;
+    for (index, s) in subscriptions.iter().enumerate() {
+        let offset = index * 48;
+        store_bytes(memory, offset, &[0; 48]);
+        store_bytes(memory, offset, &s.userdata.to_le_bytes());
+        store_bytes(memory, offset + 8, &[s.tag]);
+        store_bytes(memory, offset + 16, &s.clock_or_fd.to_le_bytes());
+        store_bytes(memory, offset + 24, &s.timeout.to_le_bytes());
+        store_bytes(memory, offset + 40, &s.flags.to_le_bytes());
+    }
+    let errno = poll_oneoff.call(0, 1024, subscriptions.len() as i32, 2048)?;
+    assert_eq!(errno, __WASI_ESUCCESS as i32);
+
+    let nevents = memory.view::<u32>()[512].get() as usize;
+    let u64_at = |offset| {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&load_bytes(memory, offset, 8));
+        u64::from_le_bytes(bytes)
+    };
+    Ok((0..nevents)
+        .map(|index| {
+            let offset = 1024 + index * 32;
+            let error = memory.view::<u16>()[offset / 2 + 4].get();
+            (u64_at(offset), error, u64_at(offset + 16))
+        })
+        .collect())
+}
+
+/// Reads `clock_id` with `clock_time_get`.
+fn now(instance: &Instance, clock_id: u32) -> Result<u64> {
+    let memory = instance.exports.get_memory("memory")?;
+    let clock_time_get = instance
+        .exports
+        .get_native_function::<(i32, i64, i32), i32>("clock_time_get")?;
+    assert_eq!(
+        clock_time_get.call(clock_id as i32, 0, 4096)?,
+        __WASI_ESUCCESS as i32
+    );
+    Ok(memory.view::<u64>()[512].get())
+}
+
+#[test]
+fn poll_oneoff_timers() -> Result<()> {
+    let store = get_store(false);
+    let mut wasi_env = wasi_env()?;
+    let (instance, _) = get_instance(&store, &mut wasi_env)?;
+    let abstime = __WASI_SUBSCRIPTION_CLOCK_ABSTIME;
+
+    // the earliest timer fires, and only that one
+    let events = poll(
+        &instance,
+        &[
+            clock(1, __WASI_CLOCK_MONOTONIC, HOUR, 0),
+            clock(2, __WASI_CLOCK_MONOTONIC, 1_000_000, 0),
+        ],
+    )?;
+    assert_eq!(events, vec![(2, __WASI_ESUCCESS, 0)]);
+
+    // absolute timeouts that already passed fire straight away
+    let events = poll(
+        &instance,
+        &[
+            clock(1, __WASI_CLOCK_MONOTONIC, HOUR, 0),
+            clock(3, __WASI_CLOCK_MONOTONIC, 1, abstime),
+        ],
+    )?;
+    assert_eq!(events, vec![(3, __WASI_ESUCCESS, 0)]);
+    // the host CPU-time clocks are only available on unix
+    if cfg!(unix) {
+        let events = poll(
+            &instance,
+            &[
+                clock(1, __WASI_CLOCK_MONOTONIC, HOUR, 0),
+                clock(4, __WASI_CLOCK_PROCESS_CPUTIME_ID, 1, abstime),
+            ],
+        )?;
+        assert_eq!(events, vec![(4, __WASI_ESUCCESS, 0)]);
+    }
+    Ok(())
+}
+
+#[test]
+fn poll_oneoff_advances_the_virtual_clock() -> Result<()> {
+    let store = get_store(false);
+    let mut wasi_env = WasiState::new("poll")
+        .deterministic(7, std::time::Duration::from_millis(1))
+        .finalize()?;
+    let (instance, _) = get_instance(&store, &mut wasi_env)?;
+    let abstime = __WASI_SUBSCRIPTION_CLOCK_ABSTIME;
+
+    // waiting an hour returns straight away, with the clock an hour later
+    let start = now(&instance, __WASI_CLOCK_MONOTONIC)?;
+    let events = poll(
+        &instance,
+        &[
+            clock(1, __WASI_CLOCK_MONOTONIC, 2 * HOUR, 0),
+            clock(2, __WASI_CLOCK_MONOTONIC, start + HOUR, abstime),
+        ],
+    )?;
+    assert_eq!(events, vec![(2, __WASI_ESUCCESS, 0)]);
+    let after = now(&instance, __WASI_CLOCK_MONOTONIC)?;
+    assert!(after >= start + HOUR && after < start + 2 * HOUR);
+
+    // the CPU-time clocks are the same virtual clock
+    let events = poll(
+        &instance,
+        &[
+            clock(1, __WASI_CLOCK_MONOTONIC, HOUR, 0),
+            clock(
+                3,
+                __WASI_CLOCK_PROCESS_CPUTIME_ID,
+                after + HOUR / 2,
+                abstime,
+            ),
+        ],
+    )?;
+    assert_eq!(events, vec![(3, __WASI_ESUCCESS, 0)]);
+    let later = now(&instance, __WASI_CLOCK_PROCESS_CPUTIME_ID)?;
+    assert!(later >= after + HOUR / 2 && later < after + HOUR);
+    Ok(())
+}
+
+#[test]
+fn poll_oneoff_fd_readiness() -> Result<()> {
+    let store = get_store(false);
+    let fs = MemFileSystem::new();
+    std::io::Write::write_all(
+        &mut fs.open(
+            std::path::Path::new("/data"),
+            OpenOptions::new().write(true).create(true),
+        )?,
+        b"hello",
+    )?;
+    let mut wasi_env = WasiState::new("poll")
+        .stdin(Box::new(Pipe::new()))
+        .stdout(Box::new(Pipe::new()))
+        .mount("mem", fs)?
+        .finalize()?;
+    let (instance, _) = get_instance(&store, &mut wasi_env)?;
+    let memory = instance.exports.get_memory("memory")?;
+    let path_open = instance
+        .exports
+        .get_native_function::<(i32, i32, i32, i32, i32, i64, i64, i32, i32), i32>("path_open")?;
+    let read = __WASI_EVENTTYPE_FD_READ;
+
+    // an empty `Pipe` can't be read yet, but can be written
+    let events = poll(
+        &instance,
+        &[
+            fd_event(1, read, 0),
+            fd_event(2, __WASI_EVENTTYPE_FD_WRITE, 1),
+        ],
+    )?;
+    assert_eq!(events, vec![(2, __WASI_ESUCCESS, 0)]);
+    std::io::Write::write_all(wasi_env.state().fs.stdin_mut()?.as_mut().unwrap(), b"abc")?;
+    let events = poll(&instance, &[fd_event(1, read, 0)])?;
+    assert_eq!(events, vec![(1, __WASI_ESUCCESS, 3)]);
+
+    // a file of a `MemFileSystem` has all of its contents available
+    store_bytes(memory, 3000, b"data");
+    assert_eq!(
+        path_open.call(
+            4,
+            0,
+            3000,
+            4,
+            0,
+            ALL_RIGHTS as i64,
+            ALL_RIGHTS as i64,
+            0,
+            3008
+        )?,
+        __WASI_ESUCCESS as i32
+    );
+    let fd = memory.view::<u32>()[752].get();
+    let events = poll(&instance, &[fd_event(3, read, fd)])?;
+    assert_eq!(events, vec![(3, __WASI_ESUCCESS, 5)]);
+
+    // directories and closed fds are reported as errors
+    let events = poll(&instance, &[fd_event(4, read, 4), fd_event(5, read, 99)])?;
+    assert_eq!(events, vec![(4, __WASI_EISDIR, 0), (5, __WASI_EBADF, 0)]);
+    Ok(())
+}
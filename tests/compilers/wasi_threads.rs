@@ -0,0 +1,170 @@
+#![cfg(feature = "wasi")]
+
+//! Start threads with `thread-spawn` from wasi-threads.
+
+use crate::utils::get_store_with_features;
+use anyhow::Result;
+use wasmer::*;
+use wasmer_wasi::types::__WASI_EINTR;
+use wasmer_wasi::{WasiError, WasiState};
+
+fn get_store() -> Store {
+    let mut features = Features::default();
+    features.threads(true);
+    get_store_with_features(features)
+}
+
+/// Every thread writes its id to the `u32` at the index of its argument.
+const THREADED: &str = r#"
+(module
+  (import "wasi" "thread-spawn" (func $spawn (param i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sched_yield" (func $yield (result i32)))
+  (import "env" "memory" (memory 1 1 shared))
+  (export "memory" (memory 0))
+  (func (export "spawn") (param i32) (result i32)
+    (call $spawn (local.get 0)))
+  (func (export "wasi_thread_start") (param $tid i32) (param $arg i32)
+    (if (i32.eqz (local.get $arg))
+      (then unreachable))
+    (i32.store (i32.mul (local.get $arg) (i32.const 4)) (local.get $tid))))
+"#;
+
+#[test]
+fn threads_share_memory() -> Result<()> {
+    let store = get_store();
+    let module = Module::new(&store, THREADED)?;
+    let mut wasi_env = WasiState::new("threads").finalize()?;
+    let import_object = wasi_env.import_object(&module)?;
+    let instance = Instance::new(&module, &import_object)?;
+    let spawn = instance.exports.get_native_function::<i32, i32>("spawn")?;
+
+    let first = spawn.call(1)?;
+    let second = spawn.call(2)?;
+    assert!(first > 0);
+    assert!(second > first);
+    wasi_env.join_threads()?;
+
+    let memory = instance.exports.get_memory("memory")?;
+    let view = memory.view::<u32>();
+    assert_eq!(view[1].get(), first as u32);
+    assert_eq!(view[2].get(), second as u32);
+
+    // a trap ends the program, and is reported when joining
+    assert!(spawn.call(0)? > second);
+    assert!(wasi_env.join_threads().is_err());
+    Ok(())
+}
+
+#[test]
+fn thread_exit_ends_the_program() -> Result<()> {
+    let store = get_store();
+    let module = Module::new(
+        &store,
+        r#"
+        (module
+          (import "wasi" "thread-spawn" (func $spawn (param i32) (result i32)))
+          (import "wasi_snapshot_preview1" "proc_exit" (func $exit (param i32)))
+          (import "env" "memory" (memory 1 1 shared))
+          (export "memory" (memory 0))
+          (func (export "spawn_and_spin")
+            (drop (call $spawn (i32.const 3)))
+            (loop $spin (br $spin)))
+          (func (export "wasi_thread_start") (param $tid i32) (param $arg i32)
+            (call $exit (local.get $arg))))
+        "#,
+    )?;
+    let mut wasi_env = WasiState::new("threads").finalize()?;
+    let import_object = wasi_env.import_object(&module)?;
+    let instance = Instance::new(&module, &import_object)?;
+    let spawn_and_spin = instance
+        .exports
+        .get_native_function::<(), ()>("spawn_and_spin")?;
+
+    let err = spawn_and_spin.call().unwrap_err();
+    assert_eq!(err.to_trap(), Some(TrapCode::Interrupt));
+    let err = wasi_env.join_threads().unwrap_err();
+    assert!(matches!(
+        err.downcast::<WasiError>(),
+        Ok(WasiError::Exit(3))
+    ));
+    Ok(())
+}
+
+#[test]
+fn stop_threads_waiting_in_syscalls() -> Result<()> {
+    let store = get_store();
+    // the thread sleeps for an hour in `poll_oneoff`, with the monotonic
+    // clock subscription at 0 and the event at 64
+    let module = Module::new(
+        &store,
+        r#"
+        (module
+          (import "wasi" "thread-spawn" (func $spawn (param i32) (result i32)))
+          (import "wasi_snapshot_preview1" "poll_oneoff"
+            (func $poll (param i32 i32 i32 i32) (result i32)))
+          (import "env" "memory" (memory 1 1 shared))
+          (export "memory" (memory 0))
+          (data (i32.const 16) "\01\00\00\00\00\00\00\00\00\a0\b8\30\46\03\00\00")
+          (func (export "spawn") (result i32)
+            (call $spawn (i32.const 1)))
+          (func (export "wasi_thread_start") (param $tid i32) (param $arg i32)
+            (i32.store (i32.const 128)
+              (call $poll (i32.const 0) (i32.const 64) (i32.const 1) (i32.const 96)))))
+        "#,
+    )?;
+    let mut wasi_env = WasiState::new("threads").finalize()?;
+    let import_object = wasi_env.import_object(&module)?;
+    let instance = Instance::new(&module, &import_object)?;
+    let spawn = instance.exports.get_native_function::<(), i32>("spawn")?;
+
+    assert!(spawn.call()? > 0);
+    // `_start` returning ends the program
+    wasi_env.stop_threads();
+    wasi_env.join_threads()?;
+    let memory = instance.exports.get_memory("memory")?;
+    assert_eq!(memory.view::<u32>()[32].get(), __WASI_EINTR as u32);
+    Ok(())
+}
+
+#[test]
+fn threads_need_a_shared_memory() -> Result<()> {
+    let store = get_store();
+    let module = Module::new(
+        &store,
+        r#"
+        (module
+          (import "wasi" "thread-spawn" (func (param i32) (result i32)))
+          (import "wasi_snapshot_preview1" "sched_yield" (func (result i32)))
+          (memory (export "memory") 1))
+        "#,
+    )?;
+    let mut wasi_env = WasiState::new("threads").finalize()?;
+    assert!(matches!(
+        wasi_env.import_object(&module),
+        Err(WasiError::Threads(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn shared_memory_joins_the_wasi_namespace() -> Result<()> {
+    let store = get_store();
+    let module = Module::new(
+        &store,
+        r#"
+        (module
+          (import "wasi" "thread-spawn" (func (param i32) (result i32)))
+          (import "wasi_snapshot_preview1" "sched_yield" (func $yield (result i32)))
+          (import "wasi_snapshot_preview1" "memory" (memory 1 1 shared))
+          (export "memory" (memory 0))
+          (func (export "yield") (result i32)
+            (call $yield)))
+        "#,
+    )?;
+    let mut wasi_env = WasiState::new("threads").finalize()?;
+    let import_object = wasi_env.import_object(&module)?;
+    let instance = Instance::new(&module, &import_object)?;
+    let yield_now = instance.exports.get_native_function::<(), i32>("yield")?;
+    assert_eq!(yield_now.call()?, 0);
+    Ok(())
+}
@@ -4,7 +4,7 @@ use crate::DummyArtifact;
 use loupe::MemoryUsage;
 use std::sync::Arc;
 use wasmer_compiler::{CompileError, Features, Target};
-use wasmer_engine::{Artifact, DeserializeError, Engine, EngineId, Tunables};
+use wasmer_engine::{Artifact, DeserializeError, Engine, EngineEpoch, EngineId, Tunables};
 use wasmer_types::FunctionType;
 use wasmer_vm::{SignatureRegistry, VMContext, VMFunctionBody, VMSharedSignatureIndex};
 
@@ -24,6 +24,7 @@ pub struct DummyEngine {
     features: Arc<Features>,
     target: Arc<Target>,
     engine_id: EngineId,
+    epoch: EngineEpoch,
 }
 
 impl DummyEngine {
@@ -34,6 +35,7 @@ impl DummyEngine {
             features: Arc::new(Default::default()),
             target: Arc::new(Default::default()),
             engine_id: EngineId::default(),
+            epoch: EngineEpoch::default(),
         }
     }
 
@@ -110,6 +112,10 @@ impl Engine for DummyEngine {
         &self.engine_id
     }
 
+    fn epoch(&self) -> &EngineEpoch {
+        &self.epoch
+    }
+
     fn cloned(&self) -> Arc<dyn Engine + Send + Sync> {
         Arc::new(self.clone())
     }
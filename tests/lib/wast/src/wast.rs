@@ -398,6 +398,8 @@ impl Wast {
             F32Const(x) => Val::F32(f32::from_bits(x.bits)),
             F64Const(x) => Val::F64(f64::from_bits(x.bits)),
             V128Const(x) => Val::V128(u128::from_le_bytes(x.to_le_bytes())),
+            RefNull(_) => Val::null(),
+            RefExtern(x) => Val::ExternRef(ExternRef::new(*x)),
             other => bail!("couldn't convert {:?} to a runtime value", other),
         })
     }
@@ -461,6 +463,12 @@ fn val_matches(actual: &Val, expected: &wast::AssertExpression) -> Result<bool>
         (Val::F32(a), wast::AssertExpression::F32(b)) => f32_matches(*a, b),
         (Val::F64(a), wast::AssertExpression::F64(b)) => f64_matches(*a, b),
         (Val::V128(a), wast::AssertExpression::V128(b)) => v128_matches(*a, b),
+        (Val::ExternRef(ExternRef::Null), wast::AssertExpression::RefNull(_)) => true,
+        (Val::ExternRef(r), wast::AssertExpression::RefExtern(x)) => r.downcast::<u32>() == Some(x),
+        (Val::FuncRef(_), wast::AssertExpression::RefFunc(_)) => true,
+        (_, wast::AssertExpression::RefNull(_))
+        | (_, wast::AssertExpression::RefExtern(_))
+        | (_, wast::AssertExpression::RefFunc(_)) => false,
         _ => bail!(
             "don't know how to compare {:?} and {:?} yet",
             actual,
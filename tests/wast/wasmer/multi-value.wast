@@ -0,0 +1,122 @@
+;; Tests passing multiple values through blocks, branches and calls.
+
+(module
+  (type $swap_t (func (param i32 f64) (result f64 i32)))
+  (table funcref (elem $swap))
+
+  (func $swap (type $swap_t) (local.get 1) (local.get 0))
+  (func $rot (param i32 i32 i32) (result i32 i32 i32)
+    (local.get 1) (local.get 2) (local.get 0))
+
+  ;; More results than there are registers to hold them.
+  (func $many (param i32) (result i32 i64 f32 f64 i32 i64 f32 f64 i32)
+    (local.get 0)
+    (i64.extend_i32_u (i32.add (local.get 0) (i32.const 1)))
+    (f32.convert_i32_u (i32.add (local.get 0) (i32.const 2)))
+    (f64.convert_i32_u (i32.add (local.get 0) (i32.const 3)))
+    (i32.add (local.get 0) (i32.const 4))
+    (i64.extend_i32_u (i32.add (local.get 0) (i32.const 5)))
+    (f32.convert_i32_u (i32.add (local.get 0) (i32.const 6)))
+    (f64.convert_i32_u (i32.add (local.get 0) (i32.const 7)))
+    (i32.add (local.get 0) (i32.const 8)))
+
+  (func (export "swap") (param i32 f64) (result f64 i32)
+    (call $swap (local.get 0) (local.get 1)))
+
+  (func (export "swap-indirect") (param i32 f64) (result f64 i32)
+    (call_indirect (type $swap_t) (local.get 0) (local.get 1) (i32.const 0)))
+
+  (func (export "rot-twice") (param i32 i32 i32) (result i32 i32 i32)
+    (call $rot (call $rot (local.get 0) (local.get 1) (local.get 2))))
+
+  (func (export "many-sum") (param i32) (result f64)
+    (local i32 i64 f32 f64 i32 i64 f32 f64 i32)
+    (call $many (local.get 0))
+    (local.set 9) (local.set 8) (local.set 7) (local.set 6) (local.set 5)
+    (local.set 4) (local.set 3) (local.set 2) (local.set 1)
+    (f64.add
+      (f64.add
+        (f64.add
+          (f64.convert_i32_u (i32.add (local.get 1) (local.get 5)))
+          (f64.convert_i64_u (i64.add (local.get 2) (local.get 6))))
+        (f64.promote_f32 (f32.add (local.get 3) (local.get 7))))
+      (f64.add
+        (f64.add (local.get 4) (local.get 8))
+        (f64.convert_i32_u (local.get 9)))))
+
+  ;; Block parameters, with a value below them that must survive the block.
+  (func (export "block-params") (param i32 i32) (result i32 i32 i32)
+    (i32.const 100)
+    (local.get 0) (local.get 1)
+    (block (param i32 i32) (result i32)
+      (i32.sub))
+    (i32.const 7))
+
+  ;; A branch carries its values out of nested blocks, dropping the rest.
+  (func (export "br-nested") (param i32) (result i32 f32)
+    (block (result i32 f32)
+      (i32.const 1)
+      (block (param i32) (result i32)
+        (i64.const 2)
+        (drop)
+        (br 1 (i32.add (local.get 0)) (f32.const 1.5)))
+      (f32.const 2.5)))
+
+  (func (export "br_if") (param i32) (result i32 i64)
+    (block (result i32 i64)
+      (br_if 0 (i32.const 1) (i64.const 2) (local.get 0))
+      (drop) (drop)
+      (i32.const 3) (i64.const 4)))
+
+  (func (export "br_table") (param i32) (result f64 i32)
+    (block (result f64 i32)
+      (block (result f64 i32)
+        (block (result f64 i32)
+          (br_table 0 1 2 (f64.const 1.25) (i32.const 10) (local.get 0)))
+        (i32.add (i32.const 1)))
+      (i32.add (i32.const 2))))
+
+  ;; Fibonacci with the state carried in loop parameters.
+  (func (export "fib") (param i64) (result i64)
+    (local i64 i64)
+    (i64.const 0) (i64.const 1) (local.get 0)
+    (loop $l (param i64 i64 i64) (result i64)
+      (local.set 0)
+      (if (param i64 i64) (result i64) (i64.eqz (local.get 0))
+        (then (drop))
+        (else
+          (local.set 2)
+          (local.set 1)
+          (local.get 2)
+          (i64.add (local.get 1) (local.get 2))
+          (br $l (i64.sub (local.get 0) (i64.const 1)))))))
+
+  ;; An `if` without `else` passes its parameters through when the condition is false.
+  (func (export "if-no-else") (param i32 f32 i64) (result f32 i64)
+    (local.get 1) (local.get 2)
+    (if (param f32 i64) (result f32 i64) (local.get 0)
+      (then
+        (i64.mul (i64.const 2))
+        (local.set 2)
+        (f32.neg)
+        (local.get 2))))
+)
+
+(assert_return (invoke "swap" (i32.const 1) (f64.const 2.5)) (f64.const 2.5) (i32.const 1))
+(assert_return (invoke "swap-indirect" (i32.const -1) (f64.const -0.5)) (f64.const -0.5) (i32.const -1))
+(assert_return (invoke "rot-twice" (i32.const 1) (i32.const 2) (i32.const 3)) (i32.const 3) (i32.const 1) (i32.const 2))
+(assert_return (invoke "many-sum" (i32.const 10)) (f64.const 126))
+(assert_return (invoke "block-params" (i32.const 10) (i32.const 3)) (i32.const 100) (i32.const 7) (i32.const 7))
+(assert_return (invoke "br-nested" (i32.const 5)) (i32.const 6) (f32.const 1.5))
+(assert_return (invoke "br_if" (i32.const 0)) (i32.const 3) (i64.const 4))
+(assert_return (invoke "br_if" (i32.const 1)) (i32.const 1) (i64.const 2))
+(assert_return (invoke "br_table" (i32.const 0)) (f64.const 1.25) (i32.const 13))
+(assert_return (invoke "br_table" (i32.const 1)) (f64.const 1.25) (i32.const 12))
+(assert_return (invoke "br_table" (i32.const 2)) (f64.const 1.25) (i32.const 10))
+(assert_return (invoke "br_table" (i32.const 7)) (f64.const 1.25) (i32.const 10))
+(assert_return (invoke "fib" (i64.const 0)) (i64.const 0))
+(assert_return (invoke "fib" (i64.const 1)) (i64.const 1))
+(assert_return (invoke "fib" (i64.const 10)) (i64.const 55))
+(assert_return (invoke "fib" (i64.const 90)) (i64.const 2880067194370816120))
+(assert_return (invoke "if-no-else" (i32.const 0) (f32.const 1.5) (i64.const 3)) (f32.const 1.5) (i64.const 3))
+(assert_return (invoke "if-no-else" (i32.const 1) (f32.const 1.5) (i64.const 3)) (f32.const -1.5) (i64.const 6))
@@ -0,0 +1,168 @@
+;; Tests `funcref` and `externref` values in locals, globals, tables and calls.
+
+(module
+  (type $ret_i32 (func (result i32)))
+  (table $t 2 funcref)
+  (table $small 1 2 funcref)
+  (elem declare func $one $two)
+
+  (global $g_func (mut funcref) (ref.func $one))
+  (global $g_extern (export "g-extern") (mut externref) (ref.null extern))
+
+  (func $one (type $ret_i32) (i32.const 1))
+  (func $two (type $ret_i32) (i32.const 2))
+
+  (func (export "extern-identity") (param externref) (result externref)
+    (local.get 0))
+  (func (export "extern-set") (param externref)
+    (global.set $g_extern (local.get 0)))
+  (func (export "extern-get") (result externref)
+    (global.get $g_extern))
+  (func (export "extern-is-null") (param externref) (result i32)
+    (ref.is_null (local.get 0)))
+  (func (export "extern-select") (param externref externref i32) (result externref)
+    (select (result externref) (local.get 0) (local.get 1) (local.get 2)))
+
+  (func (export "func-global-call") (result i32)
+    (table.set $t (i32.const 0) (global.get $g_func))
+    (call_indirect $t (type $ret_i32) (i32.const 0)))
+  (func (export "func-global-set")
+    (global.set $g_func (ref.func $two)))
+
+  (func (export "is-null") (param i32) (result i32)
+    (ref.is_null (table.get $t (local.get 0))))
+  (func (export "get") (param i32) (result funcref)
+    (table.get $t (local.get 0)))
+  (func (export "set-two") (param i32)
+    (table.set $t (local.get 0) (ref.func $two)))
+  (func (export "call") (param i32) (result i32)
+    (call_indirect $t (type $ret_i32) (local.get 0)))
+  (func (export "size") (result i32)
+    (table.size $t))
+  (func (export "grow") (param i32) (result i32)
+    (table.grow $t (ref.func $one) (local.get 0)))
+  (func (export "grow-null") (param i32) (result i32)
+    (table.grow $t (ref.null func) (local.get 0)))
+  (func (export "grow-small") (param i32) (result i32)
+    (table.grow $small (ref.null func) (local.get 0)))
+  (func (export "fill") (param i32 i32)
+    (table.fill $t (local.get 0) (ref.func $two) (local.get 1)))
+  (func (export "fill-null") (param i32 i32)
+    (table.fill $t (local.get 0) (ref.null func) (local.get 1)))
+)
+
+(assert_return (invoke "extern-identity" (ref.null extern)) (ref.null extern))
+(assert_return (invoke "extern-identity" (ref.extern 1)) (ref.extern 1))
+(assert_return (invoke "extern-get") (ref.null extern))
+(assert_return (invoke "extern-set" (ref.extern 2)))
+(assert_return (invoke "extern-get") (ref.extern 2))
+(assert_return (get "g-extern") (ref.extern 2))
+(assert_return (invoke "extern-is-null" (ref.null extern)) (i32.const 1))
+(assert_return (invoke "extern-is-null" (ref.extern 3)) (i32.const 0))
+(assert_return (invoke "extern-select" (ref.extern 4) (ref.extern 5) (i32.const 1)) (ref.extern 4))
+(assert_return (invoke "extern-select" (ref.extern 4) (ref.extern 5) (i32.const 0)) (ref.extern 5))
+
+(assert_return (invoke "func-global-call") (i32.const 1))
+(assert_return (invoke "func-global-set"))
+(assert_return (invoke "func-global-call") (i32.const 2))
+
+(assert_return (invoke "size") (i32.const 2))
+(assert_return (invoke "is-null" (i32.const 1)) (i32.const 1))
+(assert_return (invoke "get" (i32.const 1)) (ref.null func))
+(assert_trap (invoke "call" (i32.const 1)) "uninitialized element")
+(assert_return (invoke "set-two" (i32.const 1)))
+(assert_return (invoke "is-null" (i32.const 1)) (i32.const 0))
+(assert_return (invoke "get" (i32.const 1)) (ref.func))
+(assert_return (invoke "call" (i32.const 1)) (i32.const 2))
+(assert_trap (invoke "set-two" (i32.const 2)) "out of bounds table access")
+(assert_trap (invoke "get" (i32.const 2)) "out of bounds table access")
+
+(assert_return (invoke "grow" (i32.const 3)) (i32.const 2))
+(assert_return (invoke "size") (i32.const 5))
+(assert_return (invoke "call" (i32.const 4)) (i32.const 1))
+(assert_return (invoke "grow-null" (i32.const 1)) (i32.const 5))
+(assert_return (invoke "is-null" (i32.const 5)) (i32.const 1))
+(assert_return (invoke "grow-small" (i32.const 1)) (i32.const 1))
+(assert_return (invoke "grow-small" (i32.const 1)) (i32.const -1))
+
+(assert_return (invoke "fill" (i32.const 3) (i32.const 3)))
+(assert_return (invoke "call" (i32.const 2)) (i32.const 1))
+(assert_return (invoke "call" (i32.const 5)) (i32.const 2))
+(assert_return (invoke "fill-null" (i32.const 0) (i32.const 2)))
+(assert_return (invoke "is-null" (i32.const 0)) (i32.const 1))
+(assert_return (invoke "fill" (i32.const 6) (i32.const 0)))
+(assert_trap (invoke "fill" (i32.const 5) (i32.const 2)) "out of bounds table access")
+
+;; Globals initialized with references, and passive segments of references.
+(module
+  (type $ret_i32 (func (result i32)))
+  (table $t 3 funcref)
+  (elem $e funcref (ref.func $three) (ref.null func) (ref.func $three))
+
+  (global $g_func funcref (ref.func $three))
+  (global $g_copy funcref (global.get $g_func))
+  (global $g_null externref (ref.null extern))
+
+  (func $three (type $ret_i32) (i32.const 3))
+
+  (func (export "call-copy") (result i32)
+    (table.set $t (i32.const 0) (global.get $g_copy))
+    (call_indirect $t (type $ret_i32) (i32.const 0)))
+  (func (export "null-is-null") (result i32)
+    (ref.is_null (global.get $g_null)))
+  (func (export "init") (param i32 i32 i32)
+    (table.init $t $e (local.get 0) (local.get 1) (local.get 2)))
+  (func (export "drop")
+    (elem.drop $e))
+  (func (export "is-null") (param i32) (result i32)
+    (ref.is_null (table.get $t (local.get 0))))
+)
+
+(assert_return (invoke "call-copy") (i32.const 3))
+(assert_return (invoke "null-is-null") (i32.const 1))
+(assert_return (invoke "init" (i32.const 0) (i32.const 0) (i32.const 3)))
+(assert_return (invoke "is-null" (i32.const 0)) (i32.const 0))
+(assert_return (invoke "is-null" (i32.const 1)) (i32.const 1))
+(assert_return (invoke "is-null" (i32.const 2)) (i32.const 0))
+(assert_return (invoke "drop"))
+(assert_trap (invoke "init" (i32.const 0) (i32.const 0) (i32.const 1)) "out of bounds table access")
+
+;; Tables of `externref`s.
+(module
+  (table $t 2 3 externref)
+  (elem (table $t) (i32.const 0) externref (ref.null extern))
+
+  (func (export "get") (param i32) (result externref)
+    (table.get $t (local.get 0)))
+  (func (export "set") (param i32 externref)
+    (table.set $t (local.get 0) (local.get 1)))
+  (func (export "is-null") (param i32) (result i32)
+    (ref.is_null (table.get $t (local.get 0))))
+  (func (export "size") (result i32)
+    (table.size $t))
+  (func (export "grow") (param i32 externref) (result i32)
+    (table.grow $t (local.get 1) (local.get 0)))
+  (func (export "fill") (param i32 externref i32)
+    (table.fill $t (local.get 0) (local.get 1) (local.get 2)))
+)
+
+(assert_return (invoke "get" (i32.const 0)) (ref.null extern))
+(assert_return (invoke "is-null" (i32.const 1)) (i32.const 1))
+(assert_return (invoke "set" (i32.const 1) (ref.extern 1)))
+(assert_return (invoke "get" (i32.const 1)) (ref.extern 1))
+(assert_return (invoke "is-null" (i32.const 1)) (i32.const 0))
+(assert_trap (invoke "set" (i32.const 2) (ref.extern 1)) "out of bounds table access")
+(assert_trap (invoke "get" (i32.const 2)) "out of bounds table access")
+
+(assert_return (invoke "grow" (i32.const 1) (ref.extern 2)) (i32.const 2))
+(assert_return (invoke "size") (i32.const 3))
+(assert_return (invoke "get" (i32.const 2)) (ref.extern 2))
+(assert_return (invoke "grow" (i32.const 1) (ref.null extern)) (i32.const -1))
+
+(assert_return (invoke "fill" (i32.const 0) (ref.extern 3) (i32.const 2)))
+(assert_return (invoke "get" (i32.const 0)) (ref.extern 3))
+(assert_return (invoke "get" (i32.const 1)) (ref.extern 3))
+(assert_return (invoke "get" (i32.const 2)) (ref.extern 2))
+(assert_return (invoke "fill" (i32.const 1) (ref.null extern) (i32.const 2)))
+(assert_return (invoke "is-null" (i32.const 2)) (i32.const 1))
+(assert_trap (invoke "fill" (i32.const 2) (ref.null extern) (i32.const 2)) "out of bounds table access")
@@ -0,0 +1,132 @@
+;; Tests passing `v128` values through locals, globals, calls and blocks.
+
+(module
+  (type $v_v (func (param v128) (result v128)))
+  (table funcref (elem $id $swap_halves))
+  (global $g (mut v128) (v128.const i64x2 1 2))
+
+  (func $id (type $v_v) (local.get 0))
+  (func $swap_halves (type $v_v)
+    (v8x16.shuffle 8 9 10 11 12 13 14 15 0 1 2 3 4 5 6 7 (local.get 0) (local.get 0)))
+
+  ;; Enough arguments to spill some of them to the stack.
+  (func $sum (param i32 v128 f64 v128 i64 v128 v128 f32 v128) (result v128)
+    (local v128)
+    (local.set 9 (i32x4.add (local.get 1) (local.get 3)))
+    (local.set 9 (i32x4.add (local.get 9) (local.get 5)))
+    (local.set 9 (i32x4.add (local.get 9) (local.get 6)))
+    (i32x4.add (local.get 9) (local.get 8)))
+
+  (func (export "sum") (result v128)
+    (call $sum
+      (i32.const 7)
+      (v128.const i32x4 1 2 3 4)
+      (f64.const 1.5)
+      (v128.const i32x4 10 20 30 40)
+      (i64.const 9)
+      (v128.const i32x4 100 200 300 400)
+      (v128.const i32x4 1000 2000 3000 4000)
+      (f32.const 2.5)
+      (v128.const i32x4 10000 20000 30000 40000)))
+
+  (func (export "scalars_after_v128") (param v128 i32 v128 i64) (result i64)
+    (i64.add (i64.extend_i32_u (local.get 1)) (local.get 3)))
+
+  (func (export "call_indirect") (param v128 i32) (result v128)
+    (call_indirect (type $v_v) (local.get 0) (local.get 1)))
+
+  (func (export "global") (param v128) (result v128)
+    (global.get $g)
+    (global.set $g (local.get 0))
+    (global.get $g)
+    (i64x2.add))
+
+  ;; The arguments have different vector types in Cranelift.
+  (func (export "select") (param v128 v128 i32) (result v128)
+    (select
+      (i32x4.add (local.get 0) (v128.const i32x4 0 0 0 0))
+      (local.get 1)
+      (local.get 2)))
+
+  (func (export "tee") (param v128) (result v128)
+    (local v128)
+    (i64x2.add (local.tee 1 (local.get 0)) (local.get 1)))
+
+  (func (export "drop") (param v128) (result i32)
+    (i32.const 42)
+    (local.get 0)
+    (drop))
+
+  (func (export "if") (param i32) (result v128)
+    (if (result v128) (local.get 0)
+      (then (v128.const i64x2 1 2))
+      (else (v128.const i64x2 3 4))))
+
+  (func (export "br_if") (param i32) (result v128)
+    (block (result v128)
+      (br_if 0 (v128.const i64x2 5 6) (local.get 0))
+      (drop)
+      (v128.const i64x2 7 8)))
+
+  (func (export "br_table") (param i32) (result v128)
+    (block (result v128)
+      (block (result v128)
+        (br_table 0 1 (v128.const i64x2 9 10) (local.get 0)))
+      (drop)
+      (v128.const i64x2 11 12)))
+
+  (func (export "loop") (param i32) (result v128)
+    (local v128)
+    (loop $l
+      (local.set 1 (i32x4.add (local.get 1) (v128.const i32x4 1 2 3 4)))
+      (br_if $l (local.tee 0 (i32.sub (local.get 0) (i32.const 1)))))
+    (local.get 1))
+
+  (func (export "return") (param i32) (result v128)
+    (if (local.get 0) (then (return (v128.const i64x2 13 14))))
+    (v128.const i64x2 15 16))
+
+  (func (export "deep_stack") (result v128)
+    (v128.const i32x4 1 1 1 1)
+    (v128.const i32x4 2 2 2 2)
+    (v128.const i32x4 3 3 3 3)
+    (v128.const i32x4 4 4 4 4)
+    (v128.const i32x4 5 5 5 5)
+    (v128.const i32x4 6 6 6 6)
+    (v128.const i32x4 7 7 7 7)
+    (v128.const i32x4 8 8 8 8)
+    (i32x4.add) (i32x4.add) (i32x4.add) (i32x4.add)
+    (i32x4.add) (i32x4.add) (i32x4.add))
+)
+
+(assert_return (invoke "sum") (v128.const i32x4 11111 22222 33333 44444))
+(assert_return
+  (invoke "scalars_after_v128" (v128.const i64x2 1 2) (i32.const 3) (v128.const i64x2 4 5) (i64.const 6))
+  (i64.const 9))
+(assert_return
+  (invoke "call_indirect" (v128.const i64x2 1 2) (i32.const 0))
+  (v128.const i64x2 1 2))
+(assert_return
+  (invoke "call_indirect" (v128.const i64x2 1 2) (i32.const 1))
+  (v128.const i64x2 2 1))
+(assert_return (invoke "global" (v128.const i64x2 10 20)) (v128.const i64x2 11 22))
+(assert_return (invoke "global" (v128.const i64x2 30 40)) (v128.const i64x2 40 60))
+(assert_return
+  (invoke "select" (v128.const i64x2 1 2) (v128.const i64x2 3 4) (i32.const 1))
+  (v128.const i64x2 1 2))
+(assert_return
+  (invoke "select" (v128.const i64x2 1 2) (v128.const i64x2 3 4) (i32.const 0))
+  (v128.const i64x2 3 4))
+(assert_return (invoke "tee" (v128.const i64x2 1 2)) (v128.const i64x2 2 4))
+(assert_return (invoke "drop" (v128.const i64x2 1 2)) (i32.const 42))
+(assert_return (invoke "if" (i32.const 1)) (v128.const i64x2 1 2))
+(assert_return (invoke "if" (i32.const 0)) (v128.const i64x2 3 4))
+(assert_return (invoke "br_if" (i32.const 1)) (v128.const i64x2 5 6))
+(assert_return (invoke "br_if" (i32.const 0)) (v128.const i64x2 7 8))
+(assert_return (invoke "br_table" (i32.const 0)) (v128.const i64x2 11 12))
+(assert_return (invoke "br_table" (i32.const 1)) (v128.const i64x2 9 10))
+(assert_return (invoke "br_table" (i32.const 5)) (v128.const i64x2 9 10))
+(assert_return (invoke "loop" (i32.const 3)) (v128.const i32x4 3 6 9 12))
+(assert_return (invoke "return" (i32.const 1)) (v128.const i64x2 13 14))
+(assert_return (invoke "return" (i32.const 0)) (v128.const i64x2 15 16))
+(assert_return (invoke "deep_stack") (v128.const i32x4 36 36 36 36))
@@ -0,0 +1,49 @@
+;; Tests passing `v128` values among multiple values through blocks and calls.
+
+(module
+  (type $split_t (func (param v128 i32) (result i32 v128 v128)))
+  (table funcref (elem $split))
+
+  (func $split (type $split_t)
+    (local.get 1)
+    (i64x2.splat (i64x2.extract_lane 0 (local.get 0)))
+    (i64x2.splat (i64x2.extract_lane 1 (local.get 0))))
+
+  (func (export "split") (param v128 i32) (result i32 v128 v128)
+    (call $split (local.get 0) (local.get 1)))
+
+  (func (export "split-indirect") (param v128 i32) (result i32 v128 v128)
+    (call_indirect (type $split_t) (local.get 0) (local.get 1) (i32.const 0)))
+
+  (func (export "block") (param v128 f64) (result f64 v128)
+    (local.get 1) (local.get 0)
+    (block (param f64 v128) (result f64 v128)
+      (i32x4.add (v128.const i32x4 1 1 1 1))))
+
+  (func (export "loop") (param i32) (result v128 i32)
+    (v128.const i32x4 0 0 0 0) (local.get 0)
+    (loop $l (param v128 i32) (result v128 i32)
+      (local.set 0)
+      (i32x4.add (v128.const i32x4 1 2 3 4))
+      (local.tee 0 (i32.sub (local.get 0) (i32.const 1)))
+      (br_if $l (local.get 0))))
+
+  (func (export "br_if") (param i32) (result v128 v128)
+    (block (result v128 v128)
+      (br_if 0 (v128.const i64x2 1 2) (v128.const i64x2 3 4) (local.get 0))
+      (drop) (drop)
+      (v128.const i64x2 5 6) (v128.const i64x2 7 8)))
+)
+
+(assert_return
+  (invoke "split" (v128.const i64x2 1 2) (i32.const 3))
+  (i32.const 3) (v128.const i64x2 1 1) (v128.const i64x2 2 2))
+(assert_return
+  (invoke "split-indirect" (v128.const i64x2 4 5) (i32.const 6))
+  (i32.const 6) (v128.const i64x2 4 4) (v128.const i64x2 5 5))
+(assert_return
+  (invoke "block" (v128.const i32x4 1 2 3 4) (f64.const 0.5))
+  (f64.const 0.5) (v128.const i32x4 2 3 4 5))
+(assert_return (invoke "loop" (i32.const 3)) (v128.const i32x4 3 6 9 12) (i32.const 0))
+(assert_return (invoke "br_if" (i32.const 1)) (v128.const i64x2 1 2) (v128.const i64x2 3 4))
+(assert_return (invoke "br_if" (i32.const 0)) (v128.const i64x2 5 6) (v128.const i64x2 7 8))